@@ -0,0 +1,19 @@
+//! Commands which are run in response to filesystem events.
+
+use crate::{EventKind, EventSet};
+
+/// A command to run whenever one of `events` occurs, declared with `on <events> run <command>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Action {
+    pub events: EventSet,
+    /// The command line, passed to the shell as written.
+    pub command: String,
+}
+
+impl Action {
+    /// Returns true if this action should run for an event of the given kind.
+    pub fn matches(&self, kind: EventKind) -> bool {
+        self.events.contains(kind)
+    }
+}
@@ -0,0 +1,285 @@
+//! Building a configuration in code rather than parsing one.
+
+use crate::{
+    parser::{is_tag_char, path_spec},
+    Action, Config, ConfigError, EventSet, ParseOptions, RateLimit, Recursion, WatchEntry,
+    WatchOptions,
+};
+
+/// Builds a [`Config`] in code, holding it to the rules the parser enforces on configuration
+/// text.
+///
+/// Paths are written as in an unquoted `include` or `exclude`, so tildes, environment
+/// variables and globs are expanded according to the [`ParseOptions`]. Nothing is checked until
+/// [`ConfigBuilder::build`], which reports every problem at once.
+///
+/// ```
+/// use configuration::{Action, ConfigBuilder, EventKind, EventSet};
+///
+/// let modify = EventSet::from_iter([EventKind::Modify]);
+/// let config = ConfigBuilder::new()
+///     .include("/etc/nginx")
+///     .exclude("/etc/nginx/cache")
+///     .event_filter(modify)
+///     .action(Action {
+///         events: modify,
+///         command: "systemctl reload nginx".to_string(),
+///     })
+///     .build()
+///     .unwrap();
+/// assert!(config.is_watched("/etc/nginx/nginx.conf"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    options: ParseOptions,
+    includes: Vec<(String, Option<WatchOptions>)>,
+    excludes: Vec<String>,
+    events: Option<EventSet>,
+    actions: Vec<Action>,
+}
+
+impl ConfigBuilder {
+    /// Starts an empty configuration, expanding paths with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts an empty configuration, expanding paths with the given options.
+    pub fn with_options(options: ParseOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Includes `path` with [`ParseOptions::default_recursion`], as `include <path>` does.
+    pub fn include(mut self, path: impl Into<String>) -> Self {
+        self.includes.push((path.into(), None));
+        self
+    }
+
+    /// Includes `path` with the given options, as the flag and options of an include do.
+    pub fn include_with(mut self, path: impl Into<String>, options: WatchOptions) -> Self {
+        self.includes.push((path.into(), Some(options)));
+        self
+    }
+
+    pub fn exclude(mut self, path: impl Into<String>) -> Self {
+        self.excludes.push(path.into());
+        self
+    }
+
+    /// Only reports the given events, as the `events` directive does.
+    pub fn event_filter(mut self, events: EventSet) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Adds a global action, as `on <events> run <command>` does.
+    pub fn action(mut self, action: Action) -> Self {
+        self.actions.push(action);
+        self
+    }
+
+    /// Checks everything given and builds the configuration. Several problems are reported
+    /// together as a [`ConfigError::Multiple`].
+    pub fn build(self) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        let mut errors = Vec::new();
+        let parse = |path: &String| match path_spec(path, &self.options) {
+            Ok(spec) => Ok(spec),
+            Err(error) => Err(ConfigError::InvalidPath {
+                path: path.clone(),
+                error,
+            }),
+        };
+
+        for (path, options) in &self.includes {
+            let options = options.clone().unwrap_or_else(|| WatchOptions {
+                recursion: self.options.default_recursion,
+                ..Default::default()
+            });
+            errors.extend(check_options(path, &options));
+            match parse(path) {
+                Ok(path) => config.includes.push(WatchEntry { path, options }),
+                Err(error) => errors.push(error),
+            }
+        }
+        for path in &self.excludes {
+            match parse(path) {
+                Ok(spec) => config.excludes.push(spec),
+                Err(error) => errors.push(error),
+            }
+        }
+        if let Some(events) = self.events {
+            if events.is_empty() {
+                errors.push(invalid("events", "at least one event"));
+            }
+            config.events = events;
+        }
+        for action in self.actions {
+            errors.extend(check_action(&action, "action", false));
+            config.actions.push(action);
+        }
+
+        ConfigError::collect(errors)?;
+        Ok(config)
+    }
+}
+
+impl Config {
+    /// Starts building a configuration in code, see [`ConfigBuilder`].
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+}
+
+fn invalid(setting: impl Into<String>, expected: &'static str) -> ConfigError {
+    ConfigError::Invalid {
+        setting: setting.into(),
+        expected,
+    }
+}
+
+/// Checks the options of the include of `path` the way the parser checks an include's options.
+fn check_options(path: &str, options: &WatchOptions) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    let mut check = |ok: bool, option: &str, expected| {
+        if !ok {
+            errors.push(invalid(format!("{option} of include {path}"), expected));
+        }
+    };
+    check(
+        options.max_depth.is_none() || options.recursion == Recursion::Recursive,
+        "depth",
+        "a recursive include",
+    );
+    check(
+        options.events.is_none_or(|events| !events.is_empty()),
+        "events",
+        "at least one event",
+    );
+    check(
+        options.extensions.as_ref().is_none_or(|extensions| {
+            !extensions.is_empty()
+                && extensions.iter().all(|ext| {
+                    !ext.is_empty()
+                        && ext
+                            .chars()
+                            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+                })
+        }),
+        "ext",
+        "a list of extensions such as rs,toml",
+    );
+    check(
+        options.rate_limit.is_none_or(valid_rate),
+        "rate_limit",
+        "a rate and burst above zero",
+    );
+    check(
+        options
+            .tags
+            .iter()
+            .all(|tag| !tag.is_empty() && tag.chars().all(is_tag_char)),
+        "tags",
+        "a list of tags such as security,compliance",
+    );
+    for action in &options.actions {
+        errors.extend(check_action(
+            action,
+            &format!("action of include {path}"),
+            true,
+        ));
+    }
+    errors
+}
+
+/// Checks an action. Only the commands of an include's actions are quoted, so only they can
+/// span several lines.
+fn check_action(action: &Action, setting: &str, quoted: bool) -> Option<ConfigError> {
+    if action.events.is_empty() {
+        return Some(invalid(setting, "at least one event"));
+    }
+    if action.command.trim().is_empty() {
+        return Some(invalid(setting, "a command"));
+    }
+    if !quoted && action.command.contains(['\n', '\r']) {
+        return Some(invalid(setting, "a command on one line"));
+    }
+    None
+}
+
+fn valid_rate(limit: RateLimit) -> bool {
+    limit.events > 0 && limit.burst > 0 && !limit.period.is_zero()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EventKind;
+
+    #[test]
+    fn builds_what_the_parser_would() {
+        let modify = EventSet::from_iter([EventKind::Modify]);
+        let config = Config::builder()
+            .include("/etc/nginx")
+            .include_with(
+                "/srv/app",
+                WatchOptions {
+                    recursion: Recursion::Recursive,
+                    max_depth: Some(2),
+                    ..Default::default()
+                },
+            )
+            .exclude("/etc/nginx/cache")
+            .event_filter(modify)
+            .action(Action {
+                events: modify,
+                command: "systemctl reload nginx".to_string(),
+            })
+            .build()
+            .unwrap();
+        let parsed: Config = "include /etc/nginx\ninclude /srv/app depth=2\n\
+                              exclude /etc/nginx/cache\nevents modify\n\
+                              on modify run systemctl reload nginx"
+            .parse()
+            .unwrap();
+        assert_eq!(config, parsed);
+    }
+
+    #[test]
+    fn reports_every_broken_rule() {
+        let err = ConfigBuilder::new()
+            .include("/srv/[")
+            .include_with(
+                "/srv/app",
+                WatchOptions {
+                    max_depth: Some(2),
+                    tags: vec!["a b".to_string()],
+                    ..Default::default()
+                },
+            )
+            .exclude("/tmp")
+            .event_filter(EventSet::empty())
+            .action(Action {
+                events: EventSet::all(),
+                command: "echo a\necho b".to_string(),
+            })
+            .build()
+            .unwrap_err();
+        let messages: Vec<_> = err.errors().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "invalid path '/srv/[': invalid range pattern at position 5".to_string(),
+                "invalid depth of include /srv/app, expected a recursive include".to_string(),
+                "invalid tags of include /srv/app, expected a list of tags such as \
+                 security,compliance"
+                    .to_string(),
+                "invalid events, expected at least one event".to_string(),
+                "invalid action, expected a command on one line".to_string(),
+            ]
+        );
+    }
+}
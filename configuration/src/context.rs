@@ -0,0 +1,129 @@
+//! The machine a configuration is loaded on, which conditional directives are evaluated
+//! against.
+
+use std::{env, fmt};
+
+use crate::Pattern;
+
+/// Facts about the machine tested by `if` blocks and `@<os>` prefixes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Context {
+    pub hostname: String,
+    /// The operating system, named as in [`std::env::consts::OS`], such as `linux` or `macos`.
+    pub os: String,
+}
+
+impl Context {
+    /// Describes the machine the process is running on.
+    pub fn current() -> Self {
+        Self {
+            hostname: hostname().unwrap_or_default(),
+            os: env::consts::OS.to_string(),
+        }
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::current()
+    }
+}
+
+/// What a [`Condition`] tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ConditionKey {
+    Host,
+    Os,
+}
+
+impl ConditionKey {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "host" => Some(ConditionKey::Host),
+            "os" => Some(ConditionKey::Os),
+            _ => None,
+        }
+    }
+}
+
+/// A test such as `host=web*` or `os!=macos`, matched with glob syntax.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Condition {
+    pub key: ConditionKey,
+    pub negated: bool,
+    pub value: Pattern,
+}
+
+impl Condition {
+    pub fn holds(&self, context: &Context) -> bool {
+        let actual = match self.key {
+            ConditionKey::Host => &context.hostname,
+            ConditionKey::Os => &context.os,
+        };
+        self.value.matches(actual.as_ref()) != self.negated
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let key = match self.key {
+            ConditionKey::Host => "host",
+            ConditionKey::Os => "os",
+        };
+        let operator = if self.negated { "!=" } else { "=" };
+        write!(f, "{key}{operator}{}", self.value)
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buf = vec![0u8; 256];
+    // SAFETY: the pointer and length describe a live buffer owned by this frame.
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+    let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    buf.truncate(end);
+    String::from_utf8(buf).ok()
+}
+
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    env::var("COMPUTERNAME").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_conditions() {
+        let context = Context {
+            hostname: "web01".to_string(),
+            os: "linux".to_string(),
+        };
+        let condition = |key, negated, value: &str| Condition {
+            key,
+            negated,
+            value: value.parse().unwrap(),
+        };
+
+        let test_cases = vec![
+            (condition(ConditionKey::Host, false, "web01"), true),
+            (condition(ConditionKey::Host, false, "web*"), true),
+            (condition(ConditionKey::Host, false, "db*"), false),
+            (condition(ConditionKey::Host, true, "db*"), true),
+            (condition(ConditionKey::Os, false, "linux"), true),
+            (condition(ConditionKey::Os, true, "linux"), false),
+        ];
+        for (condition, holds) in test_cases {
+            assert_eq!(condition.holds(&context), holds, "{condition}");
+        }
+    }
+}
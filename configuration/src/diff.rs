@@ -0,0 +1,187 @@
+//! Comparing two configurations, such as before and after a reload.
+
+use std::fmt;
+
+use crate::{writer::write_path, Config, PathSpec, WatchEntry};
+
+/// What changed between two configurations, as returned by [`Config::diff`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigDiff {
+    /// Includes for paths the old configuration didn't include.
+    pub added_includes: Vec<WatchEntry>,
+    /// Includes for paths the new configuration doesn't include.
+    pub removed_includes: Vec<WatchEntry>,
+    /// Paths included by both whose options changed, as the old and the new include.
+    pub changed_includes: Vec<(WatchEntry, WatchEntry)>,
+    pub added_excludes: Vec<PathSpec>,
+    pub removed_excludes: Vec<PathSpec>,
+    /// The other settings which changed, named by their directive such as `events` or
+    /// `debounce`, in the order [`Config`]'s `Display` writes them. Custom directives are
+    /// named `custom`, watch groups `watch` and profile sections `profile`.
+    pub changed_settings: Vec<&'static str>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == ConfigDiff::default()
+    }
+}
+
+impl Config {
+    /// Compares two configurations. An include is matched up by its path, so one whose
+    /// recursion, events, actions or other options changed is reported as changed rather than
+    /// as removed and added. When a path is included more than once, its last include counts.
+    pub fn diff(old: &Config, new: &Config) -> ConfigDiff {
+        let old_includes = last_per_path(&old.includes);
+        let new_includes = last_per_path(&new.includes);
+        let mut diff = ConfigDiff::default();
+        for entry in &new_includes {
+            match old_includes.iter().find(|old| old.path == entry.path) {
+                None => diff.added_includes.push((*entry).clone()),
+                Some(old) if old.options != entry.options => diff
+                    .changed_includes
+                    .push(((*old).clone(), (*entry).clone())),
+                Some(_) => {}
+            }
+        }
+        diff.removed_includes = old_includes
+            .into_iter()
+            .filter(|old| !new_includes.iter().any(|entry| entry.path == old.path))
+            .cloned()
+            .collect();
+        diff.added_excludes = difference(&new.excludes, &old.excludes);
+        diff.removed_excludes = difference(&old.excludes, &new.excludes);
+
+        let settings = [
+            ("events", old.events != new.events),
+            ("debounce", old.debounce != new.debounce),
+            ("poll_interval", old.poll_interval != new.poll_interval),
+            ("max_size", old.max_size != new.max_size),
+            ("only_extensions", old.extensions != new.extensions),
+            (
+                "follow_symlinks",
+                old.follow_symlinks != new.follow_symlinks,
+            ),
+            ("include_hidden", old.include_hidden != new.include_hidden),
+            ("rate_limit", old.rate_limit != new.rate_limit),
+            ("owner", old.owners.users != new.owners.users),
+            ("group", old.owners.groups != new.owners.groups),
+            ("log_level", old.logging.level != new.logging.level),
+            ("log_file", old.logging.file != new.logging.file),
+            ("output", old.output != new.output),
+            ("on", old.actions != new.actions),
+            ("ignore", old.ignores.patterns() != new.ignores.patterns()),
+            ("ignorefile", old.ignores.files() != new.ignores.files()),
+            ("custom", old.custom != new.custom),
+            ("watch", old.groups != new.groups),
+            ("profile", old.profiles != new.profiles),
+        ];
+        diff.changed_settings = settings
+            .into_iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(name, _)| name)
+            .collect();
+        diff
+    }
+}
+
+/// The last include for each path, in the order the paths are first included.
+fn last_per_path(includes: &[WatchEntry]) -> Vec<&WatchEntry> {
+    let mut entries: Vec<&WatchEntry> = Vec::new();
+    for entry in includes {
+        match entries.iter_mut().find(|seen| seen.path == entry.path) {
+            Some(seen) => *seen = entry,
+            None => entries.push(entry),
+        }
+    }
+    entries
+}
+
+/// The specs in `a` which aren't in `b`.
+fn difference(a: &[PathSpec], b: &[PathSpec]) -> Vec<PathSpec> {
+    let mut specs: Vec<PathSpec> = Vec::new();
+    for spec in a {
+        if !b.contains(spec) && !specs.contains(spec) {
+            specs.push(spec.clone());
+        }
+    }
+    specs
+}
+
+fn change(
+    f: &mut fmt::Formatter<'_>,
+    change: &str,
+    directive: &str,
+    path: Option<&PathSpec>,
+) -> fmt::Result {
+    write!(f, "{change} {directive}")?;
+    if let Some(path) = path {
+        f.write_str(" ")?;
+        write_path(f, path)?;
+    }
+    f.write_str("\n")
+}
+
+/// One change per line, such as `added include /var/log` or `changed debounce`.
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.added_includes {
+            change(f, "added", "include", Some(&entry.path))?;
+        }
+        for entry in &self.removed_includes {
+            change(f, "removed", "include", Some(&entry.path))?;
+        }
+        for (_, entry) in &self.changed_includes {
+            change(f, "changed", "include", Some(&entry.path))?;
+        }
+        for spec in &self.added_excludes {
+            change(f, "added", "exclude", Some(spec))?;
+        }
+        for spec in &self.removed_excludes {
+            change(f, "removed", "exclude", Some(spec))?;
+        }
+        for setting in &self.changed_settings {
+            change(f, "changed", setting, None)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_what_changed() {
+        let old: Config = "include /etc, /srv\ninclude -r /home\nexclude /srv/tmp, /home/x\n\
+                           debounce 1s\nlog_level info"
+            .parse()
+            .unwrap();
+        let new: Config = "include /srv, /var\ninclude /home\nexclude /home/x, /var/cache\n\
+                           debounce 1s\nlog_level debug\nevents modify"
+            .parse()
+            .unwrap();
+
+        let diff = Config::diff(&old, &new);
+        let paths = |entries: &[WatchEntry]| -> Vec<String> {
+            entries.iter().map(|entry| entry.path.to_string()).collect()
+        };
+        assert_eq!(paths(&diff.added_includes), ["/var"]);
+        assert_eq!(paths(&diff.removed_includes), ["/etc"]);
+        assert_eq!(diff.changed_includes.len(), 1);
+        assert_eq!(diff.changed_includes[0].0, old.includes()[2]);
+        assert_eq!(diff.changed_includes[0].1, new.includes()[2]);
+        assert_eq!(diff.added_excludes, ["/var/cache".parse().unwrap()]);
+        assert_eq!(diff.removed_excludes, ["/srv/tmp".parse().unwrap()]);
+        assert_eq!(diff.changed_settings, ["events", "log_level"]);
+        assert_eq!(
+            diff.to_string(),
+            "added include /var\nremoved include /etc\nchanged include /home\n\
+             added exclude /var/cache\nremoved exclude /srv/tmp\nchanged events\n\
+             changed log_level\n"
+        );
+
+        assert!(Config::diff(&old, &old).is_empty());
+        assert_eq!(Config::diff(&old, &old).to_string(), "");
+    }
+}
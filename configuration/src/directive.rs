@@ -0,0 +1,218 @@
+//! Directives defined outside this crate, registered with a [`DirectiveRegistry`].
+
+use std::{any::Any, collections::BTreeMap, fmt, sync::Arc};
+
+/// A directive an embedding application adds to the configuration language.
+///
+/// ```
+/// use configuration::{Config, Directive, DirectiveRegistry, ParseOptions};
+///
+/// struct Endpoint;
+///
+/// impl Directive for Endpoint {
+///     type Value = String;
+///
+///     fn parse(&self, args: &str) -> Result<String, String> {
+///         match args.strip_prefix("endpoint ") {
+///             Some(url) => Ok(url.to_string()),
+///             None => Err("expected endpoint <url>".to_string()),
+///         }
+///     }
+/// }
+///
+/// let mut options = ParseOptions::default();
+/// options.directives.register("shipper", Endpoint);
+/// let config = Config::parse_with("shipper endpoint https://logs.example.com", &options)?;
+/// let urls: Vec<&String> = config.custom_values("shipper").collect();
+/// assert_eq!(urls, ["https://logs.example.com"]);
+/// # Ok::<(), configuration::ConfigError>(())
+/// ```
+pub trait Directive: Send + Sync + 'static {
+    type Value: Send + Sync + 'static;
+
+    /// Parses the arguments following the directive's name, with any trailing comment removed.
+    /// An error is reported as an invalid line.
+    fn parse(&self, args: &str) -> Result<Self::Value, String>;
+}
+
+/// [`Directive`] with its value type erased, so directives of different types can be stored
+/// together.
+trait AnyDirective: Send + Sync {
+    fn parse_any(&self, args: &str) -> Result<Arc<dyn Any + Send + Sync>, String>;
+}
+
+impl<D: Directive> AnyDirective for D {
+    fn parse_any(&self, args: &str) -> Result<Arc<dyn Any + Send + Sync>, String> {
+        Ok(Arc::new(self.parse(args)?))
+    }
+}
+
+/// The custom directives a parser accepts, consulted for any name which isn't a built in
+/// directive or alias.
+#[derive(Clone, Default)]
+pub struct DirectiveRegistry {
+    directives: BTreeMap<String, Arc<dyn AnyDirective>>,
+}
+
+impl DirectiveRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a directive, replacing any registered under the same name. Names of built in
+    /// directives can't be taken over.
+    pub fn register<D: Directive>(&mut self, name: impl Into<String>, directive: D) -> &mut Self {
+        self.directives.insert(name.into(), Arc::new(directive));
+        self
+    }
+
+    /// The names of the registered directives, in sorted order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.directives.keys().map(String::as_str)
+    }
+
+    pub(crate) fn parse(&self, name: &str, args: &str) -> Option<Result<CustomDirective, String>> {
+        let directive = self.directives.get(name)?;
+        Some(directive.parse_any(args).map(|value| CustomDirective {
+            name: name.to_string(),
+            args: args.to_string(),
+            value,
+        }))
+    }
+}
+
+impl fmt::Debug for DirectiveRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.names()).finish()
+    }
+}
+
+/// A line parsed by a [`Directive`] from a [`DirectiveRegistry`].
+///
+/// Two are equal when they were written the same way, regardless of their values.
+#[derive(Clone)]
+pub struct CustomDirective {
+    name: String,
+    args: String,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+impl CustomDirective {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The arguments as written, which are what the directive is written back out as.
+    pub fn args(&self) -> &str {
+        &self.args
+    }
+
+    /// The parsed value, if it's a `T`.
+    pub fn value<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+}
+
+impl PartialEq for CustomDirective {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.args == other.args
+    }
+}
+
+impl fmt::Debug for CustomDirective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomDirective")
+            .field("name", &self.name)
+            .field("args", &self.args)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, ConfigError, ParseOptions};
+
+    /// `shipper endpoint <url>` or `shipper batch <n>`.
+    struct Shipper;
+
+    #[derive(Debug, PartialEq)]
+    enum Setting {
+        Endpoint(String),
+        Batch(u32),
+    }
+
+    impl Directive for Shipper {
+        type Value = Setting;
+
+        fn parse(&self, args: &str) -> Result<Setting, String> {
+            match args.split_once(' ') {
+                Some(("endpoint", url)) => Ok(Setting::Endpoint(url.to_string())),
+                Some(("batch", size)) => size
+                    .parse()
+                    .map(Setting::Batch)
+                    .map_err(|_| "expected a number of events".to_string()),
+                _ => Err("expected endpoint <url> or batch <n>".to_string()),
+            }
+        }
+    }
+
+    fn options() -> ParseOptions {
+        let mut options = ParseOptions::default();
+        options.directives.register("shipper", Shipper);
+        options
+    }
+
+    #[test]
+    fn parses_registered_directives() {
+        let input = "include /var/log\n\
+                     shipper endpoint https://logs.example.com/#ingest # primary\n\
+                     shipper batch 500\n\
+                     [profile quiet]\n\
+                     shipper batch 10";
+        let config = Config::parse_with(input, &options()).unwrap();
+
+        let values: Vec<&Setting> = config.custom_values("shipper").collect();
+        assert_eq!(
+            values,
+            [
+                &Setting::Endpoint("https://logs.example.com/#ingest".to_string()),
+                &Setting::Batch(500)
+            ]
+        );
+        assert_eq!(config.custom_values::<String>("shipper").count(), 0);
+        let quiet = config.profile("quiet").unwrap();
+        assert_eq!(
+            quiet.custom_values("shipper").last(),
+            Some(&Setting::Batch(10))
+        );
+
+        let written = config.to_string();
+        assert!(
+            written
+                .contains("shipper endpoint https://logs.example.com/#ingest\nshipper batch 500\n"),
+            "{written}"
+        );
+        assert_eq!(Config::parse_with(&written, &options()).unwrap(), config);
+    }
+
+    #[test]
+    fn reports_rejected_and_unregistered_directives() {
+        let test_cases = vec![
+            (
+                "shipper batch lots",
+                "line 1, column 9: invalid arguments 'batch lots': expected a number of events",
+            ),
+            (
+                "shipper",
+                "line 1, column 8: invalid arguments '': expected endpoint <url> or batch <n>",
+            ),
+            ("shiper batch 1", "line 1: unknown directive 'shiper'"),
+        ];
+        for (input, expected) in test_cases {
+            let err = Config::parse_with(input, &options()).unwrap_err();
+            assert!(matches!(err, ConfigError::Parse(_)));
+            assert!(err.to_string().starts_with(expected), "{err}");
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Finding the configuration file when none is given explicitly.
+
+use std::{env, path::PathBuf};
+
+use crate::{expand_tilde, Config, ConfigError, ParseOptions};
+
+/// Environment variable naming the configuration file, checked before any default location.
+pub const CONFIG_ENV: &str = "OVERWATCH_CONFIG";
+
+impl Config {
+    /// Loads the first configuration file found in [`Config::search_paths`], returning it along
+    /// with the path it was loaded from.
+    pub fn discover() -> Result<(Config, PathBuf), ConfigError> {
+        Self::discover_with(&ParseOptions::default())
+    }
+
+    /// Like [`Config::discover`], using the given options.
+    pub fn discover_with(options: &ParseOptions) -> Result<(Config, PathBuf), ConfigError> {
+        let path = Self::discover_path()?;
+        Ok((Config::from_file_with(&path, options)?, path))
+    }
+
+    /// The file [`Config::discover`] would load, without loading it.
+    pub fn discover_path() -> Result<PathBuf, ConfigError> {
+        let home = expand_tilde("~").ok().map(String::from);
+        discover_path_with(|name| env::var(name).ok(), home)
+    }
+
+    /// The locations [`Config::discover`] checks, in order:
+    ///
+    /// 1. `$OVERWATCH_CONFIG`, which must exist if it is set
+    /// 2. `$XDG_CONFIG_HOME/overwatch/config`
+    /// 3. `~/.config/overwatch/config`
+    /// 4. `/etc/overwatch/config`
+    pub fn search_paths() -> Vec<PathBuf> {
+        let home = expand_tilde("~").ok().map(String::from);
+        search_paths_with(|name| env::var(name).ok(), home)
+    }
+}
+
+fn discover_path_with<F>(var: F, home: Option<String>) -> Result<PathBuf, ConfigError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let configured = var(CONFIG_ENV).filter(|path| !path.is_empty());
+    let searched = search_paths_with(var, home);
+    match configured {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => match searched.iter().find(|path| path.is_file()) {
+            Some(path) => Ok(path.clone()),
+            None => Err(ConfigError::NotFound(searched)),
+        },
+    }
+}
+
+fn search_paths_with<F>(var: F, home: Option<String>) -> Vec<PathBuf>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let relative = "overwatch/config";
+    let mut paths = Vec::new();
+    if let Some(path) = var(CONFIG_ENV).filter(|path| !path.is_empty()) {
+        paths.push(PathBuf::from(path));
+    }
+    // Relative values are invalid according to the XDG base directory spec, and are ignored.
+    if let Some(dir) = var("XDG_CONFIG_HOME").filter(|dir| dir.starts_with('/')) {
+        paths.push(PathBuf::from(dir).join(relative));
+    }
+    if let Some(home) = home {
+        paths.push(PathBuf::from(home).join(".config").join(relative));
+    }
+    paths.push(PathBuf::from("/etc").join(relative));
+    paths.dedup();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_search_paths() {
+        let test_cases = vec![
+            (vec![], None, vec!["/etc/overwatch/config"]),
+            (
+                vec![("XDG_CONFIG_HOME", "/home/a/.xdg")],
+                Some("/home/a"),
+                vec![
+                    "/home/a/.xdg/overwatch/config",
+                    "/home/a/.config/overwatch/config",
+                    "/etc/overwatch/config",
+                ],
+            ),
+            (
+                vec![
+                    ("OVERWATCH_CONFIG", "./overwatch.conf"),
+                    ("XDG_CONFIG_HOME", "/home/a/.config"),
+                ],
+                Some("/home/a"),
+                vec![
+                    "./overwatch.conf",
+                    "/home/a/.config/overwatch/config",
+                    "/etc/overwatch/config",
+                ],
+            ),
+            (
+                vec![("OVERWATCH_CONFIG", ""), ("XDG_CONFIG_HOME", "relative")],
+                None,
+                vec!["/etc/overwatch/config"],
+            ),
+        ];
+
+        for (vars, home, expected) in test_cases {
+            let var = |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            };
+            let paths = search_paths_with(var, home.map(String::from));
+            assert_eq!(
+                paths,
+                expected.into_iter().map(PathBuf::from).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn loads_the_configured_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("overwatch.conf");
+        std::fs::write(&path, "include /etc").unwrap();
+
+        let configured = path.to_str().unwrap().to_string();
+        let found = discover_path_with(
+            |name| (name == CONFIG_ENV).then(|| configured.clone()),
+            None,
+        )
+        .unwrap();
+        assert_eq!(found, path);
+        let config = Config::from_file(&found).unwrap();
+        assert_eq!(config.includes().len(), 1);
+
+        let searched = discover_path_with(
+            |_| None,
+            Some(dir.path().join("home").display().to_string()),
+        );
+        match searched {
+            Err(ConfigError::NotFound(paths)) => assert_eq!(paths.len(), 2),
+            other => panic!("expected the file not to be found, found {other:?}"),
+        }
+    }
+}
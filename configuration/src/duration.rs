@@ -0,0 +1,86 @@
+//! Duration literals such as `500ms` or `2s`, used by directives which take a time.
+
+use std::{fmt, time::Duration};
+
+/// The units a duration literal can end with, from the largest down.
+const UNITS: [(&str, u64); 5] = [
+    ("d", 86_400_000),
+    ("h", 3_600_000),
+    ("m", 60_000),
+    ("s", 1_000),
+    ("ms", 1),
+];
+
+/// Parses a whole number followed by one of `ms`, `s`, `m`, `h` or `d`, as durations are
+/// written in configuration text, so custom [`crate::Directive`]s can take them too.
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let split = input.find(|c: char| !c.is_ascii_digit())?;
+    let (number, unit) = input.split_at(split);
+    let millis = UNITS
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .map(|(_, millis)| *millis)?;
+    let number: u64 = number.parse().ok()?;
+    number.checked_mul(millis).map(Duration::from_millis)
+}
+
+/// Writes a duration as a literal [`parse_duration`] accepts, using the largest unit which
+/// divides it evenly. Precision below a millisecond is dropped.
+pub(crate) struct DisplayDuration(pub(crate) Duration);
+
+impl fmt::Display for DisplayDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = self.0.as_millis();
+        let (name, size) = UNITS
+            .iter()
+            .find(|(_, size)| millis != 0 && millis.is_multiple_of(u128::from(*size)))
+            .copied()
+            .unwrap_or(("ms", 1));
+        write!(f, "{}{name}", millis / u128::from(size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        let test_cases = vec![
+            ("500ms", Some(Duration::from_millis(500))),
+            ("2s", Some(Duration::from_secs(2))),
+            ("10m", Some(Duration::from_secs(600))),
+            ("1h", Some(Duration::from_secs(3600))),
+            ("0s", Some(Duration::ZERO)),
+            ("2", None),
+            ("s", None),
+            ("1.5s", None),
+            ("2 s", None),
+            ("3d", Some(Duration::from_secs(3 * 86_400))),
+            ("3w", None),
+            ("-1s", None),
+            ("99999999999999999999h", None),
+        ];
+        for (input, expected) in test_cases {
+            assert_eq!(parse_duration(input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn writes_durations() {
+        let test_cases = vec![
+            (Duration::from_millis(500), "500ms"),
+            (Duration::from_millis(1500), "1500ms"),
+            (Duration::from_secs(2), "2s"),
+            (Duration::from_secs(120), "2m"),
+            (Duration::from_secs(7200), "2h"),
+            (Duration::from_secs(172_800), "2d"),
+            (Duration::ZERO, "0ms"),
+        ];
+        for (duration, expected) in test_cases {
+            let written = DisplayDuration(duration).to_string();
+            assert_eq!(written, expected);
+            assert_eq!(parse_duration(&written), Some(duration));
+        }
+    }
+}
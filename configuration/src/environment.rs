@@ -0,0 +1,172 @@
+//! Settings given in `OVERWATCH_*` environment variables, layered over a configuration file.
+
+use std::env;
+
+use crate::{Config, ConfigError, ParseError, ParseErrorKind, ParseOptions};
+
+/// The prefix of the environment variables [`Config::apply_env`] reads, followed by the upper
+/// case directive name.
+pub const ENV_PREFIX: &str = "OVERWATCH_";
+
+/// The directives which can be set from the environment, in the order they are applied.
+const ENV_DIRECTIVES: [&str; 15] = [
+    "include",
+    "exclude",
+    "events",
+    "debounce",
+    "poll_interval",
+    "max_size",
+    "only_extensions",
+    "follow_symlinks",
+    "include_hidden",
+    "rate_limit",
+    "owner",
+    "group",
+    "log_level",
+    "log_file",
+    "output",
+];
+
+impl Config {
+    /// Applies the settings given in environment variables on top of this configuration, so a
+    /// container can adjust it without mounting a file. Uses the default options.
+    ///
+    /// Each variable holds the value of the directive it's named after, written as it would be
+    /// in the file: `OVERWATCH_INCLUDE=/srv, /etc` acts as `include /srv, /etc`. The variables
+    /// are `OVERWATCH_` followed by `INCLUDE`, `EXCLUDE`, `EVENTS`, `DEBOUNCE`,
+    /// `POLL_INTERVAL`, `MAX_SIZE`, `ONLY_EXTENSIONS`, `FOLLOW_SYMLINKS`, `INCLUDE_HIDDEN`,
+    /// `RATE_LIMIT`, `OWNER`, `GROUP`, `LOG_LEVEL`, `LOG_FILE` or `OUTPUT`. Unset and empty
+    /// variables are skipped.
+    ///
+    /// The environment takes precedence following [`Config::merge`] with the variables as the
+    /// later layer, so settings replace the file's and includes and excludes extend it.
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        self.apply_env_with(&ParseOptions::default())
+    }
+
+    /// Like [`Config::apply_env`], parsing the values with the given options.
+    pub fn apply_env_with(&mut self, options: &ParseOptions) -> Result<(), ConfigError> {
+        self.apply_vars(|name| env::var(name).ok(), options)
+    }
+
+    fn apply_vars<F>(&mut self, var: F, options: &ParseOptions) -> Result<(), ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut layer = Config::default();
+        for directive in ENV_DIRECTIVES {
+            let name = format!("{ENV_PREFIX}{}", directive.to_ascii_uppercase());
+            let Some(value) = var(&name).filter(|value| !value.trim().is_empty()) else {
+                continue;
+            };
+            let parsed =
+                parse_value(directive, &value, options).map_err(|err| ConfigError::Env {
+                    var: name,
+                    error: Box::new(err),
+                })?;
+            layer.merge(parsed);
+        }
+        self.merge(layer);
+        Ok(())
+    }
+}
+
+/// Parses `value` as the arguments of `directive`, with error columns counted from the start
+/// of the value.
+fn parse_value(
+    directive: &str,
+    value: &str,
+    options: &ParseOptions,
+) -> Result<Config, ConfigError> {
+    // A line break would let the value smuggle in directives of its own.
+    if let Some(index) = value.find(['\n', '\r']) {
+        return Err(ConfigError::Parse(ParseError {
+            line: 1,
+            column: value[..index].chars().count() + 1,
+            text: value[index..].trim().to_string(),
+            kind: ParseErrorKind::Unexpected,
+        }));
+    }
+    let offset = directive.len() + 1;
+    Config::parse_with(&format!("{directive} {value}"), options).map_err(|err| match err {
+        ConfigError::Parse(mut err) => {
+            err.column = err.column.saturating_sub(offset).max(1);
+            ConfigError::Parse(err)
+        }
+        err => err,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{EventKind, LogLevel};
+
+    fn applied(base: &str, vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let mut config: Config = base.parse().unwrap();
+        let var = |name: &str| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        };
+        config.apply_vars(var, &ParseOptions::default())?;
+        Ok(config)
+    }
+
+    #[test]
+    fn overrides_the_file_with_the_environment() {
+        let config = applied(
+            "include /etc\nexclude /srv\ndebounce 1s\nlog_level warn",
+            &[
+                ("OVERWATCH_INCLUDE", "/srv, /var/log"),
+                ("OVERWATCH_EXCLUDE", "/etc/ssl"),
+                ("OVERWATCH_DEBOUNCE", "250ms"),
+                ("OVERWATCH_LOG_LEVEL", "debug"),
+                ("OVERWATCH_EVENTS", "modify"),
+                ("OVERWATCH_MAX_SIZE", " "),
+            ],
+        )
+        .unwrap();
+
+        let includes = config
+            .includes()
+            .iter()
+            .map(|entry| entry.path.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(includes, ["/etc", "/srv", "/var/log"]);
+        assert_eq!(config.excludes(), ["/etc/ssl".parse().unwrap()]);
+        assert_eq!(config.debounce(), Some(Duration::from_millis(250)));
+        assert_eq!(config.logging().level, Some(LogLevel::Debug));
+        assert_eq!(config.events(), [EventKind::Modify].into_iter().collect());
+        assert_eq!(config.max_size(), None);
+
+        let unchanged = applied("include /etc\nlog_level warn", &[]).unwrap();
+        assert_eq!(unchanged, "include /etc\nlog_level warn".parse().unwrap());
+    }
+
+    #[test]
+    fn reports_the_variable_of_invalid_values() {
+        let test_cases = vec![
+            (
+                ("OVERWATCH_MAX_SIZE", "lots"),
+                "$OVERWATCH_MAX_SIZE: line 1, column 1:",
+            ),
+            (
+                ("OVERWATCH_EXCLUDE", "/srv, /etc/["),
+                "$OVERWATCH_EXCLUDE: line 1, column 7: invalid path",
+            ),
+            (
+                ("OVERWATCH_INCLUDE", "/srv\nsource /tmp/evil"),
+                "$OVERWATCH_INCLUDE: line 1, column 5:",
+            ),
+        ];
+        for (var, expected) in test_cases {
+            let err = applied("include /etc", &[var]).unwrap_err();
+            assert!(matches!(err, ConfigError::Env { .. }), "{err:?}");
+            let message = err.to_string();
+            assert!(message.starts_with(expected), "{message}");
+        }
+    }
+}
@@ -0,0 +1,385 @@
+//! Errors produced while loading a configuration.
+
+use std::{error::Error, fmt, io, path::PathBuf};
+
+use crate::{parser::DIRECTIVES, EventKind, PatternError, UnknownUser, UnsetVariable};
+
+/// Errors which can occur while loading a configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read.
+    Io(io::Error),
+    /// A line of the configuration could not be parsed.
+    Parse(ParseError),
+    /// A file pulled in by a `source` directive failed to load.
+    Source {
+        path: PathBuf,
+        error: Box<ConfigError>,
+    },
+    /// A `source` directive would load a file which is already being loaded.
+    SourceCycle(PathBuf),
+    /// `source` directives were nested deeper than [`crate::ParseOptions::max_source_depth`].
+    SourceDepth(PathBuf),
+    /// An `OVERWATCH_*` environment variable read by [`crate::Config::apply_env`] holds a value
+    /// which could not be parsed.
+    Env {
+        var: String,
+        error: Box<ConfigError>,
+    },
+    /// No configuration file exists in any of the searched locations.
+    NotFound(Vec<PathBuf>),
+    /// A path given outside the line based syntax, such as in TOML or an override, could not be
+    /// parsed.
+    InvalidPath { path: String, error: PathError },
+    /// A TOML configuration could not be deserialized.
+    #[cfg(feature = "toml")]
+    Toml(toml::de::Error),
+    /// A value given to a [`crate::ConfigBuilder`] breaks a rule the parser enforces.
+    Invalid {
+        setting: String,
+        expected: &'static str,
+    },
+    /// Several lines failed to load, each reported as it would be on its own, in the order they
+    /// appear. A file with a single bad line fails with that line's error alone.
+    Multiple(Vec<ConfigError>),
+}
+
+impl ConfigError {
+    /// Every error this one stands for: the errors of [`ConfigError::Multiple`], or this error
+    /// alone.
+    pub fn errors(&self) -> &[ConfigError] {
+        match self {
+            ConfigError::Multiple(errors) => errors,
+            error => std::slice::from_ref(error),
+        }
+    }
+
+    /// Fails with the collected errors, if there are any.
+    pub(crate) fn collect(errors: Vec<ConfigError>) -> Result<(), ConfigError> {
+        let mut errors: Vec<ConfigError> = errors
+            .into_iter()
+            .flat_map(|error| match error {
+                ConfigError::Multiple(errors) => errors,
+                error => vec![error],
+            })
+            .collect();
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(ConfigError::Multiple(errors)),
+        }
+    }
+
+    /// Marks the error as coming from the file at `path`, marking each of several errors
+    /// separately.
+    pub(crate) fn in_file(self, path: PathBuf) -> ConfigError {
+        match self {
+            ConfigError::Multiple(errors) => ConfigError::Multiple(
+                errors
+                    .into_iter()
+                    .map(|error| error.in_file(path.clone()))
+                    .collect(),
+            ),
+            error => ConfigError::Source {
+                path,
+                error: Box::new(error),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "failed to read config: {err}"),
+            ConfigError::Parse(err) => write!(f, "{err}"),
+            ConfigError::Source { path, error } => write!(f, "{}: {error}", path.display()),
+            ConfigError::SourceCycle(path) => {
+                write!(f, "{} is already being sourced", path.display())
+            }
+            ConfigError::SourceDepth(path) => {
+                write!(f, "too many nested sources loading {}", path.display())
+            }
+            ConfigError::Env { var, error } => write!(f, "${var}: {error}"),
+            ConfigError::NotFound(searched) => {
+                f.write_str("no config file found, searched:")?;
+                for path in searched {
+                    write!(f, " {}", path.display())?;
+                }
+                Ok(())
+            }
+            ConfigError::InvalidPath { path, error } => write!(f, "invalid path '{path}': {error}"),
+            #[cfg(feature = "toml")]
+            ConfigError::Toml(err) => write!(f, "invalid TOML config: {err}"),
+            ConfigError::Invalid { setting, expected } => {
+                write!(f, "invalid {setting}, expected {expected}")
+            }
+            ConfigError::Multiple(errors) => {
+                for (index, error) in errors.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str("\n")?;
+                    }
+                    write!(f, "{error}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io(err) => Some(err),
+            ConfigError::Parse(err) => Some(err),
+            ConfigError::Source { error, .. } | ConfigError::Env { error, .. } => {
+                Some(error.as_ref())
+            }
+            ConfigError::InvalidPath { error, .. } => Some(error),
+            #[cfg(feature = "toml")]
+            ConfigError::Toml(err) => Some(err),
+            ConfigError::SourceCycle(_)
+            | ConfigError::SourceDepth(_)
+            | ConfigError::NotFound(_)
+            | ConfigError::Invalid { .. }
+            | ConfigError::Multiple(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+#[cfg(feature = "toml")]
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        ConfigError::Toml(err)
+    }
+}
+
+impl From<ParseError> for ConfigError {
+    fn from(err: ParseError) -> Self {
+        ConfigError::Parse(err)
+    }
+}
+
+/// A line of the configuration which could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The line number, starting at 1.
+    pub line: usize,
+    /// The column at which the offending text starts, starting at 1.
+    pub column: usize,
+    /// The offending text.
+    pub text: String,
+    pub kind: ParseErrorKind,
+}
+
+/// What went wrong while parsing a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The line doesn't start with a known directive. Holds the closest known directive, if any
+    /// is similar enough to be a likely typo.
+    UnknownDirective { suggestion: Option<&'static str> },
+    /// An event name which isn't a known [`EventKind`].
+    UnknownEvent,
+    /// A flag which the directive doesn't support.
+    UnknownFlag,
+    /// A directive was given nothing to act on.
+    MissingArgument { expected: &'static str },
+    /// A path could not be parsed.
+    InvalidPath(PathError),
+    /// An option which the directive doesn't support.
+    UnknownOption,
+    /// An option was given a value it doesn't accept.
+    InvalidOption { expected: &'static str },
+    /// The value of a `set` directive could not be expanded.
+    InvalidValue(PathError),
+    /// A quoted path has no closing quote.
+    UnterminatedQuote,
+    /// A quoted path contains an unsupported backslash escape.
+    InvalidEscape,
+    /// An `ignore` pattern isn't a valid regular expression. Holds the reason.
+    InvalidRegex(String),
+    /// A custom directive rejected its arguments. Holds the reason it gave.
+    InvalidArguments(String),
+    /// A line of a gitignore file loaded with `ignorefile` couldn't be parsed. Holds the reason.
+    InvalidIgnore(String),
+    /// An `if` without an `endif`, or an `else` or `endif` without an `if`.
+    UnmatchedConditional,
+    /// A line starting with `[` which isn't a `[profile <name>]` header.
+    InvalidSection,
+    /// A profile header in a file sourced from within a profile.
+    NestedProfile,
+    /// A directive which can't be used inside a `watch <name> { ... }` block.
+    InvalidInGroup,
+    /// A watch group without a closing `}`, or a `}` without a group to close.
+    UnmatchedGroup,
+    /// A line longer than a [`crate::ConfigReader`] accepts. Holds the limit in bytes.
+    LineTooLong { limit: usize },
+    /// Text which doesn't belong to the directive.
+    Unexpected,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ParseError {
+            line,
+            column,
+            text,
+            kind,
+        } = self;
+        match kind {
+            ParseErrorKind::UnknownDirective {
+                suggestion: Some(suggestion),
+            } => write!(
+                f,
+                "line {line}: unknown directive '{text}', did you mean '{suggestion}'?"
+            ),
+            ParseErrorKind::UnknownDirective { suggestion: None } => write!(
+                f,
+                "line {line}: unknown directive '{text}', expected one of: {}",
+                DIRECTIVES.join(", ")
+            ),
+            ParseErrorKind::UnknownFlag => write!(
+                f,
+                "line {line}, column {column}: unknown flag '{text}', expected -r or -s"
+            ),
+            ParseErrorKind::MissingArgument { expected } => write!(
+                f,
+                "line {line}, column {column}: expected {expected} after '{text}'"
+            ),
+            ParseErrorKind::UnknownEvent => write!(
+                f,
+                "line {line}, column {column}: unknown event '{text}', expected one of: {}",
+                EventKind::ALL.map(EventKind::as_str).join(", ")
+            ),
+            ParseErrorKind::InvalidPath(err) => write!(
+                f,
+                "line {line}, column {column}: invalid path '{text}': {err}"
+            ),
+            ParseErrorKind::UnknownOption => {
+                write!(f, "line {line}, column {column}: unknown option '{text}'")
+            }
+            ParseErrorKind::InvalidOption { expected } => write!(
+                f,
+                "line {line}, column {column}: invalid option '{text}', expected {expected}"
+            ),
+            ParseErrorKind::InvalidValue(err) => write!(
+                f,
+                "line {line}, column {column}: invalid value '{text}': {err}"
+            ),
+            ParseErrorKind::UnterminatedQuote => {
+                write!(
+                    f,
+                    "line {line}, column {column}: unterminated quote in {text}"
+                )
+            }
+            ParseErrorKind::InvalidEscape => {
+                write!(f, "line {line}, column {column}: invalid escape '{text}'")
+            }
+            ParseErrorKind::InvalidRegex(message) => write!(
+                f,
+                "line {line}, column {column}: invalid regex '{text}': {message}"
+            ),
+            ParseErrorKind::InvalidArguments(message) => write!(
+                f,
+                "line {line}, column {column}: invalid arguments '{text}': {message}"
+            ),
+            ParseErrorKind::InvalidIgnore(message) => {
+                write!(f, "line {line}: invalid ignore pattern '{text}': {message}")
+            }
+            ParseErrorKind::UnmatchedConditional if text == "if" => {
+                write!(f, "line {line}: 'if' without a matching 'endif'")
+            }
+            ParseErrorKind::UnmatchedConditional => {
+                write!(f, "line {line}: '{text}' without a matching 'if'")
+            }
+            ParseErrorKind::InvalidSection => write!(
+                f,
+                "line {line}, column {column}: invalid section header '{text}', expected [profile <name>]"
+            ),
+            ParseErrorKind::NestedProfile => write!(
+                f,
+                "line {line}: profile '{text}' can't be declared in a file sourced from a profile"
+            ),
+            ParseErrorKind::InvalidInGroup => {
+                write!(f, "line {line}: '{text}' can't be used inside a watch group")
+            }
+            ParseErrorKind::UnmatchedGroup if text == "}" => {
+                write!(f, "line {line}: '}}' without a matching 'watch <name> {{'")
+            }
+            ParseErrorKind::UnmatchedGroup => {
+                write!(f, "line {line}: watch group '{text}' without a closing '}}'")
+            }
+            ParseErrorKind::LineTooLong { limit } => {
+                write!(f, "line {line}: longer than the limit of {limit} bytes")
+            }
+            ParseErrorKind::Unexpected => {
+                write!(f, "line {line}, column {column}: unexpected '{text}'")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ParseErrorKind::InvalidPath(err) | ParseErrorKind::InvalidValue(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Why a path in the configuration could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    UnsetVariable(UnsetVariable),
+    UnknownUser(UnknownUser),
+    Pattern(PatternError),
+    /// A glob where a single file is required, such as for `log_file`.
+    Glob,
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::UnsetVariable(err) => write!(f, "{err}"),
+            PathError::UnknownUser(err) => write!(f, "{err}"),
+            PathError::Pattern(err) => write!(f, "{err}"),
+            PathError::Glob => f.write_str("expected a single file, not a glob"),
+        }
+    }
+}
+
+impl Error for PathError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PathError::UnsetVariable(err) => Some(err),
+            PathError::UnknownUser(err) => Some(err),
+            PathError::Pattern(err) => Some(err),
+            PathError::Glob => None,
+        }
+    }
+}
+
+impl From<UnsetVariable> for PathError {
+    fn from(err: UnsetVariable) -> Self {
+        PathError::UnsetVariable(err)
+    }
+}
+
+impl From<UnknownUser> for PathError {
+    fn from(err: UnknownUser) -> Self {
+        PathError::UnknownUser(err)
+    }
+}
+
+impl From<PatternError> for PathError {
+    fn from(err: PatternError) -> Self {
+        PathError::Pattern(err)
+    }
+}
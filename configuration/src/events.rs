@@ -0,0 +1,117 @@
+//! Filesystem event kinds which can be filtered on.
+
+use std::{fmt, str::FromStr};
+
+/// A kind of filesystem event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Create,
+    Modify,
+    Delete,
+    Rename,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 4] = [
+        EventKind::Create,
+        EventKind::Modify,
+        EventKind::Delete,
+        EventKind::Rename,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::Create => "create",
+            EventKind::Modify => "modify",
+            EventKind::Delete => "delete",
+            EventKind::Rename => "rename",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EventKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EventKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or(())
+    }
+}
+
+/// A set of event kinds. Defaults to every kind.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EventSet(u8);
+
+impl EventSet {
+    pub fn all() -> Self {
+        EventKind::ALL.into_iter().collect()
+    }
+
+    pub fn empty() -> Self {
+        Self(0)
+    }
+
+    pub fn insert(&mut self, kind: EventKind) {
+        self.0 |= kind.bit();
+    }
+
+    pub fn contains(&self, kind: EventKind) -> bool {
+        self.0 & kind.bit() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = EventKind> + '_ {
+        EventKind::ALL
+            .into_iter()
+            .filter(|kind| self.contains(*kind))
+    }
+}
+
+impl Default for EventSet {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl FromIterator<EventKind> for EventSet {
+    fn from_iter<T: IntoIterator<Item = EventKind>>(iter: T) -> Self {
+        let mut set = EventSet::empty();
+        for kind in iter {
+            set.insert(kind);
+        }
+        set
+    }
+}
+
+impl fmt::Debug for EventSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl fmt::Display for EventSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, kind) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{kind}")?;
+        }
+        Ok(())
+    }
+}
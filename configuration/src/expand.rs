@@ -0,0 +1,331 @@
+//! Expansion of environment variables and home directories inside configured paths.
+
+use std::{borrow::Cow, env, error::Error, fmt};
+
+use crate::ParseOptions;
+
+/// Controls how `$VAR` and `${VAR}` references in paths are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EnvMode {
+    /// Expand references while parsing, replacing unset variables with an empty string.
+    #[default]
+    Expand,
+    /// Expand references while parsing, failing if a variable is unset.
+    Require,
+    /// Leave references untouched so they can be expanded later with [`expand_env`].
+    Defer,
+}
+
+/// A referenced variable was set neither by the configuration nor in the environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsetVariable(pub String);
+
+impl fmt::Display for UnsetVariable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "variable '{}' is not set", self.0)
+    }
+}
+
+impl Error for UnsetVariable {}
+
+/// Expands `$VAR` and `${VAR}` references in `input` using the process environment.
+///
+/// A `$` which isn't followed by a variable name is kept as-is. With [`EnvMode::Defer`] the input
+/// is returned unchanged.
+pub fn expand_env(input: &str, mode: EnvMode) -> Result<Cow<'_, str>, UnsetVariable> {
+    expand_with(input, mode, |name| env::var(name).ok())
+}
+
+pub(crate) fn expand_with<F>(
+    input: &str,
+    mode: EnvMode,
+    lookup: F,
+) -> Result<Cow<'_, str>, UnsetVariable>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if mode == EnvMode::Defer {
+        return Ok(Cow::Borrowed(input));
+    }
+    substitute(input, mode, lookup)
+}
+
+/// Expands references to the configuration variables in [`ParseOptions::variables`], falling
+/// back to the environment as [`ParseOptions::env`] describes. With [`EnvMode::Defer`] only
+/// configuration variables are expanded and other references are kept as written.
+pub(crate) fn expand_variables<'a>(
+    input: &'a str,
+    options: &ParseOptions,
+) -> Result<Cow<'a, str>, UnsetVariable> {
+    if options.variables.is_empty() {
+        return expand_env(input, options.env);
+    }
+    substitute(input, options.env, |name| {
+        options
+            .variables
+            .get(name)
+            .cloned()
+            .or_else(|| match options.env {
+                EnvMode::Defer => None,
+                _ => env::var(name).ok(),
+            })
+    })
+}
+
+fn substitute<F>(input: &str, mode: EnvMode, lookup: F) -> Result<Cow<'_, str>, UnsetVariable>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if !input.contains('$') {
+        return Ok(Cow::Borrowed(input));
+    }
+
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, consumed) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+
+        if !is_variable_name(name) {
+            output.push('$');
+            rest = after;
+            continue;
+        }
+
+        match lookup(name) {
+            Some(value) => output.push_str(&value),
+            None if mode == EnvMode::Require => return Err(UnsetVariable(name.to_string())),
+            None if mode == EnvMode::Defer => {
+                output.push('$');
+                output.push_str(&after[..consumed]);
+            }
+            None => {}
+        }
+        rest = &after[consumed..];
+    }
+    output.push_str(rest);
+
+    Ok(Cow::Owned(output))
+}
+
+/// The home directory of a user referenced with `~` could not be determined.
+///
+/// An empty name refers to the current user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownUser(pub String);
+
+impl fmt::Display for UnknownUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "could not determine the current user's home directory")
+        } else {
+            write!(
+                f,
+                "could not determine the home directory of user '{}'",
+                self.0
+            )
+        }
+    }
+}
+
+impl Error for UnknownUser {}
+
+/// Expands a leading `~` or `~user` in `input` to the matching home directory.
+pub fn expand_tilde(input: &str) -> Result<Cow<'_, str>, UnknownUser> {
+    tilde_with(input, |user| {
+        if user.is_empty() {
+            env::var("HOME").ok().or_else(|| home_dir(None))
+        } else {
+            home_dir(Some(user))
+        }
+    })
+}
+
+pub(crate) fn tilde_with<F>(input: &str, lookup: F) -> Result<Cow<'_, str>, UnknownUser>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let Some(rest) = input.strip_prefix('~') else {
+        return Ok(Cow::Borrowed(input));
+    };
+    let (user, tail) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    match lookup(user) {
+        Some(home) => Ok(Cow::Owned(format!("{}{tail}", home.trim_end_matches('/')))),
+        None => Err(UnknownUser(user.to_string())),
+    }
+}
+
+#[cfg(unix)]
+fn home_dir(user: Option<&str>) -> Option<String> {
+    use std::{
+        ffi::{CStr, CString},
+        mem, ptr,
+    };
+
+    let name = user.map(CString::new).transpose().ok()?;
+    let mut buf = vec![0; 16 * 1024];
+    // SAFETY: passwd is a plain C struct for which all-zeroes is a valid value.
+    let mut pwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut result = ptr::null_mut();
+    // SAFETY: every pointer refers to a live, correctly sized buffer owned by this frame.
+    let rc = unsafe {
+        match &name {
+            Some(name) => libc::getpwnam_r(
+                name.as_ptr(),
+                &mut pwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            ),
+            None => libc::getpwuid_r(
+                libc::getuid(),
+                &mut pwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            ),
+        }
+    };
+    if rc != 0 || result.is_null() || pwd.pw_dir.is_null() {
+        return None;
+    }
+    // SAFETY: getpw*_r succeeded so pw_dir points at a NUL terminated string inside buf.
+    let dir = unsafe { CStr::from_ptr(pwd.pw_dir) };
+    dir.to_str().ok().map(str::to_string)
+}
+
+#[cfg(not(unix))]
+fn home_dir(user: Option<&str>) -> Option<String> {
+    match user {
+        None => env::var("USERPROFILE").ok(),
+        Some(_) => None,
+    }
+}
+
+pub(crate) fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/user".to_string()),
+            "XDG_DATA_HOME" => Some("/home/user/.local/share".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expands_variables() {
+        let test_cases = vec![
+            ("/etc/hosts", "/etc/hosts"),
+            ("$HOME/projects", "/home/user/projects"),
+            ("${XDG_DATA_HOME}/app", "/home/user/.local/share/app"),
+            ("${HOME}${HOME}", "/home/user/home/user"),
+            ("/tmp/$UNSET/x", "/tmp//x"),
+            ("/tmp/$1/cost$", "/tmp/$1/cost$"),
+            ("/tmp/${HOME", "/tmp/${HOME"),
+        ];
+
+        for test_case in test_cases {
+            let actual = expand_with(test_case.0, EnvMode::Expand, lookup).unwrap();
+            assert_eq!(test_case.1, actual);
+        }
+    }
+
+    #[test]
+    fn expands_tilde() {
+        let lookup = |user: &str| match user {
+            "" => Some("/home/me".to_string()),
+            "alice" => Some("/home/alice/".to_string()),
+            _ => None,
+        };
+        let test_cases = vec![
+            ("/etc/hosts", Ok("/etc/hosts")),
+            ("~", Ok("/home/me")),
+            ("~/projects", Ok("/home/me/projects")),
+            ("~alice/notes", Ok("/home/alice/notes")),
+            ("/tmp/~alice", Ok("/tmp/~alice")),
+            ("~bob/notes", Err(UnknownUser("bob".to_string()))),
+        ];
+
+        for test_case in test_cases {
+            let actual = tilde_with(test_case.0, lookup);
+            assert_eq!(test_case.1.map(Cow::Borrowed), actual);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn looks_up_home_directories() {
+        // Where root's home is differs between systems, such as /var/root on macOS.
+        let root = home_dir(Some("root")).unwrap();
+        assert!(root.starts_with('/'), "{root}");
+        assert_eq!(expand_tilde("~root/x").unwrap(), format!("{root}/x"));
+        let lookup = |user: &str| home_dir(Some(user));
+        assert_eq!(tilde_with("~root", lookup).unwrap(), root);
+        assert!(expand_tilde("~overwatch-no-such-user").is_err());
+    }
+
+    #[test]
+    fn expands_config_variables() {
+        let mut options = ParseOptions::default();
+        options
+            .variables
+            .insert("LOGDIR".to_string(), "/var/log".to_string());
+
+        let test_cases = vec![
+            (
+                EnvMode::Expand,
+                "$LOGDIR/${OVERWATCH_TEST_UNSET}x",
+                Ok("/var/log/x"),
+            ),
+            (
+                EnvMode::Defer,
+                "${LOGDIR}/${OVERWATCH_TEST_UNSET}x",
+                Ok("/var/log/${OVERWATCH_TEST_UNSET}x"),
+            ),
+            (
+                EnvMode::Require,
+                "$LOGDIR/$OVERWATCH_TEST_UNSET",
+                Err(UnsetVariable("OVERWATCH_TEST_UNSET".to_string())),
+            ),
+        ];
+        for (mode, input, expected) in test_cases {
+            options.env = mode;
+            assert_eq!(
+                expand_variables(input, &options),
+                expected.map(|s| Cow::Owned(s.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn handles_modes() {
+        assert_eq!(
+            Err(UnsetVariable("UNSET".to_string())),
+            expand_with("$HOME/$UNSET", EnvMode::Require, lookup)
+        );
+        assert_eq!(
+            "$HOME/$UNSET",
+            expand_with("$HOME/$UNSET", EnvMode::Defer, lookup).unwrap()
+        );
+    }
+}
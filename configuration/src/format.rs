@@ -0,0 +1,330 @@
+//! Rewrites configuration text into a canonical layout, keeping its comments.
+
+use crate::{
+    parser::{comment_start, opens_group, resolve_alias, split_group_line},
+    reader::is_continued,
+};
+
+/// How far each level of an `if` block or watch group is indented.
+const INDENT: &str = "  ";
+
+/// Formats configuration text so that equivalent files are laid out the same way.
+///
+/// - Lines are trimmed, runs of blank lines are collapsed into one, and the lines inside `if`
+///   blocks and watch groups are indented by two spaces per level. A watch group written on
+///   one line is kept as written.
+/// - The paths of an `include`, `exclude` or `ignorefile` are sorted and deduplicated, and
+///   empty elements dropped. `source` paths keep their order, since later files take
+///   precedence.
+/// - A path list which was continued over several lines is written one path per line, with the
+///   continuation lines aligned under the first path.
+/// - Whitespace between a directive, its flag, paths and options is reduced to a single space,
+///   and a trailing comment is separated from the directive by one. Arguments which run to the
+///   end of the line, such as `ignore` patterns and commands, are kept as written.
+///
+/// Comments are kept where they are. Lines which can't be split into a path list are only
+/// trimmed, so formatting never changes what a valid configuration means.
+pub fn format(input: &str) -> String {
+    let mut output = String::new();
+    let mut depth = 0;
+    let mut blank = false;
+    for (line, continued) in logical_lines(input) {
+        let line = line.trim();
+        if line.is_empty() {
+            blank = !output.is_empty();
+            continue;
+        }
+        if blank {
+            output.push('\n');
+            blank = false;
+        }
+
+        let (guard, body) = split_guard(line);
+        let name = body.split_whitespace().next().unwrap_or_default();
+        if matches!(name, "else" | "endif") || body.starts_with('}') {
+            depth = usize::saturating_sub(depth, 1);
+        }
+        let indent = INDENT.repeat(depth);
+        output.push_str(&indent);
+        let group = guard.is_none() && opens_group(body);
+        if body.starts_with('#') || guard.is_none() && body.starts_with('[') {
+            output.push_str(&format_header(body));
+        } else if group || body.starts_with('}') {
+            let end = comment_start(body);
+            output.push_str(&with_comment(
+                body[..end].trim_end().to_string(),
+                &body[end..],
+            ));
+        } else {
+            let mut prefix = String::new();
+            if let Some(guard) = guard {
+                prefix.push_str(guard);
+                prefix.push(' ');
+            }
+            prefix.push_str(name);
+            output.push_str(&format_directive(
+                prefix,
+                name,
+                &body[name.len()..],
+                continued.then_some(indent.len()),
+            ));
+        }
+        output.push('\n');
+        if matches!(name, "if" | "else") || group && split_group_line(body).is_none() {
+            depth += 1;
+        }
+    }
+    output
+}
+
+/// Joins lines ending with a backslash onto the next, as the reader does, returning each
+/// logical line and whether it was continued.
+fn logical_lines(input: &str) -> Vec<(String, bool)> {
+    let mut lines = Vec::new();
+    let mut current: Option<(String, bool)> = None;
+    for raw in input.lines() {
+        let (joined, continued) = match current.take() {
+            Some((mut joined, _)) => {
+                joined.push_str(raw);
+                (joined, true)
+            }
+            None => (raw.to_string(), false),
+        };
+        match joined.strip_suffix('\\') {
+            Some(head) if is_continued(joined.as_bytes()) => {
+                current = Some((head.to_string(), continued))
+            }
+            _ => lines.push((joined, continued)),
+        }
+    }
+    lines.extend(current);
+    lines
+}
+
+/// Splits a leading `@<os>` guard off a line.
+fn split_guard(line: &str) -> (Option<&str>, &str) {
+    match line.strip_prefix('@') {
+        Some(rest) => {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            (Some(&line[..end + 1]), rest[end..].trim_start())
+        }
+        None => (None, line),
+    }
+}
+
+/// Comments are kept as written. Profile headers lose the padding inside their brackets.
+fn format_header(line: &str) -> String {
+    let header = line
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once(']'))
+        .filter(|(_, comment)| comment.trim().is_empty() || comment.trim().starts_with('#'));
+    match header {
+        Some((inner, comment)) => {
+            let inner = inner.split_whitespace().collect::<Vec<_>>().join(" ");
+            with_comment(format!("[{inner}]"), comment)
+        }
+        None => line.to_string(),
+    }
+}
+
+/// Formats a directive. `continued_at` is the column the line is indented to if its paths were
+/// continued over several lines.
+fn format_directive(
+    mut formatted: String,
+    name: &str,
+    args: &str,
+    continued_at: Option<usize>,
+) -> String {
+    let directive = resolve_alias(name);
+    if !matches!(directive, "include" | "exclude" | "ignorefile" | "source") {
+        let end = comment_start(args);
+        let value = args[..end].trim();
+        if !value.is_empty() {
+            formatted.push(' ');
+            formatted.push_str(value);
+        }
+        return with_comment(formatted, &args[end..]);
+    }
+
+    let mut rest = args.trim_start();
+    if directive == "include" {
+        if let Some(flag @ ("-r" | "-s")) = rest.split_whitespace().next() {
+            formatted.push(' ');
+            formatted.push_str(flag);
+            rest = rest[flag.len()..].trim_start();
+        }
+    }
+    let Some((mut paths, tail)) = split_paths(rest) else {
+        let end = comment_start(args);
+        formatted.push(' ');
+        formatted.push_str(args[..end].trim());
+        return with_comment(formatted, &args[end..]);
+    };
+    if directive != "source" {
+        paths.sort_unstable();
+        paths.dedup();
+    }
+
+    formatted.push(' ');
+    match continued_at {
+        Some(indent) if paths.len() > 1 => {
+            let align = " ".repeat(indent + formatted.len());
+            formatted.push_str(&paths.join(&format!(", \\\n{align}")));
+        }
+        _ => formatted.push_str(&paths.join(", ")),
+    }
+
+    let end = comment_start(tail);
+    let options = collapse_whitespace(&tail[..end]);
+    if !options.is_empty() {
+        formatted.push(' ');
+        formatted.push_str(&options);
+    }
+    with_comment(formatted, &tail[end..])
+}
+
+/// Splits a comma separated path list off the start of `input`, returning the paths as written
+/// and the text after them. Empty elements are dropped. Returns `None` if the list is empty or a
+/// quote isn't closed.
+fn split_paths(input: &str) -> Option<(Vec<&str>, &str)> {
+    let mut paths = Vec::new();
+    let mut rest = input;
+    loop {
+        rest = rest.trim_start();
+        let len = if rest.starts_with('"') {
+            quoted_len(rest)?
+        } else {
+            rest.find(|c: char| c == ',' || c == '#' || c.is_whitespace())
+                .unwrap_or(rest.len())
+        };
+        if len > 0 {
+            paths.push(&rest[..len]);
+        }
+        rest = &rest[len..];
+        match rest.trim_start().strip_prefix(',') {
+            Some(after) => rest = after,
+            None if paths.is_empty() => return None,
+            None => return Some((paths, rest)),
+        }
+    }
+}
+
+/// The length of the double quoted string `input` starts with, including the quotes.
+fn quoted_len(input: &str) -> Option<usize> {
+    let mut chars = input.char_indices().skip(1);
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some(index + 1),
+            '\\' => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Reduces each run of whitespace outside double quotes to a single space.
+fn collapse_whitespace(input: &str) -> String {
+    let mut output = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    for c in input.trim().chars() {
+        if quoted {
+            output.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => quoted = false,
+                _ => {}
+            }
+        } else if c.is_whitespace() {
+            if !output.ends_with(' ') {
+                output.push(' ');
+            }
+        } else {
+            quoted = c == '"';
+            output.push(c);
+        }
+    }
+    output
+}
+
+/// Appends a trailing comment, if there is one, separated by a single space.
+fn with_comment(mut line: String, comment: &str) -> String {
+    let comment = comment.trim();
+    if !comment.is_empty() {
+        line.push(' ');
+        line.push_str(comment);
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn formats_config_text() {
+        let test_cases = vec![
+            (
+                "include   /srv/b ,/srv/a,/srv/b   # apps  \n",
+                "include /srv/a, /srv/b # apps\n",
+            ),
+            ("\n\n  exclude /tmp\n\n\n\nevents  create\n\n", "exclude /tmp\n\nevents create\n"),
+            (
+                "include -r   /var/log   depth=2    on_change \"systemctl  reload\"",
+                "include -r /var/log depth=2 on_change \"systemctl  reload\"\n",
+            ),
+            (
+                "include \"/srv/a b\",, /srv/$APP,",
+                "include \"/srv/a b\", /srv/$APP\n",
+            ),
+            (
+                "source /etc/z.conf, /etc/a.conf",
+                "source /etc/z.conf, /etc/a.conf\n",
+            ),
+            (
+                "include /srv/c, \\\n   /srv/a,\\\n /srv/b events=modify",
+                "include /srv/a, \\\n        /srv/b, \\\n        /srv/c events=modify\n",
+            ),
+            (
+                "if host=web*\ninclude /var/www\n if os=linux\n@linux   exclude /proc\nendif\nelse\n# other hosts\n include /srv\nendif",
+                "if host=web*\n  include /var/www\n  if os=linux\n    @linux exclude /proc\n  endif\nelse\n  # other hosts\n  include /srv\nendif\n",
+            ),
+            ("[profile   on call ]   # pager", "[profile on call] # pager\n"),
+            ("ignore   \\.swp$   # vim", "ignore \\.swp$ # vim\n"),
+            ("on modify   run  make  all", "on modify   run  make  all\n"),
+            ("#  spaced   comment  ", "#  spaced   comment\n"),
+            (
+                "watch web {\ninclude /srv/b,/srv/a\n  }   # web\nwatch db {  include /db;  events modify }",
+                "watch web {\n  include /srv/a, /srv/b\n} # web\nwatch db {  include /db;  events modify }\n",
+            ),
+            ("include \"/unterminated, /b", "include \"/unterminated, /b\n"),
+        ];
+        for (input, expected) in test_cases {
+            let formatted = format(input);
+            assert_eq!(formatted, expected, "{input:?}");
+            assert_eq!(format(&formatted), formatted, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn keeps_the_meaning_of_the_config() {
+        let input = "include /srv/b, /srv/a, \\\n  /srv/a\nexclude   /srv/a/tmp\n\
+                     if os=none\ninclude /never\nendif\n\
+                     on delete run echo gone # notify\n[profile  work]\ninclude -r /work";
+        let before: Config = input.parse().unwrap();
+        let after: Config = format(input).parse().unwrap();
+        assert_eq!(after.excludes(), before.excludes());
+        assert_eq!(after.actions(), before.actions());
+        assert_eq!(after.profiles().collect::<Vec<_>>(), ["work"]);
+
+        let before = before.profile("work").unwrap();
+        let after = after.profile("work").unwrap();
+        for path in ["/srv/a", "/srv/b/c", "/srv/a/tmp/x", "/never", "/work/a/b"] {
+            assert_eq!(after.is_watched(path), before.is_watched(path), "{path}");
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Named sets of includes declared together in a `watch <name> { ... }` block.
+
+use std::path::Path;
+
+use crate::{
+    matcher::{is_hidden_under, rule_for},
+    Config, MatchedRule, PathSpec, WatchEntry, WatchOptions,
+};
+
+/// The includes and excludes of a `watch <name> { ... }` block, along with the settings and
+/// actions given in the block.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct WatchGroup {
+    pub name: String,
+    /// The `events`, `debounce`, `max_size`, `only_extensions`, `follow_symlinks`,
+    /// `include_hidden` and `rate_limit` directives and `on` actions of the block. Recursion and
+    /// depth are left to each include. The rate limit is shared by all of the group's events,
+    /// on top of any limit of the include they come from.
+    pub options: WatchOptions,
+    pub includes: Vec<WatchEntry>,
+    /// Excludes which only carve paths out of the group's own includes.
+    pub excludes: Vec<PathSpec>,
+}
+
+impl WatchGroup {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// The group's includes with the group's settings filled in. Options an include sets itself
+    /// win over the group's, and the group's actions and tags come before the include's own.
+    /// The group's rate limit isn't copied to its includes, since they share it.
+    pub fn entries(&self) -> impl Iterator<Item = WatchEntry> + '_ {
+        let group = &self.options;
+        self.includes.iter().map(move |entry| {
+            let own = &entry.options;
+            WatchEntry {
+                path: entry.path.clone(),
+                options: WatchOptions {
+                    recursion: own.recursion,
+                    max_depth: own.max_depth,
+                    events: own.events.or(group.events),
+                    actions: group.actions.iter().chain(&own.actions).cloned().collect(),
+                    debounce: own.debounce.or(group.debounce),
+                    max_size: own.max_size.or(group.max_size),
+                    extensions: own.extensions.clone().or_else(|| group.extensions.clone()),
+                    follow_symlinks: own.follow_symlinks.or(group.follow_symlinks),
+                    include_hidden: own.include_hidden.or(group.include_hidden),
+                    rate_limit: own.rate_limit,
+                    tags: merge_tags(&group.tags, &own.tags),
+                },
+            }
+        })
+    }
+}
+
+impl Config {
+    /// The include of the group which covers `path` with the group's settings filled in,
+    /// leaving out paths carved out by the group's excludes or the global ones.
+    pub(crate) fn group_entry_for(&self, group: &WatchGroup, path: &Path) -> Option<WatchEntry> {
+        self.group_include_for(group, path)
+            .filter(|entry| self.include_hidden_for(entry) || !is_hidden_under(entry, path))
+    }
+
+    /// Returns true if the include of the group covering `path` leaves it out as hidden.
+    pub(crate) fn is_hidden_in_group(&self, group: &WatchGroup, path: &Path) -> bool {
+        self.group_include_for(group, path)
+            .is_some_and(|entry| !self.include_hidden_for(&entry) && is_hidden_under(&entry, path))
+    }
+
+    /// The include of the group covering `path`, whether or not it's hidden below it.
+    fn group_include_for(&self, group: &WatchGroup, path: &Path) -> Option<WatchEntry> {
+        let entries: Vec<WatchEntry> = group.entries().collect();
+        let excludes: Vec<PathSpec> = self
+            .excludes
+            .iter()
+            .chain(&group.excludes)
+            .cloned()
+            .collect();
+        match rule_for(&entries, &excludes, path) {
+            Some(MatchedRule::Include(entry)) => Some(entry.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// The tags in `first` followed by the ones in `second` which aren't already among them.
+pub(crate) fn merge_tags(first: &[String], second: &[String]) -> Vec<String> {
+    let mut tags = first.to_vec();
+    for tag in second {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, EventKind, EventSet};
+
+    #[test]
+    fn parses_watch_groups() {
+        let input = "include /srv\n\
+                     watch webserver {\n\
+                     \x20 include -r /etc/nginx debounce=2s\n\
+                     \x20 exclude /etc/nginx/cache\n\
+                     \x20 events modify\n\
+                     \x20 debounce 500ms\n\
+                     \x20 on modify run systemctl reload nginx\n\
+                     }\n\
+                     watch db { include /etc/postgresql events=create; on any run logger db }\n\
+                     exclude /etc/nginx/secret";
+        let config: Config = input.parse().unwrap();
+        assert_eq!(config.includes().len(), 1);
+        assert_eq!(
+            config
+                .groups()
+                .iter()
+                .map(|g| g.name.as_str())
+                .collect::<Vec<_>>(),
+            ["webserver", "db"]
+        );
+
+        let web = config.group("webserver").unwrap();
+        assert_eq!(web.excludes, ["/etc/nginx/cache".parse().unwrap()]);
+        let entries: Vec<_> = web.entries().collect();
+        assert_eq!(entries[0].path, "/etc/nginx".parse().unwrap());
+        assert_eq!(
+            config.events_for(&entries[0]),
+            EventSet::from_iter([EventKind::Modify])
+        );
+        assert_eq!(
+            config.debounce_for(&entries[0]),
+            Some(std::time::Duration::from_secs(2))
+        );
+        assert_eq!(
+            entries[0].options.actions[0].command,
+            "systemctl reload nginx"
+        );
+
+        let db = config.group("db").unwrap();
+        let entries: Vec<_> = db.entries().collect();
+        assert_eq!(
+            config.events_for(&entries[0]),
+            EventSet::from_iter([EventKind::Create])
+        );
+        assert_eq!(entries[0].options.actions[0].command, "logger db");
+
+        for (path, watched) in [
+            ("/etc/nginx/nginx.conf", true),
+            ("/etc/nginx/cache/a", false),
+            ("/etc/nginx/secret", false),
+            ("/etc/postgresql/pg.conf", true),
+            ("/srv/cache", true),
+            ("/etc/hosts", false),
+        ] {
+            assert_eq!(config.is_watched(path), watched, "{path}");
+        }
+
+        let written = config.to_string();
+        assert!(
+            written.contains("watch webserver {\n  include -r /etc/nginx debounce=2s\n"),
+            "{written}"
+        );
+        assert_eq!(written.parse::<Config>().unwrap(), config);
+    }
+
+    #[test]
+    fn reports_misplaced_directives_and_braces() {
+        let test_cases = vec![
+            (
+                "watch web {\ninclude /srv\nlog_level info\n}",
+                "line 3: 'log_level' can't be used inside a watch group",
+            ),
+            (
+                "watch web {\nwatch db {\n}\n}",
+                "line 2: 'watch' can't be used inside a watch group",
+            ),
+            (
+                "include /srv\n\nwatch web {\ninclude /srv",
+                "line 3: watch group 'web' without a closing '}'",
+            ),
+            (
+                "watch web {\n[profile x]\n}",
+                "line 1: watch group 'web' without a closing '}'",
+            ),
+            (
+                "include /srv\n}",
+                "line 2: '}' without a matching 'watch <name> {'",
+            ),
+            (
+                "watch web { include /srv; nope /x }",
+                "line 1: unknown directive 'nope'",
+            ),
+            (
+                "watch web { include /srv; exclude /srv/[ }",
+                "line 1, column 35: invalid path",
+            ),
+        ];
+        for (input, expected) in test_cases {
+            let err = input.parse::<Config>().unwrap_err().to_string();
+            assert!(err.starts_with(expected), "{input:?}: {err}");
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Paths whose events are dropped, declared with `ignore <regex>` or loaded from gitignore
+//! files with `ignorefile <path>`.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use ::ignore::gitignore::{Gitignore, GitignoreBuilder};
+use regex::{Regex, RegexSet, RegexSetBuilder};
+
+use crate::{ConfigError, ParseError, ParseErrorKind};
+
+/// The size limit the regex crate applies to a single compiled expression by default.
+const SIZE_LIMIT: usize = 10 * (1 << 20);
+
+/// Every `ignore` pattern and `ignorefile` of a configuration. The patterns are compiled into a
+/// single [`RegexSet`] the first time a path is matched against them.
+#[derive(Clone, Default)]
+pub struct IgnoreSet {
+    patterns: Vec<String>,
+    set: OnceLock<RegexSet>,
+    files: Vec<IgnoreFile>,
+}
+
+impl IgnoreSet {
+    /// The patterns in the order they were declared.
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// The gitignore files in the order they were declared.
+    pub fn files(&self) -> &[IgnoreFile] {
+        &self.files
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty() && self.files.is_empty()
+    }
+
+    /// Returns true if any pattern matches somewhere in `path`, or a gitignore file ignores it.
+    pub fn matches(&self, path: &Path) -> bool {
+        if !self.patterns.is_empty() && self.set().is_match(&path.to_string_lossy()) {
+            return true;
+        }
+        self.files.iter().any(|file| file.matches(path))
+    }
+
+    /// Adds a pattern which has already been checked by [`check`].
+    pub(crate) fn push(&mut self, pattern: String) {
+        if !self.patterns.contains(&pattern) {
+            self.patterns.push(pattern);
+            self.set = OnceLock::new();
+        }
+    }
+
+    pub(crate) fn push_file(&mut self, file: IgnoreFile) {
+        if !self.files.contains(&file) {
+            self.files.push(file);
+        }
+    }
+
+    fn set(&self) -> &RegexSet {
+        self.set.get_or_init(|| {
+            RegexSetBuilder::new(&self.patterns)
+                .size_limit(SIZE_LIMIT * self.patterns.len())
+                .build()
+                .expect("ignore patterns are checked individually while parsing")
+        })
+    }
+}
+
+impl PartialEq for IgnoreSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.patterns == other.patterns && self.files == other.files
+    }
+}
+
+impl fmt::Debug for IgnoreSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IgnoreSet")
+            .field("patterns", &self.patterns)
+            .field("files", &self.files)
+            .finish()
+    }
+}
+
+/// A gitignore file, whose rules apply to paths under the directory containing it.
+///
+/// Negation with `!`, directory-only patterns ending in `/` and anchoring with a leading or
+/// inner `/` all follow git's semantics.
+#[derive(Clone)]
+pub struct IgnoreFile {
+    path: PathBuf,
+    matcher: Gitignore,
+}
+
+impl IgnoreFile {
+    /// Reads and parses the gitignore file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = fs::canonicalize(path)?;
+        let input = fs::read_to_string(&path)?;
+        let root = path.parent().unwrap_or(Path::new("/"));
+        let mut builder = GitignoreBuilder::new(root);
+        for (index, line) in input.lines().enumerate() {
+            if let Err(err) = builder.add_line(Some(path.clone()), line) {
+                return Err(ConfigError::Parse(ParseError {
+                    line: index + 1,
+                    column: 1,
+                    text: line.trim().to_string(),
+                    kind: ParseErrorKind::InvalidIgnore(err.to_string()),
+                }));
+            }
+        }
+        let matcher = builder.build().map_err(io::Error::other)?;
+        Ok(Self { path, matcher })
+    }
+
+    /// The canonical path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true if `path`, or a directory above it, is ignored. Paths outside the
+    /// directory containing the file never are.
+    pub fn matches(&self, path: &Path) -> bool {
+        path.starts_with(self.matcher.path())
+            && self
+                .matcher
+                .matched_path_or_any_parents(path, path.is_dir())
+                .is_ignore()
+    }
+}
+
+impl PartialEq for IgnoreFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
+}
+
+impl fmt::Debug for IgnoreFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("IgnoreFile").field(&self.path).finish()
+    }
+}
+
+/// Checks that `pattern` compiles, returning the reason it doesn't otherwise.
+pub(crate) fn check(pattern: &str) -> Result<(), String> {
+    match Regex::new(pattern) {
+        Ok(_) => Ok(()),
+        Err(regex::Error::CompiledTooBig(limit)) => {
+            Err(format!("compiled size exceeds the limit of {limit} bytes"))
+        }
+        Err(err) => {
+            let message = err.to_string();
+            let last = message.lines().last().unwrap_or_default();
+            Err(last.trim_start_matches("error: ").to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_paths() {
+        let mut set = IgnoreSet::default();
+        assert!(!set.matches(Path::new("/tmp/a.swp")));
+
+        set.push(r"\.swp$".to_string());
+        set.push("~$".to_string());
+        set.push("~$".to_string());
+        assert_eq!(set.patterns(), [r"\.swp$", "~$"]);
+
+        let test_cases = vec![
+            ("/home/a/.notes.txt.swp", true),
+            ("/home/a/notes.txt~", true),
+            ("/home/a/notes.swp.txt", false),
+            ("/home/a/notes.txt", false),
+        ];
+        for (path, ignored) in test_cases {
+            assert_eq!(set.matches(Path::new(path)), ignored, "{path}");
+        }
+    }
+
+    #[test]
+    fn applies_gitignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir_all(root.join("build/keep")).unwrap();
+        fs::create_dir_all(root.join("src/logs")).unwrap();
+        fs::write(
+            root.join(".gitignore"),
+            "# generated\n*.log\n!important.log\n/build/\nlogs/\n\\#notes\n",
+        )
+        .unwrap();
+        let file = IgnoreFile::load(root.join(".gitignore")).unwrap();
+
+        let test_cases = vec![
+            ("src/debug.log", true),
+            ("src/important.log", false),
+            ("build/keep/a.rs", true),
+            ("src/build", false),
+            ("src/logs/today", true),
+            ("#notes", true),
+            ("src/main.rs", false),
+        ];
+        for (path, ignored) in test_cases {
+            assert_eq!(file.matches(&root.join(path)), ignored, "{path}");
+        }
+        assert!(!file.matches(Path::new("/elsewhere/debug.log")));
+
+        let mut set = IgnoreSet::default();
+        set.push_file(file);
+        assert!(set.matches(&root.join("a.log")));
+
+        fs::write(root.join("bad"), "ok\n[z-a]\n").unwrap();
+        assert!(matches!(
+            IgnoreFile::load(root.join("bad")),
+            Err(ConfigError::Parse(ParseError { line: 2, .. }))
+        ));
+    }
+
+    #[test]
+    fn checks_patterns() {
+        assert_eq!(check(r"\.swp$"), Ok(()));
+        assert_eq!(check(r"\.swp("), Err("unclosed group".to_string()));
+    }
+}
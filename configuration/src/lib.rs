@@ -1,111 +1,1718 @@
 //! Configuration parsing crate.
 //! Example configuration:
 //! ```ignore
+//! # Lines starting with a hash are comments
+//! set LOGDIR /var/log
+//! include $LOGDIR/nginx
 //! include /etc/passwd
 //! include /home/user
-//! exclude /home/user/.local
+//! exclude /home/user/.local # comments can also trail a directive
+//! exclude /home/*/.cache, /var/log/**/*.gz
+//! exclude ${XDG_DATA_HOME}/Trash
+//! include "/home/user/My Documents", "/srv/a,b"
+//! include /srv/app/config, \
+//!         /srv/app/templates
+//! source /etc/overwatch/conf.d/*.conf
+//! include -r /var/log
+//! include -s /srv/www
+//! include /data depth=3
+//! events create, modify, delete
+//! debounce 500ms
+//! poll_interval 5s
+//! log_level info
+//! log_file /var/log/overwatch.log
+//! output ndjson
+//! include /etc events=modify
+//! on modify run systemctl reload nginx
+//! include /etc/nginx on_change "nginx -t && systemctl reload nginx"
+//! ignore \.swp$
+//! ignorefile /srv/app/.gitignore
+//! if host=web*
+//!   include /var/www
+//! endif
+//! @linux include /proc/sys/net
+//! watch webserver {
+//!   include /etc/nginx
+//!   exclude /etc/nginx/cache
+//!   events modify
+//! }
+//!
+//! [profile security]
+//! include /etc/ssh, /etc/sudoers.d
 //! ```
+//!
+//! A line ending with a backslash after whitespace or a comma is continued on the next one, so
+//! long path lists can be wrapped, while one ending a path, as in `include C:\`, is part of it.
+//! Comment lines are never continued. Errors report the line the directive starts on.
+//!
+//! Includes can be prefixed with `-r` to also watch every subdirectory, or `-s` to only watch the
+//! directory itself. Without either flag [`ParseOptions::default_recursion`] applies.
+//!
+//! A relative include or exclude in a file, such as `include ./src`, is resolved against the
+//! directory of that file, so project-local configurations work wherever overwatch is started.
+//! [`ParseOptions::relative_to`] resolves them against the working directory instead.
+//! Configurations which didn't come from a file keep their relative paths.
+//!
+//! Options can follow the paths of an include as `key=value` pairs:
+//! - `depth=N` watches at most `N` levels of subdirectories, implying `-r`.
+//! - `events=a,b` only reports the listed events for this include, overriding the `events`
+//!   directive.
+//! - `debounce=<duration>` overrides the `debounce` directive for this include.
+//! - `max_size=<size>` overrides the `max_size` directive for this include.
+//! - `ext=rs,toml` only reports events for files with one of the listed extensions, overriding
+//!   the `only_extensions` directive.
+//! - `follow_symlinks=on|off` overrides the `follow_symlinks` directive for this include.
+//! - `include_hidden=on|off` overrides the `include_hidden` directive for this include.
+//! - `rate_limit=10/s` overrides the `rate_limit` directive for this include, optionally
+//!   with a `burst=N` option alongside it.
+//! - `tags=security,compliance` labels the events under this include, so sinks can pick out
+//!   the events they handle. See [`Config::tags_for`].
+//!
+//! `debounce 500ms` collapses a burst of events for the same file into one, reported once no
+//! further event has arrived for that long. Durations are a whole number followed by `ms`, `s`,
+//! `m`, `h` or `d`.
+//!
+//! `poll_interval 2s` sets how often the polling backend rescans paths on filesystems which
+//! don't report changes themselves, such as network mounts.
+//!
+//! `max_size 100M` drops events for files larger than the given size, before they are hashed
+//! or any action runs. Sizes are a whole number of bytes, optionally followed by `K`, `M` or `G`
+//! for powers of 1024.
+//!
+//! `only_extensions rs, toml` only reports events for files with one of the listed extensions.
+//! Extensions are written without the leading dot and compared exactly, and only the last one
+//! counts, so `gz` matches `logs.tar.gz`.
+//!
+//! `follow_symlinks on` makes recursive includes descend into symlinked directories, which
+//! are otherwise watched as the links themselves. A directory reached twice, as through a link
+//! back up the tree, is only watched once.
+//!
+//! `include_hidden off` stops watching dotfiles and dot-directories below includes, such as
+//! `.git` under a project. They are watched by default. An include naming a hidden path itself,
+//! as in `include ~/.config`, still watches it.
+//!
+//! `rate_limit 10/s burst=50` caps how many events each include passes on, so a noisy
+//! directory can't starve the pipeline. Up to the burst go through at once, after which the
+//! rate applies, and events beyond it are dropped. The rate is a number of events per `ms`,
+//! `s`, `m`, `h` or `d`, or per a duration as in `5/10s`, and the burst defaults to the number of
+//! events. See [`RateLimit`].
+//!
+//! `owner root, admin` only reports events for files owned by one of the listed users, and
+//! `group wheel` for files belonging to one of the listed groups. Either can be a name or a
+//! numeric id, and names are looked up on the machine the events happen on.
+//!
+//! `log_level` sets how much overwatch logs, one of `error`, `warn`, `info`, `debug` or
+//! `trace`, and `log_file` the file the log is appended to. A relative `log_file` is resolved
+//! against the directory of the file it appears in.
+//!
+//! `preset system` excludes the paths almost no deployment wants to watch: `/proc`, `/sys`,
+//! `/dev`, `/run` and the common cache directories. See [`Preset::excludes`] for the full list.
+//!
+//! `output` declares the format events are written in: `plain` lines, a `json` array or
+//! `ndjson` with one object per line.
+//!
+//! Includes can also end with `on_<event> "command"` clauses, which run the quoted command for
+//! events under that include. `on_change` matches any event.
+//!
+//! `ignore <regex>` drops events for any path the regular expression matches, such as
+//! `ignore \.swp$`. The pattern is the rest of the line, up to a `#` following whitespace.
+//! `ignorefile /srv/app/.gitignore` applies a gitignore file's rules to paths under the
+//! directory containing it.
+//!
+//! Lines between `if <condition>` and `endif` only apply when the condition holds, with an
+//! optional `else` in between. Conditions test the [`Context`] in [`ParseOptions::context`]:
+//! `host=<name>` and `os=<name>` match using glob syntax, and `!=` negates them. A directive
+//! can also be prefixed with `@<os>` to only apply on that operating system, such as
+//! `@linux include /proc/sys`. Blocks must be closed in the file they are opened in.
+//!
+//! A `[profile <name>]` header starts a section which lasts until the next header or the end of
+//! the file, so one file can hold several watch sets. Directives in a section only apply once
+//! it's selected with [`Config::profile`], layered over the directives outside any section.
+//! Sections with the same name accumulate, and files sourced from a section belong to it.
+//!
+//! `watch <name> { ... }` declares a named [`WatchGroup`] of includes and excludes. The
+//! `events`, `debounce`, `max_size`, `only_extensions`, `follow_symlinks`, `include_hidden` and
+//! `on` directives inside the braces only apply to the group's includes, which can still
+//! override them with their own options, and its excludes only carve paths out of its own
+//! includes. A `rate_limit` in a group is shared by all of the group's events. No other
+//! directives are allowed in a group. A short group can be written on one line with `;`
+//! between its directives, as in `watch db { include /etc/postgresql; events modify }`, so
+//! commands in such a group can't contain `;`. Groups with the same name accumulate, and a
+//! group can't be prefixed with `@<os>`.
+//!
+//! `set NAME value` defines a variable which later paths in the same file can reference as
+//! `$NAME` or `${NAME}`. Variables shadow the environment, aren't visible to sourced files and
+//! can be seeded for every file with [`ParseOptions::variables`]. A reference to a variable
+//! which isn't set anywhere follows [`ParseOptions::env`], so it is an error with
+//! [`EnvMode::Require`].
+//!
+//! Windows paths such as `C:\Users\me` and `\\server\share` can be written unquoted. On
+//! Windows literal paths are normalized to backslashes with an upper case drive letter.
+//!
+//! Double quoted paths may contain spaces, commas and `#`. `\"` and `\\` escape a quote and a
+//! backslash, `\n` and `\t` a newline and a tab, and `\xNN` the byte with that hex value, which
+//! on unix can spell file names which aren't valid UTF-8. Quoted paths are taken literally apart
+//! from environment variable expansion, so no tilde or glob expansion is applied. Paths which
+//! aren't UTF-8 can also be given directly with [`PathSpec::from_os_str`].
+//!
+//! With the `serde` feature [`Config`], [`ConfigLine`] and the types they hold implement
+//! `Serialize` and `Deserialize`, spelling values as the DSL does. Custom directives are left
+//! out.
+//!
+//! With the `toml` feature the same settings can be written as TOML. Files with a
+//! `.toml` extension are read as TOML, including ones pulled in by `source`.
+//!
+//! [`Config::from_reader`] reads a configuration from any [`Read`], and a path of `-` given to
+//! [`Config::from_file`] or found in `$OVERWATCH_CONFIG` reads one from standard input, so
+//! generated configurations can be piped in as in `gen-config | overwatch -c -`.
+//!
+//! [`Config::apply_env`] layers settings from environment variables named after their
+//! directives over a loaded configuration, such as `OVERWATCH_INCLUDE=/srv, /etc` or
+//! `OVERWATCH_LOG_LEVEL=debug`, so containers can be adjusted without mounting a file.
+//!
+//! Applications embedding overwatch can also build a [`Config`] in code with
+//! [`ConfigBuilder`], which holds it to the same rules as a parsed one.
+//!
+//! A [`Config`] can be written back out as configuration text with its `Display` impl, which
+//! produces one directive per line in a stable order.
+//!
+//! `watch` and `unwatch` can be used in place of `include` and `exclude`. A few older directive
+//! names are still accepted but deprecated, such as `ignore_file` for `ignorefile`.
+//!
+//! [`Config::load_with_warnings`] also returns problems which don't stop a configuration from
+//! loading, such as a path included twice, an exclude outside every include or an old directive
+//! name. With [`ParseOptions::strict`] off, lines which can't be parsed are skipped and reported
+//! the same way.
+//!
+//! Loading doesn't stop at the first line which can't be parsed. A file with several bad lines,
+//! or sourcing files which have them, fails with all of their errors at once in a
+//! [`ConfigError::Multiple`], so a large configuration can be fixed in one go.
+//!
+//! Applications embedding this crate can add their own directives by registering a
+//! [`Directive`] in [`ParseOptions::directives`]. Names which aren't built in are looked up
+//! there before being rejected.
+//!
+//! [`Config::normalize`] cleans up the paths of a parsed configuration and drops includes and
+//! excludes which don't change what is watched, so a watcher registers each directory once.
+//!
+//! [`format()`] lays configuration text out canonically, sorting path lists and indenting
+//! blocks while keeping comments, so files stay diff friendly.
+//!
+//! Editor tooling can parse configuration text into a [`SyntaxTree`], which keeps the span of
+//! every directive, path, option and comment along with what each directive parses to.
+//!
+//! Tools which only need the directives, such as linters, can iterate over them with
+//! [`parse_lines`] without building a [`Config`].
+//!
+//! Very large configurations can be read with [`ConfigReader`], which yields directives one
+//! line at a time instead of loading the whole file.
+//!
+//! [`Config::diff`] reports the includes, excludes and settings which differ between two
+//! configurations, which [`ConfigReloader`] passes along with every reload. With the `tokio`
+//! feature reloads can also be awaited, through `ConfigReloader::subscribe_async`.
+//!
+//! [`PathMatcher`] answers whether a path is covered by the includes and excludes, letting the
+//! most specific rule decide.
 
-use std::path::PathBuf;
-
-use nom::{
-    branch::alt,
-    bytes::complete::{tag, take_while},
-    character::complete::{multispace0, multispace1},
-    multi::separated_list1,
-    sequence::{delimited, tuple},
-    IResult,
-};
-
-#[derive(Default)]
-struct Config {
-    includes: Vec<PathBuf>,
-    excludes: Vec<PathBuf>,
+use std::{collections::BTreeMap, io::Read, path::Path, str::FromStr, time::Duration};
+
+mod action;
+mod builder;
+mod context;
+mod diff;
+mod directive;
+mod discover;
+mod duration;
+mod environment;
+mod error;
+mod events;
+mod expand;
+mod format;
+mod group;
+mod ignore;
+mod loader;
+mod logging;
+mod matcher;
+mod merge;
+mod normalize;
+mod output;
+mod overrides;
+mod owner;
+mod parser;
+mod pattern;
+mod preset;
+mod rate;
+mod reader;
+mod reload;
+#[cfg(feature = "serde")]
+mod serialize;
+mod size;
+mod source;
+mod syntax;
+#[cfg(feature = "toml")]
+mod toml;
+mod validate;
+mod warning;
+mod watch;
+mod writer;
+
+use loader::Loader;
+
+pub use action::Action;
+pub use builder::ConfigBuilder;
+pub use context::{Condition, ConditionKey, Context};
+pub use diff::ConfigDiff;
+pub use directive::{CustomDirective, Directive, DirectiveRegistry};
+pub use discover::CONFIG_ENV;
+pub use duration::parse_duration;
+pub use environment::ENV_PREFIX;
+pub use error::{ConfigError, ParseError, ParseErrorKind, PathError};
+pub use events::{EventKind, EventSet};
+pub use expand::{expand_env, expand_tilde, EnvMode, UnknownUser, UnsetVariable};
+pub use format::format;
+pub use group::WatchGroup;
+pub use ignore::{IgnoreFile, IgnoreSet};
+pub use logging::{LogLevel, LoggingConfig};
+pub use matcher::{MatchedRule, PathMatcher};
+pub use output::OutputFormat;
+pub use overrides::Overrides;
+pub use owner::OwnerFilter;
+pub use parser::{parse_lines, parse_lines_with, ConfigLine};
+pub use pattern::{PathSpec, Pattern, PatternError};
+pub use preset::Preset;
+pub use rate::{parse_rate, RateLimit};
+pub use reader::ConfigReader;
+pub use reload::{ConfigReloader, Reload, ReloadEvent};
+pub use size::parse_size;
+#[cfg(feature = "toml")]
+pub use source::Toml;
+pub use source::{ConfigSource, Dsl, Format};
+pub use syntax::{DirectiveNode, OptionNode, Position, Span, Spanned, SyntaxTree};
+pub use validate::{Diagnostic, DiagnosticKind, Severity};
+pub use warning::{Origin, ParseOutcome, Warning, WarningKind};
+pub use watch::{Recursion, WatchEntry, WatchOptions};
+
+/// Options controlling how a configuration is parsed.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// How environment variables referenced in paths are expanded.
+    pub env: EnvMode,
+    /// Whether a leading `~` or `~user` is replaced by the user's home directory.
+    pub expand_tilde: bool,
+    /// How deeply `source` directives may nest before loading is aborted.
+    pub max_source_depth: usize,
+    /// The recursion mode for includes which don't specify one.
+    pub default_recursion: Recursion,
+    /// The machine conditionals are evaluated against.
+    pub context: Context,
+    /// Variables available to every file, as if each started with a `set` for them.
+    pub variables: BTreeMap<String, String>,
+    /// Whether a line which can't be parsed fails the load. When off such lines are skipped
+    /// and reported as [`WarningKind::SkippedLine`] warnings by [`Config::load_with_warnings`].
+    /// Only the DSL is read line by line, so TOML files are unaffected.
+    pub strict: bool,
+    /// Directives the embedding application adds to the DSL. Their values are available from
+    /// [`Config::custom`].
+    pub directives: DirectiveRegistry,
+    /// What relative includes and excludes in a file are resolved against.
+    pub relative_to: RelativeTo,
 }
 
-#[derive(Debug, PartialEq)]
-enum ConfigLine {
-    Include(Vec<PathBuf>),
-    Exclude(Vec<PathBuf>),
+/// The directory relative include and exclude paths are resolved against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RelativeTo {
+    /// The directory of the file the path appears in.
+    #[default]
+    ConfigDir,
+    /// The working directory of the process loading the configuration.
+    WorkingDir,
 }
 
-fn parse_config_line(input: &str) -> IResult<&str, ConfigLine, ()> {
-    alt((include_line, exclude_line))(input)
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            env: EnvMode::default(),
+            expand_tilde: true,
+            max_source_depth: 8,
+            default_recursion: Recursion::default(),
+            context: Context::current(),
+            variables: BTreeMap::new(),
+            strict: true,
+            directives: DirectiveRegistry::default(),
+            relative_to: RelativeTo::default(),
+        }
+    }
 }
 
-fn include_line(input: &str) -> IResult<&str, ConfigLine, ()> {
-    let (tail, (_, _, paths)) = tuple((tag("include"), multispace1, path_list))(input)?;
-    Ok((tail, ConfigLine::Include(paths)))
+/// A fully parsed configuration.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct Config {
+    includes: Vec<WatchEntry>,
+    excludes: Vec<PathSpec>,
+    events: EventSet,
+    actions: Vec<Action>,
+    ignores: IgnoreSet,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::optional_duration"))]
+    debounce: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::optional_duration"))]
+    poll_interval: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::optional_size"))]
+    max_size: Option<u64>,
+    extensions: Option<Vec<String>>,
+    owners: OwnerFilter,
+    follow_symlinks: Option<bool>,
+    include_hidden: Option<bool>,
+    rate_limit: Option<RateLimit>,
+    logging: LoggingConfig,
+    output: Option<OutputFormat>,
+    groups: Vec<WatchGroup>,
+    profiles: BTreeMap<String, Config>,
+    /// Custom values can't be rebuilt without the registry which parsed them, so they aren't
+    /// serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    custom: Vec<CustomDirective>,
 }
 
-fn exclude_line(input: &str) -> IResult<&str, ConfigLine, ()> {
-    let (tail, (_, _, paths)) = tuple((tag("exclude"), multispace1, path_list))(input)?;
-    Ok((tail, ConfigLine::Exclude(paths)))
+impl Config {
+    /// Reads and parses the configuration file at `path`. A path of `-` reads the DSL from
+    /// standard input instead, as [`Config::from_reader`] does.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Self::from_file_with(path, &ParseOptions::default())
+    }
+
+    /// Reads and parses the configuration file at `path` using the given options.
+    pub fn from_file_with<P: AsRef<Path>>(
+        path: P,
+        options: &ParseOptions,
+    ) -> Result<Self, ConfigError> {
+        let mut config = Config::default();
+        Loader::new(options).load_path(&mut config, path.as_ref())?;
+        Ok(config)
+    }
+
+    /// Reads and parses DSL configuration text from `reader`, such as standard input, using the
+    /// default options.
+    ///
+    /// The text is read a line at a time rather than all at once. Relative `source` directives
+    /// are resolved against the current working directory.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, ConfigError> {
+        Self::from_reader_with(reader, &ParseOptions::default())
+    }
+
+    /// Reads and parses DSL configuration text from `reader` using the given options.
+    pub fn from_reader_with<R: Read>(
+        reader: R,
+        options: &ParseOptions,
+    ) -> Result<Self, ConfigError> {
+        let mut config = Config::default();
+        Loader::new(options).load_read(&mut config, reader)?;
+        Ok(config)
+    }
+
+    /// Loads a configuration from any [`ConfigSource`].
+    pub fn load<S: ConfigSource + ?Sized>(
+        source: &S,
+        options: &ParseOptions,
+    ) -> Result<Self, ConfigError> {
+        source.load(options)
+    }
+
+    /// Loads a configuration from any [`ConfigSource`] along with the warnings raised while
+    /// loading it.
+    pub fn load_with_warnings<S: ConfigSource + ?Sized>(
+        source: &S,
+        options: &ParseOptions,
+    ) -> Result<ParseOutcome, ConfigError> {
+        source.load_with_warnings(options)
+    }
+
+    /// Parses a TOML configuration using the default options.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(input: &str) -> Result<Self, ConfigError> {
+        Toml(input).load(&ParseOptions::default())
+    }
+
+    /// Parses a configuration using the given options.
+    ///
+    /// Relative `source` directives are resolved against the current working directory.
+    pub fn parse_with(input: &str, options: &ParseOptions) -> Result<Self, ConfigError> {
+        let mut config = Config::default();
+        Loader::new(options).load_str(&mut config, input)?;
+        Ok(config)
+    }
+
+    /// Paths which should be watched.
+    pub fn includes(&self) -> &[WatchEntry] {
+        &self.includes
+    }
+
+    /// Adds an include after the others, as merging a layer holding only `entry` would: an
+    /// include of the same path is replaced and an exclude of exactly that path is dropped.
+    pub fn add_include(&mut self, entry: WatchEntry) {
+        self.includes.retain(|existing| existing.path != entry.path);
+        self.excludes.retain(|exclude| *exclude != entry.path);
+        self.includes.push(entry);
+    }
+
+    /// Removes the include of `path`, returning it if there was one. Without an include of its
+    /// own, the configuration's first watch group including `path` loses that include instead.
+    pub fn remove_include(&mut self, path: &PathSpec) -> Option<WatchEntry> {
+        if let Some(index) = self.includes.iter().position(|entry| entry.path == *path) {
+            return Some(self.includes.remove(index));
+        }
+        self.groups.iter_mut().find_map(|group| {
+            let index = group
+                .includes
+                .iter()
+                .position(|entry| entry.path == *path)?;
+            Some(group.includes.remove(index))
+        })
+    }
+
+    /// Keeps only the includes for which `keep` returns true, both the configuration's own and
+    /// those written in its watch groups.
+    pub fn retain_includes<F: FnMut(&WatchEntry) -> bool>(&mut self, mut keep: F) {
+        self.includes.retain(&mut keep);
+        for group in &mut self.groups {
+            group.includes.retain(&mut keep);
+        }
+    }
+
+    /// Paths which should not be watched, even if they fall under an include.
+    pub fn excludes(&self) -> &[PathSpec] {
+        &self.excludes
+    }
+
+    /// Events reported for includes which don't have their own `events` option. Every event
+    /// kind unless restricted by an `events` directive.
+    pub fn events(&self) -> EventSet {
+        self.events
+    }
+
+    /// Commands declared with `on <events> run <command>`, in the order they appear.
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    /// Every action which applies to events under `entry`: the global actions followed by the
+    /// ones bound to the include itself.
+    pub fn actions_for<'a>(&'a self, entry: &'a WatchEntry) -> impl Iterator<Item = &'a Action> {
+        self.actions.iter().chain(&entry.options.actions)
+    }
+
+    /// Patterns declared with `ignore <regex>` and files loaded with `ignorefile`.
+    pub fn ignores(&self) -> &IgnoreSet {
+        &self.ignores
+    }
+
+    /// Returns true if events for `path` should be dropped because an `ignore` pattern or
+    /// `ignorefile` matches it.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.ignores.matches(path)
+    }
+
+    /// How long to wait after an event for further ones to the same file, which are collapsed into
+    /// a single event. `None` unless set by a `debounce` directive.
+    pub fn debounce(&self) -> Option<Duration> {
+        self.debounce
+    }
+
+    /// The debounce delay for events under `entry`.
+    pub fn debounce_for(&self, entry: &WatchEntry) -> Option<Duration> {
+        entry.options.debounce.or(self.debounce)
+    }
+
+    /// How often the polling backend rescans watched paths, for filesystems such as network
+    /// mounts which don't deliver change notifications. `None` leaves it to the backend.
+    pub fn poll_interval(&self) -> Option<Duration> {
+        self.poll_interval
+    }
+
+    /// The size in bytes above which events for a file are dropped. `None` unless set by a
+    /// `max_size` directive.
+    pub fn max_size(&self) -> Option<u64> {
+        self.max_size
+    }
+
+    /// The size limit for files under `entry`.
+    pub fn max_size_for(&self, entry: &WatchEntry) -> Option<u64> {
+        entry.options.max_size.or(self.max_size)
+    }
+
+    /// The file extensions events are reported for. `None` unless restricted by an
+    /// `only_extensions` directive.
+    pub fn extensions(&self) -> Option<&[String]> {
+        self.extensions.as_deref()
+    }
+
+    /// The file extensions events are reported for under `entry`, if they are restricted.
+    pub fn extensions_for<'a>(&'a self, entry: &'a WatchEntry) -> Option<&'a [String]> {
+        entry
+            .options
+            .extensions
+            .as_deref()
+            .or(self.extensions.as_deref())
+    }
+
+    /// Returns true if the extension filter for `entry` lets events for the file at `path`
+    /// through. Directories have to be let through regardless, so this should only be asked
+    /// about files.
+    pub fn allows_extension(&self, entry: &WatchEntry, path: &Path) -> bool {
+        match self.extensions_for(entry) {
+            Some(extensions) => path
+                .extension()
+                .is_some_and(|ext| extensions.iter().any(|allowed| ext == allowed.as_str())),
+            None => true,
+        }
+    }
+
+    /// Whether symlinked directories are traversed, as set by `follow_symlinks`. Off by default.
+    pub fn follow_symlinks(&self) -> bool {
+        self.follow_symlinks.unwrap_or(false)
+    }
+
+    /// Whether symlinked directories under `entry` are traversed.
+    pub fn follow_symlinks_for(&self, entry: &WatchEntry) -> bool {
+        entry
+            .options
+            .follow_symlinks
+            .or(self.follow_symlinks)
+            .unwrap_or(false)
+    }
+
+    /// Whether dotfiles and dot-directories below includes are watched, as set by
+    /// `include_hidden`. On by default.
+    pub fn include_hidden(&self) -> bool {
+        self.include_hidden.unwrap_or(true)
+    }
+
+    /// Whether dotfiles and dot-directories below `entry` are watched.
+    pub fn include_hidden_for(&self, entry: &WatchEntry) -> bool {
+        entry
+            .options
+            .include_hidden
+            .or(self.include_hidden)
+            .unwrap_or(true)
+    }
+
+    /// How many events each include may pass on, if limited by a `rate_limit` directive.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit
+    }
+
+    /// The rate limit for events under `entry`. The includes of a watch group are also held to
+    /// the group's limit, see [`WatchGroup::options`].
+    pub fn rate_limit_for(&self, entry: &WatchEntry) -> Option<RateLimit> {
+        entry.options.rate_limit.or(self.rate_limit)
+    }
+
+    /// The users and groups set by `owner` and `group`, which files must belong to for their
+    /// events to be reported.
+    pub fn owners(&self) -> &OwnerFilter {
+        &self.owners
+    }
+
+    /// The level and file set by `log_level` and `log_file`.
+    pub fn logging(&self) -> &LoggingConfig {
+        &self.logging
+    }
+
+    /// The format events are written in, if set by an `output` directive.
+    pub fn output(&self) -> Option<OutputFormat> {
+        self.output
+    }
+
+    /// The directives parsed by [`ParseOptions::directives`], in the order they appear.
+    pub fn custom(&self) -> &[CustomDirective] {
+        &self.custom
+    }
+
+    /// The values of every custom directive called `name` whose value is a `T`.
+    pub fn custom_values<'a, T: 'static>(&'a self, name: &'a str) -> impl Iterator<Item = &'a T> {
+        self.custom
+            .iter()
+            .filter(move |directive| directive.name() == name)
+            .filter_map(CustomDirective::value)
+    }
+
+    /// The groups declared with `watch <name> { ... }`, in the order they are first declared.
+    pub fn groups(&self) -> &[WatchGroup] {
+        &self.groups
+    }
+
+    /// The watch group called `name`, if one is declared.
+    pub fn group(&self, name: &str) -> Option<&WatchGroup> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+
+    /// The names of the `[profile <name>]` sections, in sorted order.
+    pub fn profiles(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// The configuration selected by the profile `name`: the directives outside any section with
+    /// the profile's merged on top as by [`Config::merge`]. Returns `None` if no section declares
+    /// the profile.
+    pub fn profile(&self, name: &str) -> Option<Config> {
+        let section = self.profiles.get(name)?;
+        let mut config = Config {
+            profiles: BTreeMap::new(),
+            ..self.clone()
+        };
+        config.merge(section.clone());
+        Some(config)
+    }
+
+    /// Returns true if events for `path` are reported: an include covers it, no more specific
+    /// exclude carves it out as described on [`PathMatcher`], it isn't hidden below an include
+    /// which leaves out hidden files, and it isn't ignored. The includes of each watch group are
+    /// checked the same way, against the group's excludes as well as the global ones.
+    pub fn is_watched<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        (self.entry_for(path).is_some()
+            || self
+                .groups
+                .iter()
+                .any(|group| self.group_entry_for(group, path).is_some()))
+            && !self.is_ignored(path)
+    }
+
+    /// Returns true if `path` lies in a dotfile or dot-directory below the include which covers
+    /// it, and that include leaves out hidden files. The includes of watch groups count too.
+    pub fn is_hidden<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        let hidden = match matcher::rule_for(&self.includes, &self.excludes, path) {
+            Some(MatchedRule::Include(entry)) => {
+                !self.include_hidden_for(entry) && matcher::is_hidden_under(entry, path)
+            }
+            _ => false,
+        };
+        hidden
+            || self
+                .groups
+                .iter()
+                .any(|group| self.is_hidden_in_group(group, path))
+    }
+
+    /// The tags of the includes covering `path`, in the order they are first given: the tags of
+    /// the global include which decides it, then those of the deciding include of each watch
+    /// group. Empty if no include covers the path.
+    pub fn tags_for<P: AsRef<Path>>(&self, path: P) -> Vec<String> {
+        let path = path.as_ref();
+        let mut tags = match self.entry_for(path) {
+            Some(entry) => entry.options.tags.clone(),
+            None => Vec::new(),
+        };
+        for group in &self.groups {
+            if let Some(entry) = self.group_entry_for(group, path) {
+                tags = group::merge_tags(&tags, &entry.options.tags);
+            }
+        }
+        tags
+    }
+
+    /// The include which decides that `path` is watched, so per-include options such as
+    /// [`Config::debounce_for`] can be looked up for it: the deciding global include, or else
+    /// that of the first watch group covering it, with the group's settings filled in.
+    pub fn include_for<P: AsRef<Path>>(&self, path: P) -> Option<WatchEntry> {
+        let path = path.as_ref();
+        if self.is_ignored(path) {
+            return None;
+        }
+        match self.entry_for(path) {
+            Some(entry) => Some(entry.clone()),
+            None => self
+                .groups
+                .iter()
+                .find_map(|group| self.group_entry_for(group, path)),
+        }
+    }
+
+    /// The watch group whose include decides that `path` is watched, as [`Config::include_for`]
+    /// picks it. `None` if a global include decides it or nothing does.
+    pub fn group_for<P: AsRef<Path>>(&self, path: P) -> Option<&WatchGroup> {
+        let path = path.as_ref();
+        if self.is_ignored(path) || self.entry_for(path).is_some() {
+            return None;
+        }
+        self.groups
+            .iter()
+            .find(|group| self.group_entry_for(group, path).is_some())
+    }
+
+    /// The global include which decides that `path` is watched, if one does.
+    fn entry_for(&self, path: &Path) -> Option<&WatchEntry> {
+        match matcher::rule_for(&self.includes, &self.excludes, path) {
+            Some(MatchedRule::Include(entry))
+                if self.include_hidden_for(entry) || !matcher::is_hidden_under(entry, path) =>
+            {
+                Some(entry)
+            }
+            _ => None,
+        }
+    }
+
+    /// The events which should be reported for `entry`.
+    pub fn events_for(&self, entry: &WatchEntry) -> EventSet {
+        entry.options.events.unwrap_or(self.events)
+    }
 }
 
-fn path_list(input: &str) -> IResult<&str, Vec<PathBuf>, ()> {
-    let (tail, paths) = separated_list1(
-        delimited(multispace0, tag(","), multispace0),
-        take_while(|c| c != ',' && c != '\n'),
-    )(input)?;
-    Ok((
-        tail,
-        paths.iter().map(|p| p.trim().parse().unwrap()).collect(),
-    ))
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Self::parse_with(input, &ParseOptions::default())
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+
     use super::*;
 
+    fn spec(path: &str) -> PathSpec {
+        path.parse().unwrap()
+    }
+
+    fn include_paths(config: &Config) -> Vec<PathSpec> {
+        config.includes().iter().map(|i| i.path.clone()).collect()
+    }
+
     #[test]
-    fn parses_config_lines() {
+    fn parses_config() {
+        let config: Config = "include /etc/a, /etc/b\n\nexclude /etc/a/tmp\ninclude /home/user\n"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            include_paths(&config),
+            [spec("/etc/a"), spec("/etc/b"), spec("/home/user")]
+        );
+        assert_eq!(config.excludes(), [spec("/etc/a/tmp")]);
+    }
+
+    #[test]
+    fn reads_configs_from_readers() {
+        let input = "include /etc/a\nwatch web { include /srv }\nexclude /etc/a/tmp\n";
+        let config = Config::from_reader(input.as_bytes()).unwrap();
+        assert_eq!(config, input.parse().unwrap());
+
+        let err = Config::from_reader("include /etc\nmonitor /srv".as_bytes()).unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::Parse(ParseError { line: 2, .. })
+        ));
+        let err = Config::from_reader(&[b'#', 0xff, b'\n'][..]).unwrap_err();
+        assert!(matches!(err, ConfigError::Io(_)));
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
         let test_cases = vec![
+            ("# only a comment", vec![], vec![]),
+            ("\n\n   \n", vec![], vec![]),
             (
-                "include /etc/path",
-                ConfigLine::Include(vec![PathBuf::from("/etc/path")]),
+                "# header\ninclude /etc/a\n\n  # indented\nexclude /etc/a/b",
+                vec!["/etc/a"],
+                vec!["/etc/a/b"],
             ),
             (
-                "include\t/etc/a,/etc/b",
-                ConfigLine::Include(vec![PathBuf::from("/etc/a"), PathBuf::from("/etc/b")]),
+                "include /etc/a # trailing\nexclude /etc/b#tight",
+                vec!["/etc/a"],
+                vec!["/etc/b"],
             ),
             (
-                "exclude /etc/a",
-                ConfigLine::Exclude(vec![PathBuf::from("/etc/a")]),
+                "include /etc/a, /etc/b # two paths\n#exclude /etc/a",
+                vec!["/etc/a", "/etc/b"],
+                vec![],
             ),
         ];
 
         for test_case in test_cases {
-            let (_, actual) = parse_config_line(test_case.0).unwrap();
-            assert_eq!(test_case.1, actual);
+            let config: Config = test_case.0.parse().unwrap();
+            assert_eq!(
+                test_case.1.into_iter().map(spec).collect::<Vec<_>>(),
+                include_paths(&config)
+            );
+            assert_eq!(
+                test_case.2.into_iter().map(spec).collect::<Vec<_>>(),
+                config.excludes()
+            );
         }
     }
 
     #[test]
-    fn parses_file_lists() {
+    fn expands_environment_variables() {
+        // Tests run in parallel, so the environment is only read: cargo sets the manifest
+        // directory for the tests it runs, and nothing sets OVERWATCH_TEST_UNSET.
+        let dir = env!("CARGO_MANIFEST_DIR");
+        let input = "include $CARGO_MANIFEST_DIR/a, ${CARGO_MANIFEST_DIR}/b\n\
+                     exclude $OVERWATCH_TEST_UNSET/c";
+
+        let config: Config = input.parse().unwrap();
+        assert_eq!(
+            include_paths(&config),
+            [spec(&format!("{dir}/a")), spec(&format!("{dir}/b"))]
+        );
+        assert_eq!(config.excludes(), [spec("/c")]);
+
+        let deferred = ParseOptions {
+            env: EnvMode::Defer,
+            ..Default::default()
+        };
+        let config = Config::parse_with(input, &deferred).unwrap();
+        assert_eq!(config.excludes(), [spec("$OVERWATCH_TEST_UNSET/c")]);
+
+        let required = ParseOptions {
+            env: EnvMode::Require,
+            ..Default::default()
+        };
+        let err = Config::parse_with(input, &required).unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::Parse(ParseError { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn expands_tilde_unless_disabled() {
+        // The lookup itself is tested with injected home directories in `expand`.
+        let home = expand_tilde("~").unwrap();
+        let input = "include ~/projects";
+
+        let config: Config = input.parse().unwrap();
+        assert_eq!(include_paths(&config), [spec(&format!("{home}/projects"))]);
+
+        let literal = ParseOptions {
+            expand_tilde: false,
+            ..Default::default()
+        };
+        let config = Config::parse_with(input, &literal).unwrap();
+        assert_eq!(include_paths(&config), [spec("~/projects")]);
+    }
+
+    #[test]
+    fn follows_source_directives() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("conf.d")).unwrap();
+        fs::write(
+            dir.path().join("config"),
+            "include /etc/a\nsource conf.d/*.conf\nexclude /etc/a/c",
+        )
+        .unwrap();
+        fs::write(dir.path().join("conf.d/1.conf"), "include /etc/b").unwrap();
+        fs::write(dir.path().join("conf.d/2.conf"), "source ../extra\n").unwrap();
+        fs::write(dir.path().join("extra"), "exclude /etc/b/d").unwrap();
+
+        let config = Config::from_file(dir.path().join("config")).unwrap();
+        assert_eq!(include_paths(&config), [spec("/etc/a"), spec("/etc/b")]);
+        assert_eq!(config.excludes(), [spec("/etc/b/d"), spec("/etc/a/c")]);
+    }
+
+    #[test]
+    fn resolves_relative_paths_against_the_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir(root.join("nested")).unwrap();
+        fs::write(
+            root.join("config"),
+            "include ./src, /etc\nsource nested/more",
+        )
+        .unwrap();
+        fs::write(
+            root.join("nested/more"),
+            "include -r ../docs\nexclude build/*.o",
+        )
+        .unwrap();
+
+        let config = Config::from_file(root.join("config")).unwrap();
+        assert_eq!(
+            include_paths(&config),
+            [
+                PathSpec::Path(root.join("src")),
+                spec("/etc"),
+                PathSpec::Path(root.join("docs"))
+            ]
+        );
+        assert_eq!(
+            config.excludes()[0].to_string(),
+            root.join("nested/build/*.o").display().to_string()
+        );
+
+        let options = ParseOptions {
+            relative_to: RelativeTo::WorkingDir,
+            ..Default::default()
+        };
+        let config = Config::from_file_with(root.join("config"), &options).unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(include_paths(&config)[0], PathSpec::Path(cwd.join("src")));
+
+        let config: Config = "include ./src".parse().unwrap();
+        assert_eq!(include_paths(&config), [spec("./src")]);
+    }
+
+    #[test]
+    fn scopes_variables_to_their_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("config"),
+            "set LOGS /var/log\ninclude $LOGS/nginx\nsource extra\nset LOGS ${LOGS}/old\n\
+             include \"$LOGS\"",
+        )
+        .unwrap();
+        // Named so that it isn't set in the environment, which tests running at the same
+        // time could otherwise see changed.
+        fs::write(dir.path().join("extra"), "include $LOGS/$SITE").unwrap();
+
+        let mut options = ParseOptions {
+            env: EnvMode::Require,
+            ..Default::default()
+        };
+        options
+            .variables
+            .insert("SITE".to_string(), "www".to_string());
+        let err = Config::from_file_with(dir.path().join("config"), &options).unwrap_err();
+        assert!(
+            err.to_string().contains("variable 'LOGS' is not set"),
+            "{err}"
+        );
+
+        options
+            .variables
+            .insert("LOGS".to_string(), "/srv/log".to_string());
+        let config = Config::from_file_with(dir.path().join("config"), &options).unwrap();
+        assert_eq!(
+            include_paths(&config),
+            [
+                spec("/var/log/nginx"),
+                spec("/srv/log/www"),
+                spec("/var/log/old")
+            ]
+        );
+    }
+
+    #[test]
+    fn selects_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("nested"), "[profile other]\ninclude /x").unwrap();
+        fs::write(dir.path().join("keys"), "include /etc/ssl").unwrap();
+        let input = format!(
+            "include /etc\nexclude /etc/ssh/moduli\n\
+             [profile backups]\ninclude /srv/backups\nexclude /etc\n\
+             [profile security]\ninclude /etc/ssh\nsource {}\n\
+             [profile backups]\nevents create",
+            dir.path().join("keys").display()
+        );
+        let config = Config::parse_with(&input, &ParseOptions::default()).unwrap();
+        assert_eq!(include_paths(&config), [spec("/etc")]);
+        assert_eq!(
+            config.profiles().collect::<Vec<_>>(),
+            ["backups", "security"]
+        );
+
+        let backups = config.profile("backups").unwrap();
+        assert_eq!(include_paths(&backups), [spec("/srv/backups")]);
+        assert_eq!(backups.excludes(), [spec("/etc/ssh/moduli"), spec("/etc")]);
+        assert_eq!(backups.events(), [EventKind::Create].into_iter().collect());
+
+        let security = config.profile("security").unwrap();
+        assert_eq!(
+            include_paths(&security),
+            [spec("/etc"), spec("/etc/ssh"), spec("/etc/ssl")]
+        );
+        assert_eq!(config.profile("missing"), None);
+
+        let nested = format!(
+            "[profile a]\nsource {}",
+            dir.path().join("nested").display()
+        );
+        let err = Config::parse_with(&nested, &ParseOptions::default()).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("profile 'other' can't be declared"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn reads_logging_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        fs::write(
+            root.join("config"),
+            "log_level debug\nlog_file logs/overwatch.log",
+        )
+        .unwrap();
+
+        let config = Config::from_file(root.join("config")).unwrap();
+        assert_eq!(
+            config.logging(),
+            &LoggingConfig {
+                level: Some(LogLevel::Debug),
+                file: Some(root.join("logs/overwatch.log")),
+            }
+        );
+    }
+
+    #[test]
+    fn answers_whether_paths_are_watched() {
+        let config: Config = "include -r /home/user\n\
+                              exclude /home/*/.local\n\
+                              include -r /home/user/.local/share\n\
+                              ignore \\.swp$"
+            .parse()
+            .unwrap();
+
         let test_cases = vec![
-            ("/etc/file", vec!["/etc/file"]),
-            ("/etc/a,/etc/b,/etc/c", vec!["/etc/a", "/etc/b", "/etc/c"]),
-            (
-                " /etc/a  , /etc/b   ,  /etc/c ",
-                vec!["/etc/a", "/etc/b", "/etc/c"],
+            ("/home/user/notes.txt", true),
+            ("/home/user/.notes.txt.swp", false),
+            ("/home/user/.local/state/x", false),
+            ("/home/user/.local/share/x", true),
+            ("/home/other/x", false),
+        ];
+        for (path, watched) in test_cases {
+            assert_eq!(config.is_watched(path), watched, "{path}");
+        }
+    }
+
+    #[test]
+    fn leaves_out_hidden_files_when_asked() {
+        let config: Config = "include_hidden off\n\
+                              include -r /srv/app\n\
+                              include -r /home/user/.config\n\
+                              include -r /srv/www include_hidden=on"
+            .parse()
+            .unwrap();
+
+        let test_cases = vec![
+            ("/srv/app/src/main.rs", true),
+            ("/srv/app/.env", false),
+            ("/srv/app/.git/config", false),
+            ("/srv/app/a/.cache/b", false),
+            ("/srv/app/..data", false),
+            ("/home/user/.config/nvim/init.lua", true),
+            ("/home/user/.config/nvim/.netrwhist", false),
+            ("/srv/www/.well-known/x", true),
+        ];
+        for (path, watched) in test_cases {
+            assert_eq!(config.is_watched(path), watched, "{path}");
+            assert_eq!(config.is_hidden(path), !watched, "{path}");
+        }
+        assert!(!config.is_hidden("/etc/.hidden"));
+        assert!("include -r /srv/app"
+            .parse::<Config>()
+            .unwrap()
+            .is_watched("/srv/app/.env"));
+    }
+
+    #[test]
+    fn ignores_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.o\n").unwrap();
+        fs::write(
+            root.join("config"),
+            format!(
+                "include {}\nignore ~$\nignorefile .gitignore",
+                root.display()
             ),
+        )
+        .unwrap();
+
+        let config = Config::from_file(root.join("config")).unwrap();
+        assert_eq!(config.ignores().files()[0].path(), root.join(".gitignore"));
+        assert!(config.is_ignored(&root.join("notes~")));
+        assert!(config.is_ignored(&root.join("main.o")));
+        assert!(!config.is_ignored(&root.join("main.c")));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn sources_toml_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("config"),
+            "include /etc/a\nsource extra.toml",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("extra.toml"),
+            "include = [\"/etc/b\"]\nsource = [\"more\"]",
+        )
+        .unwrap();
+        fs::write(dir.path().join("more"), "exclude /etc/b/c").unwrap();
+
+        let config =
+            Config::load(dir.path().join("config").as_path(), &Default::default()).unwrap();
+        assert_eq!(include_paths(&config), [spec("/etc/a"), spec("/etc/b")]);
+        assert_eq!(config.excludes(), [spec("/etc/b/c")]);
+    }
+
+    #[test]
+    fn rejects_source_cycles_and_deep_nesting() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), "source b").unwrap();
+        fs::write(dir.path().join("b"), "source a").unwrap();
+
+        let mut err = Config::from_file(dir.path().join("a")).unwrap_err();
+        while let ConfigError::Source { error, .. } = err {
+            err = *error;
+        }
+        assert!(matches!(err, ConfigError::SourceCycle(_)));
+
+        for i in 0..4 {
+            fs::write(dir.path().join(i.to_string()), format!("source {}", i + 1)).unwrap();
+        }
+        fs::write(dir.path().join("4"), "include /etc/a").unwrap();
+        let shallow = ParseOptions {
+            max_source_depth: 3,
+            ..Default::default()
+        };
+        let mut err = Config::from_file_with(dir.path().join("0"), &shallow).unwrap_err();
+        while let ConfigError::Source { error, .. } = err {
+            err = *error;
+        }
+        assert!(matches!(err, ConfigError::SourceDepth(_)));
+        assert!(Config::from_file(dir.path().join("0")).is_ok());
+    }
+
+    #[test]
+    fn applies_recursion_flags() {
+        let input = "include -r /var/log\ninclude /etc/hosts\ninclude -s /srv";
+        let recursion = |config: &Config| {
+            config
+                .includes()
+                .iter()
+                .map(|i| i.options.recursion)
+                .collect::<Vec<_>>()
+        };
+
+        let config: Config = input.parse().unwrap();
+        assert_eq!(
+            include_paths(&config),
+            [spec("/var/log"), spec("/etc/hosts"), spec("/srv")]
+        );
+        assert_eq!(
+            recursion(&config),
+            [
+                Recursion::Recursive,
+                Recursion::NonRecursive,
+                Recursion::NonRecursive
+            ]
+        );
+
+        let recursive = ParseOptions {
+            default_recursion: Recursion::Recursive,
+            ..Default::default()
+        };
+        let config = Config::parse_with(input, &recursive).unwrap();
+        assert_eq!(
+            recursion(&config),
+            [
+                Recursion::Recursive,
+                Recursion::Recursive,
+                Recursion::NonRecursive
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_include_options() {
+        let config: Config = "include /data depth=3\ninclude -r /srv, /opt  depth=0 # shallow"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            config.includes(),
+            [
+                WatchEntry {
+                    path: spec("/data"),
+                    options: WatchOptions {
+                        recursion: Recursion::Recursive,
+                        max_depth: Some(3),
+                        ..Default::default()
+                    },
+                },
+                WatchEntry {
+                    path: spec("/srv"),
+                    options: WatchOptions {
+                        recursion: Recursion::Recursive,
+                        max_depth: Some(0),
+                        ..Default::default()
+                    },
+                },
+                WatchEntry {
+                    path: spec("/opt"),
+                    options: WatchOptions {
+                        recursion: Recursion::Recursive,
+                        max_depth: Some(0),
+                        ..Default::default()
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_events_globally_and_per_include() {
+        let config: Config = "include /etc\n".parse().unwrap();
+        assert_eq!(config.events(), EventSet::all());
+
+        let config: Config = "include /etc\ninclude /srv events=delete\nevents create,modify"
+            .parse()
+            .unwrap();
+        let includes = config.includes();
+        assert_eq!(
+            config.events_for(&includes[0]),
+            [EventKind::Create, EventKind::Modify].into_iter().collect()
+        );
+        assert_eq!(
+            config.events_for(&includes[1]),
+            [EventKind::Delete].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn limits_file_sizes_globally_and_per_include() {
+        let config: Config = "include /etc\n".parse().unwrap();
+        assert_eq!(config.max_size_for(&config.includes()[0]), None);
+
+        let config: Config = "max_size 10M\ninclude /etc\ninclude /srv/media max_size=4G"
+            .parse()
+            .unwrap();
+        let includes = config.includes();
+        assert_eq!(config.max_size(), Some(10 << 20));
+        assert_eq!(config.max_size_for(&includes[0]), Some(10 << 20));
+        assert_eq!(config.max_size_for(&includes[1]), Some(4 << 30));
+    }
+
+    #[test]
+    fn tags_paths_by_the_includes_covering_them() {
+        let config: Config = "include -r /etc tags=config\n\
+                              include -r /etc/ssh tags=security,compliance\n\
+                              exclude /etc/ssh/moduli\n\
+                              watch audit { include -r /etc/ssh tags=compliance,audit }"
+            .parse()
+            .unwrap();
+        let test_cases = vec![
+            ("/etc/hosts", vec!["config"]),
             (
-                "\t/etc/a\t,/etc/b,/etc/c",
-                vec!["/etc/a", "/etc/b", "/etc/c"],
+                "/etc/ssh/sshd_config",
+                vec!["security", "compliance", "audit"],
             ),
+            ("/etc/ssh/moduli", vec![]),
+            ("/srv/app", vec![]),
         ];
+        for (path, tags) in test_cases {
+            assert_eq!(config.tags_for(path), tags, "{path}");
+        }
+    }
 
-        for test_case in test_cases {
-            let (_, actual) = path_list(test_case.0).unwrap();
+    #[test]
+    fn finds_the_include_deciding_a_path() {
+        let config: Config = "debounce 1s\n\
+                              include -r /srv\n\
+                              include -r /srv/app debounce=50ms\n\
+                              exclude /srv/app/cache\n\
+                              watch logs { include -r /var/log; debounce 5s }"
+            .parse()
+            .unwrap();
+        let test_cases = vec![
+            ("/srv/index.html", Some(Duration::from_secs(1))),
+            ("/srv/app/main.py", Some(Duration::from_millis(50))),
+            ("/var/log/syslog", Some(Duration::from_secs(5))),
+        ];
+        for (path, debounce) in test_cases {
+            let entry = config.include_for(path).unwrap();
+            assert_eq!(config.debounce_for(&entry), debounce, "{path}");
+        }
+        assert_eq!(config.include_for("/srv/app/cache/x"), None);
+        assert_eq!(config.include_for("/etc/hosts"), None);
+
+        let group = |path| config.group_for(path).map(|group| group.name.as_str());
+        assert_eq!(group("/var/log/syslog"), Some("logs"));
+        assert_eq!(group("/srv/index.html"), None);
+        assert_eq!(group("/etc/hosts"), None);
+    }
+
+    #[test]
+    fn filters_extensions_globally_and_per_include() {
+        let config: Config = "include -r /srv\n".parse().unwrap();
+        assert!(config.allows_extension(&config.includes()[0], Path::new("/srv/a.bin")));
+
+        let config: Config = "only_extensions rs, toml\ninclude -r /src\ninclude -r /docs ext=md"
+            .parse()
+            .unwrap();
+        let test_cases = vec![
+            (0, "/src/main.rs", true),
+            (0, "/src/Cargo.toml", true),
+            (0, "/src/notes.md", false),
+            (0, "/src/Makefile", false),
+            (0, "/src/main.rs.orig", false),
+            (1, "/docs/index.md", true),
+            (1, "/docs/main.rs", false),
+        ];
+        for (include, path, allowed) in test_cases {
+            let entry = &config.includes()[include];
             assert_eq!(
-                test_case
-                    .1
-                    .iter()
-                    .map(|p| p.parse().unwrap())
-                    .collect::<Vec<PathBuf>>(),
-                actual
+                config.allows_extension(entry, Path::new(path)),
+                allowed,
+                "{path}"
             );
         }
     }
+
+    #[test]
+    fn follows_symlinks_globally_and_per_include() {
+        let config: Config = "include -r /srv\n".parse().unwrap();
+        assert!(!config.follow_symlinks_for(&config.includes()[0]));
+
+        let config: Config =
+            "follow_symlinks on\ninclude -r /srv\ninclude -r /home follow_symlinks=off"
+                .parse()
+                .unwrap();
+        assert!(config.follow_symlinks());
+        assert!(config.follow_symlinks_for(&config.includes()[0]));
+        assert!(!config.follow_symlinks_for(&config.includes()[1]));
+    }
+
+    #[test]
+    fn collects_actions() {
+        let config: Config =
+            "on modify run systemctl reload nginx\ninclude /etc/nginx\non delete run logger gone"
+                .parse()
+                .unwrap();
+
+        let commands = config
+            .actions()
+            .iter()
+            .filter(|action| action.matches(EventKind::Modify))
+            .map(|action| action.command.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(commands, ["systemctl reload nginx"]);
+        assert_eq!(config.actions().len(), 2);
+    }
+
+    #[test]
+    fn binds_actions_to_includes() {
+        let config: Config =
+            "on delete run logger gone\ninclude /etc/nginx on_modify \"nginx -s reload\"\ninclude /srv"
+                .parse()
+                .unwrap();
+
+        let commands = |entry| {
+            config
+                .actions_for(entry)
+                .map(|action| action.command.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            commands(&config.includes()[0]),
+            ["logger gone", "nginx -s reload"]
+        );
+        assert_eq!(commands(&config.includes()[1]), ["logger gone"]);
+    }
+
+    #[test]
+    fn evaluates_conditionals() {
+        let input = "include /etc\n\
+                     if host=web*\n\
+                     \x20 include /var/www\n\
+                     \x20 if os!=linux\n\
+                     \x20   include /Library\n\
+                     \x20 endif\n\
+                     else\n\
+                     \x20 include /var/lib/db\n\
+                     endif\n\
+                     @linux include /proc/sys\n\
+                     @macos include /Volumes";
+        let load = |hostname: &str, os: &str| {
+            let options = ParseOptions {
+                context: Context {
+                    hostname: hostname.to_string(),
+                    os: os.to_string(),
+                },
+                ..Default::default()
+            };
+            include_paths(&Config::parse_with(input, &options).unwrap())
+        };
+
+        assert_eq!(
+            load("web01", "linux"),
+            [spec("/etc"), spec("/var/www"), spec("/proc/sys")]
+        );
+        assert_eq!(
+            load("web02", "macos"),
+            [
+                spec("/etc"),
+                spec("/var/www"),
+                spec("/Library"),
+                spec("/Volumes")
+            ]
+        );
+
+        let test_cases = vec![
+            (
+                "if host=a\ninclude /etc",
+                "line 1: 'if' without a matching 'endif'",
+            ),
+            (
+                "include /etc\n  endif",
+                "line 2: 'endif' without a matching 'if'",
+            ),
+            (
+                "if os=linux\nelse\nelse\nendif",
+                "line 3: 'else' without a matching 'if'",
+            ),
+        ];
+        for (input, message) in test_cases {
+            let err = input.parse::<Config>().unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn reports_invalid_lines() {
+        let err = "include /etc/a\ninclide /etc/b"
+            .parse::<Config>()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::Parse(ParseError { line: 2, .. })
+        ));
+
+        let err = "exclude /etc/[".parse::<Config>().unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::Parse(ParseError { line: 1, .. })
+        ));
+
+        let err = r#"include "/etc/unterminated"#.parse::<Config>().unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::Parse(ParseError { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn reports_every_error_in_one_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+        fs::write(
+            &path,
+            "include /etc/a\nevents modfy\nsource extra\nwatch web {\n[profile x]\ndebounce soon",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("extra"),
+            "exclude /etc/[\nmax_size 1\nmax_size huge",
+        )
+        .unwrap();
+
+        let err = Config::from_file(&path).unwrap_err();
+        let extra = dir.path().join("extra").canonicalize().unwrap();
+        let errors: Vec<_> = err
+            .errors()
+            .iter()
+            .map(|err| match err {
+                ConfigError::Parse(err) => (None, err.line),
+                ConfigError::Source { path, error } => match &**error {
+                    ConfigError::Parse(err) => (Some(path.clone()), err.line),
+                    err => panic!("{err:?}"),
+                },
+                err => panic!("{err:?}"),
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (None, 2),
+                (Some(extra.clone()), 1),
+                (Some(extra.clone()), 3),
+                (None, 4),
+                (None, 6)
+            ]
+        );
+        let message = err.to_string();
+        assert_eq!(message.lines().count(), 5, "{message}");
+        assert!(message.lines().nth(1).unwrap().starts_with(&format!(
+            "{}: line 1, column 9: invalid path",
+            extra.display()
+        )));
+
+        let single = "include /etc\nevents modfy".parse::<Config>().unwrap_err();
+        assert!(matches!(
+            single,
+            ConfigError::Parse(ParseError { line: 2, .. })
+        ));
+        assert_eq!(single.errors().len(), 1);
+    }
+
+    #[test]
+    fn skips_invalid_lines_unless_strict() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+        fs::write(
+            &path,
+            "include /etc/a\ninclide /etc/b\nif os=none\nexclude /etc/[\nendif\n\
+             exclude /etc/[\ninclude /etc/c\nif os=none\n",
+        )
+        .unwrap();
+
+        let err = Config::from_file(&path).unwrap_err();
+        let lines: Vec<_> = err
+            .errors()
+            .iter()
+            .map(|err| match err {
+                ConfigError::Parse(err) => err.line,
+                err => panic!("{err:?}"),
+            })
+            .collect();
+        assert_eq!(lines, [2, 6, 8]);
+
+        let lenient = ParseOptions {
+            strict: false,
+            ..Default::default()
+        };
+        let outcome = Config::load_with_warnings(path.as_path(), &lenient).unwrap();
+        assert_eq!(
+            include_paths(&outcome.config),
+            [spec("/etc/a"), spec("/etc/c")]
+        );
+        assert!(outcome.config.excludes().is_empty());
+        let lines: Vec<_> = outcome
+            .warnings
+            .iter()
+            .map(|warning| {
+                assert_eq!(warning.file.as_deref(), Some(path.as_path()));
+                assert!(matches!(warning.kind, WarningKind::SkippedLine(_)));
+                warning.line
+            })
+            .collect();
+        assert_eq!(lines, [Some(2), Some(6), Some(8)]);
+        assert!(outcome.warnings[0]
+            .to_string()
+            .starts_with(&format!("{}: skipped line 2: ", path.display())));
+    }
+
+    #[test]
+    fn warns_about_deprecated_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let ignore = dir.path().join(".gitignore");
+        fs::write(&ignore, "*.tmp\n").unwrap();
+        let input = format!(
+            "watch /srv\n@linux ignore_file {}\nignore_file\n",
+            ignore.display()
+        );
+        let lenient = ParseOptions {
+            strict: false,
+            ..Default::default()
+        };
+        let outcome = Config::load_with_warnings(&Dsl(&input), &lenient).unwrap();
+        assert_eq!(include_paths(&outcome.config), [spec("/srv")]);
+
+        let messages: Vec<_> = outcome.warnings.iter().map(ToString::to_string).collect();
+        let mut expected = Vec::new();
+        if cfg!(target_os = "linux") {
+            expected.push("line 2: 'ignore_file' is deprecated, use 'ignorefile' instead");
+        }
+        // A deprecated name which fails to parse is only reported as skipped.
+        expected.push("skipped line 3, column 12: expected a path after 'ignorefile'");
+        assert_eq!(messages, expected);
+    }
+
+    #[test]
+    fn warns_about_suspicious_paths() {
+        let input = "include /etc, /srv,\n\
+                     exclude /etc/ssl, /var/cache, /srv/*/tmp\n\
+                     include -r /etc,,/home\n\
+                     exclude /home/*/.cache\n\
+                     [profile work]\n\
+                     exclude /work/tmp\n\
+                     include /work";
+        let outcome = Config::load_with_warnings(&Dsl(input), &ParseOptions::default()).unwrap();
+        assert_eq!(
+            include_paths(&outcome.config),
+            [spec("/etc"), spec("/srv"), spec("/etc"), spec("/home")]
+        );
+
+        let warnings: Vec<_> = outcome
+            .warnings
+            .iter()
+            .map(|warning| (warning.line, warning.kind.clone()))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                (Some(1), WarningKind::EmptyPath),
+                (Some(3), WarningKind::EmptyPath),
+                (Some(3), WarningKind::DuplicateInclude(spec("/etc"))),
+                (
+                    Some(2),
+                    WarningKind::ExcludeOutsideIncludes(spec("/var/cache"))
+                ),
+            ]
+        );
+        assert_eq!(
+            outcome.warnings[2].to_string(),
+            "line 3: /etc is already included"
+        );
+
+        let origin = |path: &str| outcome.origin(&spec(path)).map(ToString::to_string);
+        assert_eq!(origin("/etc").as_deref(), Some("line 1"));
+        assert_eq!(origin("/home/me/.cache").as_deref(), Some("line 4"));
+        assert_eq!(origin("/work").as_deref(), Some("line 7"));
+        assert_eq!(origin("/opt"), None);
+    }
+
+    #[test]
+    fn edits_includes_in_place() {
+        let mut config: Config = "include /etc, /srv\nexclude /var\n\
+                                  watch web {\ninclude /var/www, /srv/www\n}"
+            .parse()
+            .unwrap();
+        config.add_include(WatchEntry {
+            path: spec("/var"),
+            options: WatchOptions::default(),
+        });
+        config.add_include(WatchEntry {
+            path: spec("/etc"),
+            options: WatchOptions {
+                recursion: Recursion::Recursive,
+                ..Default::default()
+            },
+        });
+        assert_eq!(
+            include_paths(&config),
+            [spec("/srv"), spec("/var"), spec("/etc")]
+        );
+        assert!(config.excludes().is_empty());
+        assert_eq!(config.includes()[2].options.recursion, Recursion::Recursive);
+
+        assert_eq!(
+            config.remove_include(&spec("/srv")).map(|entry| entry.path),
+            Some(spec("/srv"))
+        );
+        assert_eq!(config.remove_include(&spec("/srv")), None);
+
+        config.retain_includes(|entry| !entry.path.covers(Path::new("/srv/www")));
+        assert_eq!(include_paths(&config), [spec("/var"), spec("/etc")]);
+        assert_eq!(config.groups()[0].includes.len(), 1);
+
+        assert_eq!(
+            config
+                .remove_include(&spec("/var/www"))
+                .map(|entry| entry.path),
+            Some(spec("/var/www"))
+        );
+        assert!(config.groups()[0].includes.is_empty());
+        assert_eq!(config.remove_include(&spec("/var/www")), None);
+    }
 }
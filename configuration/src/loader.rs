@@ -0,0 +1,469 @@
+//! Drives the parsers over whole files, following `source` directives.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    normalize::normalize_spec, parser::ConfigLine, validate::contains, Config, ConfigError,
+    ConfigReader, Format, IgnoreFile, Origin, ParseError, ParseErrorKind, ParseOptions,
+    ParseOutcome, PathSpec, RelativeTo, Warning, WarningKind, WatchEntry, WatchGroup,
+};
+
+/// A watch group which is open while reading.
+struct OpenGroup {
+    /// The group's index in the groups of the section it's in.
+    index: usize,
+    line: usize,
+    column: usize,
+}
+
+/// The path which stands for standard input, as in `overwatch -c -`.
+pub(crate) const STDIN: &str = "-";
+
+pub(crate) struct Loader<'o> {
+    options: &'o ParseOptions,
+    stack: Vec<PathBuf>,
+    depth: usize,
+    /// The profile section the lines being read belong to, including lines sourced from within
+    /// one.
+    profile: Option<String>,
+    warnings: Vec<Warning>,
+    /// Excludes to check against the includes once everything is loaded, along with the
+    /// profile they belong to.
+    excludes: Vec<(Option<String>, Warning)>,
+    origins: Vec<Origin>,
+}
+
+impl<'o> Loader<'o> {
+    pub(crate) fn new(options: &'o ParseOptions) -> Self {
+        Self {
+            options,
+            stack: Vec::new(),
+            depth: 0,
+            profile: None,
+            warnings: Vec::new(),
+            excludes: Vec::new(),
+            origins: Vec::new(),
+        }
+    }
+
+    /// Pairs the loaded configuration with the warnings raised while loading it.
+    pub(crate) fn finish(self, config: Config) -> ParseOutcome {
+        let mut warnings = self.warnings;
+        for (profile, warning) in self.excludes {
+            let includes = match &profile {
+                Some(name) => config.profile(name).unwrap_or_default().includes,
+                None => config.includes.clone(),
+            };
+            if let WarningKind::ExcludeOutsideIncludes(exclude) = &warning.kind {
+                if !includes.iter().any(|entry| contains(&entry.path, exclude)) {
+                    warnings.push(warning);
+                }
+            }
+        }
+        ParseOutcome {
+            config,
+            warnings,
+            origins: self.origins,
+        }
+    }
+
+    /// Loads the file at `path`, or the DSL from standard input if the path is [`STDIN`].
+    pub(crate) fn load_path(
+        &mut self,
+        config: &mut Config,
+        path: &Path,
+    ) -> Result<(), ConfigError> {
+        if path == Path::new(STDIN) {
+            self.load_read(config, io::stdin().lock())
+        } else {
+            self.load_file(config, path)
+        }
+    }
+
+    pub(crate) fn load_file(
+        &mut self,
+        config: &mut Config,
+        path: &Path,
+    ) -> Result<(), ConfigError> {
+        let path = fs::canonicalize(path)?;
+        if self.stack.contains(&path) {
+            return Err(ConfigError::SourceCycle(path));
+        }
+
+        let file = File::open(&path)?;
+        let format = Format::from_path(&path);
+        self.stack.push(path);
+        let result = match format {
+            Format::Dsl => self.load_reader(config, BufReader::new(file)),
+            #[cfg(feature = "toml")]
+            Format::Toml => io::read_to_string(file)
+                .map_err(ConfigError::from)
+                .and_then(|input| self.load_format(config, &input, format)),
+        };
+        self.stack.pop();
+        result
+    }
+
+    pub(crate) fn load_format(
+        &mut self,
+        config: &mut Config,
+        input: &str,
+        format: Format,
+    ) -> Result<(), ConfigError> {
+        match format {
+            Format::Dsl => self.load_str(config, input),
+            #[cfg(feature = "toml")]
+            Format::Toml => {
+                for line in crate::toml::parse(input, self.options)? {
+                    self.apply(config, line, None)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn load_str(&mut self, config: &mut Config, input: &str) -> Result<(), ConfigError> {
+        self.load_reader(config, input.as_bytes())
+    }
+
+    pub(crate) fn load_read<R: Read>(
+        &mut self,
+        config: &mut Config,
+        reader: R,
+    ) -> Result<(), ConfigError> {
+        self.load_reader(config, BufReader::new(reader))
+    }
+
+    /// Applies the lines of a DSL file. Variables it sets and blocks it opens end with the file,
+    /// as does the profile section the lines belong to. Watch groups must be closed in the
+    /// section they are opened in.
+    fn load_reader<R: BufRead>(
+        &mut self,
+        config: &mut Config,
+        reader: R,
+    ) -> Result<(), ConfigError> {
+        let mut lines = ConfigReader::with_options(reader, self.options.clone())
+            .max_line_length(usize::MAX)
+            .recover();
+        let mut section: Option<String> = None;
+        let mut group: Option<OpenGroup> = None;
+        let mut errors = Vec::new();
+        while let Some(line) = lines.next() {
+            let line = match line {
+                Ok(line) => line,
+                Err(ConfigError::Parse(error)) if !self.options.strict => {
+                    self.warn(Some(error.line), WarningKind::SkippedLine(error));
+                    continue;
+                }
+                Err(err @ ConfigError::Parse(_)) => {
+                    errors.push(err);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if let Some((name, replacement)) = lines.deprecated() {
+                let name = name.to_string();
+                self.warn(
+                    Some(lines.line_number()),
+                    WarningKind::Deprecated { name, replacement },
+                );
+            }
+            // A profile header ends an unclosed group, so the section after it is still read.
+            if let (ConfigLine::Profile(_), Some(open)) = (&line, &group) {
+                errors.push(unclosed(in_section(config, &section), open));
+                group = None;
+            }
+            let result = self.apply_line(config, line, &lines, &mut section, &mut group);
+            if let Err(err) = result {
+                errors.push(err);
+            }
+        }
+        if let Some(open) = group {
+            errors.push(unclosed(in_section(config, &section), &open));
+        }
+        ConfigError::collect(errors)
+    }
+
+    /// Applies a line read from a DSL file, keeping track of the profile section and watch
+    /// group it's in.
+    fn apply_line<R: BufRead>(
+        &mut self,
+        config: &mut Config,
+        line: ConfigLine,
+        lines: &ConfigReader<R>,
+        section: &mut Option<String>,
+        group: &mut Option<OpenGroup>,
+    ) -> Result<(), ConfigError> {
+        let target = in_section(config, section);
+        match (line, &*group) {
+            (ConfigLine::Profile(name), _) => {
+                if self.profile.is_some() {
+                    return Err(ConfigError::Parse(ParseError {
+                        line: lines.line_number(),
+                        column: lines.column(),
+                        text: name,
+                        kind: ParseErrorKind::NestedProfile,
+                    }));
+                }
+                config.profiles.entry(name.clone()).or_default();
+                *section = Some(name);
+            }
+            (ConfigLine::WatchGroup(name), None) => {
+                let index = match target.groups.iter().position(|g| g.name == name) {
+                    Some(index) => index,
+                    None => {
+                        target.groups.push(WatchGroup::new(name));
+                        target.groups.len() - 1
+                    }
+                };
+                *group = Some(OpenGroup {
+                    index,
+                    line: lines.line_number(),
+                    column: lines.column(),
+                });
+            }
+            (ConfigLine::EndWatchGroup, None) => {
+                return Err(ConfigError::Parse(ParseError {
+                    line: lines.line_number(),
+                    column: lines.column(),
+                    text: "}".to_string(),
+                    kind: ParseErrorKind::UnmatchedGroup,
+                }));
+            }
+            (ConfigLine::EndWatchGroup, Some(_)) => *group = None,
+            (line, Some(open)) => {
+                let number = lines.line_number();
+                let in_group = &mut target.groups[open.index];
+                if !self.apply_to_group(in_group, line, number)? {
+                    return Err(ConfigError::Parse(ParseError {
+                        line: number,
+                        column: lines.column(),
+                        text: lines.directive().to_string(),
+                        kind: ParseErrorKind::InvalidInGroup,
+                    }));
+                }
+            }
+            (line, None) => {
+                let outer = self.profile.clone();
+                if section.is_some() {
+                    self.profile.clone_from(section);
+                }
+                let result = self.apply(target, line, Some(lines.line_number()));
+                self.profile = outer;
+                result?
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a line inside a watch group to the group. Returns false for directives which
+    /// can't be used in a group.
+    fn apply_to_group(
+        &mut self,
+        group: &mut WatchGroup,
+        line: ConfigLine,
+        number: usize,
+    ) -> Result<bool, ConfigError> {
+        let shared = &mut group.options;
+        match line {
+            ConfigLine::Include(paths, options) => {
+                for path in self.non_empty(paths, Some(number)) {
+                    let path = self.resolve_relative(&path)?;
+                    self.record(&path, Some(number));
+                    group.includes.push(WatchEntry {
+                        path,
+                        options: options.clone(),
+                    });
+                }
+            }
+            ConfigLine::Exclude(paths) => {
+                for path in self.non_empty(paths, Some(number)) {
+                    let path = self.resolve_relative(&path)?;
+                    self.record(&path, Some(number));
+                    group.excludes.push(path);
+                }
+            }
+            ConfigLine::Events(events) => shared.events = Some(events),
+            ConfigLine::Action(action) => shared.actions.push(action),
+            ConfigLine::Debounce(delay) => shared.debounce = Some(delay),
+            ConfigLine::MaxSize(size) => shared.max_size = Some(size),
+            ConfigLine::OnlyExtensions(extensions) => shared.extensions = Some(extensions),
+            ConfigLine::FollowSymlinks(follow) => shared.follow_symlinks = Some(follow),
+            ConfigLine::IncludeHidden(hidden) => shared.include_hidden = Some(hidden),
+            ConfigLine::RateLimit(limit) => shared.rate_limit = Some(limit),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Applies a parsed line to `config`. `number` is the line it came from, if the format
+    /// tracks lines.
+    fn apply(
+        &mut self,
+        config: &mut Config,
+        line: ConfigLine,
+        number: Option<usize>,
+    ) -> Result<(), ConfigError> {
+        match line {
+            ConfigLine::Include(paths, options) => {
+                for path in self.non_empty(paths, number) {
+                    let path = self.resolve_relative(&path)?;
+                    if config.includes.iter().any(|entry| entry.path == path) {
+                        self.warn(number, WarningKind::DuplicateInclude(path.clone()));
+                    }
+                    self.record(&path, number);
+                    config.includes.push(WatchEntry {
+                        path,
+                        options: options.clone(),
+                    });
+                }
+            }
+            ConfigLine::Exclude(paths) => {
+                for path in self.non_empty(paths, number) {
+                    let path = self.resolve_relative(&path)?;
+                    let warning =
+                        self.warning(number, WarningKind::ExcludeOutsideIncludes(path.clone()));
+                    self.excludes.push((self.profile.clone(), warning));
+                    self.record(&path, number);
+                    config.excludes.push(path);
+                }
+            }
+            ConfigLine::Source(paths) => {
+                for path in self.non_empty(paths, number) {
+                    self.source(config, &path.resolve(&self.base_dir()?))?;
+                }
+            }
+            ConfigLine::Events(events) => config.events = events,
+            ConfigLine::Action(action) => config.actions.push(action),
+            ConfigLine::Ignore(pattern) => config.ignores.push(pattern),
+            ConfigLine::Debounce(delay) => config.debounce = Some(delay),
+            ConfigLine::PollInterval(interval) => config.poll_interval = Some(interval),
+            ConfigLine::MaxSize(size) => config.max_size = Some(size),
+            ConfigLine::OnlyExtensions(extensions) => config.extensions = Some(extensions),
+            ConfigLine::FollowSymlinks(follow) => config.follow_symlinks = Some(follow),
+            ConfigLine::IncludeHidden(hidden) => config.include_hidden = Some(hidden),
+            ConfigLine::RateLimit(limit) => config.rate_limit = Some(limit),
+            ConfigLine::Owner(users) => config.owners.users = users,
+            ConfigLine::Group(groups) => config.owners.groups = groups,
+            ConfigLine::Output(format) => config.output = Some(format),
+            ConfigLine::Custom(directive) => config.custom.push(directive),
+            ConfigLine::Preset(preset) => config.apply_preset(preset),
+            ConfigLine::LogLevel(level) => config.logging.level = Some(level),
+            ConfigLine::LogFile(path) => config.logging.file = Some(self.base_dir()?.join(path)),
+            // Conditionals, variables, profile headers and watch groups are handled while
+            // reading the lines.
+            ConfigLine::If(_)
+            | ConfigLine::Else
+            | ConfigLine::EndIf
+            | ConfigLine::Set(..)
+            | ConfigLine::Profile(_)
+            | ConfigLine::WatchGroup(_)
+            | ConfigLine::EndWatchGroup => {}
+            ConfigLine::IgnoreFile(paths) => {
+                for spec in self.non_empty(paths, number) {
+                    for path in spec.resolve(&self.base_dir()?).expand() {
+                        let file = IgnoreFile::load(&path).map_err(|err| ConfigError::Source {
+                            path,
+                            error: Box::new(err),
+                        })?;
+                        config.ignores.push_file(file);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the empty elements of a path list, warning about each.
+    fn non_empty(&mut self, paths: Vec<PathSpec>, line: Option<usize>) -> Vec<PathSpec> {
+        let (empty, paths): (Vec<_>, Vec<_>) = paths
+            .into_iter()
+            .partition(|path| matches!(path, PathSpec::Path(path) if path.as_os_str().is_empty()));
+        for _ in empty {
+            self.warn(line, WarningKind::EmptyPath);
+        }
+        paths
+    }
+
+    fn warning(&self, line: Option<usize>, kind: WarningKind) -> Warning {
+        Warning {
+            file: self.stack.last().cloned(),
+            line,
+            kind,
+        }
+    }
+
+    fn warn(&mut self, line: Option<usize>, kind: WarningKind) {
+        let warning = self.warning(line, kind);
+        self.warnings.push(warning);
+    }
+
+    /// Notes where an include or exclude of `path` was written.
+    fn record(&mut self, path: &PathSpec, line: Option<usize>) {
+        self.origins.push(Origin {
+            path: path.clone(),
+            file: self.stack.last().cloned(),
+            line,
+        });
+    }
+
+    fn source(&mut self, config: &mut Config, spec: &PathSpec) -> Result<(), ConfigError> {
+        if self.depth >= self.options.max_source_depth {
+            return Err(ConfigError::SourceDepth(spec.to_string().into()));
+        }
+
+        self.depth += 1;
+        let mut errors = Vec::new();
+        for path in spec.expand() {
+            if let Err(err) = self.load_file(config, &path) {
+                errors.push(err.in_file(path));
+            }
+        }
+        self.depth -= 1;
+        ConfigError::collect(errors)
+    }
+
+    /// Resolves a relative include or exclude in a file as [`ParseOptions::relative_to`] asks.
+    /// Configs which didn't come from a file keep their relative paths.
+    fn resolve_relative(&self, spec: &PathSpec) -> Result<PathSpec, ConfigError> {
+        if self.stack.is_empty() || !spec.is_relative() {
+            return Ok(spec.clone());
+        }
+        let base = match self.options.relative_to {
+            RelativeTo::ConfigDir => self.base_dir()?,
+            RelativeTo::WorkingDir => std::env::current_dir()?,
+        };
+        Ok(normalize_spec(&spec.resolve(&base)))
+    }
+
+    /// Relative `source`, `ignorefile` and `log_file` paths are resolved against the directory
+    /// of the file they appear in, falling back to the working directory for configs which
+    /// didn't come from a file.
+    fn base_dir(&self) -> Result<PathBuf, ConfigError> {
+        match self.stack.last().and_then(|path| path.parent()) {
+            Some(dir) => Ok(dir.to_path_buf()),
+            None => Ok(std::env::current_dir()?),
+        }
+    }
+}
+
+/// The profile section named `section`, or the top level of `config` outside any section.
+fn in_section<'c>(config: &'c mut Config, section: &Option<String>) -> &'c mut Config {
+    match section {
+        Some(name) => config.profiles.entry(name.clone()).or_default(),
+        None => config,
+    }
+}
+
+fn unclosed(config: &Config, open: &OpenGroup) -> ConfigError {
+    ConfigError::Parse(ParseError {
+        line: open.line,
+        column: open.column,
+        text: config.groups[open.index].name.clone(),
+        kind: ParseErrorKind::UnmatchedGroup,
+    })
+}
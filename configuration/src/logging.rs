@@ -0,0 +1,78 @@
+//! Where overwatch writes its own log and how much it writes, set with `log_level` and
+//! `log_file`.
+
+use std::{fmt, path::PathBuf, str::FromStr};
+
+/// How verbose the log is. Each level includes the messages of the ones above it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 5] = [
+        LogLevel::Error,
+        LogLevel::Warn,
+        LogLevel::Info,
+        LogLevel::Debug,
+        LogLevel::Trace,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LogLevel::ALL
+            .into_iter()
+            .find(|level| level.as_str() == s)
+            .ok_or(())
+    }
+}
+
+/// The logging settings of a configuration. Fields the configuration doesn't set are `None`,
+/// leaving the choice to whatever runs the watcher.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct LoggingConfig {
+    pub level: Option<LogLevel>,
+    /// The file to append the log to. Relative paths are resolved against the directory of the
+    /// configuration file.
+    pub file: Option<PathBuf>,
+}
+
+impl LoggingConfig {
+    /// Overrides the settings `other` sets.
+    pub(crate) fn merge(&mut self, other: LoggingConfig) {
+        if other.level.is_some() {
+            self.level = other.level;
+        }
+        if other.file.is_some() {
+            self.file = other.file;
+        }
+    }
+}
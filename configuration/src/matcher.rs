@@ -0,0 +1,154 @@
+//! Deciding whether a path is covered by the includes and excludes of a configuration.
+
+use std::path::{Component, Path};
+
+use crate::{Config, PathSpec, Recursion, WatchEntry};
+
+/// Answers whether paths are covered by a configuration's includes and excludes.
+///
+/// A path is decided by the most specific rule which reaches it, where an include or exclude is
+/// more specific the deeper the directory it names. So an exclude carves a subtree out of an
+/// include above it, and an include below that exclude brings part of the subtree back.
+///
+/// - A literal path names itself. A glob names the deepest ancestor of the path it matches.
+/// - An exclude reaches everything below what it names.
+/// - A `-s` include reaches what it names and the entries directly inside it. A recursive one
+///   reaches everything below, or `depth` levels of subdirectories and the files in them.
+/// - When an include and an exclude name the same directory the exclude wins, and of two
+///   includes naming the same directory the later one does.
+///
+/// Ignore patterns aren't taken into account, see [`Config::is_ignored`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PathMatcher {
+    includes: Vec<WatchEntry>,
+    excludes: Vec<PathSpec>,
+}
+
+/// The rule which decided whether a path is covered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchedRule<'a> {
+    Include(&'a WatchEntry),
+    Exclude(&'a PathSpec),
+}
+
+impl PathMatcher {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            includes: config.includes.clone(),
+            excludes: config.excludes.clone(),
+        }
+    }
+
+    /// Returns true if an include covers `path` and no more specific exclude carves it out.
+    pub fn matches(&self, path: &Path) -> bool {
+        matches!(self.rule_for(path), Some(MatchedRule::Include(_)))
+    }
+
+    /// The rule which decides `path`, or `None` if no include or exclude reaches it.
+    pub fn rule_for(&self, path: &Path) -> Option<MatchedRule<'_>> {
+        rule_for(&self.includes, &self.excludes, path)
+    }
+}
+
+pub(crate) fn rule_for<'a>(
+    includes: &'a [WatchEntry],
+    excludes: &'a [PathSpec],
+    path: &Path,
+) -> Option<MatchedRule<'a>> {
+    let depth = path.components().count();
+    let include = includes
+        .iter()
+        .filter_map(|entry| {
+            let anchor = anchor(&entry.path, path)?;
+            let reach = match entry.options.recursion {
+                Recursion::NonRecursive => 1,
+                Recursion::Recursive => entry.options.max_depth.map_or(usize::MAX, |d| d + 1),
+            };
+            (depth - anchor <= reach).then_some((anchor, entry))
+        })
+        // `max_by_key` keeps the last of equal elements, so later includes win ties.
+        .max_by_key(|(anchor, _)| *anchor);
+    let exclude = excludes
+        .iter()
+        .filter_map(|spec| Some((anchor(spec, path)?, spec)))
+        .max_by_key(|(anchor, _)| *anchor);
+
+    match (include, exclude) {
+        (Some((include, entry)), Some((exclude, _))) if include > exclude => {
+            Some(MatchedRule::Include(entry))
+        }
+        (_, Some((_, spec))) => Some(MatchedRule::Exclude(spec)),
+        (Some((_, entry)), None) => Some(MatchedRule::Include(entry)),
+        (None, None) => None,
+    }
+}
+
+/// Returns true if a file or directory below the one `entry` names for `path` has a name starting
+/// with a dot, such as `/srv/app/.git/config` under `include -r /srv/app`.
+pub(crate) fn is_hidden_under(entry: &WatchEntry, path: &Path) -> bool {
+    anchor(&entry.path, path).is_some_and(|anchor| {
+        path.components().skip(anchor).any(|component| {
+            matches!(component, Component::Normal(name)
+                if name.as_encoded_bytes().starts_with(b"."))
+        })
+    })
+}
+
+/// The number of components in the directory `spec` names for `path`, if it covers `path`.
+pub(crate) fn anchor(spec: &PathSpec, path: &Path) -> Option<usize> {
+    let named = match spec {
+        PathSpec::Path(base) => path.starts_with(base).then_some(base.as_path())?,
+        PathSpec::Pattern(pattern) => path.ancestors().find(|p| pattern.matches(p))?,
+    };
+    Some(named.components().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_the_most_specific_rule() {
+        let config: Config = "include -r /home/user\n\
+                              exclude /home/user/.local\n\
+                              include -r /home/user/.local/share/notes\n\
+                              exclude /home/user/.local/share/notes/*.tmp\n\
+                              include /etc\n\
+                              include -r /srv depth=1\n\
+                              exclude /srv/cache\n\
+                              include -r /srv\n\
+                              include -r /var/*/logs\n\
+                              exclude /var/old"
+            .parse()
+            .unwrap();
+        let matcher = PathMatcher::new(&config);
+
+        let test_cases = vec![
+            ("/home/user", true),
+            ("/home/user/docs/a/b.txt", true),
+            ("/home/user/.local", false),
+            ("/home/user/.local/share/x", false),
+            ("/home/user/.local/share/notes/todo.md", true),
+            ("/home/user/.local/share/notes/a.tmp", false),
+            ("/home/user/.local/share/notes/a.tmp/b", false),
+            ("/home/other", false),
+            ("/etc/hosts", true),
+            ("/etc/ssh/sshd_config", false),
+            ("/srv/a/b/c", true),
+            ("/srv/cache/x", false),
+            ("/var/www/logs/today/access.log", true),
+            ("/var/old/x", false),
+            ("/var/old/logs/x", true),
+            ("/var/logs", false),
+        ];
+        for (path, covered) in test_cases {
+            assert_eq!(matcher.matches(Path::new(path)), covered, "{path}");
+        }
+
+        assert_eq!(
+            matcher.rule_for(Path::new("/srv/a")),
+            Some(MatchedRule::Include(&config.includes()[4]))
+        );
+        assert_eq!(matcher.rule_for(Path::new("/tmp")), None);
+    }
+}
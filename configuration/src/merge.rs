@@ -0,0 +1,220 @@
+//! Layering several configurations into one.
+
+use std::path::Path;
+
+use crate::{loader::Loader, Config, ConfigError, EventSet, ParseOptions};
+
+impl Config {
+    /// Merges `other` on top of this configuration, with `other` taking precedence.
+    ///
+    /// - Includes and excludes of `other` are appended, dropping exact duplicates. An include
+    ///   listed again replaces the earlier entry, so its options come from the later layer.
+    /// - A path `other` includes is no longer excluded by an earlier layer, and a path it
+    ///   excludes is no longer included. Excludes still win over any include they fall under.
+    /// - `other`'s `events` replaces the current set unless it allows every event, and its
+    ///   `debounce`, `poll_interval`, `max_size`, `only_extensions`, `follow_symlinks`,
+    ///   `include_hidden`, `rate_limit`, `owner`, `group`, `log_level`, `log_file` and `output`
+    ///   replace the current ones if set.
+    /// - Global actions, ignore patterns and ignore files are appended, skipping ones which are already
+    ///   present.
+    /// - A watch group replaces the group of the same name, if there is one.
+    /// - Profiles are merged with the profile of the same name by these rules.
+    pub fn merge(&mut self, other: Config) {
+        for entry in other.includes {
+            self.includes.retain(|existing| existing.path != entry.path);
+            self.excludes.retain(|exclude| *exclude != entry.path);
+            self.includes.push(entry);
+        }
+        for exclude in other.excludes {
+            self.includes.retain(|entry| entry.path != exclude);
+            if !self.excludes.contains(&exclude) {
+                self.excludes.push(exclude);
+            }
+        }
+        if other.events != EventSet::all() {
+            self.events = other.events;
+        }
+        if other.debounce.is_some() {
+            self.debounce = other.debounce;
+        }
+        if other.poll_interval.is_some() {
+            self.poll_interval = other.poll_interval;
+        }
+        if other.max_size.is_some() {
+            self.max_size = other.max_size;
+        }
+        if other.extensions.is_some() {
+            self.extensions = other.extensions;
+        }
+        if other.follow_symlinks.is_some() {
+            self.follow_symlinks = other.follow_symlinks;
+        }
+        if other.include_hidden.is_some() {
+            self.include_hidden = other.include_hidden;
+        }
+        if other.rate_limit.is_some() {
+            self.rate_limit = other.rate_limit;
+        }
+        if !other.owners.users.is_empty() {
+            self.owners.users = other.owners.users;
+        }
+        if !other.owners.groups.is_empty() {
+            self.owners.groups = other.owners.groups;
+        }
+        self.logging.merge(other.logging);
+        if other.output.is_some() {
+            self.output = other.output;
+        }
+        for pattern in other.ignores.patterns() {
+            self.ignores.push(pattern.clone());
+        }
+        for file in other.ignores.files() {
+            self.ignores.push_file(file.clone());
+        }
+        for action in other.actions {
+            if !self.actions.contains(&action) {
+                self.actions.push(action);
+            }
+        }
+        self.custom.extend(other.custom);
+        for group in other.groups {
+            match self
+                .groups
+                .iter_mut()
+                .find(|existing| existing.name == group.name)
+            {
+                Some(existing) => *existing = group,
+                None => self.groups.push(group),
+            }
+        }
+        for (name, profile) in other.profiles {
+            self.profiles.entry(name).or_default().merge(profile);
+        }
+    }
+
+    /// Loads each file in order and merges them with [`Config::merge`], so later files take
+    /// precedence. Typically the main file followed by the sorted contents of a `conf.d`
+    /// directory.
+    pub fn from_files<I>(paths: I, options: &ParseOptions) -> Result<Config, ConfigError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        let mut merged = Config::default();
+        for path in paths {
+            let path = path.as_ref();
+            let mut config = Config::default();
+            Loader::new(options)
+                .load_path(&mut config, path)
+                .map_err(|err| err.in_file(path.to_path_buf()))?;
+            merged.merge(config);
+        }
+        Ok(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use crate::{Config, ConfigError, EventKind, OutputFormat, PathSpec, Recursion};
+
+    fn spec(path: &str) -> PathSpec {
+        path.parse().unwrap()
+    }
+
+    #[test]
+    fn merges_with_later_precedence() {
+        let test_cases = vec![
+            (
+                "include /etc, /srv\nexclude /srv/tmp",
+                "include /var\nexclude /srv/tmp, /etc/ssl",
+                vec!["/etc", "/srv", "/var"],
+                vec!["/srv/tmp", "/etc/ssl"],
+            ),
+            (
+                "include /etc\nexclude /srv",
+                "include /srv\nexclude /etc",
+                vec!["/srv"],
+                vec!["/etc"],
+            ),
+            (
+                "include /etc, /srv",
+                "include /etc",
+                vec!["/srv", "/etc"],
+                vec![],
+            ),
+        ];
+
+        for (base, layer, includes, excludes) in test_cases {
+            let mut config: Config = base.parse().unwrap();
+            config.merge(layer.parse().unwrap());
+            assert_eq!(
+                config
+                    .includes()
+                    .iter()
+                    .map(|entry| entry.path.clone())
+                    .collect::<Vec<_>>(),
+                includes.into_iter().map(spec).collect::<Vec<_>>()
+            );
+            assert_eq!(
+                config.excludes(),
+                excludes.into_iter().map(spec).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn merges_options_events_and_actions() {
+        let mut config: Config = "include /etc\nevents modify\non any run a".parse().unwrap();
+        config.merge(
+            "include -r /etc\non any run a\non delete run b"
+                .parse()
+                .unwrap(),
+        );
+
+        assert_eq!(config.includes()[0].options.recursion, Recursion::Recursive);
+        assert_eq!(config.events(), [EventKind::Modify].into_iter().collect());
+        assert_eq!(config.actions().len(), 2);
+
+        config.merge("events delete".parse().unwrap());
+        assert_eq!(config.events(), [EventKind::Delete].into_iter().collect());
+
+        config.merge("output ndjson".parse().unwrap());
+        config.merge("include /srv".parse().unwrap());
+        assert_eq!(config.output(), Some(OutputFormat::Ndjson));
+        config.merge("output json".parse().unwrap());
+        assert_eq!(config.output(), Some(OutputFormat::Json));
+    }
+
+    #[test]
+    fn merges_poll_intervals() {
+        let mut config: Config = "include /etc\npoll_interval 5s".parse().unwrap();
+        assert_eq!(config.poll_interval(), Some(Duration::from_secs(5)));
+
+        config.merge("include /srv".parse().unwrap());
+        assert_eq!(config.poll_interval(), Some(Duration::from_secs(5)));
+        config.merge("poll_interval 1m".parse().unwrap());
+        assert_eq!(config.poll_interval(), Some(Duration::from_secs(60)));
+        assert_eq!(
+            "include /etc".parse::<Config>().unwrap().poll_interval(),
+            None
+        );
+    }
+
+    #[test]
+    fn loads_files_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("main"), "include /etc, /srv").unwrap();
+        fs::write(dir.path().join("host"), "exclude /srv").unwrap();
+
+        let files = [dir.path().join("main"), dir.path().join("host")];
+        let config = Config::from_files(&files, &Default::default()).unwrap();
+        assert_eq!(config.includes().len(), 1);
+        assert_eq!(config.excludes(), [spec("/srv")]);
+
+        let missing = [dir.path().join("main"), dir.path().join("missing")];
+        let err = Config::from_files(&missing, &Default::default()).unwrap_err();
+        assert!(matches!(err, ConfigError::Source { path, .. } if path == missing[1]));
+    }
+}
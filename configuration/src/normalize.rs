@@ -0,0 +1,318 @@
+//! Cleaning up the paths of a configuration so that no path is watched twice.
+
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    matcher::{anchor, is_hidden_under},
+    pattern::GLOB_CHARS,
+    Config, PathSpec, Recursion, WatchOptions,
+};
+
+impl Config {
+    /// Rewrites the includes and excludes into their simplest form without changing which paths
+    /// are watched, so a watcher registers each directory once.
+    ///
+    /// - `.` segments and repeated separators are dropped, and `..` removes the segment before
+    ///   it. A `..` directly after a glob segment is kept, since what it cancels depends on what
+    ///   the glob matches.
+    /// - An include of a path included again later is dropped, as the later one decides how the
+    ///   path is watched. Repeated excludes are dropped as well.
+    /// - A literal include below an unlimited recursive include with the same options is
+    ///   dropped, unless an exclude or another include in between makes it matter. Literal
+    ///   excludes below another exclude are dropped in the same way.
+    ///
+    /// Paths are only rewritten as text, see [`Config::canonicalize`] to also resolve symlinks.
+    /// The paths of watch groups are cleaned up the same way, and profiles are normalized too.
+    pub fn normalize(&mut self) {
+        for entry in &mut self.includes {
+            entry.path = normalize_spec(&entry.path);
+        }
+        for spec in &mut self.excludes {
+            *spec = normalize_spec(spec);
+        }
+        if let Some(file) = &mut self.logging.file {
+            *file = normalize_path(file);
+        }
+        for group in &mut self.groups {
+            for entry in &mut group.includes {
+                entry.path = normalize_spec(&entry.path);
+            }
+            for spec in &mut group.excludes {
+                *spec = normalize_spec(spec);
+            }
+        }
+
+        let mut index = 0;
+        while index < self.includes.len() {
+            let path = &self.includes[index].path;
+            if self.includes[index + 1..]
+                .iter()
+                .any(|later| later.path == *path)
+            {
+                self.includes.remove(index);
+            } else {
+                index += 1;
+            }
+        }
+        let mut excludes: Vec<PathSpec> = Vec::new();
+        for spec in self.excludes.drain(..) {
+            if !excludes.contains(&spec) {
+                excludes.push(spec);
+            }
+        }
+        self.excludes = excludes;
+
+        while let Some(index) = (0..self.includes.len()).find(|&i| self.is_shadowed_include(i)) {
+            self.includes.remove(index);
+        }
+        while let Some(index) = (0..self.excludes.len()).find(|&i| self.is_shadowed_exclude(i)) {
+            self.excludes.remove(index);
+        }
+
+        for profile in self.profiles.values_mut() {
+            profile.normalize();
+        }
+    }
+
+    /// Resolves every literal path which exists to its canonical form, following symlinks, and
+    /// then [normalizes](Config::normalize) the configuration. Paths which don't exist yet and
+    /// patterns are only normalized.
+    pub fn canonicalize(&mut self) {
+        self.canonicalize_paths();
+        self.normalize();
+    }
+
+    fn canonicalize_paths(&mut self) {
+        let specs = self
+            .includes
+            .iter_mut()
+            .map(|entry| &mut entry.path)
+            .chain(&mut self.excludes);
+        for spec in specs {
+            if let PathSpec::Path(path) = spec {
+                if let Ok(canonical) = fs::canonicalize(&*path) {
+                    *path = canonical;
+                }
+            }
+        }
+        for profile in self.profiles.values_mut() {
+            profile.canonicalize_paths();
+        }
+    }
+
+    /// Whether removing the include at `index` leaves every path watched the same way.
+    fn is_shadowed_include(&self, index: usize) -> bool {
+        let entry = &self.includes[index];
+        let PathSpec::Path(path) = &entry.path else {
+            return false;
+        };
+        self.includes.iter().enumerate().any(|(other, broader)| {
+            let PathSpec::Path(base) = &broader.path else {
+                return false;
+            };
+            let depth = base.components().count();
+            other != index
+                && base != path
+                && path.starts_with(base)
+                && broader.options.recursion == Recursion::Recursive
+                && broader.options.max_depth.is_none()
+                && same_except_reach(&broader.options, &entry.options)
+                && (self.include_hidden_for(broader) || !is_hidden_under(broader, path))
+                && !self
+                    .excludes
+                    .iter()
+                    .any(|spec| anchor(spec, path).is_some_and(|anchor| anchor > depth))
+                && !self.includes.iter().enumerate().any(|(i, between)| {
+                    i != index
+                        && i != other
+                        && anchor(&between.path, path).is_some_and(|anchor| anchor > depth)
+                })
+        })
+    }
+
+    /// Whether removing the exclude at `index` leaves every path watched the same way.
+    fn is_shadowed_exclude(&self, index: usize) -> bool {
+        let PathSpec::Path(path) = &self.excludes[index] else {
+            return false;
+        };
+        self.excludes.iter().enumerate().any(|(other, spec)| {
+            let Some(depth) = anchor(spec, path).filter(|_| other != index) else {
+                return false;
+            };
+            !self
+                .includes
+                .iter()
+                .any(|entry| anchor(&entry.path, path).is_some_and(|anchor| anchor > depth))
+        })
+    }
+}
+
+/// Whether two includes watch the paths they reach the same way.
+fn same_except_reach(a: &WatchOptions, b: &WatchOptions) -> bool {
+    let b = WatchOptions {
+        recursion: a.recursion,
+        max_depth: a.max_depth,
+        ..b.clone()
+    };
+    *a == b
+}
+
+pub(crate) fn normalize_spec(spec: &PathSpec) -> PathSpec {
+    match spec {
+        PathSpec::Path(path) => PathSpec::Path(normalize_path(path)),
+        PathSpec::Pattern(pattern) => {
+            let normalized = normalize_components(Path::new(pattern.as_str()), |name| {
+                !name.to_string_lossy().contains(GLOB_CHARS)
+            });
+            match normalized.to_str().map(str::parse) {
+                Some(Ok(spec)) => spec,
+                _ => spec.clone(),
+            }
+        }
+    }
+}
+
+fn normalize_path(path: &Path) -> PathBuf {
+    normalize_components(path, |_| true)
+}
+
+/// Drops `.` segments and repeated separators, and lets `..` remove the segment before it if
+/// `removable` allows it. A `..` can't go above the root.
+fn normalize_components(path: &Path, removable: impl Fn(&OsStr) -> bool) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(name)) if removable(name) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        normalized.push(".");
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(input: &str) -> String {
+        let mut config: Config = input.parse().unwrap();
+        config.normalize();
+        config.to_string()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn normalizes_paths() {
+        let test_cases = vec![
+            ("/srv//app/./logs/", "/srv/app/logs"),
+            ("/srv/app/../www", "/srv/www"),
+            ("/../etc", "/etc"),
+            ("./src", "src"),
+            ("src/../..", ".."),
+            ("/home/*/../.cache", "/home/*/../.cache"),
+            ("/var//log/./*.log", "/var/log/*.log"),
+        ];
+        for (input, expected) in test_cases {
+            let spec = normalize_spec(&input.parse().unwrap());
+            assert_eq!(spec.to_string(), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn drops_redundant_paths() {
+        let test_cases = vec![
+            (
+                "include /etc\ninclude /etc/ events=modify",
+                "include /etc events=modify\n",
+            ),
+            (
+                "include -r /srv\ninclude /srv/app\ninclude -r /srv/www depth=2",
+                "include -r /srv\n",
+            ),
+            (
+                "include -r /srv\ninclude /srv/app events=modify",
+                "include -r /srv\ninclude /srv/app events=modify\n",
+            ),
+            (
+                "include -r /srv depth=3\ninclude /srv/app",
+                "include /srv depth=3\ninclude /srv/app\n",
+            ),
+            (
+                "include -r /srv\nexclude /srv/app\ninclude -r /srv/app/logs",
+                "include -r /srv\ninclude -r /srv/app/logs\nexclude /srv/app\n",
+            ),
+            (
+                "include -r /srv\ninclude -s /srv/app\ninclude -r /srv/app/logs",
+                "include -r /srv\n",
+            ),
+            (
+                "include -r /srv\ninclude -s /srv/app events=modify\ninclude -r /srv/app/logs",
+                "include -r /srv\ninclude /srv/app events=modify\ninclude -r /srv/app/logs\n",
+            ),
+            (
+                "include_hidden off\ninclude -r /home/me\ninclude -r /home/me/.config",
+                "include -r /home/me\ninclude -r /home/me/.config\ninclude_hidden off\n",
+            ),
+            (
+                "include -r /srv\nexclude /srv/tmp\nexclude /srv/tmp/a\nexclude /srv/tmp",
+                "include -r /srv\nexclude /srv/tmp\n",
+            ),
+            (
+                "include -r /srv\nexclude /srv/*\nexclude /srv/a/b",
+                "include -r /srv\nexclude /srv/*\n",
+            ),
+            (
+                "include -r /srv\nexclude /srv/a\ninclude -r /srv/a/b\nexclude /srv/a/b/c",
+                "include -r /srv\ninclude -r /srv/a/b\nexclude /srv/a\nexclude /srv/a/b/c\n",
+            ),
+            (
+                "[profile a]\ninclude /x/./y\ninclude /x/y",
+                "[profile a]\ninclude /x/y\n",
+            ),
+        ];
+        for (input, expected) in test_cases {
+            assert_eq!(normalized(input), expected, "{input}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_symlinks_when_canonicalizing() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir(root.join("real")).unwrap();
+        std::os::unix::fs::symlink(root.join("real"), root.join("link")).unwrap();
+
+        let input = format!(
+            "include -r {root}/real\ninclude -r {root}/link\ninclude {root}/missing/../new",
+            root = root.display()
+        );
+        let mut config: Config = input.parse().unwrap();
+        config.canonicalize();
+        let paths: Vec<String> = config
+            .includes()
+            .iter()
+            .map(|entry| entry.path.to_string())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                root.join("real").display().to_string(),
+                root.join("new").display().to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! The format events are written in, set with `output`.
+
+use std::{fmt, str::FromStr};
+
+/// How the event sink renders each event.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    /// One human readable line per event.
+    #[default]
+    Plain,
+    /// A single JSON array holding every event, written once the watcher stops.
+    Json,
+    /// One JSON object per line, written as each event arrives.
+    Ndjson,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 3] = [
+        OutputFormat::Plain,
+        OutputFormat::Json,
+        OutputFormat::Ndjson,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OutputFormat::Plain => "plain",
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OutputFormat::ALL
+            .into_iter()
+            .find(|format| format.as_str() == s)
+            .ok_or(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_output_formats() {
+        for format in OutputFormat::ALL {
+            assert_eq!(format.to_string().parse(), Ok(format));
+        }
+        let test_cases = vec![
+            ("plain", Ok(OutputFormat::Plain)),
+            ("JSON", Err(())),
+            ("", Err(())),
+        ];
+        for (input, expected) in test_cases {
+            assert_eq!(input.parse::<OutputFormat>(), expected, "{input}");
+        }
+    }
+}
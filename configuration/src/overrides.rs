@@ -0,0 +1,100 @@
+//! Paths given on the command line, layered over a configuration file.
+
+use crate::{
+    parser::path_spec, Config, ConfigError, ParseOptions, PathSpec, WatchEntry, WatchOptions,
+};
+
+/// Includes and excludes supplied outside the configuration file, such as with `--include` and
+/// `--exclude` flags.
+///
+/// Paths are written as they would be in an unquoted `include` or `exclude` directive, so
+/// tildes, environment variables and globs are expanded according to the [`ParseOptions`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overrides {
+    pub includes: Vec<String>,
+    pub excludes: Vec<String>,
+}
+
+impl Config {
+    /// Applies `overrides` on top of this configuration, which extends it rather than replacing
+    /// it. Precedence follows [`Config::merge`] with the overrides as the later layer:
+    ///
+    /// - an overriding include is added even if the file excludes exactly that path, and
+    ///   replaces the file's include of the same path,
+    /// - an overriding exclude is added, and drops the file's include of exactly that path.
+    pub fn apply_overrides(
+        &mut self,
+        overrides: &Overrides,
+        options: &ParseOptions,
+    ) -> Result<(), ConfigError> {
+        let parse = |path: &String| match path_spec(path, options) {
+            Ok(spec) => Ok(spec),
+            Err(error) => Err(ConfigError::InvalidPath {
+                path: path.clone(),
+                error,
+            }),
+        };
+        let layer = Config {
+            includes: overrides
+                .includes
+                .iter()
+                .map(|path| {
+                    Ok(WatchEntry {
+                        path: parse(path)?,
+                        options: WatchOptions {
+                            recursion: options.default_recursion,
+                            ..Default::default()
+                        },
+                    })
+                })
+                .collect::<Result<_, ConfigError>>()?,
+            excludes: overrides
+                .excludes
+                .iter()
+                .map(parse)
+                .collect::<Result<Vec<PathSpec>, _>>()?,
+            ..Default::default()
+        };
+        self.merge(layer);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extends_the_file_config() {
+        let mut config: Config = "include /etc, /srv\nexclude /tmp/foo, /etc/ssl"
+            .parse()
+            .unwrap();
+        let overrides = Overrides {
+            includes: vec!["/tmp/foo".to_string(), "/var/*.log".to_string()],
+            excludes: vec!["/srv".to_string()],
+        };
+        config
+            .apply_overrides(&overrides, &ParseOptions::default())
+            .unwrap();
+
+        let includes = config
+            .includes()
+            .iter()
+            .map(|entry| entry.path.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(includes, ["/etc", "/tmp/foo", "/var/*.log"]);
+        assert_eq!(
+            config.excludes(),
+            ["/etc/ssl".parse().unwrap(), "/srv".parse().unwrap()]
+        );
+
+        let invalid = Overrides {
+            excludes: vec!["/etc/[".to_string()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.apply_overrides(&invalid, &ParseOptions::default()),
+            Err(ConfigError::InvalidPath { .. })
+        ));
+    }
+}
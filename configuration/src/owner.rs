@@ -0,0 +1,125 @@
+//! Filtering events by who owns a file, set with the `owner` and `group` directives.
+
+use std::fs::Metadata;
+
+/// The users and groups a file must belong to for its events to be reported. Each is written as
+/// a name or a numeric id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct OwnerFilter {
+    pub users: Vec<String>,
+    pub groups: Vec<String>,
+}
+
+impl OwnerFilter {
+    /// Returns true if neither users nor groups are restricted.
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty() && self.groups.is_empty()
+    }
+
+    /// Returns true if the file `metadata` describes is owned by one of the users and belongs to
+    /// one of the groups, where an empty list allows anyone. A name which doesn't exist on this
+    /// system matches nothing. Other platforms have no owners, so every file matches there.
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            is_one_of(&self.users, metadata.uid(), user_id)
+                && is_one_of(&self.groups, metadata.gid(), group_id)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = metadata;
+            true
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_one_of(names: &[String], id: u32, lookup: fn(&str) -> Option<u32>) -> bool {
+    names.is_empty()
+        || names
+            .iter()
+            .any(|name| name.parse().ok().or_else(|| lookup(name)) == Some(id))
+}
+
+#[cfg(unix)]
+fn user_id(name: &str) -> Option<u32> {
+    use std::{ffi::CString, mem, ptr};
+
+    let name = CString::new(name).ok()?;
+    let mut buf = vec![0; 16 * 1024];
+    // SAFETY: passwd is a plain C struct for which all-zeroes is a valid value.
+    let mut pwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut result = ptr::null_mut();
+    // SAFETY: every pointer refers to a live, correctly sized buffer owned by this frame.
+    let rc = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut pwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    (rc == 0 && !result.is_null()).then_some(pwd.pw_uid)
+}
+
+#[cfg(unix)]
+fn group_id(name: &str) -> Option<u32> {
+    use std::{ffi::CString, mem, ptr};
+
+    let name = CString::new(name).ok()?;
+    let mut buf = vec![0; 16 * 1024];
+    // SAFETY: group is a plain C struct for which all-zeroes is a valid value.
+    let mut grp: libc::group = unsafe { mem::zeroed() };
+    let mut result = ptr::null_mut();
+    // SAFETY: every pointer refers to a live, correctly sized buffer owned by this frame.
+    let rc = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut grp,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    (rc == 0 && !result.is_null()).then_some(grp.gr_gid)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, os::unix::fs::MetadataExt};
+
+    use super::*;
+
+    #[test]
+    fn matches_owners_by_name_or_id() {
+        assert_eq!(user_id("root"), Some(0));
+        assert_eq!(user_id("overwatch-no-such-user"), None);
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let metadata = fs::metadata(file.path()).unwrap();
+        let (uid, gid) = (metadata.uid().to_string(), metadata.gid().to_string());
+        let filter = |users: &[&str], groups: &[&str]| OwnerFilter {
+            users: users.iter().map(|name| name.to_string()).collect(),
+            groups: groups.iter().map(|name| name.to_string()).collect(),
+        };
+
+        let test_cases = vec![
+            (filter(&[], &[]), true),
+            (filter(&[&uid], &[]), true),
+            (filter(&["overwatch-no-such-user", &uid], &[&gid]), true),
+            (filter(&["overwatch-no-such-user"], &[]), false),
+            (filter(&[&uid], &["overwatch-no-such-group"]), false),
+        ];
+        for (filter, matches) in test_cases {
+            assert_eq!(filter.matches(&metadata), matches, "{filter:?}");
+        }
+    }
+}
@@ -0,0 +1,1835 @@
+//! Parsers for individual configuration lines.
+
+use std::{borrow::Cow, path::PathBuf, time::Duration};
+
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_till, take_till1, take_while1},
+    character::complete::alphanumeric1,
+    character::complete::{char, multispace0, multispace1, not_line_ending, space0},
+    combinator::{eof, map, not, opt, peek, value},
+    error::ErrorKind,
+    multi::{many0, separated_list1},
+    sequence::{delimited, preceded, separated_pair, terminated, tuple},
+    IResult,
+};
+
+use crate::{
+    context::{Condition, ConditionKey},
+    duration::parse_duration,
+    expand::{expand_variables, is_variable_name},
+    expand_tilde, ignore,
+    pattern::literal_path,
+    rate::{parse_burst, parse_rate},
+    size::parse_size,
+    syntax::logical_lines,
+    Action, CustomDirective, EventKind, EventSet, LogLevel, OutputFormat, ParseError,
+    ParseErrorKind, ParseOptions, PathError, PathSpec, Preset, RateLimit, Recursion, WatchOptions,
+};
+
+/// Every directive understood by the parser.
+pub(crate) const DIRECTIVES: &[&str] = &[
+    "include",
+    "exclude",
+    "source",
+    "events",
+    "on",
+    "ignore",
+    "ignorefile",
+    "if",
+    "else",
+    "endif",
+    "set",
+    "debounce",
+    "poll_interval",
+    "max_size",
+    "only_extensions",
+    "follow_symlinks",
+    "include_hidden",
+    "rate_limit",
+    "owner",
+    "group",
+    "log_level",
+    "log_file",
+    "output",
+    "preset",
+];
+
+/// Another name a directive can be written as.
+struct Alias {
+    name: &'static str,
+    directive: &'static str,
+    /// Whether the name is only accepted for old configurations, with a warning pointing at the
+    /// directive to use instead.
+    deprecated: bool,
+}
+
+const ALIASES: &[Alias] = &[
+    Alias {
+        name: "watch",
+        directive: "include",
+        deprecated: false,
+    },
+    Alias {
+        name: "unwatch",
+        directive: "exclude",
+        deprecated: false,
+    },
+    Alias {
+        name: "ignore_file",
+        directive: "ignorefile",
+        deprecated: true,
+    },
+];
+
+/// What a duration literal is described as in errors.
+const DURATION: &str = "a duration such as 500ms or 2s";
+const SIZE: &str = "a size such as 512K or 100M";
+const EXTENSIONS: &str = "a list of extensions such as rs,toml";
+const SWITCH: &str = "on or off";
+const NAMES: &str = "a list of names or ids such as root,1000";
+const TAGS: &str = "a list of tags such as security,compliance";
+const RATE: &str = "a rate such as 10/s or 100/m";
+const BURST: &str = "a number of events above zero";
+
+type Res<'a, T> = IResult<&'a str, T, SyntaxError<'a>>;
+
+/// A single directive, as yielded by [`crate::ConfigReader`] and [`parse_lines`].
+///
+/// The reader evaluates `If`, `Else`, `EndIf` and `Set` itself rather than yielding them, while
+/// [`parse_lines`] yields them as written.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ConfigLine {
+    Include(Vec<PathSpec>, WatchOptions),
+    Exclude(Vec<PathSpec>),
+    Source(Vec<PathSpec>),
+    Events(EventSet),
+    Action(Action),
+    Ignore(String),
+    IgnoreFile(Vec<PathSpec>),
+    If(Condition),
+    Else,
+    EndIf,
+    Set(String, String),
+    Profile(String),
+    /// The `watch <name> {` opening a watch group.
+    WatchGroup(String),
+    /// The `}` closing a watch group.
+    EndWatchGroup,
+    Debounce(#[cfg_attr(feature = "serde", serde(with = "crate::serialize::duration"))] Duration),
+    PollInterval(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serialize::duration"))] Duration,
+    ),
+    OnlyExtensions(Vec<String>),
+    FollowSymlinks(bool),
+    IncludeHidden(bool),
+    RateLimit(RateLimit),
+    Owner(Vec<String>),
+    Group(Vec<String>),
+    MaxSize(#[cfg_attr(feature = "serde", serde(with = "crate::serialize::size"))] u64),
+    LogLevel(LogLevel),
+    LogFile(PathBuf),
+    Output(OutputFormat),
+    Preset(Preset),
+    /// A directive registered with [`ParseOptions::directives`]. It can't be serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(CustomDirective),
+}
+
+/// The parser's error type, pointing into the line being parsed.
+#[derive(Debug, PartialEq)]
+pub(crate) struct SyntaxError<'a> {
+    at: &'a str,
+    text: &'a str,
+    kind: ParseErrorKind,
+}
+
+impl<'a> SyntaxError<'a> {
+    fn failure(at: &'a str, text: &'a str, kind: ParseErrorKind) -> nom::Err<Self> {
+        nom::Err::Failure(Self { at, text, kind })
+    }
+
+    /// Converts into a [`ParseError`]. `raw` must be the full line which was being parsed.
+    fn into_parse_error(self, line: usize, raw: &str) -> ParseError {
+        let offset = (self.at.as_ptr() as usize)
+            .saturating_sub(raw.as_ptr() as usize)
+            .min(raw.len());
+        ParseError {
+            line,
+            column: raw[..offset].chars().count() + 1,
+            text: self.text.to_string(),
+            kind: self.kind,
+        }
+    }
+}
+
+impl<'a> nom::error::ParseError<&'a str> for SyntaxError<'a> {
+    fn from_error_kind(input: &'a str, _: ErrorKind) -> Self {
+        Self {
+            at: input,
+            text: input.trim(),
+            kind: ParseErrorKind::Unexpected,
+        }
+    }
+
+    fn append(_: &'a str, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+/// Parses a complete line of the configuration, returning `None` for blank and comment lines.
+/// `number` is the line number used in errors.
+pub(crate) fn parse_line(
+    raw: &str,
+    number: usize,
+    options: &ParseOptions,
+) -> Result<Option<ConfigLine>, ParseError> {
+    let line = raw.trim();
+    if line.is_empty() || comment(line).is_ok() {
+        return Ok(None);
+    }
+    let line = match os_guard(line) {
+        Ok((Some(os), _)) if os != options.context.os => return Ok(None),
+        Ok((_, body)) => body,
+        Err(err) => return Err(err.into_parse_error(number, raw)),
+    };
+
+    let result = match parse_config_line(line, options) {
+        Ok((tail, _)) if line_end(tail).is_err() => {
+            let tail = tail.trim_start();
+            Err(SyntaxError {
+                at: tail,
+                text: tail.trim_end(),
+                kind: ParseErrorKind::Unexpected,
+            })
+        }
+        Ok((_, parsed)) => Ok(Some(parsed)),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(err),
+        Err(nom::Err::Incomplete(_)) => Err(SyntaxError {
+            at: &line[line.len()..],
+            text: "",
+            kind: ParseErrorKind::Unexpected,
+        }),
+    };
+    result.map_err(|err| err.into_parse_error(number, raw))
+}
+
+/// Parses the directives of `input` one at a time with the default options, without building a
+/// [`crate::Config`]. See [`parse_lines_with`].
+pub fn parse_lines(input: &str) -> impl Iterator<Item = Result<ConfigLine, ParseError>> + '_ {
+    parse_lines_with(input, ParseOptions::default())
+}
+
+/// Parses the directives of `input` one at a time, yielding each as written along with an error
+/// for each line which can't be parsed.
+///
+/// Unlike [`crate::ConfigReader`] nothing is evaluated, so `if`, `else`, `endif` and `set` are
+/// yielded along with the lines of every branch, and `source` directives aren't followed.
+/// Variables declared with `set` are still expanded in the lines after them. Lines prefixed
+/// with another operating system's `@<os>` are skipped, and a watch group written on one line
+/// is yielded as if it was written over several.
+pub fn parse_lines_with(
+    input: &str,
+    mut options: ParseOptions,
+) -> impl Iterator<Item = Result<ConfigLine, ParseError>> + '_ {
+    logical_lines(input)
+        .into_iter()
+        .flat_map(|line| {
+            let pieces = split_group_line(&line.text).unwrap_or_else(|| vec![line.text.clone()]);
+            pieces.into_iter().map(move |piece| (line.line, piece))
+        })
+        .filter_map(move |(number, piece)| {
+            let parsed = parse_line(&piece, number, &options).transpose()?;
+            if let Ok(ConfigLine::Set(name, value)) = &parsed {
+                options.variables.insert(name.clone(), value.clone());
+            }
+            Some(parsed)
+        })
+}
+
+pub(crate) fn parse_config_line<'a>(input: &'a str, options: &ParseOptions) -> Res<'a, ConfigLine> {
+    if input.starts_with('[') {
+        return profile_header(input);
+    }
+    if let Some(tail) = input.strip_prefix('}') {
+        return Ok((tail, ConfigLine::EndWatchGroup));
+    }
+    let (tail, name) = directive_name(input)?;
+    match resolve_alias(name) {
+        "include" if name == "watch" => alt((group_start, |i| include_line(i, options)))(tail),
+        "include" => include_line(tail, options),
+        "exclude" => exclude_line(tail, options),
+        "source" => source_line(tail, options),
+        "events" => events_line(tail),
+        "on" => action_line(tail),
+        "ignore" => ignore_line(tail),
+        "ignorefile" => ignorefile_line(tail, options),
+        "if" => if_line(tail),
+        "else" => Ok((tail, ConfigLine::Else)),
+        "endif" => Ok((tail, ConfigLine::EndIf)),
+        "set" => set_line(tail, options),
+        "debounce" => map(|i| duration_argument(i, "debounce"), ConfigLine::Debounce)(tail),
+        "max_size" => max_size_line(tail),
+        "only_extensions" => only_extensions_line(tail),
+        "follow_symlinks" => map(
+            |i| switch_argument(i, "follow_symlinks"),
+            ConfigLine::FollowSymlinks,
+        )(tail),
+        "include_hidden" => map(
+            |i| switch_argument(i, "include_hidden"),
+            ConfigLine::IncludeHidden,
+        )(tail),
+        "rate_limit" => rate_limit_line(tail),
+        "owner" => map(|i| name_list_argument(i, "owner"), ConfigLine::Owner)(tail),
+        "group" => map(|i| name_list_argument(i, "group"), ConfigLine::Group)(tail),
+        "poll_interval" => poll_interval_line(tail),
+        "log_level" => log_level_line(tail),
+        "log_file" => log_file_line(tail, options),
+        "output" => output_line(tail),
+        "preset" => preset_line(tail),
+        _ => custom_line(tail, name, options).unwrap_or_else(|| {
+            Err(SyntaxError::failure(
+                input,
+                name,
+                ParseErrorKind::UnknownDirective {
+                    suggestion: suggest(name),
+                },
+            ))
+        }),
+    }
+}
+
+pub(crate) fn directive_name(input: &str) -> Res<'_, &str> {
+    take_till1(|c: char| c.is_whitespace() || c == '#')(input)
+}
+
+/// The directive `name` stands for, if it's an alias of one.
+pub(crate) fn resolve_alias(name: &str) -> &str {
+    ALIASES
+        .iter()
+        .find(|alias| alias.name == name)
+        .map_or(name, |alias| alias.directive)
+}
+
+/// If the directive on `raw` is written with a deprecated name, returns that name and the
+/// directive to use instead.
+pub(crate) fn deprecated_name(raw: &str) -> Option<(&str, &'static str)> {
+    let name = written_name(raw)?;
+    ALIASES
+        .iter()
+        .find(|alias| alias.deprecated && alias.name == name)
+        .map(|alias| (name, alias.directive))
+}
+
+/// The name the directive on `raw` is written with, after any `@<os>` prefix.
+pub(crate) fn written_name(raw: &str) -> Option<&str> {
+    let (_, line) = os_guard(raw.trim_start()).ok()?;
+    directive_name(line).ok().map(|(_, name)| name)
+}
+
+/// Returns true if `raw` opens, continues or closes a conditional block. These are the only
+/// lines parsed inside a block whose condition doesn't hold.
+pub(crate) fn is_block_line(raw: &str) -> bool {
+    matches!(
+        directive_name(raw.trim_start()),
+        Ok((_, "if" | "else" | "endif"))
+    )
+}
+
+/// Splits a leading `@<os>` guard off a line, returning the operating system it names and the
+/// directive which follows.
+fn os_guard(line: &str) -> Result<(Option<&str>, &str), SyntaxError<'_>> {
+    let Some(rest) = line.strip_prefix('@') else {
+        return Ok((None, line));
+    };
+    let os = match directive_name(rest) {
+        Ok((_, os)) => os,
+        Err(_) => "",
+    };
+    let text = &line[..os.len() + 1];
+    let body = rest[os.len()..].trim_start();
+    if os.is_empty() || body.is_empty() || comment(body).is_ok() {
+        return Err(SyntaxError {
+            at: &line[text.len()..],
+            text,
+            kind: ParseErrorKind::MissingArgument {
+                expected: "a directive",
+            },
+        });
+    }
+    Ok((Some(os), body))
+}
+
+/// Parses `if <key>=<value>` or `if <key>!=<value>`.
+fn if_line(input: &str) -> Res<'_, ConfigLine> {
+    let (input, _) = required_space(input, "if", "a condition")?;
+    let (tail, text) = take_till1(|c: char| c.is_whitespace() || c == '#')(input)?;
+    let invalid = || {
+        SyntaxError::failure(
+            input,
+            text,
+            ParseErrorKind::InvalidOption {
+                expected: "host=<name> or os=<name>",
+            },
+        )
+    };
+    let (key, negated, value) = match text.split_once('=') {
+        Some((key, value)) => match key.strip_suffix('!') {
+            Some(key) => (key, true, value),
+            None => (key, false, value),
+        },
+        None => return Err(invalid()),
+    };
+    let key = ConditionKey::from_name(key).ok_or_else(invalid)?;
+    let value = value.parse().map_err(|_| invalid())?;
+    Ok((
+        tail,
+        ConfigLine::If(Condition {
+            key,
+            negated,
+            value,
+        }),
+    ))
+}
+
+fn include_line<'a>(input: &'a str, options: &ParseOptions) -> Res<'a, ConfigLine> {
+    let (input, recursion) = recursion_flag(input)?;
+    let (input, paths) = arguments(input, "include", options)?;
+    let (tail, watch) = watch_options(
+        input,
+        WatchOptions {
+            recursion: recursion.unwrap_or(options.default_recursion),
+            ..Default::default()
+        },
+        recursion,
+    )?;
+    Ok((tail, ConfigLine::Include(paths, watch)))
+}
+
+/// Parses the `key=value` options following the paths of an include. `flag` is the recursion
+/// flag given on the directive, if any.
+fn watch_options(
+    input: &str,
+    mut watch: WatchOptions,
+    flag: Option<Recursion>,
+) -> Res<'_, WatchOptions> {
+    let (tail, clauses) = many0(preceded(
+        multispace1,
+        alt((
+            map(action_clause, Clause::Action),
+            map(key_value, |(key, value)| Clause::Option(key, value)),
+        )),
+    ))(input)?;
+    let mut burst = None;
+    for clause in clauses {
+        let (key, value) = match clause {
+            Clause::Option(key, value) => (key, value),
+            Clause::Action(action) => {
+                watch.actions.push(action);
+                continue;
+            }
+        };
+        let at = starting_at(input, key);
+        let text = &at[..key.len() + 1 + value.len()];
+        match key {
+            "depth" => {
+                if flag == Some(Recursion::NonRecursive) {
+                    return Err(SyntaxError::failure(
+                        at,
+                        text,
+                        ParseErrorKind::InvalidOption {
+                            expected: "no depth on a -s include",
+                        },
+                    ));
+                }
+                let depth = value.parse().map_err(|_| {
+                    SyntaxError::failure(
+                        at,
+                        text,
+                        ParseErrorKind::InvalidOption {
+                            expected: "a number of directories",
+                        },
+                    )
+                })?;
+                watch.recursion = Recursion::Recursive;
+                watch.max_depth = Some(depth);
+            }
+            "events" => {
+                let (rest, events) = event_list(value)?;
+                if !rest.is_empty() {
+                    return Err(SyntaxError::failure(rest, rest, ParseErrorKind::Unexpected));
+                }
+                watch.events = Some(events);
+            }
+            "debounce" => {
+                let delay = parse_duration(value).ok_or_else(|| {
+                    SyntaxError::failure(
+                        at,
+                        text,
+                        ParseErrorKind::InvalidOption { expected: DURATION },
+                    )
+                })?;
+                watch.debounce = Some(delay);
+            }
+            "max_size" => {
+                let size = parse_size(value).ok_or_else(|| {
+                    SyntaxError::failure(at, text, ParseErrorKind::InvalidOption { expected: SIZE })
+                })?;
+                watch.max_size = Some(size);
+            }
+            "follow_symlinks" => {
+                let follow = parse_switch(value).ok_or_else(|| {
+                    SyntaxError::failure(
+                        at,
+                        text,
+                        ParseErrorKind::InvalidOption { expected: SWITCH },
+                    )
+                })?;
+                watch.follow_symlinks = Some(follow);
+            }
+            "include_hidden" => {
+                let hidden = parse_switch(value).ok_or_else(|| {
+                    SyntaxError::failure(
+                        at,
+                        text,
+                        ParseErrorKind::InvalidOption { expected: SWITCH },
+                    )
+                })?;
+                watch.include_hidden = Some(hidden);
+            }
+            "ext" => {
+                let extensions = match extension_list(value) {
+                    Ok(("", extensions)) => extensions,
+                    _ => {
+                        return Err(SyntaxError::failure(
+                            at,
+                            text,
+                            ParseErrorKind::InvalidOption {
+                                expected: EXTENSIONS,
+                            },
+                        ))
+                    }
+                };
+                watch.extensions = Some(extensions);
+            }
+            "rate_limit" => {
+                let limit = parse_rate(value).ok_or_else(|| {
+                    SyntaxError::failure(at, text, ParseErrorKind::InvalidOption { expected: RATE })
+                })?;
+                watch.rate_limit = Some(limit);
+            }
+            "burst" => {
+                let value = parse_burst(value).ok_or_else(|| {
+                    SyntaxError::failure(
+                        at,
+                        text,
+                        ParseErrorKind::InvalidOption { expected: BURST },
+                    )
+                })?;
+                burst = Some((at, text, value));
+            }
+            "tags" => match tag_list(value) {
+                Ok(("", tags)) => watch.tags = tags.into_iter().map(str::to_string).collect(),
+                _ => {
+                    return Err(SyntaxError::failure(
+                        at,
+                        text,
+                        ParseErrorKind::InvalidOption { expected: TAGS },
+                    ))
+                }
+            },
+            _ => return Err(SyntaxError::failure(at, key, ParseErrorKind::UnknownOption)),
+        }
+    }
+    if let Some((at, text, value)) = burst {
+        let Some(limit) = &mut watch.rate_limit else {
+            return Err(SyntaxError::failure(
+                at,
+                text,
+                ParseErrorKind::InvalidOption {
+                    expected: "a rate_limit option alongside it",
+                },
+            ));
+        };
+        limit.burst = value;
+    }
+    Ok((tail, watch))
+}
+
+/// Tags are letters, digits, `_`, `-`, `.` and `:`, so they can be namespaced as in
+/// `team:ops`.
+pub(crate) fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')
+}
+
+/// Something following the paths of an include.
+enum Clause<'a> {
+    Option(&'a str, &'a str),
+    Action(Action),
+}
+
+/// Parses an `on_<event> "command"` clause, where `on_change` matches any event.
+fn action_clause(input: &str) -> Res<'_, Action> {
+    let (rest, name) = terminated(
+        preceded(tag("on_"), alphanumeric1),
+        multispace1::<_, SyntaxError>,
+    )(input)?;
+    let events = match name {
+        "change" => EventSet::all(),
+        _ => match name.parse::<EventKind>() {
+            Ok(kind) => [kind].into_iter().collect(),
+            Err(_) => {
+                let text = &input[..name.len() + 3];
+                return Err(SyntaxError::failure(
+                    input,
+                    text,
+                    ParseErrorKind::UnknownOption,
+                ));
+            }
+        },
+    };
+    if !rest.starts_with('"') {
+        let text = &input[..name.len() + 3];
+        return Err(SyntaxError::failure(
+            rest,
+            text,
+            ParseErrorKind::MissingArgument {
+                expected: "a quoted command",
+            },
+        ));
+    }
+    let (tail, command) = quoted(rest, false)?;
+    let command = String::from_utf8(command).expect("only ASCII byte escapes are accepted");
+    Ok((tail, Action { events, command }))
+}
+
+fn key_value(input: &str) -> Res<'_, (&str, &str)> {
+    separated_pair(
+        take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+        char('='),
+        take_till1(|c: char| c.is_whitespace() || c == '#'),
+    )(input)
+}
+
+/// Parses an optional `-r` or `-s` flag, leaving the whitespace before the paths in place.
+fn recursion_flag(input: &str) -> Res<'_, Option<Recursion>> {
+    let (tail, flag) = opt(preceded(
+        multispace1,
+        terminated(
+            preceded(char('-'), take_till1(char::is_whitespace)),
+            peek(multispace1),
+        ),
+    ))(input)?;
+    match flag {
+        None => Ok((input, None)),
+        Some("r") => Ok((tail, Some(Recursion::Recursive))),
+        Some("s") => Ok((tail, Some(Recursion::NonRecursive))),
+        Some(flag) => {
+            let at = starting_at(input, flag);
+            let at = &input[input.len() - at.len() - 1..];
+            Err(SyntaxError::failure(
+                at,
+                &at[..flag.len() + 1],
+                ParseErrorKind::UnknownFlag,
+            ))
+        }
+    }
+}
+
+fn exclude_line<'a>(input: &'a str, options: &ParseOptions) -> Res<'a, ConfigLine> {
+    let (tail, paths) = arguments(input, "exclude", options)?;
+    Ok((tail, ConfigLine::Exclude(paths)))
+}
+
+fn source_line<'a>(input: &'a str, options: &ParseOptions) -> Res<'a, ConfigLine> {
+    let (tail, paths) = arguments(input, "source", options)?;
+    Ok((tail, ConfigLine::Source(paths)))
+}
+
+fn ignorefile_line<'a>(input: &'a str, options: &ParseOptions) -> Res<'a, ConfigLine> {
+    let (tail, paths) = arguments(input, "ignorefile", options)?;
+    Ok((tail, ConfigLine::IgnoreFile(paths)))
+}
+
+fn events_line(input: &str) -> Res<'_, ConfigLine> {
+    let (input, _) = required_space(input, "events", "an event")?;
+    let (tail, events) = event_list(input)?;
+    Ok((tail, ConfigLine::Events(events)))
+}
+
+fn only_extensions_line(input: &str) -> Res<'_, ConfigLine> {
+    let (input, _) = required_space(input, "only_extensions", "an extension")?;
+    let (tail, extensions) = extension_list(input).map_err(|_| {
+        let text = &input[..input.find(char::is_whitespace).unwrap_or(input.len())];
+        SyntaxError::failure(
+            input,
+            text,
+            ParseErrorKind::InvalidOption {
+                expected: EXTENSIONS,
+            },
+        )
+    })?;
+    Ok((tail, ConfigLine::OnlyExtensions(extensions)))
+}
+
+/// Parses the `on` or `off` argument of a directive such as `follow_symlinks on`.
+fn switch_argument<'a>(input: &'a str, directive: &'static str) -> Res<'a, bool> {
+    let (input, _) = required_space(input, directive, SWITCH)?;
+    let (tail, text) = take_till1(|c: char| c.is_whitespace() || c == '#')(input)?;
+    let on = parse_switch(text).ok_or_else(|| {
+        SyntaxError::failure(
+            input,
+            text,
+            ParseErrorKind::InvalidOption { expected: SWITCH },
+        )
+    })?;
+    Ok((tail, on))
+}
+
+fn parse_switch(text: &str) -> Option<bool> {
+    match text {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parses the user or group names following a directive such as `owner root, admin`.
+fn name_list_argument<'a>(input: &'a str, directive: &'static str) -> Res<'a, Vec<String>> {
+    let (input, _) = required_space(input, directive, "a name")?;
+    let (tail, names) = separated_list1(
+        delimited(space0, char(','), space0),
+        take_while1(|c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '$')),
+    )(input)
+    .map_err(|_: nom::Err<SyntaxError>| {
+        let text = &input[..input.find(char::is_whitespace).unwrap_or(input.len())];
+        SyntaxError::failure(
+            input,
+            text,
+            ParseErrorKind::InvalidOption { expected: NAMES },
+        )
+    })?;
+    Ok((tail, names.into_iter().map(str::to_string).collect()))
+}
+
+/// Parses the duration argument of a directive such as `debounce 500ms`.
+fn duration_argument<'a>(input: &'a str, directive: &'static str) -> Res<'a, Duration> {
+    let (input, _) = required_space(input, directive, "a duration")?;
+    let (tail, text) = take_till1(|c: char| c.is_whitespace() || c == '#')(input)?;
+    let duration = parse_duration(text).ok_or_else(|| {
+        SyntaxError::failure(
+            input,
+            text,
+            ParseErrorKind::InvalidOption { expected: DURATION },
+        )
+    })?;
+    Ok((tail, duration))
+}
+
+fn max_size_line(input: &str) -> Res<'_, ConfigLine> {
+    let (input, _) = required_space(input, "max_size", "a size")?;
+    let (tail, text) = take_till1(|c: char| c.is_whitespace() || c == '#')(input)?;
+    let size = parse_size(text).ok_or_else(|| {
+        SyntaxError::failure(
+            input,
+            text,
+            ParseErrorKind::InvalidOption { expected: SIZE },
+        )
+    })?;
+    Ok((tail, ConfigLine::MaxSize(size)))
+}
+
+/// Parses `rate_limit <rate>`, optionally followed by `burst=<n>`.
+fn rate_limit_line(input: &str) -> Res<'_, ConfigLine> {
+    let (input, _) = required_space(input, "rate_limit", "a rate")?;
+    let (tail, text) = take_till1(|c: char| c.is_whitespace() || c == '#')(input)?;
+    let mut limit = parse_rate(text).ok_or_else(|| {
+        SyntaxError::failure(
+            input,
+            text,
+            ParseErrorKind::InvalidOption { expected: RATE },
+        )
+    })?;
+    let (tail, option) = opt(preceded(multispace1, key_value))(tail)?;
+    if let Some((key, value)) = option {
+        let at = starting_at(input, key);
+        if key != "burst" {
+            return Err(SyntaxError::failure(at, key, ParseErrorKind::UnknownOption));
+        }
+        limit.burst = parse_burst(value).ok_or_else(|| {
+            SyntaxError::failure(
+                at,
+                &at[..key.len() + 1 + value.len()],
+                ParseErrorKind::InvalidOption { expected: BURST },
+            )
+        })?;
+    }
+    Ok((tail, ConfigLine::RateLimit(limit)))
+}
+
+/// Parses `poll_interval <duration>`, which can't be zero since the poller would never sleep.
+fn poll_interval_line(input: &str) -> Res<'_, ConfigLine> {
+    let (tail, interval) = duration_argument(input, "poll_interval")?;
+    if interval.is_zero() {
+        let at = input.trim_start();
+        return Err(SyntaxError::failure(
+            at,
+            &at[..at.len() - tail.len()],
+            ParseErrorKind::InvalidOption {
+                expected: "a duration longer than zero",
+            },
+        ));
+    }
+    Ok((tail, ConfigLine::PollInterval(interval)))
+}
+
+fn log_level_line(input: &str) -> Res<'_, ConfigLine> {
+    let (input, _) = required_space(input, "log_level", "a level")?;
+    let (tail, text) = take_till1(|c: char| c.is_whitespace() || c == '#')(input)?;
+    let level = text.parse().map_err(|_| {
+        SyntaxError::failure(
+            input,
+            text,
+            ParseErrorKind::InvalidOption {
+                expected: "one of error, warn, info, debug, trace",
+            },
+        )
+    })?;
+    Ok((tail, ConfigLine::LogLevel(level)))
+}
+
+fn preset_line(input: &str) -> Res<'_, ConfigLine> {
+    let (input, _) = required_space(input, "preset", "a preset")?;
+    let (tail, text) = take_till1(|c: char| c.is_whitespace() || c == '#')(input)?;
+    let preset = text.parse().map_err(|_| {
+        SyntaxError::failure(
+            input,
+            text,
+            ParseErrorKind::InvalidOption {
+                expected: "one of system",
+            },
+        )
+    })?;
+    Ok((tail, ConfigLine::Preset(preset)))
+}
+
+fn output_line(input: &str) -> Res<'_, ConfigLine> {
+    let (input, _) = required_space(input, "output", "a format")?;
+    let (tail, text) = take_till1(|c: char| c.is_whitespace() || c == '#')(input)?;
+    let format = text.parse().map_err(|_| {
+        SyntaxError::failure(
+            input,
+            text,
+            ParseErrorKind::InvalidOption {
+                expected: "one of plain, json, ndjson",
+            },
+        )
+    })?;
+    Ok((tail, ConfigLine::Output(format)))
+}
+
+/// Parses `log_file <path>`, which must name a single file rather than a glob.
+fn log_file_line<'a>(input: &'a str, options: &ParseOptions) -> Res<'a, ConfigLine> {
+    let (input, _) = required_space(input, "log_file", "a path")?;
+    let (tail, spec) = path_element(input, options)?;
+    match spec {
+        PathSpec::Path(path) => Ok((tail, ConfigLine::LogFile(path))),
+        PathSpec::Pattern(_) => Err(SyntaxError::failure(
+            input,
+            &input[..input.len() - tail.len()],
+            ParseErrorKind::InvalidPath(PathError::Glob),
+        )),
+    }
+}
+
+fn action_line(input: &str) -> Res<'_, ConfigLine> {
+    let (input, _) = required_space(input, "on", "an event")?;
+    let (input, events) = event_selector(input)?;
+    let (input, _) =
+        terminated(multispace1, tag("run"))(input).map_err(|_: nom::Err<SyntaxError>| {
+            SyntaxError::failure(
+                input,
+                "on",
+                ParseErrorKind::MissingArgument { expected: "run" },
+            )
+        })?;
+    let (tail, command) = rest_of_line(input, "run", "a command")?;
+    Ok((tail, ConfigLine::Action(Action { events, command })))
+}
+
+/// Parses either `any` or a list of events.
+fn event_selector(input: &str) -> Res<'_, EventSet> {
+    alt((
+        value(
+            EventSet::all(),
+            terminated(tag("any"), peek(not(alphanumeric1))),
+        ),
+        event_list,
+    ))(input)
+}
+
+/// Parses a `[profile <name>]` section header.
+fn profile_header(input: &str) -> Res<'_, ConfigLine> {
+    let end = input.find(']').map_or(input.len(), |i| i + 1);
+    let (header, tail) = input.split_at(end);
+    let name = header
+        .strip_prefix("[profile")
+        .and_then(|rest| rest.strip_suffix(']'))
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .map(str::trim)
+        .filter(|name| {
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        });
+    match name {
+        Some(name) => Ok((tail, ConfigLine::Profile(name.to_string()))),
+        None => Err(SyntaxError::failure(
+            input,
+            header.trim_end(),
+            ParseErrorKind::InvalidSection,
+        )),
+    }
+}
+
+/// Parses the `<name> {` following `watch` which opens a watch group.
+fn group_start(input: &str) -> Res<'_, ConfigLine> {
+    map(
+        delimited(
+            multispace1,
+            take_while1(|c: char| c.is_alphanumeric() || c == '-' || c == '_'),
+            tuple((space0, char('{'))),
+        ),
+        |name: &str| ConfigLine::WatchGroup(name.to_string()),
+    )(input)
+}
+
+/// Returns true if `line` opens a watch group, whether or not the rest of the group follows on
+/// the same line.
+pub(crate) fn opens_group(line: &str) -> bool {
+    matches!(directive_name(line), Ok((rest, "watch")) if group_start(rest).is_ok())
+}
+
+/// Splits a watch group written on one line, such as `watch web { include /srv; events modify }`,
+/// into its header, a line per directive and the closing brace. Each piece is padded with
+/// spaces to where it starts in `raw`, so errors point at the right column. Returns `None` for
+/// any other line, including a header with nothing after its brace.
+pub(crate) fn split_group_line(raw: &str) -> Option<Vec<String>> {
+    let (rest, name) = directive_name(raw.trim_start()).ok()?;
+    if name != "watch" {
+        return None;
+    }
+    let (body, _) = group_start(rest).ok()?;
+    let body = &body[..comment_start(body)];
+    if body.trim().is_empty() {
+        return None;
+    }
+
+    let mut pieces = split_outside_quotes(body, ';');
+    if let Some(last) = pieces.pop() {
+        match last.trim_end().strip_suffix('}') {
+            Some(directive) => {
+                pieces.push(directive);
+                pieces.push(&last[directive.len()..directive.len() + 1]);
+            }
+            None => pieces.push(last),
+        }
+    }
+    let padded = |piece: &str| {
+        let offset = piece.as_ptr() as usize - raw.as_ptr() as usize;
+        format!("{}{piece}", " ".repeat(raw[..offset].chars().count()))
+    };
+    let header = &raw[..body.as_ptr() as usize - raw.as_ptr() as usize];
+    Some(
+        std::iter::once(header.to_string())
+            .chain(pieces.into_iter().map(padded))
+            .collect(),
+    )
+}
+
+/// Splits `input` at each `separator` which isn't inside double quotes.
+fn split_outside_quotes(input: &str, separator: char) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                pieces.push(&input[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    pieces.push(&input[start..]);
+    pieces
+}
+
+/// Parses `set <name> <value>`, where the value is the rest of the line with variables and a
+/// leading tilde expanded.
+fn set_line<'a>(input: &'a str, options: &ParseOptions) -> Res<'a, ConfigLine> {
+    let (input, _) = required_space(input, "set", "a variable name")?;
+    let (rest, name) = take_till1(|c: char| c.is_whitespace() || c == '#')(input)?;
+    if !is_variable_name(name) {
+        return Err(SyntaxError::failure(
+            input,
+            name,
+            ParseErrorKind::InvalidOption {
+                expected: "a variable name of letters, digits and underscores",
+            },
+        ));
+    }
+    let (tail, raw) = rest_of_line(rest, "set", "a value")?;
+    let at = starting_at(rest, rest.trim_start());
+    let value = if options.expand_tilde {
+        expand_tilde(&raw).map_err(PathError::from)
+    } else {
+        Ok(Cow::Borrowed(raw.as_str()))
+    }
+    .and_then(|value| Ok(expand_variables(&value, options)?.into_owned()))
+    .map_err(|err| SyntaxError::failure(at, &at[..raw.len()], ParseErrorKind::InvalidValue(err)))?;
+    Ok((tail, ConfigLine::Set(name.to_string(), value)))
+}
+
+/// Parses `ignore <regex>`, where the regex is the rest of the line.
+fn ignore_line(input: &str) -> Res<'_, ConfigLine> {
+    let (tail, pattern) = rest_of_line(input, "ignore", "a pattern")?;
+    if let Err(message) = ignore::check(&pattern) {
+        let text = starting_at(input, input.trim_start());
+        return Err(SyntaxError::failure(
+            text,
+            &text[..pattern.len()],
+            ParseErrorKind::InvalidRegex(message),
+        ));
+    }
+    Ok((tail, ConfigLine::Ignore(pattern)))
+}
+
+/// Parses a directive registered with [`ParseOptions::directives`], handing it the rest of the
+/// line. Returns `None` if no directive called `name` is registered.
+fn custom_line<'a>(
+    input: &'a str,
+    name: &str,
+    options: &ParseOptions,
+) -> Option<Res<'a, ConfigLine>> {
+    let input = input.trim_start();
+    let args = input[..comment_start(input)].trim_end();
+    Some(match options.directives.parse(name, args)? {
+        Ok(directive) => Ok((&input[args.len()..], ConfigLine::Custom(directive))),
+        Err(message) => Err(SyntaxError::failure(
+            input,
+            args,
+            ParseErrorKind::InvalidArguments(message),
+        )),
+    })
+}
+
+/// The offset of the comment in `input`, or its length if there is none. A `#` only starts a
+/// comment at the start or after whitespace, and not within single or double quotes, so it can
+/// appear in arguments. A quote which is never closed is taken literally.
+pub(crate) fn comment_start(input: &str) -> usize {
+    let mut quoted_until = 0;
+    let mut after_space = true;
+    for (i, c) in input.char_indices() {
+        if i < quoted_until {
+            continue;
+        }
+        match c {
+            '#' if after_space => return i,
+            '"' | '\'' => quoted_until = closing_quote(&input[i..]).map_or(0, |end| i + end),
+            _ => {}
+        }
+        after_space = c.is_whitespace();
+    }
+    input.len()
+}
+
+/// The offset just past the quote closing the one `input` starts with. Within double quotes a
+/// backslash escapes the character after it.
+fn closing_quote(input: &str) -> Option<usize> {
+    let quote = input.chars().next()?;
+    let mut chars = input.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if quote == '"' => {
+                chars.next();
+            }
+            c if c == quote => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parses the rest of the line as the argument of `keyword`, such as a command. A `#` only
+/// starts a comment when it follows whitespace outside quotes, so it can still be used within
+/// the argument.
+fn rest_of_line<'a>(
+    input: &'a str,
+    keyword: &'static str,
+    expected: &'static str,
+) -> Res<'a, String> {
+    let (input, _) = required_space(input, keyword, expected)?;
+    let command = input[..comment_start(input)].trim_end();
+    if command.is_empty() {
+        return Err(SyntaxError::failure(
+            input,
+            keyword,
+            ParseErrorKind::MissingArgument { expected },
+        ));
+    }
+    Ok((&input[command.len()..], command.to_string()))
+}
+
+fn event_list(input: &str) -> Res<'_, EventSet> {
+    let (tail, names) = separated_list1(
+        delimited(space0, char(','), space0),
+        take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+    )(input)?;
+    let events = names
+        .into_iter()
+        .map(|name| {
+            name.parse()
+                .map_err(|_| SyntaxError::failure(name, name, ParseErrorKind::UnknownEvent))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((tail, events))
+}
+
+/// Parses a comma separated list of file extensions, written without their leading dot.
+fn extension_list(input: &str) -> Res<'_, Vec<String>> {
+    map(
+        separated_list1(
+            delimited(space0, char(','), space0),
+            take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
+        ),
+        |extensions: Vec<&str>| extensions.into_iter().map(str::to_string).collect(),
+    )(input)
+}
+
+fn tag_list(input: &str) -> Res<'_, Vec<&str>> {
+    separated_list1(char(','), take_while1(is_tag_char))(input)
+}
+
+/// Consumes the whitespace separating `directive` from its arguments, failing if there is none.
+fn required_space<'a>(
+    input: &'a str,
+    directive: &'static str,
+    expected: &'static str,
+) -> Res<'a, &'a str> {
+    multispace1::<_, SyntaxError>(input).map_err(|_| {
+        SyntaxError::failure(
+            input,
+            directive,
+            ParseErrorKind::MissingArgument { expected },
+        )
+    })
+}
+
+fn arguments<'a>(
+    input: &'a str,
+    directive: &'static str,
+    options: &ParseOptions,
+) -> Res<'a, Vec<PathSpec>> {
+    let (input, _) = required_space(input, directive, "a path")?;
+    path_list(input, options)
+}
+
+fn path_list<'a>(input: &'a str, options: &ParseOptions) -> Res<'a, Vec<PathSpec>> {
+    separated_list1(delimited(multispace0, tag(","), multispace0), |i| {
+        path_element(i, options)
+    })(input)
+}
+
+fn path_element<'a>(input: &'a str, options: &ParseOptions) -> Res<'a, PathSpec> {
+    let (input, _) = space0(input)?;
+    if input.starts_with('"') {
+        let (tail, path) = quoted(input, cfg!(unix))?;
+        return match quoted_path_spec(path, options) {
+            Ok(spec) => Ok((tail, spec)),
+            Err(err) => Err(SyntaxError::failure(
+                input,
+                &input[..input.len() - tail.len()],
+                ParseErrorKind::InvalidPath(err),
+            )),
+        };
+    }
+
+    let (tail, raw) = take_till(|c: char| c == ',' || c == '#' || c.is_whitespace())(input)?;
+    match path_spec(raw, options) {
+        Ok(spec) => Ok((tail, spec)),
+        Err(err) => Err(SyntaxError::failure(
+            input,
+            raw,
+            ParseErrorKind::InvalidPath(err),
+        )),
+    }
+}
+
+/// Parses a double quoted string, handling the `\"`, `\\`, `\n`, `\t` and `\xNN` escapes.
+/// Unless `bytes` is set `\xNN` is limited to ASCII, so the result is always valid UTF-8.
+pub(crate) fn quoted(input: &str, bytes: bool) -> Res<'_, Vec<u8>> {
+    let mut text = Vec::new();
+    let mut chars = input.char_indices().skip(1);
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Ok((&input[index + 1..], text)),
+            '\\' => {
+                let escaped = match chars.next() {
+                    Some((_, c @ ('"' | '\\'))) => Some(c as u8),
+                    Some((_, 'n')) => Some(b'\n'),
+                    Some((_, 't')) => Some(b'\t'),
+                    Some((_, 'x')) => {
+                        let digits = input.get(index + 2..index + 4);
+                        let byte = digits
+                            .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+                            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                            .filter(|byte| bytes || byte.is_ascii());
+                        if byte.is_some() {
+                            chars.nth(1);
+                        }
+                        byte
+                    }
+                    Some(_) => None,
+                    None => break,
+                };
+                match escaped {
+                    Some(byte) => text.push(byte),
+                    None => {
+                        let rest = &input[index..];
+                        return Err(SyntaxError::failure(
+                            rest,
+                            escape_text(rest),
+                            ParseErrorKind::InvalidEscape,
+                        ));
+                    }
+                }
+            }
+            c => text.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    Err(SyntaxError::failure(
+        input,
+        input.trim_end(),
+        ParseErrorKind::UnterminatedQuote,
+    ))
+}
+
+/// The escape at the start of `rest` as shown in errors: the backslash and the character after
+/// it, along with up to two digits for `\x`.
+fn escape_text(rest: &str) -> &str {
+    let len = if rest[1..].starts_with('x') { 4 } else { 2 };
+    let end = rest.char_indices().nth(len).map_or(rest.len(), |(i, _)| i);
+    let text = &rest[..end];
+    match text[1..].find('"') {
+        Some(quote) if quote > 0 => &text[..quote + 1],
+        _ => text,
+    }
+}
+
+/// Makes the literal path of a quoted string. On unix it may hold bytes which aren't valid UTF-8,
+/// in which case variables are expanded in the valid stretches around them.
+fn quoted_path_spec(raw: Vec<u8>, options: &ParseOptions) -> Result<PathSpec, PathError> {
+    let raw = match String::from_utf8(raw) {
+        Ok(raw) => {
+            return Ok(PathSpec::Path(literal_path(&expand_variables(
+                &raw, options,
+            )?)))
+        }
+        Err(err) => err.into_bytes(),
+    };
+    let mut path = Vec::with_capacity(raw.len());
+    for chunk in raw.utf8_chunks() {
+        path.extend_from_slice(expand_variables(chunk.valid(), options)?.as_bytes());
+        path.extend_from_slice(chunk.invalid());
+    }
+    Ok(PathSpec::Path(path_from_bytes(path)))
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+    OsString::from_vec(bytes).into()
+}
+
+/// Quoted strings only hold bytes which aren't UTF-8 on unix.
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    literal_path(&String::from_utf8_lossy(&bytes))
+}
+
+pub(crate) fn path_spec(raw: &str, options: &ParseOptions) -> Result<PathSpec, PathError> {
+    let raw = if options.expand_tilde {
+        expand_tilde(raw)?
+    } else {
+        Cow::Borrowed(raw)
+    };
+    Ok(expand_variables(&raw, options)?.parse()?)
+}
+
+fn comment(input: &str) -> IResult<&str, &str, ()> {
+    preceded(tag("#"), not_line_ending)(input)
+}
+
+fn line_end(input: &str) -> IResult<&str, (), ()> {
+    value((), tuple((space0, opt(comment), eof)))(input)
+}
+
+/// Returns the rest of `input` starting at `part`, which must be a slice of `input`.
+fn starting_at<'a>(input: &'a str, part: &str) -> &'a str {
+    &input[part.as_ptr() as usize - input.as_ptr() as usize..]
+}
+
+/// Finds the known directive closest to `name`, if it is close enough to be a typo.
+fn suggest(name: &str) -> Option<&'static str> {
+    DIRECTIVES
+        .iter()
+        .map(|directive| (edit_distance(name, directive), *directive))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, directive)| directive)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PatternError;
+
+    fn spec(path: &str) -> PathSpec {
+        path.parse().unwrap()
+    }
+
+    fn watch(recursion: Recursion) -> WatchOptions {
+        WatchOptions {
+            recursion,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_config_lines() {
+        let test_cases = vec![
+            (
+                r"include -r C:\Users\me\Documents, \\server\share depth=2",
+                ConfigLine::Include(
+                    vec![spec(r"C:\Users\me\Documents"), spec(r"\\server\share")],
+                    WatchOptions {
+                        recursion: Recursion::Recursive,
+                        max_depth: Some(2),
+                        ..Default::default()
+                    },
+                ),
+            ),
+            (
+                "include /etc/path",
+                ConfigLine::Include(vec![spec("/etc/path")], watch(Recursion::NonRecursive)),
+            ),
+            (
+                "include\t/etc/a,/etc/b",
+                ConfigLine::Include(
+                    vec![spec("/etc/a"), spec("/etc/b")],
+                    watch(Recursion::NonRecursive),
+                ),
+            ),
+            (
+                "include -r /var/log",
+                ConfigLine::Include(vec![spec("/var/log")], watch(Recursion::Recursive)),
+            ),
+            (
+                "include\t-s\t/srv",
+                ConfigLine::Include(vec![spec("/srv")], watch(Recursion::NonRecursive)),
+            ),
+            (
+                "include /data depth=3",
+                ConfigLine::Include(
+                    vec![spec("/data")],
+                    WatchOptions {
+                        recursion: Recursion::Recursive,
+                        max_depth: Some(3),
+                        ..Default::default()
+                    },
+                ),
+            ),
+            (
+                "include /etc events=modify,delete debounce=2s",
+                ConfigLine::Include(
+                    vec![spec("/etc")],
+                    WatchOptions {
+                        events: Some([EventKind::Modify, EventKind::Delete].into_iter().collect()),
+                        debounce: Some(Duration::from_secs(2)),
+                        ..Default::default()
+                    },
+                ),
+            ),
+            (
+                "include /srv/media max_size=2G ext=mkv,mp4 include_hidden=off tags=media,team:ops burst=20 rate_limit=5/10s",
+                ConfigLine::Include(
+                    vec![spec("/srv/media")],
+                    WatchOptions {
+                        rate_limit: Some(RateLimit {
+                            events: 5,
+                            period: Duration::from_secs(10),
+                            burst: 20,
+                        }),
+                        max_size: Some(2 << 30),
+                        extensions: Some(vec!["mkv".into(), "mp4".into()]),
+                        include_hidden: Some(false),
+                        tags: vec!["media".into(), "team:ops".into()],
+                        ..Default::default()
+                    },
+                ),
+            ),
+            (
+                r#"include /etc/nginx on_change "nginx -t && systemctl reload nginx" on_delete "logger \"gone\"""#,
+                ConfigLine::Include(
+                    vec![spec("/etc/nginx")],
+                    WatchOptions {
+                        actions: vec![
+                            Action {
+                                events: EventSet::all(),
+                                command: "nginx -t && systemctl reload nginx".to_string(),
+                            },
+                            Action {
+                                events: [EventKind::Delete].into_iter().collect(),
+                                command: r#"logger "gone""#.to_string(),
+                            },
+                        ],
+                        ..Default::default()
+                    },
+                ),
+            ),
+            (
+                "on modify run systemctl reload nginx",
+                ConfigLine::Action(Action {
+                    events: [EventKind::Modify].into_iter().collect(),
+                    command: "systemctl reload nginx".to_string(),
+                }),
+            ),
+            (
+                "on create, delete run echo '#1' >> /tmp/log # note",
+                ConfigLine::Action(Action {
+                    events: [EventKind::Create, EventKind::Delete].into_iter().collect(),
+                    command: "echo '#1' >> /tmp/log".to_string(),
+                }),
+            ),
+            (
+                r#"on modify run echo "a # b" 'c # d' "\" # e" # note"#,
+                ConfigLine::Action(Action {
+                    events: [EventKind::Modify].into_iter().collect(),
+                    command: r#"echo "a # b" 'c # d' "\" # e""#.to_string(),
+                }),
+            ),
+            (
+                "on modify run echo don't # note",
+                ConfigLine::Action(Action {
+                    events: [EventKind::Modify].into_iter().collect(),
+                    command: "echo don't".to_string(),
+                }),
+            ),
+            (
+                "on any run /usr/local/bin/notify",
+                ConfigLine::Action(Action {
+                    events: EventSet::all(),
+                    command: "/usr/local/bin/notify".to_string(),
+                }),
+            ),
+            (
+                "events create, rename",
+                ConfigLine::Events([EventKind::Create, EventKind::Rename].into_iter().collect()),
+            ),
+            ("exclude /etc/a", ConfigLine::Exclude(vec![spec("/etc/a")])),
+            (
+                "exclude /home/*/.cache, /var/log/**/*.gz",
+                ConfigLine::Exclude(vec![spec("/home/*/.cache"), spec("/var/log/**/*.gz")]),
+            ),
+            (
+                r"ignore \.sw[po]$",
+                ConfigLine::Ignore(r"\.sw[po]$".to_string()),
+            ),
+            ("ignore ~$ # backups", ConfigLine::Ignore("~$".to_string())),
+            (
+                "if host!=web*",
+                ConfigLine::If(Condition {
+                    key: ConditionKey::Host,
+                    negated: true,
+                    value: "web*".parse().unwrap(),
+                }),
+            ),
+            ("endif # web", ConfigLine::EndIf),
+            (
+                "debounce 500ms # bursts",
+                ConfigLine::Debounce(Duration::from_millis(500)),
+            ),
+            ("max_size 100M", ConfigLine::MaxSize(100 << 20)),
+            ("follow_symlinks on", ConfigLine::FollowSymlinks(true)),
+            ("include_hidden off", ConfigLine::IncludeHidden(false)),
+            (
+                "follow_symlinks off # default",
+                ConfigLine::FollowSymlinks(false),
+            ),
+            (
+                "owner root, 1000",
+                ConfigLine::Owner(vec!["root".into(), "1000".into()]),
+            ),
+            (
+                "group wheel # admins",
+                ConfigLine::Group(vec!["wheel".into()]),
+            ),
+            (
+                "only_extensions rs , toml,c-h # sources",
+                ConfigLine::OnlyExtensions(vec!["rs".into(), "toml".into(), "c-h".into()]),
+            ),
+            (
+                "poll_interval 2s",
+                ConfigLine::PollInterval(Duration::from_secs(2)),
+            ),
+            ("log_level debug", ConfigLine::LogLevel(LogLevel::Debug)),
+            ("output ndjson", ConfigLine::Output(OutputFormat::Ndjson)),
+            (
+                "preset system # defaults",
+                ConfigLine::Preset(Preset::System),
+            ),
+            (
+                r#"log_file "/var/log/over watch.log""#,
+                ConfigLine::LogFile("/var/log/over watch.log".into()),
+            ),
+            (
+                "[profile backups] # nightly",
+                ConfigLine::Profile("backups".to_string()),
+            ),
+            (
+                "[profile  on-call ]",
+                ConfigLine::Profile("on-call".to_string()),
+            ),
+            (
+                "set LOGDIR /var/log # logs",
+                ConfigLine::Set("LOGDIR".to_string(), "/var/log".to_string()),
+            ),
+            (
+                "ignorefile /srv/app/.gitignore",
+                ConfigLine::IgnoreFile(vec![spec("/srv/app/.gitignore")]),
+            ),
+            (
+                "watch -r /srv",
+                ConfigLine::Include(
+                    vec![spec("/srv")],
+                    WatchOptions {
+                        recursion: Recursion::Recursive,
+                        ..Default::default()
+                    },
+                ),
+            ),
+            (
+                "unwatch /srv/tmp",
+                ConfigLine::Exclude(vec![spec("/srv/tmp")]),
+            ),
+            (
+                "ignore_file /srv/.gitignore",
+                ConfigLine::IgnoreFile(vec![spec("/srv/.gitignore")]),
+            ),
+        ];
+
+        for test_case in test_cases {
+            let (_, actual) = parse_config_line(test_case.0, &ParseOptions::default()).unwrap();
+            assert_eq!(test_case.1, actual);
+        }
+    }
+
+    #[test]
+    fn parses_quoted_paths() {
+        let test_cases = vec![
+            (
+                r#""/home/user/My Documents""#,
+                vec!["/home/user/My Documents"],
+            ),
+            (r#""/srv/a,b", /srv/c"#, vec!["/srv/a,b", "/srv/c"]),
+            (r#"/srv/c ,  "/srv/#1"  "#, vec!["/srv/c", "/srv/#1"]),
+            (r#""/srv/\"quoted\"\\dir""#, vec![r#"/srv/"quoted"\dir"#]),
+            (r#""/srv/[literal]*""#, vec!["/srv/[literal]*"]),
+            (r#""/srv/a\tb\n\x41\x7e""#, vec!["/srv/a\tb\nA~"]),
+            (r#""""#, vec![""]),
+        ];
+
+        for test_case in test_cases {
+            let (tail, actual) = path_list(test_case.0, &ParseOptions::default()).unwrap();
+            assert!(line_end(tail).is_ok(), "{} left {tail:?}", test_case.0);
+            assert_eq!(
+                test_case
+                    .1
+                    .into_iter()
+                    .map(|p| PathSpec::Path(p.into()))
+                    .collect::<Vec<_>>(),
+                actual
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parses_paths_which_are_not_utf8() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let mut options = ParseOptions::default();
+        options
+            .variables
+            .insert("DIR".to_string(), "/srv".to_string());
+        let (_, actual) = path_list(r#""$DIR/caf\xe9\xff/${DIR}""#, &options).unwrap();
+        assert_eq!(
+            actual,
+            [PathSpec::Path(
+                OsStr::from_bytes(b"/srv/caf\xe9\xff//srv").into()
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_position() {
+        let test_cases = vec![
+            (
+                "inclide /etc/a",
+                "line 3: unknown directive 'inclide', did you mean 'include'?",
+            ),
+            (
+                "monitor /etc/a",
+                "line 3: unknown directive 'monitor', expected one of: include, exclude, source, events, on, ignore, ignorefile, if, else, endif, set, debounce, poll_interval, max_size, only_extensions, follow_symlinks, include_hidden, rate_limit, owner, group, log_level, log_file, output, preset",
+            ),
+            (
+                "  exclude",
+                "line 3, column 10: expected a path after 'exclude'",
+            ),
+            (
+                "include /etc/a, /etc/[b",
+                "line 3, column 17: invalid path '/etc/[b': invalid range pattern at position 5",
+            ),
+            (
+                r#"include "/etc/a"#,
+                r#"line 3, column 9: unterminated quote in "/etc/a"#,
+            ),
+            (
+                r#"include "/etc/\a""#,
+                r#"line 3, column 15: invalid escape '\a'"#,
+            ),
+            (
+                r#"include "/etc/\xZZ""#,
+                r#"line 3, column 15: invalid escape '\xZZ'"#,
+            ),
+            (
+                r#"include "/etc/\x4""#,
+                r#"line 3, column 15: invalid escape '\x4'"#,
+            ),
+            (
+                r#"include /etc on_change "echo \xff""#,
+                r#"line 3, column 30: invalid escape '\xff'"#,
+            ),
+            (
+                "include -x /etc/a",
+                "line 3, column 9: unknown flag '-x', expected -r or -s",
+            ),
+            (
+                "include /data depth=deep",
+                "line 3, column 15: invalid option 'depth=deep', expected a number of directories",
+            ),
+            (
+                "include -s /data depth=1",
+                "line 3, column 18: invalid option 'depth=1', expected no depth on a -s include",
+            ),
+            (
+                "events create,modfy",
+                "line 3, column 15: unknown event 'modfy', expected one of: create, modify, delete, rename",
+            ),
+            (
+                "include /etc events=create,moved",
+                "line 3, column 28: unknown event 'moved', expected one of: create, modify, delete, rename",
+            ),
+            ("events", "line 3, column 7: expected an event after 'events'"),
+            ("on modify", "line 3, column 10: expected run after 'on'"),
+            ("if hostname=web01", "line 3, column 4: invalid option 'hostname=web01', expected host=<name> or os=<name>"),
+            ("if", "line 3, column 3: expected a condition after 'if'"),
+            ("set LOG-DIR /var/log", "line 3, column 5: invalid option 'LOG-DIR', expected a variable name of letters, digits and underscores"),
+            ("set LOGDIR", "line 3, column 11: expected a value after 'set'"),
+            ("debounce", "line 3, column 9: expected a duration after 'debounce'"),
+            ("output yaml", "line 3, column 8: invalid option 'yaml', expected one of plain, json, ndjson"),
+            ("preset desktop", "line 3, column 8: invalid option 'desktop', expected one of system"),
+            ("log_level loud", "line 3, column 11: invalid option 'loud', expected one of error, warn, info, debug, trace"),
+            ("log_file /var/log/*.log", "line 3, column 10: invalid path '/var/log/*.log': expected a single file, not a glob"),
+            ("poll_interval 0s", "line 3, column 15: invalid option '0s', expected a duration longer than zero"),
+            ("debounce 5", "line 3, column 10: invalid option '5', expected a duration such as 500ms or 2s"),
+            ("include /etc debounce=fast", "line 3, column 14: invalid option 'debounce=fast', expected a duration such as 500ms or 2s"),
+            ("max_size 1.5G", "line 3, column 10: invalid option '1.5G', expected a size such as 512K or 100M"),
+            ("owner", "line 3, column 6: expected a name after 'owner'"),
+            ("follow_symlinks yes", "line 3, column 17: invalid option 'yes', expected on or off"),
+            ("include /srv follow_symlinks=1", "line 3, column 14: invalid option 'follow_symlinks=1', expected on or off"),
+            ("group @staff", "line 3, column 7: invalid option '@staff', expected a list of names or ids such as root,1000"),
+            ("only_extensions .rs", "line 3, column 17: invalid option '.rs', expected a list of extensions such as rs,toml"),
+            ("include /src ext=rs,,toml", "line 3, column 14: invalid option 'ext=rs,,toml', expected a list of extensions such as rs,toml"),
+            ("include /etc max_size=huge", "line 3, column 14: invalid option 'max_size=huge', expected a size such as 512K or 100M"),
+            ("rate_limit 10", "line 3, column 12: invalid option '10', expected a rate such as 10/s or 100/m"),
+            ("rate_limit 10/s burst=0", "line 3, column 17: invalid option 'burst=0', expected a number of events above zero"),
+            ("rate_limit 10/s limit=5", "line 3, column 17: unknown option 'limit'"),
+            ("include /srv burst=5", "line 3, column 14: invalid option 'burst=5', expected a rate_limit option alongside it"),
+            ("include /srv rate_limit=fast", "line 3, column 14: invalid option 'rate_limit=fast', expected a rate such as 10/s or 100/m"),
+            ("include /etc/ssh tags=security,", "line 3, column 18: invalid option 'tags=security,', expected a list of tags such as security,compliance"),
+            ("[backups]", "line 3, column 1: invalid section header '[backups]', expected [profile <name>]"),
+            ("  [profile a b]", "line 3, column 3: invalid section header '[profile a b]', expected [profile <name>]"),
+            ("[profile a", "line 3, column 1: invalid section header '[profile a', expected [profile <name>]"),
+            ("endif now", "line 3, column 7: unexpected 'now'"),
+            ("  @linux # nothing", "line 3, column 9: expected a directive after '@linux'"),
+            ("ignore  # swap files", "line 3, column 9: expected a pattern after 'ignore'"),
+            (
+                r"ignore \.swp( # unclosed",
+                r"line 3, column 8: invalid regex '\.swp(': unclosed group",
+            ),
+            ("on modify run  # comment", "line 3, column 16: expected a command after 'run'"),
+            (
+                "include /etc on_change reload",
+                "line 3, column 24: expected a quoted command after 'on_change'",
+            ),
+            (
+                r#"include /etc on_chmod "reload""#,
+                "line 3, column 14: unknown option 'on_chmod'",
+            ),
+            (
+                "include /data size=1",
+                "line 3, column 15: unknown option 'size'",
+            ),
+            (
+                r#"include "/etc/a" /etc/b # comment"#,
+                "line 3, column 18: unexpected '/etc/b # comment'",
+            ),
+        ];
+
+        for test_case in test_cases {
+            let err = parse_line(test_case.0, 3, &ParseOptions::default()).unwrap_err();
+            assert_eq!(test_case.1, err.to_string());
+        }
+    }
+
+    #[test]
+    fn reports_path_errors() {
+        let options = ParseOptions {
+            env: crate::EnvMode::Require,
+            ..Default::default()
+        };
+        let test_cases = vec![
+            (
+                "include /etc/[",
+                PathError::Pattern(PatternError {
+                    position: 5,
+                    message: "invalid range pattern",
+                }),
+            ),
+            (
+                "include $OVERWATCH_TEST_NEVER_SET/a",
+                PathError::UnsetVariable(crate::UnsetVariable(
+                    "OVERWATCH_TEST_NEVER_SET".to_string(),
+                )),
+            ),
+            (
+                r#"include "${OVERWATCH_TEST_NEVER_SET}""#,
+                PathError::UnsetVariable(crate::UnsetVariable(
+                    "OVERWATCH_TEST_NEVER_SET".to_string(),
+                )),
+            ),
+            (
+                "include ~overwatch-no-such-user/a",
+                PathError::UnknownUser(crate::UnknownUser("overwatch-no-such-user".to_string())),
+            ),
+        ];
+
+        for test_case in test_cases {
+            let err = parse_line(test_case.0, 1, &options).unwrap_err();
+            assert_eq!(ParseErrorKind::InvalidPath(test_case.1), err.kind);
+        }
+    }
+
+    #[test]
+    fn parses_lines_one_at_a_time() {
+        let input = "set D /srv\n\
+                     include $D/a, \\\n  $D/b\n\
+                     if os=none\n\
+                     exclude /never\n\
+                     endif\n\
+                     events modfy\n\
+                     @none include /other\n\
+                     watch db { include /etc/pg }";
+        let lines: Vec<_> = parse_lines(input).collect();
+        assert_eq!(lines.len(), 9, "{lines:?}");
+        assert_eq!(
+            lines[0],
+            Ok(ConfigLine::Set("D".to_string(), "/srv".to_string()))
+        );
+        assert_eq!(
+            lines[1],
+            Ok(ConfigLine::Include(
+                vec![spec("/srv/a"), spec("/srv/b")],
+                watch(Recursion::NonRecursive)
+            ))
+        );
+        assert!(matches!(lines[2], Ok(ConfigLine::If(_))));
+        assert_eq!(lines[3], Ok(ConfigLine::Exclude(vec![spec("/never")])));
+        assert_eq!(lines[4], Ok(ConfigLine::EndIf));
+        assert!(matches!(
+            &lines[5],
+            Err(ParseError {
+                line: 7,
+                kind: ParseErrorKind::UnknownEvent,
+                ..
+            })
+        ));
+        assert_eq!(lines[6], Ok(ConfigLine::WatchGroup("db".to_string())));
+        assert_eq!(lines[8], Ok(ConfigLine::EndWatchGroup));
+    }
+
+    #[test]
+    fn parses_file_lists() {
+        let test_cases = vec![
+            ("/etc/file", vec!["/etc/file"]),
+            ("/etc/a,/etc/b,/etc/c", vec!["/etc/a", "/etc/b", "/etc/c"]),
+            (
+                " /etc/a  , /etc/b   ,  /etc/c ",
+                vec!["/etc/a", "/etc/b", "/etc/c"],
+            ),
+            (
+                "\t/etc/a\t,/etc/b,/etc/c",
+                vec!["/etc/a", "/etc/b", "/etc/c"],
+            ),
+        ];
+
+        for test_case in test_cases {
+            let (_, actual) = path_list(test_case.0, &ParseOptions::default()).unwrap();
+            assert_eq!(
+                test_case
+                    .1
+                    .iter()
+                    .map(|p| p.parse().unwrap())
+                    .collect::<Vec<PathSpec>>(),
+                actual
+            );
+        }
+    }
+}
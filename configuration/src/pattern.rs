@@ -0,0 +1,311 @@
+//! Literal paths and glob patterns used by include and exclude directives.
+
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use glob::MatchOptions;
+
+pub(crate) const GLOB_CHARS: [char; 3] = ['*', '?', '['];
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// A path as written in the configuration.
+///
+/// Anything containing glob syntax (`*`, `?` or `[`) is treated as a [`Pattern`], everything
+/// else is taken literally.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSpec {
+    Path(PathBuf),
+    Pattern(Pattern),
+}
+
+impl PathSpec {
+    /// Returns true if `path` is this path, or lies beneath it.
+    pub fn covers(&self, path: &Path) -> bool {
+        match self {
+            PathSpec::Path(base) => path.starts_with(base),
+            PathSpec::Pattern(pattern) => path.ancestors().any(|p| pattern.matches(p)),
+        }
+    }
+
+    /// Makes a spec from a path which may not be valid UTF-8. Such a path is always taken
+    /// literally, since globs can only be written in UTF-8, and no expansion is applied.
+    pub fn from_os_str(path: &OsStr) -> Result<PathSpec, PatternError> {
+        match path.to_str() {
+            Some(path) => path.parse(),
+            None => Ok(PathSpec::Path(path.into())),
+        }
+    }
+
+    /// Returns true if the spec doesn't start at a root, such as `./src` or `*.log`.
+    pub fn is_relative(&self) -> bool {
+        match self {
+            PathSpec::Path(path) => path.is_relative(),
+            PathSpec::Pattern(pattern) => Path::new(pattern.as_str()).is_relative(),
+        }
+    }
+
+    /// Makes a relative spec absolute by prefixing it with `base`.
+    pub fn resolve(&self, base: &Path) -> PathSpec {
+        match self {
+            PathSpec::Path(path) => PathSpec::Path(base.join(path)),
+            PathSpec::Pattern(pattern) if Path::new(pattern.as_str()).is_relative() => {
+                let base = glob::Pattern::escape(&base.to_string_lossy());
+                let joined = Path::new(&base).join(pattern.as_str());
+                match glob::Pattern::new(&joined.to_string_lossy()) {
+                    Ok(joined) => PathSpec::Pattern(Pattern(joined)),
+                    Err(_) => self.clone(),
+                }
+            }
+            PathSpec::Pattern(_) => self.clone(),
+        }
+    }
+
+    /// Resolves this spec into concrete paths. Literal paths are returned as-is, patterns are
+    /// expanded against the filesystem.
+    pub fn expand(&self) -> Vec<PathBuf> {
+        match self {
+            PathSpec::Path(path) => vec![path.clone()],
+            PathSpec::Pattern(pattern) => pattern.expand(),
+        }
+    }
+}
+
+impl FromStr for PathSpec {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(GLOB_CHARS) {
+            Ok(PathSpec::Pattern(s.parse()?))
+        } else {
+            Ok(PathSpec::Path(literal_path(s)))
+        }
+    }
+}
+
+impl fmt::Display for PathSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSpec::Path(path) => write!(f, "{}", path.display()),
+            PathSpec::Pattern(pattern) => write!(f, "{pattern}"),
+        }
+    }
+}
+
+/// Turns a literal path from the configuration into a [`PathBuf`].
+///
+/// On Windows the path is normalized so the same path written differently compares equal:
+/// forward slashes become backslashes, repeated separators are collapsed apart from the two
+/// starting a UNC path such as `\\server\share`, and the drive letter is upper cased.
+/// Verbatim `\\?\` paths are taken as written.
+#[cfg(windows)]
+pub(crate) fn literal_path(raw: &str) -> PathBuf {
+    if raw.starts_with(r"\\?\") {
+        return PathBuf::from(raw);
+    }
+    let is_separator = |c: char| c == '\\' || c == '/';
+    let mut path = String::with_capacity(raw.len());
+    let mut rest = raw;
+    if rest.starts_with(is_separator) && rest[1..].starts_with(is_separator) {
+        path.push_str(r"\\");
+        rest = &rest[2..];
+    } else if let [drive, b':', ..] = rest.as_bytes() {
+        if drive.is_ascii_alphabetic() {
+            path.push(drive.to_ascii_uppercase() as char);
+            path.push(':');
+            rest = &rest[2..];
+        }
+    }
+    for c in rest.chars() {
+        if !is_separator(c) {
+            path.push(c);
+        } else if !path.ends_with('\\') {
+            path.push('\\');
+        }
+    }
+    PathBuf::from(path)
+}
+
+/// Turns a literal path from the configuration into a [`PathBuf`], taking it as written.
+#[cfg(not(windows))]
+pub(crate) fn literal_path(raw: &str) -> PathBuf {
+    PathBuf::from(raw)
+}
+
+/// A compiled glob pattern such as `/home/*/.cache` or `/var/log/**/*.log`.
+///
+/// `*` never matches a path separator, use `**` to match across directories.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern(glob::Pattern);
+
+impl Pattern {
+    /// The pattern as written in the configuration.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        self.0.matches_path_with(path, MATCH_OPTIONS)
+    }
+
+    /// Returns every existing path on the filesystem which matches this pattern.
+    pub fn expand(&self) -> Vec<PathBuf> {
+        match glob::glob_with(self.as_str(), MATCH_OPTIONS) {
+            Ok(paths) => paths.filter_map(Result::ok).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Pattern(glob::Pattern::new(s)?))
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A glob pattern could not be compiled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError {
+    /// The byte offset into the pattern at which the error occurred.
+    pub position: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl Error for PatternError {}
+
+impl From<glob::PatternError> for PatternError {
+    fn from(err: glob::PatternError) -> Self {
+        Self {
+            position: err.pos,
+            message: err.msg,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_path_specs() {
+        assert!(matches!("/etc/hosts".parse(), Ok(PathSpec::Path(_))));
+        assert!(matches!("/home/*/.cache".parse(), Ok(PathSpec::Pattern(_))));
+        assert!(matches!(
+            "/var/log/**/*.log".parse(),
+            Ok(PathSpec::Pattern(_))
+        ));
+        assert!("/etc/[".parse::<PathSpec>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn accepts_paths_which_are_not_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let raw = OsStr::from_bytes(b"/srv/caf\xe9*");
+        assert_eq!(
+            PathSpec::from_os_str(raw).unwrap(),
+            PathSpec::Path(raw.into())
+        );
+        assert!(matches!(
+            PathSpec::from_os_str(OsStr::new("/srv/*.log")),
+            Ok(PathSpec::Pattern(_))
+        ));
+    }
+
+    #[test]
+    fn parses_windows_paths() {
+        let test_cases = vec![
+            r"C:\Users\me\Documents",
+            r"\\server\share\dir",
+            r"D:relative",
+        ];
+        for raw in test_cases {
+            assert_eq!(raw.parse::<PathSpec>().unwrap().to_string(), raw);
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn normalizes_windows_paths() {
+        let test_cases = vec![
+            (r"C:\Users\me\Documents", r"C:\Users\me\Documents"),
+            ("c:/Users//me/", r"C:\Users\me\"),
+            (r"\\server\share\\dir", r"\\server\share\dir"),
+            ("//server/share", r"\\server\share"),
+            (r"\\?\c:\odd//name", r"\\?\c:\odd//name"),
+            ("relative/dir", r"relative\dir"),
+        ];
+        for (raw, expected) in test_cases {
+            assert_eq!(literal_path(raw), PathBuf::from(expected), "{raw}");
+        }
+
+        let spec: PathSpec = "c:/Users/me".parse().unwrap();
+        assert!(spec.covers(Path::new(r"C:\Users\me\notes.txt")));
+        assert!(Path::new(r"\\server\share\dir").is_absolute());
+    }
+
+    #[test]
+    fn resolves_relative_specs() {
+        let test_cases = vec![
+            ("conf.d/a.conf", "/etc/overwatch/conf.d/a.conf"),
+            ("/etc/hosts", "/etc/hosts"),
+            ("conf.d/*.conf", "/etc/overwatch/conf.d/*.conf"),
+            ("/srv/*.conf", "/srv/*.conf"),
+        ];
+
+        for test_case in test_cases {
+            let spec: PathSpec = test_case.0.parse().unwrap();
+            let resolved = spec.resolve(Path::new("/etc/overwatch"));
+            assert_eq!(test_case.1, resolved.to_string());
+        }
+    }
+
+    #[test]
+    fn covers_paths() {
+        let test_cases = vec![
+            ("/etc", "/etc", true),
+            ("/etc", "/etc/nginx/nginx.conf", true),
+            ("/etc", "/etcetera", false),
+            ("/home/*/.cache", "/home/user/.cache", true),
+            ("/home/*/.cache", "/home/user/.cache/app/file", true),
+            ("/home/*/.cache", "/home/user/nested/.cache", false),
+            ("/var/log/**/*.log", "/var/log/nginx/access.log", true),
+            ("/var/log/**/*.log", "/var/log/syslog", false),
+        ];
+
+        for test_case in test_cases {
+            let spec: PathSpec = test_case.0.parse().unwrap();
+            assert_eq!(
+                test_case.2,
+                spec.covers(Path::new(test_case.1)),
+                "{} covers {}",
+                test_case.0,
+                test_case.1
+            );
+        }
+    }
+}
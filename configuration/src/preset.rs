@@ -0,0 +1,104 @@
+//! Named sets of excludes which most deployments need, applied with `preset <name>`.
+
+use std::{fmt, str::FromStr};
+
+use crate::{Config, PathSpec};
+
+/// A named set of excludes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Preset {
+    /// Kernel and device filesystems, runtime state and caches, which produce event storms and
+    /// permission errors when watched.
+    System,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 1] = [Preset::System];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Preset::System => "system",
+        }
+    }
+
+    /// The paths the preset excludes.
+    pub fn excludes(self) -> Vec<PathSpec> {
+        let paths: &[&str] = match self {
+            Preset::System => &[
+                "/proc",
+                "/sys",
+                "/dev",
+                "/run",
+                "/var/cache",
+                "/root/.cache",
+                "/home/*/.cache",
+            ],
+        };
+        paths
+            .iter()
+            .map(|path| path.parse().expect("preset paths are valid"))
+            .collect()
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Preset {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Preset::ALL
+            .into_iter()
+            .find(|preset| preset.as_str() == s)
+            .ok_or(())
+    }
+}
+
+impl Config {
+    /// Adds the excludes of [`Preset::System`], as the `preset system` directive does.
+    pub fn use_default_excludes(&mut self) {
+        self.apply_preset(Preset::System);
+    }
+
+    /// Adds the excludes of `preset` which aren't already excluded.
+    pub fn apply_preset(&mut self, preset: Preset) {
+        for spec in preset.excludes() {
+            if !self.excludes.contains(&spec) {
+                self.excludes.push(spec);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn excludes_system_paths() {
+        let mut config: Config = "include -r /\nexclude /proc".parse().unwrap();
+        config.use_default_excludes();
+        assert_eq!(config.excludes().len(), Preset::System.excludes().len());
+        assert_eq!(
+            config,
+            "include -r /\npreset system".parse::<Config>().unwrap()
+        );
+
+        let test_cases = vec![
+            ("/proc/1/status", false),
+            ("/sys/class/net", false),
+            ("/home/user/.cache/pip/x", false),
+            ("/home/user/projects/a", true),
+            ("/etc/hosts", true),
+        ];
+        for (path, watched) in test_cases {
+            assert_eq!(config.is_watched(Path::new(path)), watched, "{path}");
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Rate limits such as `10/s burst=50`, capping how many events an include lets through.
+
+use std::{fmt, time::Duration};
+
+use crate::duration::{parse_duration, DisplayDuration};
+
+/// How many events an include or watch group may pass on, declared with `rate_limit 10/s` or
+/// a `rate_limit=10/s` option. Events beyond the limit are dropped, so a noisy directory can't
+/// starve the rest of the pipeline.
+///
+/// The limit is a token bucket: up to `burst` events go through at once, after which `events`
+/// more are allowed every `period`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(deny_unknown_fields)
+)]
+pub struct RateLimit {
+    pub events: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::duration"))]
+    pub period: Duration,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// A limit of `events` per `period`, with a burst of the same size.
+    pub fn new(events: u32, period: Duration) -> Self {
+        Self {
+            events,
+            period,
+            burst: events,
+        }
+    }
+}
+
+/// Written as the rate alone, such as `10/s`, without the burst.
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let period = DisplayDuration(self.period).to_string();
+        match period.strip_prefix('1') {
+            Some(unit) if unit.starts_with(char::is_alphabetic) => {
+                write!(f, "{}/{unit}", self.events)
+            }
+            _ => write!(f, "{}/{period}", self.events),
+        }
+    }
+}
+
+/// Parses a rate such as `10/s`, `100/m` or `5/10s`: a number of events, a `/` and either a
+/// unit of `ms`, `s`, `m`, `h` or `d` or a whole duration. Neither may be zero.
+pub fn parse_rate(input: &str) -> Option<RateLimit> {
+    let (events, period) = input.split_once('/')?;
+    if events.is_empty() || !events.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let events: u32 = events.parse().ok().filter(|&events| events > 0)?;
+    let period = if period.starts_with(|c: char| c.is_ascii_digit()) {
+        parse_duration(period)?
+    } else {
+        parse_duration(&format!("1{period}"))?
+    };
+    (!period.is_zero()).then(|| RateLimit::new(events, period))
+}
+
+/// Parses the number of a `burst=N` option, which must be at least one.
+pub(crate) fn parse_burst(input: &str) -> Option<u32> {
+    if input.is_empty() || !input.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    input.parse().ok().filter(|&burst| burst > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_writes_rates() {
+        let test_cases = vec![
+            ("10/s", Some((10, Duration::from_secs(1)))),
+            ("100/m", Some((100, Duration::from_secs(60)))),
+            ("5/10s", Some((5, Duration::from_secs(10)))),
+            ("1/h", Some((1, Duration::from_secs(3600)))),
+            ("20/250ms", Some((20, Duration::from_millis(250)))),
+            ("0/s", None),
+            ("10/0s", None),
+            ("10", None),
+            ("10/", None),
+            ("/s", None),
+            ("10/d", Some((10, Duration::from_secs(86_400)))),
+            ("10/w", None),
+            ("-1/s", None),
+            ("99999999999/s", None),
+        ];
+        for (input, expected) in test_cases {
+            let rate = parse_rate(input);
+            assert_eq!(
+                rate.map(|rate| (rate.events, rate.period)),
+                expected,
+                "{input}"
+            );
+            if let Some(rate) = rate {
+                assert_eq!(rate.burst, rate.events);
+                assert_eq!(rate.to_string(), input);
+            }
+        }
+        assert_eq!(parse_burst("50"), Some(50));
+        assert_eq!(parse_burst("0"), None);
+        assert_eq!(parse_burst("+5"), None);
+    }
+}
@@ -0,0 +1,424 @@
+//! Reads configuration lines one at a time from any [`BufRead`].
+
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, Read},
+    str,
+};
+
+use crate::{
+    parser::{
+        deprecated_name, is_block_line, parse_line, split_group_line, written_name, ConfigLine,
+    },
+    ConfigError, ParseError, ParseErrorKind, ParseOptions,
+};
+
+/// The longest line a [`ConfigReader`] accepts by default, in bytes.
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// An `if` block which is open while reading.
+struct Block {
+    /// The line the `if` is on, and the column it starts at.
+    line: usize,
+    column: usize,
+    /// Whether the lines around the block are being applied.
+    parent_active: bool,
+    /// Whether the condition held.
+    taken: bool,
+    /// Whether the lines currently being read are applied.
+    active: bool,
+    has_else: bool,
+}
+
+/// Parses configuration text lazily, yielding each directive as its line is read.
+///
+/// Only one line is held in memory at a time, and lines longer than
+/// [`ConfigReader::max_line_length`] are rejected rather than buffered, so arbitrarily large
+/// configurations can be read with bounded memory.
+///
+/// The reader evaluates `if` blocks, `@<os>` prefixes and `set` itself, so it yields only the
+/// directives which apply, with variables already expanded. `source` directives, profile
+/// headers and the lines opening and closing watch groups are yielded as they are for the
+/// caller to act on. A watch group written on one line is yielded as if it was written over
+/// several.
+///
+/// Iteration stops after the first error, unless [`ParseOptions::strict`] is off. Then a line
+/// which can't be parsed is yielded as an error and reading carries on with the next one, as if
+/// the line wasn't there. Errors reading the input always stop iteration.
+pub struct ConfigReader<R> {
+    reader: R,
+    options: ParseOptions,
+    max_line_length: usize,
+    bytes: Vec<u8>,
+    buf: String,
+    /// The line the last logical line started on, and the last line read.
+    number: usize,
+    last: usize,
+    column: usize,
+    /// The rest of a watch group written on one line, yielded before reading further.
+    pending: VecDeque<String>,
+    blocks: Vec<Block>,
+    /// Whether to carry on after a line which can't be parsed, even when strict.
+    recover: bool,
+    done: bool,
+}
+
+impl<R: BufRead> ConfigReader<R> {
+    /// Reads with the default [`ParseOptions`].
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, ParseOptions::default())
+    }
+
+    pub fn with_options(reader: R, options: ParseOptions) -> Self {
+        Self {
+            reader,
+            options,
+            max_line_length: MAX_LINE_LENGTH,
+            bytes: Vec::new(),
+            buf: String::new(),
+            number: 0,
+            last: 0,
+            column: 1,
+            pending: VecDeque::new(),
+            blocks: Vec::new(),
+            recover: false,
+            done: false,
+        }
+    }
+
+    /// Sets the longest line accepted, in bytes, not counting the line ending. Defaults to
+    /// 64 KiB.
+    pub fn max_line_length(mut self, limit: usize) -> Self {
+        self.max_line_length = limit;
+        self
+    }
+
+    /// Carries on after a line which can't be parsed even with [`ParseOptions::strict`] on, so
+    /// the loader can report every error in one go.
+    pub(crate) fn recover(mut self) -> Self {
+        self.recover = true;
+        self
+    }
+
+    /// The number of the line the last directive read starts on, counting from 1. Lines a
+    /// directive is continued onto are counted too, so the numbers match the input.
+    pub fn line_number(&self) -> usize {
+        self.number
+    }
+
+    /// If the last directive read was written with a deprecated name, returns that name and the
+    /// directive to use instead.
+    pub fn deprecated(&self) -> Option<(&str, &'static str)> {
+        deprecated_name(&self.buf)
+    }
+
+    /// The name the directive on the last line read is written with.
+    pub(crate) fn directive(&self) -> &str {
+        written_name(&self.buf).unwrap_or_default()
+    }
+
+    /// The column the directive on the last line read starts at.
+    pub(crate) fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Reads the next logical line into the buffer, joining lines which end with a backslash
+    /// onto the one after. Returns false at the end of the input.
+    fn read_line(&mut self) -> Result<bool, ConfigError> {
+        self.bytes.clear();
+        loop {
+            let start = self.bytes.len();
+            let limit = (self.max_line_length - start).saturating_add(2) as u64;
+            let read = self
+                .reader
+                .by_ref()
+                .take(limit)
+                .read_until(b'\n', &mut self.bytes)?;
+            if read == 0 {
+                if start == 0 {
+                    return Ok(false);
+                }
+                break;
+            }
+            self.last += 1;
+            if start == 0 {
+                self.number = self.last;
+            }
+            let newline = self.bytes.ends_with(b"\n");
+            if newline {
+                self.bytes.pop();
+                if self.bytes.ends_with(b"\r") {
+                    self.bytes.pop();
+                }
+            }
+            if self.bytes.len() > self.max_line_length {
+                // Drop the rest of the line, so reading can carry on from the next one when
+                // errors aren't fatal.
+                if !newline {
+                    self.reader.skip_until(b'\n')?;
+                }
+                return Err(ConfigError::Parse(ParseError {
+                    line: self.number,
+                    column: 1,
+                    text: String::new(),
+                    kind: ParseErrorKind::LineTooLong {
+                        limit: self.max_line_length,
+                    },
+                }));
+            }
+            if !is_continued(&self.bytes) {
+                break;
+            }
+            self.bytes.pop();
+            if !newline {
+                break;
+            }
+        }
+        let line = str::from_utf8(&self.bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.buf.clear();
+        self.buf.push_str(line);
+        Ok(true)
+    }
+
+    /// Moves on to the next piece of a watch group written on one line, or else reads the next
+    /// logical line. Returns false at the end of the input.
+    fn advance(&mut self) -> Result<bool, ConfigError> {
+        if let Some(piece) = self.pending.pop_front() {
+            self.buf = piece;
+        } else if !self.read_line()? {
+            return Ok(false);
+        } else if let Some(pieces) = split_group_line(&self.buf) {
+            self.pending.extend(pieces);
+            return self.advance();
+        }
+        self.column = self.buf.len() - self.buf.trim_start().len() + 1;
+        Ok(true)
+    }
+
+    fn next_line(&mut self) -> Result<Option<ConfigLine>, ConfigError> {
+        while self.advance()? {
+            let number = self.number;
+            let active = self.blocks.last().is_none_or(|block| block.active);
+            if !active && !is_block_line(&self.buf) {
+                continue;
+            }
+            match parse_line(&self.buf, number, &self.options)? {
+                Some(ConfigLine::If(condition)) => {
+                    let taken = condition.holds(&self.options.context);
+                    self.blocks.push(Block {
+                        line: number,
+                        column: self.column,
+                        parent_active: active,
+                        taken,
+                        active: active && taken,
+                        has_else: false,
+                    });
+                }
+                Some(ConfigLine::Else) => match self.blocks.last_mut() {
+                    Some(block) if !block.has_else => {
+                        block.has_else = true;
+                        block.active = block.parent_active && !block.taken;
+                    }
+                    _ => return Err(unmatched(number, self.column, "else")),
+                },
+                Some(ConfigLine::EndIf) => {
+                    self.blocks
+                        .pop()
+                        .ok_or_else(|| unmatched(number, self.column, "endif"))?;
+                }
+                Some(ConfigLine::Set(name, value)) => {
+                    self.options.variables.insert(name, value);
+                }
+                Some(line) => return Ok(Some(line)),
+                None => {}
+            }
+        }
+        match self.blocks.pop() {
+            Some(block) => Err(unmatched(block.line, block.column, "if")),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for ConfigReader<R> {
+    type Item = Result<ConfigLine, ConfigError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_line().transpose();
+        let fatal = match &result {
+            Some(Ok(_)) => false,
+            Some(Err(ConfigError::Parse(_))) => self.options.strict && !self.recover,
+            _ => true,
+        };
+        self.done = fatal;
+        result
+    }
+}
+
+fn unmatched(line: usize, column: usize, text: &str) -> ConfigError {
+    ConfigError::Parse(ParseError {
+        line,
+        column,
+        text: text.to_string(),
+        kind: ParseErrorKind::UnmatchedConditional,
+    })
+}
+
+/// Whether `line` is continued on the next one: it ends with a backslash which follows
+/// whitespace or a comma, or stands alone. A backslash straight after anything else, as in
+/// `include C:\`, is part of the path it ends. A comment can't be continued, so a backslash
+/// ending one is just part of it.
+pub(crate) fn is_continued(line: &[u8]) -> bool {
+    let line = line.trim_ascii_start();
+    if line.starts_with(b"#") {
+        return false;
+    }
+    match line.strip_suffix(b"\\") {
+        Some(head) => head
+            .last()
+            .is_none_or(|&last| last == b',' || last.is_ascii_whitespace()),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PathSpec;
+
+    #[test]
+    fn yields_lines_lazily() {
+        let input = "set DIR /srv\r\n\
+                     include $DIR/a # first\n\
+                     \n\
+                     if os=none\n\
+                     include /never\n\
+                     endif\n\
+                     exclude $DIR/a/b\n";
+        let mut reader = ConfigReader::new(input.as_bytes());
+
+        let spec = |path: &str| path.parse::<PathSpec>().unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Ok(ConfigLine::Include(paths, _))) if paths == [spec("/srv/a")]
+        ));
+        assert_eq!(reader.line_number(), 2);
+        assert_eq!(
+            reader.next().unwrap().unwrap(),
+            ConfigLine::Exclude(vec![spec("/srv/a/b")])
+        );
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn joins_continued_lines() {
+        let input = "include /etc/a, \\\n\
+                     \x20   /etc/b,\\\n\
+                     \x20   /etc/c\n\
+                     # not continued \\\n\
+                     exclude /etc/a/x \\\r\n\
+                     \x20 , /etc/b/y\n\
+                     include /srv \\";
+        let mut reader = ConfigReader::new(input.as_bytes());
+
+        let spec = |path: &str| path.parse::<PathSpec>().unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Ok(ConfigLine::Include(paths, _)))
+                if paths == [spec("/etc/a"), spec("/etc/b"), spec("/etc/c")]
+        ));
+        assert_eq!(
+            reader.next().unwrap().unwrap(),
+            ConfigLine::Exclude(vec![spec("/etc/a/x"), spec("/etc/b/y")])
+        );
+        assert_eq!(reader.line_number(), 5);
+        assert!(matches!(reader.next(), Some(Ok(ConfigLine::Include(..)))));
+        assert_eq!(reader.line_number(), 7);
+        assert!(reader.next().is_none());
+
+        let err = ConfigReader::new("include /a,\\\n/b,\\\n/c [".as_bytes())
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert!(err.to_string().starts_with("line 1, "), "{err}");
+
+        // A backslash ending a path is part of it.
+        let mut reader = ConfigReader::new("include C:\\\ninclude /b \\\n, /c".as_bytes());
+        assert!(matches!(
+            reader.next(),
+            Some(Ok(ConfigLine::Include(paths, _))) if paths == [spec(r"C:\")]
+        ));
+        assert!(matches!(
+            reader.next(),
+            Some(Ok(ConfigLine::Include(paths, _))) if paths == [spec("/b"), spec("/c")]
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn stops_at_the_first_error() {
+        let test_cases = vec![
+            (
+                "include /a\nmonitor /b\ninclude /c",
+                "line 2: unknown directive",
+            ),
+            (
+                "include /a\nif os=none\n",
+                "line 2: 'if' without a matching 'endif'",
+            ),
+            (
+                "include /a\ninclude /0123456789\n",
+                "line 2: longer than the limit of 16 bytes",
+            ),
+        ];
+        for (input, expected) in test_cases {
+            let mut reader = ConfigReader::new(input.as_bytes()).max_line_length(16);
+            assert!(matches!(reader.next(), Some(Ok(_))));
+            let err = reader.next().unwrap().unwrap_err().to_string();
+            assert!(err.starts_with(expected), "{err}");
+            assert!(reader.next().is_none());
+
+            let lenient = ParseOptions {
+                strict: false,
+                ..Default::default()
+            };
+            let mut reader =
+                ConfigReader::with_options(input.as_bytes(), lenient).max_line_length(16);
+            assert!(matches!(reader.next(), Some(Ok(_))));
+            assert!(reader.next().unwrap().is_err());
+            assert!(reader.all(|line| line.is_ok()));
+        }
+    }
+
+    #[test]
+    fn carries_on_past_errors_when_lenient() {
+        let input = "include /a\ninclude /0123456789abcdef\nelse\ninclude /b";
+        let lenient = ParseOptions {
+            strict: false,
+            ..Default::default()
+        };
+        let lines: Vec<_> = ConfigReader::with_options(input.as_bytes(), lenient)
+            .max_line_length(16)
+            .map(|line| line.map_err(|err| err.to_string()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                Ok(ConfigLine::Include(
+                    vec!["/a".parse().unwrap()],
+                    Default::default()
+                )),
+                Err("line 2: longer than the limit of 16 bytes".to_string()),
+                Err("line 3: 'else' without a matching 'if'".to_string()),
+                Ok(ConfigLine::Include(
+                    vec!["/b".parse().unwrap()],
+                    Default::default()
+                )),
+            ]
+        );
+    }
+}
@@ -0,0 +1,280 @@
+//! Reloading a configuration file while it is in use.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, Weak,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::{Config, ConfigDiff, ConfigError, ParseOptions, WatchEntry};
+
+/// Sent to subscribers of a [`ConfigReloader`] whenever a reload is attempted.
+#[derive(Debug, Clone)]
+pub enum ReloadEvent {
+    /// The file changed and the new configuration is now current.
+    Reloaded(Reload),
+    /// The file could not be loaded, so the previous configuration is still current.
+    Failed(Arc<ConfigError>),
+}
+
+/// A configuration which replaced the previous one.
+#[derive(Debug, Clone)]
+pub struct Reload {
+    pub config: Arc<Config>,
+    /// Includes which weren't in the previous configuration and should start being watched.
+    pub added: Vec<WatchEntry>,
+    /// Includes which are no longer in the configuration and should stop being watched. An
+    /// include whose options changed appears in both lists.
+    pub removed: Vec<WatchEntry>,
+    /// Everything which changed, as reported by [`Config::diff`].
+    pub diff: ConfigDiff,
+}
+
+/// Keeps the configuration loaded from a file up to date.
+///
+/// [`ConfigReloader::current`] always returns a complete configuration: a new one is swapped in
+/// only once it has loaded successfully. Clones share the same state, and the background thread
+/// started by [`ConfigReloader::spawn`] stops once every clone has been dropped.
+#[derive(Debug, Clone)]
+pub struct ConfigReloader {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    path: PathBuf,
+    options: ParseOptions,
+    current: Mutex<Arc<Config>>,
+    stamp: Mutex<Option<Stamp>>,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+/// Where a subscriber receives reload events.
+#[derive(Debug)]
+enum Subscriber {
+    Sync(Sender<ReloadEvent>),
+    #[cfg(feature = "tokio")]
+    Async(tokio::sync::mpsc::UnboundedSender<ReloadEvent>),
+}
+
+impl Subscriber {
+    /// Sends `event`, returning false once the receiver is gone.
+    fn send(&self, event: ReloadEvent) -> bool {
+        match self {
+            Subscriber::Sync(sender) => sender.send(event).is_ok(),
+            #[cfg(feature = "tokio")]
+            Subscriber::Async(sender) => sender.send(event).is_ok(),
+        }
+    }
+}
+
+/// What is compared to decide whether the file changed.
+type Stamp = (SystemTime, u64);
+
+impl ConfigReloader {
+    /// Loads the configuration file at `path`, failing if the initial load fails.
+    pub fn new<P: AsRef<Path>>(path: P, options: ParseOptions) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let stamp = stamp(path);
+        let config = Config::from_file_with(path, &options)?;
+        let reloader = Self::with_config(path, options, config);
+        *reloader.inner.stamp.lock().unwrap() = stamp;
+        Ok(reloader)
+    }
+
+    /// Starts from `config`, already loaded from the file at `path`, such as by
+    /// [`Config::discover_with`].
+    pub fn with_config<P: AsRef<Path>>(path: P, options: ParseOptions, config: Config) -> Self {
+        let path = path.as_ref().to_path_buf();
+        Self {
+            inner: Arc::new(Inner {
+                stamp: Mutex::new(stamp(&path)),
+                path,
+                options,
+                current: Mutex::new(Arc::new(config)),
+                subscribers: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// The configuration file being reloaded.
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    /// The most recently loaded configuration.
+    pub fn current(&self) -> Arc<Config> {
+        self.inner.current.lock().unwrap().clone()
+    }
+
+    /// Returns a channel which receives an event for every reload from now on.
+    pub fn subscribe(&self) -> Receiver<ReloadEvent> {
+        let (sender, receiver) = mpsc::channel();
+        let subscriber = Subscriber::Sync(sender);
+        self.inner.subscribers.lock().unwrap().push(subscriber);
+        receiver
+    }
+
+    /// Like [`ConfigReloader::subscribe`], with a channel which can be awaited.
+    #[cfg(feature = "tokio")]
+    pub fn subscribe_async(&self) -> tokio::sync::mpsc::UnboundedReceiver<ReloadEvent> {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let subscriber = Subscriber::Async(sender);
+        self.inner.subscribers.lock().unwrap().push(subscriber);
+        receiver
+    }
+
+    /// Loads the file again, swapping in the result if it differs from the current
+    /// configuration. Returns `Ok(None)` if nothing changed.
+    pub fn reload(&self) -> Result<Option<Reload>, Arc<ConfigError>> {
+        self.inner.reload()
+    }
+
+    /// Reloads if the file's modification time or size changed since it was last loaded.
+    ///
+    /// Only the file itself is checked, not files it pulls in with `source`.
+    pub fn reload_if_changed(&self) -> Result<Option<Reload>, Arc<ConfigError>> {
+        self.inner.reload_if_changed()
+    }
+
+    /// Starts a thread which calls [`ConfigReloader::reload_if_changed`] every `interval`.
+    /// Reloading on a signal is left to the program, which can call
+    /// [`ConfigReloader::reload`] from its own handling of it.
+    pub fn spawn(&self, interval: Duration) -> thread::JoinHandle<()> {
+        let inner: Weak<Inner> = Arc::downgrade(&self.inner);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            let Some(inner) = inner.upgrade() else {
+                return;
+            };
+            let _ = inner.reload_if_changed();
+        })
+    }
+}
+
+impl Inner {
+    fn reload_if_changed(&self) -> Result<Option<Reload>, Arc<ConfigError>> {
+        if stamp(&self.path) == *self.stamp.lock().unwrap() {
+            return Ok(None);
+        }
+        self.reload()
+    }
+
+    fn reload(&self) -> Result<Option<Reload>, Arc<ConfigError>> {
+        *self.stamp.lock().unwrap() = stamp(&self.path);
+        let config = match Config::from_file_with(&self.path, &self.options) {
+            Ok(config) => config,
+            Err(err) => {
+                let err = Arc::new(err);
+                self.notify(ReloadEvent::Failed(err.clone()));
+                return Err(err);
+            }
+        };
+
+        let reload = {
+            let mut current = self.current.lock().unwrap();
+            if **current == config {
+                return Ok(None);
+            }
+            let reload = Reload {
+                added: difference(config.includes(), current.includes()),
+                removed: difference(current.includes(), config.includes()),
+                diff: Config::diff(&current, &config),
+                config: Arc::new(config),
+            };
+            *current = reload.config.clone();
+            reload
+        };
+        self.notify(ReloadEvent::Reloaded(reload.clone()));
+        Ok(Some(reload))
+    }
+
+    fn notify(&self, event: ReloadEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()));
+    }
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// The entries of `a` which aren't in `b`.
+fn difference(a: &[WatchEntry], b: &[WatchEntry]) -> Vec<WatchEntry> {
+    a.iter()
+        .filter(|entry| !b.contains(entry))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(entries: &[WatchEntry]) -> Vec<String> {
+        entries.iter().map(|entry| entry.path.to_string()).collect()
+    }
+
+    #[test]
+    fn reloads_and_notifies() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+        fs::write(&path, "include /etc, /srv").unwrap();
+
+        let reloader = ConfigReloader::new(&path, ParseOptions::default()).unwrap();
+        let events = reloader.subscribe();
+        assert!(reloader.reload().unwrap().is_none());
+
+        fs::write(&path, "include /srv\ninclude -r /var").unwrap();
+        let reload = reloader.reload().unwrap().unwrap();
+        assert_eq!(paths(&reload.added), ["/var"]);
+        assert_eq!(paths(&reload.removed), ["/etc"]);
+        assert_eq!(
+            reload.diff.to_string(),
+            "added include /var\nremoved include /etc\n"
+        );
+        assert_eq!(paths(reloader.current().includes()), ["/srv", "/var"]);
+        assert!(matches!(events.try_recv(), Ok(ReloadEvent::Reloaded(_))));
+        #[cfg(feature = "tokio")]
+        let mut async_events = reloader.subscribe_async();
+
+        fs::write(&path, "inclde /etc").unwrap();
+        assert!(reloader.reload().is_err());
+        assert!(matches!(events.try_recv(), Ok(ReloadEvent::Failed(_))));
+        #[cfg(feature = "tokio")]
+        assert!(matches!(
+            async_events.try_recv(),
+            Ok(ReloadEvent::Failed(_))
+        ));
+        assert_eq!(paths(reloader.current().includes()), ["/srv", "/var"]);
+    }
+
+    #[test]
+    fn polls_for_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+        fs::write(&path, "include /etc").unwrap();
+
+        let reloader = ConfigReloader::new(&path, ParseOptions::default()).unwrap();
+        let events = reloader.subscribe();
+        let handle = reloader.spawn(Duration::from_millis(10));
+        fs::write(&path, "include /etc/nginx").unwrap();
+
+        match events.recv_timeout(Duration::from_secs(5)) {
+            Ok(ReloadEvent::Reloaded(reload)) => {
+                assert_eq!(paths(reload.config.includes()), ["/etc/nginx"])
+            }
+            other => panic!("expected a reload, got {other:?}"),
+        }
+        drop(reloader);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,307 @@
+//! serde support for the configuration types, enabled by the `serde` feature.
+//!
+//! Values are written the way the DSL spells them where there is a spelling, so event kinds,
+//! log levels, output formats, durations and sizes are strings such as `"modify"`, `"500ms"` or
+//! `"100M"`, and
+//! paths and patterns are the text they were written as.
+
+use std::{path::PathBuf, time::Duration};
+
+use serde::{de, ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    duration::{parse_duration, DisplayDuration},
+    ignore,
+    size::{parse_size, DisplaySize},
+    EventKind, EventSet, IgnoreFile, IgnoreSet, LogLevel, OutputFormat, PathSpec, Pattern, Preset,
+};
+
+/// Implements serde for a type as its `Display` text, parsed back with `FromStr`. `what`
+/// describes the type in errors.
+macro_rules! as_string {
+    ($($ty:ty => $what:literal),* $(,)?) => {$(
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let text = String::deserialize(deserializer)?;
+                text.parse()
+                    .map_err(|_| de::Error::custom(format_args!("invalid {} '{text}'", $what)))
+            }
+        }
+    )*};
+}
+
+as_string! {
+    EventKind => "event",
+    LogLevel => "log level",
+    OutputFormat => "output format",
+    Preset => "preset",
+    Pattern => "pattern",
+}
+
+/// A literal path is written as a string, so it must be valid UTF-8 like any [`PathBuf`].
+impl Serialize for PathSpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            PathSpec::Path(path) => path.serialize(serializer),
+            PathSpec::Pattern(pattern) => pattern.serialize(serializer),
+        }
+    }
+}
+
+/// Parsed as in the DSL, so anything with glob syntax becomes a pattern.
+impl<'de> Deserialize<'de> for PathSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse()
+            .map_err(|err| de::Error::custom(format_args!("invalid path '{text}': {err}")))
+    }
+}
+
+impl Serialize for EventSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(None)?;
+        for kind in self.iter() {
+            seq.serialize_element(&kind)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for EventSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Vec::<EventKind>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+/// The patterns and the paths of the files. Deserializing checks the patterns and loads the
+/// files again.
+#[derive(Serialize, Deserialize)]
+struct IgnoreSetRepr {
+    #[serde(default)]
+    patterns: Vec<String>,
+    #[serde(default)]
+    files: Vec<PathBuf>,
+}
+
+impl Serialize for IgnoreSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        IgnoreSetRepr {
+            patterns: self.patterns().to_vec(),
+            files: self
+                .files()
+                .iter()
+                .map(|file| file.path().to_path_buf())
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for IgnoreSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = IgnoreSetRepr::deserialize(deserializer)?;
+        let mut set = IgnoreSet::default();
+        for pattern in repr.patterns {
+            ignore::check(&pattern).map_err(|message| {
+                de::Error::custom(format_args!("invalid regex '{pattern}': {message}"))
+            })?;
+            set.push(pattern);
+        }
+        for path in repr.files {
+            let file = IgnoreFile::load(&path)
+                .map_err(|err| de::Error::custom(format_args!("{}: {err}", path.display())))?;
+            set.push_file(file);
+        }
+        Ok(set)
+    }
+}
+
+/// A [`Duration`] as a literal such as `"500ms"`, for use with `#[serde(with)]`.
+pub(crate) mod duration {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&DisplayDuration(*duration))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        parse(&String::deserialize(deserializer)?)
+    }
+}
+
+/// An optional [`Duration`] as a literal, for use with `#[serde(with)]`.
+pub(crate) mod optional_duration {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => super::duration::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|text| parse(&text))
+            .transpose()
+    }
+}
+
+/// A size in bytes as a literal such as `"100M"`, for use with `#[serde(with)]`.
+pub(crate) mod size {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(size: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&DisplaySize(*size))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        parse_size_literal(&String::deserialize(deserializer)?)
+    }
+}
+
+/// An optional size as a literal, for use with `#[serde(with)]`.
+pub(crate) mod optional_size {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        size: &Option<u64>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match size {
+            Some(size) => super::size::serialize(size, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u64>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|text| parse_size_literal(&text))
+            .transpose()
+    }
+}
+
+/// Parses a size literal, failing like serde does for other invalid values.
+fn parse_size_literal<E: de::Error>(text: &str) -> Result<u64, E> {
+    parse_size(text)
+        .ok_or_else(|| E::invalid_value(de::Unexpected::Str(text), &"a size such as 512K or 100M"))
+}
+
+/// Parses a duration literal, failing like serde does for other invalid values.
+fn parse<E: de::Error>(text: &str) -> Result<Duration, E> {
+    parse_duration(text).ok_or_else(|| {
+        E::invalid_value(de::Unexpected::Str(text), &"a duration such as 500ms or 2s")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{Condition, ConditionKey, Config, ConfigLine, ParseOptions};
+
+    #[test]
+    fn round_trips_configs() {
+        let dir = tempfile::tempdir().unwrap();
+        let ignore = dir.path().join(".gitignore");
+        fs::write(&ignore, "*.log\n").unwrap();
+        let input = format!(
+            "include -r /srv/app depth=2 events=modify debounce=2s max_size=1G on_change \"make\"\n\
+             exclude /srv/app/*.tmp\n\
+             events create, modify\n\
+             debounce 500ms\n\
+             max_size 512K\n\
+             log_level debug\n\
+             output ndjson\n\
+             on delete run echo gone\n\
+             ignore \\.swp$\n\
+             ignorefile {}\n\
+             [profile quiet]\n\
+             log_level error",
+            ignore.display()
+        );
+        let config = Config::parse_with(&input, &ParseOptions::default()).unwrap();
+
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["includes"][0]["path"], "/srv/app");
+        assert_eq!(json["includes"][0]["options"]["recursion"], "recursive");
+        assert_eq!(json["includes"][0]["options"]["debounce"], "2s");
+        assert_eq!(json["excludes"][0], "/srv/app/*.tmp");
+        assert_eq!(json["events"], serde_json::json!(["create", "modify"]));
+        assert_eq!(json["debounce"], "500ms");
+        assert_eq!(json["max_size"], "512K");
+        assert_eq!(json["includes"][0]["options"]["max_size"], "1G");
+        assert_eq!(json["ignores"]["patterns"][0], "\\.swp$");
+        assert_eq!(json["profiles"]["quiet"]["logging"]["level"], "error");
+
+        let back: Config = serde_json::from_value(json).unwrap();
+        assert_eq!(back, config);
+        assert!(back.is_ignored(&dir.path().join("debug.log")));
+    }
+
+    #[test]
+    fn fills_in_missing_fields_and_rejects_bad_values() {
+        let config: Config = serde_json::from_str(r#"{"includes": [{"path": "/etc"}]}"#).unwrap();
+        assert_eq!(config, "include /etc".parse().unwrap());
+
+        let test_cases = vec![
+            (r#"{"events": ["touch"]}"#, "invalid event 'touch'"),
+            (
+                r#"{"debounce": "soon"}"#,
+                "expected a duration such as 500ms",
+            ),
+            (r#"{"max_size": "big"}"#, "expected a size such as 512K"),
+            (r#"{"excludes": ["/etc/["]}"#, "invalid path '/etc/['"),
+            (r#"{"ignores": {"patterns": ["("]}}"#, "invalid regex '('"),
+            (r#"{"include": []}"#, "unknown field `include`"),
+        ];
+        for (input, expected) in test_cases {
+            let err = serde_json::from_str::<Config>(input).unwrap_err();
+            assert!(err.to_string().contains(expected), "{err}");
+        }
+    }
+
+    #[test]
+    fn round_trips_config_lines() {
+        let lines = vec![
+            ConfigLine::Include(vec!["/srv/*".parse().unwrap()], Default::default()),
+            ConfigLine::Debounce(Duration::from_millis(250)),
+            ConfigLine::If(Condition {
+                key: ConditionKey::Host,
+                negated: true,
+                value: "web*".parse().unwrap(),
+            }),
+            ConfigLine::Set("DIR".to_string(), "/srv".to_string()),
+        ];
+        for line in lines {
+            let json = serde_json::to_string(&line).unwrap();
+            let back: ConfigLine = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, line, "{json}");
+        }
+        assert_eq!(
+            serde_json::to_string(&ConfigLine::Debounce(Duration::from_secs(2))).unwrap(),
+            r#"{"debounce":"2s"}"#
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! Size literals such as `100M` or `4K`, used by directives which take a file size.
+
+use std::fmt;
+
+/// The suffixes a size literal can end with, from the largest down. Each is a power of 1024.
+const UNITS: [(char, u64); 3] = [('G', 1 << 30), ('M', 1 << 20), ('K', 1 << 10)];
+
+/// Parses a whole number of bytes, optionally followed by `K`, `M` or `G` in either case, so
+/// custom [`crate::Directive`]s can take sizes too.
+pub fn parse_size(input: &str) -> Option<u64> {
+    let (number, multiplier) = match input.char_indices().last()? {
+        (index, c) if c.is_ascii_alphabetic() => {
+            let unit = c.to_ascii_uppercase();
+            let (_, size) = UNITS.iter().find(|(name, _)| *name == unit)?;
+            (&input[..index], *size)
+        }
+        _ => (input, 1),
+    };
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let number: u64 = number.parse().ok()?;
+    number.checked_mul(multiplier)
+}
+
+/// Writes a size as a literal [`parse_size`] accepts, using the largest unit which divides it
+/// evenly.
+pub(crate) struct DisplaySize(pub(crate) u64);
+
+impl fmt::Display for DisplaySize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self.0;
+        match UNITS
+            .iter()
+            .find(|(_, size)| bytes != 0 && bytes.is_multiple_of(*size))
+        {
+            Some((name, size)) => write!(f, "{}{name}", bytes / size),
+            None => write!(f, "{bytes}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        let test_cases = vec![
+            ("512", Some(512)),
+            ("4K", Some(4096)),
+            ("4k", Some(4096)),
+            ("100M", Some(100 << 20)),
+            ("2G", Some(2 << 30)),
+            ("0", Some(0)),
+            ("M", None),
+            ("1.5M", None),
+            ("10 M", None),
+            ("10MB", None),
+            ("3T", None),
+            ("-1K", None),
+            ("99999999999999999999G", None),
+        ];
+        for (input, expected) in test_cases {
+            assert_eq!(parse_size(input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn writes_sizes() {
+        let test_cases = vec![
+            (512, "512"),
+            (4096, "4K"),
+            (1536, "1536"),
+            (100 << 20, "100M"),
+            (3 << 30, "3G"),
+            (0, "0"),
+        ];
+        for (size, expected) in test_cases {
+            let written = DisplaySize(size).to_string();
+            assert_eq!(written, expected);
+            assert_eq!(parse_size(&written), Some(size));
+        }
+    }
+}
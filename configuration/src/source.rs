@@ -0,0 +1,77 @@
+//! The formats a configuration can be written in and where it can be loaded from.
+
+use std::path::Path;
+
+use crate::{loader::Loader, Config, ConfigError, ParseOptions, ParseOutcome};
+
+/// A configuration file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The line based `include`/`exclude` syntax.
+    Dsl,
+    /// The same settings expressed as TOML.
+    #[cfg(feature = "toml")]
+    Toml,
+}
+
+impl Format {
+    /// Picks the format for a file from its extension, treating anything but `.toml` as the DSL.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            #[cfg(feature = "toml")]
+            Some(ext) if ext == "toml" => Format::Toml,
+            _ => Format::Dsl,
+        }
+    }
+}
+
+/// Something a [`Config`] can be loaded from.
+///
+/// Every format produces the same [`Config`], and files pulled in with `source` may use a
+/// different format to the file sourcing them.
+pub trait ConfigSource {
+    fn load_with_warnings(&self, options: &ParseOptions) -> Result<ParseOutcome, ConfigError>;
+
+    fn load(&self, options: &ParseOptions) -> Result<Config, ConfigError> {
+        Ok(self.load_with_warnings(options)?.config)
+    }
+}
+
+/// Configuration text in the line based DSL.
+#[derive(Debug, Clone, Copy)]
+pub struct Dsl<'a>(pub &'a str);
+
+impl ConfigSource for Dsl<'_> {
+    fn load_with_warnings(&self, options: &ParseOptions) -> Result<ParseOutcome, ConfigError> {
+        let mut config = Config::default();
+        let mut loader = Loader::new(options);
+        loader.load_format(&mut config, self.0, Format::Dsl)?;
+        Ok(loader.finish(config))
+    }
+}
+
+/// Configuration text in TOML.
+#[cfg(feature = "toml")]
+#[derive(Debug, Clone, Copy)]
+pub struct Toml<'a>(pub &'a str);
+
+#[cfg(feature = "toml")]
+impl ConfigSource for Toml<'_> {
+    fn load_with_warnings(&self, options: &ParseOptions) -> Result<ParseOutcome, ConfigError> {
+        let mut config = Config::default();
+        let mut loader = Loader::new(options);
+        loader.load_format(&mut config, self.0, Format::Toml)?;
+        Ok(loader.finish(config))
+    }
+}
+
+/// A configuration file, in the format given by [`Format::from_path`], or the DSL from standard
+/// input for a path of `-`.
+impl ConfigSource for Path {
+    fn load_with_warnings(&self, options: &ParseOptions) -> Result<ParseOutcome, ConfigError> {
+        let mut config = Config::default();
+        let mut loader = Loader::new(options);
+        loader.load_path(&mut config, self)?;
+        Ok(loader.finish(config))
+    }
+}
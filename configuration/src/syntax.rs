@@ -0,0 +1,550 @@
+//! A syntax tree of configuration text which keeps where everything in it was written, for
+//! editor tooling.
+
+use std::{mem, ops::Range};
+
+use crate::{
+    parser::{
+        comment_start, directive_name, opens_group, parse_line, quoted, resolve_alias,
+        split_group_line, ConfigLine,
+    },
+    reader::is_continued,
+    ParseError, ParseOptions,
+};
+
+/// A place in the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    /// The byte offset into the source.
+    pub offset: usize,
+    /// The line, starting at 1.
+    pub line: usize,
+    /// The column, counted in characters and starting at 1.
+    pub column: usize,
+}
+
+/// The stretch of source text something was written in, from `start` up to but not including
+/// `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    /// The byte range of the span in the source.
+    pub fn range(&self) -> Range<usize> {
+        self.start.offset..self.end.offset
+    }
+}
+
+/// A value along with the span it was written in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Span,
+}
+
+/// Configuration text split into directives, keeping the span of each directive and of the
+/// paths, options and comments in it.
+///
+/// Unlike loading a configuration, building the tree evaluates nothing: every directive is
+/// parsed whatever `if` block or `@<os>` prefix it's under, and `source` directives aren't
+/// followed. Variables declared with `set` are still expanded in the paths after them. Each
+/// directive is parsed on its own, so problems which span several lines, such as an `if`
+/// without an `endif`, are left to loading.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyntaxTree {
+    /// The directives in the order they are written, with each directive of a watch group
+    /// written on one line as a node of its own.
+    pub directives: Vec<DirectiveNode>,
+    /// Lines holding nothing but a comment. Comments following a directive belong to it.
+    pub comments: Vec<Spanned<String>>,
+}
+
+/// A directive of a [`SyntaxTree`]. Text values are as written, after joining lines continued
+/// with a backslash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectiveNode {
+    /// The directive without any trailing comment.
+    pub span: Span,
+    /// The operating system of an `@<os>` prefix, spanning the `@` as well.
+    pub guard: Option<Spanned<String>>,
+    /// The directive as it is named, such as `watch` rather than `include`. A profile header
+    /// is named `profile` and a closing brace `}`.
+    pub name: Spanned<String>,
+    /// The `-r` or `-s` flag of a path directive.
+    pub flag: Option<Spanned<String>>,
+    /// The paths of an `include`, `exclude`, `source` or `ignorefile`, including any quotes.
+    /// They are in the same order as the paths of the [`ConfigLine`] it parses to.
+    pub paths: Vec<Spanned<String>>,
+    /// The options following the paths of an include.
+    pub options: Vec<OptionNode>,
+    /// The arguments of any other directive, such as `500ms` for `debounce` or the name of a
+    /// profile or watch group.
+    pub argument: Option<Spanned<String>>,
+    /// The comment ending the line, including the `#`.
+    pub comment: Option<Spanned<String>>,
+    /// The directive parsed as loading would, or the error along with the span of the text it
+    /// complains about.
+    pub parsed: Result<ConfigLine, Spanned<ParseError>>,
+}
+
+/// A `key=value` option or `on_<event> "command"` clause of an include.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionNode {
+    pub span: Span,
+    /// The key, such as `depth` or `on_modify`.
+    pub key: Spanned<String>,
+    /// The value as written, keeping the quotes of a command.
+    pub value: Spanned<String>,
+}
+
+impl SyntaxTree {
+    /// Builds the syntax tree of `input`, parsing paths with the given options.
+    pub fn parse(input: &str, options: &ParseOptions) -> SyntaxTree {
+        let source = Source::new(input);
+        let mut options = options.clone();
+        let mut tree = SyntaxTree::default();
+        for logical in logical_lines(input) {
+            let pieces =
+                split_group_line(&logical.text).unwrap_or_else(|| vec![logical.text.clone()]);
+            for piece in pieces {
+                let locate = |range: Range<usize>| {
+                    let start =
+                        logical.source_offset(to_logical(&piece, &logical.text, range.start));
+                    let end = logical.source_end(to_logical(&piece, &logical.text, range.end));
+                    source.span(start, end)
+                };
+                let text = |range: &Range<usize>| Spanned {
+                    value: piece[range.clone()].to_string(),
+                    span: locate(range.clone()),
+                };
+
+                let shape = scan(&piece);
+                let Some(name) = shape.name else {
+                    if let Some(comment) = &shape.comment {
+                        tree.comments.push(text(comment));
+                    }
+                    continue;
+                };
+
+                // Lines under another operating system's prefix are parsed all the same.
+                let os = shape.guard.as_ref().map(|guard| {
+                    mem::replace(&mut options.context.os, piece[guard.clone()].to_string())
+                });
+                let result = parse_line(&piece, logical.line, &options);
+                if let Some(os) = os {
+                    options.context.os = os;
+                }
+                let parsed = match result {
+                    Ok(Some(line)) => {
+                        if let ConfigLine::Set(name, value) = &line {
+                            options.variables.insert(name.clone(), value.clone());
+                        }
+                        Ok(line)
+                    }
+                    Ok(None) => continue,
+                    Err(error) => {
+                        let start = piece
+                            .char_indices()
+                            .nth(error.column - 1)
+                            .map_or(piece.len(), |(i, _)| i);
+                        let end = match piece[start..].starts_with(&error.text) {
+                            true => start + error.text.len(),
+                            false => start,
+                        };
+                        Err(Spanned {
+                            value: error,
+                            span: locate(start..end),
+                        })
+                    }
+                };
+
+                tree.directives.push(DirectiveNode {
+                    span: locate(shape.directive.clone()),
+                    guard: shape.guard.as_ref().map(|guard| Spanned {
+                        value: piece[guard.clone()].to_string(),
+                        span: locate(guard.start - 1..guard.end),
+                    }),
+                    name: text(&name),
+                    flag: shape.flag.as_ref().map(text),
+                    paths: shape.paths.iter().map(text).collect(),
+                    options: shape
+                        .options
+                        .iter()
+                        .map(|(key, value)| OptionNode {
+                            span: locate(key.start..value.end),
+                            key: text(key),
+                            value: text(value),
+                        })
+                        .collect(),
+                    argument: shape.argument.as_ref().map(text),
+                    comment: shape.comment.as_ref().map(text),
+                    parsed,
+                });
+            }
+        }
+        tree
+    }
+}
+
+/// The source text, for turning offsets into lines and columns.
+struct Source<'a> {
+    input: &'a str,
+    /// The offset each line starts at.
+    starts: Vec<usize>,
+}
+
+impl<'a> Source<'a> {
+    fn new(input: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { input, starts }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let index = self.starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.starts[index];
+        Position {
+            offset,
+            line: index + 1,
+            column: self.input[start..offset].chars().count() + 1,
+        }
+    }
+
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span {
+            start: self.position(start),
+            end: self.position(end.max(start)),
+        }
+    }
+}
+
+/// A line of text as the reader sees it, with lines ending in a backslash joined onto the next.
+pub(crate) struct LogicalLine {
+    pub(crate) text: String,
+    /// The line it starts on.
+    pub(crate) line: usize,
+    /// Where each physical line joined into it starts, as offsets into `text` and the source.
+    segments: Vec<(usize, usize)>,
+}
+
+impl LogicalLine {
+    /// The source offset of the text starting at `offset`.
+    fn source_offset(&self, offset: usize) -> usize {
+        let index = self.segments.partition_point(|&(start, _)| start <= offset) - 1;
+        let (start, source) = self.segments[index];
+        source + offset - start
+    }
+
+    /// The source offset of the text ending at `offset`, which stays on the line the text
+    /// ends on rather than moving to the start of the next.
+    fn source_end(&self, offset: usize) -> usize {
+        let index = self
+            .segments
+            .partition_point(|&(start, _)| start < offset)
+            .max(1)
+            - 1;
+        let (start, source) = self.segments[index];
+        source + offset - start
+    }
+}
+
+pub(crate) fn logical_lines(input: &str) -> Vec<LogicalLine> {
+    let mut lines = Vec::new();
+    let mut current: Option<LogicalLine> = None;
+    let mut offset = 0;
+    for (index, raw) in input.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += raw.len();
+        let raw = raw.strip_suffix('\n').unwrap_or(raw);
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        let mut line = current.take().unwrap_or_else(|| LogicalLine {
+            text: String::new(),
+            line: index + 1,
+            segments: Vec::new(),
+        });
+        line.segments.push((line.text.len(), start));
+        line.text.push_str(raw);
+        if is_continued(line.text.as_bytes()) {
+            line.text.pop();
+            current = Some(line);
+        } else {
+            lines.push(line);
+        }
+    }
+    lines.extend(current);
+    lines
+}
+
+/// Moves an offset into a piece of a watch group written on one line to the same place in the
+/// whole line. Pieces are padded with a space for each character before them.
+fn to_logical(piece: &str, line: &str, offset: usize) -> usize {
+    if piece.len() == line.len() {
+        return offset;
+    }
+    let chars = piece[..offset].chars().count();
+    line.char_indices()
+        .nth(chars)
+        .map_or(line.len(), |(i, _)| i)
+}
+
+/// The ranges of the parts of a line.
+#[derive(Default)]
+struct Shape {
+    directive: Range<usize>,
+    guard: Option<Range<usize>>,
+    name: Option<Range<usize>>,
+    flag: Option<Range<usize>>,
+    paths: Vec<Range<usize>>,
+    options: Vec<(Range<usize>, Range<usize>)>,
+    argument: Option<Range<usize>>,
+    comment: Option<Range<usize>>,
+}
+
+/// Splits a line into its parts. Text which doesn't fit the directive is left out, as parsing
+/// the line reports it.
+fn scan(line: &str) -> Shape {
+    let mut shape = Shape::default();
+    let mut at = skip_space(line, 0);
+    if line[at..].starts_with('#') {
+        shape.comment = Some(at..line.trim_end().len());
+        return shape;
+    }
+    if at == line.len() {
+        return shape;
+    }
+    let start = at;
+    if line[at..].starts_with('@') {
+        let end = token_end(line, at + 1);
+        shape.guard = Some(at + 1..end);
+        at = skip_space(line, end);
+    }
+
+    let mut end;
+    if let Some(rest) = line[at..].strip_prefix('[') {
+        let name = at
+            + 1
+            + rest
+                .find(|c: char| !c.is_alphanumeric())
+                .unwrap_or(rest.len());
+        shape.name = Some(at + 1..name);
+        let close = line[name..].find(']').map_or(line.len(), |i| name + i);
+        shape.argument = trimmed(line, name..close);
+        end = (close + 1).min(line.len());
+    } else if line[at..].starts_with('}') {
+        shape.name = Some(at..at + 1);
+        end = at + 1;
+    } else {
+        let name = token_end(line, at);
+        shape.name = Some(at..name);
+        end = name;
+        match resolve_alias(&line[at..name]) {
+            "include" if opens_group(&line[at..]) => {
+                let brace = line[name..].find('{').map_or(line.len(), |i| name + i);
+                shape.argument = trimmed(line, name..brace);
+                end = brace + 1;
+            }
+            "include" | "exclude" | "source" | "ignorefile" => {
+                end = scan_paths(line, name, &mut shape);
+            }
+            _ => {
+                let rest = skip_space(line, name);
+                let stop = rest + comment_start(&line[rest..]);
+                if let Some(argument) = trimmed(line, rest..stop) {
+                    end = argument.end;
+                    shape.argument = Some(argument);
+                }
+            }
+        }
+    }
+
+    let rest = skip_space(line, end);
+    let comment = rest + comment_start(&line[rest..]);
+    if comment < line.len() {
+        shape.comment = Some(comment..line.trim_end().len());
+    }
+    shape.directive = start..end;
+    shape
+}
+
+/// Scans the flag, paths and options following a path directive ending at `at`, returning
+/// where the last of them ends.
+fn scan_paths(line: &str, mut at: usize, shape: &mut Shape) -> usize {
+    let next = skip_space(line, at);
+    if next > at && line[next..].starts_with('-') {
+        at = token_end(line, next);
+        shape.flag = Some(next..at);
+    }
+
+    at = skip_space(line, at);
+    let mut end = at;
+    if at == line.len() || line[at..].starts_with('#') {
+        return end;
+    }
+    loop {
+        let path_end = match line[at..].starts_with('"') {
+            true => quoted_end(line, at),
+            false => {
+                at + line[at..]
+                    .find(|c: char| c == ',' || c == '#' || c.is_whitespace())
+                    .unwrap_or(line.len() - at)
+            }
+        };
+        shape.paths.push(at..path_end);
+        end = path_end;
+        let next = skip_space(line, path_end);
+        if !line[next..].starts_with(',') {
+            break;
+        }
+        at = skip_space(line, next + 1);
+    }
+
+    loop {
+        let key = skip_space(line, end);
+        if key == end || key == line.len() || line[key..].starts_with('#') {
+            break;
+        }
+        let (key_end, value) = match line[key..].find(['=', ' ', '\t']) {
+            Some(i) if line[key + i..].starts_with('=') => (key + i, key + i + 1),
+            _ if line[key..].starts_with("on_") => {
+                let key_end = token_end(line, key);
+                (key_end, skip_space(line, key_end))
+            }
+            _ => break,
+        };
+        let value_end = match line[value..].starts_with('"') {
+            true => quoted_end(line, value),
+            false => token_end(line, value),
+        };
+        shape.options.push((key..key_end, value..value_end));
+        end = value_end;
+    }
+    end
+}
+
+/// Where the quoted string starting at `at` ends, or the end of the line if it isn't closed.
+fn quoted_end(line: &str, at: usize) -> usize {
+    match quoted(&line[at..], true) {
+        Ok((tail, _)) => line.len() - tail.len(),
+        Err(_) => line.trim_end().len(),
+    }
+}
+
+fn skip_space(line: &str, at: usize) -> usize {
+    line.len() - line[at..].trim_start().len()
+}
+
+/// Where the word starting at `at` ends, at whitespace or a comment.
+fn token_end(line: &str, at: usize) -> usize {
+    match directive_name(&line[at..]) {
+        Ok((tail, _)) => line.len() - tail.len(),
+        Err(_) => at,
+    }
+}
+
+/// `range` without the whitespace around it, if anything is left.
+fn trimmed(line: &str, range: Range<usize>) -> Option<Range<usize>> {
+    let text = &line[range.clone()];
+    let start = range.start + text.len() - text.trim_start().len();
+    let end = range.start + text.trim_end().len();
+    (start < end).then_some(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParseErrorKind;
+
+    fn texts(tree: &SyntaxTree, input: &str) -> Vec<String> {
+        tree.directives
+            .iter()
+            .map(|node| input[node.span.range()].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_spans_of_every_part() {
+        let input = "# watched\n\
+                     @linux include -r /srv, \"/a b\" depth=2 on_modify \"make\" # app\n\
+                     debounce 500ms\n\
+                     exclude /srv/tmp, \\\n        /srv/cache\n\
+                     [profile ci]\n\
+                     watch db { include /etc/pg; events modify }\n";
+        let tree = SyntaxTree::parse(input, &ParseOptions::default());
+
+        assert_eq!(
+            texts(&tree, input),
+            [
+                "@linux include -r /srv, \"/a b\" depth=2 on_modify \"make\"",
+                "debounce 500ms",
+                "exclude /srv/tmp, \\\n        /srv/cache",
+                "[profile ci]",
+                "watch db {",
+                "include /etc/pg",
+                "events modify",
+                "}",
+            ]
+        );
+        assert_eq!(tree.comments[0].value, "# watched");
+
+        let include = &tree.directives[0];
+        assert_eq!(include.guard.as_ref().unwrap().value, "linux");
+        assert_eq!(include.name.value, "include");
+        assert_eq!(include.flag.as_ref().unwrap().value, "-r");
+        let paths: Vec<_> = include.paths.iter().map(|p| p.value.as_str()).collect();
+        assert_eq!(paths, ["/srv", "\"/a b\""]);
+        assert_eq!(include.paths[1].span.start.line, 2);
+        assert_eq!(include.paths[1].span.start.column, 25);
+        let options: Vec<_> = include
+            .options
+            .iter()
+            .map(|o| (o.key.value.as_str(), o.value.value.as_str()))
+            .collect();
+        assert_eq!(options, [("depth", "2"), ("on_modify", "\"make\"")]);
+        assert_eq!(include.comment.as_ref().unwrap().value, "# app");
+        assert!(matches!(
+            &include.parsed,
+            Ok(ConfigLine::Include(paths, _)) if paths.len() == 2
+        ));
+
+        assert_eq!(tree.directives[1].argument.as_ref().unwrap().value, "500ms");
+
+        let exclude = &tree.directives[2];
+        let second = &exclude.paths[1];
+        assert_eq!(second.value, "/srv/cache");
+        assert_eq!(&input[second.span.range()], "/srv/cache");
+        assert_eq!((second.span.start.line, second.span.start.column), (5, 9));
+        assert_eq!(exclude.span.end.line, 5);
+
+        assert_eq!(tree.directives[3].name.value, "profile");
+        assert_eq!(tree.directives[3].argument.as_ref().unwrap().value, "ci");
+        assert_eq!(tree.directives[4].argument.as_ref().unwrap().value, "db");
+        let grouped = &tree.directives[5];
+        assert_eq!(&input[grouped.paths[0].span.range()], "/etc/pg");
+        assert_eq!(grouped.span.start.line, 7);
+    }
+
+    #[test]
+    fn locates_errors() {
+        let input = "include /srv depth=x\nset D /data\nsource $D/[a\ndebounce  soon # later";
+        let tree = SyntaxTree::parse(input, &ParseOptions::default());
+        let errors: Vec<_> = tree
+            .directives
+            .iter()
+            .filter_map(|node| node.parsed.as_ref().err())
+            .map(|error| (&input[error.span.range()], error.span.start.line))
+            .collect();
+        assert_eq!(errors, [("depth=x", 1), ("$D/[a", 3), ("soon", 4)]);
+
+        let source = &tree.directives[2];
+        assert_eq!(&input[source.paths[0].span.range()], "$D/[a");
+        assert!(matches!(
+            source.parsed.as_ref().unwrap_err().value.kind,
+            ParseErrorKind::InvalidPath(_)
+        ));
+        assert_eq!(tree.directives[3].argument.as_ref().unwrap().value, "soon");
+    }
+}
@@ -0,0 +1,556 @@
+//! The TOML configuration format.
+//!
+//! ```toml
+//! exclude = ["/home/*/.cache"]
+//! source = ["conf.d/*.conf"]
+//! events = ["create", "modify", "delete"]
+//! debounce = "500ms"
+//! poll_interval = "5s"
+//! max_size = "100M"
+//! only_extensions = ["conf", "log"]
+//! follow_symlinks = false
+//! include_hidden = true
+//! rate_limit = { rate = "100/m", burst = 500 }
+//! owner = ["root"]
+//! group = ["wheel"]
+//! log_level = "info"
+//! log_file = "/var/log/overwatch.log"
+//! output = "ndjson"
+//! preset = ["system"]
+//!
+//! [[include]]
+//! paths = ["/etc/passwd", "~/projects"]
+//!
+//! [[include]]
+//! path = "/var/log"
+//! depth = 3
+//! events = ["modify"]
+//! actions = [{ on = "change", run = "logger changed" }]
+//! tags = ["logs"]
+//! rate_limit = "10/s"
+//!
+//! [[action]]
+//! on = ["modify"]
+//! run = "systemctl reload nginx"
+//!
+//! ignore = ['\.swp$', '~$']
+//! ignorefile = ["/srv/app/.gitignore"]
+//! ```
+//!
+//! `include` may also be a plain list mixing paths and tables, such as
+//! `include = ["/etc/hosts", { path = "/srv", recursive = true }]`. Paths get the same tilde,
+//! environment variable and glob handling as in the DSL, and an action's `on` is either a list
+//! of events or `"change"` for any event.
+
+use std::time::Duration;
+
+use serde::{de, Deserialize, Deserializer};
+
+use crate::{
+    duration::parse_duration,
+    ignore,
+    parser::{is_tag_char, path_spec, ConfigLine},
+    rate::parse_rate,
+    size::parse_size,
+    Action, ConfigError, EventKind, EventSet, LogLevel, OutputFormat, ParseOptions, PathError,
+    PathSpec, Preset, RateLimit, Recursion, WatchOptions,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Document {
+    #[serde(default)]
+    include: Vec<Include>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    source: Vec<String>,
+    #[serde(default, deserialize_with = "events")]
+    events: Option<EventSet>,
+    #[serde(default)]
+    action: Vec<ActionTable>,
+    #[serde(default, deserialize_with = "ignores")]
+    ignore: Vec<String>,
+    #[serde(default)]
+    ignorefile: Vec<String>,
+    #[serde(default, deserialize_with = "duration")]
+    debounce: Option<Duration>,
+    #[serde(default, deserialize_with = "poll_interval")]
+    poll_interval: Option<Duration>,
+    #[serde(default, deserialize_with = "size")]
+    max_size: Option<u64>,
+    #[serde(default, deserialize_with = "extensions")]
+    only_extensions: Option<Vec<String>>,
+    follow_symlinks: Option<bool>,
+    include_hidden: Option<bool>,
+    #[serde(default, deserialize_with = "rate_limit")]
+    rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    owner: Vec<String>,
+    #[serde(default)]
+    group: Vec<String>,
+    #[serde(default, deserialize_with = "log_level")]
+    log_level: Option<LogLevel>,
+    log_file: Option<String>,
+    #[serde(default, deserialize_with = "output")]
+    output: Option<OutputFormat>,
+    #[serde(default, deserialize_with = "presets")]
+    preset: Vec<Preset>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Include {
+    Path(String),
+    Table(IncludeTable),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IncludeTable {
+    path: Option<String>,
+    #[serde(default)]
+    paths: Vec<String>,
+    recursive: Option<bool>,
+    depth: Option<usize>,
+    #[serde(default, deserialize_with = "events")]
+    events: Option<EventSet>,
+    #[serde(default)]
+    actions: Vec<ActionTable>,
+    #[serde(default, deserialize_with = "duration")]
+    debounce: Option<Duration>,
+    #[serde(default, deserialize_with = "size")]
+    max_size: Option<u64>,
+    #[serde(default, deserialize_with = "extensions")]
+    ext: Option<Vec<String>>,
+    follow_symlinks: Option<bool>,
+    include_hidden: Option<bool>,
+    #[serde(default, deserialize_with = "rate_limit")]
+    rate_limit: Option<RateLimit>,
+    #[serde(default, deserialize_with = "tags")]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ActionTable {
+    #[serde(deserialize_with = "selector")]
+    on: EventSet,
+    run: String,
+}
+
+impl From<ActionTable> for Action {
+    fn from(table: ActionTable) -> Self {
+        Action {
+            events: table.on,
+            command: table.run,
+        }
+    }
+}
+
+/// Parses a TOML document into the same lines the DSL parser produces.
+pub(crate) fn parse(input: &str, options: &ParseOptions) -> Result<Vec<ConfigLine>, ConfigError> {
+    let document: Document = ::toml::from_str(input)?;
+    let paths = |raw: Vec<String>| -> Result<Vec<PathSpec>, ConfigError> {
+        raw.into_iter()
+            .map(|path| match path_spec(&path, options) {
+                Ok(spec) => Ok(spec),
+                Err(error) => Err(ConfigError::InvalidPath { path, error }),
+            })
+            .collect()
+    };
+
+    let mut lines = Vec::new();
+    for include in document.include {
+        let line = match include {
+            Include::Path(path) => ConfigLine::Include(
+                paths(vec![path])?,
+                WatchOptions {
+                    recursion: options.default_recursion,
+                    ..Default::default()
+                },
+            ),
+            Include::Table(table) => {
+                let recursion = match (table.recursive, table.depth) {
+                    (Some(true), _) | (None, Some(_)) => Recursion::Recursive,
+                    (Some(false), _) => Recursion::NonRecursive,
+                    (None, None) => options.default_recursion,
+                };
+                ConfigLine::Include(
+                    paths(table.path.into_iter().chain(table.paths).collect())?,
+                    WatchOptions {
+                        recursion,
+                        max_depth: table.depth,
+                        events: table.events,
+                        actions: table.actions.into_iter().map(Action::from).collect(),
+                        debounce: table.debounce,
+                        max_size: table.max_size,
+                        extensions: table.ext,
+                        follow_symlinks: table.follow_symlinks,
+                        include_hidden: table.include_hidden,
+                        rate_limit: table.rate_limit,
+                        tags: table.tags,
+                    },
+                )
+            }
+        };
+        lines.push(line);
+    }
+    if !document.exclude.is_empty() {
+        lines.push(ConfigLine::Exclude(paths(document.exclude)?));
+    }
+    lines.extend(document.preset.into_iter().map(ConfigLine::Preset));
+    if !document.source.is_empty() {
+        lines.push(ConfigLine::Source(paths(document.source)?));
+    }
+    if let Some(events) = document.events {
+        lines.push(ConfigLine::Events(events));
+    }
+    if let Some(delay) = document.debounce {
+        lines.push(ConfigLine::Debounce(delay));
+    }
+    if let Some(interval) = document.poll_interval {
+        lines.push(ConfigLine::PollInterval(interval));
+    }
+    if let Some(size) = document.max_size {
+        lines.push(ConfigLine::MaxSize(size));
+    }
+    if let Some(extensions) = document.only_extensions {
+        lines.push(ConfigLine::OnlyExtensions(extensions));
+    }
+    if let Some(follow) = document.follow_symlinks {
+        lines.push(ConfigLine::FollowSymlinks(follow));
+    }
+    if let Some(hidden) = document.include_hidden {
+        lines.push(ConfigLine::IncludeHidden(hidden));
+    }
+    if let Some(limit) = document.rate_limit {
+        lines.push(ConfigLine::RateLimit(limit));
+    }
+    if !document.owner.is_empty() {
+        lines.push(ConfigLine::Owner(document.owner));
+    }
+    if !document.group.is_empty() {
+        lines.push(ConfigLine::Group(document.group));
+    }
+    if let Some(level) = document.log_level {
+        lines.push(ConfigLine::LogLevel(level));
+    }
+    if let Some(format) = document.output {
+        lines.push(ConfigLine::Output(format));
+    }
+    if let Some(path) = document.log_file {
+        match paths(vec![path.clone()])?.pop() {
+            Some(PathSpec::Path(file)) => lines.push(ConfigLine::LogFile(file)),
+            _ => {
+                return Err(ConfigError::InvalidPath {
+                    path,
+                    error: PathError::Glob,
+                })
+            }
+        }
+    }
+    lines.extend(
+        document
+            .action
+            .into_iter()
+            .map(|action| ConfigLine::Action(action.into())),
+    );
+    lines.extend(document.ignore.into_iter().map(ConfigLine::Ignore));
+    if !document.ignorefile.is_empty() {
+        lines.push(ConfigLine::IgnoreFile(paths(document.ignorefile)?));
+    }
+    Ok(lines)
+}
+
+fn events<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<EventSet>, D::Error> {
+    let names = Vec::<String>::deserialize(deserializer)?;
+    event_set(names).map(Some)
+}
+
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    match parse_duration(&text) {
+        Some(delay) => Ok(Some(delay)),
+        None => Err(de::Error::custom(format_args!(
+            "invalid duration '{text}', expected a number followed by ms, s, m, h or d"
+        ))),
+    }
+}
+
+fn poll_interval<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    match duration(deserializer)? {
+        Some(interval) if interval.is_zero() => Err(de::Error::custom(
+            "invalid poll interval, expected a duration longer than zero",
+        )),
+        interval => Ok(interval),
+    }
+}
+
+fn size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    match parse_size(&text) {
+        Some(size) => Ok(Some(size)),
+        None => Err(de::Error::custom(format_args!(
+            "invalid size '{text}', expected a number optionally followed by K, M or G"
+        ))),
+    }
+}
+
+fn extensions<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<String>>, D::Error> {
+    let extensions = Vec::<String>::deserialize(deserializer)?;
+    match extensions.iter().find(|ext| {
+        ext.is_empty()
+            || !ext
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    }) {
+        Some(ext) => Err(de::Error::custom(format_args!(
+            "invalid extension '{ext}', expected letters, digits, '_' or '-' without a leading dot"
+        ))),
+        None => Ok(Some(extensions)),
+    }
+}
+
+/// A rate limit is either a rate such as `"10/s"` or a table with a `rate` and a `burst`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RateLimitSpec {
+    Rate(String),
+    Table { rate: String, burst: u32 },
+}
+
+fn rate_limit<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<RateLimit>, D::Error> {
+    let (rate, burst) = match RateLimitSpec::deserialize(deserializer)? {
+        RateLimitSpec::Rate(rate) => (rate, None),
+        RateLimitSpec::Table { rate, burst } => (rate, Some(burst)),
+    };
+    let mut limit = parse_rate(&rate).ok_or_else(|| {
+        de::Error::custom(format_args!(
+            "invalid rate '{rate}', expected a number of events per unit such as 10/s"
+        ))
+    })?;
+    if let Some(burst) = burst {
+        if burst == 0 {
+            return Err(de::Error::custom(
+                "invalid burst, expected a number above zero",
+            ));
+        }
+        limit.burst = burst;
+    }
+    Ok(Some(limit))
+}
+
+fn tags<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let tags = Vec::<String>::deserialize(deserializer)?;
+    match tags
+        .iter()
+        .find(|tag| tag.is_empty() || !tag.chars().all(is_tag_char))
+    {
+        Some(tag) => Err(de::Error::custom(format_args!(
+            "invalid tag '{tag}', expected letters, digits, '_', '-', '.' or ':'"
+        ))),
+        None => Ok(tags),
+    }
+}
+
+fn log_level<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<LogLevel>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse().map(Some).map_err(|_| {
+        de::Error::custom(format_args!(
+            "unknown log level '{name}', expected one of: {}",
+            LogLevel::ALL.map(LogLevel::as_str).join(", ")
+        ))
+    })
+}
+
+fn output<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<OutputFormat>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse().map(Some).map_err(|_| {
+        de::Error::custom(format_args!(
+            "unknown output format '{name}', expected one of: {}",
+            OutputFormat::ALL.map(OutputFormat::as_str).join(", ")
+        ))
+    })
+}
+
+fn presets<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Preset>, D::Error> {
+    let names = Vec::<String>::deserialize(deserializer)?;
+    names
+        .iter()
+        .map(|name| {
+            name.parse().map_err(|_| {
+                de::Error::custom(format_args!(
+                    "unknown preset '{name}', expected one of: {}",
+                    Preset::ALL.map(Preset::as_str).join(", ")
+                ))
+            })
+        })
+        .collect()
+}
+
+fn ignores<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let patterns = Vec::<String>::deserialize(deserializer)?;
+    for pattern in &patterns {
+        ignore::check(pattern).map_err(|message| {
+            de::Error::custom(format_args!("invalid regex '{pattern}': {message}"))
+        })?;
+    }
+    Ok(patterns)
+}
+
+fn selector<'de, D: Deserializer<'de>>(deserializer: D) -> Result<EventSet, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Selector {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    match Selector::deserialize(deserializer)? {
+        Selector::One(name) if name == "change" || name == "any" => Ok(EventSet::all()),
+        Selector::One(name) => event_set(vec![name]),
+        Selector::Many(names) => event_set(names),
+    }
+}
+
+fn event_set<E: de::Error>(names: Vec<String>) -> Result<EventSet, E> {
+    names
+        .iter()
+        .map(|name| {
+            name.parse::<EventKind>().map_err(|_| {
+                de::Error::custom(format_args!(
+                    "unknown event '{name}', expected one of: {}",
+                    EventKind::ALL.map(EventKind::as_str).join(", ")
+                ))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, ConfigSource, Toml, WatchEntry};
+
+    fn spec(path: &str) -> PathSpec {
+        path.parse().unwrap()
+    }
+
+    #[test]
+    fn matches_the_dsl() {
+        let toml = r#"
+            include = [
+                "/etc/hosts",
+                { path = "/var/log", depth = 2, follow_symlinks = true, tags = ["logs"], rate_limit = "10/s", actions = [{ on = "change", run = "logger changed" }] },
+            ]
+            exclude = ["/var/log/*.gz"]
+            events = ["create", "modify"]
+            debounce = "2s"
+            max_size = "10M"
+            only_extensions = ["log", "gz"]
+            owner = ["root", "1000"]
+            include_hidden = false
+            rate_limit = { rate = "5/10s", burst = 20 }
+            output = "json"
+            preset = ["system"]
+
+            ignore = ['\.swp$']
+
+            [[action]]
+            on = ["delete"]
+            run = "logger deleted"
+        "#;
+        let dsl = "include /etc/hosts\n\
+                   include /var/log depth=2 follow_symlinks=on rate_limit=10/s tags=logs on_change \"logger changed\"\n\
+                   exclude /var/log/*.gz\n\
+                   events create,modify\n\
+                   debounce 2s\n\
+                   max_size 10M\n\
+                   only_extensions log,gz\n\
+                   owner root,1000\n\
+                   include_hidden off\n\
+                   rate_limit 5/10s burst=20\n\
+                   output json\n\
+                   preset system\n\
+                   on delete run logger deleted\n\
+                   ignore \\.swp$";
+
+        let from_toml = Config::from_toml(toml).unwrap();
+        assert_eq!(dsl.parse::<Config>().unwrap(), from_toml);
+        assert_eq!(
+            from_toml.includes()[1],
+            WatchEntry {
+                path: spec("/var/log"),
+                options: WatchOptions {
+                    recursion: Recursion::Recursive,
+                    max_depth: Some(2),
+                    events: None,
+                    actions: vec![Action {
+                        events: EventSet::all(),
+                        command: "logger changed".to_string(),
+                    }],
+                    debounce: None,
+                    max_size: None,
+                    extensions: None,
+                    follow_symlinks: Some(true),
+                    include_hidden: None,
+                    rate_limit: Some(RateLimit::new(10, Duration::from_secs(1))),
+                    tags: vec!["logs".to_string()],
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        let options = ParseOptions::default();
+        assert!(matches!(
+            Toml("events = [\"moved\"]").load(&options),
+            Err(ConfigError::Toml(_))
+        ));
+        assert!(matches!(
+            Toml("ignore = ['(']").load(&options),
+            Err(ConfigError::Toml(_))
+        ));
+        assert!(matches!(
+            Toml("debounce = \"soon\"").load(&options),
+            Err(ConfigError::Toml(_))
+        ));
+        assert!(matches!(
+            Toml("poll_interval = \"0ms\"").load(&options),
+            Err(ConfigError::Toml(_))
+        ));
+        assert!(matches!(
+            Toml("log_file = \"/var/log/*.log\"").load(&options),
+            Err(ConfigError::InvalidPath {
+                error: PathError::Glob,
+                ..
+            })
+        ));
+        assert!(matches!(
+            Toml("preset = [\"desktop\"]").load(&options),
+            Err(ConfigError::Toml(_))
+        ));
+        assert!(matches!(
+            Toml("max_size = \"10MB\"").load(&options),
+            Err(ConfigError::Toml(_))
+        ));
+        assert!(matches!(
+            Toml("only_extensions = [\".rs\"]").load(&options),
+            Err(ConfigError::Toml(_))
+        ));
+        assert!(matches!(
+            Toml("rate_limit = { rate = \"10/s\", burst = 0 }").load(&options),
+            Err(ConfigError::Toml(_))
+        ));
+        assert!(matches!(
+            Toml("inclde = [\"/etc\"]").load(&options),
+            Err(ConfigError::Toml(_))
+        ));
+        assert!(matches!(
+            Toml("exclude = [\"/etc/[\"]").load(&options),
+            Err(ConfigError::InvalidPath { .. })
+        ));
+    }
+}
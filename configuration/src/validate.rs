@@ -0,0 +1,218 @@
+//! Checks a parsed configuration against the filesystem.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{Config, PathSpec};
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The configuration works, but probably not as intended.
+    Warning,
+    /// Part of the configuration can't take effect.
+    Error,
+}
+
+/// A problem found by [`Config::validate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The include or exclude the problem was found with.
+    pub path: PathSpec,
+    pub kind: DiagnosticKind,
+}
+
+/// What [`Config::validate`] found wrong with a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// An included path doesn't exist.
+    Missing,
+    /// An included path exists but can't be read.
+    Unreadable(io::ErrorKind),
+    /// An included pattern doesn't match anything.
+    NoMatches,
+    /// An exclude doesn't fall under any include, so it has no effect.
+    NotIncluded,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        let path = &self.path;
+        match self.kind {
+            DiagnosticKind::Missing => write!(f, "{severity}: {path} does not exist"),
+            DiagnosticKind::Unreadable(kind) => {
+                write!(
+                    f,
+                    "{severity}: {path} can't be read: {}",
+                    io::Error::from(kind)
+                )
+            }
+            DiagnosticKind::NoMatches => write!(f, "{severity}: {path} doesn't match any paths"),
+            DiagnosticKind::NotIncluded => {
+                write!(f, "{severity}: exclude {path} isn't under any include")
+            }
+        }
+    }
+}
+
+impl Config {
+    /// Checks that every include exists and is readable and that every exclude falls under an
+    /// include, returning the problems found in the order the paths appear.
+    ///
+    /// Globs don't need checking here since they are compiled while parsing.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut report = |severity, path: &PathSpec, kind| {
+            diagnostics.push(Diagnostic {
+                severity,
+                path: path.clone(),
+                kind,
+            })
+        };
+
+        for entry in &self.includes {
+            match &entry.path {
+                PathSpec::Path(path) => {
+                    if let Err(kind) = check_readable(path) {
+                        report(Severity::Error, &entry.path, kind);
+                    }
+                }
+                PathSpec::Pattern(pattern) => {
+                    let matches = pattern.expand();
+                    if matches.is_empty() {
+                        report(Severity::Warning, &entry.path, DiagnosticKind::NoMatches);
+                    }
+                    for path in matches {
+                        if let Err(DiagnosticKind::Unreadable(kind)) = check_readable(&path) {
+                            report(
+                                Severity::Error,
+                                &PathSpec::Path(path),
+                                DiagnosticKind::Unreadable(kind),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        for exclude in &self.excludes {
+            if !self
+                .includes
+                .iter()
+                .any(|entry| contains(&entry.path, exclude))
+            {
+                report(Severity::Warning, exclude, DiagnosticKind::NotIncluded);
+            }
+        }
+        diagnostics
+    }
+}
+
+fn check_readable(path: &Path) -> Result<(), DiagnosticKind> {
+    let result = match fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::read_dir(path).map(drop),
+        Ok(_) => fs::File::open(path).map(drop),
+        Err(err) => Err(err),
+    };
+    result.map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => DiagnosticKind::Missing,
+        kind => DiagnosticKind::Unreadable(kind),
+    })
+}
+
+/// Whether anything matched by `exclude` could fall under `include`.
+pub(crate) fn contains(include: &PathSpec, exclude: &PathSpec) -> bool {
+    match exclude {
+        PathSpec::Path(path) => include.covers(path),
+        PathSpec::Pattern(pattern) => {
+            let prefix = literal_prefix(pattern.as_str());
+            include.covers(&prefix)
+                || pattern.as_str().starts_with(&include.to_string())
+                || pattern.expand().iter().any(|path| include.covers(path))
+        }
+    }
+}
+
+/// The leading components of a pattern which don't contain any glob syntax.
+fn literal_prefix(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|component| {
+            !component
+                .as_os_str()
+                .to_string_lossy()
+                .contains(['*', '?', '['])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn reports_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().display();
+        fs::create_dir(dir.path().join("srv")).unwrap();
+        fs::write(dir.path().join("file"), "").unwrap();
+
+        let config: Config = format!(
+            "include {root}/srv, {root}/file, {root}/missing, {root}/*.none, {root}/s*\n\
+             exclude {root}/srv/tmp, {root}/srv/*.log, {root}/s*/.cache, /var/log/*.gz, /opt"
+        )
+        .parse()
+        .unwrap();
+        let found = config
+            .validate()
+            .into_iter()
+            .map(|d| (d.severity, d.path.to_string(), d.kind))
+            .collect::<Vec<_>>();
+
+        let expected = vec![
+            (
+                Severity::Error,
+                format!("{root}/missing"),
+                DiagnosticKind::Missing,
+            ),
+            (
+                Severity::Warning,
+                format!("{root}/*.none"),
+                DiagnosticKind::NoMatches,
+            ),
+            (
+                Severity::Warning,
+                "/var/log/*.gz".to_string(),
+                DiagnosticKind::NotIncluded,
+            ),
+            (
+                Severity::Warning,
+                "/opt".to_string(),
+                DiagnosticKind::NotIncluded,
+            ),
+        ];
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn formats_diagnostics() {
+        let diagnostic = Diagnostic {
+            severity: Severity::Warning,
+            path: "/opt".parse().unwrap(),
+            kind: DiagnosticKind::NotIncluded,
+        };
+        assert_eq!(
+            diagnostic.to_string(),
+            "warning: exclude /opt isn't under any include"
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Problems which don't stop a configuration from loading.
+
+use std::{fmt, path::PathBuf};
+
+use crate::{Config, ParseError, PathSpec};
+
+/// A configuration along with the warnings raised while loading it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseOutcome {
+    pub config: Config,
+    pub warnings: Vec<Warning>,
+    /// Where each include and exclude was written, in the order they were loaded.
+    pub origins: Vec<Origin>,
+}
+
+impl ParseOutcome {
+    /// Where `path` was first included or excluded. A path which was never written itself,
+    /// such as one a pattern matched, is found at the first pattern matching it.
+    pub fn origin(&self, path: &PathSpec) -> Option<&Origin> {
+        self.origins
+            .iter()
+            .find(|origin| origin.path == *path)
+            .or_else(|| {
+                match path {
+                PathSpec::Path(path) => self.origins.iter().find(|origin| {
+                    matches!(&origin.path, PathSpec::Pattern(pattern) if pattern.matches(path))
+                }),
+                PathSpec::Pattern(_) => None,
+            }
+            })
+    }
+}
+
+/// The file and line an include or exclude was written on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+    pub path: PathSpec,
+    /// The file the path was written in, if the configuration was loaded from one.
+    pub file: Option<PathBuf>,
+    /// The line the path was written on, `None` for formats which don't track lines.
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}: line {line}", file.display()),
+            (Some(file), None) => write!(f, "{}", file.display()),
+            (None, Some(line)) => write!(f, "line {line}"),
+            (None, None) => write!(f, "{}", self.path),
+        }
+    }
+}
+
+/// A problem found while loading which didn't stop the configuration from loading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    /// The file the problem is in, if the configuration was loaded from one.
+    pub file: Option<PathBuf>,
+    /// The line the problem is on. TOML files don't track lines, so this is `None` for them.
+    pub line: Option<usize>,
+    pub kind: WarningKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WarningKind {
+    /// A line which couldn't be parsed was skipped, because [`crate::ParseOptions::strict`]
+    /// was off.
+    SkippedLine(ParseError),
+    /// A path is included more than once. The later include's options win.
+    DuplicateInclude(PathSpec),
+    /// An exclude doesn't fall under any include, so it has no effect.
+    ExcludeOutsideIncludes(PathSpec),
+    /// A path list has an empty element, as in `include /a,,/b`, which was dropped.
+    EmptyPath,
+    /// A directive was written with a name which is only kept for old configurations.
+    Deprecated {
+        name: String,
+        replacement: &'static str,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file.display())?;
+        }
+        match (&self.kind, self.line) {
+            // The parse error already says which line it is on.
+            (WarningKind::SkippedLine(_), _) | (_, None) => write!(f, "{}", self.kind),
+            (kind, Some(line)) => write!(f, "line {line}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WarningKind::SkippedLine(error) => write!(f, "skipped {error}"),
+            WarningKind::DuplicateInclude(path) => write!(f, "{path} is already included"),
+            WarningKind::ExcludeOutsideIncludes(path) => {
+                write!(f, "exclude {path} isn't under any include")
+            }
+            WarningKind::EmptyPath => f.write_str("empty path in list"),
+            WarningKind::Deprecated { name, replacement } => {
+                write!(f, "'{name}' is deprecated, use '{replacement}' instead")
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Included paths and the settings which control how they are watched.
+
+use std::time::Duration;
+
+use crate::{Action, EventSet, PathSpec, RateLimit};
+
+/// Whether the watcher descends into the subdirectories of an included directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Recursion {
+    /// Only the directory itself and the files directly inside it are watched.
+    #[default]
+    NonRecursive,
+    /// Every directory below the included one is watched as well.
+    Recursive,
+}
+
+/// Settings given on an include directive, shared by every path it lists.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct WatchOptions {
+    pub recursion: Recursion,
+    /// How many levels of subdirectories are watched below a recursive include. `None` means
+    /// there is no limit.
+    pub max_depth: Option<usize>,
+    /// The events reported for this include, overriding [`crate::Config::events`].
+    pub events: Option<EventSet>,
+    /// Commands run for events under this include, in addition to the global actions.
+    pub actions: Vec<Action>,
+    /// How long events for a file are held back, overriding [`crate::Config::debounce`].
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::optional_duration"))]
+    pub debounce: Option<Duration>,
+    /// The size in bytes above which events for a file are dropped, overriding
+    /// [`crate::Config::max_size`].
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::optional_size"))]
+    pub max_size: Option<u64>,
+    /// The file extensions events are reported for, overriding [`crate::Config::extensions`].
+    pub extensions: Option<Vec<String>>,
+    /// Whether symlinked directories are traversed, overriding
+    /// [`crate::Config::follow_symlinks`].
+    pub follow_symlinks: Option<bool>,
+    /// Whether dotfiles and dot-directories below the include are watched, overriding
+    /// [`crate::Config::include_hidden`].
+    pub include_hidden: Option<bool>,
+    /// How many events this include may pass on, overriding [`crate::Config::rate_limit`].
+    pub rate_limit: Option<RateLimit>,
+    /// Labels attached to the events under this include, so sinks can route them.
+    pub tags: Vec<String>,
+}
+
+/// A single included path along with how it should be watched.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(deny_unknown_fields)
+)]
+pub struct WatchEntry {
+    pub path: PathSpec,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub options: WatchOptions,
+}
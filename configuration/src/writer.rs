@@ -0,0 +1,399 @@
+//! Writes a [`Config`] back out as configuration text.
+
+use std::{borrow::Cow, fmt, path::Path};
+
+use crate::{
+    duration::DisplayDuration, size::DisplaySize, Action, Config, EventSet, LoggingConfig,
+    PathSpec, RateLimit, Recursion, WatchEntry, WatchGroup,
+};
+
+/// How far the directives inside a watch group are indented.
+const INDENT: &str = "  ";
+
+/// Writes the configuration in canonical form: includes, then excludes, then the `events`
+/// directive if it restricts anything, the `debounce`, `poll_interval`, `max_size`,
+/// `only_extensions`, `follow_symlinks`, `include_hidden`, `rate_limit`, `owner`, `group`,
+/// logging and `output` directives which are set, then global actions, ignore patterns and
+/// files, custom directives as they were written and finally watch groups, with one directive
+/// per line.
+/// Profiles follow in sorted order, each under its own header.
+///
+/// Parsing the output with the default [`crate::ParseOptions`] gives back an equal `Config`,
+/// with two exceptions: an action bound to an include which matches some but not all events is
+/// written as one `on_<event>` clause per event, and commands of global actions and ignore
+/// patterns can't contain ` #` since it starts a comment. Custom directives are only parsed
+/// back if the same ones are registered.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_directives(f, self)?;
+        let mut separate = has_directives(self);
+        for (name, profile) in &self.profiles {
+            if separate {
+                f.write_str("\n")?;
+            }
+            writeln!(f, "[profile {name}]")?;
+            write_directives(f, profile)?;
+            separate = true;
+        }
+        Ok(())
+    }
+}
+
+fn has_directives(config: &Config) -> bool {
+    !config.includes.is_empty()
+        || !config.excludes.is_empty()
+        || config.events != EventSet::all()
+        || config.debounce.is_some()
+        || config.poll_interval.is_some()
+        || config.max_size.is_some()
+        || config.extensions.is_some()
+        || config.follow_symlinks.is_some()
+        || config.include_hidden.is_some()
+        || config.rate_limit.is_some()
+        || !config.owners.is_empty()
+        || config.logging != LoggingConfig::default()
+        || config.output.is_some()
+        || !config.actions.is_empty()
+        || !config.ignores.is_empty()
+        || !config.custom.is_empty()
+        || !config.groups.is_empty()
+}
+
+fn write_directives(f: &mut fmt::Formatter<'_>, config: &Config) -> fmt::Result {
+    for entry in &config.includes {
+        writeln!(f, "{entry}")?;
+    }
+    for path in &config.excludes {
+        f.write_str("exclude ")?;
+        write_path(f, path)?;
+        f.write_str("\n")?;
+    }
+    if config.events != EventSet::all() {
+        writeln!(f, "events {}", config.events)?;
+    }
+    if let Some(delay) = config.debounce {
+        writeln!(f, "debounce {}", DisplayDuration(delay))?;
+    }
+    if let Some(interval) = config.poll_interval {
+        writeln!(f, "poll_interval {}", DisplayDuration(interval))?;
+    }
+    if let Some(size) = config.max_size {
+        writeln!(f, "max_size {}", DisplaySize(size))?;
+    }
+    if let Some(extensions) = &config.extensions {
+        writeln!(f, "only_extensions {}", extensions.join(","))?;
+    }
+    if let Some(follow) = config.follow_symlinks {
+        writeln!(f, "follow_symlinks {}", switch(follow))?;
+    }
+    if let Some(hidden) = config.include_hidden {
+        writeln!(f, "include_hidden {}", switch(hidden))?;
+    }
+    if let Some(limit) = config.rate_limit {
+        writeln!(f, "rate_limit {}", DisplayRateLimit(limit))?;
+    }
+    if !config.owners.users.is_empty() {
+        writeln!(f, "owner {}", config.owners.users.join(","))?;
+    }
+    if !config.owners.groups.is_empty() {
+        writeln!(f, "group {}", config.owners.groups.join(","))?;
+    }
+    if let Some(level) = config.logging.level {
+        writeln!(f, "log_level {level}")?;
+    }
+    if let Some(path) = &config.logging.file {
+        f.write_str("log_file ")?;
+        write_path(f, &PathSpec::Path(path.clone()))?;
+        f.write_str("\n")?;
+    }
+    if let Some(format) = config.output {
+        writeln!(f, "output {format}")?;
+    }
+    for action in &config.actions {
+        writeln!(f, "on {} run {}", selector(action.events), action.command)?;
+    }
+    for pattern in config.ignores.patterns() {
+        writeln!(f, "ignore {pattern}")?;
+    }
+    for file in config.ignores.files() {
+        f.write_str("ignorefile ")?;
+        write_path(f, &PathSpec::Path(file.path().to_path_buf()))?;
+        f.write_str("\n")?;
+    }
+    for directive in &config.custom {
+        f.write_str(directive.name())?;
+        if !directive.args().is_empty() {
+            write!(f, " {}", directive.args())?;
+        }
+        f.write_str("\n")?;
+    }
+    for group in &config.groups {
+        write_group(f, group)?;
+    }
+    Ok(())
+}
+
+/// Writes a watch group as a block, with its includes as they were written rather than with
+/// the group's settings filled in.
+fn write_group(f: &mut fmt::Formatter<'_>, group: &WatchGroup) -> fmt::Result {
+    let options = &group.options;
+    writeln!(f, "watch {} {{", group.name)?;
+    for entry in &group.includes {
+        writeln!(f, "{INDENT}{entry}")?;
+    }
+    for path in &group.excludes {
+        write!(f, "{INDENT}exclude ")?;
+        write_path(f, path)?;
+        f.write_str("\n")?;
+    }
+    if let Some(events) = options.events {
+        writeln!(f, "{INDENT}events {events}")?;
+    }
+    if let Some(delay) = options.debounce {
+        writeln!(f, "{INDENT}debounce {}", DisplayDuration(delay))?;
+    }
+    if let Some(size) = options.max_size {
+        writeln!(f, "{INDENT}max_size {}", DisplaySize(size))?;
+    }
+    if let Some(extensions) = &options.extensions {
+        writeln!(f, "{INDENT}only_extensions {}", extensions.join(","))?;
+    }
+    if let Some(follow) = options.follow_symlinks {
+        writeln!(f, "{INDENT}follow_symlinks {}", switch(follow))?;
+    }
+    if let Some(hidden) = options.include_hidden {
+        writeln!(f, "{INDENT}include_hidden {}", switch(hidden))?;
+    }
+    if let Some(limit) = options.rate_limit {
+        writeln!(f, "{INDENT}rate_limit {}", DisplayRateLimit(limit))?;
+    }
+    for action in &options.actions {
+        writeln!(
+            f,
+            "{INDENT}on {} run {}",
+            selector(action.events),
+            action.command
+        )?;
+    }
+    f.write_str("}\n")
+}
+
+/// Writes the include line of the entry, without a line break, as [`Config`]'s `Display` does.
+impl fmt::Display for WatchEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_include(f, self)
+    }
+}
+
+fn write_include(f: &mut fmt::Formatter<'_>, entry: &WatchEntry) -> fmt::Result {
+    let options = &entry.options;
+    f.write_str("include ")?;
+    if options.recursion == Recursion::Recursive && options.max_depth.is_none() {
+        f.write_str("-r ")?;
+    }
+    write_path(f, &entry.path)?;
+    if let Some(depth) = options.max_depth {
+        write!(f, " depth={depth}")?;
+    }
+    if let Some(events) = options.events {
+        write!(f, " events={events}")?;
+    }
+    if let Some(delay) = options.debounce {
+        write!(f, " debounce={}", DisplayDuration(delay))?;
+    }
+    if let Some(size) = options.max_size {
+        write!(f, " max_size={}", DisplaySize(size))?;
+    }
+    if let Some(extensions) = &options.extensions {
+        write!(f, " ext={}", extensions.join(","))?;
+    }
+    if let Some(follow) = options.follow_symlinks {
+        write!(f, " follow_symlinks={}", switch(follow))?;
+    }
+    if let Some(hidden) = options.include_hidden {
+        write!(f, " include_hidden={}", switch(hidden))?;
+    }
+    if let Some(limit) = options.rate_limit {
+        write!(f, " rate_limit={}", DisplayRateLimit(limit))?;
+    }
+    if !options.tags.is_empty() {
+        write!(f, " tags={}", options.tags.join(","))?;
+    }
+    for Action { events, command } in &options.actions {
+        if *events == EventSet::all() {
+            f.write_str(" on_change ")?;
+            write_quoted(f, command)?;
+        } else {
+            for kind in events.iter() {
+                write!(f, " on_{kind} ")?;
+                write_quoted(f, command)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a rate limit, followed by its `burst=N` option unless the burst is the default.
+struct DisplayRateLimit(RateLimit);
+
+impl fmt::Display for DisplayRateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let DisplayRateLimit(limit) = self;
+        write!(f, "{limit}")?;
+        if limit.burst != limit.events {
+            write!(f, " burst={}", limit.burst)?;
+        }
+        Ok(())
+    }
+}
+
+fn switch(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// Writes `path` so that it parses back to the same spec, quoting literal paths which would
+/// otherwise be split, treated as a glob or have their tilde expanded, or which hold control
+/// characters or bytes which aren't UTF-8.
+pub(crate) fn write_path(f: &mut fmt::Formatter<'_>, path: &PathSpec) -> fmt::Result {
+    match path {
+        PathSpec::Pattern(pattern) => write!(f, "{pattern}"),
+        PathSpec::Path(path) => {
+            let bytes = path_bytes(path);
+            let plain = match std::str::from_utf8(&bytes) {
+                Ok(path) => {
+                    !path.is_empty()
+                        && !path.starts_with('~')
+                        && !path.contains(|c: char| {
+                            c.is_whitespace() || c.is_control() || ",#\"\\*?[".contains(c)
+                        })
+                }
+                Err(_) => false,
+            };
+            if plain {
+                f.write_str(&path.to_string_lossy())
+            } else {
+                write_quoted_bytes(f, &bytes)
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+        Cow::Owned(path) => Cow::Owned(path.into_bytes()),
+    }
+}
+
+fn write_quoted(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    write_quoted_bytes(f, text.as_bytes())
+}
+
+/// Writes `text` as a double quoted string, escaping control characters and bytes which aren't
+/// UTF-8.
+fn write_quoted_bytes(f: &mut fmt::Formatter<'_>, text: &[u8]) -> fmt::Result {
+    f.write_str("\"")?;
+    for chunk in text.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '"' | '\\' => write!(f, "\\{c}")?,
+                '\n' => f.write_str("\\n")?,
+                '\t' => f.write_str("\\t")?,
+                c if c.is_ascii_control() => write!(f, "\\x{:02x}", c as u8)?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        for byte in chunk.invalid() {
+            write!(f, "\\x{byte:02x}")?;
+        }
+    }
+    f.write_str("\"")
+}
+
+fn selector(events: EventSet) -> String {
+    if events == EventSet::all() {
+        "any".to_string()
+    } else {
+        events.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Config;
+
+    #[test]
+    fn writes_canonical_config() {
+        let test_cases = vec![
+            ("", ""),
+            (
+                "exclude /a/*.gz, /b\ninclude /etc, /srv # comment",
+                "include /etc\ninclude /srv\nexclude /a/*.gz\nexclude /b\n",
+            ),
+            (
+                "events modify, create\ninclude -s /etc\ninclude -r /var/log\ninclude /data depth=2",
+                "include /etc\ninclude -r /var/log\ninclude /data depth=2\nevents create,modify\n",
+            ),
+            (
+                r#"include "/srv/My Files", "/srv/a,b", "/srv/[x]", "~/x", "/q\"\\", "/\t\n\x01""#,
+                "include \"/srv/My Files\"\ninclude \"/srv/a,b\"\ninclude \"/srv/[x]\"\n\
+                 include \"~/x\"\ninclude \"/q\\\"\\\\\"\ninclude \"/\\t\\n\\x01\"\n",
+            ),
+            (
+                "on delete, rename run logger gone\non any run true\n\
+                 include /etc events=delete on_change \"say \\\"hi\\\"\" on_modify \"x\"",
+                "include /etc events=delete on_change \"say \\\"hi\\\"\" on_modify \"x\"\n\
+                 on delete,rename run logger gone\non any run true\n",
+            ),
+            (
+                "ignore ~$\ninclude /home\nignore \\.sw[po]$",
+                "include /home\nignore ~$\nignore \\.sw[po]$\n",
+            ),
+            (
+                "log_file \"/var/log/a b.log\"\npoll_interval 90s\ndebounce 1000ms\nmax_size 1024K\n\
+                 include /etc max_size=100 debounce=250ms events=modify\noutput json\nlog_level warn\n\
+                 include -r /src follow_symlinks=off ext=rs,toml\nonly_extensions md , txt\ngroup wheel\n\
+                 owner root, 0\nfollow_symlinks on\ninclude /home tags=home include_hidden=on\ninclude_hidden off\n\
+                 rate_limit 100/m burst=500\ninclude /var/log burst=5 rate_limit=10/s",
+                "include /etc events=modify debounce=250ms max_size=100\ninclude -r /src ext=rs,toml follow_symlinks=off\ninclude /home include_hidden=on tags=home\ninclude /var/log rate_limit=10/s burst=5\ndebounce 1s\npoll_interval 90s\n\
+                 max_size 1M\nonly_extensions md,txt\nfollow_symlinks on\ninclude_hidden off\nrate_limit 100/m burst=500\nowner root,0\ngroup wheel\nlog_level warn\nlog_file \"/var/log/a b.log\"\noutput json\n",
+            ),
+            (
+                "[profile b]\ninclude /b\n[profile a]\nevents create\n[profile b]\nexclude /c",
+                "[profile a]\nevents create\n\n[profile b]\ninclude /b\nexclude /c\n",
+            ),
+            (
+                "include /etc\n[profile a]\n",
+                "include /etc\n\n[profile a]\n",
+            ),
+        ];
+
+        for (input, expected) in test_cases {
+            let config: Config = input.parse().unwrap();
+            let written = config.to_string();
+            assert_eq!(written, expected);
+            assert_eq!(written.parse::<Config>().unwrap(), config);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escapes_paths_which_are_not_utf8() {
+        let config: Config = r#"include "/srv/caf\xe9""#.parse().unwrap();
+        let written = config.to_string();
+        assert_eq!(written, "include \"/srv/caf\\xe9\"\n");
+        assert_eq!(written.parse::<Config>().unwrap(), config);
+    }
+}
@@ -0,0 +1,168 @@
+//! Running the actions a configuration binds to events.
+
+use std::{
+    error::Error,
+    fmt, io,
+    process::{Command, ExitStatus, Stdio},
+};
+
+use configuration::Action;
+use watcher::Event;
+
+use crate::template::{self, TemplateError};
+
+/// Something which carries out actions, such as running their command.
+///
+/// Runners are shared between the threads events are dispatched from, so they shouldn't hold
+/// anything one run could leave behind for the next.
+pub trait ActionRunner: Send + Sync {
+    /// Carries out `action` for `event`, waiting for it to finish.
+    fn run(&self, action: &Action, event: &Event) -> Result<ActionOutput, ActionError>;
+}
+
+/// What an action wrote while it ran.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActionOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// Errors which can occur while carrying out an action.
+#[derive(Debug)]
+pub enum ActionError {
+    /// The action couldn't be started.
+    Spawn(io::Error),
+    /// The command has a placeholder where its value can't be filled in safely.
+    Template(TemplateError),
+    /// The command ran, but exited unsuccessfully.
+    Failed {
+        status: ExitStatus,
+        output: ActionOutput,
+    },
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionError::Spawn(err) => write!(f, "failed to start: {err}"),
+            ActionError::Template(err) => write!(f, "not run: {err}"),
+            ActionError::Failed { status, output } => {
+                write!(f, "failed with {status}")?;
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                match stderr.trim() {
+                    "" => Ok(()),
+                    stderr => write!(f, ": {stderr}"),
+                }
+            }
+        }
+    }
+}
+
+impl Error for ActionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ActionError::Spawn(err) => Some(err),
+            ActionError::Template(err) => Some(err),
+            ActionError::Failed { .. } => None,
+        }
+    }
+}
+
+/// Runs the command of an action with the system shell, `sh -c` or `cmd /C` on Windows,
+/// capturing what it writes. Placeholders such as `{path}` are filled in for the event first,
+/// see [`crate::template::render`]. Its stdin is empty.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommandRunner;
+
+impl ActionRunner for CommandRunner {
+    fn run(&self, action: &Action, event: &Event) -> Result<ActionOutput, ActionError> {
+        let command = template::render(&action.command, event).map_err(ActionError::Template)?;
+        let output = shell(&command)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(ActionError::Spawn)?;
+        let status = output.status;
+        let output = ActionOutput {
+            stdout: output.stdout,
+            stderr: output.stderr,
+        };
+        if !status.success() {
+            return Err(ActionError::Failed { status, output });
+        }
+        Ok(output)
+    }
+}
+
+/// Runs nothing, printing the command each action would run to standard output instead, with
+/// its placeholders filled in, for trying a configuration out with `--dry-run`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DryRunner;
+
+impl ActionRunner for DryRunner {
+    fn run(&self, action: &Action, event: &Event) -> Result<ActionOutput, ActionError> {
+        let command = template::render(&action.command, event).map_err(ActionError::Template)?;
+        println!(
+            "would run `{command}` for {} of {}",
+            event.kind,
+            event.path.display()
+        );
+        Ok(ActionOutput::default())
+    }
+}
+
+/// A command running `command` with the system shell.
+fn shell(command: &str) -> Command {
+    let (program, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut shell = Command::new(program);
+    shell.arg(flag).arg(command);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use configuration::{EventKind, EventSet};
+
+    use super::*;
+
+    #[test]
+    fn runs_commands_and_captures_their_output() {
+        let action = |command: &str| Action {
+            events: EventSet::all(),
+            command: command.to_string(),
+        };
+        let event = Event::new("/srv/app/file", EventKind::Modify);
+
+        let output = CommandRunner.run(&action("echo out; echo err >&2"), &event);
+        assert_eq!(
+            output.unwrap(),
+            ActionOutput {
+                stdout: b"out\n".to_vec(),
+                stderr: b"err\n".to_vec(),
+            }
+        );
+
+        let err = CommandRunner
+            .run(&action("echo broken >&2; exit 3"), &event)
+            .unwrap_err();
+        assert!(
+            matches!(&err, ActionError::Failed { status, .. } if status.code() == Some(3)),
+            "{err:?}"
+        );
+        assert_eq!(err.to_string(), "failed with exit status: 3: broken");
+
+        let output = CommandRunner.run(&action("echo {event} {filename}"), &event);
+        assert_eq!(output.unwrap().stdout, b"modify file\n");
+        let err = CommandRunner.run(&action("echo \"{path}\""), &event);
+        assert!(matches!(err, Err(ActionError::Template(_))), "{err:?}");
+
+        let dir = tempfile::tempdir().unwrap();
+        let touched = dir.path().join("touched");
+        let output = DryRunner.run(&action(&format!("touch {}", touched.display())), &event);
+        assert_eq!(output.unwrap(), ActionOutput::default());
+        assert!(!touched.exists());
+    }
+}
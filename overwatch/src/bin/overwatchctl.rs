@@ -0,0 +1,104 @@
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+
+/// Manages a running overwatch through the control socket its configuration opens with
+/// control_socket.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// The control socket to connect to, /run/overwatch.sock by default.
+    #[arg(short, long)]
+    socket: Option<std::path::PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Starts watching what an include line with these arguments would, such as -r /srv.
+    Add {
+        #[arg(required = true, allow_hyphen_values = true, trailing_var_arg = true)]
+        include: Vec<String>,
+    },
+    /// Stops watching the include of a path.
+    Remove { path: String },
+    /// Lists the includes being watched.
+    List,
+    /// Stops acting on events until resumed, dropping those which arrive meanwhile.
+    Pause,
+    /// Acts on events again.
+    Resume,
+    /// Prints the counts of events and actions as JSON.
+    Stats,
+    /// Loads the configuration file again.
+    Reload,
+}
+
+#[cfg(unix)]
+fn main() -> ExitCode {
+    use overwatch::{ControlClient, ControlCommand, DEFAULT_CONTROL_SOCKET};
+    use serde_json::Value;
+
+    let cli = Cli::parse();
+    let command = match cli.command {
+        Command::Add { include } => {
+            let words: Vec<_> = include
+                .iter()
+                .map(|word| {
+                    if word.contains(char::is_whitespace) {
+                        format!("{word:?}")
+                    } else {
+                        word.clone()
+                    }
+                })
+                .collect();
+            ControlCommand::Add(words.join(" "))
+        }
+        Command::Remove { path } => match path.parse() {
+            Ok(path) => ControlCommand::Remove(path),
+            Err(err) => {
+                eprintln!("overwatchctl: {err}");
+                return ExitCode::FAILURE;
+            }
+        },
+        Command::List => ControlCommand::List,
+        Command::Pause => ControlCommand::Pause,
+        Command::Resume => ControlCommand::Resume,
+        Command::Stats => ControlCommand::Stats,
+        Command::Reload => ControlCommand::Reload,
+    };
+    let socket = cli.socket.unwrap_or_else(|| DEFAULT_CONTROL_SOCKET.into());
+    let mut client = match ControlClient::connect(&socket) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!(
+                "overwatchctl: failed to connect to {}: {err}",
+                socket.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    match client.send(&command) {
+        Ok(Value::String(text)) => println!("{text}"),
+        Ok(Value::Array(lines)) if lines.iter().all(Value::is_string) => {
+            for line in lines {
+                println!("{}", line.as_str().unwrap_or_default());
+            }
+        }
+        Ok(value) => println!("{value:#}"),
+        Err(err) => {
+            eprintln!("overwatchctl: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(not(unix))]
+fn main() -> ExitCode {
+    let _ = Cli::parse();
+    eprintln!("overwatchctl: the control socket is only supported on Unix");
+    ExitCode::FAILURE
+}
@@ -0,0 +1,370 @@
+//! The socket a running overwatch is managed through, declared with `control_socket`, and the
+//! client side of it, which `overwatchctl` uses.
+//!
+//! Clients send one command per line, such as `add -r /srv tags=web` or `stats`, and get one
+//! JSON object per line back: `{"ok": ...}` with what the command reports, or
+//! `{"error": "..."}` saying why it failed.
+
+use std::{
+    error::Error,
+    fmt,
+    fs::{self, Permissions},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use configuration::{Config, Directive, PathSpec};
+use serde_json::{json, Value};
+
+/// Where `control_socket` listens without a path, and where `overwatchctl` connects by
+/// default.
+pub const DEFAULT_CONTROL_SOCKET: &str = "/run/overwatch.sock";
+
+/// How long a connection waits for the event loop to answer a command.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The `control_socket [path]` directive, parsing to the path of the socket to listen on,
+/// [`DEFAULT_CONTROL_SOCKET`] if none is given.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ControlSocketDirective;
+
+impl ControlSocketDirective {
+    pub const NAME: &'static str = "control_socket";
+
+    /// The socket the last `control_socket` directive of `config` names, if there is one.
+    pub fn path(config: &Config) -> Option<&Path> {
+        config
+            .custom_values::<PathBuf>(Self::NAME)
+            .last()
+            .map(PathBuf::as_path)
+    }
+}
+
+impl Directive for ControlSocketDirective {
+    type Value = PathBuf;
+
+    fn parse(&self, args: &str) -> Result<PathBuf, String> {
+        match args.trim() {
+            "" => Ok(PathBuf::from(DEFAULT_CONTROL_SOCKET)),
+            path if path.starts_with('/') => Ok(PathBuf::from(path)),
+            path => Err(format!("expected an absolute path, found {path:?}")),
+        }
+    }
+}
+
+/// A command sent over the control socket.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlCommand {
+    /// `add <include>` starts watching what an include line with these arguments would, such
+    /// as `add -r /srv tags=web`.
+    Add(String),
+    /// `remove <path>` stops watching the include of the path.
+    Remove(PathSpec),
+    /// `list` reports the includes being watched, as include lines.
+    List,
+    /// `pause` stops acting on events, which are read and dropped until `resume`.
+    Pause,
+    Resume,
+    /// `stats` reports the counts the metrics keep.
+    Stats,
+    /// `reload` loads the configuration file again, as `SIGHUP` does.
+    Reload,
+}
+
+impl ControlCommand {
+    /// The name starting the command's line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ControlCommand::Add(_) => "add",
+            ControlCommand::Remove(_) => "remove",
+            ControlCommand::List => "list",
+            ControlCommand::Pause => "pause",
+            ControlCommand::Resume => "resume",
+            ControlCommand::Stats => "stats",
+            ControlCommand::Reload => "reload",
+        }
+    }
+}
+
+impl fmt::Display for ControlCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlCommand::Add(include) => write!(f, "add {include}"),
+            ControlCommand::Remove(path) => write!(f, "remove {path}"),
+            command => f.write_str(command.name()),
+        }
+    }
+}
+
+impl FromStr for ControlCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (name, args) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        let args = args.trim();
+        let command = match name {
+            "add" if !args.is_empty() => ControlCommand::Add(args.to_string()),
+            "add" => return Err("expected the include to add, as in add -r /srv".to_string()),
+            "remove" => ControlCommand::Remove(
+                args.parse()
+                    .map_err(|err| format!("expected the path to remove: {err}"))?,
+            ),
+            "list" => ControlCommand::List,
+            "pause" => ControlCommand::Pause,
+            "resume" => ControlCommand::Resume,
+            "stats" => ControlCommand::Stats,
+            "reload" => ControlCommand::Reload,
+            _ => {
+                return Err(format!(
+                    "unknown command {name}, expected add, remove, list, pause, resume, stats \
+                     or reload"
+                ))
+            }
+        };
+        match command {
+            ControlCommand::Add(_) | ControlCommand::Remove(_) => Ok(command),
+            _ if args.is_empty() => Ok(command),
+            _ => Err(format!("{name} takes no arguments")),
+        }
+    }
+}
+
+/// What a command reports, or why it failed.
+pub type ControlReply = Result<Value, String>;
+
+/// A command received over the control socket, for the event loop to carry out and answer.
+#[derive(Debug)]
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: Sender<ControlReply>,
+}
+
+impl ControlRequest {
+    pub fn answer(self, reply: ControlReply) {
+        let _ = self.reply.send(reply);
+    }
+}
+
+/// Listens on the control socket, handing each command received to whoever reads the
+/// [`ControlRequest`]s. The socket is only open to the user overwatch runs as, and is removed
+/// once the server is dropped.
+#[derive(Debug)]
+pub struct ControlServer {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlServer {
+    /// Listens at `path`, replacing a socket left behind by an instance which is gone, but not
+    /// one another instance still answers on.
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("another instance is listening on {}", path.display()),
+            ));
+        }
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, Permissions::from_mode(0o600))?;
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Accepts connections on a thread of its own, serving each on another, and returns the
+    /// channel their commands arrive on.
+    pub fn spawn(&self) -> io::Result<Receiver<ControlRequest>> {
+        let listener = self.listener.try_clone()?;
+        let (requests, received) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let requests = requests.clone();
+                thread::spawn(move || serve(stream, &requests));
+            }
+        });
+        Ok(received)
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Answers the commands sent over `stream`, one line after another, until it's closed.
+fn serve(stream: UnixStream, requests: &Sender<ControlRequest>) -> io::Result<()> {
+    let mut writer = &stream;
+    for line in BufReader::new(&stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match line.parse() {
+            Ok(command) => {
+                let (reply, answered) = mpsc::channel();
+                let _ = requests.send(ControlRequest { command, reply });
+                answered
+                    .recv_timeout(ANSWER_TIMEOUT)
+                    .unwrap_or_else(|_| Err("overwatch did not answer in time".to_string()))
+            }
+            Err(err) => Err(err),
+        };
+        let reply = match reply {
+            Ok(value) => json!({ "ok": value }),
+            Err(err) => json!({ "error": err }),
+        };
+        writeln!(writer, "{reply}")?;
+    }
+    Ok(())
+}
+
+/// Errors which can occur while talking to overwatch over the control socket.
+#[derive(Debug)]
+pub enum ControlError {
+    Io(io::Error),
+    /// Overwatch couldn't carry the command out, for the reason given.
+    Failed(String),
+    /// What came back wasn't a reply.
+    Protocol(String),
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlError::Io(err) => write!(f, "{err}"),
+            ControlError::Failed(reason) => f.write_str(reason),
+            ControlError::Protocol(reply) => write!(f, "unexpected reply {reply:?}"),
+        }
+    }
+}
+
+impl Error for ControlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ControlError::Io(err) => Some(err),
+            ControlError::Failed(_) | ControlError::Protocol(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ControlError {
+    fn from(err: io::Error) -> Self {
+        ControlError::Io(err)
+    }
+}
+
+/// A connection to the control socket of a running overwatch.
+#[derive(Debug)]
+pub struct ControlClient {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl ControlClient {
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        let writer = UnixStream::connect(path)?;
+        Ok(Self {
+            reader: BufReader::new(writer.try_clone()?),
+            writer,
+        })
+    }
+
+    /// Sends `command` and waits for what it reports.
+    pub fn send(&mut self, command: &ControlCommand) -> Result<Value, ControlError> {
+        writeln!(self.writer, "{command}")?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(ControlError::Protocol(line));
+        }
+        let mut reply: Value =
+            serde_json::from_str(&line).map_err(|_| ControlError::Protocol(line.clone()))?;
+        if let Some(value) = reply.get_mut("ok") {
+            return Ok(value.take());
+        }
+        match reply.get("error").and_then(Value::as_str) {
+            Some(reason) => Err(ControlError::Failed(reason.to_string())),
+            None => Err(ControlError::Protocol(line)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_commands_over_the_socket() {
+        let test_cases = vec![
+            ("add -r /srv tags=web", Ok("add -r /srv tags=web")),
+            ("remove /srv", Ok("remove /srv")),
+            (" stats ", Ok("stats")),
+            ("add", Err(())),
+            ("list everything", Err(())),
+            ("restart", Err(())),
+        ];
+        for (input, expected) in test_cases {
+            assert_eq!(
+                input
+                    .parse::<ControlCommand>()
+                    .map(|command| command.to_string())
+                    .map_err(|_| ()),
+                expected.map(String::from),
+                "{input}"
+            );
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("overwatch.sock");
+        let server = ControlServer::bind(&path).unwrap();
+        let requests = server.spawn().unwrap();
+        thread::spawn(move || {
+            for request in requests {
+                let reply = match &request.command {
+                    ControlCommand::List => Ok(json!(["include /srv"])),
+                    command => Err(format!("{} failed", command.name())),
+                };
+                request.answer(reply);
+            }
+        });
+        assert!(ControlServer::bind(&path).is_err());
+
+        let mut client = ControlClient::connect(&path).unwrap();
+        assert_eq!(
+            client.send(&ControlCommand::List).unwrap(),
+            json!(["include /srv"])
+        );
+        assert_eq!(
+            client.send(&ControlCommand::Pause).unwrap_err().to_string(),
+            "pause failed"
+        );
+        writeln!(client.writer, "restart").unwrap();
+        let mut line = String::new();
+        client.reader.read_line(&mut line).unwrap();
+        assert!(
+            line.starts_with(r#"{"error":"unknown command restart"#),
+            "{line}"
+        );
+
+        drop(server);
+        assert!(!path.exists());
+    }
+}
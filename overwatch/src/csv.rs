@@ -0,0 +1,335 @@
+//! Writing events as CSV rows, declared with `notify csv <file>` or `notify csv -`.
+
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use configuration::parse_size;
+
+use crate::{time::rfc3339, EventRecord, Output, Sink, SinkError};
+
+/// How many rotated files are kept when `keep` doesn't say.
+pub const DEFAULT_KEEP: u32 = 5;
+
+/// A column of the CSV rows, named as in the header row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Timestamp,
+    Kind,
+    Path,
+    From,
+    Tags,
+    Group,
+}
+
+impl Column {
+    pub const ALL: [Column; 6] = [
+        Column::Timestamp,
+        Column::Kind,
+        Column::Path,
+        Column::From,
+        Column::Tags,
+        Column::Group,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Column::Timestamp => "timestamp",
+            Column::Kind => "kind",
+            Column::Path => "path",
+            Column::From => "from",
+            Column::Tags => "tags",
+            Column::Group => "group",
+        }
+    }
+
+    /// The value of the column for `record`, as [`EventRecord::to_json`] gives it, with tags
+    /// separated by commas and what doesn't apply left empty.
+    pub fn value(self, record: &EventRecord) -> String {
+        match self {
+            Column::Timestamp => rfc3339(record.timestamp),
+            Column::Kind => record.kind.as_str().to_string(),
+            Column::Path => record.path.to_string_lossy().into_owned(),
+            Column::From => record
+                .from
+                .as_ref()
+                .map(|from| from.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            Column::Tags => record.tags.join(","),
+            Column::Group => record.group.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Column::ALL
+            .into_iter()
+            .find(|column| column.as_str() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Column::ALL.map(Column::as_str).into();
+                format!("unknown column {s}, expected {}", names.join(", "))
+            })
+    }
+}
+
+/// The settings of a `notify csv <target>` line:
+///
+/// - `columns=timestamp,kind,path` picks the columns and their order, every one of
+///   [`Column::ALL`] by default,
+/// - `rotate=10M` starts a new file once the current one would grow past that size, moving it
+///   to `<file>.1` and older files up by one,
+/// - `keep=5` is how many of those are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvConfig {
+    pub output: Output,
+    pub columns: Vec<Column>,
+    pub rotate: Option<u64>,
+    pub keep: u32,
+}
+
+impl CsvConfig {
+    pub(crate) fn parse(target: &str, options: &[(&str, &str)]) -> Result<Self, String> {
+        let mut config = CsvConfig {
+            output: Output::parse(target)?,
+            columns: Column::ALL.to_vec(),
+            rotate: None,
+            keep: DEFAULT_KEEP,
+        };
+        for (key, value) in options {
+            match *key {
+                "columns" => {
+                    config.columns = value
+                        .split(',')
+                        .map(|column| column.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "rotate" => match parse_size(value) {
+                    Some(size) if size > 0 => config.rotate = Some(size),
+                    _ => return Err(format!("expected a size above zero, found {value}")),
+                },
+                "keep" => match value.parse() {
+                    Ok(keep) if keep > 0 => config.keep = keep,
+                    _ => return Err(format!("expected a number of files to keep, found {value}")),
+                },
+                _ => {
+                    return Err(format!(
+                        "unknown option {key}, expected columns, rotate, keep or tags"
+                    ))
+                }
+            }
+        }
+        if config.rotate.is_some() && config.output == Output::Stdout {
+            return Err("only files can be rotated, not standard output".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// Writes each event as a row of the columns the configuration picks, quoted as RFC 4180
+/// describes. A header row naming the columns starts every new file, and standard output.
+pub struct Csv {
+    config: CsvConfig,
+    out: Box<dyn Write + Send>,
+    /// The size of the file written to, to know when it's due to be rotated.
+    written: u64,
+}
+
+impl Csv {
+    pub fn open(config: CsvConfig) -> io::Result<Self> {
+        let out = config.output.open()?;
+        let written = match &config.output {
+            Output::File(path) => fs::metadata(path)?.len(),
+            Output::Stdout => 0,
+        };
+        let mut csv = Self {
+            config,
+            out,
+            written,
+        };
+        if csv.written == 0 {
+            csv.write_header()?;
+        }
+        Ok(csv)
+    }
+
+    /// The row describing `record`, ending in CRLF.
+    pub fn row(&self, record: &EventRecord) -> String {
+        let values: Vec<_> = self
+            .config
+            .columns
+            .iter()
+            .map(|column| column.value(record))
+            .collect();
+        line(values.iter().map(String::as_str))
+    }
+
+    fn header(&self) -> String {
+        line(self.config.columns.iter().map(|column| column.as_str()))
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        self.write(&self.header())
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.out.write_all(text.as_bytes())?;
+        self.out.flush()?;
+        self.written += text.len() as u64;
+        Ok(())
+    }
+
+    /// Moves the file to `<file>.1`, and older ones up by one, then starts a new one.
+    fn rotate(&mut self, path: &Path) -> io::Result<()> {
+        let rotated = |n: u32| {
+            let mut name = path.as_os_str().to_owned();
+            name.push(format!(".{n}"));
+            PathBuf::from(name)
+        };
+        match fs::remove_file(rotated(self.config.keep)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        for n in (1..self.config.keep).rev() {
+            match fs::rename(rotated(n), rotated(n + 1)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        fs::rename(path, rotated(1))?;
+        self.out = self.config.output.open()?;
+        self.written = 0;
+        self.write_header()
+    }
+}
+
+impl Sink for Csv {
+    fn send(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        let row = self.row(record);
+        if let (Some(limit), Output::File(path)) = (self.config.rotate, &self.config.output) {
+            // A file holding no more than its header gets the row however long it is.
+            let header = self.header().len() as u64;
+            if self.written + row.len() as u64 > limit && self.written > header {
+                let path = path.clone();
+                self.rotate(&path)?;
+            }
+        }
+        self.write(&row)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), SinkError> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Joins `values` into a CSV line, quoting those holding commas, quotes or line breaks.
+fn line<'a>(values: impl Iterator<Item = &'a str>) -> String {
+    let mut line = String::new();
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+        if value.contains([',', '"', '\r', '\n']) {
+            line.push('"');
+            line.push_str(&value.replace('"', "\"\""));
+            line.push('"');
+        } else {
+            line.push_str(value);
+        }
+    }
+    line.push_str("\r\n");
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use configuration::EventKind;
+
+    use super::*;
+
+    fn record(path: &str) -> EventRecord {
+        EventRecord {
+            path: path.into(),
+            kind: EventKind::Modify,
+            from: None,
+            timestamp: UNIX_EPOCH,
+            tags: vec!["audit".to_string(), "web".to_string()],
+            group: None,
+            tampered: Vec::new(),
+            failure: None,
+        }
+    }
+
+    #[test]
+    fn writes_quoted_rows_and_rotates() {
+        let test_cases = vec![
+            ("", Ok(6)),
+            ("columns=kind,path", Ok(2)),
+            ("columns=kind,size", Err(())),
+            ("rotate=1K keep=2", Ok(6)),
+            ("rotate=0", Err(())),
+            ("keep=0", Err(())),
+            ("delimiter=;", Err(())),
+        ];
+        for (options, expected) in test_cases {
+            let options = crate::options::parse_options(options).unwrap();
+            let parsed = CsvConfig::parse("/var/log/changes.csv", &options);
+            assert_eq!(
+                parsed.map(|config| config.columns.len()).map_err(|_| ()),
+                expected,
+                "{options:?}"
+            );
+        }
+        assert!(CsvConfig::parse("-", &[("rotate", "1K")]).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("changes.csv");
+        let target = path.to_str().unwrap();
+        let options = [
+            ("columns", "kind,path,tags"),
+            ("rotate", "60"),
+            ("keep", "2"),
+        ];
+        let mut csv = Csv::open(CsvConfig::parse(target, &options).unwrap()).unwrap();
+        for path in [
+            "/srv/a.html",
+            "/srv/say \"hi\".txt",
+            "/srv/c.html",
+            "/srv/d.html",
+        ] {
+            csv.send(&record(path)).unwrap();
+        }
+        drop(csv);
+
+        let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+        // The file holding the first row was dropped to keep two.
+        assert_eq!(
+            read("changes.csv.2"),
+            "kind,path,tags\r\nmodify,\"/srv/say \"\"hi\"\".txt\",\"audit,web\"\r\n"
+        );
+        assert_eq!(
+            read("changes.csv.1"),
+            "kind,path,tags\r\nmodify,/srv/c.html,\"audit,web\"\r\n"
+        );
+        assert!(!dir.path().join("changes.csv.3").exists());
+        assert_eq!(
+            read("changes.csv"),
+            "kind,path,tags\r\nmodify,/srv/d.html,\"audit,web\"\r\n"
+        );
+    }
+}
@@ -0,0 +1,50 @@
+//! Detaching from the terminal to run in the background, for `--daemon`.
+
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io,
+    os::fd::AsRawFd,
+    path::Path,
+};
+
+/// Forks twice, leaving a process in a session of its own which can't regain a terminal, and
+/// returns in that process only: the ones before it exit. The working directory becomes `/`,
+/// stdin reads from `/dev/null` and stdout and stderr are appended to `log`, or discarded
+/// without one.
+///
+/// Only the calling thread survives a fork, so this has to be called before any threads are
+/// started. Paths used afterwards should be absolute.
+pub fn detach(log: Option<&Path>) -> io::Result<()> {
+    // Opened first so a log which can't be written to is reported while there's a terminal.
+    let out = match log {
+        Some(path) => OpenOptions::new().create(true).append(true).open(path)?,
+        None => OpenOptions::new().write(true).open("/dev/null")?,
+    };
+    let null = File::open("/dev/null")?;
+    fork()?;
+    // SAFETY: setsid takes no arguments, and the process isn't a group leader after forking.
+    if unsafe { libc::setsid() } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    fork()?;
+    env::set_current_dir("/")?;
+    for (from, to) in [(&null, 0), (&out, 1), (&out, 2)] {
+        // SAFETY: both descriptors are open, and replacing the standard ones is the point.
+        if unsafe { libc::dup2(from.as_raw_fd(), to) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Forks, exiting in the parent straight away and returning in the child.
+fn fork() -> io::Result<()> {
+    // SAFETY: the process has a single thread, see `detach`, and the parent exits without
+    // running anything else, destructors included, so nothing it holds is released twice.
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => unsafe { libc::_exit(0) },
+    }
+}
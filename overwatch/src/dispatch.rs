@@ -0,0 +1,276 @@
+//! Handing each event to the actions which apply to it.
+
+use std::{
+    sync::{mpsc, Arc},
+    thread,
+};
+
+use configuration::{Action, Config};
+use watcher::Event;
+
+use crate::{
+    retry::{jitter, RetryPolicy},
+    ActionError, ActionFailure, ActionOutput, ActionRunner, Metrics,
+};
+#[cfg(feature = "sqlite")]
+use crate::{ActionRecord, Store};
+
+/// Finds the actions a configuration binds to each event and runs them with an
+/// [`ActionRunner`], logging what fails.
+///
+/// The actions of an event are the global `on` actions followed by those of the include
+/// deciding its path, as [`Config::actions_for`] lists them, which react to its kind.
+///
+/// An action which fails is run again as its `retry` line says, see [`RetryPolicy`], waiting
+/// on the calling thread in between. Once its retries run out it's reported to
+/// [`Dispatcher::subscribe_failures`].
+#[derive(Debug, Clone)]
+pub struct Dispatcher<R> {
+    config: Config,
+    runner: R,
+    failures: Vec<mpsc::Sender<ActionFailure>>,
+    metrics: Option<Arc<Metrics>>,
+    #[cfg(feature = "sqlite")]
+    store: Option<Arc<Store>>,
+}
+
+impl<R: ActionRunner> Dispatcher<R> {
+    pub fn new(config: &Config, runner: R) -> Self {
+        Self {
+            config: config.clone(),
+            runner,
+            failures: Vec::new(),
+            metrics: None,
+            #[cfg(feature = "sqlite")]
+            store: None,
+        }
+    }
+
+    /// Returns a receiver of the actions which failed for good, once their retries ran out.
+    pub fn subscribe_failures(&mut self) -> mpsc::Receiver<ActionFailure> {
+        let (sender, receiver) = mpsc::channel();
+        self.failures.push(sender);
+        receiver
+    }
+
+    /// Counts the actions run, and retried, in `metrics`.
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Keeps how each action went, once its retries were done, in `store`.
+    #[cfg(feature = "sqlite")]
+    pub fn set_store(&mut self, store: Arc<Store>) {
+        self.store = Some(store);
+    }
+
+    pub(crate) fn metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_deref()
+    }
+
+    /// Dispatches later events by `config`, as after a reload.
+    pub fn set_config(&mut self, config: &Config) {
+        self.config = config.clone();
+    }
+
+    /// The actions which apply to `event`, in the order they run.
+    pub fn actions_for(&self, event: &Event) -> Vec<Action> {
+        match self.config.include_for(&event.path) {
+            Some(entry) => self
+                .config
+                .actions_for(&entry)
+                .filter(|action| action.matches(event.kind))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Runs the actions which apply to `event` one after another, returning how each went.
+    pub fn dispatch(&self, event: &Event) -> Vec<Result<ActionOutput, ActionError>> {
+        self.actions_for(event)
+            .iter()
+            .map(|action| self.run(action, event))
+            .collect()
+    }
+
+    /// Runs `action` for `event`, retrying it while it fails and its retries last. Failures
+    /// are logged as errors, and what successful actions wrote to stderr as warnings.
+    pub fn run(&self, action: &Action, event: &Event) -> Result<ActionOutput, ActionError> {
+        let policy = RetryPolicy::for_action(&self.config, action);
+        let _span = tracing::info_span!(
+            "action",
+            command = %action.command,
+            path = %event.path.display()
+        )
+        .entered();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let result = self.runner.run(action, event);
+            self.report(action, event, &result);
+            let err = match result {
+                Ok(output) => {
+                    if let Some(metrics) = self.metrics() {
+                        metrics.record_action(true);
+                    }
+                    self.keep(action, event, attempts, None);
+                    return Ok(output);
+                }
+                // A command which can't be filled in won't be the next time either.
+                Err(err @ ActionError::Template(_)) => err,
+                Err(err) if attempts > policy.retries => err,
+                Err(_) => {
+                    if let Some(metrics) = self.metrics() {
+                        metrics.record_retry();
+                    }
+                    let delay = policy.delay(attempts, jitter());
+                    tracing::info!(
+                        "retrying `{}` in {delay:?}, attempt {} of {}",
+                        action.command,
+                        attempts + 1,
+                        policy.retries + 1
+                    );
+                    thread::sleep(delay);
+                    continue;
+                }
+            };
+            if let Some(metrics) = self.metrics() {
+                metrics.record_action(false);
+            }
+            self.keep(action, event, attempts, Some(&err));
+            if policy.retries > 0 {
+                tracing::error!(
+                    "gave up on `{}` for {} after {attempts} attempts",
+                    action.command,
+                    event.path.display()
+                );
+            }
+            let failure = ActionFailure {
+                action: action.clone(),
+                event: event.clone(),
+                attempts,
+                error: err.to_string(),
+            };
+            for subscriber in &self.failures {
+                let _ = subscriber.send(failure.clone());
+            }
+            return Err(err);
+        }
+    }
+
+    /// Keeps how `action` went for `event` in the store, if there is one.
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    fn keep(&self, action: &Action, event: &Event, attempts: u32, err: Option<&ActionError>) {
+        #[cfg(feature = "sqlite")]
+        if let Some(store) = &self.store {
+            let record = ActionRecord::new(action, event, attempts, err.map(ToString::to_string));
+            if let Err(err) = store.record_action(&record) {
+                tracing::error!(
+                    "failed to keep how `{}` went in the store: {err}",
+                    action.command
+                );
+            }
+        }
+    }
+
+    fn report(&self, action: &Action, event: &Event, result: &Result<ActionOutput, ActionError>) {
+        let path = event.path.display();
+        let command = &action.command;
+        match result {
+            Ok(output) => {
+                tracing::debug!("ran `{command}` for {} of {path}", event.kind);
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                if !stderr.trim().is_empty() {
+                    tracing::warn!("`{command}` for {path}: {}", stderr.trim());
+                }
+            }
+            Err(err) => tracing::error!("`{command}` for {} of {path} {err}", event.kind),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use configuration::EventKind;
+
+    use super::*;
+
+    /// Records the commands it's asked to run, failing those which start with `fail`.
+    #[derive(Default)]
+    struct Recording(Mutex<Vec<String>>);
+
+    impl ActionRunner for Recording {
+        fn run(&self, action: &Action, _event: &Event) -> Result<ActionOutput, ActionError> {
+            self.0.lock().unwrap().push(action.command.clone());
+            if action.command.starts_with("fail") {
+                return Err(ActionError::Spawn(std::io::ErrorKind::NotFound.into()));
+            }
+            Ok(ActionOutput::default())
+        }
+    }
+
+    #[test]
+    fn retries_until_the_retries_run_out() {
+        let mut options = configuration::ParseOptions::default();
+        crate::register_directives(&mut options.directives);
+        let config = Config::parse_with(
+            "on modify run fail\non create run work\ninclude /srv\nretry 2 backoff=1ms",
+            &options,
+        )
+        .unwrap();
+        let mut dispatcher = Dispatcher::new(&config, Recording::default());
+        let failures = dispatcher.subscribe_failures();
+
+        assert!(dispatcher.dispatch(&Event::new("/srv/a", EventKind::Create))[0].is_ok());
+        assert!(failures.try_recv().is_err());
+        assert!(dispatcher.dispatch(&Event::new("/srv/a", EventKind::Modify))[0].is_err());
+        assert_eq!(
+            *dispatcher.runner.0.lock().unwrap(),
+            ["work", "fail", "fail", "fail"]
+        );
+        let failure = failures.try_recv().unwrap();
+        assert_eq!(
+            (failure.action.command.as_str(), failure.attempts),
+            ("fail", 3)
+        );
+        assert_eq!(failure.error, "failed to start: entity not found");
+    }
+
+    #[test]
+    fn runs_the_actions_of_each_event() {
+        let config: Config = "on delete run logger gone\n\
+                              include -r /srv/app on_modify \"deploy\" on_create \"fail\"\n\
+                              include -r /srv/www"
+            .parse()
+            .unwrap();
+        let dispatcher = Dispatcher::new(&config, Recording::default());
+
+        let test_cases = vec![
+            ("/srv/app/main.rs", EventKind::Modify, vec!["deploy"]),
+            ("/srv/app/main.rs", EventKind::Delete, vec!["logger gone"]),
+            (
+                "/srv/www/index.html",
+                EventKind::Delete,
+                vec!["logger gone"],
+            ),
+            ("/srv/www/index.html", EventKind::Modify, vec![]),
+            ("/etc/hosts", EventKind::Delete, vec![]),
+        ];
+        for (path, kind, expected) in test_cases {
+            let event = Event::new(path, kind);
+            let commands: Vec<_> = dispatcher
+                .actions_for(&event)
+                .into_iter()
+                .map(|action| action.command)
+                .collect();
+            assert_eq!(commands, expected, "{path} {kind}");
+        }
+
+        let results = dispatcher.dispatch(&Event::new("/srv/app/new", EventKind::Create));
+        assert!(matches!(results[..], [Err(ActionError::Spawn(_))]));
+        assert_eq!(*dispatcher.runner.0.lock().unwrap(), ["fail"]);
+    }
+}
@@ -0,0 +1,112 @@
+//! Whether overwatch is up and watching, for liveness and readiness probes.
+
+use std::{
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// How long the event loop may go without hearing from the backend before it's considered
+/// stalled. The loop reads with a timeout well below this, so it hears back even when
+/// nothing changes.
+pub const STALE_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+struct State {
+    /// How many includes are watched, once every one was registered.
+    watches: Option<usize>,
+    last_read: Option<Instant>,
+    failed: Option<String>,
+}
+
+/// What probes are told: whether the watches were all registered, and whether the backend
+/// has answered the event loop lately.
+#[derive(Debug, Default)]
+pub struct Health {
+    state: Mutex<State>,
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Marks every one of `watches` includes as registered.
+    pub fn set_ready(&self, watches: usize) {
+        let mut state = self.lock();
+        state.watches = Some(watches);
+        state.last_read = Some(Instant::now());
+    }
+
+    /// Notes that a read from the backend returned, with events or without.
+    pub fn record_read(&self) {
+        self.lock().last_read = Some(Instant::now());
+    }
+
+    /// Marks the watcher as having failed with `reason`.
+    pub fn set_failed(&self, reason: impl ToString) {
+        self.lock().failed = Some(reason.to_string());
+    }
+
+    /// `Ok` with a line to show if overwatch is ready to report changes, or `Err` with why not.
+    pub fn readiness(&self) -> Result<String, String> {
+        let state = self.lock();
+        match (&state.failed, state.watches) {
+            (Some(reason), _) => Err(format!("failed: {reason}")),
+            (None, Some(watches)) => Ok(format!("ready: watching {watches} includes")),
+            (None, None) => Err("not ready: registering watches".to_string()),
+        }
+    }
+
+    /// `Ok` with a line to show if the watcher is running and has heard from the backend
+    /// within `stale_after`, or `Err` with why not. A watcher still registering its watches
+    /// counts as alive.
+    pub fn liveness(&self, stale_after: Duration) -> Result<String, String> {
+        let state = self.lock();
+        if let Some(reason) = &state.failed {
+            return Err(format!("failed: {reason}"));
+        }
+        match state.last_read.map(|read| read.elapsed()) {
+            Some(quiet) if quiet > stale_after => Err(format!(
+                "stalled: no word from the backend in {}s",
+                quiet.as_secs()
+            )),
+            _ => Ok("ok".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_readiness_and_liveness() {
+        let health = Health::new();
+        assert!(health.readiness().is_err());
+        assert!(health.liveness(STALE_AFTER).is_ok());
+
+        health.set_ready(3);
+        assert_eq!(health.readiness().unwrap(), "ready: watching 3 includes");
+        assert!(health.liveness(STALE_AFTER).is_ok());
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(health
+            .liveness(Duration::from_millis(10))
+            .unwrap_err()
+            .starts_with("stalled"));
+        health.record_read();
+        assert!(health.liveness(Duration::from_millis(10)).is_ok());
+
+        health.set_failed("the watcher hung up");
+        assert_eq!(
+            health.liveness(STALE_AFTER).unwrap_err(),
+            "failed: the watcher hung up"
+        );
+        assert!(health.readiness().is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! The HTTP endpoint monitoring reads from, declared with `http_listen <address>`.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use configuration::Directive;
+
+use crate::{health::STALE_AFTER, Health, Metrics};
+
+/// How long a client has to send its request before it's given up on.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The `http_listen` directive, parsing to the address to listen on, such as
+/// `127.0.0.1:9100`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HttpListenDirective;
+
+impl HttpListenDirective {
+    pub const NAME: &'static str = "http_listen";
+}
+
+impl Directive for HttpListenDirective {
+    type Value = String;
+
+    fn parse(&self, args: &str) -> Result<String, String> {
+        let address = args.trim();
+        if address.is_empty() || address.contains(' ') || !address.contains(':') {
+            return Err(format!(
+                "expected an address and port, such as 127.0.0.1:9100, found {address:?}"
+            ));
+        }
+        Ok(address.to_string())
+    }
+}
+
+/// A small HTTP server answering
+///
+/// - `GET /metrics` with the [`Metrics`] in Prometheus' text format,
+/// - `GET /healthz`, the liveness probe, with 200 unless the watcher failed or the backend
+///   hasn't answered for [`STALE_AFTER`],
+/// - `GET /readyz`, the readiness probe, with 200 once every include is watched,
+///
+/// and 503 where a probe fails, as [`Health`] decides. Requests are answered one at a time,
+/// each on a connection of its own.
+#[derive(Debug)]
+pub struct HttpServer {
+    listener: TcpListener,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn text(status: &'static str, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into(),
+        }
+    }
+}
+
+impl HttpServer {
+    pub fn bind(address: &str, metrics: Arc<Metrics>, health: Arc<Health>) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(address)?,
+            metrics,
+            health,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Answers requests on a thread of its own, for as long as the process runs.
+    pub fn spawn(self) -> JoinHandle<()> {
+        thread::spawn(move || {
+            for stream in self.listener.incoming().flatten() {
+                let _ = self.handle(stream);
+            }
+        })
+    }
+
+    fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(&stream);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+        }
+        let mut words = request.split_whitespace();
+        let method = words.next().unwrap_or_default();
+        let target = words.next().unwrap_or_default();
+        let path = target.split('?').next().unwrap_or_default();
+        let response = if method != "GET" && method != "HEAD" {
+            Response::text("405 Method Not Allowed", "only GET is supported\n")
+        } else {
+            self.respond(path)
+        };
+        let mut stream = &stream;
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            response.status,
+            response.content_type,
+            response.body.len()
+        )?;
+        if method != "HEAD" {
+            stream.write_all(response.body.as_bytes())?;
+        }
+        stream.flush()
+    }
+
+    fn respond(&self, path: &str) -> Response {
+        let probe = |result: Result<String, String>| match result {
+            Ok(line) => Response::text("200 OK", line + "\n"),
+            Err(line) => Response::text("503 Service Unavailable", line + "\n"),
+        };
+        match path {
+            "/healthz" => probe(self.health.liveness(STALE_AFTER)),
+            "/readyz" => probe(self.health.readiness()),
+            "/metrics" => Response {
+                status: "200 OK",
+                content_type: "text/plain; version=0.0.4; charset=utf-8",
+                body: self.metrics.render(),
+            },
+            _ => Response::text("404 Not Found", "not found\n"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    /// Sends `request` to `address`, returning the status line and the body of the response.
+    fn get(address: SocketAddr, request: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "{request}\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+
+    #[test]
+    fn serves_metrics_and_probes() {
+        let metrics = Arc::new(Metrics::new());
+        let health = Arc::new(Health::new());
+        metrics.set_watches(4);
+        let server = HttpServer::bind("127.0.0.1:0", metrics, health.clone()).unwrap();
+        let address = server.local_addr().unwrap();
+        server.spawn();
+
+        let (status, body) = get(address, "GET /metrics HTTP/1.1");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(body.contains("\noverwatch_watches 4\n"), "{body}");
+        assert_eq!(
+            get(address, "GET /healthz HTTP/1.1"),
+            ("HTTP/1.1 200 OK".to_string(), "ok\n".to_string())
+        );
+        assert_eq!(
+            get(address, "GET /readyz HTTP/1.1").0,
+            "HTTP/1.1 503 Service Unavailable"
+        );
+        health.set_ready(4);
+        assert_eq!(
+            get(address, "GET /readyz?verbose HTTP/1.1"),
+            (
+                "HTTP/1.1 200 OK".to_string(),
+                "ready: watching 4 includes\n".to_string()
+            )
+        );
+        assert_eq!(
+            get(address, "GET /nothing HTTP/1.1").0,
+            "HTTP/1.1 404 Not Found"
+        );
+        assert_eq!(
+            get(address, "POST /metrics HTTP/1.1").0,
+            "HTTP/1.1 405 Method Not Allowed"
+        );
+        assert!(HttpListenDirective.parse("9100").is_err());
+        assert_eq!(
+            HttpListenDirective.parse(" [::1]:9100 ").unwrap(),
+            "[::1]:9100"
+        );
+    }
+}
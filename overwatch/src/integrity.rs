@@ -0,0 +1,577 @@
+//! File integrity monitoring, declared with `integrity <database>`: the contents, permissions
+//! and owners of the watched paths are checked against a database recorded with
+//! `overwatch --baseline`, and whatever changed since is reported as tampering.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use configuration::{parse_duration, Config, Directive, EventKind};
+use watcher::{Event, PathState, WatchState};
+
+use crate::options::parse_options;
+
+/// The context a database's signing key is derived from the key file's contents in.
+const KEY_CONTEXT: &str = "overwatch 2026-10-14 integrity database signature";
+
+/// The prefix of the last line of a signed database.
+const SIGNATURE: &str = "signature ";
+
+/// Where the integrity database is kept, how often every watched path is checked against it
+/// and the key it's signed with, from a line such as
+/// `integrity /var/lib/overwatch/integrity interval=1h key=/etc/overwatch/integrity.key`.
+///
+/// Paths are checked as overwatch starts and whenever an event is seen for them, and with an
+/// `interval` every file is hashed again that often, which catches changes made without the
+/// watcher seeing them. Without a key the database isn't signed.
+///
+/// A database which isn't signed with the key, or whose signature doesn't match, is reported
+/// as tampered with and overwatch refuses to start, unless `untrusted=continue` says to run
+/// on without checking integrity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityConfig {
+    pub database: PathBuf,
+    pub interval: Option<Duration>,
+    pub key: Option<PathBuf>,
+    pub continue_untrusted: bool,
+}
+
+impl IntegrityConfig {
+    /// The settings of the last `integrity` directive of `config`, if there is one.
+    pub fn from_config(config: &Config) -> Option<IntegrityConfig> {
+        config
+            .custom_values::<IntegrityConfig>(IntegrityDirective::NAME)
+            .last()
+            .cloned()
+    }
+
+    /// Reads the key the database is signed with, if one is configured.
+    pub fn signing_key(&self) -> Result<Option<SigningKey>, IntegrityError> {
+        self.key.as_deref().map(SigningKey::read).transpose()
+    }
+}
+
+impl FromStr for IntegrityConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let absolute = |path: &str| match path {
+            path if path.starts_with('/') => Ok(PathBuf::from(path)),
+            path => Err(format!("expected an absolute path, found {path:?}")),
+        };
+        let s = s.trim();
+        let (database, rest) = s.split_once(' ').unwrap_or((s, ""));
+        let mut integrity = IntegrityConfig {
+            database: absolute(database)?,
+            interval: None,
+            key: None,
+            continue_untrusted: false,
+        };
+        for (key, value) in parse_options(rest)? {
+            match key {
+                "interval" => match parse_duration(value) {
+                    Some(interval) if !interval.is_zero() => integrity.interval = Some(interval),
+                    _ => return Err(format!("expected a duration above zero, found {value}")),
+                },
+                "key" => integrity.key = Some(absolute(value)?),
+                "untrusted" => match value {
+                    "exit" => integrity.continue_untrusted = false,
+                    "continue" => integrity.continue_untrusted = true,
+                    _ => return Err(format!("expected exit or continue, found {value}")),
+                },
+                _ => {
+                    return Err(format!(
+                        "unknown option {key}, expected interval, key or untrusted"
+                    ))
+                }
+            }
+        }
+        Ok(integrity)
+    }
+}
+
+/// The `integrity` directive, parsing to an [`IntegrityConfig`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IntegrityDirective;
+
+impl IntegrityDirective {
+    pub const NAME: &'static str = "integrity";
+}
+
+impl Directive for IntegrityDirective {
+    type Value = IntegrityConfig;
+
+    fn parse(&self, args: &str) -> Result<IntegrityConfig, String> {
+        args.parse()
+    }
+}
+
+/// Errors which can occur while reading or recording the integrity database.
+#[derive(Debug)]
+pub enum IntegrityError {
+    Io(io::Error),
+    /// The key file couldn't be read, or is empty.
+    Key(PathBuf, io::Error),
+    /// A key is configured, but the database isn't signed.
+    Unsigned,
+    /// The database's signature doesn't match its contents, or was made with another key.
+    BadSignature,
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityError::Io(err) => write!(f, "{err}"),
+            IntegrityError::Key(path, err) => {
+                write!(f, "failed to read the key {}: {err}", path.display())
+            }
+            IntegrityError::Unsigned => f.write_str("the database is not signed"),
+            IntegrityError::BadSignature => f.write_str(
+                "the database's signature doesn't match, it may have been tampered with",
+            ),
+        }
+    }
+}
+
+impl Error for IntegrityError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IntegrityError::Io(err) | IntegrityError::Key(_, err) => Some(err),
+            IntegrityError::Unsigned | IntegrityError::BadSignature => None,
+        }
+    }
+}
+
+impl From<io::Error> for IntegrityError {
+    fn from(err: io::Error) -> Self {
+        IntegrityError::Io(err)
+    }
+}
+
+/// The key integrity databases are signed with, derived from the contents of a key file.
+#[derive(Clone)]
+pub struct SigningKey([u8; 32]);
+
+impl SigningKey {
+    pub fn read(path: &Path) -> Result<Self, IntegrityError> {
+        let contents = fs::read(path)
+            .and_then(|contents| {
+                if contents.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "it's empty"));
+                }
+                Ok(contents)
+            })
+            .map_err(|err| IntegrityError::Key(path.to_path_buf(), err))?;
+        Ok(Self::derive(&contents))
+    }
+
+    fn derive(material: &[u8]) -> Self {
+        Self(blake3::derive_key(KEY_CONTEXT, material))
+    }
+
+    fn sign(&self, data: &[u8]) -> blake3::Hash {
+        blake3::keyed_hash(&self.0, data)
+    }
+}
+
+/// Leaves the key out.
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SigningKey(..)")
+    }
+}
+
+/// Writes `state` to the database at `path`, followed by its signature with `key`.
+pub fn record_database(
+    state: &WatchState,
+    path: &Path,
+    key: Option<&SigningKey>,
+) -> Result<(), IntegrityError> {
+    let mut data = Vec::new();
+    state.write(&mut data)?;
+    if let Some(key) = key {
+        let signature = key.sign(&data);
+        data.extend_from_slice(format!("{SIGNATURE}{}\n", signature.to_hex()).as_bytes());
+    }
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".tmp");
+    fs::write(&partial, data)?;
+    fs::rename(partial, path)?;
+    Ok(())
+}
+
+/// Reads the database at `path`, checking its signature with `key`. Without a key a signature
+/// is ignored.
+pub fn load_database(path: &Path, key: Option<&SigningKey>) -> Result<WatchState, IntegrityError> {
+    let data = fs::read(path)?;
+    let body = data.strip_suffix(b"\n").unwrap_or(&data);
+    let start = body
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |at| at + 1);
+    let (state, signature) = match body[start..].strip_prefix(SIGNATURE.as_bytes()) {
+        Some(signature) => (&data[..start], Some(signature)),
+        None => (&data[..], None),
+    };
+    if let Some(key) = key {
+        let signature = signature.ok_or(IntegrityError::Unsigned)?;
+        let signature = std::str::from_utf8(signature)
+            .ok()
+            .and_then(|hex| blake3::Hash::from_hex(hex).ok())
+            .ok_or(IntegrityError::BadSignature)?;
+        // Comparing digests takes the same time wherever they differ.
+        if key.sign(state) != signature {
+            return Err(IntegrityError::BadSignature);
+        }
+    }
+    Ok(WatchState::read(state)?)
+}
+
+/// What changed about a path since the integrity database was recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Tampering {
+    Created,
+    Deleted,
+    /// A file's contents, or a directory that's now a file or the other way around. Files
+    /// over their `max_size` aren't hashed, and are compared by size and modification time.
+    Content,
+    Permissions,
+    Owner,
+    /// The database itself: it isn't signed with the key, or its signature doesn't match.
+    Signature,
+}
+
+impl Tampering {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Tampering::Created => "created",
+            Tampering::Deleted => "deleted",
+            Tampering::Content => "content",
+            Tampering::Permissions => "permissions",
+            Tampering::Owner => "owner",
+            Tampering::Signature => "signature",
+        }
+    }
+
+    /// What differs between a path as `recorded` and as it is `now`, either of which may be
+    /// missing.
+    pub fn between(recorded: Option<&PathState>, now: Option<&PathState>) -> Vec<Tampering> {
+        let (recorded, now) = match (recorded, now) {
+            (None, None) => return Vec::new(),
+            (None, Some(_)) => return vec![Tampering::Created],
+            (Some(_), None) => return vec![Tampering::Deleted],
+            (Some(recorded), Some(now)) => (recorded, now),
+        };
+        let content = recorded.dir != now.dir
+            || match (recorded.hash, now.hash) {
+                (Some(recorded), Some(now)) => recorded != now,
+                _ => !now.dir && (recorded.size != now.size || recorded.modified != now.modified),
+            };
+        [
+            (content, Tampering::Content),
+            (recorded.mode != now.mode, Tampering::Permissions),
+            (recorded.owner != now.owner, Tampering::Owner),
+        ]
+        .into_iter()
+        .filter_map(|(changed, tampering)| changed.then_some(tampering))
+        .collect()
+    }
+}
+
+/// Parses what [`Tampering::as_str`] writes.
+impl FromStr for Tampering {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        [
+            Tampering::Created,
+            Tampering::Deleted,
+            Tampering::Content,
+            Tampering::Permissions,
+            Tampering::Owner,
+            Tampering::Signature,
+        ]
+        .into_iter()
+        .find(|tampering| tampering.as_str() == s)
+        .ok_or(())
+    }
+}
+
+impl fmt::Display for Tampering {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A path found to differ from the integrity database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tamper {
+    pub path: PathBuf,
+    pub tampered: Vec<Tampering>,
+}
+
+impl Tamper {
+    /// The event the tampering amounts to: a creation, a deletion or a modification.
+    pub fn event(&self) -> Event {
+        let kind = match self.tampered.as_slice() {
+            [Tampering::Created] => EventKind::Create,
+            [Tampering::Deleted] => EventKind::Delete,
+            _ => EventKind::Modify,
+        };
+        Event::new(&self.path, kind)
+    }
+}
+
+/// Checks the watched paths against the integrity database, reporting each change once: a
+/// path is only reported again if it changes further.
+#[derive(Debug)]
+pub struct IntegrityMonitor {
+    database: WatchState,
+    /// What each path reported as tampered with was like when it was.
+    reported: HashMap<PathBuf, Option<PathState>>,
+    interval: Option<Duration>,
+    next_check: Option<Instant>,
+}
+
+impl IntegrityMonitor {
+    /// Checks against `database`, checking every path again each `interval` from `now`.
+    pub fn new(database: WatchState, interval: Option<Duration>, now: Instant) -> Self {
+        Self {
+            database,
+            reported: HashMap::new(),
+            interval,
+            next_check: interval.map(|interval| now + interval),
+        }
+    }
+
+    /// Returns true once it's time to check every path again.
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.next_check.is_some_and(|next| next <= now)
+    }
+
+    /// Hashes every path `config` watches and checks them, along with those the database has
+    /// which are gone, as of `now`.
+    pub fn check_all(&mut self, config: &Config, now: Instant) -> Vec<Tamper> {
+        self.next_check = self.interval.map(|interval| now + interval);
+        let state = WatchState::scan(config, None);
+        let paths: BTreeSet<PathBuf> = self
+            .database
+            .iter()
+            .chain(state.iter())
+            .map(|(path, _)| path.to_path_buf())
+            .collect();
+        paths
+            .into_iter()
+            .filter_map(|path| {
+                let found = state.get(&path).copied();
+                self.compare(path, found)
+            })
+            .collect()
+    }
+
+    /// Checks `path`, which an event was seen for.
+    pub fn check_path(&mut self, config: &Config, path: &Path) -> Option<Tamper> {
+        if self.database.get(path).is_none() && !config.is_watched(path) {
+            return None;
+        }
+        let found = fs::symlink_metadata(path)
+            .ok()
+            .map(|metadata| PathState::of(config, path, &metadata, None));
+        self.compare(path.to_path_buf(), found)
+    }
+
+    fn compare(&mut self, path: PathBuf, found: Option<PathState>) -> Option<Tamper> {
+        let tampered = Tampering::between(self.database.get(&path), found.as_ref());
+        if tampered.is_empty() {
+            self.reported.remove(&path);
+            return None;
+        }
+        if let Some(reported) = self.reported.get(&path) {
+            if Tampering::between(reported.as_ref(), found.as_ref()).is_empty() {
+                return None;
+            }
+        }
+        self.reported.insert(path.clone(), found);
+        Some(Tamper { path, tampered })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    /// A directory of three files, a configuration watching it and a database recording it
+    /// signed with `key`.
+    fn recorded(key: Option<&SigningKey>) -> (tempfile::TempDir, PathBuf, Config, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("etc");
+        fs::create_dir(&root).unwrap();
+        for file in ["passwd", "hosts", "motd"] {
+            fs::write(root.join(file), file).unwrap();
+        }
+        let config: Config = format!("include -r {}", root.display()).parse().unwrap();
+        let database = dir.path().join("integrity");
+        record_database(&WatchState::scan(&config, None), &database, key).unwrap();
+        (dir, root, config, database)
+    }
+
+    fn tamper(root: &Path, name: &str, tampered: &[Tampering]) -> Tamper {
+        Tamper {
+            path: root.join(name),
+            tampered: tampered.to_vec(),
+        }
+    }
+
+    #[test]
+    fn parses_integrity_lines() {
+        let test_cases = vec![
+            ("/var/lib/overwatch/integrity", Ok((None, None, false))),
+            (
+                "/var/lib/fim interval=1h key=/etc/fim.key",
+                Ok((Some(3600), Some("/etc/fim.key"), false)),
+            ),
+            (
+                "/var/lib/fim key=/etc/fim.key untrusted=continue",
+                Ok((None, Some("/etc/fim.key"), true)),
+            ),
+            ("fim.db", Err(())),
+            ("/var/lib/fim key=fim.key", Err(())),
+            ("/var/lib/fim interval=never", Err(())),
+            ("/var/lib/fim untrusted=maybe", Err(())),
+        ];
+        for (input, expected) in test_cases {
+            let parsed = IntegrityDirective.parse(input).map(|integrity| {
+                (
+                    integrity.interval.map(|interval| interval.as_secs()),
+                    integrity.key.map(|key| key.display().to_string()),
+                    integrity.continue_untrusted,
+                )
+            });
+            let expected = expected
+                .map(|(interval, key, untrusted)| (interval, key.map(String::from), untrusted));
+            assert_eq!(parsed.map_err(|_| ()), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn signs_the_database() {
+        let key = SigningKey::derive(b"secret");
+        let (_dir, _root, config, database) = recorded(Some(&key));
+        let recorded = load_database(&database, Some(&key)).unwrap();
+        assert_eq!(recorded, WatchState::scan(&config, None));
+        // Without a key the signature is ignored.
+        assert_eq!(load_database(&database, None).unwrap(), recorded);
+    }
+
+    #[test]
+    fn rejects_bad_signatures() {
+        let key = SigningKey::derive(b"secret");
+        let (_dir, _root, _config, database) = recorded(Some(&key));
+        let other = SigningKey::derive(b"other");
+        assert!(matches!(
+            load_database(&database, Some(&other)),
+            Err(IntegrityError::BadSignature)
+        ));
+
+        let signed = fs::read_to_string(&database).unwrap();
+        fs::write(&database, signed.replacen(" 6 ", " 7 ", 1)).unwrap();
+        assert!(matches!(
+            load_database(&database, Some(&key)),
+            Err(IntegrityError::BadSignature)
+        ));
+
+        let unsigned = &signed[..signed.rfind(SIGNATURE).unwrap()];
+        fs::write(&database, unsigned).unwrap();
+        assert!(matches!(
+            load_database(&database, Some(&key)),
+            Err(IntegrityError::Unsigned)
+        ));
+    }
+
+    #[test]
+    fn reports_content_changes() {
+        let (_dir, root, config, database) = recorded(None);
+        let recorded = load_database(&database, None).unwrap();
+        let mut monitor = IntegrityMonitor::new(recorded, None, Instant::now());
+        assert_eq!(monitor.check_all(&config, Instant::now()), vec![]);
+        assert!(!monitor.is_due(Instant::now() + Duration::from_secs(3600)));
+
+        fs::write(root.join("passwd"), "PASSWD").unwrap();
+        fs::remove_file(root.join("motd")).unwrap();
+        fs::write(root.join("shadow"), "new").unwrap();
+        assert_eq!(
+            monitor.check_path(&config, &root.join("passwd")),
+            Some(tamper(&root, "passwd", &[Tampering::Content]))
+        );
+        assert_eq!(
+            monitor.check_all(&config, Instant::now()),
+            vec![
+                tamper(&root, "motd", &[Tampering::Deleted]),
+                tamper(&root, "shadow", &[Tampering::Created]),
+            ]
+        );
+        assert_eq!(
+            tamper(&root, "motd", &[Tampering::Deleted]).event().kind,
+            EventKind::Delete
+        );
+    }
+
+    #[test]
+    fn reports_permission_and_owner_changes() {
+        let (_dir, root, config, database) = recorded(None);
+        let recorded = load_database(&database, None).unwrap();
+        let mut monitor = IntegrityMonitor::new(recorded.clone(), None, Instant::now());
+        fs::set_permissions(root.join("hosts"), fs::Permissions::from_mode(0o666)).unwrap();
+        assert_eq!(
+            monitor.check_all(&config, Instant::now()),
+            vec![tamper(&root, "hosts", &[Tampering::Permissions])]
+        );
+
+        // Changing the owner needs root, so the recorded state is changed instead.
+        let passwd = *recorded.get(&root.join("passwd")).unwrap();
+        let chowned = PathState {
+            owner: passwd.owner.map(|(user, group)| (user + 1, group)),
+            ..passwd
+        };
+        assert_eq!(
+            Tampering::between(Some(&passwd), Some(&chowned)),
+            vec![Tampering::Owner]
+        );
+    }
+
+    #[test]
+    fn reports_tampering_once() {
+        let (_dir, root, config, database) = recorded(None);
+        let recorded = load_database(&database, None).unwrap();
+        let mut monitor = IntegrityMonitor::new(recorded, None, Instant::now());
+        fs::write(root.join("passwd"), "PASSWD").unwrap();
+        fs::set_permissions(root.join("hosts"), fs::Permissions::from_mode(0o666)).unwrap();
+        assert_eq!(monitor.check_all(&config, Instant::now()).len(), 2);
+        assert_eq!(monitor.check_all(&config, Instant::now()), vec![]);
+        assert_eq!(monitor.check_path(&config, &root.join("passwd")), None);
+
+        // Changing further is reported again, and changing back forgets the report.
+        fs::write(root.join("passwd"), "passwd").unwrap();
+        fs::write(root.join("hosts"), "HOSTS").unwrap();
+        assert_eq!(
+            monitor.check_all(&config, Instant::now()),
+            vec![tamper(
+                &root,
+                "hosts",
+                &[Tampering::Content, Tampering::Permissions]
+            )]
+        );
+        fs::write(root.join("passwd"), "PASSWD").unwrap();
+        assert_eq!(
+            monitor.check_all(&config, Instant::now()),
+            vec![tamper(&root, "passwd", &[Tampering::Content])]
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Writing events to the systemd journal, declared with `notify journald`.
+
+use std::{
+    io,
+    os::unix::{ffi::OsStrExt, net::UnixDatagram},
+    path::PathBuf,
+};
+
+use crate::{
+    syslog::{severity_option, DEFAULT_SEVERITIES},
+    EventRecord, Severity, Sink, SinkError,
+};
+
+/// The socket journald reads entries from.
+pub const DEFAULT_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// The settings of a `notify journald` line:
+///
+/// - `identifier=name` sets the `SYSLOG_IDENTIFIER` of the entries, `overwatch` by default,
+/// - `severity.delete=warning` sets the `PRIORITY` of one kind of event, as for syslog,
+/// - `socket=/path` writes to another socket than [`DEFAULT_SOCKET`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournaldConfig {
+    pub socket: PathBuf,
+    pub identifier: String,
+    /// The priority of each kind of event, in the order of [`configuration::EventKind::ALL`].
+    pub severities: [Severity; 4],
+}
+
+impl JournaldConfig {
+    pub(crate) fn parse(options: &[(&str, &str)]) -> Result<Self, String> {
+        let mut config = JournaldConfig {
+            socket: PathBuf::from(DEFAULT_SOCKET),
+            identifier: "overwatch".to_string(),
+            severities: DEFAULT_SEVERITIES,
+        };
+        for (key, value) in options {
+            if severity_option(&mut config.severities, key, value)? {
+                continue;
+            }
+            match *key {
+                "identifier" if !value.is_empty() => config.identifier = value.to_string(),
+                "socket" if !value.is_empty() => config.socket = PathBuf::from(value),
+                "identifier" | "socket" => return Err(format!("expected a value for {key}")),
+                _ => {
+                    return Err(format!(
+                    "unknown option {key}, expected identifier, severity.<event>, socket or tags"
+                ))
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Writes each event to the journal as an entry with structured fields, so that
+/// `journalctl -t overwatch OVERWATCH_EVENT=delete` finds them:
+///
+/// - `OVERWATCH_PATH` and, for renames, `OVERWATCH_FROM`,
+/// - `OVERWATCH_EVENT`, the kind of event,
+/// - `OVERWATCH_TAGS`, the tags separated by commas, and `OVERWATCH_GROUP`, where there are
+///   any,
+///
+/// along with `MESSAGE`, `PRIORITY` and `SYSLOG_IDENTIFIER`.
+#[derive(Debug)]
+pub struct Journald {
+    config: JournaldConfig,
+    socket: UnixDatagram,
+}
+
+impl Journald {
+    /// Connects to the journal's socket.
+    pub fn new(config: JournaldConfig) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(&config.socket)?;
+        Ok(Self { config, socket })
+    }
+
+    /// The entry describing `record`, in the journal's native protocol.
+    pub fn entry(&self, record: &EventRecord) -> Vec<u8> {
+        let mut entry = Vec::new();
+        let priority = self.config.severities[record.kind as usize].code();
+        field(&mut entry, "MESSAGE", record.summary().as_bytes());
+        field(&mut entry, "PRIORITY", priority.to_string().as_bytes());
+        field(
+            &mut entry,
+            "SYSLOG_IDENTIFIER",
+            self.config.identifier.as_bytes(),
+        );
+        field(
+            &mut entry,
+            "OVERWATCH_PATH",
+            record.path.as_os_str().as_bytes(),
+        );
+        if let Some(from) = &record.from {
+            field(&mut entry, "OVERWATCH_FROM", from.as_os_str().as_bytes());
+        }
+        field(
+            &mut entry,
+            "OVERWATCH_EVENT",
+            record.kind.as_str().as_bytes(),
+        );
+        if !record.tags.is_empty() {
+            field(
+                &mut entry,
+                "OVERWATCH_TAGS",
+                record.tags.join(",").as_bytes(),
+            );
+        }
+        if let Some(group) = &record.group {
+            field(&mut entry, "OVERWATCH_GROUP", group.as_bytes());
+        }
+        entry
+    }
+}
+
+impl Sink for Journald {
+    fn send(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        self.socket.send(&self.entry(record))?;
+        Ok(())
+    }
+}
+
+/// Appends a field to `entry`, as `NAME=value` or, for values spanning lines, as the name
+/// followed by the length of the value and the value itself.
+fn field(entry: &mut Vec<u8>, name: &str, value: &[u8]) {
+    entry.extend_from_slice(name.as_bytes());
+    if value.contains(&b'\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value);
+    entry.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use configuration::EventKind;
+
+    use super::*;
+
+    #[test]
+    fn writes_entries_with_event_fields() {
+        let test_cases = vec![
+            ("", Ok(("overwatch", 4))),
+            (
+                "identifier=fim severity.delete=crit",
+                Ok(("fim", Severity::Critical.code())),
+            ),
+            ("identifier=\"\"", Err(())),
+            ("severity.delete=loud", Err(())),
+            ("facility=local0", Err(())),
+        ];
+        for (options, expected) in test_cases {
+            let options = crate::options::parse_options(options).unwrap();
+            let parsed = JournaldConfig::parse(&options).map(|config| {
+                (
+                    config.identifier.clone(),
+                    config.severities[EventKind::Delete as usize].code(),
+                )
+            });
+            assert_eq!(
+                parsed.map_err(|_| ()),
+                expected.map(|(identifier, code)| (identifier.to_string(), code)),
+                "{options:?}"
+            );
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("journal.socket");
+        let journal = UnixDatagram::bind(&socket).unwrap();
+        let socket = socket.to_str().unwrap();
+        let mut journald =
+            Journald::new(JournaldConfig::parse(&[("socket", socket)]).unwrap()).unwrap();
+        let record = EventRecord {
+            path: "/srv/two\nlines".into(),
+            kind: EventKind::Delete,
+            from: None,
+            timestamp: UNIX_EPOCH,
+            tags: vec!["security".to_string(), "web".to_string()],
+            group: None,
+            tampered: Vec::new(),
+            failure: None,
+        };
+        journald.send(&record).unwrap();
+
+        let mut buf = [0; 1024];
+        let len = journal.recv(&mut buf).unwrap();
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&21u64.to_le_bytes());
+        expected.extend_from_slice(
+            b"delete /srv/two\nlines\nPRIORITY=4\nSYSLOG_IDENTIFIER=overwatch\nOVERWATCH_PATH\n",
+        );
+        expected.extend_from_slice(&14u64.to_le_bytes());
+        expected.extend_from_slice(
+            b"/srv/two\nlines\nOVERWATCH_EVENT=delete\nOVERWATCH_TAGS=security,web\n",
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&buf[..len]),
+            String::from_utf8_lossy(&expected)
+        );
+    }
+}
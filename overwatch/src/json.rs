@@ -0,0 +1,85 @@
+//! Writing every event as one JSON document, declared with `output json`.
+
+use std::io::{self, Write};
+
+use serde_json::Value;
+
+use crate::{EventRecord, Output, Sink, SinkError};
+
+/// Keeps the JSON object of each event, see [`EventRecord::to_json`], and writes them all as
+/// a single array once the watcher stops, for tools which read a whole document rather than
+/// lines as they arrive.
+pub struct Json {
+    out: Box<dyn Write + Send>,
+    records: Vec<Value>,
+}
+
+impl Json {
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        Self {
+            out: Box::new(out),
+            records: Vec::new(),
+        }
+    }
+
+    pub fn open(output: &Output) -> io::Result<Self> {
+        Ok(Self {
+            out: output.open()?,
+            records: Vec::new(),
+        })
+    }
+}
+
+impl Sink for Json {
+    fn send(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        self.records.push(record.to_json());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), SinkError> {
+        let records = Value::from(std::mem::take(&mut self.records));
+        writeln!(self.out, "{records:#}")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::UNIX_EPOCH};
+
+    use configuration::EventKind;
+
+    use super::*;
+
+    #[test]
+    fn writes_every_event_as_one_array() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.json");
+        let record = |path: &str, kind| EventRecord {
+            path: path.into(),
+            kind,
+            from: None,
+            timestamp: UNIX_EPOCH,
+            tags: Vec::new(),
+            group: None,
+            tampered: Vec::new(),
+            failure: None,
+        };
+        let records = [
+            record("/srv/index.html", EventKind::Create),
+            record("/etc/motd", EventKind::Delete),
+        ];
+        let mut json = Json::open(&Output::File(path.clone())).unwrap();
+        for record in &records {
+            json.send(record).unwrap();
+        }
+        // Nothing is written until the sink is flushed, as overwatch stops.
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        json.flush().unwrap();
+
+        let written: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let expected: Vec<_> = records.iter().map(EventRecord::to_json).collect();
+        assert_eq!(written, Value::from(expected));
+    }
+}
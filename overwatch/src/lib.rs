@@ -0,0 +1,175 @@
+//! Acting on what the watcher reports: running the actions a configuration binds to events and
+//! logging how they went.
+//!
+//! A [`Dispatcher`] looks up the `on <events> run <command>` actions which apply to each event
+//! and hands them to an [`ActionRunner`]. [`CommandRunner`] runs their commands with the system
+//! shell, capturing what they write, and other kinds of action plug in as runners of their own.
+//! Commands ask for details of the event with placeholders such as `{path}`, listed in
+//! [`template::PLACEHOLDERS`], which are filled in quoted for the shell. One inside double
+//! quotes or backticks, where the shell would expand a `$(...)` in a file's name, keeps the
+//! action from running. How they went is reported with `tracing`, each action in a span
+//! naming its command and path, and [`init_logging`] writes it where the configuration's
+//! `log_file` and `log_level` say, as text or as JSON with `log_format json`.
+//!
+//! An [`ActionQueue`] runs actions on a pool of threads instead, as many at once as
+//! `max_concurrent 4` allows, or `max_concurrent 1 action="<command>"` for one action. Actions
+//! wait in a queue of bounded length, `queue=N`, and `overflow=block|drop-oldest|coalesce` says
+//! what happens once it's full. When overwatch stops, the actions queued and running get up
+//! to `shutdown_timeout 30s` to finish.
+//!
+//! `retry 3 backoff=2s` runs a failed action up to three more times, waiting two seconds and
+//! then twice as long after each further failure, with some jitter. An action which still
+//! fails is reported to [`Dispatcher::subscribe_failures`], and the daemon hands it to the sinks
+//! and the store as a record of its event, see [`EventRecord::failure`].
+//!
+//! `storm_limit 500/s for=10s` guards against event storms, such as a runaway build job's:
+//! once events arrive faster than that for ten seconds, a [`StormGuard`] suppresses them,
+//! logging how many were suppressed under which directory, until they've kept under the rate
+//! for as long again.
+//!
+//! Events are also handed on to the [`Sink`]s declared with `notify`, as [`EventRecord`]s
+//! carrying the tags and watch group of their paths, and `tags=security` limits a sink to the
+//! events of includes with those tags. A [`Notifier`] runs every sink on a thread of its own.
+//!
+//! - `notify webhook <url>` posts each event as JSON, with `header="Name: value"`,
+//!   `timeout=5s` and `retry=3 backoff=2s` options.
+//! - `notify syslog udp://logs.example.com` sends RFC 5424 messages over UDP, TCP or, with
+//!   `unix:///dev/log`, a local socket, under `facility=local0` and with severities such as
+//!   `severity.delete=warning`.
+//! - `notify journald`, on Linux, writes entries to the systemd journal with
+//!   `OVERWATCH_PATH`, `OVERWATCH_EVENT` and `OVERWATCH_TAGS` fields.
+//! - `notify ndjson <file>` appends each event's JSON object to a file as a line of its own,
+//!   and `notify ndjson -`, or `output ndjson`, writes them to standard output.
+//! - `output plain` writes each event to standard output as a line for people to read, and
+//!   `output json` writes them all as one JSON array once overwatch stops.
+//! - `notify csv <file>` writes rows of the `columns=timestamp,kind,path` picked, starting a
+//!   new file once the current one reaches `rotate=10M` and keeping `keep=5` older ones.
+//!
+//! `batch 100 events / 1s` hands events to the sinks and the store in batches, once a batch
+//! holds a hundred or its first event is a second old, see [`Sink::send_batch`]. A webhook
+//! posts each batch as one JSON array, and the store keeps each in one transaction.
+//!
+//! `integrity /var/lib/overwatch/integrity key=/etc/overwatch/integrity.key` checks the
+//! watched paths against a database of their digests, permissions and owners, recorded and
+//! signed by `overwatch --baseline`. An [`IntegrityMonitor`] checks them as overwatch starts,
+//! whenever an event is seen for one and, with `interval=1h`, every hour, handing each change
+//! found to the sinks as an [`EventRecord`] listing the [`Tampering`]. A database whose
+//! signature doesn't match is reported too, and overwatch doesn't start.
+//!
+//! `store /var/lib/overwatch/store.db retain=30d` keeps a history of the events seen and how
+//! the actions run for them went, along with the baseline, in a SQLite database when
+//! overwatch is built with the `sqlite` feature. `overwatch query --since 24h --path /etc`
+//! reads it back offline. What's older than `retain`, or past `max_events=N`, is pruned.
+//!
+//! `http_listen 127.0.0.1:9100` serves the [`Metrics`] at `/metrics` for Prometheus: events by
+//! kind, watch group and tag, events the filters dropped, how actions went, how many wait in
+//! the queue and how many includes are watched. `/healthz` and `/readyz` answer liveness and
+//! readiness probes as [`Health`] decides.
+//!
+//! Run as a `Type=notify` systemd service, overwatch says it's ready once every include is
+//! watched and pings the watchdog a `WatchdogSec=` setting starts, while the event loop keeps
+//! hearing from the backend. On Unix this goes through [`SdNotify`], and [`Signals`] has
+//! `SIGHUP` reload the configuration and `SIGTERM` stop overwatch once the events held back
+//! are handed on. The configuration file is also reloaded whenever it changes.
+//!
+//! On Unix, `control_socket /run/overwatch.sock` lets `overwatchctl` manage a running
+//! overwatch through a [`ControlServer`]: add and remove includes, list them, pause and resume
+//! acting on events, read the stats and reload.
+//!
+//! Directives like these are added to a parser with [`register_directives`].
+
+mod action;
+#[cfg(unix)]
+mod control;
+mod csv;
+#[cfg(unix)]
+mod detach;
+mod dispatch;
+mod health;
+mod http;
+mod integrity;
+#[cfg(target_os = "linux")]
+mod journald;
+mod json;
+mod log;
+mod metrics;
+mod ndjson;
+mod options;
+mod output;
+mod pidfile;
+mod plain;
+mod queue;
+mod record;
+mod retry;
+mod signal;
+mod sink;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod store;
+mod storm;
+mod syslog;
+#[cfg(unix)]
+mod systemd;
+pub mod template;
+pub mod time;
+mod webhook;
+
+pub use action::{ActionError, ActionOutput, ActionRunner, CommandRunner, DryRunner};
+#[cfg(unix)]
+pub use control::{
+    ControlClient, ControlCommand, ControlError, ControlReply, ControlRequest, ControlServer,
+    ControlSocketDirective, DEFAULT_CONTROL_SOCKET,
+};
+pub use csv::{Column, Csv, CsvConfig, DEFAULT_KEEP};
+#[cfg(unix)]
+pub use detach::detach;
+pub use dispatch::Dispatcher;
+pub use health::{Health, STALE_AFTER};
+pub use http::{HttpListenDirective, HttpServer};
+pub use integrity::{
+    load_database, record_database, IntegrityConfig, IntegrityDirective, IntegrityError,
+    IntegrityMonitor, SigningKey, Tamper, Tampering,
+};
+#[cfg(target_os = "linux")]
+pub use journald::{Journald, JournaldConfig, DEFAULT_SOCKET};
+pub use json::Json;
+pub use log::{init_logging, LogFormat, LogFormatDirective, DEFAULT_LEVEL, LOG_FILTER_ENV};
+pub use metrics::Metrics;
+pub use ndjson::Ndjson;
+pub use output::Output;
+pub use pidfile::{Pidfile, PidfileError, DEFAULT_PIDFILE};
+pub use plain::Plain;
+pub use queue::{
+    ActionQueue, Limits, MaxConcurrent, MaxConcurrentDirective, Overflow, ShutdownTimeoutDirective,
+    DEFAULT_QUEUE, DEFAULT_SHUTDOWN_TIMEOUT,
+};
+pub use record::EventRecord;
+pub use retry::{ActionFailure, Retry, RetryDirective, RetryPolicy, DEFAULT_BACKOFF, MAX_BACKOFF};
+pub use signal::{Signal, Signals};
+pub use sink::{Notifier, Notify, NotifyDirective, Sink, SinkConfig, SinkError};
+#[cfg(feature = "sqlite")]
+pub use sqlite::{ActionRecord, Store, StoreError, StoreQuery};
+pub use store::{StoreConfig, StoreDirective, DEFAULT_RETAIN};
+pub use storm::{StormDirective, StormGuard, StormLimit, StormReport};
+pub use syslog::{Facility, Severity, Syslog, SyslogConfig, SyslogTransport, DEFAULT_PORT};
+#[cfg(unix)]
+pub use systemd::SdNotify;
+pub use webhook::{Webhook, WebhookConfig, DEFAULT_TIMEOUT};
+
+/// Registers the directives this crate and the watcher define, such as
+/// [`MaxConcurrentDirective`], so configuration text using them parses.
+pub fn register_directives(registry: &mut configuration::DirectiveRegistry) {
+    watcher::register_directives(registry);
+    registry
+        .register(MaxConcurrentDirective::NAME, MaxConcurrentDirective)
+        .register(ShutdownTimeoutDirective::NAME, ShutdownTimeoutDirective)
+        .register(RetryDirective::NAME, RetryDirective)
+        .register(NotifyDirective::NAME, NotifyDirective)
+        .register(HttpListenDirective::NAME, HttpListenDirective)
+        .register(LogFormatDirective::NAME, LogFormatDirective)
+        .register(StormDirective::NAME, StormDirective)
+        .register(IntegrityDirective::NAME, IntegrityDirective)
+        .register(StoreDirective::NAME, StoreDirective);
+    #[cfg(unix)]
+    registry.register(ControlSocketDirective::NAME, ControlSocketDirective);
+}
@@ -0,0 +1,187 @@
+//! Overwatch's own log: what it reports with `tracing`, written where the configuration's
+//! `log_file`, `log_level` and `log_format` say.
+
+use std::{
+    env,
+    fs::OpenOptions,
+    io::{self, IsTerminal},
+    sync::Mutex,
+};
+
+use configuration::{Config, Directive, LogLevel, LoggingConfig};
+use tracing::{level_filters::LevelFilter, Subscriber};
+use tracing_subscriber::{
+    fmt::{writer::BoxMakeWriter, MakeWriter},
+    EnvFilter,
+};
+
+/// The level logged at when the configuration doesn't set one.
+pub const DEFAULT_LEVEL: LogLevel = LogLevel::Info;
+
+/// The environment variable whose filter directives, such as `info,watcher=trace`, replace
+/// the configuration's `log_level`.
+pub const LOG_FILTER_ENV: &str = "RUST_LOG";
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// A line of text each, stamped with the time and level, after the spans it was logged in.
+    #[default]
+    Text,
+    /// A JSON object each, with the fields of the event and of the spans it was logged in.
+    Json,
+}
+
+/// The `log_format text|json` directive.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogFormatDirective;
+
+impl LogFormatDirective {
+    pub const NAME: &'static str = "log_format";
+
+    /// The format the last `log_format` directive of `config` asks for, text if there is none.
+    pub fn format(config: &Config) -> LogFormat {
+        config
+            .custom_values::<LogFormat>(Self::NAME)
+            .last()
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl Directive for LogFormatDirective {
+    type Value = LogFormat;
+
+    fn parse(&self, args: &str) -> Result<LogFormat, String> {
+        match args.trim() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            format => Err(format!("expected text or json, found {format:?}")),
+        }
+    }
+}
+
+/// Starts logging what's reported with `tracing` as `logging` and `format` describe, appending
+/// to its `log_file` or else writing to stderr. Directives in [`LOG_FILTER_ENV`] take the
+/// place of its `log_level`.
+pub fn init_logging(logging: &LoggingConfig, format: LogFormat) -> io::Result<()> {
+    let (out, ansi) = match &logging.file {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            (BoxMakeWriter::new(Mutex::new(file)), false)
+        }
+        None => (BoxMakeWriter::new(io::stderr), io::stderr().is_terminal()),
+    };
+    let level = logging.level.unwrap_or(DEFAULT_LEVEL);
+    let directives = env::var(LOG_FILTER_ENV).unwrap_or_default();
+    let subscriber = subscriber(filter(level, &directives), format, out, ansi);
+    tracing::subscriber::set_global_default(subscriber).map_err(io::Error::other)
+}
+
+/// Lets through what `directives` do, or with none messages of `level` and above.
+fn filter(level: LogLevel, directives: &str) -> EnvFilter {
+    let level = match level {
+        LogLevel::Error => LevelFilter::ERROR,
+        LogLevel::Warn => LevelFilter::WARN,
+        LogLevel::Info => LevelFilter::INFO,
+        LogLevel::Debug => LevelFilter::DEBUG,
+        LogLevel::Trace => LevelFilter::TRACE,
+    };
+    EnvFilter::builder()
+        .with_default_directive(level.into())
+        .parse_lossy(directives)
+}
+
+fn subscriber<W>(
+    filter: EnvFilter,
+    format: LogFormat,
+    out: W,
+    ansi: bool,
+) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(out)
+        .with_ansi(ansi)
+        .with_target(false);
+    match format {
+        LogFormat::Text => Box::new(builder.finish()),
+        LogFormat::Json => Box::new(builder.json().flatten_event(true).finish()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, sync::Arc};
+
+    use serde_json::Value;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'w> MakeWriter<'w> for Shared {
+        type Writer = Shared;
+
+        fn make_writer(&'w self) -> Shared {
+            self.clone()
+        }
+    }
+
+    /// What logging a few messages at every level writes with `directives` and `format`.
+    fn written(directives: &str, format: LogFormat) -> Vec<String> {
+        let out = Shared::default();
+        let subscriber = subscriber(
+            filter(LogLevel::Warn, directives),
+            format,
+            out.clone(),
+            false,
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("action", command = "make").entered();
+            tracing::error!("broken");
+            tracing::warn!(path = "/srv", "odd");
+            tracing::info!("fine");
+            tracing::debug!("noisy");
+        });
+        let written = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        written.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn writes_enabled_levels() {
+        let lines = written("", LogFormat::Text);
+        assert_eq!(lines.len(), 2, "{lines:?}");
+        assert!(lines[0].ends_with(" ERROR broken"), "{}", lines[0]);
+        assert!(
+            lines[1].ends_with(" WARN odd path=\"/srv\""),
+            "{}",
+            lines[1]
+        );
+        assert_eq!(written("info", LogFormat::Text).len(), 3);
+        assert_eq!(written("debug,bogus=", LogFormat::Text).len(), 4);
+
+        let lines = written("info", LogFormat::Json);
+        let line: Value = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["message"], "odd");
+        assert_eq!(line["path"], "/srv");
+        assert_eq!(line["span"]["command"], "make");
+
+        assert_eq!(LogFormatDirective.parse(" json ").unwrap(), LogFormat::Json);
+        assert!(LogFormatDirective.parse("xml").is_err());
+    }
+}
@@ -0,0 +1,1845 @@
+use std::{
+    io::{self, Write},
+    iter,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{mpsc::Receiver, Arc},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "sqlite")]
+use std::time::SystemTime;
+
+use clap::{builder::PossibleValuesParser, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use configuration::{
+    Action, Config, ConfigError, ConfigReloader, Diagnostic, ParseOptions, ParseOutcome, PathSpec,
+    Reload, Severity, Warning, WarningKind, WatchEntry,
+};
+use overwatch::{
+    init_logging, load_database, record_database, template, ActionError, ActionFailure,
+    ActionOutput, ActionQueue, ActionRunner, CommandRunner, Dispatcher, DryRunner, EventRecord,
+    Health, HttpListenDirective, HttpServer, IntegrityConfig, IntegrityDirective, IntegrityError,
+    IntegrityMonitor, Limits, LogFormatDirective, MaxConcurrentDirective, Metrics, Notifier,
+    Pidfile, ShutdownTimeoutDirective, Signal, Signals, SinkError, StoreConfig, StoreDirective,
+    StormGuard, StormLimit, StormReport, Tamper, Tampering, DEFAULT_PIDFILE,
+};
+#[cfg(feature = "sqlite")]
+use overwatch::{time::rfc3339, Store, StoreQuery};
+#[cfg(unix)]
+use overwatch::{
+    ControlCommand, ControlReply, ControlRequest, ControlServer, ControlSocketDirective, SdNotify,
+};
+#[cfg(unix)]
+use serde_json::Value;
+use watcher::{
+    watch_entries, AutoWatcher, BaselineFileDirective, BatchPolicy, Batcher, Debounced,
+    DedupeDirective, Deduplicator, Event, Filter, Filtered, StateFileDirective, Verified,
+    VerifyDirective, WatchError, WatchState, Watcher,
+};
+
+/// Watches the paths a configuration includes and runs its actions as they change.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// The configuration file, found in the usual locations if not given.
+    #[arg(short, long, global = true)]
+    config: Option<PathBuf>,
+
+    /// The [profile <name>] section to layer over the rest of the configuration.
+    #[arg(short, long, global = true)]
+    profile: Option<String>,
+
+    /// Detaches from the terminal to run in the background, writing output to the
+    /// configuration's log_file.
+    #[arg(long)]
+    daemon: bool,
+
+    /// Writes the process id to this file, refusing to start while another instance holds
+    /// it. Defaults to /run/overwatch.pid with --daemon.
+    #[arg(long)]
+    pidfile: Option<PathBuf>,
+
+    /// Logs the events seen and prints the commands actions would run without running them,
+    /// writing what sinks would be handed to standard error instead.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Records the metadata and digests of every watched path in the configuration's
+    /// baseline_file, integrity database and store, and exits. Runs after that report where
+    /// the paths deviate from it.
+    #[arg(long, conflicts_with_all = ["daemon", "dry_run"])]
+    baseline: bool,
+
+    /// What to do instead of watching.
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Checks the configuration and the paths it names, printing every problem found with the
+    /// line it's on, and exits non-zero if any is an error.
+    Validate,
+    /// Prints every path which would be watched, once the includes are expanded, walked and
+    /// excluded from.
+    List {
+        /// Prints the paths as a JSON array, along with whether each is a directory, a file
+        /// or missing.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Prints a script completing overwatch's subcommands and flags in the given shell. The
+    /// profiles completed are those the configuration declares when the script is generated.
+    Completions { shell: Shell },
+    /// Rewrites a configuration file in the canonical layout, sorting the paths of each
+    /// directive and aligning continued lines while keeping its comments.
+    Fmt {
+        /// Leaves the file as it is and exits non-zero if it isn't already formatted.
+        #[arg(long)]
+        check: bool,
+        file: PathBuf,
+    },
+    /// Prints the events kept in the configuration's store, oldest first, or how the actions
+    /// run for them went.
+    #[cfg(feature = "sqlite")]
+    Query {
+        /// Only what happened within this long, such as 24h.
+        #[arg(long, value_parser = parse_since)]
+        since: Option<Duration>,
+        /// Only what happened to this path or under it.
+        #[arg(long)]
+        path: Option<PathBuf>,
+        /// Only events of this kind.
+        #[arg(
+            long,
+            value_parser = PossibleValuesParser::new(["create", "modify", "delete", "rename"])
+        )]
+        kind: Option<String>,
+        /// Only this many of the most recent.
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Prints the actions run, and whether they failed, instead of the events.
+        #[arg(long)]
+        actions: bool,
+        /// Prints them as a JSON array.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let mut options = ParseOptions::default();
+    overwatch::register_directives(&mut options.directives);
+    let profile = cli.profile.as_deref();
+    match cli.command {
+        Some(Command::Validate) => {
+            return written(validate(cli.config, profile, &options, &mut io::stdout()))
+        }
+        Some(Command::List { json }) => {
+            let out = &mut io::stdout();
+            return written(list(cli.config.as_deref(), profile, &options, json, out));
+        }
+        Some(Command::Completions { shell }) => {
+            return completions(shell, cli.config.as_deref(), &options, &mut io::stdout())
+        }
+        Some(Command::Fmt { check, file }) => return fmt(&file, check),
+        #[cfg(feature = "sqlite")]
+        Some(Command::Query {
+            since,
+            path,
+            kind,
+            limit,
+            actions,
+            json,
+        }) => {
+            let store_query = StoreQuery {
+                since: since.and_then(|since| SystemTime::now().checked_sub(since)),
+                path,
+                kind: kind.and_then(|kind| kind.parse().ok()),
+                limit,
+            };
+            let loaded = load(cli.config.as_deref(), &options).map_err(|err| err.to_string());
+            let config = loaded.and_then(|(config, _)| select(&config, profile));
+            return query(config, &store_query, actions, json);
+        }
+        None => {}
+    }
+    let (reloader, config) = match load(cli.config.as_deref(), &options) {
+        Ok((config, path)) => match select(&config, profile) {
+            Ok(selected) => (ConfigReloader::with_config(path, options, config), selected),
+            Err(err) => {
+                eprintln!("overwatch: {err}");
+                return ExitCode::FAILURE;
+            }
+        },
+        Err(err) => {
+            eprintln!("overwatch: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if cli.baseline {
+        return baseline(&config);
+    }
+    let pidfile = cli
+        .pidfile
+        .or_else(|| cli.daemon.then(|| PathBuf::from(DEFAULT_PIDFILE)));
+    let mut pidfile = match pidfile.map(|path| Pidfile::acquire(absolute(&path))) {
+        None => None,
+        Some(Ok(pidfile)) => Some(pidfile),
+        Some(Err(err)) => {
+            eprintln!("overwatch: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if cli.daemon {
+        if let Err(err) = daemonize(&config) {
+            eprintln!("overwatch: failed to detach: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Some(pidfile) = &mut pidfile {
+        if let Err(err) = pidfile.write_pid() {
+            eprintln!(
+                "overwatch: failed to write {}: {err}",
+                pidfile.path().display()
+            );
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Err(err) = init_logging(config.logging(), LogFormatDirective::format(&config)) {
+        eprintln!("overwatch: failed to open the log: {err}");
+        return ExitCode::FAILURE;
+    }
+    let notifier = match notifier(&config, cli.dry_run) {
+        Ok(notifier) => notifier,
+        Err(err) => {
+            tracing::error!("failed to start a sink: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    #[cfg(feature = "sqlite")]
+    let store = match StoreConfig::from_config(&config) {
+        // A dry run leaves the history as it is.
+        Some(_) if cli.dry_run => None,
+        Some(store) => match Store::open(&store) {
+            Ok(opened) => Some(Arc::new(opened)),
+            Err(err) => {
+                tracing::error!("failed to open the store {}: {err}", store.path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    let metrics = Arc::new(Metrics::new());
+    let health = Arc::new(Health::new());
+    if let Some(address) = config
+        .custom_values::<String>(HttpListenDirective::NAME)
+        .last()
+    {
+        match HttpServer::bind(address, metrics.clone(), health.clone()) {
+            Ok(server) => {
+                tracing::info!("serving metrics and probes on {address}");
+                server.spawn();
+            }
+            Err(err) => {
+                tracing::error!("failed to listen on {address}: {err}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    #[cfg(unix)]
+    let systemd = match SdNotify::from_env() {
+        Ok(systemd) => systemd,
+        Err(err) => {
+            tracing::error!("failed to reach systemd: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let signals = match Signals::install() {
+        Ok(signals) => signals,
+        Err(err) => {
+            tracing::error!("failed to install signal handlers: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    #[cfg(unix)]
+    let control = match ControlSocketDirective::path(&config).map(listen) {
+        None => None,
+        Some(Ok(control)) => {
+            tracing::info!("listening for commands on {}", control.0.path().display());
+            Some(control)
+        }
+        Some(Err(err)) => {
+            tracing::error!("failed to open the control socket: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let dedupe = DedupeDirective::window(&config).map(Deduplicator::new);
+    let storm =
+        StormLimit::from_config(&config).map(|limit| StormGuard::new(limit, Instant::now()));
+    let batch = BatchPolicy::from_config(&config).map(Batcher::new);
+    let mut daemon = Daemon {
+        config,
+        profile: cli.profile,
+        reloader,
+        notifier,
+        metrics,
+        health,
+        #[cfg(unix)]
+        systemd,
+        signals,
+        #[cfg(unix)]
+        control,
+        paused: None,
+        dry_run: cli.dry_run,
+        dedupe,
+        storm,
+        batch,
+        state: None,
+        integrity: None,
+        #[cfg(feature = "sqlite")]
+        store,
+    };
+    if daemon.start_integrity().is_err() {
+        return ExitCode::FAILURE;
+    }
+    match daemon.run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            daemon.health.set_failed(&err);
+            tracing::error!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Loads the configuration at `path`, or the one found in the usual locations, along with the
+/// absolute path it was loaded from.
+fn load(path: Option<&Path>, options: &ParseOptions) -> Result<(Config, PathBuf), ConfigError> {
+    match path {
+        // Made absolute so paths resolved against it still hold once detached.
+        Some(path) => {
+            let path = absolute(path);
+            Config::from_file_with(&path, options).map(|config| (config, path))
+        }
+        None => Config::discover_with(options).map(|(config, path)| (config, absolute(&path))),
+    }
+}
+
+/// `config` with the profile `name` layered over it, or `config` itself without a profile.
+fn select(config: &Config, profile: Option<&str>) -> Result<Config, String> {
+    match profile {
+        Some(name) => config
+            .profile(name)
+            .ok_or_else(|| format!("the configuration declares no profile {name}")),
+        None => Ok(config.clone()),
+    }
+}
+
+/// Prints the completion script for `shell`, with the profiles of the configuration at `path`
+/// as the values `--profile` completes to. A configuration which doesn't load completes none.
+fn completions(
+    shell: Shell,
+    path: Option<&Path>,
+    options: &ParseOptions,
+    out: &mut impl Write,
+) -> ExitCode {
+    let profiles: Vec<String> = match load(path, options) {
+        Ok((config, _)) => config.profiles().map(String::from).collect(),
+        Err(_) => Vec::new(),
+    };
+    let mut command = Cli::command();
+    if !profiles.is_empty() {
+        command = command.mut_arg("profile", |arg| {
+            arg.value_parser(PossibleValuesParser::new(profiles))
+        });
+    }
+    clap_complete::generate(shell, &mut command, "overwatch", out);
+    ExitCode::SUCCESS
+}
+
+/// Formats the configuration file at `path` with [`configuration::format`], writing it back
+/// only if that changes it. With `check` the file is left alone, and a file which isn't
+/// formatted fails.
+fn fmt(path: &Path, check: bool) -> ExitCode {
+    let input = match std::fs::read_to_string(path) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("overwatch: {}: {err}", path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let formatted = configuration::format(&input);
+    if formatted == input {
+        return ExitCode::SUCCESS;
+    }
+    if check {
+        println!("{}: not formatted", path.display());
+        return ExitCode::FAILURE;
+    }
+    if let Err(err) = std::fs::write(path, formatted) {
+        eprintln!("overwatch: {}: {err}", path.display());
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+/// Prints the paths [`watcher::watch_paths`] finds for the configuration, one per line or as
+/// JSON.
+fn list(
+    path: Option<&Path>,
+    profile: Option<&str>,
+    options: &ParseOptions,
+    json: bool,
+    out: &mut impl Write,
+) -> io::Result<ExitCode> {
+    let config = match load(path, options).map_err(|err| err.to_string()) {
+        Ok((config, _)) => select(&config, profile),
+        Err(err) => Err(err),
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("overwatch: {err}");
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    let paths = watcher::watch_paths(&config);
+    if !json {
+        for path in paths {
+            writeln!(out, "{}", path.display())?;
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    let paths: Vec<_> = paths
+        .iter()
+        .map(|path| {
+            let kind = match std::fs::metadata(path) {
+                Ok(metadata) if metadata.is_dir() => "directory",
+                Ok(_) => "file",
+                Err(_) => "missing",
+            };
+            serde_json::json!({ "path": path.display().to_string(), "type": kind })
+        })
+        .collect();
+    writeln!(out, "{:#}", serde_json::Value::from(paths))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Loads the configuration at `path`, or the one found in the usual locations, and prints
+/// the warnings raised loading it and the problems [`Config::validate`] finds, each with the
+/// file and line it's on. Fails if the configuration doesn't load or a problem is an error.
+fn validate(
+    path: Option<PathBuf>,
+    profile: Option<&str>,
+    options: &ParseOptions,
+    out: &mut impl Write,
+) -> io::Result<ExitCode> {
+    let path = match path.map_or_else(Config::discover_path, Ok) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("overwatch: {err}");
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    let outcome = match Config::load_with_warnings(path.as_path(), options) {
+        Ok(outcome) => outcome,
+        Err(err) => {
+            for error in err.errors() {
+                writeln!(out, "{}: error: {error}", path.display())?;
+            }
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    let config = match select(&outcome.config, profile) {
+        Ok(config) => config,
+        Err(err) => {
+            writeln!(out, "{}: error: {err}", path.display())?;
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    // Excludes outside the includes were already warned about while loading.
+    let warned = |path: &PathSpec| {
+        outcome.warnings.iter().any(|warning| {
+            matches!(&warning.kind, WarningKind::ExcludeOutsideIncludes(warned) if warned == path)
+        })
+    };
+    let diagnostics: Vec<_> = config
+        .validate()
+        .into_iter()
+        .filter(|diagnostic| !warned(&diagnostic.path))
+        .collect();
+    for warning in &outcome.warnings {
+        writeln!(out, "{}", warning_line(warning))?;
+    }
+    for diagnostic in &diagnostics {
+        writeln!(out, "{}", diagnostic_line(&outcome, diagnostic))?;
+    }
+    // Actions whose placeholders the shell would expand, which are never run.
+    let mut commands: Vec<String> = config
+        .actions()
+        .iter()
+        .map(|action| action.command.clone())
+        .collect();
+    for entry in watch_entries(&config) {
+        for action in entry.options.actions {
+            if !commands.contains(&action.command) {
+                commands.push(action.command);
+            }
+        }
+    }
+    let unsafe_commands: Vec<_> = commands
+        .iter()
+        .filter_map(|command| Some((command, template::check(command).err()?)))
+        .collect();
+    for (command, err) in &unsafe_commands {
+        writeln!(out, "{}: error: `{command}`: {err}", path.display())?;
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+    let warnings = outcome.warnings.len() + diagnostics.len() - errors;
+    let errors = errors + unsafe_commands.len();
+    let warnings = plural(warnings, "warning");
+    if errors > 0 {
+        writeln!(
+            out,
+            "{}: {}, {warnings}",
+            path.display(),
+            plural(errors, "error")
+        )?;
+        return Ok(ExitCode::FAILURE);
+    }
+    writeln!(out, "{}: ok, {warnings}", path.display())?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The exit code of a subcommand, or a failure if its output couldn't be written.
+fn written(result: io::Result<ExitCode>) -> ExitCode {
+    result.unwrap_or_else(|err| {
+        eprintln!("overwatch: failed to write the output: {err}");
+        ExitCode::FAILURE
+    })
+}
+
+/// `count` followed by `noun`, with an s unless there is one.
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {noun}"),
+        count => format!("{count} {noun}s"),
+    }
+}
+
+/// `warning` as `file: line N: warning: ...`, leaving out what isn't known.
+fn warning_line(warning: &Warning) -> String {
+    let mut line = String::new();
+    if let Some(file) = &warning.file {
+        line += &format!("{}: ", file.display());
+    }
+    match (&warning.kind, warning.line) {
+        // The parse error already says which line it is on.
+        (WarningKind::SkippedLine(_), _) | (_, None) => {}
+        (_, Some(number)) => line += &format!("line {number}: "),
+    }
+    line + &format!("warning: {}", warning.kind)
+}
+
+/// `diagnostic` after the file and line its path was written on, if that's known.
+fn diagnostic_line(outcome: &ParseOutcome, diagnostic: &Diagnostic) -> String {
+    match outcome.origin(&diagnostic.path) {
+        Some(origin) if origin.file.is_some() || origin.line.is_some() => {
+            format!("{origin}: {diagnostic}")
+        }
+        _ => diagnostic.to_string(),
+    }
+}
+
+/// The sinks of `config`, or with `dry_run` stand-ins for them writing to standard error.
+fn notifier(config: &Config, dry_run: bool) -> Result<Notifier, SinkError> {
+    if dry_run {
+        Ok(Notifier::dry_run(config))
+    } else {
+        Notifier::from_config(config)
+    }
+}
+
+/// What carries the actions out, printing what they would run with `dry_run`.
+fn runner(dry_run: bool) -> Runner {
+    if dry_run {
+        Runner::DryRun(DryRunner)
+    } else {
+        Runner::Command(CommandRunner)
+    }
+}
+
+/// `path` made absolute, or left as it is if the working directory is gone.
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Opens the control socket at `path`, with the channel its commands arrive on.
+#[cfg(unix)]
+fn listen(path: &Path) -> io::Result<(ControlServer, Receiver<ControlRequest>)> {
+    let server = ControlServer::bind(path)?;
+    let requests = server.spawn()?;
+    Ok((server, requests))
+}
+
+/// Detaches from the terminal, see [`overwatch::detach`].
+#[cfg(unix)]
+fn daemonize(config: &Config) -> io::Result<()> {
+    let log = config.logging().file.as_deref();
+    if log.is_none() {
+        eprintln!("overwatch: no log_file is set, so output is discarded once detached");
+    }
+    overwatch::detach(log)
+}
+
+#[cfg(not(unix))]
+fn daemonize(_config: &Config) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "not supported on this platform, run overwatch as a service instead",
+    ))
+}
+
+/// Everything events are handed to once the configuration is loaded.
+struct Daemon {
+    /// The configuration in use: the file's, with the includes added and removed over the
+    /// control socket since it was last loaded.
+    config: Config,
+    /// The profile selected from the file, each time it's loaded.
+    profile: Option<String>,
+    reloader: ConfigReloader,
+    notifier: Notifier,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+    #[cfg(unix)]
+    systemd: SdNotify,
+    signals: Signals,
+    #[cfg(unix)]
+    control: Option<(ControlServer, Receiver<ControlRequest>)>,
+    /// While paused, how many events were dropped since.
+    paused: Option<u64>,
+    /// Whether `--dry-run` was given, so events are logged and nothing is acted on.
+    dry_run: bool,
+    /// What drops repeated events, if `dedupe` is set.
+    dedupe: Option<Deduplicator>,
+    /// What holds events back during a storm, if `storm_limit` is set.
+    storm: Option<StormGuard>,
+    /// What collects the records handed to the sinks and the store into batches, if `batch`
+    /// is set.
+    batch: Option<Batcher<EventRecord>>,
+    /// What the watched paths were like as overwatch started, if `state_file` is set.
+    state: Option<WatchState>,
+    /// What checks the watched paths against the `integrity` database, if there is one.
+    integrity: Option<IntegrityMonitor>,
+    /// Where events and how actions went are kept, if there's a `store`.
+    #[cfg(feature = "sqlite")]
+    store: Option<Arc<Store>>,
+}
+
+/// What carries actions out: their commands, or with `--dry-run` a line saying what would
+/// run.
+#[derive(Debug, Clone, Copy)]
+enum Runner {
+    Command(CommandRunner),
+    DryRun(DryRunner),
+}
+
+impl ActionRunner for Runner {
+    fn run(&self, action: &Action, event: &Event) -> Result<ActionOutput, ActionError> {
+        match self {
+            Runner::Command(runner) => runner.run(action, event),
+            Runner::DryRun(runner) => runner.run(action, event),
+        }
+    }
+}
+
+/// How long each read from the watcher waits at most, so signals are acted on soon after they
+/// arrive and the probes hear from the event loop while nothing changes.
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often the configuration file is checked for changes, which are reloaded as if
+/// overwatch had been sent `SIGHUP`.
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The settings which are only read as overwatch starts, so a reload changing them is
+/// reported as needing a restart.
+const READ_AT_START: [&str; 4] = ["log_level", "log_file", "poll_interval", "follow_symlinks"];
+
+/// The watchers events go through before they're debounced, built for the configuration.
+trait Pipeline: Watcher {
+    fn filtered(&self) -> &Filtered<AutoWatcher>;
+
+    /// Filters later events by a reloaded configuration.
+    fn set_config(&mut self, config: &Config);
+}
+
+impl Pipeline for Filtered<AutoWatcher> {
+    fn filtered(&self) -> &Filtered<AutoWatcher> {
+        self
+    }
+
+    fn set_config(&mut self, config: &Config) {
+        Filtered::set_config(self, config);
+    }
+}
+
+impl Pipeline for Verified<Filtered<AutoWatcher>> {
+    fn filtered(&self) -> &Filtered<AutoWatcher> {
+        self.get_ref()
+    }
+
+    fn set_config(&mut self, config: &Config) {
+        Verified::set_config(self, config);
+        self.get_mut().set_config(config);
+    }
+}
+
+impl Daemon {
+    /// Watches the configuration's includes until the watcher fails, or a signal says to
+    /// stop.
+    fn run(&mut self) -> Result<(), WatchError> {
+        let config = self.config.clone();
+        let watcher = {
+            let _span = tracing::info_span!("register").entered();
+            Filtered::new(AutoWatcher::new(&config)?, &config)
+        };
+        self.set_ready(watcher.get_ref().includes().len());
+        if VerifyDirective::enabled(&config) {
+            self.watch(Debounced::new(Verified::new(watcher, &config), &config))
+        } else {
+            self.watch(Debounced::new(watcher, &config))
+        }
+    }
+
+    /// Marks overwatch as ready, with every one of `watches` includes watched.
+    fn set_ready(&self, watches: usize) {
+        self.metrics.set_watches(watches);
+        self.health.set_ready(watches);
+        #[cfg(unix)]
+        if let Err(err) = self.systemd.ready(&format!("watching {watches} includes")) {
+            tracing::warn!("failed to tell systemd: {err}");
+        }
+    }
+
+    /// Dispatches the events of `watcher`, and hands them to the notifier, until it fails or
+    /// a signal says to stop.
+    fn watch<W: Pipeline>(&mut self, mut watcher: Debounced<W>) -> Result<(), WatchError> {
+        if self.dry_run {
+            tracing::warn!(
+                "dry run: actions are printed rather than run, and sinks write to stderr"
+            );
+        }
+        let mut dispatcher = Dispatcher::new(&self.config, runner(self.dry_run));
+        let failures = dispatcher.subscribe_failures();
+        dispatcher.set_metrics(self.metrics.clone());
+        #[cfg(feature = "sqlite")]
+        if let Some(store) = &self.store {
+            dispatcher.set_store(store.clone());
+        }
+        let queue = ActionQueue::new(dispatcher, Limits::from_config(&self.config));
+        tracing::info!("watching");
+        self.scan_at_start(&queue);
+        self.check_integrity();
+        #[cfg(unix)]
+        let watchdog = self.systemd.watchdog_interval();
+        #[cfg(not(unix))]
+        let watchdog: Option<Duration> = None;
+        let timeout = watchdog.map_or(READ_TIMEOUT, |interval| interval.min(READ_TIMEOUT));
+        let mut pinged = Instant::now();
+        let mut checked = Instant::now();
+        loop {
+            if self.answer_signal(&mut watcher, &queue) {
+                self.shutdown(watcher, queue, &failures);
+                return Ok(());
+            }
+            if checked.elapsed() >= CONFIG_CHECK_INTERVAL {
+                self.reload_if_changed(&mut watcher, &queue);
+                checked = Instant::now();
+            }
+            #[cfg(unix)]
+            self.answer_requests(&mut watcher, &queue);
+            // Batches are handed on as their delay passes, though nothing else arrives.
+            let due = self.batch.as_ref().and_then(Batcher::due);
+            let wait = due.map_or(timeout, |due| {
+                timeout.min(due.saturating_duration_since(Instant::now()))
+            });
+            let read = watcher.read_events_timeout(Some(wait));
+            self.health.record_read();
+            // The watchdog is only fed while reads return, so a wedged watcher gets restarted.
+            if watchdog.is_some_and(|interval| pinged.elapsed() >= interval) {
+                #[cfg(unix)]
+                if let Err(err) = self.systemd.watchdog() {
+                    tracing::warn!("failed to ping the watchdog: {err}");
+                }
+                pinged = Instant::now();
+            }
+            match read {
+                Ok(events) => {
+                    self.handle(&events, &queue);
+                    self.hand_on_due(false);
+                    if let Some(monitor) = &self.integrity {
+                        if monitor.is_due(Instant::now()) {
+                            self.check_integrity();
+                        }
+                    }
+                }
+                Err(WatchError::Overflow) => {
+                    self.metrics.record_overflow();
+                    tracing::warn!("{}", WatchError::Overflow);
+                }
+                Err(err) => return Err(err),
+            }
+            self.report_failures(&failures);
+            self.metrics
+                .set_filter_counts(watcher.get_ref().filtered().counts());
+        }
+    }
+
+    /// Acts on the signal which arrived since the last call, reloading the configuration on
+    /// `SIGHUP`. Returns whether the signal asks overwatch to stop.
+    fn answer_signal<W: Pipeline>(
+        &mut self,
+        watcher: &mut Debounced<W>,
+        queue: &ActionQueue<Runner>,
+    ) -> bool {
+        match self.signals.take() {
+            Some(Signal::Reload) => {
+                let _ = self.reload(watcher, queue);
+                false
+            }
+            Some(Signal::Shutdown) => true,
+            None => false,
+        }
+    }
+
+    /// Scans the watched paths as overwatch starts, if there's a `state_file`, or a baseline
+    /// in the `baseline_file` or the store, to compare them against. What changed since the
+    /// state was saved, as overwatch last stopped, is acted on, and the state saved again.
+    /// Where the paths deviate from the baseline is logged and handed to the sinks.
+    fn scan_at_start(&mut self, queue: &ActionQueue<Runner>) {
+        let state_file = StateFileDirective::path(&self.config).map(Path::to_path_buf);
+        let baseline = match BaselineFileDirective::path(&self.config) {
+            Some(path) => {
+                let baseline = load_state(path);
+                if baseline.is_none() {
+                    tracing::warn!(
+                        "no baseline is recorded in {}, run overwatch --baseline",
+                        path.display()
+                    );
+                }
+                baseline
+            }
+            None => self.stored_baseline(),
+        };
+        if state_file.is_none() && baseline.is_none() {
+            return;
+        }
+        let saved = state_file.as_deref().and_then(load_state);
+        let state = scan_at_start(&self.config, saved.as_ref(), baseline.is_some());
+        let filter = Filter::new(&self.config);
+        let changes = |before: &WatchState| -> Vec<Event> {
+            before
+                .changes(&state)
+                .into_iter()
+                .filter(|event| filter.check(event).is_none())
+                .collect()
+        };
+
+        if let Some(baseline) = &baseline {
+            let _span = tracing::info_span!("baseline").entered();
+            let deviations = changes(baseline);
+            for event in &deviations {
+                let record = EventRecord::new(event, &self.config);
+                tracing::warn!("deviates from the baseline: {}", record.summary());
+                self.hand_on(record);
+            }
+            tracing::info!("{} deviations from the baseline", deviations.len());
+        }
+        if let Some(path) = &state_file {
+            let _span = tracing::info_span!("catch_up", path = %path.display()).entered();
+            save_state(&state, path);
+            if let Some(saved) = &saved {
+                let events = changes(saved);
+                tracing::info!(
+                    "caught up on {} changes made while overwatch was down",
+                    events.len()
+                );
+                self.handle(&events, queue);
+            }
+        }
+        self.state = Some(state);
+    }
+
+    /// The baseline recorded in the store, if there is one.
+    fn stored_baseline(&self) -> Option<WatchState> {
+        #[cfg(feature = "sqlite")]
+        if let Some(store) = &self.store {
+            match store.baseline() {
+                Ok(baseline) => return baseline,
+                Err(err) => tracing::error!("failed to read the baseline from the store: {err}"),
+            }
+        }
+        None
+    }
+
+    /// Hands the actions which failed for good since the last call to the sinks and the store,
+    /// each as a record of the event it ran for.
+    fn report_failures(&mut self, failures: &Receiver<ActionFailure>) {
+        for failure in failures.try_iter() {
+            let record = EventRecord::failure(&failure, &self.config);
+            self.hand_on(record);
+        }
+    }
+
+    /// Hands `record` to the sinks which take it and keeps it in the store's history, if
+    /// there is a store. With `batch` it's added to the batch instead, which is handed on
+    /// once it's full.
+    fn hand_on(&mut self, record: EventRecord) {
+        let Some(batcher) = &mut self.batch else {
+            self.notifier.notify(&record);
+            self.keep(std::slice::from_ref(&record));
+            return;
+        };
+        if let Some(batch) = batcher.push(record, Instant::now()) {
+            self.hand_on_batch(&batch);
+        }
+    }
+
+    /// Hands on the batch collected so far once its `max_delay` has passed, or with `all`
+    /// whatever it holds.
+    fn hand_on_due(&mut self, all: bool) {
+        let Some(batcher) = &mut self.batch else {
+            return;
+        };
+        let batch = if all {
+            batcher.flush()
+        } else {
+            batcher.poll(Instant::now()).unwrap_or_default()
+        };
+        if !batch.is_empty() {
+            self.hand_on_batch(&batch);
+        }
+    }
+
+    /// Hands `batch` to the sinks as one batch each, and keeps it in the store together.
+    fn hand_on_batch(&self, batch: &[EventRecord]) {
+        self.notifier.notify_batch(batch);
+        self.keep(batch);
+    }
+
+    /// Keeps `records` in the store's history, if there is a store.
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    fn keep(&self, records: &[EventRecord]) {
+        #[cfg(feature = "sqlite")]
+        if let Some(store) = &self.store {
+            if let Err(err) = store.record_events(records) {
+                let kept = match records {
+                    [record] => record.summary(),
+                    _ => plural(records.len(), "event"),
+                };
+                tracing::error!("failed to keep {kept} in the store: {err}");
+            }
+        }
+    }
+
+    /// Loads the `integrity` database to check the watched paths against, if there is one.
+    /// A database which isn't signed with the key, or whose signature doesn't match, is
+    /// reported as tampered with, and fails unless it's `untrusted=continue`.
+    fn start_integrity(&mut self) -> Result<(), IntegrityError> {
+        let Some(integrity) = IntegrityConfig::from_config(&self.config) else {
+            return Ok(());
+        };
+        let path = integrity.database.display();
+        let database = integrity
+            .signing_key()
+            .and_then(|key| load_database(&integrity.database, key.as_ref()));
+        match database {
+            Ok(database) => {
+                let now = Instant::now();
+                self.integrity = Some(IntegrityMonitor::new(database, integrity.interval, now));
+            }
+            Err(IntegrityError::Io(err)) if err.kind() == io::ErrorKind::NotFound => {
+                tracing::warn!(
+                    "no integrity database is recorded in {path}, run overwatch --baseline"
+                )
+            }
+            Err(err @ (IntegrityError::Unsigned | IntegrityError::BadSignature)) => {
+                self.report_tamper(Tamper {
+                    path: integrity.database.clone(),
+                    tampered: vec![Tampering::Signature],
+                });
+                if !integrity.continue_untrusted {
+                    tracing::error!("{path}: {err}, set untrusted=continue to run without it");
+                    return Err(err);
+                }
+                tracing::error!("not checking integrity against {path}: {err}");
+            }
+            Err(err) => tracing::error!("not checking integrity against {path}: {err}"),
+        }
+        Ok(())
+    }
+
+    /// Checks every watched path against the integrity database, reporting what was tampered
+    /// with.
+    fn check_integrity(&mut self) {
+        let Some(monitor) = &mut self.integrity else {
+            return;
+        };
+        let _span = tracing::info_span!("integrity").entered();
+        let tampers = monitor.check_all(&self.config, Instant::now());
+        tracing::debug!("checked integrity, {} paths tampered with", tampers.len());
+        for tamper in tampers {
+            self.report_tamper(tamper);
+        }
+    }
+
+    /// Logs `tamper` and hands it to the sinks.
+    fn report_tamper(&mut self, tamper: Tamper) {
+        let mut record = EventRecord::new(&tamper.event(), &self.config);
+        record.tampered = tamper.tampered;
+        tracing::warn!("tampered with: {}", record.summary());
+        self.metrics.record_tampering();
+        self.hand_on(record);
+    }
+
+    /// Queues the actions of `events` and hands them to the notifier, unless paused, repeats
+    /// within the `dedupe` window or suppressed during an event storm.
+    fn handle(&mut self, events: &[Event], queue: &ActionQueue<Runner>) {
+        if let Some(dropped) = &mut self.paused {
+            *dropped += events.len() as u64;
+            return;
+        }
+        if let Some(monitor) = &mut self.integrity {
+            let paths = events
+                .iter()
+                .flat_map(|event| iter::once(&event.path).chain(&event.from));
+            let tampers: Vec<_> = paths
+                .filter_map(|path| monitor.check_path(&self.config, path))
+                .collect();
+            for tamper in tampers {
+                self.report_tamper(tamper);
+            }
+        }
+        let admitted: Vec<Event>;
+        let events = match &mut self.dedupe {
+            Some(dedupe) => {
+                let now = Instant::now();
+                admitted = events
+                    .iter()
+                    .filter(|event| dedupe.admit(event, now))
+                    .cloned()
+                    .collect();
+                self.metrics
+                    .record_duplicates(events.len() - admitted.len());
+                &admitted[..]
+            }
+            None => events,
+        };
+        if let Some(storm) = &mut self.storm {
+            for report in storm.tick(Instant::now()) {
+                match report {
+                    StormReport::Ended { .. } => tracing::info!("{report}"),
+                    _ => tracing::warn!("{report}"),
+                }
+            }
+            if !storm.admit(events) {
+                self.metrics.record_suppressed(events.len());
+                return;
+            }
+        }
+        for event in events {
+            queue.submit(event);
+            let record = EventRecord::new(event, &self.config);
+            if self.dry_run {
+                tracing::info!("{}", record.summary());
+            }
+            self.metrics.record_event(&record);
+            self.hand_on(record);
+        }
+    }
+
+    /// Has later events watched, filtered, debounced and dispatched by `self.config`, once its
+    /// includes changed.
+    fn update<W: Pipeline>(&self, watcher: &mut Debounced<W>, queue: &ActionQueue<Runner>) {
+        watcher.set_config(&self.config);
+        watcher.get_mut().set_config(&self.config);
+        queue.set_config(&self.config);
+        self.set_ready(watcher.get_ref().filtered().get_ref().includes().len());
+    }
+
+    /// Loads the configuration file again and applies what changed, keeping the
+    /// configuration in use if the file doesn't load. Returns what became of it.
+    fn reload<W: Pipeline>(
+        &mut self,
+        watcher: &mut Debounced<W>,
+        queue: &ActionQueue<Runner>,
+    ) -> Result<String, String> {
+        let path = self.reloader.path().display().to_string();
+        let _span = tracing::info_span!("reload", path).entered();
+        tracing::info!("reloading {path}");
+        let reloaded = self.reloader.reload();
+        self.apply_reload(reloaded, watcher, queue)
+    }
+
+    /// Reloads the configuration if its file's modification time or size changed since it
+    /// was last loaded.
+    fn reload_if_changed<W: Pipeline>(
+        &mut self,
+        watcher: &mut Debounced<W>,
+        queue: &ActionQueue<Runner>,
+    ) {
+        let reloaded = self.reloader.reload_if_changed();
+        if matches!(reloaded, Ok(None)) {
+            return;
+        }
+        let path = self.reloader.path().display().to_string();
+        let _span = tracing::info_span!("reload", path).entered();
+        tracing::info!("{path} changed, reloading it");
+        let _ = self.apply_reload(reloaded, watcher, queue);
+    }
+
+    /// Applies what reloading the configuration file gave, returning what came of it.
+    fn apply_reload<W: Pipeline>(
+        &mut self,
+        reloaded: Result<Option<Reload>, Arc<ConfigError>>,
+        watcher: &mut Debounced<W>,
+        queue: &ActionQueue<Runner>,
+    ) -> Result<String, String> {
+        let path = self.reloader.path().display().to_string();
+        #[cfg(unix)]
+        if let Err(err) = self.systemd.reloading() {
+            tracing::warn!("failed to tell systemd: {err}");
+        }
+        let selected = match reloaded {
+            Ok(Some(reload)) => select(&reload.config, self.profile.as_deref()).map(Some),
+            Ok(None) => Ok(None),
+            Err(err) => Err(err.to_string()),
+        };
+        let result = match selected {
+            Ok(Some(config)) => {
+                self.apply(config, watcher);
+                Ok(format!("reloaded {path}"))
+            }
+            Ok(None) => Ok("the configuration is unchanged".to_string()),
+            Err(err) => Err(format!(
+                "failed to reload, keeping the configuration in use: {err}"
+            )),
+        };
+        match &result {
+            Ok(outcome) => tracing::info!("{outcome}"),
+            Err(err) => tracing::error!("{err}"),
+        }
+        self.update(watcher, queue);
+        result
+    }
+
+    /// Puts the reloaded `config` in use: includes are watched and unwatched as they differ
+    /// from those in use, sinks are started again if they changed, and settings which can't
+    /// change while running are reported.
+    fn apply<W: Pipeline>(&mut self, config: Config, watcher: &mut Debounced<W>) {
+        let old = std::mem::replace(&mut self.config, config);
+        let policy = BatchPolicy::from_config(&self.config);
+        if self.batch.as_ref().map(Batcher::policy) != policy {
+            // What was collected under the old policy goes out before the new one applies.
+            self.hand_on_due(true);
+            self.batch = policy.map(Batcher::new);
+        }
+        let config = &self.config;
+        // An include which changed is removed and added again, with its new options. Those
+        // in use are compared against, so includes added and removed over the control socket
+        // are put back as the file has them. Watch groups' includes are among them.
+        let (old_entries, entries) = (watch_entries(&old), watch_entries(config));
+        for entry in difference(&old_entries, &entries) {
+            if let Err(err) = watcher.remove(&entry.path) {
+                tracing::error!("failed to stop watching {}: {err}", entry.path);
+            }
+        }
+        for entry in difference(&entries, &old_entries) {
+            if let Err(err) = watcher.add(entry.clone()) {
+                tracing::error!("failed to watch {}: {err}", entry.path);
+            }
+        }
+
+        let window = DedupeDirective::window(config);
+        if self.dedupe.as_ref().map(Deduplicator::window) != window {
+            self.dedupe = window.map(Deduplicator::new);
+        }
+        let limit = StormLimit::from_config(config);
+        if self.storm.as_ref().map(StormGuard::limit) != limit {
+            self.storm = limit.map(|limit| StormGuard::new(limit, Instant::now()));
+        }
+
+        let diff = Config::diff(&old, config);
+        let changed = |setting| diff.changed_settings.contains(&setting);
+        if changed("custom") || changed("output") {
+            match notifier(config, self.dry_run) {
+                Ok(notifier) => self.notifier = notifier,
+                Err(err) => tracing::error!("failed to start a sink, keeping those in use: {err}"),
+            }
+        }
+        let mut restart: Vec<_> = READ_AT_START
+            .into_iter()
+            .filter(|setting| changed(setting))
+            .collect();
+        for name in [
+            MaxConcurrentDirective::NAME,
+            HttpListenDirective::NAME,
+            IntegrityDirective::NAME,
+            LogFormatDirective::NAME,
+            StoreDirective::NAME,
+            VerifyDirective::NAME,
+        ] {
+            if custom_args(&old, name) != custom_args(config, name) {
+                restart.push(name);
+            }
+        }
+        for line in diff.to_string().lines() {
+            tracing::info!("{line}");
+        }
+        if !restart.is_empty() {
+            tracing::warn!(
+                "restart overwatch to apply the changes to {}",
+                restart.join(", ")
+            );
+        }
+    }
+
+    /// Carries out the commands which came in over the control socket since last time.
+    #[cfg(unix)]
+    fn answer_requests<W: Pipeline>(
+        &mut self,
+        watcher: &mut Debounced<W>,
+        queue: &ActionQueue<Runner>,
+    ) {
+        let Some((_, requests)) = &self.control else {
+            return;
+        };
+        let requests: Vec<_> = requests.try_iter().collect();
+        for request in requests {
+            tracing::info!("control socket: {}", request.command);
+            let reply = self.carry_out(&request.command, watcher, queue);
+            if let Err(err) = &reply {
+                tracing::warn!("control socket: {err}");
+            }
+            request.answer(reply);
+        }
+    }
+
+    #[cfg(unix)]
+    fn carry_out<W: Pipeline>(
+        &mut self,
+        command: &ControlCommand,
+        watcher: &mut Debounced<W>,
+        queue: &ActionQueue<Runner>,
+    ) -> ControlReply {
+        match command {
+            ControlCommand::Add(include) => {
+                let parsed: Config = format!("include {include}")
+                    .parse()
+                    .map_err(|err: ConfigError| err.to_string())?;
+                let mut added = Vec::new();
+                for entry in parsed.includes() {
+                    let result = watcher.add(entry.clone());
+                    if let Err(err) = result {
+                        self.update(watcher, queue);
+                        return Err(format!("failed to watch {}: {err}", entry.path));
+                    }
+                    self.config.add_include(entry.clone());
+                    added.push(entry.to_string());
+                }
+                self.update(watcher, queue);
+                Ok(added.into())
+            }
+            ControlCommand::Remove(path) => {
+                watcher.remove(path).map_err(|err| err.to_string())?;
+                self.config.remove_include(path);
+                self.update(watcher, queue);
+                Ok(format!("stopped watching {path}").into())
+            }
+            ControlCommand::List => {
+                let includes = self.config.includes().iter();
+                Ok(includes.map(ToString::to_string).collect::<Vec<_>>().into())
+            }
+            ControlCommand::Pause => {
+                self.paused.get_or_insert(0);
+                Ok("paused".into())
+            }
+            ControlCommand::Resume => match self.paused.take() {
+                Some(dropped) => {
+                    Ok(format!("resumed, {dropped} events were dropped while paused").into())
+                }
+                None => Err("not paused".to_string()),
+            },
+            ControlCommand::Stats => {
+                let mut stats = self.metrics.to_json();
+                stats["paused"] = self.paused.is_some().into();
+                Ok(stats)
+            }
+            ControlCommand::Reload => self.reload(watcher, queue).map(Value::from),
+        }
+    }
+
+    /// Hands on the events the debouncer holds, stops watching, and gives the actions
+    /// queued and running until the configuration's `shutdown_timeout` to finish. The actions
+    /// which failed meanwhile are reported once they have.
+    fn shutdown<W: Pipeline>(
+        &mut self,
+        mut watcher: Debounced<W>,
+        queue: ActionQueue<Runner>,
+        failures: &Receiver<ActionFailure>,
+    ) {
+        tracing::info!("stopping");
+        #[cfg(unix)]
+        if let Err(err) = self.systemd.stopping() {
+            tracing::warn!("failed to tell systemd: {err}");
+        }
+        let held = watcher.flush();
+        drop(watcher);
+        self.handle(&held, &queue);
+        if let Some(path) = StateFileDirective::path(&self.config) {
+            let state = WatchState::scan(&self.config, self.state.as_ref());
+            save_state(&state, path);
+        }
+        let timeout = ShutdownTimeoutDirective::timeout(&self.config);
+        if !queue.shutdown(timeout) {
+            tracing::warn!("stopped waiting for actions to finish after {timeout:?}");
+        }
+        self.report_failures(failures);
+        self.hand_on_due(true);
+    }
+}
+
+/// Scans the paths `config` watches as overwatch starts, reusing the digests `saved` has for
+/// files which look unchanged. Compared against a baseline every file is hashed again, since
+/// an edit of the same size whose modification time was put back would go unseen otherwise.
+fn scan_at_start(config: &Config, saved: Option<&WatchState>, baseline: bool) -> WatchState {
+    WatchState::scan(config, if baseline { None } else { saved })
+}
+
+/// Scans the paths `config` watches and records them in its `baseline_file`, its integrity
+/// database and its store.
+fn baseline(config: &Config) -> ExitCode {
+    let baseline_file = BaselineFileDirective::path(config);
+    let integrity = IntegrityConfig::from_config(config);
+    let store = StoreConfig::from_config(config);
+    if baseline_file.is_none() && integrity.is_none() && store.is_none() {
+        eprintln!(
+            "overwatch: declare a baseline_file, an integrity database or a store to record the \
+             baseline in"
+        );
+        return ExitCode::FAILURE;
+    }
+    let state = WatchState::scan(config, None);
+    if let Some(path) = baseline_file {
+        if let Err(err) = state.save(path) {
+            eprintln!("overwatch: failed to write {}: {err}", path.display());
+            return ExitCode::FAILURE;
+        }
+        println!(
+            "{}: recorded the baseline of {} paths",
+            path.display(),
+            state.len()
+        );
+    }
+    if let Some(integrity) = integrity {
+        let path = integrity.database.display();
+        let key = match integrity.signing_key() {
+            Ok(key) => key,
+            Err(err) => {
+                eprintln!("overwatch: {err}");
+                return ExitCode::FAILURE;
+            }
+        };
+        if let Err(err) = record_database(&state, &integrity.database, key.as_ref()) {
+            eprintln!("overwatch: failed to write {path}: {err}");
+            return ExitCode::FAILURE;
+        }
+        let signed = if key.is_some() { ", signed" } else { "" };
+        println!(
+            "{path}: recorded the integrity of {} paths{signed}",
+            state.len()
+        );
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(store) = store {
+        let path = store.path.display();
+        let recorded = Store::open(&store).and_then(|store| store.record_baseline(&state));
+        if let Err(err) = recorded {
+            eprintln!("overwatch: failed to record the baseline in {path}: {err}");
+            return ExitCode::FAILURE;
+        }
+        println!("{path}: recorded the baseline of {} paths", state.len());
+    }
+    ExitCode::SUCCESS
+}
+
+/// Parses the duration of `--since`, such as `24h`.
+#[cfg(feature = "sqlite")]
+fn parse_since(since: &str) -> Result<Duration, String> {
+    configuration::parse_duration(since)
+        .ok_or_else(|| format!("expected a duration such as 30m, 24h or 7d, found {since:?}"))
+}
+
+/// Prints what `query` picks from the store of `config`, its events or with `actions` how the
+/// actions went, a line each or as JSON.
+#[cfg(feature = "sqlite")]
+fn query(
+    config: Result<Config, String>,
+    query: &StoreQuery,
+    actions: bool,
+    json: bool,
+) -> ExitCode {
+    let found = config.and_then(|config| {
+        let declared = StoreConfig::from_config(&config)
+            .ok_or_else(|| "the configuration declares no store".to_string())?;
+        let path = declared.path.display();
+        let store = Store::open_read_only(&declared.path)
+            .map_err(|err| format!("failed to open the store {path}: {err}"))?;
+        let found: Result<Vec<_>, _> = if actions {
+            store.actions(query).map(|actions| {
+                let found = actions.iter();
+                found
+                    .map(|action| (action.timestamp, action.summary(), action.to_json()))
+                    .collect()
+            })
+        } else {
+            store.events(query).map(|events| {
+                let found = events.iter();
+                found
+                    .map(|event| (event.timestamp, event.summary(), event.to_json()))
+                    .collect()
+            })
+        };
+        found.map_err(|err| format!("failed to query the store {path}: {err}"))
+    });
+    let found = match found {
+        Ok(found) => found,
+        Err(err) => {
+            eprintln!("overwatch: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if json {
+        let found: Vec<_> = found.into_iter().map(|(_, _, json)| json).collect();
+        println!("{:#}", serde_json::Value::from(found));
+        return ExitCode::SUCCESS;
+    }
+    for (timestamp, summary, _) in found {
+        println!("{} {summary}", rfc3339(timestamp));
+    }
+    ExitCode::SUCCESS
+}
+
+/// The state saved at `path`, if it can be read.
+fn load_state(path: &Path) -> Option<WatchState> {
+    WatchState::load(path).unwrap_or_else(|err| {
+        tracing::warn!("failed to read {}: {err}", path.display());
+        None
+    })
+}
+
+fn save_state(state: &WatchState, path: &Path) {
+    match state.save(path) {
+        Ok(()) => tracing::debug!("saved the state of {} paths", state.len()),
+        Err(err) => tracing::error!("failed to save the state to {}: {err}", path.display()),
+    }
+}
+
+/// The entries of `a` which aren't in `b`.
+fn difference(a: &[WatchEntry], b: &[WatchEntry]) -> Vec<WatchEntry> {
+    a.iter()
+        .filter(|entry| !b.contains(entry))
+        .cloned()
+        .collect()
+}
+
+/// The arguments of each `name` directive of `config`, as written.
+fn custom_args<'a>(config: &'a Config, name: &str) -> Vec<&'a str> {
+    config
+        .custom()
+        .iter()
+        .filter(|directive| directive.name() == name)
+        .map(|directive| directive.args())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use overwatch::{Notify, Sink};
+
+    use super::*;
+
+    /// The watcher and queue a daemon's events go through.
+    type Watching = (Debounced<Filtered<AutoWatcher>>, ActionQueue<Runner>);
+
+    /// Parses `input` with overwatch's own directives.
+    fn parse(input: &str) -> Config {
+        let mut options = ParseOptions::default();
+        overwatch::register_directives(&mut options.directives);
+        Config::parse_with(input, &options).unwrap()
+    }
+
+    /// A daemon for the configuration file at `path`, dry running its actions, and what it
+    /// watches the configuration's includes with.
+    fn daemon(path: &Path) -> (Daemon, Watching) {
+        let mut options = ParseOptions::default();
+        overwatch::register_directives(&mut options.directives);
+        let config = Config::from_file_with(path, &options).unwrap();
+        let watcher = Filtered::new(AutoWatcher::new(&config).unwrap(), &config);
+        let watcher = Debounced::new(watcher, &config);
+        let dispatcher = Dispatcher::new(&config, runner(true));
+        let queue = ActionQueue::new(dispatcher, Limits::from_config(&config));
+        let daemon = Daemon {
+            config: config.clone(),
+            profile: None,
+            reloader: ConfigReloader::with_config(path, options, config),
+            notifier: Notifier::new(),
+            metrics: Arc::new(Metrics::new()),
+            health: Arc::new(Health::new()),
+            #[cfg(unix)]
+            systemd: SdNotify::from_env().unwrap(),
+            signals: Signals::install().unwrap(),
+            #[cfg(unix)]
+            control: None,
+            paused: None,
+            dry_run: true,
+            dedupe: None,
+            storm: None,
+            batch: None,
+            state: None,
+            integrity: None,
+            #[cfg(feature = "sqlite")]
+            store: None,
+        };
+        (daemon, (watcher, queue))
+    }
+
+    /// Whether writing to `file` comes out of `watcher` as an event for it.
+    fn notices<W: Watcher>(watcher: &mut W, file: &Path) -> bool {
+        std::fs::write(file, "changed").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            let events = watcher.read_events_timeout(Some(Duration::from_millis(100)));
+            if events.unwrap().iter().any(|event| event.path == file) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Keeps the summaries of the records it's sent.
+    struct Summaries(Arc<Mutex<Vec<String>>>);
+
+    impl Sink for Summaries {
+        fn send(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+            self.0.lock().unwrap().push(record.summary());
+            Ok(())
+        }
+    }
+
+    /// The includes `watcher` has, those of watch groups among them.
+    fn watched(watcher: &Debounced<Filtered<AutoWatcher>>) -> Vec<PathSpec> {
+        let includes = watcher.get_ref().get_ref().includes();
+        includes.into_iter().map(|entry| entry.path).collect()
+    }
+
+    #[test]
+    fn validates_config_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().display();
+        let test_cases = vec![
+            (
+                format!("include {root}"),
+                None,
+                ExitCode::SUCCESS,
+                "ok, 0 warnings",
+            ),
+            (
+                format!("include {root}\ninclude {root}"),
+                None,
+                ExitCode::SUCCESS,
+                "ok, 1 warning",
+            ),
+            (
+                format!("include {root}/missing"),
+                None,
+                ExitCode::FAILURE,
+                "1 error, 0 warnings",
+            ),
+            (
+                format!("include {root} on_change \"echo `{{path}}`\""),
+                None,
+                ExitCode::FAILURE,
+                "1 error, 0 warnings",
+            ),
+            (
+                format!("include {root}"),
+                Some("web"),
+                ExitCode::FAILURE,
+                "error: the configuration declares no profile web",
+            ),
+            ("include".to_string(), None, ExitCode::FAILURE, "error: "),
+        ];
+        let file = dir.path().join("config");
+        for (input, profile, expected, last) in test_cases {
+            std::fs::write(&file, &input).unwrap();
+            let mut options = ParseOptions::default();
+            overwatch::register_directives(&mut options.directives);
+            let mut out = Vec::new();
+            let code = validate(Some(file.clone()), profile, &options, &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert_eq!(code, expected, "{input}: {out}");
+            let line = out.lines().last().unwrap();
+            assert!(
+                line.starts_with(&format!("{}: ", file.display())) && line.contains(last),
+                "{input}: {out}"
+            );
+        }
+
+        let mut out = Vec::new();
+        let missing = Some(dir.path().join("missing"));
+        let code = validate(missing, None, &ParseOptions::default(), &mut out).unwrap();
+        assert_eq!(code, ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn lists_the_watched_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("etc");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::create_dir(root.join("nginx")).unwrap();
+        std::fs::create_dir(root.join("ssl")).unwrap();
+        std::fs::write(root.join("passwd"), "").unwrap();
+        let config = dir.path().join("config");
+        std::fs::write(
+            &config,
+            format!(
+                "include -r {root}\nexclude {root}/ssl\ninclude {root}/passwd\ninclude {root}/missing",
+                root = root.display()
+            ),
+        )
+        .unwrap();
+        let options = ParseOptions::default();
+
+        let mut out = Vec::new();
+        let code = list(Some(&config), None, &options, false, &mut out).unwrap();
+        assert_eq!(code, ExitCode::SUCCESS);
+        let paths: Vec<_> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(
+            paths,
+            watcher::watch_paths(&Config::from_file(&config).unwrap())
+        );
+        assert!(paths.contains(&root.join("nginx")));
+        assert!(!paths.contains(&root.join("ssl")));
+
+        let mut out = Vec::new();
+        let code = list(Some(&config), None, &options, true, &mut out).unwrap();
+        assert_eq!(code, ExitCode::SUCCESS);
+        let listed: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let kind = |path: &Path| {
+            let path = path.display().to_string();
+            listed
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|listed| listed["path"] == path.as_str())
+                .map(|listed| listed["type"].clone())
+        };
+        assert_eq!(kind(&root), Some("directory".into()));
+        assert_eq!(kind(&root.join("passwd")), Some("file".into()));
+        assert_eq!(kind(&root.join("missing")), Some("missing".into()));
+
+        let mut out = Vec::new();
+        let code = list(Some(&config), Some("web"), &options, false, &mut out).unwrap();
+        assert_eq!(code, ExitCode::FAILURE);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn completes_the_declared_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config");
+        std::fs::write(&config, "include /etc\n[profile security]\n[profile web]\n").unwrap();
+        let options = ParseOptions::default();
+        let script = |path: &Path| {
+            let mut out = Vec::new();
+            let code = completions(Shell::Bash, Some(path), &options, &mut out);
+            assert_eq!(code, ExitCode::SUCCESS);
+            String::from_utf8(out).unwrap()
+        };
+
+        let completed = script(&config);
+        assert!(completed.contains("validate"), "{completed}");
+        assert!(completed.contains("security web"), "{completed}");
+        // A configuration which doesn't load still completes everything but the profiles.
+        let completed = script(&dir.path().join("missing"));
+        assert!(completed.contains("validate"), "{completed}");
+        assert!(!completed.contains("security"), "{completed}");
+    }
+
+    #[test]
+    fn dry_runs_leave_actions_and_sinks_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let touched = dir.path().join("touched");
+        let action = Action {
+            events: configuration::EventSet::all(),
+            command: format!("touch {}", touched.display()),
+        };
+        let event = Event::new(dir.path().join("file"), configuration::EventKind::Modify);
+        runner(true).run(&action, &event).unwrap();
+        assert!(!touched.exists());
+        runner(false).run(&action, &event).unwrap();
+        assert!(touched.exists());
+
+        let events = dir.path().join("missing").join("events.ndjson");
+        let config = parse(&format!("notify ndjson {}", events.display()));
+        assert!(notifier(&config, false).is_err());
+        assert!(notifier(&config, true).is_ok());
+        assert!(!events.exists());
+    }
+
+    #[test]
+    fn hands_failed_actions_on_as_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config");
+        let config = format!("include {}\non modify run exit 3\n", dir.path().display());
+        std::fs::write(&file, config).unwrap();
+        let (mut daemon, _) = daemon(&file);
+        let summaries = Arc::default();
+        let sink: Notify = "webhook http://localhost/hook".parse().unwrap();
+        daemon
+            .notifier
+            .add(sink, Box::new(Summaries(Arc::clone(&summaries))));
+        let mut dispatcher = Dispatcher::new(&daemon.config, runner(false));
+        let failures = dispatcher.subscribe_failures();
+        let queue = ActionQueue::new(dispatcher, Limits::from_config(&daemon.config));
+        let index = dir.path().join("index.html");
+        queue.submit(&Event::new(&index, configuration::EventKind::Modify));
+        queue.wait_idle();
+        daemon.report_failures(&failures);
+        drop(daemon);
+
+        let summaries = summaries.lock().unwrap();
+        let expected = format!(
+            "modify {} (gave up on `exit 3` after 1 attempts: failed with exit status: 3)",
+            index.display()
+        );
+        assert_eq!(*summaries, [expected]);
+    }
+
+    #[test]
+    fn reloads_the_includes_of_watch_groups() {
+        let dir = tempfile::tempdir().unwrap();
+        let (top, grouped) = (dir.path().join("top"), dir.path().join("grouped"));
+        std::fs::create_dir(&top).unwrap();
+        std::fs::create_dir(&grouped).unwrap();
+        let file = dir.path().join("config");
+        let include = format!("include {}\n", top.display());
+        std::fs::write(&file, &include).unwrap();
+        let (mut daemon, (mut watcher, queue)) = daemon(&file);
+        let spec = |path: &Path| PathSpec::Path(path.to_path_buf());
+        assert_eq!(watched(&watcher), [spec(&top)]);
+
+        let group = format!("watch web {{\ninclude {}\n}}\n", grouped.display());
+        std::fs::write(&file, format!("{include}{group}")).unwrap();
+        daemon.reload_if_changed(&mut watcher, &queue);
+        assert_eq!(watched(&watcher), [spec(&top), spec(&grouped)]);
+        assert!(notices(&mut watcher, &grouped.join("index.html")));
+
+        std::fs::write(&file, &include).unwrap();
+        daemon.reload_if_changed(&mut watcher, &queue);
+        assert_eq!(watched(&watcher), [spec(&top)]);
+        assert!(!notices(&mut watcher, &grouped.join("index.html")));
+        assert!(notices(&mut watcher, &top.join("hosts")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reloads_watch_groups_on_sighup() {
+        let dir = tempfile::tempdir().unwrap();
+        let grouped = dir.path().join("grouped");
+        std::fs::create_dir(&grouped).unwrap();
+        let file = dir.path().join("config");
+        std::fs::write(&file, format!("include {}\n", dir.path().display())).unwrap();
+        let (mut daemon, (mut watcher, queue)) = daemon(&file);
+        assert!(!daemon.answer_signal(&mut watcher, &queue));
+
+        let group = format!("watch web {{\ninclude {}\n}}\n", grouped.display());
+        std::fs::write(&file, group).unwrap();
+        // SAFETY: raising a signal the daemon's handlers catch.
+        unsafe { libc::raise(libc::SIGHUP) };
+        assert!(!daemon.answer_signal(&mut watcher, &queue));
+        let grouped_spec = PathSpec::Path(grouped.clone());
+        assert_eq!(watched(&watcher), [grouped_spec]);
+        assert!(notices(&mut watcher, &grouped.join("index.html")));
+        assert!(!notices(&mut watcher, &dir.path().join("hosts")));
+    }
+
+    #[test]
+    fn formats_config_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config");
+        let unformatted = "include   /b /a\n\n\n# comment\n";
+        let formatted = configuration::format(unformatted);
+        assert_ne!(formatted, unformatted);
+
+        std::fs::write(&file, unformatted).unwrap();
+        assert_eq!(fmt(&file, true), ExitCode::FAILURE);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), unformatted);
+
+        assert_eq!(fmt(&file, false), ExitCode::SUCCESS);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), formatted);
+        assert_eq!(fmt(&file, true), ExitCode::SUCCESS);
+
+        assert_eq!(fmt(&dir.path().join("missing"), false), ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn records_the_baseline() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("etc");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("passwd"), "passwd").unwrap();
+        let include = format!("include -r {}\n", root.display());
+        assert_eq!(baseline(&parse(&include)), ExitCode::FAILURE);
+
+        let file = dir.path().join("baseline");
+        let config = parse(&format!("{include}baseline_file {}", file.display()));
+        assert_eq!(baseline(&config), ExitCode::SUCCESS);
+        let recorded = WatchState::load(&file).unwrap().unwrap();
+        assert_eq!(recorded, WatchState::scan(&config, None));
+
+        // An edit of the same size, with the old modification time put back.
+        let passwd = root.join("passwd");
+        let modified = std::fs::metadata(&passwd).unwrap().modified().unwrap();
+        std::fs::write(&passwd, "PASSWD").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&passwd)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let state = scan_at_start(&config, Some(&recorded), true);
+        let events: Vec<_> = recorded
+            .changes(&state)
+            .into_iter()
+            .map(|event| event.path)
+            .collect();
+        assert_eq!(events, vec![passwd.clone()]);
+        // A state file alone keeps the digest it saved.
+        let state = scan_at_start(&config, Some(&recorded), false);
+        assert_eq!(recorded.changes(&state), vec![]);
+    }
+}
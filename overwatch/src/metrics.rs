@@ -0,0 +1,348 @@
+//! Counting what overwatch does, for Prometheus to scrape.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Mutex, MutexGuard},
+};
+
+use serde_json::{json, Map, Value};
+use watcher::{DropReason, FilterCounts};
+
+use crate::EventRecord;
+
+#[derive(Debug, Default)]
+struct Counts {
+    /// Events by kind and watch group, the empty string standing for none.
+    events: BTreeMap<(&'static str, String), u64>,
+    tags: BTreeMap<String, u64>,
+    filter: FilterCounts,
+    overflows: u64,
+    succeeded: u64,
+    failed: u64,
+    retries: u64,
+    dropped_actions: u64,
+    duplicate_events: u64,
+    suppressed_events: u64,
+    tampered_paths: u64,
+    queue_depth: usize,
+    watches: usize,
+}
+
+/// The counters and gauges overwatch exposes, updated as events and actions go through. The
+/// parts which keep count of something themselves, such as the filters, are copied in as they
+/// change.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counts: Mutex<Counts>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Counts> {
+        self.counts
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Counts an event which came through the filters.
+    pub fn record_event(&self, record: &EventRecord) {
+        let mut counts = self.lock();
+        let group = record.group.clone().unwrap_or_default();
+        *counts
+            .events
+            .entry((record.kind.as_str(), group))
+            .or_default() += 1;
+        for tag in &record.tags {
+            *counts.tags.entry(tag.clone()).or_default() += 1;
+        }
+    }
+
+    /// Takes the counts of the filters events go through, see [`watcher::Filtered::counts`].
+    pub fn set_filter_counts(&self, filter: FilterCounts) {
+        self.lock().filter = filter;
+    }
+
+    /// Counts a time events were lost as the operating system's queue overflowed.
+    pub fn record_overflow(&self) {
+        self.lock().overflows += 1;
+    }
+
+    /// Counts an action which ran, after any retries, as having succeeded or not.
+    pub fn record_action(&self, succeeded: bool) {
+        let mut counts = self.lock();
+        if succeeded {
+            counts.succeeded += 1;
+        } else {
+            counts.failed += 1;
+        }
+    }
+
+    pub fn record_retry(&self) {
+        self.lock().retries += 1;
+    }
+
+    /// Counts an action the queue's overflow policy dropped.
+    pub fn record_dropped_action(&self) {
+        self.lock().dropped_actions += 1;
+    }
+
+    /// Counts `events` dropped as repeats within the `dedupe` window.
+    pub fn record_duplicates(&self, events: usize) {
+        self.lock().duplicate_events += events as u64;
+    }
+
+    /// Counts `events` suppressed during an event storm.
+    pub fn record_suppressed(&self, events: usize) {
+        self.lock().suppressed_events += events as u64;
+    }
+
+    /// Counts a path an integrity check found tampered with.
+    pub fn record_tampering(&self) {
+        self.lock().tampered_paths += 1;
+    }
+
+    pub fn set_queue_depth(&self, depth: usize) {
+        self.lock().queue_depth = depth;
+    }
+
+    /// Sets the number of includes being watched.
+    pub fn set_watches(&self, watches: usize) {
+        self.lock().watches = watches;
+    }
+
+    /// The metrics in Prometheus' text format.
+    pub fn render(&self) -> String {
+        let counts = self.lock();
+        let mut out = String::new();
+        let mut family = |name: &str, kind: &str, help: &str, samples: Vec<(String, u64)>| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{name}{labels} {value}");
+            }
+        };
+        family(
+            "overwatch_events_total",
+            "counter",
+            "Events which came through the filters, by kind and watch group.",
+            counts
+                .events
+                .iter()
+                .map(|((kind, group), count)| (labels(&[("kind", kind), ("watch", group)]), *count))
+                .collect(),
+        );
+        family(
+            "overwatch_tagged_events_total",
+            "counter",
+            "Events which came through the filters, by the tags of their paths.",
+            counts
+                .tags
+                .iter()
+                .map(|(tag, count)| (labels(&[("tag", tag)]), *count))
+                .collect(),
+        );
+        family(
+            "overwatch_dropped_events_total",
+            "counter",
+            "Events the filters dropped, by the reason they were dropped.",
+            DropReason::ALL
+                .iter()
+                .map(|reason| {
+                    let count = counts.filter.dropped_for(*reason);
+                    (labels(&[("reason", reason.as_str())]), count)
+                })
+                .collect(),
+        );
+        family(
+            "overwatch_overflows_total",
+            "counter",
+            "Times events were lost as the operating system's queue overflowed.",
+            vec![(String::new(), counts.overflows)],
+        );
+        family(
+            "overwatch_actions_total",
+            "counter",
+            "Actions which ran, by whether they succeeded once retried.",
+            vec![
+                (labels(&[("result", "success")]), counts.succeeded),
+                (labels(&[("result", "failure")]), counts.failed),
+            ],
+        );
+        family(
+            "overwatch_action_retries_total",
+            "counter",
+            "Times failed actions were run again.",
+            vec![(String::new(), counts.retries)],
+        );
+        family(
+            "overwatch_dropped_actions_total",
+            "counter",
+            "Actions the queue's overflow policy dropped.",
+            vec![(String::new(), counts.dropped_actions)],
+        );
+        family(
+            "overwatch_duplicate_events_total",
+            "counter",
+            "Events dropped as repeats within the dedupe window.",
+            vec![(String::new(), counts.duplicate_events)],
+        );
+        family(
+            "overwatch_suppressed_events_total",
+            "counter",
+            "Events suppressed during event storms.",
+            vec![(String::new(), counts.suppressed_events)],
+        );
+        family(
+            "overwatch_tampered_paths_total",
+            "counter",
+            "Paths integrity checks found tampered with.",
+            vec![(String::new(), counts.tampered_paths)],
+        );
+        family(
+            "overwatch_queue_depth",
+            "gauge",
+            "Actions waiting in the queue.",
+            vec![(String::new(), counts.queue_depth as u64)],
+        );
+        family(
+            "overwatch_watches",
+            "gauge",
+            "Includes being watched.",
+            vec![(String::new(), counts.watches as u64)],
+        );
+        out
+    }
+
+    /// The same counts as a JSON object, with events totalled by kind and by tag:
+    ///
+    /// ```json
+    /// {
+    ///   "events": {"modify": 12, "create": 2},
+    ///   "tagged_events": {"web": 14},
+    ///   "dropped_events": {"excluded": 3, "ignored": 40},
+    ///   "overflows": 0,
+    ///   "actions": {"success": 13, "failure": 1},
+    ///   "action_retries": 2,
+    ///   "dropped_actions": 0,
+    ///   "duplicate_events": 0,
+    ///   "suppressed_events": 0,
+    ///   "tampered_paths": 0,
+    ///   "queue_depth": 0,
+    ///   "watches": 4
+    /// }
+    /// ```
+    pub fn to_json(&self) -> Value {
+        let counts = self.lock();
+        let mut events = BTreeMap::<&str, u64>::new();
+        for ((kind, _), count) in &counts.events {
+            *events.entry(kind).or_default() += count;
+        }
+        let dropped: Map<_, _> = DropReason::ALL
+            .iter()
+            .map(|reason| {
+                (
+                    reason.to_string(),
+                    counts.filter.dropped_for(*reason).into(),
+                )
+            })
+            .collect();
+        json!({
+            "events": events,
+            "tagged_events": counts.tags,
+            "dropped_events": dropped,
+            "overflows": counts.overflows,
+            "actions": {"success": counts.succeeded, "failure": counts.failed},
+            "action_retries": counts.retries,
+            "dropped_actions": counts.dropped_actions,
+            "duplicate_events": counts.duplicate_events,
+            "suppressed_events": counts.suppressed_events,
+            "tampered_paths": counts.tampered_paths,
+            "queue_depth": counts.queue_depth,
+            "watches": counts.watches,
+        })
+    }
+}
+
+/// Writes `pairs` as a label set, escaping their values.
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<_> = pairs
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{name}=\"{value}\"")
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use configuration::EventKind;
+
+    use super::*;
+
+    #[test]
+    fn renders_prometheus_text() {
+        let metrics = Metrics::new();
+        let record = |kind, tags: &[&str], group: Option<&str>| EventRecord {
+            path: "/srv/index.html".into(),
+            kind,
+            from: None,
+            timestamp: UNIX_EPOCH,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            group: group.map(String::from),
+            tampered: Vec::new(),
+            failure: None,
+        };
+        metrics.record_event(&record(EventKind::Modify, &["web"], Some("app")));
+        metrics.record_event(&record(EventKind::Modify, &["web", "a\"b"], Some("app")));
+        metrics.record_event(&record(EventKind::Create, &[], None));
+        metrics.record_action(true);
+        metrics.record_action(false);
+        metrics.record_retry();
+        metrics.record_duplicates(1);
+        metrics.record_suppressed(5);
+        metrics.record_tampering();
+        metrics.set_queue_depth(3);
+        metrics.set_watches(2);
+
+        let text = metrics.render();
+        for line in [
+            "# TYPE overwatch_events_total counter",
+            "overwatch_events_total{kind=\"create\",watch=\"\"} 1",
+            "overwatch_events_total{kind=\"modify\",watch=\"app\"} 2",
+            "overwatch_tagged_events_total{tag=\"a\\\"b\"} 1",
+            "overwatch_tagged_events_total{tag=\"web\"} 2",
+            "overwatch_dropped_events_total{reason=\"excluded\"} 0",
+            "overwatch_overflows_total 0",
+            "overwatch_actions_total{result=\"success\"} 1",
+            "overwatch_actions_total{result=\"failure\"} 1",
+            "overwatch_action_retries_total 1",
+            "overwatch_duplicate_events_total 1",
+            "overwatch_suppressed_events_total 5",
+            "overwatch_tampered_paths_total 1",
+            "# TYPE overwatch_queue_depth gauge",
+            "overwatch_queue_depth 3",
+            "overwatch_watches 2",
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line} in\n{text}");
+        }
+
+        let stats = metrics.to_json();
+        assert_eq!(stats["events"], json!({"create": 1, "modify": 2}));
+        assert_eq!(stats["tagged_events"]["web"], 2);
+        assert_eq!(stats["dropped_events"]["excluded"], 0);
+        assert_eq!(stats["actions"], json!({"success": 1, "failure": 1}));
+        assert_eq!(stats["suppressed_events"], 5);
+        assert_eq!(stats["queue_depth"], 3);
+    }
+}
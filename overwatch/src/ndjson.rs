@@ -0,0 +1,83 @@
+//! Writing events as JSON lines, declared with `notify ndjson <file>` or `notify ndjson -`.
+
+use std::io::{self, Write};
+
+use crate::{EventRecord, Output, Sink, SinkError};
+
+/// Writes each event as one line holding its JSON object, see [`EventRecord::to_json`], for
+/// tools such as `jq` to read as they arrive. Every line is flushed once written.
+pub struct Ndjson {
+    out: Box<dyn Write + Send>,
+}
+
+impl Ndjson {
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        Self { out: Box::new(out) }
+    }
+
+    pub fn open(output: &Output) -> io::Result<Self> {
+        Ok(Self {
+            out: output.open()?,
+        })
+    }
+}
+
+impl Sink for Ndjson {
+    fn send(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        writeln!(self.out, "{}", record.to_json())?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), SinkError> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::UNIX_EPOCH};
+
+    use configuration::EventKind;
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn writes_one_object_per_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.ndjson");
+        fs::write(&path, "{\"kept\":true}\n").unwrap();
+        let record = |path: &str, kind| EventRecord {
+            path: path.into(),
+            kind,
+            from: None,
+            timestamp: UNIX_EPOCH,
+            tags: Vec::new(),
+            group: None,
+            tampered: Vec::new(),
+            failure: None,
+        };
+        let records = [
+            record("/srv/index.html", EventKind::Create),
+            record("/srv/two\nlines", EventKind::Delete),
+        ];
+        let mut ndjson = Ndjson::open(&Output::parse(path.to_str().unwrap()).unwrap()).unwrap();
+        for record in &records {
+            ndjson.send(record).unwrap();
+        }
+        drop(ndjson);
+
+        let written = fs::read_to_string(&path).unwrap();
+        let lines: Vec<Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let mut expected = vec![serde_json::json!({ "kept": true })];
+        expected.extend(records.iter().map(EventRecord::to_json));
+        assert_eq!(lines, expected);
+        assert_eq!(Output::parse("-"), Ok(Output::Stdout));
+        assert!(Output::parse("").is_err());
+    }
+}
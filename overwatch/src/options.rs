@@ -0,0 +1,45 @@
+//! The `key=value` options following the arguments of this crate's directives.
+
+/// Splits `input` into its `key=value` options, where a value may be quoted to hold spaces,
+/// as in `action="systemctl reload nginx"`.
+pub(crate) fn parse_options(input: &str) -> Result<Vec<(&str, &str)>, String> {
+    let mut options = Vec::new();
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        let (key, value) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value options, found {rest}"))?;
+        let (value, after) = match value.strip_prefix('"') {
+            Some(quoted) => quoted
+                .split_once('"')
+                .ok_or_else(|| format!("unterminated quote in {key}="))?,
+            None => value.split_once(' ').unwrap_or((value, "")),
+        };
+        options.push((key, value));
+        rest = after.trim_start();
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_options() {
+        let test_cases = vec![
+            ("", Ok(vec![])),
+            ("queue=10", Ok(vec![("queue", "10")])),
+            (
+                " action=\"nginx -s reload\"  queue=2",
+                Ok(vec![("action", "nginx -s reload"), ("queue", "2")]),
+            ),
+            ("action=\"\"", Ok(vec![("action", "")])),
+            ("queue", Err(())),
+            ("action=\"open", Err(())),
+        ];
+        for (input, expected) in test_cases {
+            assert_eq!(parse_options(input).map_err(|_| ()), expected, "{input}");
+        }
+    }
+}
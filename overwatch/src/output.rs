@@ -0,0 +1,44 @@
+//! Where sinks writing text, such as `notify ndjson`, write it.
+
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// Standard output, given as `-`, or a file appended to, created if missing. Relative paths
+/// are taken from the directory overwatch is started in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    Stdout,
+    File(PathBuf),
+}
+
+impl Output {
+    pub(crate) fn parse(target: &str) -> Result<Self, String> {
+        match target {
+            "" => Err("expected a file to write to, or - for standard output".to_string()),
+            "-" => Ok(Output::Stdout),
+            _ => Ok(Output::File(PathBuf::from(target))),
+        }
+    }
+
+    pub fn open(&self) -> io::Result<Box<dyn Write + Send>> {
+        match self {
+            Output::Stdout => Ok(Box::new(io::stdout())),
+            Output::File(path) => Ok(Box::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Output::Stdout => f.write_str("-"),
+            Output::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! The file recording the process id of a running overwatch, and keeping a second one from
+//! starting alongside it.
+
+use std::{
+    error::Error,
+    fmt,
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Where `--daemon` writes its pidfile unless `--pidfile` says otherwise.
+pub const DEFAULT_PIDFILE: &str = "/run/overwatch.pid";
+
+/// Errors which can occur while taking a pidfile.
+#[derive(Debug)]
+pub enum PidfileError {
+    Io(io::Error),
+    /// Another process holds the pidfile, with the process id it wrote if there was one.
+    Held {
+        path: PathBuf,
+        pid: Option<u32>,
+    },
+}
+
+impl fmt::Display for PidfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PidfileError::Io(err) => write!(f, "{err}"),
+            PidfileError::Held {
+                path,
+                pid: Some(pid),
+            } => write!(
+                f,
+                "another instance, pid {pid}, is running with {}",
+                path.display()
+            ),
+            PidfileError::Held { path, pid: None } => {
+                write!(f, "another instance is running with {}", path.display())
+            }
+        }
+    }
+}
+
+impl Error for PidfileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PidfileError::Io(err) => Some(err),
+            PidfileError::Held { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for PidfileError {
+    fn from(err: io::Error) -> Self {
+        PidfileError::Io(err)
+    }
+}
+
+/// A pidfile held locked for as long as it's kept, and removed once dropped.
+///
+/// The lock, rather than whether the file exists, is what tells a running instance apart, so
+/// a pidfile left behind by one which crashed doesn't keep the next from starting. The lock is
+/// kept by processes forked after it's taken, so it can be taken before detaching to report a
+/// clash, and the pid written after.
+#[derive(Debug)]
+pub struct Pidfile {
+    path: PathBuf,
+    file: File,
+}
+
+impl Pidfile {
+    /// Takes the pidfile at `path`, creating it if missing, unless another process holds it.
+    pub fn acquire(path: impl AsRef<Path>) -> Result<Self, PidfileError> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Self {
+                path: path.to_path_buf(),
+                file,
+            }),
+            Err(TryLockError::WouldBlock) => {
+                let mut written = String::new();
+                let pid = file
+                    .read_to_string(&mut written)
+                    .ok()
+                    .and_then(|_| written.trim().parse().ok());
+                Err(PidfileError::Held {
+                    path: path.to_path_buf(),
+                    pid,
+                })
+            }
+            Err(TryLockError::Error(err)) => Err(err.into()),
+        }
+    }
+
+    /// Writes the id of the current process, replacing what the file held.
+    pub fn write_pid(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.rewind()?;
+        writeln!(self.file, "{}", process::id())?;
+        self.file.sync_data()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Pidfile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_a_second_instance_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("overwatch.pid");
+        let mut pidfile = Pidfile::acquire(&path).unwrap();
+        pidfile.write_pid().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", process::id())
+        );
+
+        match Pidfile::acquire(&path) {
+            Err(PidfileError::Held { pid, .. }) => assert_eq!(pid, Some(process::id())),
+            other => panic!("expected the pidfile to be held, found {other:?}"),
+        }
+        drop(pidfile);
+        assert!(!path.exists());
+
+        // A pidfile left behind without its lock is taken over.
+        fs::write(&path, "1\n").unwrap();
+        assert!(Pidfile::acquire(&path).is_ok());
+    }
+}
@@ -0,0 +1,81 @@
+//! Writing events as lines for people to read, declared with `output plain`.
+
+use std::io::{self, Write};
+
+use crate::{time::rfc3339, EventRecord, Output, Sink, SinkError};
+
+/// Writes each event as a line holding when it happened and its [`EventRecord::summary`],
+/// such as `2026-10-14T07:53:07.303Z modify /etc/passwd`. Every line is flushed once written.
+pub struct Plain {
+    out: Box<dyn Write + Send>,
+}
+
+impl Plain {
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        Self { out: Box::new(out) }
+    }
+
+    pub fn open(output: &Output) -> io::Result<Self> {
+        Ok(Self {
+            out: output.open()?,
+        })
+    }
+}
+
+impl Sink for Plain {
+    fn send(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        writeln!(
+            self.out,
+            "{} {}",
+            rfc3339(record.timestamp),
+            record.summary()
+        )?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), SinkError> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use configuration::EventKind;
+
+    use super::*;
+
+    #[test]
+    fn writes_one_line_per_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.log");
+        let record = |path: &str, kind| EventRecord {
+            path: path.into(),
+            kind,
+            from: None,
+            timestamp: UNIX_EPOCH + Duration::from_secs(86_400),
+            tags: Vec::new(),
+            group: None,
+            tampered: Vec::new(),
+            failure: None,
+        };
+        let mut plain = Plain::open(&Output::File(path.clone())).unwrap();
+        plain
+            .send(&record("/srv/index.html", EventKind::Create))
+            .unwrap();
+        plain.send(&record("/etc/motd", EventKind::Delete)).unwrap();
+        drop(plain);
+
+        let day = rfc3339(UNIX_EPOCH + Duration::from_secs(86_400));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{day} create /srv/index.html\n{day} delete /etc/motd\n")
+        );
+    }
+}
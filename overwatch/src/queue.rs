@@ -0,0 +1,701 @@
+//! Running actions on a pool of threads, so a burst of events can't start more commands than
+//! the host can take.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    str::FromStr,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use configuration::{parse_duration, Action, Config, Directive};
+use watcher::Event;
+
+use crate::{options::parse_options, ActionRunner, Dispatcher};
+
+/// How many actions waiting to run a queue holds when `max_concurrent` doesn't say.
+pub const DEFAULT_QUEUE: usize = 256;
+
+/// How long stopping waits for actions to finish when `shutdown_timeout` doesn't say.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What to do with an action when the queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Wait for room, holding up the events behind it.
+    #[default]
+    Block,
+    /// Drop the action which has waited longest to make room.
+    DropOldest,
+    /// Replace the queued run of the same action for the same path, or else drop the new one.
+    Coalesce,
+}
+
+impl Overflow {
+    pub const ALL: [Overflow; 3] = [Overflow::Block, Overflow::DropOldest, Overflow::Coalesce];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Overflow::Block => "block",
+            Overflow::DropOldest => "drop-oldest",
+            Overflow::Coalesce => "coalesce",
+        }
+    }
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Overflow {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Overflow::ALL
+            .into_iter()
+            .find(|overflow| overflow.as_str() == s)
+            .ok_or(())
+    }
+}
+
+/// One `max_concurrent` line: `max_concurrent 4 queue=100 overflow=coalesce` for all actions,
+/// or `max_concurrent 1 action="systemctl reload nginx"` for the action with that command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaxConcurrent {
+    pub limit: usize,
+    /// The command of the action limited, or `None` for all of them.
+    pub action: Option<String>,
+    pub queue: Option<usize>,
+    pub overflow: Option<Overflow>,
+}
+
+impl FromStr for MaxConcurrent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = |what: &str| format!("{what}, as in max_concurrent 4 queue=100");
+        let (limit, rest) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        let limit = match limit.parse() {
+            Ok(0) | Err(_) => return Err(invalid("expected a number of actions above zero")),
+            Ok(limit) => limit,
+        };
+        let mut parsed = MaxConcurrent {
+            limit,
+            action: None,
+            queue: None,
+            overflow: None,
+        };
+        for (key, value) in parse_options(rest)? {
+            match key {
+                "action" => parsed.action = Some(value.to_string()),
+                "queue" => match value.parse() {
+                    Ok(0) | Err(_) => return Err(invalid("expected a queue length above zero")),
+                    Ok(queue) => parsed.queue = Some(queue),
+                },
+                "overflow" => {
+                    let overflow = value.parse().map_err(|_| {
+                        "expected overflow=block, overflow=drop-oldest or overflow=coalesce"
+                            .to_string()
+                    })?;
+                    parsed.overflow = Some(overflow);
+                }
+                _ => {
+                    return Err(format!(
+                        "unknown option {key}, expected action, queue or overflow"
+                    ))
+                }
+            }
+        }
+        if parsed.action.is_some() && (parsed.queue.is_some() || parsed.overflow.is_some()) {
+            return Err("queue and overflow are shared by all actions, not set per action".into());
+        }
+        Ok(parsed)
+    }
+}
+
+/// The `max_concurrent` directive, parsing to a [`MaxConcurrent`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaxConcurrentDirective;
+
+impl MaxConcurrentDirective {
+    pub const NAME: &'static str = "max_concurrent";
+}
+
+impl Directive for MaxConcurrentDirective {
+    type Value = MaxConcurrent;
+
+    fn parse(&self, args: &str) -> Result<MaxConcurrent, String> {
+        args.parse()
+    }
+}
+
+/// The `shutdown_timeout 30s` directive, saying how long [`ActionQueue::shutdown`] waits for
+/// the actions queued and running when overwatch is stopped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShutdownTimeoutDirective;
+
+impl ShutdownTimeoutDirective {
+    pub const NAME: &'static str = "shutdown_timeout";
+
+    /// The timeout the last `shutdown_timeout` directive of `config` sets, or
+    /// [`DEFAULT_SHUTDOWN_TIMEOUT`] without one.
+    pub fn timeout(config: &Config) -> Duration {
+        config
+            .custom_values(Self::NAME)
+            .last()
+            .copied()
+            .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT)
+    }
+}
+
+impl Directive for ShutdownTimeoutDirective {
+    type Value = Duration;
+
+    fn parse(&self, args: &str) -> Result<Duration, String> {
+        parse_duration(args.trim())
+            .ok_or_else(|| format!("expected a duration such as 30s, found {:?}", args.trim()))
+    }
+}
+
+/// How many actions an [`ActionQueue`] runs at once and holds waiting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// How many actions run at once, across all of them.
+    pub global: usize,
+    /// How many runs of the action with each command run at once.
+    pub per_action: HashMap<String, usize>,
+    pub queue: usize,
+    pub overflow: Overflow,
+}
+
+impl Limits {
+    /// The limits the `max_concurrent` lines of `config` set, where the last line for all
+    /// actions wins. Without one, as many actions run at once as the host has cores.
+    pub fn from_config(config: &Config) -> Limits {
+        let mut limits = Limits {
+            global: thread::available_parallelism().map_or(1, usize::from),
+            ..Limits::default()
+        };
+        for line in config.custom_values::<MaxConcurrent>(MaxConcurrentDirective::NAME) {
+            match &line.action {
+                Some(command) => {
+                    limits.per_action.insert(command.clone(), line.limit);
+                }
+                None => {
+                    limits.global = line.limit;
+                    limits.queue = line.queue.unwrap_or(DEFAULT_QUEUE);
+                    limits.overflow = line.overflow.unwrap_or_default();
+                }
+            }
+        }
+        limits
+    }
+
+    fn allows(&self, command: &str, running: &HashMap<String, usize>) -> bool {
+        self.per_action
+            .get(command)
+            .is_none_or(|limit| running.get(command).copied().unwrap_or(0) < *limit)
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            global: 1,
+            per_action: HashMap::new(),
+            queue: DEFAULT_QUEUE,
+            overflow: Overflow::default(),
+        }
+    }
+}
+
+/// An action waiting to run for an event.
+#[derive(Debug, Clone)]
+struct Job {
+    action: Action,
+    event: Event,
+}
+
+/// What became of a job offered to a full queue.
+#[derive(Debug)]
+enum Pushed {
+    Queued,
+    /// Queued, dropping an older one.
+    Replaced(Job),
+    /// Not queued, since the policy dropped it.
+    Dropped(Job),
+    /// Not queued, since it has to wait for room.
+    Full(Job),
+}
+
+#[derive(Debug, Default)]
+struct State {
+    waiting: VecDeque<Job>,
+    running: usize,
+    running_per: HashMap<String, usize>,
+    dropped: u64,
+    closed: bool,
+}
+
+impl State {
+    fn push(&mut self, job: Job, limits: &Limits) -> Pushed {
+        if self.waiting.len() < limits.queue {
+            self.waiting.push_back(job);
+            return Pushed::Queued;
+        }
+        match limits.overflow {
+            Overflow::Block => Pushed::Full(job),
+            Overflow::DropOldest => {
+                let oldest = self.waiting.pop_front();
+                self.waiting.push_back(job);
+                oldest.map_or(Pushed::Queued, Pushed::Replaced)
+            }
+            Overflow::Coalesce => {
+                let same = self.waiting.iter_mut().find(|queued| {
+                    queued.action == job.action && queued.event.path == job.event.path
+                });
+                match same {
+                    Some(queued) => Pushed::Replaced(std::mem::replace(queued, job)),
+                    None => Pushed::Dropped(job),
+                }
+            }
+        }
+    }
+
+    /// Takes the first waiting job whose action isn't at its own limit.
+    fn take(&mut self, limits: &Limits) -> Option<Job> {
+        let index = self
+            .waiting
+            .iter()
+            .position(|job| limits.allows(&job.action.command, &self.running_per))?;
+        let job = self.waiting.remove(index)?;
+        self.running += 1;
+        *self
+            .running_per
+            .entry(job.action.command.clone())
+            .or_default() += 1;
+        Some(job)
+    }
+
+    fn finish(&mut self, job: &Job) {
+        self.running -= 1;
+        if let Some(running) = self.running_per.get_mut(&job.action.command) {
+            *running -= 1;
+            if *running == 0 {
+                self.running_per.remove(&job.action.command);
+            }
+        }
+    }
+}
+
+struct Shared<R> {
+    /// Swapped for one with the new configuration on a reload, while the actions already
+    /// running finish with the one they started with.
+    dispatcher: Mutex<Arc<Dispatcher<R>>>,
+    limits: Limits,
+    state: Mutex<State>,
+    /// Signalled when a job is queued or finishes, so idle threads look for work.
+    changed: Condvar,
+}
+
+impl<R> Shared<R> {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn dispatcher(&self) -> Arc<Dispatcher<R>> {
+        self.dispatcher
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+impl<R: ActionRunner> Shared<R> {
+    /// Tells the dispatcher's metrics, if it has any, how many actions are waiting.
+    fn update_depth(&self, state: &State) {
+        if let Some(metrics) = self.dispatcher().metrics() {
+            metrics.set_queue_depth(state.waiting.len());
+        }
+    }
+}
+
+/// Runs the actions of each event on a pool of threads, within the [`Limits`] of the
+/// configuration's `max_concurrent` lines.
+///
+/// Actions wait in a queue of bounded length until a thread and their own limit allow them to
+/// run, in the order they were submitted except where an action at its limit lets later ones
+/// by. What happens when the queue is full is up to its [`Overflow`] policy, and actions
+/// dropped are logged and counted. Dropping the queue waits for what's queued to finish, and
+/// [`ActionQueue::shutdown`] does so for a while at most.
+pub struct ActionQueue<R> {
+    shared: Arc<Shared<R>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl<R: ActionRunner + 'static> ActionQueue<R> {
+    pub fn new(dispatcher: Dispatcher<R>, limits: Limits) -> Self {
+        let shared = Arc::new(Shared {
+            dispatcher: Mutex::new(Arc::new(dispatcher)),
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+            limits,
+        });
+        let threads = (0..shared.limits.global.max(1))
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || work(&shared))
+            })
+            .collect();
+        Self { shared, threads }
+    }
+
+    /// Queues the actions which apply to `event`, blocking for room if the queue is full and
+    /// its policy says to.
+    pub fn submit(&self, event: &Event) {
+        let shared = &self.shared;
+        let dispatcher = shared.dispatcher();
+        for action in dispatcher.actions_for(event) {
+            let mut job = Job {
+                action,
+                event: event.clone(),
+            };
+            let mut state = shared.lock();
+            loop {
+                match state.push(job, &shared.limits) {
+                    Pushed::Queued => break,
+                    Pushed::Replaced(old) | Pushed::Dropped(old) => {
+                        state.dropped += 1;
+                        if let Some(metrics) = dispatcher.metrics() {
+                            metrics.record_dropped_action();
+                        }
+                        tracing::warn!(
+                            "the action queue is full, dropped `{}` for {}",
+                            old.action.command,
+                            old.event.path.display()
+                        );
+                        break;
+                    }
+                    Pushed::Full(full) => {
+                        job = full;
+                        state = shared
+                            .changed
+                            .wait(state)
+                            .unwrap_or_else(|poisoned| poisoned.into_inner());
+                    }
+                }
+            }
+            shared.update_depth(&state);
+            drop(state);
+            shared.changed.notify_all();
+        }
+    }
+
+    /// How many actions the overflow policy has dropped.
+    pub fn dropped(&self) -> u64 {
+        self.shared.lock().dropped
+    }
+
+    /// Waits until every queued action has run.
+    pub fn wait_idle(&self) {
+        let mut state = self.shared.lock();
+        while !state.waiting.is_empty() || state.running > 0 {
+            state = self
+                .shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    /// Stops taking actions and waits for those queued and running to finish, for up to
+    /// `timeout`. Returns false if some were still going by then, which are left to finish
+    /// as the process exits.
+    pub fn shutdown(mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.lock();
+        state.closed = true;
+        self.shared.changed.notify_all();
+        while !state.waiting.is_empty() || state.running > 0 {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                // Not joined on drop, since they're still busy.
+                self.threads.clear();
+                return false;
+            }
+            state = self
+                .shared
+                .changed
+                .wait_timeout(state, left)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+        true
+    }
+}
+
+impl<R: ActionRunner + Clone> ActionQueue<R> {
+    /// Runs the actions of later events by `config`, as after a reload. The limits the queue
+    /// was started with stay as they are.
+    pub fn set_config(&self, config: &Config) {
+        let mut dispatcher = self
+            .shared
+            .dispatcher
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut updated = Dispatcher::clone(&dispatcher);
+        updated.set_config(config);
+        *dispatcher = Arc::new(updated);
+    }
+}
+
+/// Runs queued jobs until the queue is closed and empty.
+fn work<R: ActionRunner>(shared: &Shared<R>) {
+    let mut state = shared.lock();
+    loop {
+        if let Some(job) = state.take(&shared.limits) {
+            shared.update_depth(&state);
+            drop(state);
+            let _ = shared.dispatcher().run(&job.action, &job.event);
+            state = shared.lock();
+            state.finish(&job);
+            shared.changed.notify_all();
+        } else if state.closed && state.waiting.is_empty() {
+            return;
+        } else {
+            state = shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+}
+
+impl<R> Drop for ActionQueue<R> {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.changed.notify_all();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<R> fmt::Debug for ActionQueue<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ActionQueue")
+            .field("limits", &self.shared.limits)
+            .field("state", &*self.shared.lock())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::Ordering, time::Duration};
+
+    use configuration::{EventKind, EventSet, ParseOptions};
+
+    use super::*;
+    use crate::{ActionError, ActionOutput};
+
+    #[test]
+    fn parses_limits() {
+        let line = |limit, action: Option<&str>, queue, overflow| MaxConcurrent {
+            limit,
+            action: action.map(String::from),
+            queue,
+            overflow,
+        };
+        let test_cases = vec![
+            ("4", Ok(line(4, None, None, None))),
+            (
+                "4 queue=10 overflow=drop-oldest",
+                Ok(line(4, None, Some(10), Some(Overflow::DropOldest))),
+            ),
+            (
+                "1 action=\"systemctl reload nginx\"",
+                Ok(line(1, Some("systemctl reload nginx"), None, None)),
+            ),
+            ("1 action=deploy", Ok(line(1, Some("deploy"), None, None))),
+            ("0", Err(())),
+            ("4 queue=0", Err(())),
+            ("4 overflow=explode", Err(())),
+            ("4 burst=2", Err(())),
+            ("1 action=deploy queue=2", Err(())),
+            ("1 action=\"deploy", Err(())),
+        ];
+        for (input, expected) in test_cases {
+            assert_eq!(
+                input.parse::<MaxConcurrent>().map_err(|_| ()),
+                expected,
+                "{input}"
+            );
+        }
+
+        let mut options = ParseOptions::default();
+        crate::register_directives(&mut options.directives);
+        let config = Config::parse_with(
+            "max_concurrent 2 overflow=coalesce\nmax_concurrent 1 action=deploy",
+            &options,
+        )
+        .unwrap();
+        let limits = Limits::from_config(&config);
+        assert_eq!(
+            limits,
+            Limits {
+                global: 2,
+                per_action: HashMap::from([("deploy".to_string(), 1)]),
+                queue: DEFAULT_QUEUE,
+                overflow: Overflow::Coalesce,
+            }
+        );
+    }
+
+    #[test]
+    fn applies_the_overflow_policy() {
+        let job = |command: &str, path: &str| Job {
+            action: Action {
+                events: EventSet::all(),
+                command: command.to_string(),
+            },
+            event: Event::new(path, EventKind::Modify),
+        };
+        let queued = |state: &State| -> Vec<_> {
+            state
+                .waiting
+                .iter()
+                .map(|job| format!("{} {}", job.action.command, job.event.path.display()))
+                .collect()
+        };
+        let test_cases = vec![
+            (Overflow::Block, "Full", vec!["a /1", "b /2"]),
+            (Overflow::DropOldest, "Replaced", vec!["b /2", "a /2"]),
+            (Overflow::Coalesce, "Dropped", vec!["a /1", "b /2"]),
+        ];
+        for (overflow, pushed, expected) in test_cases {
+            let limits = Limits {
+                queue: 2,
+                overflow,
+                ..Limits::default()
+            };
+            let mut state = State::default();
+            state.push(job("a", "/1"), &limits);
+            state.push(job("b", "/2"), &limits);
+            let result = format!("{:?}", state.push(job("a", "/2"), &limits));
+            assert!(result.starts_with(pushed), "{overflow}: {result}");
+            assert_eq!(queued(&state), expected, "{overflow}");
+        }
+
+        let limits = Limits {
+            queue: 2,
+            overflow: Overflow::Coalesce,
+            ..Limits::default()
+        };
+        let mut state = State::default();
+        state.push(job("a", "/1"), &limits);
+        state.push(job("b", "/2"), &limits);
+        assert!(matches!(
+            state.push(job("b", "/2"), &limits),
+            Pushed::Replaced(_)
+        ));
+        assert_eq!(queued(&state), ["a /1", "b /2"]);
+
+        let limits = Limits {
+            per_action: HashMap::from([("a".to_string(), 1)]),
+            ..Limits::default()
+        };
+        let mut state = State::default();
+        state.push(job("a", "/1"), &limits);
+        state.push(job("a", "/2"), &limits);
+        state.push(job("b", "/3"), &limits);
+        assert_eq!(state.take(&limits).unwrap().event.path.to_str(), Some("/1"));
+        assert_eq!(state.take(&limits).unwrap().event.path.to_str(), Some("/3"));
+        assert!(state.take(&limits).is_none());
+    }
+
+    /// Counts how many runs overlap, taking a little while over each.
+    #[derive(Default)]
+    struct Overlapping {
+        running: std::sync::atomic::AtomicUsize,
+        most: std::sync::atomic::AtomicUsize,
+        runs: std::sync::atomic::AtomicUsize,
+    }
+
+    impl ActionRunner for Arc<Overlapping> {
+        fn run(&self, _action: &Action, _event: &Event) -> Result<ActionOutput, ActionError> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most.fetch_max(running, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            self.runs.fetch_add(1, Ordering::SeqCst);
+            Ok(ActionOutput::default())
+        }
+    }
+
+    #[test]
+    fn runs_within_the_limit() {
+        let config: Config = "on modify run work\ninclude /srv".parse().unwrap();
+        let runner = Arc::new(Overlapping::default());
+        let dispatcher = Dispatcher::new(&config, runner.clone());
+        let queue = ActionQueue::new(
+            dispatcher,
+            Limits {
+                global: 2,
+                queue: 1,
+                ..Limits::default()
+            },
+        );
+        for i in 0..8 {
+            queue.submit(&Event::new(format!("/srv/{i}"), EventKind::Modify));
+        }
+        queue.wait_idle();
+        assert_eq!(runner.runs.load(Ordering::SeqCst), 8);
+        assert_eq!(runner.most.load(Ordering::SeqCst), 2);
+        assert_eq!(queue.dropped(), 0);
+    }
+
+    #[test]
+    fn shuts_down_within_the_timeout() {
+        let config: Config = "on modify run work\ninclude /srv".parse().unwrap();
+        let runner = Arc::new(Overlapping::default());
+        let queue = |runner: &Arc<Overlapping>| {
+            let dispatcher = Dispatcher::new(&config, runner.clone());
+            ActionQueue::new(dispatcher, Limits::default())
+        };
+        let submit = |queue: &ActionQueue<_>, count| {
+            for i in 0..count {
+                queue.submit(&Event::new(format!("/srv/{i}"), EventKind::Modify));
+            }
+        };
+
+        let finished = queue(&runner);
+        submit(&finished, 3);
+        assert!(finished.shutdown(Duration::from_secs(5)));
+        assert_eq!(runner.runs.load(Ordering::SeqCst), 3);
+
+        let cut_short = queue(&runner);
+        submit(&cut_short, 10);
+        assert!(!cut_short.shutdown(Duration::from_millis(30)));
+        assert!(runner.runs.load(Ordering::SeqCst) < 13);
+
+        let mut options = ParseOptions::default();
+        crate::register_directives(&mut options.directives);
+        let config = Config::parse_with("shutdown_timeout 5s", &options).unwrap();
+        assert_eq!(
+            ShutdownTimeoutDirective::timeout(&config),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            ShutdownTimeoutDirective::timeout(&Config::default()),
+            DEFAULT_SHUTDOWN_TIMEOUT
+        );
+        assert!(ShutdownTimeoutDirective.parse("soon").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! Events as sinks hand them on, with what the configuration says about their paths.
+
+use std::{path::PathBuf, time::SystemTime};
+
+use configuration::{Config, EventKind};
+use serde_json::{json, Value};
+use watcher::Event;
+
+use crate::{integrity::Tampering, time::rfc3339, ActionFailure};
+
+/// An event along with the tags and watch group of its path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventRecord {
+    pub path: PathBuf,
+    pub kind: EventKind,
+    /// The old path of a rename, see [`Event::from`].
+    pub from: Option<PathBuf>,
+    pub timestamp: SystemTime,
+    /// The tags of the includes covering the path, see [`Config::tags_for`].
+    pub tags: Vec<String>,
+    /// The watch group whose include decides the path, see [`Config::group_for`].
+    pub group: Option<String>,
+    /// What an integrity check found changed about the path, for an event reporting
+    /// tampering.
+    pub tampered: Vec<Tampering>,
+    /// How an action run for the event failed once its retries ran out, for an event
+    /// reporting that, such as ``gave up on `systemctl reload nginx` after 3 attempts: failed
+    /// with exit status: 1``.
+    pub failure: Option<String>,
+}
+
+impl EventRecord {
+    pub fn new(event: &Event, config: &Config) -> Self {
+        Self {
+            path: event.path.clone(),
+            kind: event.kind,
+            from: event.from.clone(),
+            timestamp: event.timestamp,
+            tags: config.tags_for(&event.path),
+            group: config
+                .group_for(&event.path)
+                .map(|group| group.name.clone()),
+            tampered: Vec::new(),
+            failure: None,
+        }
+    }
+
+    /// A record of the event `failure` ran its action for, reporting that the action failed.
+    pub fn failure(failure: &ActionFailure, config: &Config) -> Self {
+        Self {
+            failure: Some(format!(
+                "gave up on `{}` after {} attempts: {}",
+                failure.action.command, failure.attempts, failure.error
+            )),
+            ..Self::new(&failure.event, config)
+        }
+    }
+
+    /// A line describing the event for people, such as `modify /srv/index.html`,
+    /// `rename /srv/old.html -> /srv/index.html` or, for tampering,
+    /// `modify /etc/passwd (tampered: content, owner)`. An action which failed for good is
+    /// described after the event, as in ``modify /srv/app.py (gave up on `make` after 1
+    /// attempts: failed with exit status: 2)``.
+    pub fn summary(&self) -> String {
+        let summary = match &self.from {
+            Some(from) => format!(
+                "{} {} -> {}",
+                self.kind,
+                from.display(),
+                self.path.display()
+            ),
+            None => format!("{} {}", self.kind, self.path.display()),
+        };
+        let summary = match self.tampered.as_slice() {
+            [] => summary,
+            tampered => {
+                let tampered: Vec<_> = tampered.iter().copied().map(Tampering::as_str).collect();
+                format!("{summary} (tampered: {})", tampered.join(", "))
+            }
+        };
+        match &self.failure {
+            Some(failure) => format!("{summary} ({failure})"),
+            None => summary,
+        }
+    }
+
+    /// The record as a JSON object:
+    ///
+    /// ```json
+    /// {
+    ///   "path": "/srv/app/main.rs",
+    ///   "kind": "rename",
+    ///   "from": "/srv/app/old.rs",
+    ///   "timestamp": "2024-05-01T12:30:00.250Z",
+    ///   "tags": ["deploy"],
+    ///   "group": "app",
+    ///   "tampered": [],
+    ///   "failure": null
+    /// }
+    /// ```
+    ///
+    /// `kind` is one of `create`, `modify`, `delete` or `rename`, and `timestamp` is when the
+    /// event was received, in RFC 3339 form in UTC. `from` and `group` are `null` where they
+    /// don't apply, and `tags` is empty. `tampered` lists what an integrity check found
+    /// changed, `content`, `permissions`, `owner`, `created` or `deleted`, and is empty for
+    /// other events. `failure` says how an action run for the event failed for good, for an
+    /// event reporting that, and is `null` otherwise. Paths which aren't valid UTF-8 have their
+    /// invalid bytes replaced. Fields may be added, but these keep their names and meaning.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "path": self.path.to_string_lossy(),
+            "kind": self.kind.as_str(),
+            "from": self.from.as_ref().map(|from| from.to_string_lossy()),
+            "timestamp": rfc3339(self.timestamp),
+            "tags": self.tags,
+            "group": self.group,
+            "tampered": self.tampered.iter().copied().map(Tampering::as_str).collect::<Vec<_>>(),
+            "failure": self.failure,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use configuration::{Action, EventSet};
+
+    use super::*;
+
+    #[test]
+    fn describes_events_as_json() {
+        let config: Config = "include -r /srv tags=web\n\
+                              watch app { include -r /opt/app tags=deploy }"
+            .parse()
+            .unwrap();
+        let at = UNIX_EPOCH + Duration::from_millis(1_714_566_600_250);
+        let renamed = Event {
+            timestamp: at,
+            ..Event::renamed("/opt/app/old.rs", "/opt/app/main.rs")
+        };
+        let modified = Event {
+            timestamp: at,
+            ..Event::new("/srv/index.html", EventKind::Modify)
+        };
+        let test_cases = vec![
+            (
+                renamed,
+                json!({
+                    "path": "/opt/app/main.rs",
+                    "kind": "rename",
+                    "from": "/opt/app/old.rs",
+                    "timestamp": "2024-05-01T12:30:00.250Z",
+                    "tags": ["deploy"],
+                    "group": "app",
+                    "tampered": [],
+                    "failure": null,
+                }),
+            ),
+            (
+                modified.clone(),
+                json!({
+                    "path": "/srv/index.html",
+                    "kind": "modify",
+                    "from": null,
+                    "timestamp": "2024-05-01T12:30:00.250Z",
+                    "tags": ["web"],
+                    "group": null,
+                    "tampered": ["content"],
+                    "failure": null,
+                }),
+            ),
+        ];
+        for (event, expected) in test_cases {
+            let mut record = EventRecord::new(&event, &config);
+            if event.kind == EventKind::Modify {
+                record.tampered = vec![Tampering::Content];
+                assert_eq!(
+                    record.summary(),
+                    "modify /srv/index.html (tampered: content)"
+                );
+            }
+            assert_eq!(record.to_json(), expected);
+        }
+
+        let failure = ActionFailure {
+            action: Action {
+                events: EventSet::all(),
+                command: "make".to_string(),
+            },
+            event: modified,
+            attempts: 3,
+            error: "failed with exit status: 2".to_string(),
+        };
+        let record = EventRecord::failure(&failure, &config);
+        let message = "gave up on `make` after 3 attempts: failed with exit status: 2";
+        assert_eq!(record.failure.as_deref(), Some(message));
+        assert_eq!(
+            record.summary(),
+            format!("modify /srv/index.html ({message})")
+        );
+        assert_eq!(record.to_json()["failure"], message);
+    }
+}
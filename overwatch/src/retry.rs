@@ -0,0 +1,196 @@
+//! Running failed actions again after a while, for failures which pass on their own.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use configuration::{parse_duration, Action, Config, Directive};
+use watcher::Event;
+
+use crate::options::parse_options;
+
+/// The delay before the first retry when `retry` doesn't give one.
+pub const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest delay between attempts, however many have failed.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// One `retry` line: `retry 3 backoff=2s` for every action, or
+/// `retry 5 action="systemctl reload nginx"` for the action with that command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retry {
+    pub policy: RetryPolicy,
+    /// The command of the action retried, or `None` for all of them.
+    pub action: Option<String>,
+}
+
+impl FromStr for Retry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (retries, rest) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        let retries = retries
+            .parse()
+            .map_err(|_| "expected a number of retries, as in retry 3 backoff=2s".to_string())?;
+        let mut retry = Retry {
+            policy: RetryPolicy {
+                retries,
+                backoff: DEFAULT_BACKOFF,
+            },
+            action: None,
+        };
+        for (key, value) in parse_options(rest)? {
+            match key {
+                "action" => retry.action = Some(value.to_string()),
+                "backoff" => match parse_duration(value) {
+                    Some(backoff) if !backoff.is_zero() => retry.policy.backoff = backoff,
+                    _ => return Err(format!("expected a duration above zero, found {value}")),
+                },
+                _ => return Err(format!("unknown option {key}, expected action or backoff")),
+            }
+        }
+        Ok(retry)
+    }
+}
+
+/// The `retry` directive, parsing to a [`Retry`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetryDirective;
+
+impl RetryDirective {
+    pub const NAME: &'static str = "retry";
+}
+
+impl Directive for RetryDirective {
+    type Value = Retry;
+
+    fn parse(&self, args: &str) -> Result<Retry, String> {
+        args.parse()
+    }
+}
+
+/// How often a failed action is run again, and how long to wait before each attempt.
+///
+/// The wait doubles with every failure, starting from `backoff`, up to [`MAX_BACKOFF`]. Each
+/// wait is jittered to between half and all of that, so actions which failed together don't
+/// all retry at the same moment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// The policy for `action` under `config`: that of the last `retry` line naming its
+    /// command, or else of the last one for every action. Without either it isn't retried.
+    pub fn for_action(config: &Config, action: &Action) -> RetryPolicy {
+        let lines: Vec<&Retry> = config.custom_values(RetryDirective::NAME).collect();
+        let last = |action: Option<&str>| {
+            lines
+                .iter()
+                .rev()
+                .find(|line| line.action.as_deref() == action)
+                .map(|line| line.policy)
+        };
+        last(Some(&action.command))
+            .or_else(|| last(None))
+            .unwrap_or_default()
+    }
+
+    /// How long to wait before retry number `retry`, counting from 1, given `jitter` between 0
+    /// and 1.
+    pub fn delay(&self, retry: u32, jitter: f64) -> Duration {
+        let doubled = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)));
+        let full = doubled.min(MAX_BACKOFF);
+        full / 2 + full.mul_f64(jitter.clamp(0.0, 1.0) / 2.0)
+    }
+}
+
+/// A random number between 0 and 1, for jittering delays.
+pub(crate) fn jitter() -> f64 {
+    let random = RandomState::new().hash_one(SystemTime::now());
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// An action which still failed once its retries ran out, as [`crate::Dispatcher`] reports
+/// them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionFailure {
+    pub action: Action,
+    pub event: Event,
+    /// How many times the action was run, the first attempt included.
+    pub attempts: u32,
+    /// What the last attempt failed with, as it was logged.
+    pub error: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use configuration::ParseOptions;
+
+    use super::*;
+
+    #[test]
+    fn parses_retries_and_backs_off() {
+        let retry = |retries, secs, action: Option<&str>| Retry {
+            policy: RetryPolicy {
+                retries,
+                backoff: Duration::from_secs(secs),
+            },
+            action: action.map(String::from),
+        };
+        let test_cases = vec![
+            ("3", Ok(retry(3, 1, None))),
+            ("3 backoff=2s", Ok(retry(3, 2, None))),
+            (
+                "5 action=\"nginx -s reload\"",
+                Ok(retry(5, 1, Some("nginx -s reload"))),
+            ),
+            ("0", Ok(retry(0, 1, None))),
+            ("many", Err(())),
+            ("3 backoff=0s", Err(())),
+            ("3 backoff=soon", Err(())),
+            ("3 jitter=1", Err(())),
+        ];
+        for (input, expected) in test_cases {
+            assert_eq!(input.parse::<Retry>().map_err(|_| ()), expected, "{input}");
+        }
+
+        let mut options = ParseOptions::default();
+        crate::register_directives(&mut options.directives);
+        let config = Config::parse_with(
+            "retry 2 backoff=2s\nretry 4 action=deploy\nretry 3 backoff=1s",
+            &options,
+        )
+        .unwrap();
+        let action = |command: &str| Action {
+            events: Default::default(),
+            command: command.to_string(),
+        };
+        assert_eq!(
+            RetryPolicy::for_action(&config, &action("deploy")),
+            retry(4, 1, None).policy
+        );
+        assert_eq!(
+            RetryPolicy::for_action(&config, &action("other")),
+            retry(3, 1, None).policy
+        );
+        assert_eq!(
+            RetryPolicy::for_action(&Config::default(), &action("other")),
+            RetryPolicy::default()
+        );
+
+        let policy = retry(5, 2, None).policy;
+        let secs = |retry, jitter| policy.delay(retry, jitter).as_secs_f64();
+        assert_eq!(secs(1, 0.0), 1.0);
+        assert_eq!(secs(1, 1.0), 2.0);
+        assert_eq!(secs(3, 0.5), 6.0);
+        assert_eq!(secs(20, 1.0), 300.0);
+        assert!((0.0..1.0).contains(&jitter()));
+    }
+}
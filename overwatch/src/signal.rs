@@ -0,0 +1,97 @@
+//! Catching the signals overwatch acts on: `SIGHUP` to reload, `SIGTERM` and `SIGINT` to stop.
+//! There are none to catch on platforms other than Unix.
+
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static HANGUP: AtomicBool = AtomicBool::new(false);
+static TERMINATE: AtomicBool = AtomicBool::new(false);
+
+/// What a signal caught asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// `SIGHUP`: load the configuration again.
+    Reload,
+    /// `SIGTERM` or `SIGINT`: finish what's under way and exit.
+    Shutdown,
+}
+
+/// The handlers installed for [`Signal`]s. The handlers only note which signals arrived, for
+/// the event loop to pick up with [`Signals::take`] between reads, so nothing is done from
+/// within a handler.
+#[derive(Debug)]
+pub struct Signals {
+    _installed: (),
+}
+
+impl Signals {
+    /// Installs the handlers, for as long as the process runs.
+    #[cfg(unix)]
+    pub fn install() -> io::Result<Self> {
+        extern "C" fn on_hangup(_: libc::c_int) {
+            HANGUP.store(true, Ordering::Relaxed);
+        }
+        extern "C" fn on_terminate(_: libc::c_int) {
+            TERMINATE.store(true, Ordering::Relaxed);
+        }
+
+        let handlers: [(libc::c_int, extern "C" fn(libc::c_int)); 3] = [
+            (libc::SIGHUP, on_hangup),
+            (libc::SIGTERM, on_terminate),
+            (libc::SIGINT, on_terminate),
+        ];
+        for (signal, handler) in handlers {
+            // SAFETY: the handlers only store to atomics, which is async-signal-safe, and the
+            // sigaction is zeroed before the fields used are set.
+            let result = unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = handler as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(signal, &action, std::ptr::null_mut())
+            };
+            if result != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Self { _installed: () })
+    }
+
+    /// Installs nothing, so [`Signals::take`] never returns a signal.
+    #[cfg(not(unix))]
+    pub fn install() -> io::Result<Self> {
+        Ok(Self { _installed: () })
+    }
+
+    /// The signal which arrived since the last call, if any. A shutdown goes before a
+    /// reload, and several of the same signal count as one.
+    pub fn take(&self) -> Option<Signal> {
+        if TERMINATE.swap(false, Ordering::Relaxed) {
+            Some(Signal::Shutdown)
+        } else if HANGUP.swap(false, Ordering::Relaxed) {
+            Some(Signal::Reload)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_the_signals_which_arrive() {
+        let signals = Signals::install().unwrap();
+        assert_eq!(signals.take(), None);
+        for signal in [libc::SIGHUP, libc::SIGHUP, libc::SIGTERM] {
+            // SAFETY: raising a signal the handlers above catch.
+            unsafe { libc::raise(signal) };
+        }
+        assert_eq!(signals.take(), Some(Signal::Shutdown));
+        assert_eq!(signals.take(), Some(Signal::Reload));
+        assert_eq!(signals.take(), None);
+    }
+}
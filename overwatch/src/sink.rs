@@ -0,0 +1,508 @@
+//! Handing events on to other systems, as the configuration's `notify` lines ask for.
+
+use std::{
+    error::Error,
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+    sync::mpsc,
+    thread::{self, JoinHandle},
+};
+
+use configuration::{Config, Directive, OutputFormat};
+
+#[cfg(target_os = "linux")]
+use crate::journald::{Journald, JournaldConfig};
+use crate::{
+    csv::{Csv, CsvConfig},
+    json::Json,
+    ndjson::Ndjson,
+    options::parse_options,
+    plain::Plain,
+    syslog::{Syslog, SyslogConfig},
+    webhook::{Webhook, WebhookConfig},
+    EventRecord, Output,
+};
+
+/// Somewhere events are handed on to, such as a webhook or syslog.
+pub trait Sink: Send {
+    /// Hands on `record`, waiting until it's delivered or has failed to be.
+    fn send(&mut self, record: &EventRecord) -> Result<(), SinkError>;
+
+    /// Hands on the records of a batch, collected as a `batch` line asks for. Sinks which
+    /// can deliver many at once do so, others send each in turn.
+    fn send_batch(&mut self, records: &[EventRecord]) -> Result<(), SinkError> {
+        records.iter().try_for_each(|record| self.send(record))
+    }
+
+    /// Delivers anything the sink has held back, before it's dropped.
+    fn flush(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+/// Errors which can occur while handing on events.
+#[derive(Debug)]
+pub enum SinkError {
+    Io(io::Error),
+    /// The receiving end couldn't be reached or turned the event down.
+    Delivery(String),
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkError::Io(err) => write!(f, "{err}"),
+            SinkError::Delivery(reason) => write!(f, "failed to deliver: {reason}"),
+        }
+    }
+}
+
+impl Error for SinkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SinkError::Io(err) => Some(err),
+            SinkError::Delivery(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for SinkError {
+    fn from(err: io::Error) -> Self {
+        SinkError::Io(err)
+    }
+}
+
+/// The kind of sink a `notify` line declares, with its settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SinkConfig {
+    Webhook(WebhookConfig),
+    Syslog(SyslogConfig),
+    Ndjson(Output),
+    Csv(CsvConfig),
+    #[cfg(target_os = "linux")]
+    Journald(JournaldConfig),
+    /// Declared with `output plain`, as are the two below with `output json`.
+    Plain(Output),
+    Json(Output),
+}
+
+impl SinkConfig {
+    /// The name the line declares the sink by, as in `notify webhook`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SinkConfig::Webhook(_) => "webhook",
+            SinkConfig::Syslog(_) => "syslog",
+            SinkConfig::Ndjson(_) => "ndjson",
+            SinkConfig::Csv(_) => "csv",
+            #[cfg(target_os = "linux")]
+            SinkConfig::Journald(_) => "journald",
+            SinkConfig::Plain(_) => "plain",
+            SinkConfig::Json(_) => "json",
+        }
+    }
+
+    /// Starts the sink.
+    pub fn open(&self) -> Result<Box<dyn Sink>, SinkError> {
+        match self {
+            SinkConfig::Webhook(config) => Ok(Box::new(Webhook::new(config.clone()))),
+            SinkConfig::Syslog(config) => Ok(Box::new(Syslog::new(config.clone()))),
+            SinkConfig::Ndjson(output) => Ok(Box::new(Ndjson::open(output)?)),
+            SinkConfig::Csv(config) => Ok(Box::new(Csv::open(config.clone())?)),
+            #[cfg(target_os = "linux")]
+            SinkConfig::Journald(config) => Ok(Box::new(Journald::new(config.clone())?)),
+            SinkConfig::Plain(output) => Ok(Box::new(Plain::open(output)?)),
+            SinkConfig::Json(output) => Ok(Box::new(Json::open(output)?)),
+        }
+    }
+}
+
+/// One `notify <sink> <target> [options]` line, such as
+/// `notify webhook https://alerts.example.com/hook timeout=5s tags=security`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notify {
+    pub sink: SinkConfig,
+    /// Only events carrying one of these tags are handed to the sink, or every event if empty.
+    pub tags: Vec<String>,
+}
+
+impl Notify {
+    /// Returns true if the sink takes `record`.
+    pub fn accepts(&self, record: &EventRecord) -> bool {
+        self.tags.is_empty() || record.tags.iter().any(|tag| self.tags.contains(tag))
+    }
+}
+
+impl FromStr for Notify {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (kind, rest) = first_word(s);
+        // The journal is always in the same place, so its options follow straight on.
+        let (target, rest) = if kind == "journald" {
+            ("", rest)
+        } else {
+            first_word(rest)
+        };
+        let mut options = parse_options(rest)?;
+        let mut tags = Vec::new();
+        options.retain(|(key, value)| {
+            if *key != "tags" {
+                return true;
+            }
+            tags.extend(value.split(',').map(|tag| tag.trim().to_string()));
+            false
+        });
+        let sink = match kind {
+            "webhook" => SinkConfig::Webhook(WebhookConfig::parse(target, &options)?),
+            "syslog" => SinkConfig::Syslog(SyslogConfig::parse(target, &options)?),
+            "ndjson" => match options.first() {
+                None => SinkConfig::Ndjson(Output::parse(target)?),
+                Some((key, _)) => return Err(format!("unknown option {key}, expected tags")),
+            },
+            "csv" => SinkConfig::Csv(CsvConfig::parse(target, &options)?),
+            #[cfg(target_os = "linux")]
+            "journald" => SinkConfig::Journald(JournaldConfig::parse(&options)?),
+            #[cfg(not(target_os = "linux"))]
+            "journald" => return Err("the journal is only available on Linux".to_string()),
+            "" => return Err("expected a sink, such as notify webhook <url>".to_string()),
+            _ => {
+                return Err(format!(
+                    "unknown sink {kind}, expected webhook, syslog, journald, ndjson or csv"
+                ))
+            }
+        };
+        Ok(Notify { sink, tags })
+    }
+}
+
+/// Splits the first word off `s`.
+fn first_word(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    s.split_once(' ').unwrap_or((s, ""))
+}
+
+/// The `notify` directive, parsing to a [`Notify`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NotifyDirective;
+
+impl NotifyDirective {
+    pub const NAME: &'static str = "notify";
+}
+
+impl Directive for NotifyDirective {
+    type Value = Notify;
+
+    fn parse(&self, args: &str) -> Result<Notify, String> {
+        args.parse()
+    }
+}
+
+/// Hands every event to the sinks which take it, each on a thread of its own so a slow sink
+/// only holds up its own events. What fails to be delivered is logged. Dropping the notifier
+/// waits for the sinks to deliver what they were given.
+#[derive(Debug, Default)]
+pub struct Notifier {
+    sinks: Vec<SinkThread>,
+}
+
+#[derive(Debug)]
+struct SinkThread {
+    notify: Notify,
+    sender: Option<mpsc::Sender<Delivery>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// What a sink's thread is handed to send.
+#[derive(Debug)]
+enum Delivery {
+    Record(EventRecord),
+    Batch(Vec<EventRecord>),
+}
+
+impl Notifier {
+    /// A notifier without sinks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts the sinks the `notify` lines of `config` declare, along with one writing to
+    /// standard output in the format its `output` line names.
+    pub fn from_config(config: &Config) -> Result<Self, SinkError> {
+        let mut notifier = Self::new();
+        for notify in declared(config) {
+            let sink = notify.sink.open()?;
+            notifier.add(notify, sink);
+        }
+        Ok(notifier)
+    }
+
+    /// Like [`Notifier::from_config`], with every sink replaced by one writing what it would
+    /// be handed to standard error, for `--dry-run`.
+    pub fn dry_run(config: &Config) -> Self {
+        let mut notifier = Self::new();
+        for notify in declared(config) {
+            let kind = notify.sink.kind();
+            notifier.add(notify, Box::new(DryRun { kind }));
+        }
+        notifier
+    }
+
+    /// Hands the events `notify` takes to `sink`.
+    pub fn add(&mut self, notify: Notify, mut sink: Box<dyn Sink>) {
+        let (sender, receiver) = mpsc::channel::<Delivery>();
+        let span = tracing::info_span!("sink", kind = notify.sink.kind());
+        let thread = thread::spawn(move || {
+            let _span = span.enter();
+            for delivery in receiver {
+                match delivery {
+                    Delivery::Record(record) => {
+                        if let Err(err) = sink.send(&record) {
+                            tracing::error!(
+                                "failed to hand on {} of {}: {err}",
+                                record.kind,
+                                record.path.display()
+                            );
+                        }
+                    }
+                    Delivery::Batch(records) => {
+                        if let Err(err) = sink.send_batch(&records) {
+                            tracing::error!(
+                                "failed to hand on a batch of {} events: {err}",
+                                records.len()
+                            );
+                        }
+                    }
+                }
+            }
+            if let Err(err) = sink.flush() {
+                tracing::error!("{err}");
+            }
+        });
+        self.sinks.push(SinkThread {
+            notify,
+            sender: Some(sender),
+            thread: Some(thread),
+        });
+    }
+
+    /// Queues `record` for every sink which takes it.
+    pub fn notify(&self, record: &EventRecord) {
+        for sink in &self.sinks {
+            if let Some(sender) = sink.sender.as_ref().filter(|_| sink.notify.accepts(record)) {
+                let _ = sender.send(Delivery::Record(record.clone()));
+            }
+        }
+    }
+
+    /// Queues the records of `batch` each sink takes as one batch for it, see
+    /// [`Sink::send_batch`].
+    pub fn notify_batch(&self, batch: &[EventRecord]) {
+        for sink in &self.sinks {
+            let Some(sender) = &sink.sender else {
+                continue;
+            };
+            let records: Vec<_> = batch
+                .iter()
+                .filter(|record| sink.notify.accepts(record))
+                .cloned()
+                .collect();
+            if !records.is_empty() {
+                let _ = sender.send(Delivery::Batch(records));
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+}
+
+/// The sinks `config` declares: standard output in the format of its `output` line, then those
+/// of its `notify` lines.
+fn declared(config: &Config) -> Vec<Notify> {
+    let stdout = config.output().map(|format| Notify {
+        sink: match format {
+            OutputFormat::Plain => SinkConfig::Plain(Output::Stdout),
+            OutputFormat::Json => SinkConfig::Json(Output::Stdout),
+            OutputFormat::Ndjson => SinkConfig::Ndjson(Output::Stdout),
+        },
+        tags: Vec::new(),
+    });
+    stdout
+        .into_iter()
+        .chain(
+            config
+                .custom_values::<Notify>(NotifyDirective::NAME)
+                .cloned(),
+        )
+        .collect()
+}
+
+/// Stands in for a sink of `kind`, writing each record as a `<kind>: <json>` line to standard
+/// error.
+struct DryRun {
+    kind: &'static str,
+}
+
+impl Sink for DryRun {
+    fn send(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        writeln!(io::stderr(), "{}: {}", self.kind, record.to_json())?;
+        Ok(())
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        for sink in &mut self.sinks {
+            sink.sender = None;
+        }
+        for sink in &mut self.sinks {
+            if let Some(thread) = sink.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::UNIX_EPOCH,
+    };
+
+    use configuration::EventKind;
+
+    use super::*;
+
+    /// Keeps the paths of the records it's sent.
+    struct Collect(Arc<Mutex<Vec<String>>>);
+
+    impl Sink for Collect {
+        fn send(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(record.path.display().to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn hands_events_to_the_sinks_taking_them() {
+        let notify: Notify = "webhook http://localhost/hook tags=security,audit"
+            .parse()
+            .unwrap();
+        assert_eq!(notify.sink.kind(), "webhook");
+        assert_eq!(notify.tags, ["security", "audit"]);
+        assert!("pager localhost".parse::<Notify>().is_err());
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            "journald tags=security".parse::<Notify>().unwrap().tags,
+            ["security"]
+        );
+        assert!("".parse::<Notify>().is_err());
+
+        let record = |path: &str, tags: &[&str]| EventRecord {
+            path: path.into(),
+            kind: EventKind::Modify,
+            from: None,
+            timestamp: UNIX_EPOCH,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            group: None,
+            tampered: Vec::new(),
+            failure: None,
+        };
+        let everything = Notify {
+            tags: Vec::new(),
+            ..notify.clone()
+        };
+        let (all, tagged) = (Arc::default(), Arc::default());
+        let mut notifier = Notifier::new();
+        notifier.add(everything, Box::new(Collect(Arc::clone(&all))));
+        notifier.add(notify, Box::new(Collect(Arc::clone(&tagged))));
+        notifier.notify(&record("/etc/shadow", &["security"]));
+        notifier.notify(&record("/srv/index.html", &["web"]));
+        notifier.notify(&record("/var/log/audit.log", &["web", "audit"]));
+        drop(notifier);
+
+        assert_eq!(
+            *all.lock().unwrap(),
+            ["/etc/shadow", "/srv/index.html", "/var/log/audit.log"]
+        );
+        assert_eq!(
+            *tagged.lock().unwrap(),
+            ["/etc/shadow", "/var/log/audit.log"]
+        );
+    }
+
+    /// Keeps the paths of the batches it's sent, a batch to each entry.
+    struct CollectBatches(Arc<Mutex<Vec<Vec<String>>>>);
+
+    impl Sink for CollectBatches {
+        fn send(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+            self.send_batch(std::slice::from_ref(record))
+        }
+
+        fn send_batch(&mut self, records: &[EventRecord]) -> Result<(), SinkError> {
+            let paths = records
+                .iter()
+                .map(|record| record.path.display().to_string());
+            self.0.lock().unwrap().push(paths.collect());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn hands_batches_to_the_sinks_taking_them() {
+        let record = |path: &str, tags: &[&str]| EventRecord {
+            path: path.into(),
+            kind: EventKind::Modify,
+            from: None,
+            timestamp: UNIX_EPOCH,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            group: None,
+            tampered: Vec::new(),
+            failure: None,
+        };
+        let tagged: Notify = "webhook http://localhost/hook tags=security"
+            .parse()
+            .unwrap();
+        let everything = Notify {
+            tags: Vec::new(),
+            ..tagged.clone()
+        };
+        let (all, security) = (Arc::default(), Arc::default());
+        let mut notifier = Notifier::new();
+        notifier.add(everything, Box::new(CollectBatches(Arc::clone(&all))));
+        notifier.add(tagged, Box::new(CollectBatches(Arc::clone(&security))));
+        notifier.notify_batch(&[record("/etc/shadow", &["security"]), record("/srv/a", &[])]);
+        notifier.notify_batch(&[record("/srv/b", &[])]);
+        drop(notifier);
+
+        assert_eq!(
+            *all.lock().unwrap(),
+            [vec!["/etc/shadow", "/srv/a"], vec!["/srv/b"]]
+        );
+        assert_eq!(*security.lock().unwrap(), [vec!["/etc/shadow"]]);
+    }
+
+    #[test]
+    fn declares_standard_output_in_the_output_format() {
+        let test_cases = vec![
+            ("", vec![]),
+            ("output plain", vec![SinkConfig::Plain(Output::Stdout)]),
+            ("output json", vec![SinkConfig::Json(Output::Stdout)]),
+            ("output ndjson", vec![SinkConfig::Ndjson(Output::Stdout)]),
+        ];
+        for (input, expected) in test_cases {
+            let config: Config = input.parse().unwrap();
+            let sinks: Vec<_> = declared(&config)
+                .into_iter()
+                .map(|notify| notify.sink)
+                .collect();
+            assert_eq!(sinks, expected, "{input}");
+        }
+    }
+}
@@ -0,0 +1,628 @@
+//! The store a `store` directive declares, kept in a SQLite database: the events seen, how the
+//! actions run for them went and the baseline recorded with `overwatch --baseline`.
+
+use std::{
+    error::Error,
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use configuration::{Action, EventKind};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
+use serde_json::{json, Value};
+use watcher::{Event, WatchState};
+
+use crate::{integrity::Tampering, time::rfc3339, EventRecord, StoreConfig};
+
+/// How often the history is pruned while it's written to.
+const PRUNE_EVERY: Duration = Duration::from_secs(60);
+
+/// The version of the schema, as the database's `user_version`.
+const SCHEMA_VERSION: i64 = 2;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS events (
+        id INTEGER PRIMARY KEY,
+        timestamp INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        path TEXT NOT NULL,
+        from_path TEXT,
+        tags TEXT NOT NULL,
+        watch_group TEXT,
+        tampered TEXT NOT NULL,
+        failure TEXT
+    );
+    CREATE INDEX IF NOT EXISTS events_by_timestamp ON events (timestamp);
+    CREATE INDEX IF NOT EXISTS events_by_path ON events (path);
+    CREATE TABLE IF NOT EXISTS actions (
+        id INTEGER PRIMARY KEY,
+        timestamp INTEGER NOT NULL,
+        command TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        path TEXT NOT NULL,
+        attempts INTEGER NOT NULL,
+        error TEXT
+    );
+    CREATE INDEX IF NOT EXISTS actions_by_timestamp ON actions (timestamp);
+    CREATE TABLE IF NOT EXISTS baseline (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        recorded INTEGER NOT NULL,
+        state TEXT NOT NULL
+    );
+";
+
+/// Errors which can occur while using the store.
+#[derive(Debug)]
+pub enum StoreError {
+    Sqlite(rusqlite::Error),
+    Io(io::Error),
+    /// The database was written by a newer overwatch, with a schema of this version.
+    Schema(i64),
+    /// A row holds something overwatch doesn't write.
+    Invalid(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Sqlite(err) => write!(f, "{err}"),
+            StoreError::Io(err) => write!(f, "{err}"),
+            StoreError::Schema(version) => write!(
+                f,
+                "the store has schema version {version}, newer than this overwatch knows"
+            ),
+            StoreError::Invalid(value) => write!(f, "the store holds an invalid value {value:?}"),
+        }
+    }
+}
+
+impl Error for StoreError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StoreError::Sqlite(err) => Some(err),
+            StoreError::Io(err) => Some(err),
+            StoreError::Schema(_) | StoreError::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(err: rusqlite::Error) -> Self {
+        StoreError::Sqlite(err)
+    }
+}
+
+impl From<io::Error> for StoreError {
+    fn from(err: io::Error) -> Self {
+        StoreError::Io(err)
+    }
+}
+
+/// How an action run for an event went, once its retries were done.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionRecord {
+    pub command: String,
+    pub path: PathBuf,
+    pub kind: EventKind,
+    /// When the last attempt finished.
+    pub timestamp: SystemTime,
+    pub attempts: u32,
+    /// Why the last attempt failed, if it did.
+    pub error: Option<String>,
+}
+
+impl ActionRecord {
+    pub fn new(action: &Action, event: &Event, attempts: u32, error: Option<String>) -> Self {
+        Self {
+            command: action.command.clone(),
+            path: event.path.clone(),
+            kind: event.kind,
+            timestamp: SystemTime::now(),
+            attempts,
+            error,
+        }
+    }
+
+    /// A line describing how it went, such as `ran make for modify /srv/main.rs` or
+    /// `make for modify /srv/main.rs failed after 3 attempts: exited with status 2`.
+    pub fn summary(&self) -> String {
+        let of = format!("{} for {} {}", self.command, self.kind, self.path.display());
+        match &self.error {
+            None => format!("ran {of}"),
+            Some(err) if self.attempts == 1 => format!("{of} failed: {err}"),
+            Some(err) => format!("{of} failed after {} attempts: {err}", self.attempts),
+        }
+    }
+
+    /// The record as a JSON object with `command`, `path`, `kind` and `timestamp` keys as in
+    /// [`EventRecord::to_json`], the number of `attempts` and the `error`, `null` if it
+    /// succeeded.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "command": self.command,
+            "path": self.path.to_string_lossy(),
+            "kind": self.kind.as_str(),
+            "timestamp": rfc3339(self.timestamp),
+            "attempts": self.attempts,
+            "error": self.error,
+        })
+    }
+}
+
+/// Which of the history [`Store::events`] and [`Store::actions`] return.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoreQuery {
+    /// Only what happened at or after this time.
+    pub since: Option<SystemTime>,
+    /// Only what happened to this path or under it, including renames from there.
+    pub path: Option<PathBuf>,
+    pub kind: Option<EventKind>,
+    /// Only this many of the most recent.
+    pub limit: Option<usize>,
+}
+
+impl StoreQuery {
+    /// The parameters the `WHERE` clause of [`Store::select`] takes: the earliest time, the
+    /// path, the prefix of paths under it, the kind and the limit.
+    fn bindings(&self) -> (i64, Option<String>, Option<String>, Option<&str>, i64) {
+        let path = self.path.as_ref().map(|path| path.to_string_lossy());
+        let under = path.as_ref().map(|path| {
+            let mut under = path.trim_end_matches('/').to_string();
+            under.push('/');
+            under
+        });
+        (
+            self.since.map_or(0, millis),
+            path.map(String::from),
+            under,
+            self.kind.map(EventKind::as_str),
+            self.limit.map_or(-1, |limit| limit as i64),
+        )
+    }
+}
+
+/// A SQLite database keeping the history of events and of the actions run for them, pruned as
+/// the [`StoreConfig`] it was opened with says, along with the baseline. It's shared between
+/// the event loop and the threads actions run on.
+#[derive(Debug)]
+pub struct Store {
+    connection: Mutex<Connection>,
+    retain: Option<Duration>,
+    max_events: Option<u64>,
+    /// When the history was last pruned.
+    pruned: Mutex<Instant>,
+}
+
+impl Store {
+    /// Opens the store `config` declares, creating it if there's none yet, and prunes it.
+    pub fn open(config: &StoreConfig) -> Result<Store, StoreError> {
+        let connection = Connection::open(&config.path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        let version: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(StoreError::Schema(version));
+        }
+        if version == 1 {
+            // The events of version 1 couldn't report failed actions.
+            connection.execute_batch("ALTER TABLE events ADD COLUMN failure TEXT")?;
+        }
+        connection.execute_batch(SCHEMA)?;
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        let store = Store {
+            connection: Mutex::new(connection),
+            retain: config.retain,
+            max_events: config.max_events,
+            pruned: Mutex::new(Instant::now()),
+        };
+        store.prune(SystemTime::now())?;
+        Ok(store)
+    }
+
+    /// Opens the store at `path` to be queried, failing if there's none. Nothing is pruned,
+    /// and nothing can be recorded.
+    pub fn open_read_only(path: &Path) -> Result<Store, StoreError> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let connection = Connection::open_with_flags(path, flags)?;
+        let version: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(StoreError::Schema(version));
+        }
+        Ok(Store {
+            connection: Mutex::new(connection),
+            retain: None,
+            max_events: None,
+            pruned: Mutex::new(Instant::now()),
+        })
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Adds `record` to the history of events.
+    pub fn record_event(&self, record: &EventRecord) -> Result<(), StoreError> {
+        self.record_events(std::slice::from_ref(record))
+    }
+
+    /// Adds the records of a batch to the history of events, in one transaction.
+    pub fn record_events(&self, records: &[EventRecord]) -> Result<(), StoreError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        for record in records {
+            let tampered: Vec<_> = record
+                .tampered
+                .iter()
+                .copied()
+                .map(Tampering::as_str)
+                .collect();
+            transaction.execute(
+                "INSERT INTO events
+                 (timestamp, kind, path, from_path, tags, watch_group, tampered, failure)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    millis(record.timestamp),
+                    record.kind.as_str(),
+                    record.path.to_string_lossy(),
+                    record.from.as_ref().map(|from| from.to_string_lossy()),
+                    Value::from(record.tags.clone()).to_string(),
+                    record.group,
+                    Value::from(tampered).to_string(),
+                    record.failure,
+                ],
+            )?;
+        }
+        transaction.commit()?;
+        drop(connection);
+        self.prune_if_due()
+    }
+
+    /// Adds `record` to the history of actions.
+    pub fn record_action(&self, record: &ActionRecord) -> Result<(), StoreError> {
+        self.connection().execute(
+            "INSERT INTO actions (timestamp, command, kind, path, attempts, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                millis(record.timestamp),
+                record.command,
+                record.kind.as_str(),
+                record.path.to_string_lossy(),
+                record.attempts,
+                record.error,
+            ],
+        )?;
+        self.prune_if_due()
+    }
+
+    /// Records `state` as the baseline, in place of the one recorded before.
+    pub fn record_baseline(&self, state: &WatchState) -> Result<(), StoreError> {
+        let mut text = Vec::new();
+        state.write(&mut text)?;
+        self.connection().execute(
+            "INSERT OR REPLACE INTO baseline (id, recorded, state) VALUES (1, ?1, ?2)",
+            params![millis(SystemTime::now()), String::from_utf8_lossy(&text)],
+        )?;
+        Ok(())
+    }
+
+    /// The baseline last recorded, if there is one.
+    pub fn baseline(&self) -> Result<Option<WatchState>, StoreError> {
+        let text: Option<String> = self
+            .connection()
+            .query_row("SELECT state FROM baseline WHERE id = 1", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        match text {
+            Some(text) => Ok(Some(WatchState::read(text.as_bytes())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The events `query` picks, oldest first.
+    pub fn events(&self, query: &StoreQuery) -> Result<Vec<EventRecord>, StoreError> {
+        self.select(
+            "SELECT timestamp, kind, path, from_path, tags, watch_group, tampered, failure
+             FROM events",
+            "(path = ?2 OR substr(path, 1, length(?3)) = ?3
+              OR from_path = ?2 OR substr(from_path, 1, length(?3)) = ?3)",
+            query,
+            |row| {
+                let list = |text: String| -> Result<Vec<String>, StoreError> {
+                    serde_json::from_str(&text).map_err(|_| StoreError::Invalid(text))
+                };
+                let tampered = list(row.get(6)?)?
+                    .into_iter()
+                    .map(|tampering| {
+                        tampering
+                            .parse()
+                            .map_err(|()| StoreError::Invalid(tampering))
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(EventRecord {
+                    timestamp: time(row.get(0)?),
+                    kind: kind(row.get(1)?)?,
+                    path: PathBuf::from(row.get::<_, String>(2)?),
+                    from: row.get::<_, Option<String>>(3)?.map(PathBuf::from),
+                    tags: list(row.get(4)?)?,
+                    group: row.get(5)?,
+                    tampered,
+                    failure: row.get(7)?,
+                })
+            },
+        )
+    }
+
+    /// The results of the actions `query` picks by the events they ran for, oldest first.
+    pub fn actions(&self, query: &StoreQuery) -> Result<Vec<ActionRecord>, StoreError> {
+        self.select(
+            "SELECT timestamp, command, kind, path, attempts, error FROM actions",
+            "(path = ?2 OR substr(path, 1, length(?3)) = ?3)",
+            query,
+            |row| {
+                Ok(ActionRecord {
+                    timestamp: time(row.get(0)?),
+                    command: row.get(1)?,
+                    kind: kind(row.get(2)?)?,
+                    path: PathBuf::from(row.get::<_, String>(3)?),
+                    attempts: row.get(4)?,
+                    error: row.get(5)?,
+                })
+            },
+        )
+    }
+
+    /// Runs `select` for the rows `query` picks, with `under` matching those for its path,
+    /// and turns each into a `T` with `of`.
+    fn select<T>(
+        &self,
+        select: &str,
+        under: &str,
+        query: &StoreQuery,
+        of: impl Fn(&Row<'_>) -> Result<T, StoreError>,
+    ) -> Result<Vec<T>, StoreError> {
+        let sql = format!(
+            "{select} WHERE timestamp >= ?1 AND (?2 IS NULL OR {under})
+             AND (?4 IS NULL OR kind = ?4) ORDER BY timestamp DESC, id DESC LIMIT ?5"
+        );
+        let connection = self.connection();
+        let mut statement = connection.prepare(&sql)?;
+        let mut rows = statement.query(query.bindings())?;
+        let mut found = Vec::new();
+        while let Some(row) = rows.next()? {
+            found.push(of(row)?);
+        }
+        found.reverse();
+        Ok(found)
+    }
+
+    fn prune_if_due(&self) -> Result<(), StoreError> {
+        let mut pruned = self
+            .pruned
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if pruned.elapsed() < PRUNE_EVERY {
+            return Ok(());
+        }
+        *pruned = Instant::now();
+        drop(pruned);
+        self.prune(SystemTime::now()).map(drop)
+    }
+
+    /// Deletes the events and action results older than the store retains as of `now`, and
+    /// the oldest past its `max_events`, returning how many were deleted.
+    pub fn prune(&self, now: SystemTime) -> Result<usize, StoreError> {
+        let connection = self.connection();
+        let mut deleted = 0;
+        for table in ["events", "actions"] {
+            if let Some(retain) = self.retain {
+                let before = now.checked_sub(retain).map_or(0, millis);
+                deleted += connection.execute(
+                    &format!("DELETE FROM {table} WHERE timestamp < ?1"),
+                    [before],
+                )?;
+            }
+            if let Some(max) = self.max_events {
+                deleted += connection.execute(
+                    &format!(
+                        "DELETE FROM {table} WHERE id <=
+                         (SELECT id FROM {table} ORDER BY id DESC LIMIT 1 OFFSET ?1)"
+                    ),
+                    [i64::try_from(max).unwrap_or(i64::MAX)],
+                )?;
+            }
+        }
+        if deleted > 0 {
+            tracing::debug!("pruned {deleted} rows from the store");
+        }
+        Ok(deleted)
+    }
+}
+
+/// `time` as the milliseconds since the Unix epoch the store keeps it as.
+fn millis(time: SystemTime) -> i64 {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    since.as_millis().try_into().unwrap_or(i64::MAX)
+}
+
+fn time(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.try_into().unwrap_or_default())
+}
+
+fn kind(kind: String) -> Result<EventKind, StoreError> {
+    kind.parse().map_err(|()| StoreError::Invalid(kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use configuration::{Config, EventSet};
+
+    use super::*;
+
+    #[test]
+    fn keeps_and_queries_the_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig {
+            path: dir.path().join("store.db"),
+            retain: Some(Duration::from_secs(3600)),
+            max_events: Some(3),
+        };
+        let store = Store::open(&config).unwrap();
+        // What the store keeps, to the millisecond.
+        let now = time(millis(SystemTime::now()));
+        let ago = |secs| now - Duration::from_secs(secs);
+        let etc: Config = "include -r /etc tags=security".parse().unwrap();
+        let record = |path: &str, kind, secs| EventRecord {
+            timestamp: ago(secs),
+            ..EventRecord::new(&Event::new(path, kind), &etc)
+        };
+        let mut tampered = record("/etc/passwd", EventKind::Modify, 20);
+        tampered.tampered = vec![Tampering::Content, Tampering::Owner];
+        let renamed = EventRecord {
+            timestamp: ago(10),
+            failure: Some("gave up on `nginx -s reload` after 1 attempts: failed".to_string()),
+            ..EventRecord::new(&Event::renamed("/etc/nginx", "/srv/nginx"), &etc)
+        };
+        for record in [
+            record("/etc/hosts", EventKind::Create, 7200),
+            record("/srv/index.html", EventKind::Modify, 30),
+        ] {
+            store.record_event(&record).unwrap();
+        }
+        let batch = [
+            tampered.clone(),
+            record("/etcetera", EventKind::Delete, 15),
+            renamed.clone(),
+        ];
+        store.record_events(&batch).unwrap();
+
+        let query = |since: Option<u64>, path: Option<&str>, kind, limit| StoreQuery {
+            since: since.map(ago),
+            path: path.map(PathBuf::from),
+            kind,
+            limit,
+        };
+        let paths = |query: StoreQuery| -> Vec<String> {
+            let events = store.events(&query).unwrap();
+            events.iter().map(EventRecord::summary).collect()
+        };
+        let test_cases = vec![
+            (
+                query(None, None, None, None),
+                vec![
+                    "create /etc/hosts",
+                    "modify /srv/index.html",
+                    "modify /etc/passwd (tampered: content, owner)",
+                    "delete /etcetera",
+                    "rename /etc/nginx -> /srv/nginx (gave up on `nginx -s reload` after 1 attempts: failed)",
+                ],
+            ),
+            (
+                query(None, Some("/etc/"), None, None),
+                vec![
+                    "create /etc/hosts",
+                    "modify /etc/passwd (tampered: content, owner)",
+                    "rename /etc/nginx -> /srv/nginx (gave up on `nginx -s reload` after 1 attempts: failed)",
+                ],
+            ),
+            (
+                query(Some(25), Some("/srv"), None, None),
+                vec!["rename /etc/nginx -> /srv/nginx (gave up on `nginx -s reload` after 1 attempts: failed)"],
+            ),
+            (
+                query(None, Some("/"), Some(EventKind::Modify), Some(1)),
+                vec!["modify /etc/passwd (tampered: content, owner)"],
+            ),
+        ];
+        for (query, expected) in test_cases {
+            assert_eq!(paths(query.clone()), expected, "{query:?}");
+        }
+        let events = store
+            .events(&query(None, Some("/etc/passwd"), None, None))
+            .unwrap();
+        assert_eq!(events, vec![tampered]);
+
+        assert_eq!(store.prune(now).unwrap(), 2);
+        assert_eq!(store.events(&StoreQuery::default()).unwrap().len(), 3);
+        assert_eq!(store.prune(now + Duration::from_secs(3590)).unwrap(), 2);
+        assert_eq!(store.events(&StoreQuery::default()).unwrap(), vec![renamed]);
+
+        let deploy = Action {
+            events: EventSet::all(),
+            command: "deploy".to_string(),
+        };
+        let failed = ActionRecord::new(
+            &deploy,
+            &Event::new("/srv/app", EventKind::Modify),
+            3,
+            Some("exited with status 2".to_string()),
+        );
+        store.record_action(&failed).unwrap();
+        assert_eq!(
+            failed.summary(),
+            "deploy for modify /srv/app failed after 3 attempts: exited with status 2"
+        );
+        let reader = Store::open_read_only(&config.path).unwrap();
+        let actions = reader
+            .actions(&query(Some(60), Some("/srv"), None, None))
+            .unwrap();
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].to_json()["error"], "exited with status 2");
+        assert!(reader.record_action(&failed).is_err());
+
+        assert!(Store::open_read_only(&dir.path().join("missing.db")).is_err());
+
+        assert!(store.baseline().unwrap().is_none());
+        let motd = dir.path().join("motd");
+        std::fs::write(&motd, "hello").unwrap();
+        let config: Config = format!("include {}", motd.display()).parse().unwrap();
+        let state = WatchState::scan(&config, None);
+        store.record_baseline(&state).unwrap();
+        let recorded = store.baseline().unwrap().unwrap();
+        assert_eq!(recorded.len(), 1);
+        assert!(recorded.changes(&state).is_empty());
+    }
+
+    #[test]
+    fn upgrades_stores_of_version_1() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig {
+            path: dir.path().join("store.db"),
+            retain: None,
+            max_events: None,
+        };
+        let connection = Connection::open(&config.path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE events (
+                     id INTEGER PRIMARY KEY,
+                     timestamp INTEGER NOT NULL,
+                     kind TEXT NOT NULL,
+                     path TEXT NOT NULL,
+                     from_path TEXT,
+                     tags TEXT NOT NULL,
+                     watch_group TEXT,
+                     tampered TEXT NOT NULL
+                 );
+                 INSERT INTO events (timestamp, kind, path, tags, tampered)
+                 VALUES (0, 'modify', '/etc/hosts', '[]', '[]');
+                 PRAGMA user_version = 1;",
+            )
+            .unwrap();
+        drop(connection);
+
+        let store = Store::open(&config).unwrap();
+        let events = store.events(&StoreQuery::default()).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].failure, None);
+        let version: i64 = store
+            .connection()
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+    }
+}
@@ -0,0 +1,127 @@
+//! Keeping a history of what overwatch saw and did, declared with
+//! `store /var/lib/overwatch/store.db retain=30d`, so it can be queried offline with
+//! `overwatch query`. The store needs overwatch built with the `sqlite` feature.
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use configuration::{parse_duration, Config, Directive};
+
+use crate::options::parse_options;
+
+/// How long events and action results are kept in the store without a `retain=` option.
+pub const DEFAULT_RETAIN: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Where the store is kept and how much of the history it holds on to, from a line such as
+/// `store /var/lib/overwatch/store.db retain=7d max_events=100000`.
+///
+/// Events and action results older than `retain` are pruned, `retain=forever` keeping them
+/// all, and past `max_events` of either the oldest are. The baseline recorded with
+/// `overwatch --baseline` is kept until the next one replaces it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreConfig {
+    pub path: PathBuf,
+    pub retain: Option<Duration>,
+    pub max_events: Option<u64>,
+}
+
+impl StoreConfig {
+    /// The settings of the last `store` directive of `config`, if there is one.
+    pub fn from_config(config: &Config) -> Option<StoreConfig> {
+        config
+            .custom_values::<StoreConfig>(StoreDirective::NAME)
+            .last()
+            .cloned()
+    }
+}
+
+impl FromStr for StoreConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let (path, rest) = s.split_once(' ').unwrap_or((s, ""));
+        if !Path::new(path).is_absolute() {
+            return Err(format!("expected an absolute path, found {path:?}"));
+        }
+        let mut store = StoreConfig {
+            path: PathBuf::from(path),
+            retain: Some(DEFAULT_RETAIN),
+            max_events: None,
+        };
+        for (key, value) in parse_options(rest)? {
+            match key {
+                "retain" if value == "forever" => store.retain = None,
+                "retain" => match parse_duration(value) {
+                    Some(retain) if !retain.is_zero() => store.retain = Some(retain),
+                    _ => {
+                        return Err(format!(
+                            "expected a duration above zero or forever, found {value}"
+                        ))
+                    }
+                },
+                "max_events" => match value.parse() {
+                    Ok(max) if max > 0 => store.max_events = Some(max),
+                    _ => return Err(format!("expected a number above zero, found {value}")),
+                },
+                _ => {
+                    return Err(format!(
+                        "unknown option {key}, expected retain or max_events"
+                    ))
+                }
+            }
+        }
+        Ok(store)
+    }
+}
+
+/// The `store` directive, parsing to a [`StoreConfig`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StoreDirective;
+
+impl StoreDirective {
+    pub const NAME: &'static str = "store";
+}
+
+impl Directive for StoreDirective {
+    type Value = StoreConfig;
+
+    fn parse(&self, args: &str) -> Result<StoreConfig, String> {
+        if cfg!(not(feature = "sqlite")) {
+            return Err("the store needs overwatch built with the sqlite feature".to_string());
+        }
+        args.parse()
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_store_lines() {
+        let days = |days: u64| Some(Duration::from_secs(days * 24 * 60 * 60));
+        let test_cases = vec![
+            ("/var/lib/overwatch.db", Ok((days(30), None))),
+            (" /var/lib/overwatch.db retain=7d ", Ok((days(7), None))),
+            (
+                "/var/lib/overwatch.db retain=forever max_events=1000",
+                Ok((None, Some(1000))),
+            ),
+            ("overwatch.db", Err(())),
+            ("./data/overwatch.db retain=7d", Err(())),
+            ("/var/lib/overwatch.db retain=0s", Err(())),
+            ("/var/lib/overwatch.db max_events=0", Err(())),
+            ("/var/lib/overwatch.db keep=5", Err(())),
+        ];
+        for (input, expected) in test_cases {
+            let parsed = StoreDirective
+                .parse(input)
+                .map(|store| (store.retain, store.max_events));
+            assert_eq!(parsed.map_err(|_| ()), expected, "{input}");
+        }
+    }
+}
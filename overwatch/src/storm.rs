@@ -0,0 +1,326 @@
+//! Holding events back while they arrive faster than actions and sinks could keep up with,
+//! declared with `storm_limit 500/s for=10s`.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use configuration::{parse_duration, parse_rate, Config, Directive, RateLimit};
+use watcher::Event;
+
+use crate::options::parse_options;
+
+/// When events count as a storm: more than `rate` allows, period after period, for
+/// `sustain`. A storm passes once they've kept under it for as long again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StormLimit {
+    pub rate: RateLimit,
+    pub sustain: Duration,
+}
+
+impl StormLimit {
+    /// The limit the last `storm_limit` directive of `config` sets, if there is one.
+    pub fn from_config(config: &Config) -> Option<StormLimit> {
+        config
+            .custom_values::<StormLimit>(StormDirective::NAME)
+            .last()
+            .copied()
+    }
+
+    /// How many periods of the rate make up `sustain`, at least one.
+    fn periods(&self) -> u32 {
+        let periods = self
+            .sustain
+            .as_nanos()
+            .div_ceil(self.rate.period.as_nanos());
+        periods.clamp(1, u128::from(u32::MAX)) as u32
+    }
+}
+
+/// Parses the arguments of a `storm_limit` line, such as `500/s for=10s`. Without `for=` one
+/// period over the rate starts a storm.
+impl FromStr for StormLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let (rate, rest) = s.split_once(' ').unwrap_or((s, ""));
+        let rate = parse_rate(rate).ok_or_else(|| {
+            format!("expected a rate, as in storm_limit 500/s for=10s, found {rate:?}")
+        })?;
+        let mut limit = StormLimit {
+            rate,
+            sustain: rate.period,
+        };
+        for (key, value) in parse_options(rest)? {
+            match key {
+                "for" => match parse_duration(value) {
+                    Some(sustain) if !sustain.is_zero() => limit.sustain = sustain,
+                    _ => return Err(format!("expected a duration above zero, found {value}")),
+                },
+                _ => return Err(format!("unknown option {key}, expected for")),
+            }
+        }
+        Ok(limit)
+    }
+}
+
+/// The `storm_limit` directive, parsing to a [`StormLimit`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StormDirective;
+
+impl StormDirective {
+    pub const NAME: &'static str = "storm_limit";
+}
+
+impl Directive for StormDirective {
+    type Value = StormLimit;
+
+    fn parse(&self, args: &str) -> Result<StormLimit, String> {
+        args.parse()
+    }
+}
+
+/// What a [`StormGuard`] has to say, to be logged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StormReport {
+    /// Events went over the limit for long enough, so the ones after are suppressed.
+    Started(StormLimit),
+    /// `events` were suppressed since the last report, all of them under `under`.
+    Suppressed { events: u64, under: PathBuf },
+    /// Events kept under the limit for long enough, after `suppressed` in all were.
+    Ended { suppressed: u64 },
+}
+
+impl fmt::Display for StormReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StormReport::Started(limit) => write!(
+                f,
+                "event storm: more than {} for {:?}, suppressing events until it passes",
+                limit.rate, limit.sustain
+            ),
+            StormReport::Suppressed { events, under } => {
+                write!(f, "{events} events under {} suppressed", under.display())
+            }
+            StormReport::Ended { suppressed } => write!(
+                f,
+                "event storm passed after {suppressed} events were suppressed"
+            ),
+        }
+    }
+}
+
+/// The events held back in a storm.
+#[derive(Debug, Default)]
+struct Storm {
+    total: u64,
+    /// Those since the last report, and the deepest directory they all fell under.
+    pending: u64,
+    under: Option<PathBuf>,
+    /// The periods gone since the last report.
+    periods: u32,
+}
+
+impl Storm {
+    fn suppress(&mut self, path: &Path) {
+        self.total += 1;
+        self.pending += 1;
+        self.under = Some(match self.under.take() {
+            Some(under) => common_ancestor(&under, path),
+            None => path.parent().unwrap_or(path).to_path_buf(),
+        });
+    }
+
+    /// Reports the events suppressed since the last report, if there were any.
+    fn report(&mut self, reports: &mut Vec<StormReport>) {
+        self.periods = 0;
+        if let Some(under) = self.under.take() {
+            reports.push(StormReport::Suppressed {
+                events: std::mem::take(&mut self.pending),
+                under,
+            });
+        }
+    }
+}
+
+fn common_ancestor(a: &Path, b: &Path) -> PathBuf {
+    a.components()
+        .zip(b.components())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a)
+        .collect()
+}
+
+/// Counts the events read in each period of a [`StormLimit`]'s rate, and suppresses them
+/// while there's a storm, so a runaway build can't flood the actions and sinks. Once a
+/// `sustain` of the storm goes by, what was suppressed in it is summed up in a report.
+#[derive(Debug)]
+pub struct StormGuard {
+    limit: StormLimit,
+    /// When the current period started, and how many events arrived in it.
+    started: Instant,
+    events: u64,
+    /// The periods in a row over the limit, or in a storm under it.
+    streak: u32,
+    storm: Option<Storm>,
+}
+
+impl StormGuard {
+    pub fn new(limit: StormLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            started: now,
+            events: 0,
+            streak: 0,
+            storm: None,
+        }
+    }
+
+    pub fn limit(&self) -> StormLimit {
+        self.limit
+    }
+
+    /// Returns true during a storm.
+    pub fn is_storming(&self) -> bool {
+        self.storm.is_some()
+    }
+
+    /// Closes the periods which ended by `now`, returning what became of the storm.
+    pub fn tick(&mut self, now: Instant) -> Vec<StormReport> {
+        let mut reports = Vec::new();
+        let period = self.limit.rate.period;
+        let elapsed = now.saturating_duration_since(self.started);
+        let periods = elapsed.as_nanos() / period.as_nanos();
+        if periods == 0 {
+            return reports;
+        }
+        let events = std::mem::take(&mut self.events);
+        self.close(events > u64::from(self.limit.rate.events), &mut reports);
+        // The periods after the first had no events, and more of them than `sustain` ends
+        // any storm.
+        let quiet = (periods - 1).min(u128::from(self.limit.periods()));
+        for _ in 0..quiet {
+            self.close(false, &mut reports);
+        }
+        let into = elapsed.as_nanos() % period.as_nanos();
+        self.started = now - Duration::from_nanos(into as u64);
+        reports
+    }
+
+    fn close(&mut self, over: bool, reports: &mut Vec<StormReport>) {
+        let periods = self.limit.periods();
+        let Some(storm) = &mut self.storm else {
+            self.streak = if over { self.streak + 1 } else { 0 };
+            if self.streak >= periods {
+                self.streak = 0;
+                self.storm = Some(Storm::default());
+                reports.push(StormReport::Started(self.limit));
+            }
+            return;
+        };
+        self.streak = if over { 0 } else { self.streak + 1 };
+        storm.periods += 1;
+        if self.streak >= periods {
+            storm.report(reports);
+            reports.push(StormReport::Ended {
+                suppressed: storm.total,
+            });
+            self.streak = 0;
+            self.storm = None;
+        } else if storm.periods >= periods {
+            storm.report(reports);
+        }
+    }
+
+    /// Counts `events` towards the current period, returning false if they're suppressed.
+    pub fn admit(&mut self, events: &[Event]) -> bool {
+        self.events += events.len() as u64;
+        let Some(storm) = &mut self.storm else {
+            return true;
+        };
+        for event in events {
+            storm.suppress(&event.path);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use configuration::EventKind;
+
+    use super::*;
+
+    #[test]
+    fn suppresses_events_during_a_storm() {
+        let test_cases = vec![
+            ("10/s", Ok((10, 1000, 1000))),
+            (" 500/s for=10s ", Ok((500, 1000, 10_000))),
+            ("5/100ms for=250ms", Ok((5, 100, 250))),
+            ("fast", Err(())),
+            ("10/s for=0s", Err(())),
+            ("10/s burst=20", Err(())),
+        ];
+        for (input, expected) in test_cases {
+            let limit = StormDirective.parse(input).map(|limit| {
+                (
+                    limit.rate.events,
+                    limit.rate.period.as_millis(),
+                    limit.sustain.as_millis(),
+                )
+            });
+            assert_eq!(limit.map_err(|_| ()), expected, "{input}");
+        }
+
+        let limit: StormLimit = "2/s for=2s".parse().unwrap();
+        let start = Instant::now();
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+        let events = |paths: &[&str]| -> Vec<Event> {
+            paths
+                .iter()
+                .map(|path| Event::new(*path, EventKind::Modify))
+                .collect()
+        };
+        let mut guard = StormGuard::new(limit, start);
+        let build = events(&["/srv/app/target/a.o", "/srv/app/target/b.o", "/srv/app/c"]);
+
+        assert!(guard.admit(&build));
+        assert_eq!(guard.tick(at(1.0)), vec![]);
+        assert!(guard.admit(&build));
+        assert_eq!(guard.tick(at(2.5)), vec![StormReport::Started(limit)]);
+        assert!(guard.is_storming());
+
+        assert!(!guard.admit(&build[..2]));
+        assert!(!guard.admit(&build[..1]));
+        assert_eq!(guard.tick(at(3.0)), vec![]);
+        assert!(!guard.admit(&build));
+        let reports = guard.tick(at(4.0));
+        assert_eq!(
+            reports,
+            vec![StormReport::Suppressed {
+                events: 6,
+                under: PathBuf::from("/srv/app"),
+            }]
+        );
+        assert_eq!(reports[0].to_string(), "6 events under /srv/app suppressed");
+
+        assert!(!guard.admit(&build[..1]));
+        assert_eq!(guard.tick(at(5.0)), vec![]);
+        assert_eq!(
+            guard.tick(at(20.0)),
+            vec![
+                StormReport::Suppressed {
+                    events: 1,
+                    under: PathBuf::from("/srv/app/target"),
+                },
+                StormReport::Ended { suppressed: 7 },
+            ]
+        );
+        assert!(guard.admit(&build));
+        assert_eq!(guard.tick(at(21.0)), vec![]);
+    }
+}
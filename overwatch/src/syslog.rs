@@ -0,0 +1,466 @@
+//! Sending events to syslog as RFC 5424 messages, declared with
+//! `notify syslog udp://<host>[:port]`, `tcp://<host>[:port]` or `unix://<path>`.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    net::{TcpStream, ToSocketAddrs, UdpSocket},
+    str::FromStr,
+};
+#[cfg(unix)]
+use std::{os::unix::net::UnixDatagram, path::PathBuf};
+
+use configuration::EventKind;
+
+use crate::{time::rfc3339, EventRecord, Sink, SinkError};
+
+/// The port syslog listens on when the target doesn't give one.
+pub const DEFAULT_PORT: u16 = 514;
+
+/// The structured data ID events are described under, with the enterprise number set aside
+/// for examples, as overwatch has none of its own.
+const SD_ID: &str = "overwatch@32473";
+
+const FACILITIES: [&str; 24] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv",
+    "ftp", "ntp", "audit", "alert", "clock", "local0", "local1", "local2", "local3", "local4",
+    "local5", "local6", "local7",
+];
+
+/// The facility messages are logged under, by its usual name such as `daemon` or `local0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Facility(u8);
+
+impl Facility {
+    pub const DAEMON: Facility = Facility(3);
+
+    pub fn code(self) -> u8 {
+        self.0
+    }
+
+    pub fn as_str(self) -> &'static str {
+        FACILITIES[self.0 as usize]
+    }
+}
+
+impl fmt::Display for Facility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Facility {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        FACILITIES
+            .iter()
+            .position(|name| *name == s)
+            .map(|code| Facility(code as u8))
+            .ok_or_else(|| format!("unknown facility {s}, expected one such as daemon or local0"))
+    }
+}
+
+/// How serious a message is, from `emerg` down to `debug`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Emergency,
+    Alert,
+    Critical,
+    Error,
+    Warning,
+    Notice,
+    Info,
+    Debug,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 8] = [
+        Severity::Emergency,
+        Severity::Alert,
+        Severity::Critical,
+        Severity::Error,
+        Severity::Warning,
+        Severity::Notice,
+        Severity::Info,
+        Severity::Debug,
+    ];
+
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Emergency => "emerg",
+            Severity::Alert => "alert",
+            Severity::Critical => "crit",
+            Severity::Error => "err",
+            Severity::Warning => "warning",
+            Severity::Notice => "notice",
+            Severity::Info => "info",
+            Severity::Debug => "debug",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Severity::ALL
+            .into_iter()
+            .find(|severity| severity.as_str() == s)
+            .ok_or_else(|| format!("unknown severity {s}, expected one such as notice or warning"))
+    }
+}
+
+/// The severity of each kind of event, in the order of [`EventKind::ALL`], unless a
+/// `severity.<event>` option sets it: `warning` for deletes and `notice` for the rest.
+pub const DEFAULT_SEVERITIES: [Severity; 4] = [
+    Severity::Notice,
+    Severity::Notice,
+    Severity::Warning,
+    Severity::Notice,
+];
+
+/// Applies a `severity.delete=warning` option to `severities`, returning false if `key` is
+/// some other option.
+pub(crate) fn severity_option(
+    severities: &mut [Severity; 4],
+    key: &str,
+    value: &str,
+) -> Result<bool, String> {
+    let Some(kind) = key.strip_prefix("severity.") else {
+        return Ok(false);
+    };
+    let kind: EventKind = kind
+        .parse()
+        .map_err(|_| format!("unknown event {kind} in {key}"))?;
+    severities[kind as usize] = value.parse()?;
+    Ok(true)
+}
+
+/// Where syslog messages are sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyslogTransport {
+    /// One datagram per message to `host:port`.
+    Udp(String),
+    /// A stream to `host:port`, with each message preceded by its length as RFC 6587 frames
+    /// them.
+    Tcp(String),
+    /// One datagram per message to a local socket such as `/dev/log`.
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl SyslogTransport {
+    fn parse(target: &str) -> Result<Self, String> {
+        let address = |address: &str| {
+            if address.is_empty() {
+                Err(format!("expected a host in {target}"))
+            } else if address.contains(':') {
+                Ok(address.to_string())
+            } else {
+                Ok(format!("{address}:{DEFAULT_PORT}"))
+            }
+        };
+        if let Some(rest) = target.strip_prefix("udp://") {
+            Ok(SyslogTransport::Udp(address(rest)?))
+        } else if let Some(rest) = target.strip_prefix("tcp://") {
+            Ok(SyslogTransport::Tcp(address(rest)?))
+        } else if let Some(path) = target.strip_prefix("unix://") {
+            #[cfg(unix)]
+            return Ok(SyslogTransport::Unix(PathBuf::from(path)));
+            #[cfg(not(unix))]
+            return Err(format!("unix sockets are not supported here, found {path}"));
+        } else {
+            Err(format!(
+                "expected a udp://, tcp:// or unix:// target, found {target:?}"
+            ))
+        }
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        match self {
+            SyslogTransport::Udp(address) => {
+                let addr = address.to_socket_addrs()?.next().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, format!("no address for {address}"))
+                })?;
+                let local = if addr.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(addr)?;
+                Ok(Connection::Udp(socket))
+            }
+            SyslogTransport::Tcp(address) => Ok(Connection::Tcp(TcpStream::connect(address)?)),
+            #[cfg(unix)]
+            SyslogTransport::Unix(path) => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                Ok(Connection::Unix(socket))
+            }
+        }
+    }
+}
+
+/// The settings of a `notify syslog <target>` line:
+///
+/// - `facility=local0` logs under that facility, `daemon` by default,
+/// - `severity.delete=warning` sets the severity of one kind of event, see
+///   [`DEFAULT_SEVERITIES`],
+/// - `app=name` sets the APP-NAME of the messages, `overwatch` by default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyslogConfig {
+    pub transport: SyslogTransport,
+    pub facility: Facility,
+    /// The severity of each kind of event, in the order of [`EventKind::ALL`].
+    pub severities: [Severity; 4],
+    pub app: String,
+}
+
+impl SyslogConfig {
+    pub(crate) fn parse(target: &str, options: &[(&str, &str)]) -> Result<Self, String> {
+        let mut config = SyslogConfig {
+            transport: SyslogTransport::parse(target)?,
+            facility: Facility::DAEMON,
+            severities: DEFAULT_SEVERITIES,
+            app: "overwatch".to_string(),
+        };
+        for (key, value) in options {
+            if severity_option(&mut config.severities, key, value)? {
+                continue;
+            }
+            match *key {
+                "facility" => config.facility = value.parse()?,
+                "app" if !value.is_empty() && !value.contains(' ') => {
+                    config.app = value.to_string();
+                }
+                "app" => {
+                    return Err(format!(
+                        "expected an app name without spaces, found {value:?}"
+                    ))
+                }
+                _ => {
+                    return Err(format!(
+                        "unknown option {key}, expected facility, severity.<event>, app or tags"
+                    ))
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn severity_for(&self, kind: EventKind) -> Severity {
+        self.severities[kind as usize]
+    }
+}
+
+#[derive(Debug)]
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+}
+
+impl Connection {
+    fn write(&mut self, message: &str) -> io::Result<()> {
+        match self {
+            Connection::Udp(socket) => socket.send(message.as_bytes()).map(|_| ()),
+            Connection::Tcp(stream) => write!(stream, "{} {message}", message.len()),
+            #[cfg(unix)]
+            Connection::Unix(socket) => socket.send(message.as_bytes()).map(|_| ()),
+        }
+    }
+}
+
+/// Sends each event as an RFC 5424 message, such as
+///
+/// ```text
+/// <29>1 2024-05-01T12:30:00.250Z web1 overwatch 812 modify [overwatch@32473 path="/srv/index.html" tags="web"] modify /srv/index.html
+/// ```
+///
+/// with the kind of event as the MSGID, and its path, old path, tags and watch group as
+/// structured data. The connection is made on the first event, and made again after it fails.
+#[derive(Debug)]
+pub struct Syslog {
+    config: SyslogConfig,
+    hostname: String,
+    connection: Option<Connection>,
+}
+
+impl Syslog {
+    pub fn new(config: SyslogConfig) -> Self {
+        Self {
+            config,
+            hostname: hostname(),
+            connection: None,
+        }
+    }
+
+    /// The message describing `record`.
+    pub fn message(&self, record: &EventRecord) -> String {
+        let priority =
+            self.config.facility.code() * 8 + self.config.severity_for(record.kind).code();
+        let mut data = format!(
+            "[{SD_ID} path=\"{}\"",
+            escape(&record.path.to_string_lossy())
+        );
+        if let Some(from) = &record.from {
+            data.push_str(&format!(" from=\"{}\"", escape(&from.to_string_lossy())));
+        }
+        if !record.tags.is_empty() {
+            data.push_str(&format!(" tags=\"{}\"", escape(&record.tags.join(","))));
+        }
+        if let Some(group) = &record.group {
+            data.push_str(&format!(" group=\"{}\"", escape(group)));
+        }
+        data.push(']');
+        format!(
+            "<{priority}>1 {} {} {} {} {} {data} {}",
+            rfc3339(record.timestamp),
+            self.hostname,
+            self.config.app,
+            std::process::id(),
+            record.kind,
+            record.summary(),
+        )
+    }
+
+    fn write(&mut self, message: &str) -> io::Result<()> {
+        let connection = match &mut self.connection {
+            Some(connection) => connection,
+            None => self.connection.insert(self.config.transport.connect()?),
+        };
+        let result = connection.write(message);
+        if result.is_err() {
+            self.connection = None;
+        }
+        result
+    }
+}
+
+impl Sink for Syslog {
+    fn send(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        let message = self.message(record);
+        let reused = self.connection.is_some();
+        match self.write(&message) {
+            // A stream the server has since closed only fails once written to.
+            Err(_) if reused => self.write(&message)?,
+            result => result?,
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), SinkError> {
+        if let Some(Connection::Tcp(stream)) = &mut self.connection {
+            stream.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Escapes the characters RFC 5424 doesn't allow in a parameter value.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The name of this machine, or `-` for none when it can't be found.
+fn hostname() -> String {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty() && !name.contains(' '))
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn parses_syslog_options() {
+        let test_cases = vec![
+            ("udp://logs", "", Ok((3, [5, 5, 4, 5]))),
+            (
+                "tcp://logs:6514",
+                "facility=local0 severity.modify=info severity.delete=crit",
+                Ok((16, [5, 6, 2, 5])),
+            ),
+            ("udp://logs", "app=watchdog", Ok((3, [5, 5, 4, 5]))),
+            ("udp://logs", "facility=local8", Err(())),
+            ("udp://logs", "severity.delete=loud", Err(())),
+            ("udp://logs", "severity.touch=info", Err(())),
+            ("udp://logs", "app=\"two words\"", Err(())),
+            ("logs:514", "", Err(())),
+            ("udp://", "", Err(())),
+        ];
+        for (target, options, expected) in test_cases {
+            let options = crate::options::parse_options(options).unwrap();
+            let parsed = SyslogConfig::parse(target, &options).map(|config| {
+                (
+                    config.facility.code(),
+                    config.severities.map(Severity::code),
+                )
+            });
+            assert_eq!(parsed.map_err(|_| ()), expected, "{target} {options:?}");
+        }
+        assert_eq!(
+            SyslogConfig::parse("udp://logs", &[]).unwrap().transport,
+            SyslogTransport::Udp(format!("logs:{DEFAULT_PORT}"))
+        );
+    }
+
+    #[test]
+    fn sends_rfc_5424_messages() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = format!("udp://{}", server.local_addr().unwrap());
+        let options = [("facility", "local0"), ("app", "watchdog")];
+        let mut syslog = Syslog::new(SyslogConfig::parse(&target, &options).unwrap());
+        syslog.hostname = "web1".to_string();
+        let record = EventRecord {
+            path: "/opt/app/main.rs".into(),
+            kind: EventKind::Rename,
+            from: Some("/opt/app/\"old\".rs".into()),
+            timestamp: UNIX_EPOCH + Duration::from_millis(1_714_566_600_250),
+            tags: vec!["deploy".to_string(), "web".to_string()],
+            group: Some("app".to_string()),
+            tampered: Vec::new(),
+            failure: None,
+        };
+        syslog.send(&record).unwrap();
+
+        let mut buf = [0; 1024];
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buf[..len]).unwrap(),
+            format!(
+                "<133>1 2024-05-01T12:30:00.250Z web1 watchdog {} rename [overwatch@32473 \
+                 path=\"/opt/app/main.rs\" from=\"/opt/app/\\\"old\\\".rs\" tags=\"deploy,web\" \
+                 group=\"app\"] rename /opt/app/\"old\".rs -> /opt/app/main.rs",
+                std::process::id()
+            )
+        );
+    }
+}
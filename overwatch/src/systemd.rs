@@ -0,0 +1,143 @@
+//! Telling systemd how a `Type=notify` service is doing, as `sd_notify` does.
+
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::{
+    env, io,
+    os::unix::net::{SocketAddr, UnixDatagram},
+    time::Duration,
+};
+
+/// Sends state changes to the socket systemd names in `NOTIFY_SOCKET`, and says how often it
+/// expects `WATCHDOG=1` pings. Without the variable, as when not started by systemd, it sends
+/// nothing.
+#[derive(Debug)]
+pub struct SdNotify {
+    socket: Option<(UnixDatagram, SocketAddr)>,
+    watchdog: Option<Duration>,
+}
+
+impl SdNotify {
+    /// Reads `NOTIFY_SOCKET`, `WATCHDOG_USEC` and `WATCHDOG_PID` from the environment.
+    pub fn from_env() -> io::Result<Self> {
+        let var = |name| env::var(name).ok();
+        Self::from_vars(
+            var("NOTIFY_SOCKET").as_deref(),
+            var("WATCHDOG_USEC").as_deref(),
+            var("WATCHDOG_PID").as_deref(),
+        )
+    }
+
+    fn from_vars(
+        socket: Option<&str>,
+        watchdog_usec: Option<&str>,
+        watchdog_pid: Option<&str>,
+    ) -> io::Result<Self> {
+        let socket = match socket.filter(|socket| !socket.is_empty()) {
+            Some(name) => Some((UnixDatagram::unbound()?, address(name)?)),
+            None => None,
+        };
+        let ours = watchdog_pid.is_none_or(|pid| pid.parse() == Ok(std::process::id()));
+        let watchdog = watchdog_usec
+            .and_then(|usec| usec.parse().ok())
+            .filter(|usec| *usec > 0 && ours)
+            .map(Duration::from_micros);
+        Ok(Self { socket, watchdog })
+    }
+
+    /// Returns true if systemd is listening.
+    pub fn is_enabled(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    /// How often to send [`SdNotify::watchdog`] pings: half the watchdog timeout, if systemd
+    /// set one for this process.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.socket.as_ref().and(self.watchdog).map(|usec| usec / 2)
+    }
+
+    /// Says the service has started, with `status` to show in `systemctl status`.
+    pub fn ready(&self, status: &str) -> io::Result<()> {
+        self.send(&format!("READY=1\nSTATUS={status}"))
+    }
+
+    /// Says the configuration is being reloaded, until [`SdNotify::ready`] is sent again.
+    pub fn reloading(&self) -> io::Result<()> {
+        self.send(&format!(
+            "RELOADING=1\nMONOTONIC_USEC={}",
+            monotonic().as_micros()
+        ))
+    }
+
+    /// Says the service is shutting down.
+    pub fn stopping(&self) -> io::Result<()> {
+        self.send("STOPPING=1")
+    }
+
+    /// Tells the watchdog the service is still running.
+    pub fn watchdog(&self) -> io::Result<()> {
+        self.send("WATCHDOG=1")
+    }
+
+    fn send(&self, state: &str) -> io::Result<()> {
+        match &self.socket {
+            Some((socket, address)) => socket.send_to_addr(state.as_bytes(), address).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The address `NOTIFY_SOCKET` names: a path, or on Linux an abstract socket after `@`.
+fn address(name: &str) -> io::Result<SocketAddr> {
+    #[cfg(target_os = "linux")]
+    if let Some(name) = name.strip_prefix('@') {
+        return SocketAddr::from_abstract_name(name);
+    }
+    SocketAddr::from_pathname(name)
+}
+
+/// The time on the monotonic clock systemd compares `RELOADING=1` against.
+fn monotonic() -> Duration {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `now` is a valid timespec for the call to fill in.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_states_to_the_notify_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let systemd = UnixDatagram::bind(&path).unwrap();
+        let pid = std::process::id().to_string();
+        let notify =
+            SdNotify::from_vars(path.to_str(), Some("10000000"), Some(pid.as_str())).unwrap();
+        assert_eq!(notify.watchdog_interval(), Some(Duration::from_secs(5)));
+
+        let mut buf = [0; 256];
+        let mut received = || {
+            let len = systemd.recv(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf[..len]).into_owned()
+        };
+        notify.ready("watching 3 includes").unwrap();
+        assert_eq!(received(), "READY=1\nSTATUS=watching 3 includes");
+        notify.watchdog().unwrap();
+        assert_eq!(received(), "WATCHDOG=1");
+        notify.reloading().unwrap();
+        assert!(received().starts_with("RELOADING=1\nMONOTONIC_USEC="));
+
+        let elsewhere = SdNotify::from_vars(path.to_str(), Some("10000000"), Some("1")).unwrap();
+        assert_eq!(elsewhere.watchdog_interval(), None);
+        let off = SdNotify::from_vars(None, Some("10000000"), None).unwrap();
+        assert!(!off.is_enabled());
+        assert_eq!(off.watchdog_interval(), None);
+        off.ready("ignored").unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+//! Filling in the details of an event where an action's command asks for them.
+
+use std::{error::Error, fmt, path::Path};
+
+use watcher::Event;
+
+use crate::time::rfc3339;
+
+/// The placeholders a command can use, each written in braces as in `{path}`:
+///
+/// - `{path}` is the path the event happened to,
+/// - `{event}` its kind, such as `modify`,
+/// - `{dir}` the directory the path is in,
+/// - `{filename}` the last component of the path,
+/// - `{timestamp}` when the event was received, in RFC 3339 form in UTC.
+pub const PLACEHOLDERS: [&str; 5] = ["path", "event", "dir", "filename", "timestamp"];
+
+/// A placeholder written where its value can't be quoted safely: inside double quotes or
+/// backticks, where the shell would still expand a `$(...)` in a file's name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateError {
+    pub placeholder: String,
+    /// `double quotes` or `backticks`.
+    pub inside: &'static str,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{{{}}} is inside {}, where the shell would expand what it holds, write it \
+             outside them, as in `logger \"changed\" {{{}}}`",
+            self.placeholder, self.inside, self.placeholder
+        )
+    }
+}
+
+impl Error for TemplateError {}
+
+/// How the shell reads the text at some point of a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quoting {
+    Unquoted,
+    Single,
+    Double,
+    Backtick,
+}
+
+/// `command` with every placeholder replaced by its value for `event`, quoted for the shell
+/// so a path with spaces, quotes or `$(...)` in it stays one argument and is never run as
+/// code. A placeholder inside single quotes is filled in without closing them, and one inside
+/// double quotes or backticks is an error, since the shell would expand its value there.
+/// Anything else in braces, such as the `{}` of `find -exec` or a shell's `${HOME}`, is left as
+/// written.
+pub fn render(command: &str, event: &Event) -> Result<String, TemplateError> {
+    fill(command, |name| value(name, event))
+}
+
+/// Checks that every placeholder of `command` is where [`render`] can fill it in.
+pub fn check(command: &str) -> Result<(), TemplateError> {
+    fill(command, |name| {
+        PLACEHOLDERS.contains(&name).then(String::new)
+    })
+    .map(drop)
+}
+
+/// `command` with the placeholders `value` knows the value of filled in, following the
+/// quoting of `sh`, or on Windows the double quotes of `cmd`.
+fn fill(command: &str, value: impl Fn(&str) -> Option<String>) -> Result<String, TemplateError> {
+    let mut rendered = String::with_capacity(command.len());
+    let mut quoting = Quoting::Unquoted;
+    let mut escaped = false;
+    let mut rest = command;
+    while let Some(c) = rest.chars().next() {
+        let placeholder = (c == '{')
+            .then(|| rest.find('}'))
+            .flatten()
+            .and_then(|end| Some((&rest[1..end], value(&rest[1..end])?, end)));
+        if let Some((name, value, end)) = placeholder {
+            let inside = match quoting {
+                Quoting::Unquoted => {
+                    rendered.push_str(&quote(&value));
+                    None
+                }
+                Quoting::Single => {
+                    rendered.push_str(&value.replace('\'', r"'\''"));
+                    None
+                }
+                Quoting::Double => Some("double quotes"),
+                Quoting::Backtick => Some("backticks"),
+            };
+            if let Some(inside) = inside {
+                return Err(TemplateError {
+                    placeholder: name.to_string(),
+                    inside,
+                });
+            }
+            rest = &rest[end + 1..];
+            continue;
+        }
+        rendered.push(c);
+        rest = &rest[c.len_utf8()..];
+        if std::mem::take(&mut escaped) {
+            continue;
+        }
+        quoting = match (quoting, c) {
+            // cmd has neither escapes nor single quotes, and backslashes separate paths.
+            (Quoting::Unquoted | Quoting::Double | Quoting::Backtick, '\\') if !cfg!(windows) => {
+                escaped = true;
+                quoting
+            }
+            (Quoting::Unquoted, '\'') if !cfg!(windows) => Quoting::Single,
+            (Quoting::Unquoted, '"') => Quoting::Double,
+            (Quoting::Unquoted, '`') if !cfg!(windows) => Quoting::Backtick,
+            (Quoting::Single, '\'') | (Quoting::Double, '"') | (Quoting::Backtick, '`') => {
+                Quoting::Unquoted
+            }
+            (quoting, _) => quoting,
+        };
+    }
+    Ok(rendered)
+}
+
+/// The value of the placeholder `name` for `event`, if it is one.
+fn value(name: &str, event: &Event) -> Option<String> {
+    let lossy = |path: Option<&Path>| path.map(|path| path.to_string_lossy().into_owned());
+    Some(match name {
+        "path" => event.path.to_string_lossy().into_owned(),
+        "event" => event.kind.to_string(),
+        "dir" => lossy(event.path.parent()).unwrap_or_default(),
+        "filename" => lossy(event.path.file_name().map(Path::new)).unwrap_or_default(),
+        "timestamp" => rfc3339(event.timestamp),
+        _ => return None,
+    })
+}
+
+/// `value` quoted as one argument for the shell commands are run with: in single quotes for
+/// `sh`, and in double quotes with embedded ones doubled for `cmd` on Windows.
+pub fn quote(value: &str) -> String {
+    if cfg!(windows) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{
+        process::Command,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use configuration::EventKind;
+
+    use super::*;
+
+    #[test]
+    fn fills_in_placeholders() {
+        let event = Event {
+            timestamp: UNIX_EPOCH + Duration::from_secs(1_714_566_600),
+            ..Event::new("/srv/it's here/a b.txt", EventKind::Modify)
+        };
+        let test_cases = vec![
+            ("cat {path}", r"cat '/srv/it'\''s here/a b.txt'"),
+            ("echo {event} {filename}", "echo 'modify' 'a b.txt'"),
+            ("cd {dir} && make", r"cd '/srv/it'\''s here' && make"),
+            ("logger {timestamp}", "logger '2024-05-01T12:30:00.000Z'"),
+            (
+                "find . -exec rm {} \\; ${HOME} {unknown}",
+                "find . -exec rm {} \\; ${HOME} {unknown}",
+            ),
+            ("{path", "{path"),
+            ("{{event}}", "{'modify'}"),
+        ];
+        for (command, expected) in test_cases {
+            assert_eq!(render(command, &event).unwrap(), expected, "{command}");
+        }
+    }
+
+    #[test]
+    fn never_runs_file_names_as_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let pwned = dir.path().join("pwned");
+        let name = format!("/x/$(touch {})`touch {}`", pwned.display(), pwned.display());
+        let event = Event::new(&name, EventKind::Modify);
+        let test_cases = vec![
+            ("printf %s {path}", Ok(name.clone())),
+            ("printf %s 'changed {path}'", Ok(format!("changed {name}"))),
+            ("printf %s \"a b\" {path}", Ok(format!("a b{name}"))),
+            ("logger \"changed {path}\"", Err("double quotes")),
+            ("logger `echo {path}`", Err("backticks")),
+            ("logger \\\"{path}", Ok(format!("\"{name}"))),
+        ];
+        for (command, expected) in test_cases {
+            let rendered = render(command, &event);
+            assert_eq!(check(command), rendered.clone().map(drop), "{command}");
+            match (rendered, expected) {
+                (Ok(rendered), Ok(expected)) => {
+                    let rendered = rendered.replacen("logger", "printf %s", 1);
+                    let output = Command::new("sh")
+                        .arg("-c")
+                        .arg(&rendered)
+                        .output()
+                        .unwrap();
+                    assert_eq!(
+                        String::from_utf8_lossy(&output.stdout),
+                        expected,
+                        "{command}"
+                    );
+                }
+                (Err(err), Err(inside)) => assert_eq!(err.inside, inside, "{command}"),
+                (rendered, _) => panic!("{command}: {rendered:?}"),
+            }
+            assert!(!pwned.exists(), "{command}");
+        }
+        assert_eq!(
+            check("logger \"{path}\"").unwrap_err().to_string(),
+            "{path} is inside double quotes, where the shell would expand what it holds, \
+             write it outside them, as in `logger \"changed\" {path}`"
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! Posting events to an HTTP endpoint, declared with `notify webhook <url>`.
+
+use std::{thread, time::Duration};
+
+use configuration::parse_duration;
+use serde_json::Value;
+
+use crate::{
+    retry::{jitter, RetryPolicy, DEFAULT_BACKOFF},
+    EventRecord, Sink, SinkError,
+};
+
+/// How long a webhook waits for each request when `timeout` doesn't say.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The settings of a `notify webhook <url>` line:
+///
+/// - `header="Name: value"` sends an extra header with every request, and can be repeated,
+/// - `timeout=5s` gives up on a request taking longer, 10 seconds by default,
+/// - `retry=3` and `backoff=2s` send a request which failed again, as `retry` does for
+///   actions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub timeout: Duration,
+    pub retry: RetryPolicy,
+}
+
+impl WebhookConfig {
+    pub(crate) fn parse(url: &str, options: &[(&str, &str)]) -> Result<Self, String> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(format!(
+                "expected an http:// or https:// url, found {url:?}"
+            ));
+        }
+        let mut config = WebhookConfig {
+            url: url.to_string(),
+            headers: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+            retry: RetryPolicy {
+                retries: 0,
+                backoff: DEFAULT_BACKOFF,
+            },
+        };
+        let duration = |value: &str| match parse_duration(value) {
+            Some(duration) if !duration.is_zero() => Ok(duration),
+            _ => Err(format!("expected a duration above zero, found {value}")),
+        };
+        for (key, value) in options {
+            match *key {
+                "header" => {
+                    let (name, value) = value
+                        .split_once(':')
+                        .ok_or_else(|| format!("expected header=\"Name: value\", found {value}"))?;
+                    config
+                        .headers
+                        .push((name.trim().to_string(), value.trim().to_string()));
+                }
+                "timeout" => config.timeout = duration(value)?,
+                "retry" => {
+                    config.retry.retries = value
+                        .parse()
+                        .map_err(|_| format!("expected a number of retries, found {value}"))?;
+                }
+                "backoff" => config.retry.backoff = duration(value)?,
+                _ => {
+                    return Err(format!(
+                        "unknown option {key}, expected header, timeout, retry, backoff or tags"
+                    ))
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Posts each event as a JSON object, see [`EventRecord::to_json`], to a URL, or each batch as
+/// a JSON array of them. A request is delivered once it's answered with a 2xx status.
+#[derive(Debug)]
+pub struct Webhook {
+    config: WebhookConfig,
+    agent: ureq::Agent,
+}
+
+impl Webhook {
+    pub fn new(config: WebhookConfig) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(config.timeout))
+            .build()
+            .into();
+        Self { config, agent }
+    }
+
+    fn post(&self, body: &[u8]) -> Result<(), SinkError> {
+        let mut request = self
+            .agent
+            .post(&self.config.url)
+            .header("Content-Type", "application/json");
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+        match request.send(body) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Io(err)) => Err(SinkError::Io(err)),
+            Err(err) => Err(SinkError::Delivery(err.to_string())),
+        }
+    }
+
+    /// Posts `body`, again after a failure as many times as `retry` allows.
+    fn deliver(&self, body: String) -> Result<(), SinkError> {
+        let policy = self.config.retry;
+        let mut retry = 0;
+        loop {
+            match self.post(body.as_bytes()) {
+                Err(_) if retry < policy.retries => {
+                    retry += 1;
+                    thread::sleep(policy.delay(retry, jitter()));
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Sink for Webhook {
+    fn send(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        self.deliver(record.to_json().to_string())
+    }
+
+    fn send_batch(&mut self, records: &[EventRecord]) -> Result<(), SinkError> {
+        let records: Vec<_> = records.iter().map(EventRecord::to_json).collect();
+        self.deliver(Value::from(records).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        time::UNIX_EPOCH,
+    };
+
+    use configuration::EventKind;
+
+    use super::*;
+
+    /// Answers a request with each of `statuses` in turn, returning the requests it read.
+    fn serve(statuses: Vec<u16>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    request.push_str(&line);
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                request.push_str(&String::from_utf8(body).unwrap());
+                requests.push(request);
+                write!(
+                    &stream,
+                    "HTTP/1.1 {status} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn parses_webhook_options() {
+        let test_cases = vec![
+            ("", Ok(0)),
+            ("timeout=2s retry=2 backoff=1ms", Ok(2)),
+            ("header=\"X-Token: secret\"", Ok(0)),
+            ("header=broken", Err(())),
+            ("timeout=never", Err(())),
+            ("method=PUT", Err(())),
+        ];
+        for (options, expected) in test_cases {
+            let options = crate::options::parse_options(options).unwrap();
+            let parsed = WebhookConfig::parse("http://localhost/hook", &options);
+            assert_eq!(
+                parsed.map(|config| config.retry.retries).map_err(|_| ()),
+                expected,
+                "{options:?}"
+            );
+        }
+        assert!(WebhookConfig::parse("localhost/hook", &[]).is_err());
+    }
+
+    #[test]
+    fn posts_events_and_retries() {
+        let record = EventRecord {
+            path: "/etc/shadow".into(),
+            kind: EventKind::Modify,
+            from: None,
+            timestamp: UNIX_EPOCH,
+            tags: vec!["security".to_string()],
+            group: None,
+            tampered: Vec::new(),
+            failure: None,
+        };
+        let (url, server) = serve(vec![500, 200]);
+        let options = [
+            ("header", "X-Token: secret"),
+            ("retry", "1"),
+            ("backoff", "1ms"),
+        ];
+        let mut webhook = Webhook::new(WebhookConfig::parse(&url, &options).unwrap());
+        webhook.send(&record).unwrap();
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        for request in &requests {
+            assert!(request.starts_with("POST /hook HTTP/1.1\r\n"), "{request}");
+            assert!(request.contains("x-token: secret\r\n"), "{request}");
+            assert!(
+                request.ends_with(&record.to_json().to_string()),
+                "{request}"
+            );
+        }
+
+        let (url, server) = serve(vec![503]);
+        let mut webhook = Webhook::new(WebhookConfig::parse(&url, &[]).unwrap());
+        let err = webhook.send(&record).unwrap_err();
+        assert!(matches!(err, SinkError::Delivery(_)), "{err:?}");
+        server.join().unwrap();
+
+        let (url, server) = serve(vec![200]);
+        let mut webhook = Webhook::new(WebhookConfig::parse(&url, &[]).unwrap());
+        let deleted = EventRecord {
+            path: "/etc/hosts".into(),
+            kind: EventKind::Delete,
+            ..record.clone()
+        };
+        webhook
+            .send_batch(&[record.clone(), deleted.clone()])
+            .unwrap();
+        let requests = server.join().unwrap();
+        let batch = Value::from(vec![record.to_json(), deleted.to_json()]);
+        assert!(requests[0].ends_with(&batch.to_string()), "{requests:?}");
+    }
+}
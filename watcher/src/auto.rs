@@ -0,0 +1,383 @@
+//! Picking a backend for each include from the filesystem it lives on.
+
+use std::{
+    path::Path,
+    sync::mpsc::{self, RecvTimeoutError},
+    time::Duration,
+};
+
+use configuration::{Config, PathSpec, WatchEntry};
+
+#[cfg(target_os = "macos")]
+use crate::FsEventsWatcher;
+use crate::PollWatcher;
+#[cfg(windows)]
+use crate::WindowsWatcher;
+use crate::{
+    backend::{deadline, remaining},
+    walk,
+    worker::{Batch, Worker},
+    Capabilities, Event, WatchError, Watcher,
+};
+#[cfg(target_os = "linux")]
+use crate::{FanotifyWatcher, InotifyWatcher};
+
+/// The backends a watcher can be built on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+    #[cfg(target_os = "linux")]
+    Inotify,
+    #[cfg(target_os = "linux")]
+    Fanotify,
+    #[cfg(target_os = "macos")]
+    FsEvents,
+    #[cfg(windows)]
+    Windows,
+    Poll,
+}
+
+impl Backend {
+    /// The backend the operating system provides for local filesystems.
+    pub const NATIVE: Backend = {
+        #[cfg(target_os = "linux")]
+        let native = Backend::Inotify;
+        #[cfg(target_os = "macos")]
+        let native = Backend::FsEvents;
+        #[cfg(windows)]
+        let native = Backend::Windows;
+        #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+        let native = Backend::Poll;
+        native
+    };
+
+    /// Starts a watcher of this kind for `config`.
+    pub fn open(self, config: &Config) -> Result<Box<dyn Watcher + Send>, WatchError> {
+        Ok(match self {
+            #[cfg(target_os = "linux")]
+            Backend::Inotify => Box::new(InotifyWatcher::new(config)?),
+            #[cfg(target_os = "linux")]
+            Backend::Fanotify => Box::new(FanotifyWatcher::new(config)?),
+            #[cfg(target_os = "macos")]
+            Backend::FsEvents => Box::new(FsEventsWatcher::new(config)?),
+            #[cfg(windows)]
+            Backend::Windows => Box::new(WindowsWatcher::new(config)?),
+            Backend::Poll => Box::new(PollWatcher::new(config)?),
+        })
+    }
+}
+
+/// The backend suited to the filesystem `path` is on: polling for network and FUSE mounts,
+/// whose changes the kernel may never hear of, and [`Backend::NATIVE`] otherwise. A path which
+/// doesn't exist yet is judged by the nearest directory above it which does.
+pub fn select(path: &Path) -> Backend {
+    let remote = path.ancestors().find_map(is_remote).unwrap_or(false);
+    if remote {
+        Backend::Poll
+    } else {
+        Backend::NATIVE
+    }
+}
+
+/// Whether `path` is on a network or FUSE filesystem, or `None` if it can't be told.
+#[cfg(target_os = "linux")]
+fn is_remote(path: &Path) -> Option<bool> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    /// The `f_type`s of NFS, SMB, CIFS, SMB2, FUSE, AFS, 9P and Ceph.
+    const REMOTE: [u32; 8] = [
+        0x6969,
+        0x517B,
+        0xFF53_4D42,
+        0xFE53_4D42,
+        0x6573_5546,
+        0x5346_414F,
+        0x0102_1997,
+        0x00C3_6400,
+    ];
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statfs is plain data, for which all zeroes is valid.
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: the path is nul terminated and `stat` is valid for writes.
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    // The magic numbers are 32 bits, whatever the width of the field.
+    Some(REMOTE.contains(&(stat.f_type as u32)))
+}
+
+/// Whether `path` is on a network or FUSE filesystem, or `None` if it can't be told.
+#[cfg(target_os = "macos")]
+fn is_remote(path: &Path) -> Option<bool> {
+    use std::{
+        ffi::{CStr, CString},
+        os::unix::ffi::OsStrExt,
+    };
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statfs is plain data, for which all zeroes is valid.
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: the path is nul terminated and `stat` is valid for writes.
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    // SAFETY: the kernel fills the name in with a nul terminated string.
+    let name = unsafe { CStr::from_ptr(stat.f_fstypename.as_ptr()) }.to_string_lossy();
+    Some(matches!(&*name, "nfs" | "smbfs" | "afpfs" | "webdav" | "cifs") || name.contains("fuse"))
+}
+
+/// Whether `path` is on a network filesystem. ReadDirectoryChangesW works on SMB shares, so
+/// nothing needs telling apart.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn is_remote(_path: &Path) -> Option<bool> {
+    Some(false)
+}
+
+/// The backend for `entry`, judged by the first path it names.
+fn select_entry(entry: &WatchEntry) -> Backend {
+    match entry.path.expand().first() {
+        Some(path) => select(path),
+        None => Backend::NATIVE,
+    }
+}
+
+/// Watches each include with the backend suited to the filesystem it's on, see [`select`].
+///
+/// Each backend runs on a thread of its own, and their events are returned together. On Linux
+/// a configuration needing more inotify watches than `fs.inotify.max_user_watches` allows is
+/// watched with fanotify instead, where that's permitted.
+#[derive(Debug)]
+pub struct AutoWatcher {
+    config: Config,
+    runners: Vec<Runner>,
+    /// Kept so the channel stays open while no backend is running.
+    sender: mpsc::Sender<Batch>,
+    receiver: mpsc::Receiver<Batch>,
+}
+
+impl AutoWatcher {
+    /// Picks a backend for every include of `config`, and starts each of them.
+    pub fn new(config: &Config) -> Result<Self, WatchError> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = Self {
+            config: config.clone(),
+            runners: Vec::new(),
+            sender,
+            receiver,
+        };
+        let selected: Vec<_> = walk::watch_entries(config)
+            .into_iter()
+            .map(|entry| {
+                let backend = select_entry(&entry);
+                (entry, backend)
+            })
+            .collect();
+        let mut backends = Vec::new();
+        for (_, backend) in &selected {
+            if !backends.contains(backend) {
+                backends.push(*backend);
+            }
+        }
+        for backend in backends {
+            let mut part = config.clone();
+            part.retain_includes(|entry| selected.contains(&(entry.clone(), backend)));
+            watcher.start(backend, &part)?;
+        }
+        Ok(watcher)
+    }
+
+    /// The includes being watched, those of watch groups among them.
+    pub fn includes(&self) -> Vec<WatchEntry> {
+        walk::watch_entries(&self.config)
+    }
+
+    /// The backends running, in the order they were started.
+    pub fn backends(&self) -> Vec<Backend> {
+        self.runners.iter().map(|runner| runner.backend).collect()
+    }
+
+    /// Starts a thread watching `config` with the backend `selected` calls for.
+    fn start(&mut self, selected: Backend, config: &Config) -> Result<(), WatchError> {
+        let (backend, watcher) = open(selected, config)?;
+        tracing::debug!(
+            "watching {} includes with {backend:?}",
+            walk::watch_entries(config).len()
+        );
+        let capabilities = watcher.capabilities();
+        let events = self.sender.clone();
+        let worker = Worker::spawn(watcher, move |batch| events.send(batch).is_ok());
+        self.runners.push(Runner {
+            selected,
+            backend,
+            capabilities,
+            worker,
+        });
+        Ok(())
+    }
+}
+
+/// Opens the backend `selected` calls for, returning which one it was.
+#[cfg(target_os = "linux")]
+fn open(
+    selected: Backend,
+    config: &Config,
+) -> Result<(Backend, Box<dyn Watcher + Send>), WatchError> {
+    if selected == Backend::Inotify {
+        let limit = std::fs::read_to_string("/proc/sys/fs/inotify/max_user_watches")
+            .ok()
+            .and_then(|limit| limit.trim().parse::<usize>().ok());
+        if limit.is_some_and(|limit| walk::watch_paths(config).len() > limit) {
+            if let Ok(watcher) = FanotifyWatcher::new(config) {
+                return Ok((Backend::Fanotify, Box::new(watcher)));
+            }
+        }
+    }
+    Ok((selected, selected.open(config)?))
+}
+
+/// Opens the backend `selected` calls for, returning which one it was.
+#[cfg(not(target_os = "linux"))]
+fn open(
+    selected: Backend,
+    config: &Config,
+) -> Result<(Backend, Box<dyn Watcher + Send>), WatchError> {
+    Ok((selected, selected.open(config)?))
+}
+
+impl Watcher for AutoWatcher {
+    /// Events from different backends are returned in the order they were read, which may not
+    /// be the order they happened in.
+    fn read_events_timeout(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, WatchError> {
+        let received = match remaining(deadline(timeout)) {
+            Some(left) => self.receiver.recv_timeout(left),
+            None => self
+                .receiver
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        // The watcher holds a sender itself, so the channel can't be disconnected.
+        received.unwrap_or_else(|_| Ok(Vec::new()))
+    }
+
+    /// Hands `entry` to the backend already watching its filesystem, or starts one.
+    fn add(&mut self, entry: WatchEntry) -> Result<(), WatchError> {
+        self.config.add_include(entry.clone());
+        let selected = select_entry(&entry);
+        match self
+            .runners
+            .iter()
+            .find(|runner| runner.selected == selected)
+        {
+            Some(runner) => runner.worker.call(move |watcher| watcher.add(entry)),
+            None => {
+                let mut part = self.config.clone();
+                part.retain_includes(|include| *include == entry);
+                self.start(selected, &part)
+            }
+        }
+    }
+
+    fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+        self.config
+            .remove_include(path)
+            .ok_or_else(|| WatchError::NotIncluded(path.clone()))?;
+        for runner in &self.runners {
+            let path = path.clone();
+            match runner.worker.call(move |watcher| watcher.remove(&path)) {
+                Ok(()) | Err(WatchError::NotIncluded(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// What every backend running is able to notice, and whether any of them reports remote
+    /// changes.
+    fn capabilities(&self) -> Capabilities {
+        let all = |notices: fn(&Capabilities) -> bool| {
+            self.runners
+                .iter()
+                .all(|runner| notices(&runner.capabilities))
+        };
+        Capabilities {
+            recursive: all(|capabilities| capabilities.recursive),
+            realtime: all(|capabilities| capabilities.realtime),
+            remote: self.runners.iter().any(|runner| runner.capabilities.remote),
+        }
+    }
+}
+
+impl Drop for AutoWatcher {
+    fn drop(&mut self) {
+        // Stopped together, so they all finish within the same tick.
+        for runner in &mut self.runners {
+            runner.worker.stop();
+        }
+        for runner in &mut self.runners {
+            runner.worker.join();
+        }
+    }
+}
+
+/// A backend running on a thread of its own.
+#[derive(Debug)]
+struct Runner {
+    /// What [`select`] called for, which is what new includes are matched by.
+    selected: Backend,
+    backend: Backend,
+    capabilities: Capabilities,
+    worker: Worker,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use configuration::EventKind;
+
+    use super::*;
+
+    #[test]
+    fn selects_the_native_backend_for_local_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(select(dir.path()), Backend::NATIVE);
+        assert_eq!(select(&dir.path().join("missing/deeper")), Backend::NATIVE);
+    }
+
+    #[test]
+    fn watches_with_the_selected_backends() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("first")).unwrap();
+        fs::create_dir_all(root.join("second")).unwrap();
+        let config: Config = format!("include {}", root.join("first").display())
+            .parse()
+            .unwrap();
+        let mut watcher = AutoWatcher::new(&config).unwrap();
+        assert_eq!(watcher.backends(), [Backend::NATIVE]);
+        assert!(watcher.capabilities().realtime);
+
+        let second: Config = format!("include {}", root.join("second").display())
+            .parse()
+            .unwrap();
+        watcher.add(second.includes()[0].clone()).unwrap();
+        assert_eq!(watcher.backends(), [Backend::NATIVE]);
+        watcher.remove(&PathSpec::Path(root.join("first"))).unwrap();
+        assert!(matches!(
+            watcher.remove(&PathSpec::Path(root.join("first"))),
+            Err(WatchError::NotIncluded(_))
+        ));
+
+        fs::write(root.join("first/file"), "x").unwrap();
+        fs::write(root.join("second/file"), "x").unwrap();
+        let mut seen = Vec::new();
+        while !seen.contains(&(root.join("second/file"), EventKind::Create)) {
+            let events = watcher.read_events_timeout(Some(Duration::from_secs(10)));
+            let events = events.unwrap();
+            assert!(!events.is_empty(), "no events arrived");
+            seen.extend(events.into_iter().map(|event| (event.path, event.kind)));
+        }
+        assert!(seen
+            .iter()
+            .all(|(path, _)| path.starts_with(root.join("second"))));
+    }
+}
@@ -0,0 +1,88 @@
+//! What every backend provides.
+
+use std::time::{Duration, Instant};
+
+use configuration::{PathSpec, WatchEntry};
+
+#[cfg(feature = "tokio")]
+use crate::EventStream;
+use crate::{Event, Events, WatchError};
+
+/// A source of events for the paths a configuration includes, implemented by each backend.
+pub trait Watcher {
+    /// Starts watching `entry` along with the includes the watcher has, as if it had been
+    /// added to the configuration with [`configuration::Config::add_include`].
+    fn add(&mut self, entry: WatchEntry) -> Result<(), WatchError>;
+
+    /// Stops watching the include of `path`, failing with [`WatchError::NotIncluded`] if
+    /// there is none. Paths another include covers are still watched.
+    fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError>;
+
+    /// Blocks until events arrive or `timeout` passes, and returns them in the order they
+    /// happened. Nothing is returned if the timeout passes first.
+    fn read_events_timeout(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, WatchError>;
+
+    /// Blocks until events arrive and returns them in the order they happened.
+    fn read_events(&mut self) -> Result<Vec<Event>, WatchError> {
+        self.read_events_timeout(None)
+    }
+
+    /// Iterates over events one at a time, blocking until each arrives, see [`Events`].
+    fn events(&mut self) -> Events<'_, Self>
+    where
+        Self: Sized,
+    {
+        Events::new(self)
+    }
+
+    /// Reads events on a thread of its own, returning them as a stream, see [`EventStream`].
+    #[cfg(feature = "tokio")]
+    fn stream(self) -> EventStream
+    where
+        Self: Sized + Send + 'static,
+    {
+        EventStream::new(self)
+    }
+
+    /// What the backend is able to notice.
+    fn capabilities(&self) -> Capabilities;
+}
+
+impl<W: Watcher + ?Sized> Watcher for Box<W> {
+    fn add(&mut self, entry: WatchEntry) -> Result<(), WatchError> {
+        (**self).add(entry)
+    }
+
+    fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+        (**self).remove(path)
+    }
+
+    fn read_events_timeout(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, WatchError> {
+        (**self).read_events_timeout(timeout)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        (**self).capabilities()
+    }
+}
+
+/// What a backend is able to notice, so callers can make up for what it can't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Subdirectories created below a recursive include are watched without adding them.
+    pub recursive: bool,
+    /// Changes are reported as they happen, rather than found by a later scan.
+    pub realtime: bool,
+    /// Changes made by other machines to a network filesystem are reported.
+    pub remote: bool,
+}
+
+/// When a wait of `timeout` started now ends.
+pub(crate) fn deadline(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.map(|timeout| Instant::now() + timeout)
+}
+
+/// How much of a wait until `deadline` is left, `None` meaning forever.
+pub(crate) fn remaining(deadline: Option<Instant>) -> Option<Duration> {
+    deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
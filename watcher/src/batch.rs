@@ -0,0 +1,289 @@
+//! Grouping events into batches, for sinks which handle many at once.
+
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use configuration::{parse_duration, Config, Directive, PathSpec, WatchEntry};
+
+use crate::{
+    backend::{deadline, remaining},
+    Capabilities, Event, WatchError, Watcher,
+};
+
+/// When a batch is handed on: once it holds `max_events`, or once its first event is
+/// `max_delay` old, whichever comes first. At least one of them is set.
+///
+/// Written in configuration text as `batch 100 events / 1s`, or `batch 100 events` or
+/// `batch 1s` for just one of the limits, once [`BatchDirective`] is registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchPolicy {
+    pub max_events: Option<usize>,
+    pub max_delay: Option<Duration>,
+}
+
+impl BatchPolicy {
+    /// The policy the last `batch` directive of `config` sets, if any.
+    pub fn from_config(config: &Config) -> Option<BatchPolicy> {
+        config.custom_values(BatchDirective::NAME).last().copied()
+    }
+}
+
+impl FromStr for BatchPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || "expected <n> events / <duration>, such as 100 events / 1s".to_string();
+        let (size, delay) = match s.split_once('/') {
+            Some((size, delay)) => (Some(size.trim()), Some(delay.trim())),
+            None if s.trim().ends_with("event") || s.trim().ends_with("events") => {
+                (Some(s.trim()), None)
+            }
+            None => (None, Some(s.trim())),
+        };
+        let max_events = match size {
+            Some(size) => {
+                let count = size
+                    .strip_suffix("events")
+                    .or_else(|| size.strip_suffix("event"))
+                    .ok_or_else(invalid)?
+                    .trim();
+                match count.parse() {
+                    Ok(0) | Err(_) => return Err(invalid()),
+                    Ok(count) => Some(count),
+                }
+            }
+            None => None,
+        };
+        let max_delay = match delay {
+            Some(delay) => match parse_duration(delay) {
+                Some(delay) if !delay.is_zero() => Some(delay),
+                _ => return Err(invalid()),
+            },
+            None => None,
+        };
+        Ok(BatchPolicy {
+            max_events,
+            max_delay,
+        })
+    }
+}
+
+/// The `batch` directive, parsing to a [`BatchPolicy`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BatchDirective;
+
+impl BatchDirective {
+    pub const NAME: &'static str = "batch";
+}
+
+impl Directive for BatchDirective {
+    type Value = BatchPolicy;
+
+    fn parse(&self, args: &str) -> Result<BatchPolicy, String> {
+        args.parse()
+    }
+}
+
+/// Collects events into batches following a [`BatchPolicy`], or anything else made of them,
+/// such as the records handed to sinks.
+#[derive(Debug)]
+pub struct Batcher<T = Event> {
+    policy: BatchPolicy,
+    batch: Vec<T>,
+    /// When the first event of the batch arrived.
+    started: Option<Instant>,
+}
+
+impl<T> Batcher<T> {
+    pub fn new(policy: BatchPolicy) -> Self {
+        Self {
+            policy,
+            batch: Vec::new(),
+            started: None,
+        }
+    }
+
+    pub fn policy(&self) -> BatchPolicy {
+        self.policy
+    }
+
+    /// Adds an event which arrived at `now`, returning the batch if that filled it.
+    pub fn push(&mut self, event: T, now: Instant) -> Option<Vec<T>> {
+        self.started.get_or_insert(now);
+        self.batch.push(event);
+        if self
+            .policy
+            .max_events
+            .is_some_and(|max| self.batch.len() >= max)
+        {
+            return Some(self.flush());
+        }
+        None
+    }
+
+    /// Returns the batch if it has been waiting for `max_delay` by `now`.
+    pub fn poll(&mut self, now: Instant) -> Option<Vec<T>> {
+        let due = self.due()?;
+        if due <= now {
+            return Some(self.flush());
+        }
+        None
+    }
+
+    /// When the batch collected so far is due to be handed on, if it's due by time.
+    pub fn due(&self) -> Option<Instant> {
+        Some(self.started? + self.policy.max_delay?)
+    }
+
+    /// Removes and returns the events collected so far, however few.
+    pub fn flush(&mut self) -> Vec<T> {
+        self.started = None;
+        std::mem::take(&mut self.batch)
+    }
+}
+
+/// A watcher whose reads return whole batches formed by a [`Batcher`].
+#[derive(Debug)]
+pub struct Batched<W> {
+    watcher: W,
+    batcher: Batcher,
+    /// Batches filled by one read of the watcher, which returned more than fit in one.
+    full: VecDeque<Vec<Event>>,
+}
+
+impl<W: Watcher> Batched<W> {
+    pub fn new(watcher: W, policy: BatchPolicy) -> Self {
+        Self {
+            watcher,
+            batcher: Batcher::new(policy),
+            full: VecDeque::new(),
+        }
+    }
+
+    /// The watcher events are read from.
+    pub fn get_ref(&self) -> &W {
+        &self.watcher
+    }
+
+    /// The watcher, dropping any events collected but not yet returned.
+    pub fn into_inner(self) -> W {
+        self.watcher
+    }
+}
+
+impl<W: Watcher> Watcher for Batched<W> {
+    /// Returns the next batch, as soon as it fills or its delay passes. Events the watcher
+    /// returns together may be split across batches, and a read which times out returns
+    /// nothing even if a partial batch has been collected.
+    fn read_events_timeout(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, WatchError> {
+        let deadline = deadline(timeout);
+        loop {
+            if let Some(batch) = self.full.pop_front() {
+                return Ok(batch);
+            }
+            if let Some(batch) = self.batcher.poll(Instant::now()) {
+                return Ok(batch);
+            }
+            let left = remaining(deadline);
+            if left == Some(Duration::ZERO) {
+                return Ok(Vec::new());
+            }
+            let until_due = self
+                .batcher
+                .due()
+                .map(|due| due.saturating_duration_since(Instant::now()));
+            let wait = match (left, until_due) {
+                (Some(left), Some(until_due)) => Some(left.min(until_due)),
+                (left, until_due) => left.or(until_due),
+            };
+            for event in self.watcher.read_events_timeout(wait)? {
+                self.full.extend(self.batcher.push(event, Instant::now()));
+            }
+        }
+    }
+
+    fn add(&mut self, entry: WatchEntry) -> Result<(), WatchError> {
+        self.watcher.add(entry)
+    }
+
+    fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+        self.watcher.remove(path)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.watcher.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use configuration::{EventKind, ParseOptions};
+
+    use super::*;
+
+    #[test]
+    fn parses_batch_policies() {
+        let policy = |events, millis: Option<u64>| BatchPolicy {
+            max_events: events,
+            max_delay: millis.map(Duration::from_millis),
+        };
+        let test_cases = vec![
+            ("100 events / 1s", Ok(policy(Some(100), Some(1000)))),
+            ("5events/250ms", Ok(policy(Some(5), Some(250)))),
+            ("100 events", Ok(policy(Some(100), None))),
+            ("1 event / 1m", Ok(policy(Some(1), Some(60_000)))),
+            ("2s", Ok(policy(None, Some(2000)))),
+            ("0 events / 1s", Err(())),
+            ("100 / 1s", Err(())),
+            ("100 events / 0s", Err(())),
+            ("lots", Err(())),
+        ];
+        for (input, expected) in test_cases {
+            assert_eq!(
+                input.parse::<BatchPolicy>().map_err(|_| ()),
+                expected,
+                "{input}"
+            );
+        }
+
+        let mut options = ParseOptions::default();
+        crate::register_directives(&mut options.directives);
+        let config =
+            Config::parse_with("batch 10 events\nbatch 100 events / 1s", &options).unwrap();
+        assert_eq!(
+            BatchPolicy::from_config(&config),
+            Some(policy(Some(100), Some(1000)))
+        );
+    }
+
+    #[test]
+    fn flushes_by_size_or_time() {
+        let mut batcher = Batcher::new("3 events / 100ms".parse().unwrap());
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let event = |name: &str| Event::new(name, EventKind::Modify);
+        let paths = |batch: Vec<Event>| -> Vec<_> {
+            batch
+                .into_iter()
+                .map(|event| event.path.display().to_string())
+                .collect()
+        };
+
+        assert_eq!(batcher.push(event("a"), at(0)), None);
+        assert_eq!(batcher.push(event("b"), at(10)), None);
+        assert_eq!(
+            paths(batcher.push(event("c"), at(20)).unwrap()),
+            ["a", "b", "c"]
+        );
+        assert_eq!(batcher.due(), None);
+
+        assert_eq!(batcher.push(event("d"), at(30)), None);
+        assert_eq!(batcher.due(), Some(at(130)));
+        assert_eq!(batcher.poll(at(129)), None);
+        assert_eq!(paths(batcher.poll(at(130)).unwrap()), ["d"]);
+        assert_eq!(batcher.poll(at(500)), None);
+    }
+}
@@ -0,0 +1,288 @@
+//! Collapsing bursts of events for the same path into one.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use configuration::{Config, EventKind, PathSpec, WatchEntry};
+
+use crate::{
+    backend::{deadline, remaining},
+    Capabilities, Event, WatchError, Watcher,
+};
+
+/// Merges events for the same path which arrive within its `debounce` delay of each other.
+///
+/// An event is held until no further event for its path has arrived for the delay of the
+/// include deciding the path, see [`Config::debounce_for`]. Paths without a delay pass straight
+/// through. What a burst amounts to is reported as one event, stamped when the burst started:
+///
+/// - a path created and then changed or renamed into place was created,
+/// - a path created and then deleted or renamed away is dropped, as are the temporary files
+///   editors save through,
+/// - a path deleted and then created again, or renamed over, was modified,
+/// - otherwise the last event wins, so a modified and then deleted path was deleted.
+///
+/// Whether a rename moved a path away or into place is told by whether it still exists.
+#[derive(Debug)]
+pub struct Debouncer {
+    config: Config,
+    pending: HashMap<PathBuf, Pending>,
+    /// Events which needed no delay, waiting to be popped.
+    ready: Vec<Event>,
+    /// Counts events pushed, so events are popped in the order their bursts started.
+    pushed: u64,
+}
+
+#[derive(Debug)]
+struct Pending {
+    event: Event,
+    due: Instant,
+    order: u64,
+}
+
+impl Debouncer {
+    /// Starts a debouncer using the delays `config` sets.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+            pending: HashMap::new(),
+            ready: Vec::new(),
+            pushed: 0,
+        }
+    }
+
+    /// Uses the delays `config` sets for events pushed from now on.
+    pub fn set_config(&mut self, config: &Config) {
+        self.config = config.clone();
+    }
+
+    /// Adds an event which arrived at `now`.
+    pub fn push(&mut self, event: Event, now: Instant) {
+        let delay = match self.config.include_for(&event.path) {
+            Some(entry) => self.config.debounce_for(&entry),
+            None => self.config.debounce(),
+        };
+        let Some(delay) = delay.filter(|delay| !delay.is_zero()) else {
+            self.ready.push(event);
+            return;
+        };
+        self.pushed += 1;
+        let due = now + delay;
+        match self.pending.get_mut(&event.path) {
+            Some(pending) => match merge(pending.event.kind, &event) {
+                Some(kind) => {
+                    if kind == event.kind {
+                        pending.event.from = event.from;
+                    }
+                    pending.event.kind = kind;
+                    pending.due = due;
+                }
+                None => {
+                    self.pending.remove(&event.path);
+                }
+            },
+            None => {
+                let order = self.pushed;
+                let path = event.path.clone();
+                self.pending.insert(path, Pending { event, due, order });
+            }
+        }
+    }
+
+    /// Removes and returns the events whose delay has passed by `now`, in the order they
+    /// started.
+    pub fn pop_ready(&mut self, now: Instant) -> Vec<Event> {
+        let mut due: Vec<_> = self
+            .pending
+            .extract_if(|_, pending| pending.due <= now)
+            .map(|(_, pending)| pending)
+            .collect();
+        due.sort_by_key(|pending| pending.order);
+        let mut events = std::mem::take(&mut self.ready);
+        events.extend(due.into_iter().map(|pending| pending.event));
+        events
+    }
+
+    /// When the next held event is due, if any are held.
+    pub fn next_due(&self) -> Option<Instant> {
+        if !self.ready.is_empty() {
+            return Some(Instant::now());
+        }
+        self.pending.values().map(|pending| pending.due).min()
+    }
+
+    /// Removes and returns every event held, due or not.
+    pub fn flush(&mut self) -> Vec<Event> {
+        let mut pending: Vec<_> = self.pending.drain().map(|(_, pending)| pending).collect();
+        pending.sort_by_key(|pending| pending.order);
+        let mut events = std::mem::take(&mut self.ready);
+        events.extend(pending.into_iter().map(|pending| pending.event));
+        events
+    }
+}
+
+/// What a path which saw `first` and then `event` amounts to, or `None` if nothing happened.
+fn merge(first: EventKind, event: &Event) -> Option<EventKind> {
+    let gone = || fs::symlink_metadata(&event.path).is_err();
+    match (first, event.kind) {
+        (EventKind::Create, EventKind::Delete) => None,
+        (EventKind::Create, EventKind::Rename) if gone() => None,
+        (EventKind::Create, _) => Some(EventKind::Create),
+        (EventKind::Delete, EventKind::Create | EventKind::Rename) => Some(EventKind::Modify),
+        (_, then) => Some(then),
+    }
+}
+
+/// A watcher whose events go through a [`Debouncer`] before they are returned.
+#[derive(Debug)]
+pub struct Debounced<W> {
+    watcher: W,
+    debouncer: Debouncer,
+}
+
+impl<W: Watcher> Debounced<W> {
+    /// Debounces the events of `watcher` with the delays `config` sets, which should be the
+    /// configuration the watcher was built from.
+    pub fn new(watcher: W, config: &Config) -> Self {
+        Self {
+            watcher,
+            debouncer: Debouncer::new(config),
+        }
+    }
+
+    /// The watcher events are read from.
+    pub fn get_ref(&self) -> &W {
+        &self.watcher
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.watcher
+    }
+
+    /// Debounces later events with the delays `config` sets, as after a reload. Events
+    /// already held keep the delay they were given.
+    pub fn set_config(&mut self, config: &Config) {
+        self.debouncer.set_config(config);
+    }
+
+    /// Removes and returns every event held, due or not, as before stopping.
+    pub fn flush(&mut self) -> Vec<Event> {
+        self.debouncer.flush()
+    }
+
+    /// The watcher, dropping any events still held.
+    pub fn into_inner(self) -> W {
+        self.watcher
+    }
+}
+
+impl<W: Watcher> Watcher for Debounced<W> {
+    /// Events are returned once their delay has passed, so a read may return nothing until
+    /// then even though the watcher reported something.
+    fn read_events_timeout(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, WatchError> {
+        let deadline = deadline(timeout);
+        loop {
+            let now = Instant::now();
+            let events = self.debouncer.pop_ready(now);
+            if !events.is_empty() {
+                return Ok(events);
+            }
+            let left = remaining(deadline);
+            if left == Some(Duration::ZERO) {
+                return Ok(Vec::new());
+            }
+            let until_due = self
+                .debouncer
+                .next_due()
+                .map(|due| due.saturating_duration_since(now));
+            let wait = match (left, until_due) {
+                (Some(left), Some(until_due)) => Some(left.min(until_due)),
+                (left, until_due) => left.or(until_due),
+            };
+            for event in self.watcher.read_events_timeout(wait)? {
+                self.debouncer.push(event, Instant::now());
+            }
+        }
+    }
+
+    fn add(&mut self, entry: WatchEntry) -> Result<(), WatchError> {
+        self.debouncer.config.add_include(entry.clone());
+        self.watcher.add(entry)
+    }
+
+    fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+        self.watcher.remove(path)?;
+        self.debouncer.config.remove_include(path);
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.watcher.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collapses_bursts_per_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("present"), "").unwrap();
+        let config: Config = format!(
+            "debounce 100ms\ninclude -r {}\ninclude -r /tmp/now debounce=0ms",
+            root.display()
+        )
+        .parse()
+        .unwrap();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        use EventKind::*;
+        let test_cases = vec![
+            ("present", vec![Create, Modify], Some(Create)),
+            ("present", vec![Create, Modify, Rename], Some(Create)),
+            ("gone", vec![Create, Modify, Rename], None),
+            ("gone", vec![Create, Delete], None),
+            ("present", vec![Delete, Rename], Some(Modify)),
+            ("gone", vec![Modify, Delete], Some(Delete)),
+            ("present", vec![Modify, Modify], Some(Modify)),
+        ];
+        for (name, kinds, expected) in test_cases {
+            let mut debouncer = Debouncer::new(&config);
+            for (i, kind) in kinds.iter().enumerate() {
+                debouncer.push(Event::new(root.join(name), *kind), at(i as u64 * 50));
+            }
+            let last = (kinds.len() as u64 - 1) * 50;
+            assert_eq!(debouncer.pop_ready(at(last + 99)), [], "{name} {kinds:?}");
+            let merged: Vec<_> = debouncer
+                .pop_ready(at(last + 100))
+                .into_iter()
+                .map(|event| event.kind)
+                .collect();
+            assert_eq!(merged, Vec::from_iter(expected), "{name} {kinds:?}");
+        }
+
+        let mut debouncer = Debouncer::new(&config);
+        debouncer.push(Event::new(root.join("b"), Modify), at(0));
+        debouncer.push(Event::new(root.join("a"), Modify), at(10));
+        debouncer.push(Event::new("/tmp/now/x", Modify), at(20));
+        debouncer.push(Event::new(root.join("b"), Modify), at(30));
+        let paths =
+            |events: Vec<Event>| -> Vec<_> { events.into_iter().map(|event| event.path).collect() };
+        assert_eq!(
+            paths(debouncer.pop_ready(at(20))),
+            [PathBuf::from("/tmp/now/x")]
+        );
+        assert_eq!(debouncer.next_due(), Some(at(110)));
+        assert_eq!(
+            paths(debouncer.pop_ready(at(130))),
+            [root.join("b"), root.join("a")]
+        );
+        assert!(debouncer.flush().is_empty());
+    }
+}
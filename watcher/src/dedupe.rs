@@ -0,0 +1,220 @@
+//! Dropping repeats of an event, for applications which write the same file in a tight loop.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use configuration::{parse_duration, Config, Directive, EventKind, PathSpec, WatchEntry};
+
+use crate::{
+    backend::{deadline, remaining},
+    Capabilities, Event, WatchError, Watcher,
+};
+
+/// The `dedupe <duration>` directive, parsing to the window a [`Deduplicator`] drops repeats
+/// within. `dedupe off` turns it off again.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DedupeDirective;
+
+impl DedupeDirective {
+    pub const NAME: &'static str = "dedupe";
+
+    /// The window the last `dedupe` directive of `config` sets, if there is one which isn't
+    /// `off`.
+    pub fn window(config: &Config) -> Option<Duration> {
+        config
+            .custom_values::<Option<Duration>>(Self::NAME)
+            .last()
+            .copied()
+            .flatten()
+    }
+}
+
+impl Directive for DedupeDirective {
+    type Value = Option<Duration>;
+
+    fn parse(&self, args: &str) -> Result<Option<Duration>, String> {
+        match args.trim() {
+            "off" => Ok(None),
+            window => match parse_duration(window) {
+                Some(window) if !window.is_zero() => Ok(Some(window)),
+                _ => Err(format!(
+                    "expected a duration above zero or off, found {window:?}"
+                )),
+            },
+        }
+    }
+}
+
+/// Drops an event when one for the same path and of the same kind came through less than a
+/// window ago. Unlike [`Debounced`](crate::Debounced) nothing is held back: the first event
+/// goes through at once, and the repeats within the window after it are dropped.
+#[derive(Debug)]
+pub struct Deduplicator {
+    window: Duration,
+    /// When the last event passed for each path and kind.
+    passed: HashMap<(PathBuf, EventKind), Instant>,
+    /// When the events whose window is over were last forgotten.
+    pruned: Instant,
+    dropped: u64,
+}
+
+impl Deduplicator {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            passed: HashMap::new(),
+            pruned: Instant::now(),
+            dropped: 0,
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// How many events were dropped as repeats so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Returns false if `event`, which arrived at `now`, repeats one within the window.
+    pub fn admit(&mut self, event: &Event, now: Instant) -> bool {
+        if now.saturating_duration_since(self.pruned) >= self.window {
+            let window = self.window;
+            self.passed
+                .retain(|_, passed| now.saturating_duration_since(*passed) < window);
+            self.pruned = now;
+        }
+        let key = (event.path.clone(), event.kind);
+        match self.passed.get(&key) {
+            Some(passed) if now.saturating_duration_since(*passed) < self.window => {
+                self.dropped += 1;
+                false
+            }
+            _ => {
+                self.passed.insert(key, now);
+                true
+            }
+        }
+    }
+}
+
+/// A watcher whose reads leave out the repeats a [`Deduplicator`] drops.
+#[derive(Debug)]
+pub struct Deduplicated<W> {
+    watcher: W,
+    deduplicator: Deduplicator,
+}
+
+impl<W: Watcher> Deduplicated<W> {
+    pub fn new(watcher: W, window: Duration) -> Self {
+        Self {
+            watcher,
+            deduplicator: Deduplicator::new(window),
+        }
+    }
+
+    pub fn deduplicator(&self) -> &Deduplicator {
+        &self.deduplicator
+    }
+
+    /// The watcher events are read from.
+    pub fn get_ref(&self) -> &W {
+        &self.watcher
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.watcher
+    }
+
+    pub fn into_inner(self) -> W {
+        self.watcher
+    }
+}
+
+impl<W: Watcher> Watcher for Deduplicated<W> {
+    /// Reads again while every event read was a repeat, so a read only returns nothing early
+    /// once the timeout passed.
+    fn read_events_timeout(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, WatchError> {
+        let deadline = deadline(timeout);
+        loop {
+            let mut events = self.watcher.read_events_timeout(remaining(deadline))?;
+            let read = events.len();
+            let now = Instant::now();
+            events.retain(|event| self.deduplicator.admit(event, now));
+            if !events.is_empty() || read == 0 || remaining(deadline) == Some(Duration::ZERO) {
+                return Ok(events);
+            }
+        }
+    }
+
+    fn add(&mut self, entry: WatchEntry) -> Result<(), WatchError> {
+        self.watcher.add(entry)
+    }
+
+    fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+        self.watcher.remove(path)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.watcher.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use configuration::ParseOptions;
+
+    use super::*;
+
+    #[test]
+    fn drops_repeats_within_the_window() {
+        let test_cases = vec![
+            ("2s", Ok(Some(Duration::from_secs(2)))),
+            (" 250ms ", Ok(Some(Duration::from_millis(250)))),
+            ("off", Ok(None)),
+            ("0s", Err(())),
+            ("often", Err(())),
+        ];
+        for (input, expected) in test_cases {
+            assert_eq!(
+                DedupeDirective.parse(input).map_err(|_| ()),
+                expected,
+                "{input}"
+            );
+        }
+        let mut options = ParseOptions::default();
+        crate::register_directives(&mut options.directives);
+        let config = Config::parse_with("dedupe 1s\ndedupe off", &options).unwrap();
+        assert_eq!(DedupeDirective::window(&config), None);
+
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut deduplicator = Deduplicator::new(Duration::from_secs(1));
+        let modify = Event::new("/srv/app.log", EventKind::Modify);
+        let delete = Event::new("/srv/app.log", EventKind::Delete);
+        let other = Event::new("/srv/other.log", EventKind::Modify);
+        let test_cases = vec![
+            (&modify, 0, true),
+            (&modify, 100, false),
+            (&other, 200, true),
+            (&delete, 300, true),
+            (&modify, 900, false),
+            (&modify, 1000, true),
+            (&modify, 1500, false),
+            (&other, 2500, true),
+        ];
+        for (event, millis, admitted) in test_cases {
+            assert_eq!(
+                deduplicator.admit(event, at(millis)),
+                admitted,
+                "{event:?} at {millis}ms"
+            );
+        }
+        assert_eq!(deduplicator.dropped(), 3);
+        assert_eq!(deduplicator.passed.len(), 1);
+    }
+}
@@ -0,0 +1,47 @@
+//! Errors produced while watching paths.
+
+use std::{error::Error, fmt, io, path::PathBuf};
+
+use configuration::PathSpec;
+
+/// Errors which can occur while setting up or reading a watcher.
+#[derive(Debug)]
+pub enum WatchError {
+    /// The watcher itself could not be created or read from.
+    Io(io::Error),
+    /// A path could not be registered with the watcher.
+    Watch { path: PathBuf, error: io::Error },
+    /// The operating system's event queue filled up and events were lost. Watching carries on,
+    /// but anything which happened in the meantime has to be rescanned.
+    Overflow,
+    /// A path asked to be removed isn't one the watcher includes.
+    NotIncluded(PathSpec),
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchError::Io(err) => write!(f, "failed to read events: {err}"),
+            WatchError::Watch { path, error } => {
+                write!(f, "failed to watch {}: {error}", path.display())
+            }
+            WatchError::Overflow => f.write_str("the event queue overflowed, events were lost"),
+            WatchError::NotIncluded(path) => write!(f, "{path} is not included"),
+        }
+    }
+}
+
+impl Error for WatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WatchError::Io(err) | WatchError::Watch { error: err, .. } => Some(err),
+            WatchError::Overflow | WatchError::NotIncluded(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for WatchError {
+    fn from(err: io::Error) -> Self {
+        WatchError::Io(err)
+    }
+}
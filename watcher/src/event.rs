@@ -0,0 +1,163 @@
+//! The events watchers report.
+
+use std::{collections::VecDeque, path::PathBuf, time::SystemTime};
+
+use configuration::EventKind;
+
+use crate::{WatchError, Watcher};
+
+/// Something which happened to a watched path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// The file or directory the event happened to.
+    pub path: PathBuf,
+    pub kind: EventKind,
+    /// For a rename whose old and new paths were both seen, the path it was renamed from, with
+    /// `path` the one it was renamed to. A rename only seen from one end has none.
+    pub from: Option<PathBuf>,
+    /// When the watcher received the event from the operating system.
+    pub timestamp: SystemTime,
+}
+
+impl Event {
+    /// An event for `path` received now.
+    pub fn new(path: impl Into<PathBuf>, kind: EventKind) -> Self {
+        Self {
+            path: path.into(),
+            kind,
+            from: None,
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    /// A rename of `from` to `to` received now.
+    pub fn renamed(from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> Self {
+        Self {
+            from: Some(from.into()),
+            ..Self::new(to, EventKind::Rename)
+        }
+    }
+}
+
+/// The events of a watcher one at a time, blocking until the next one arrives, returned by
+/// [`Watcher::events`].
+///
+/// Iteration ends at the first error, which [`Events::take_error`] then returns:
+///
+/// ```no_run
+/// # use watcher::{AutoWatcher, Watcher};
+/// # let config = "include /etc".parse().unwrap();
+/// let mut watcher = AutoWatcher::new(&config)?;
+/// let mut events = watcher.events();
+/// for event in events.by_ref() {
+///     println!("{:?} {}", event.kind, event.path.display());
+/// }
+/// if let Some(err) = events.take_error() {
+///     eprintln!("{err}");
+/// }
+/// # Ok::<(), watcher::WatchError>(())
+/// ```
+#[derive(Debug)]
+pub struct Events<'a, W: ?Sized> {
+    watcher: &'a mut W,
+    pending: VecDeque<Event>,
+    error: Option<WatchError>,
+}
+
+impl<'a, W: Watcher + ?Sized> Events<'a, W> {
+    pub(crate) fn new(watcher: &'a mut W) -> Self {
+        Self {
+            watcher,
+            pending: VecDeque::new(),
+            error: None,
+        }
+    }
+
+    /// The error which ended iteration, if any. Iterating again afterwards waits for more
+    /// events.
+    pub fn take_error(&mut self) -> Option<WatchError> {
+        self.error.take()
+    }
+}
+
+impl<W: Watcher + ?Sized> Iterator for Events<'_, W> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        if self.error.is_some() {
+            return None;
+        }
+        while self.pending.is_empty() {
+            match self.watcher.read_events() {
+                Ok(events) => self.pending.extend(events),
+                Err(err) => {
+                    self.error = Some(err);
+                    return None;
+                }
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use configuration::{PathSpec, WatchEntry};
+
+    use super::*;
+    use crate::Capabilities;
+
+    /// Returns the batches it was given, then fails.
+    struct Scripted(Vec<Vec<Event>>);
+
+    impl Watcher for Scripted {
+        fn add(&mut self, _entry: WatchEntry) -> Result<(), WatchError> {
+            Ok(())
+        }
+
+        fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+            Err(WatchError::NotIncluded(path.clone()))
+        }
+
+        fn read_events_timeout(
+            &mut self,
+            _timeout: Option<Duration>,
+        ) -> Result<Vec<Event>, WatchError> {
+            if self.0.is_empty() {
+                return Err(WatchError::Overflow);
+            }
+            Ok(self.0.remove(0))
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                recursive: false,
+                realtime: false,
+                remote: false,
+            }
+        }
+    }
+
+    #[test]
+    fn iterates_until_an_error() {
+        let event = |path: &str| Event::new(path, EventKind::Create);
+        let mut watcher = Scripted(vec![
+            vec![event("/a"), event("/b")],
+            vec![],
+            vec![event("/c")],
+        ]);
+        let mut events = watcher.events();
+        let paths: Vec<_> = events.by_ref().map(|event| event.path).collect();
+        assert_eq!(paths, [PathBuf::from("/a"), "/b".into(), "/c".into()]);
+        assert!(matches!(events.take_error(), Some(WatchError::Overflow)));
+        assert!(events.take_error().is_none());
+
+        let mut boxed: Box<dyn Watcher> = Box::new(Scripted(vec![vec![event("/d")]]));
+        assert_eq!(
+            boxed.events().next().map(|event| event.path),
+            Some("/d".into())
+        );
+    }
+}
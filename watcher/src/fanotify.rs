@@ -0,0 +1,376 @@
+//! The Linux backend for whole filesystems, built on fanotify.
+
+use std::{
+    collections::HashSet,
+    ffi::{CString, OsStr},
+    fs, io, mem,
+    os::{
+        fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::{ffi::OsStrExt, fs::MetadataExt},
+    },
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use configuration::{Config, EventKind, PathSpec, WatchEntry};
+
+use crate::{
+    backend::deadline, inotify::wait_readable, walk, Capabilities, Event, WatchError, Watcher,
+};
+
+/// The events every mark is registered for, on directories as well as files.
+const MARK_MASK: u64 = libc::FAN_CREATE
+    | libc::FAN_MODIFY
+    | libc::FAN_ATTRIB
+    | libc::FAN_DELETE
+    | libc::FAN_DELETE_SELF
+    | libc::FAN_MOVED_FROM
+    | libc::FAN_MOVED_TO
+    | libc::FAN_MOVE_SELF
+    | libc::FAN_ONDIR;
+
+const BUFFER_SIZE: usize = 16 * 1024;
+
+/// Watches the includes of a configuration by marking the filesystems they live on with
+/// fanotify.
+///
+/// Each filesystem holding an include is marked once, however many directories below it are
+/// watched, so there is no per-directory watch to run out of on large trees and new
+/// subdirectories are covered as soon as they appear. The kernel reports changes anywhere on
+/// the filesystem, and only those to paths the configuration watches are returned.
+///
+/// Requires Linux 5.9 and `CAP_SYS_ADMIN`. Filesystems which can't identify their files by
+/// handle, such as some FUSE ones, can't be marked.
+#[derive(Debug)]
+pub struct FanotifyWatcher {
+    fd: OwnedFd,
+    config: Config,
+    /// A directory on each marked filesystem, which file handles are opened relative to.
+    filesystems: Vec<(PathBuf, OwnedFd)>,
+    /// The device of each marked filesystem.
+    devices: HashSet<u64>,
+    buffer: Vec<u8>,
+}
+
+impl FanotifyWatcher {
+    /// Creates a fanotify group and marks the filesystem of every path `config` includes.
+    ///
+    /// Fails with [`io::ErrorKind::PermissionDenied`] without `CAP_SYS_ADMIN`.
+    pub fn new(config: &Config) -> Result<Self, WatchError> {
+        // SAFETY: fanotify_init takes no pointers, and a non-negative result is a new fd.
+        let fd = unsafe {
+            libc::fanotify_init(
+                libc::FAN_CLOEXEC | libc::FAN_CLASS_NOTIF | libc::FAN_REPORT_DFID_NAME,
+                (libc::O_RDONLY | libc::O_LARGEFILE) as u32,
+            )
+        };
+        if fd < 0 {
+            return Err(WatchError::Io(io::Error::last_os_error()));
+        }
+        let mut watcher = Self {
+            // SAFETY: the fd was just created and nothing else owns it.
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            config: config.clone(),
+            filesystems: Vec::new(),
+            devices: HashSet::new(),
+            buffer: vec![0; BUFFER_SIZE],
+        };
+        watcher.mark_all()?;
+        Ok(watcher)
+    }
+
+    /// Marks the filesystems of the configuration's includes which aren't marked yet.
+    fn mark_all(&mut self) -> Result<(), WatchError> {
+        for path in walk::roots(&self.config) {
+            let device = fs::metadata(&path).map_err(|error| WatchError::Watch {
+                path: path.clone(),
+                error,
+            })?;
+            if !self.devices.contains(&device.dev()) {
+                self.mark(path)?;
+                self.devices.insert(device.dev());
+            }
+        }
+        Ok(())
+    }
+
+    /// A path on each filesystem which is marked.
+    pub fn marked(&self) -> impl Iterator<Item = &Path> {
+        self.filesystems.iter().map(|(path, _)| path.as_path())
+    }
+
+    fn read(&mut self) -> Result<usize, WatchError> {
+        loop {
+            // SAFETY: the buffer is valid for writes of its whole length.
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    self.buffer.as_mut_ptr().cast(),
+                    self.buffer.len(),
+                )
+            };
+            if read >= 0 {
+                return Ok(read as usize);
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(WatchError::Io(err));
+            }
+        }
+    }
+
+    /// The path an event's information records name: the directory identified by its handle,
+    /// joined with the entry name which follows it.
+    fn path(&self, mut records: &[u8]) -> Option<PathBuf> {
+        let header_len = mem::size_of::<libc::fanotify_event_info_header>();
+        while records.len() >= header_len {
+            // SAFETY: a whole record header is in bounds, and may be unaligned.
+            let header = unsafe {
+                records
+                    .as_ptr()
+                    .cast::<libc::fanotify_event_info_header>()
+                    .read_unaligned()
+            };
+            let len = (header.len as usize).clamp(header_len, records.len());
+            let (record, rest) = records.split_at(len);
+            records = rest;
+            if header.info_type != libc::FAN_EVENT_INFO_TYPE_DFID_NAME {
+                continue;
+            }
+            // The fsid, then a `file_handle`, then the nul terminated entry name.
+            let handle = record.get(mem::size_of::<libc::fanotify_event_info_fid>()..)?;
+            let handle_len = mem::size_of::<libc::file_handle>()
+                + u32::from_ne_bytes(handle.get(..4)?.try_into().ok()?) as usize;
+            let (handle, name) = (handle.get(..handle_len)?, handle.get(handle_len..)?);
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+
+            let dir = self.open_handle(handle)?;
+            return Some(match name {
+                b"" | b"." => dir,
+                name => dir.join(OsStr::from_bytes(name)),
+            });
+        }
+        None
+    }
+
+    /// Resolves a file handle of a directory to its current path.
+    fn open_handle(&self, handle: &[u8]) -> Option<PathBuf> {
+        // `file_handle` must be aligned for its integer fields.
+        let mut aligned = vec![0u32; handle.len().div_ceil(4)];
+        // SAFETY: `aligned` holds at least `handle.len()` bytes.
+        unsafe {
+            handle
+                .as_ptr()
+                .copy_to_nonoverlapping(aligned.as_mut_ptr().cast(), handle.len())
+        };
+        self.filesystems.iter().find_map(|(_, mount)| {
+            // SAFETY: `aligned` is a whole `file_handle` with the length it declares.
+            let fd = unsafe {
+                libc::open_by_handle_at(
+                    mount.as_raw_fd(),
+                    aligned.as_mut_ptr().cast(),
+                    libc::O_PATH | libc::O_CLOEXEC,
+                )
+            };
+            if fd < 0 {
+                return None;
+            }
+            // SAFETY: the fd was just opened and nothing else owns it.
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd())).ok()
+        })
+    }
+
+    fn mark(&mut self, path: PathBuf) -> Result<(), WatchError> {
+        let fail = |path, error| WatchError::Watch { path, error };
+        let c_path = match CString::new(path.as_os_str().as_bytes()) {
+            Ok(c_path) => c_path,
+            Err(err) => return Err(fail(path, err.into())),
+        };
+        // SAFETY: `c_path` is a valid nul terminated string for the duration of the calls.
+        let marked = unsafe {
+            libc::fanotify_mark(
+                self.fd.as_raw_fd(),
+                libc::FAN_MARK_ADD | libc::FAN_MARK_FILESYSTEM,
+                MARK_MASK,
+                libc::AT_FDCWD,
+                c_path.as_ptr(),
+            )
+        };
+        if marked < 0 {
+            return Err(fail(path, io::Error::last_os_error()));
+        }
+        // SAFETY: as above. open_by_handle_at refuses O_PATH descriptors.
+        let mount = unsafe { libc::open(c_path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC) };
+        if mount < 0 {
+            return Err(fail(path, io::Error::last_os_error()));
+        }
+        // SAFETY: the fd was just opened and nothing else owns it.
+        let mount = unsafe { OwnedFd::from_raw_fd(mount) };
+        self.filesystems.push((path, mount));
+        Ok(())
+    }
+}
+
+impl Watcher for FanotifyWatcher {
+    /// Only events for paths the configuration watches are returned.
+    ///
+    /// fanotify merges events for the same path which are still queued, so a file created,
+    /// written and removed again can arrive as one. Such events are returned in the order
+    /// create, modify, rename, delete.
+    ///
+    /// Events whose path can no longer be found, such as for a directory which was removed
+    /// along with its parent, are left out.
+    fn read_events_timeout(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, WatchError> {
+        let deadline = deadline(timeout);
+        loop {
+            if !wait_readable(self.fd.as_fd(), deadline).map_err(WatchError::Io)? {
+                return Ok(Vec::new());
+            }
+            let read = self.read()?;
+            let mut events = Vec::new();
+            let mut overflowed = false;
+            let mut offset = 0;
+            while offset + mem::size_of::<libc::fanotify_event_metadata>() <= read {
+                // SAFETY: the kernel wrote a whole event header at `offset`.
+                let meta = unsafe {
+                    self.buffer
+                        .as_ptr()
+                        .add(offset)
+                        .cast::<libc::fanotify_event_metadata>()
+                        .read_unaligned()
+                };
+                let event_len = meta.event_len as usize;
+                if event_len < mem::size_of::<libc::fanotify_event_metadata>() {
+                    break;
+                }
+                let record = offset + meta.metadata_len as usize..(offset + event_len).min(read);
+                offset += event_len;
+                if meta.fd >= 0 {
+                    // SAFETY: the kernel opened this fd for the event, and nothing else owns it.
+                    drop(unsafe { OwnedFd::from_raw_fd(meta.fd) });
+                }
+
+                if meta.mask & libc::FAN_Q_OVERFLOW != 0 {
+                    overflowed = true;
+                    continue;
+                }
+                let kinds = kinds(meta.mask);
+                if kinds.is_empty() {
+                    continue;
+                }
+                let Some(path) = self.path(&self.buffer[record]) else {
+                    continue;
+                };
+                if self.config.is_watched(&path) {
+                    events.extend(kinds.into_iter().map(|kind| Event::new(path.clone(), kind)));
+                }
+            }
+            if overflowed && events.is_empty() {
+                return Err(WatchError::Overflow);
+            }
+            if !events.is_empty() {
+                return Ok(events);
+            }
+        }
+    }
+
+    fn add(&mut self, entry: WatchEntry) -> Result<(), WatchError> {
+        self.config.add_include(entry);
+        self.mark_all()
+    }
+
+    /// The filesystem stays marked, with events for paths no longer watched left out.
+    fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+        self.config
+            .remove_include(path)
+            .map(drop)
+            .ok_or_else(|| WatchError::NotIncluded(path.clone()))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            recursive: true,
+            realtime: true,
+            remote: false,
+        }
+    }
+}
+
+impl AsRawFd for FanotifyWatcher {
+    /// The fanotify group, which becomes readable when events are waiting.
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// The kinds of change a fanotify mask reports, in the order they most likely happened.
+fn kinds(mask: u64) -> Vec<EventKind> {
+    [
+        (libc::FAN_CREATE, EventKind::Create),
+        (libc::FAN_MODIFY | libc::FAN_ATTRIB, EventKind::Modify),
+        (
+            libc::FAN_MOVED_FROM | libc::FAN_MOVED_TO | libc::FAN_MOVE_SELF,
+            EventKind::Rename,
+        ),
+        (libc::FAN_DELETE | libc::FAN_DELETE_SELF, EventKind::Delete),
+    ]
+    .into_iter()
+    .filter(|(bits, _)| mask & bits != 0)
+    .map(|(_, kind)| kind)
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_watched_paths_anywhere_on_the_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a")).unwrap();
+        let config: Config = format!(
+            "include -r {0}\nexclude {0}/skip\ninclude_hidden off",
+            root.display()
+        )
+        .parse()
+        .unwrap();
+        let mut watcher = match FanotifyWatcher::new(&config) {
+            Ok(watcher) => watcher,
+            // Without CAP_SYS_ADMIN, or on a filesystem fanotify can't mark, there is nothing
+            // to test.
+            Err(WatchError::Io(_) | WatchError::Watch { .. }) => return,
+            Err(err) => panic!("{err}"),
+        };
+        assert_eq!(watcher.marked().collect::<Vec<_>>(), [root]);
+
+        // Directories created after the watcher are covered without registering them.
+        fs::create_dir_all(root.join("a/new/deeper")).unwrap();
+        fs::create_dir_all(root.join("skip")).unwrap();
+        fs::write(root.join("skip/file"), "x").unwrap();
+        fs::write(root.join("a/.hidden"), "x").unwrap();
+        fs::write(root.join("a/new/deeper/file"), "x").unwrap();
+        fs::remove_file(root.join("a/new/deeper/file")).unwrap();
+
+        let mut events: Vec<Event> = Vec::new();
+        while !events.iter().any(|event| event.kind == EventKind::Delete) {
+            events.extend(watcher.read_events().unwrap());
+        }
+        let mut kinds: Vec<_> = events
+            .iter()
+            .map(|event| (event.path.clone(), event.kind))
+            .collect();
+        kinds.dedup();
+        assert_eq!(
+            kinds,
+            [
+                (root.join("a/new"), EventKind::Create),
+                (root.join("a/new/deeper"), EventKind::Create),
+                (root.join("a/new/deeper/file"), EventKind::Create),
+                (root.join("a/new/deeper/file"), EventKind::Modify),
+                (root.join("a/new/deeper/file"), EventKind::Delete),
+            ]
+        );
+    }
+}
@@ -0,0 +1,426 @@
+//! Dropping the events a configuration leaves out, before anything acts on them.
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    time::{Duration, Instant},
+};
+
+use configuration::{Config, PathSpec, RateLimit, WatchEntry};
+
+use crate::{
+    backend::{deadline, remaining},
+    Capabilities, Event, WatchError, Watcher,
+};
+
+/// Why a [`Filter`] dropped an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DropReason {
+    /// No include covers the path, or an exclude carves it out.
+    Excluded,
+    /// An `ignore` pattern or ignore file matches the path.
+    Ignored,
+    /// The path is hidden below an include which leaves out hidden files.
+    Hidden,
+    /// The include's `events` leave out the event's kind.
+    Kind,
+    /// The file's extension isn't one of the include's `only_extensions`.
+    Extension,
+    /// The file is larger than the include's `max_size`.
+    Size,
+    /// The file isn't owned by one of the `owner` users or `group` groups.
+    Owner,
+    /// The include, or the watch group it's in, passed on as many events as its `rate_limit`
+    /// allows.
+    RateLimit,
+}
+
+impl DropReason {
+    pub const ALL: [DropReason; 8] = [
+        DropReason::Excluded,
+        DropReason::Ignored,
+        DropReason::Hidden,
+        DropReason::Kind,
+        DropReason::Extension,
+        DropReason::Size,
+        DropReason::Owner,
+        DropReason::RateLimit,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DropReason::Excluded => "excluded",
+            DropReason::Ignored => "ignored",
+            DropReason::Hidden => "hidden",
+            DropReason::Kind => "kind",
+            DropReason::Extension => "extension",
+            DropReason::Size => "size",
+            DropReason::Owner => "owner",
+            DropReason::RateLimit => "rate_limit",
+        }
+    }
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How many events a [`Filter`] has let through, and how many it dropped for each reason.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilterCounts {
+    pub passed: u64,
+    dropped: [u64; DropReason::ALL.len()],
+}
+
+impl FilterCounts {
+    /// The number of events dropped for `reason`.
+    pub fn dropped_for(&self, reason: DropReason) -> u64 {
+        self.dropped[reason as usize]
+    }
+
+    /// The number of events dropped for any reason.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.iter().sum()
+    }
+}
+
+/// Applies the filters of a configuration to events as the operating system reports them.
+///
+/// An event comes through if the configuration watches its path, as [`Config::is_watched`]
+/// decides, and the include deciding the path reports its kind. Files which still exist are
+/// then checked against the include's `only_extensions`, `max_size` and the `owner` and `group`
+/// lists. Directories pass those regardless, and a path which is gone can only be checked by
+/// extension.
+///
+/// Events which pass are finally held to the `rate_limit` of their include and, shared by
+/// all of its includes, that of the watch group it's in.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    config: Config,
+    counts: FilterCounts,
+    buckets: HashMap<Bucket, TokenBucket>,
+}
+
+/// What a rate limit is kept for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Bucket {
+    Include(String),
+    Group(String),
+}
+
+/// A token bucket holding events to a [`RateLimit`]: it starts full with `burst` tokens and
+/// refills at the rate, and each event passed takes one.
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: f64::from(limit.burst),
+            refilled: now,
+        }
+    }
+
+    /// Takes a token for an event at `now`, returning false if there are none left.
+    fn take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        let rate = f64::from(self.limit.events) / self.limit.period.as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(f64::from(self.limit.burst));
+        self.refilled = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+impl Filter {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+            counts: FilterCounts::default(),
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Why `event` would be dropped, or `None` if it comes through. Nothing is counted, and
+    /// rate limits aren't checked since that would use them up.
+    pub fn check(&self, event: &Event) -> Option<DropReason> {
+        let config = &self.config;
+        let path = &event.path;
+        let Some(entry) = config.include_for(path) else {
+            return Some(if config.is_ignored(path) {
+                DropReason::Ignored
+            } else if config.is_hidden(path) {
+                DropReason::Hidden
+            } else {
+                DropReason::Excluded
+            });
+        };
+        if !config.events_for(&entry).contains(event.kind) {
+            return Some(DropReason::Kind);
+        }
+        let metadata = fs::metadata(path).ok();
+        if metadata.as_ref().is_some_and(|metadata| metadata.is_dir()) {
+            return None;
+        }
+        if !config.allows_extension(&entry, path) {
+            return Some(DropReason::Extension);
+        }
+        let metadata = metadata?;
+        if config
+            .max_size_for(&entry)
+            .is_some_and(|max| metadata.len() > max)
+        {
+            return Some(DropReason::Size);
+        }
+        if !config.owners().matches(&metadata) {
+            return Some(DropReason::Owner);
+        }
+        None
+    }
+
+    /// Returns true if `event` comes through, counting it either way.
+    pub fn apply(&mut self, event: &Event) -> bool {
+        self.apply_at(event, Instant::now())
+    }
+
+    fn apply_at(&mut self, event: &Event, now: Instant) -> bool {
+        let reason = self
+            .check(event)
+            .or_else(|| (!self.within_rate_limits(event, now)).then_some(DropReason::RateLimit));
+        match reason {
+            Some(reason) => {
+                self.counts.dropped[reason as usize] += 1;
+                false
+            }
+            None => {
+                self.counts.passed += 1;
+                true
+            }
+        }
+    }
+
+    pub fn counts(&self) -> FilterCounts {
+        self.counts
+    }
+
+    /// Takes a token for `event` from the buckets of its include and group, returning false if
+    /// either is empty. The group's bucket is only taken from once the include's allows it.
+    fn within_rate_limits(&mut self, event: &Event, now: Instant) -> bool {
+        let Some(entry) = self.config.include_for(&event.path) else {
+            return true;
+        };
+        let mut limits = vec![];
+        if let Some(limit) = self.config.rate_limit_for(&entry) {
+            limits.push((Bucket::Include(entry.path.to_string()), limit));
+        }
+        if let Some(group) = self.config.group_for(&event.path) {
+            if let Some(limit) = group.options.rate_limit {
+                limits.push((Bucket::Group(group.name.clone()), limit));
+            }
+        }
+        limits.into_iter().all(|(key, limit)| {
+            let bucket = self
+                .buckets
+                .entry(key)
+                .or_insert_with(|| TokenBucket::new(limit, now));
+            // A reload changing the limit starts it over.
+            if bucket.limit != limit {
+                *bucket = TokenBucket::new(limit, now);
+            }
+            bucket.take(now)
+        })
+    }
+}
+
+/// A watcher whose events go through a [`Filter`] before they are returned.
+#[derive(Debug)]
+pub struct Filtered<W> {
+    watcher: W,
+    filter: Filter,
+}
+
+impl<W: Watcher> Filtered<W> {
+    /// Filters the events of `watcher` by `config`, which should be the configuration the
+    /// watcher was built from.
+    pub fn new(watcher: W, config: &Config) -> Self {
+        Self {
+            watcher,
+            filter: Filter::new(config),
+        }
+    }
+
+    /// How many events have been let through and dropped so far.
+    pub fn counts(&self) -> FilterCounts {
+        self.filter.counts()
+    }
+
+    /// Filters later events by `config`, as after a reload, keeping the counts so far. The
+    /// watcher itself is told of added and removed includes with [`Watcher::add`] and
+    /// [`Watcher::remove`].
+    pub fn set_config(&mut self, config: &Config) {
+        self.filter.config = config.clone();
+    }
+
+    /// The watcher events are read from.
+    pub fn get_ref(&self) -> &W {
+        &self.watcher
+    }
+
+    pub fn into_inner(self) -> W {
+        self.watcher
+    }
+}
+
+impl<W: Watcher> Watcher for Filtered<W> {
+    /// A read the filter drops everything from is retried until the timeout passes, so reads
+    /// only return nothing once it has.
+    fn read_events_timeout(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, WatchError> {
+        let deadline = deadline(timeout);
+        loop {
+            let mut events = self.watcher.read_events_timeout(remaining(deadline))?;
+            let read = events.len();
+            events.retain(|event| self.filter.apply(event));
+            if !events.is_empty() || read == 0 || remaining(deadline) == Some(Duration::ZERO) {
+                return Ok(events);
+            }
+        }
+    }
+
+    fn add(&mut self, entry: WatchEntry) -> Result<(), WatchError> {
+        self.filter.config.add_include(entry.clone());
+        self.watcher.add(entry)
+    }
+
+    fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+        self.watcher.remove(path)?;
+        self.filter.config.remove_include(path);
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.watcher.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use configuration::EventKind;
+
+    use super::*;
+
+    #[test]
+    fn drops_what_the_configuration_leaves_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src/.git")).unwrap();
+        fs::create_dir_all(root.join("src/dir.d")).unwrap();
+        fs::create_dir_all(root.join("logs")).unwrap();
+        fs::write(root.join("src/main.rs"), "main").unwrap();
+        fs::write(root.join("src/large.rs"), "x".repeat(100)).unwrap();
+        fs::write(root.join("src/notes.txt"), "").unwrap();
+        fs::write(root.join("logs/app.log"), "").unwrap();
+        let config: Config = format!(
+            "include_hidden off\n\
+             max_size 10\n\
+             ignore ~$\n\
+             include -r {0} ext=rs\n\
+             include -r {1} events=create\n\
+             exclude {0}/target",
+            root.join("src").display(),
+            root.join("logs").display(),
+        )
+        .parse()
+        .unwrap();
+        let mut filter = Filter::new(&config);
+
+        use EventKind::*;
+        let test_cases = vec![
+            ("src/main.rs", Modify, None),
+            ("src/dir.d", Create, None),
+            ("src/gone.rs", Delete, None),
+            ("src/gone.txt", Delete, Some(DropReason::Extension)),
+            ("src/notes.txt", Modify, Some(DropReason::Extension)),
+            ("src/large.rs", Modify, Some(DropReason::Size)),
+            ("src/.git/index", Modify, Some(DropReason::Hidden)),
+            ("src/main.rs~", Modify, Some(DropReason::Ignored)),
+            ("src/target/debug.rs", Create, Some(DropReason::Excluded)),
+            ("elsewhere/file", Modify, Some(DropReason::Excluded)),
+            ("logs/app.log", Create, None),
+            ("logs/app.log", Modify, Some(DropReason::Kind)),
+        ];
+        for (name, kind, expected) in &test_cases {
+            let event = Event::new(root.join(name), *kind);
+            assert_eq!(filter.check(&event), *expected, "{name} {kind}");
+            assert_eq!(filter.apply(&event), expected.is_none(), "{name} {kind}");
+        }
+        let counts = filter.counts();
+        assert_eq!(counts.passed, 4);
+        assert_eq!(counts.dropped(), 8);
+        assert_eq!(counts.dropped_for(DropReason::Extension), 2);
+        assert_eq!(counts.dropped_for(DropReason::Excluded), 2);
+        assert_eq!(counts.dropped_for(DropReason::Owner), 0);
+
+        let config: Config = format!("owner no-such-user\ninclude -r {}", root.display())
+            .parse()
+            .unwrap();
+        let event = Event::new(root.join("src/main.rs"), Modify);
+        let expected = cfg!(unix).then_some(DropReason::Owner);
+        assert_eq!(Filter::new(&config).check(&event), expected);
+    }
+
+    #[test]
+    fn drops_events_over_the_rate_limit() {
+        let config: Config = "rate_limit 2/s\n\
+                              include -r /srv/app\n\
+                              include -r /srv/media rate_limit=1/s burst=3\n\
+                              watch logs {\n\
+                                  rate_limit 3/s\n\
+                                  include -r /var/log/nginx rate_limit=10/s\n\
+                                  include -r /var/log/app\n\
+                              }"
+        .parse()
+        .unwrap();
+        let mut filter = Filter::new(&config);
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let modify = |path: &str| Event::new(path, EventKind::Modify);
+
+        let test_cases = vec![
+            ("/srv/app/a", 0, true),
+            ("/srv/app/b", 0, true),
+            ("/srv/app/c", 0, false),
+            ("/srv/app/a", 400, false),
+            ("/srv/app/a", 500, true),
+            ("/srv/media/a", 500, true),
+            ("/srv/media/a", 500, true),
+            ("/srv/media/a", 500, true),
+            ("/srv/media/a", 500, false),
+            ("/srv/media/a", 1500, true),
+            ("/var/log/nginx/access.log", 0, true),
+            ("/var/log/app/app.log", 0, true),
+            ("/var/log/nginx/access.log", 0, true),
+            ("/var/log/app/app.log", 0, false),
+            ("/var/log/nginx/access.log", 0, false),
+            ("/var/log/app/app.log", 500, true),
+        ];
+        for (path, millis, passed) in test_cases {
+            assert_eq!(
+                filter.apply_at(&modify(path), at(millis)),
+                passed,
+                "{path} at {millis}ms"
+            );
+        }
+        assert_eq!(filter.counts().dropped_for(DropReason::RateLimit), 5);
+        assert_eq!(filter.check(&modify("/srv/app/c")), None);
+    }
+}
@@ -0,0 +1,319 @@
+//! Confirming modifications by hashing file contents, so writes which change nothing are
+//! dropped.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use configuration::{Config, Directive, EventKind, PathSpec, WatchEntry};
+
+use crate::{
+    backend::{deadline, remaining},
+    Capabilities, Event, WatchError, Watcher,
+};
+
+/// The blake3 digest of the file at `path`.
+pub fn hash_file<P: AsRef<Path>>(path: P) -> io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?)?;
+    Ok(hasher.finalize())
+}
+
+/// The last known digest of each file, as [`Verified`] keeps them.
+#[derive(Debug, Clone, Default)]
+pub struct HashCache {
+    digests: HashMap<PathBuf, blake3::Hash>,
+}
+
+impl HashCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hashes the file at `path` and records its digest, returning true if it differs from
+    /// the digest recorded before. A file without one has changed.
+    pub fn update(&mut self, path: &Path) -> io::Result<bool> {
+        let digest = hash_file(path)?;
+        Ok(self.digests.insert(path.to_path_buf(), digest) != Some(digest))
+    }
+
+    /// The digest recorded for `path`, if any.
+    pub fn get(&self, path: &Path) -> Option<&blake3::Hash> {
+        self.digests.get(path)
+    }
+
+    /// Drops the digests of `path` and of everything below it.
+    pub fn forget(&mut self, path: &Path) {
+        self.digests.retain(|known, _| !known.starts_with(path));
+    }
+
+    /// Moves the digests of `from` and of everything below it to the same paths under `to`.
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        self.forget(to);
+        let moved: Vec<_> = self
+            .digests
+            .extract_if(|known, _| known.starts_with(from))
+            .collect();
+        for (known, digest) in moved {
+            let below = known.strip_prefix(from).unwrap_or(Path::new(""));
+            self.digests.insert(to.join(below), digest);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.digests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.digests.is_empty()
+    }
+}
+
+/// The `verify_content on|off` directive, which turns on [`Verified`] for a configuration.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VerifyDirective;
+
+impl VerifyDirective {
+    pub const NAME: &'static str = "verify_content";
+
+    /// Whether the last `verify_content` directive of `config` turns verification on. It's
+    /// off without one.
+    pub fn enabled(config: &Config) -> bool {
+        config
+            .custom_values(Self::NAME)
+            .last()
+            .copied()
+            .unwrap_or(false)
+    }
+}
+
+impl Directive for VerifyDirective {
+    type Value = bool;
+
+    fn parse(&self, args: &str) -> Result<bool, String> {
+        match args {
+            "on" => Ok(true),
+            "off" => Ok(false),
+            _ => Err("expected on or off".to_string()),
+        }
+    }
+}
+
+/// A watcher whose modify events are only returned if the file's contents changed.
+///
+/// Every file created or modified is hashed with blake3 and its digest kept in a
+/// [`HashCache`]. A modification leaving the digest as it was, such as a `touch` or a rewrite
+/// with the same contents, is dropped. The first modification of a file not seen before always
+/// comes through, as do events for directories, for files over their `max_size` and for files
+/// which can't be read. Deletions and renames update the cache and come through as they are.
+#[derive(Debug)]
+pub struct Verified<W> {
+    watcher: W,
+    config: Config,
+    cache: HashCache,
+}
+
+impl<W: Watcher> Verified<W> {
+    /// Verifies the modifications `watcher` reports, skipping files over the sizes `config`
+    /// sets, which should be the configuration the watcher was built from.
+    pub fn new(watcher: W, config: &Config) -> Self {
+        Self {
+            watcher,
+            config: config.clone(),
+            cache: HashCache::new(),
+        }
+    }
+
+    /// The digests recorded so far.
+    pub fn cache(&self) -> &HashCache {
+        &self.cache
+    }
+
+    /// The watcher events are read from.
+    pub fn get_ref(&self) -> &W {
+        &self.watcher
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.watcher
+    }
+
+    /// Skips files by the sizes `config` sets from now on, as after a reload, keeping the
+    /// digests recorded.
+    pub fn set_config(&mut self, config: &Config) {
+        self.config = config.clone();
+    }
+
+    /// The watcher, dropping the digests recorded.
+    pub fn into_inner(self) -> W {
+        self.watcher
+    }
+
+    /// Records what `event` did to the cache, returning false if it changed nothing.
+    fn verify(&mut self, event: &Event) -> bool {
+        match (event.kind, &event.from) {
+            (EventKind::Delete, _) | (EventKind::Rename, None) => {
+                self.cache.forget(&event.path);
+                return true;
+            }
+            (EventKind::Rename, Some(from)) => {
+                self.cache.rename(from, &event.path);
+                return true;
+            }
+            (EventKind::Create | EventKind::Modify, _) => {}
+        }
+        let max_size = self
+            .config
+            .include_for(&event.path)
+            .and_then(|entry| self.config.max_size_for(&entry));
+        match fs::metadata(&event.path) {
+            Ok(metadata)
+                if metadata.is_file() && max_size.is_none_or(|max| metadata.len() <= max) =>
+            {
+                match self.cache.update(&event.path) {
+                    Ok(changed) => changed || event.kind == EventKind::Create,
+                    Err(_) => {
+                        self.cache.forget(&event.path);
+                        true
+                    }
+                }
+            }
+            _ => {
+                self.cache.forget(&event.path);
+                true
+            }
+        }
+    }
+}
+
+impl<W: Watcher> Watcher for Verified<W> {
+    /// Hashes the files the watcher reports as modified before returning, so a read only
+    /// returns nothing early if every event it saw was dropped and the timeout passed.
+    fn read_events_timeout(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, WatchError> {
+        let deadline = deadline(timeout);
+        loop {
+            let mut events = self.watcher.read_events_timeout(remaining(deadline))?;
+            let read = events.len();
+            events.retain(|event| self.verify(event));
+            if !events.is_empty() || read == 0 || remaining(deadline) == Some(Duration::ZERO) {
+                return Ok(events);
+            }
+        }
+    }
+
+    fn add(&mut self, entry: WatchEntry) -> Result<(), WatchError> {
+        self.config.add_include(entry.clone());
+        self.watcher.add(entry)
+    }
+
+    fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+        self.watcher.remove(path)?;
+        self.config.remove_include(path);
+        if let PathSpec::Path(path) = path {
+            self.cache.forget(path);
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.watcher.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_writes_which_change_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("file"), "one").unwrap();
+        fs::write(root.join("large"), "too large").unwrap();
+        let config: Config = format!("include -r {}\nmax_size 4", root.display())
+            .parse()
+            .unwrap();
+        let mut verified = Verified::new(Scripted(Vec::new()), &config);
+        let event = |name: &str, kind| Event::new(root.join(name), kind);
+
+        use EventKind::*;
+        let test_cases = vec![
+            ("file", None, Modify, true),
+            ("file", None, Modify, false),
+            ("file", Some("two"), Modify, true),
+            ("file", Some("two"), Modify, false),
+            ("large", None, Modify, true),
+            ("large", None, Modify, true),
+            ("missing", None, Modify, true),
+            ("new", Some("new"), Create, true),
+            ("new", None, Modify, false),
+        ];
+        for (name, contents, kind, expected) in test_cases {
+            if let Some(contents) = contents {
+                fs::write(root.join(name), contents).unwrap();
+            }
+            assert_eq!(
+                verified.verify(&event(name, kind)),
+                expected,
+                "{name} {kind}"
+            );
+        }
+
+        fs::rename(root.join("new"), root.join("renamed")).unwrap();
+        assert!(verified.verify(&Event::renamed(root.join("new"), root.join("renamed"))));
+        assert!(!verified.verify(&event("renamed", Modify)));
+        assert!(verified.verify(&event("file", Delete)));
+        assert_eq!(verified.cache().get(&root.join("file")), None);
+        assert_eq!(verified.cache().len(), 1);
+    }
+
+    #[test]
+    fn filters_what_the_watcher_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("file"), "same").unwrap();
+        let config: Config = format!("include -r {}", root.display()).parse().unwrap();
+        let modified = || Event::new(root.join("file"), EventKind::Modify);
+        let reads = vec![vec![modified()], vec![modified()], vec![]];
+        let mut verified = Verified::new(Scripted(reads), &config);
+
+        assert_eq!(verified.read_events().unwrap().len(), 1);
+        assert!(verified.read_events().unwrap().is_empty());
+    }
+
+    /// Returns the given reads in turn, then nothing.
+    #[derive(Debug)]
+    struct Scripted(Vec<Vec<Event>>);
+
+    impl Watcher for Scripted {
+        fn add(&mut self, _entry: WatchEntry) -> Result<(), WatchError> {
+            Ok(())
+        }
+
+        fn remove(&mut self, _path: &PathSpec) -> Result<(), WatchError> {
+            Ok(())
+        }
+
+        fn read_events_timeout(
+            &mut self,
+            _timeout: Option<Duration>,
+        ) -> Result<Vec<Event>, WatchError> {
+            if self.0.is_empty() {
+                return Ok(Vec::new());
+            }
+            Ok(self.0.remove(0))
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                recursive: false,
+                realtime: false,
+                remote: false,
+            }
+        }
+    }
+}
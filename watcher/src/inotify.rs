@@ -0,0 +1,762 @@
+//! The Linux backend, built on inotify.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{CString, OsStr},
+    fs, io, mem,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use configuration::{Config, EventKind, PathSpec, WatchEntry};
+
+use crate::{
+    backend::{deadline, remaining},
+    walk, Capabilities, Event, WatchError, Watcher,
+};
+
+/// The events every watch is registered for.
+const WATCH_MASK: u32 = libc::IN_CREATE
+    | libc::IN_MODIFY
+    | libc::IN_ATTRIB
+    | libc::IN_DELETE
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_MOVE_SELF;
+
+/// Room for many events at once, and at least one with the longest possible name.
+const BUFFER_SIZE: usize = 16 * 1024;
+
+/// How long the old path of a rename waits for the new one. A path moved somewhere which
+/// isn't watched never gets one, and is reported as deleted once this passes.
+const MOVE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Watches the includes of a configuration with inotify.
+///
+/// Each directory found for the configuration gets a watch of its own, which reports changes
+/// to the entries directly inside it, and an include naming a file watches just that file.
+///
+/// A directory created or moved below a recursive include is registered as soon as its event
+/// is read, along with its subdirectories. Anything created inside them before they were
+/// registered is reported as created, so nothing done in between goes unnoticed, though
+/// something created just as its directory was registered may be reported twice.
+#[derive(Debug)]
+pub struct InotifyWatcher {
+    fd: OwnedFd,
+    config: Config,
+    /// The paths each watch descriptor was registered for. inotify hands out the same
+    /// descriptor for every path to the same inode, such as a directory and a symlink to it,
+    /// and its events are reported on each of them.
+    watches: HashMap<i32, Vec<PathBuf>>,
+    /// Every path of `watches`, to look them up by.
+    registered: HashSet<PathBuf>,
+    /// Paths moved away whose new path hasn't been seen yet.
+    moves: Vec<Move>,
+    /// Whether the queue overflowed in a batch which also had events, so the next read reports
+    /// it rather than it being lost.
+    overflowed: bool,
+    buffer: Vec<u8>,
+}
+
+/// The old path of a rename, waiting for the event with the same cookie naming the new one.
+#[derive(Debug)]
+struct Move {
+    cookie: u32,
+    from: PathBuf,
+    dir: bool,
+    due: Instant,
+}
+
+impl InotifyWatcher {
+    /// Creates an inotify instance and registers a watch for every path `config` includes.
+    pub fn new(config: &Config) -> Result<Self, WatchError> {
+        // SAFETY: inotify_init1 takes no pointers, and a non-negative result is a new fd.
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(WatchError::Io(io::Error::last_os_error()));
+        }
+        let mut watcher = Self {
+            // SAFETY: the fd was just created and nothing else owns it.
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            config: config.clone(),
+            watches: HashMap::new(),
+            registered: HashSet::new(),
+            moves: Vec::new(),
+            overflowed: false,
+            buffer: vec![0; BUFFER_SIZE],
+        };
+        watcher.sync()?;
+        Ok(watcher)
+    }
+
+    /// Registers the paths the configuration now asks for which aren't yet, and drops the
+    /// watches of those it no longer does.
+    fn sync(&mut self) -> Result<(), WatchError> {
+        let wanted = walk::watch_paths(&self.config);
+        {
+            let wanted: HashSet<_> = wanted.iter().map(PathBuf::as_path).collect();
+            self.drop_paths(|path| !wanted.contains(path));
+        }
+        for path in wanted {
+            if !self.registered.contains(&path) {
+                self.add_watch(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The paths currently registered.
+    pub fn watched(&self) -> impl Iterator<Item = &Path> {
+        self.watches.values().flatten().map(PathBuf::as_path)
+    }
+
+    /// Forgets the registered paths `unwanted` picks, removing the watches left with none.
+    fn drop_paths(&mut self, unwanted: impl Fn(&Path) -> bool) {
+        let fd = self.fd.as_raw_fd();
+        let registered = &mut self.registered;
+        self.watches.retain(|&wd, paths| {
+            paths.retain(|path| {
+                let keep = !unwanted(path);
+                if !keep {
+                    registered.remove(path);
+                }
+                keep
+            });
+            if paths.is_empty() {
+                // SAFETY: inotify_rm_watch takes no pointers. The watch may already be gone,
+                // as after its directory was removed, which isn't a problem.
+                unsafe { libc::inotify_rm_watch(fd, wd) };
+            }
+            !paths.is_empty()
+        });
+    }
+
+    /// The paths an event is about, none if it is about a watch which is no longer
+    /// registered.
+    fn paths(&self, raw: &libc::inotify_event, name: &OsStr) -> Vec<PathBuf> {
+        let Some(watched) = self.watches.get(&raw.wd) else {
+            return Vec::new();
+        };
+        watched
+            .iter()
+            .map(|watched| {
+                if name.is_empty() {
+                    watched.clone()
+                } else {
+                    watched.join(name)
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the directory holding `path` is watched, so its watch reports what happens to
+    /// `path` itself.
+    fn is_watched_parent(&self, path: &Path) -> bool {
+        path.parent()
+            .is_some_and(|parent| self.registered.contains(parent))
+    }
+
+    /// Points the watches of the tree at `from`, which was renamed, at `to`.
+    fn move_watches(&mut self, from: &Path, to: &Path) {
+        for path in self.watches.values_mut().flatten() {
+            if let Ok(below) = path.strip_prefix(from) {
+                let moved = if below.as_os_str().is_empty() {
+                    to.to_path_buf()
+                } else {
+                    to.join(below)
+                };
+                self.registered.remove(path);
+                self.registered.insert(moved.clone());
+                *path = moved;
+            }
+        }
+    }
+
+    /// Reports the moves which have waited for their new path for too long as deletions,
+    /// dropping the watches of directories moved away.
+    fn expire_moves(&mut self, now: Instant) -> Vec<Event> {
+        let mut events = Vec::new();
+        let expired: Vec<_> = self
+            .moves
+            .extract_if(.., |pending| pending.due <= now)
+            .collect();
+        for expired in expired {
+            if expired.dir {
+                self.drop_paths(|path| path.starts_with(&expired.from));
+            }
+            events.push(Event::new(expired.from, EventKind::Delete));
+        }
+        events
+    }
+
+    fn add_watch(&mut self, path: PathBuf) -> Result<(), WatchError> {
+        let fail = |path, error| WatchError::Watch { path, error };
+        let c_path = match CString::new(path.as_os_str().as_bytes()) {
+            Ok(c_path) => c_path,
+            Err(err) => return Err(fail(path, err.into())),
+        };
+        // SAFETY: `c_path` is a valid nul terminated string for the duration of the call.
+        let wd =
+            unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), c_path.as_ptr(), WATCH_MASK) };
+        if wd < 0 {
+            return Err(fail(path, io::Error::last_os_error()));
+        }
+        tracing::trace!("watching {}", path.display());
+        let paths = self.watches.entry(wd).or_default();
+        if !paths.contains(&path) {
+            self.registered.insert(path.clone());
+            paths.push(path);
+        }
+        Ok(())
+    }
+
+    /// Registers `dir`, a directory which just appeared, and whatever below it the
+    /// configuration watches, returning create events for what is already inside.
+    fn register_new(&mut self, dir: &Path) -> Vec<Event> {
+        let mut events = Vec::new();
+        for path in walk::watch_paths_below(&self.config, dir) {
+            // A directory which is already gone again can't be registered, and its events
+            // have already been reported.
+            if self.registered.contains(&path) || self.add_watch(path.clone()).is_err() {
+                continue;
+            }
+            let Ok(entries) = fs::read_dir(&path) else {
+                continue;
+            };
+            events.extend(
+                entries
+                    .filter_map(Result::ok)
+                    .map(|entry| Event::new(entry.path(), EventKind::Create)),
+            );
+        }
+        events
+    }
+}
+
+impl Watcher for InotifyWatcher {
+    fn add(&mut self, entry: WatchEntry) -> Result<(), WatchError> {
+        self.config.add_include(entry);
+        self.sync()
+    }
+
+    fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+        self.config
+            .remove_include(path)
+            .ok_or_else(|| WatchError::NotIncluded(path.clone()))?;
+        self.sync()
+    }
+
+    /// Events inotify reports about the watches themselves, such as a watched directory being
+    /// removed, come through as the event on that path, after which the watch is dropped.
+    ///
+    /// A rename within the watched paths is one event on the new path, naming the old one. A
+    /// path moved in from elsewhere is reported as created, and one moved away as deleted.
+    ///
+    /// An overflow of the queue is reported as [`WatchError::Overflow`], after the events read
+    /// along with it.
+    fn read_events_timeout(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, WatchError> {
+        if mem::take(&mut self.overflowed) {
+            return Err(WatchError::Overflow);
+        }
+        let deadline = deadline(timeout);
+        loop {
+            let expired = self.expire_moves(Instant::now());
+            if !expired.is_empty() {
+                return Ok(expired);
+            }
+            let next_move = self.moves.iter().map(|pending| pending.due).min();
+            let wait = match (deadline, next_move) {
+                (Some(deadline), Some(next_move)) => Some(deadline.min(next_move)),
+                (deadline, next_move) => deadline.or(next_move),
+            };
+            if !wait_readable(self.fd.as_fd(), wait).map_err(WatchError::Io)? {
+                if remaining(deadline) == Some(Duration::ZERO) {
+                    return Ok(self.expire_moves(Instant::now()));
+                }
+                continue;
+            }
+            let events = self.read_batch()?;
+            if !events.is_empty() {
+                return Ok(events);
+            }
+        }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            recursive: true,
+            realtime: true,
+            remote: false,
+        }
+    }
+}
+
+impl InotifyWatcher {
+    /// Reads whatever events are queued, blocking until there are some.
+    fn read_batch(&mut self) -> Result<Vec<Event>, WatchError> {
+        let read = loop {
+            // SAFETY: the buffer is valid for writes of its whole length.
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    self.buffer.as_mut_ptr().cast(),
+                    self.buffer.len(),
+                )
+            };
+            if read >= 0 {
+                break read as usize;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(WatchError::Io(err));
+            }
+        };
+
+        let mut events = Vec::new();
+        let mut new_dirs = Vec::new();
+        let mut overflowed = false;
+        let mut offset = 0;
+        while offset + mem::size_of::<libc::inotify_event>() <= read {
+            // SAFETY: the kernel wrote a whole event header at `offset`, which may be unaligned.
+            let raw: libc::inotify_event = unsafe {
+                self.buffer
+                    .as_ptr()
+                    .add(offset)
+                    .cast::<libc::inotify_event>()
+                    .read_unaligned()
+            };
+            let start = offset + mem::size_of::<libc::inotify_event>();
+            offset = start + raw.len as usize;
+            // The name is padded with nul bytes up to `len`.
+            let name = &self.buffer[start..offset.min(read)];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+
+            if raw.mask & libc::IN_Q_OVERFLOW != 0 {
+                overflowed = true;
+                continue;
+            }
+            let dir = raw.mask & libc::IN_ISDIR != 0;
+            // The old path of a rename is queued once for each path of its watch, and the new
+            // path takes all of them at once, as they are the same rename.
+            let paired: Vec<_> = if raw.mask & libc::IN_MOVED_TO != 0 {
+                self.moves
+                    .extract_if(.., |pending| pending.cookie == raw.cookie)
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let paths = self.paths(&raw, OsStr::from_bytes(name));
+            let count = paths.len();
+            for (index, path) in paths.into_iter().enumerate() {
+                if raw.mask & libc::IN_MOVED_FROM != 0 {
+                    self.moves.push(Move {
+                        cookie: raw.cookie,
+                        from: path,
+                        dir,
+                        due: Instant::now() + MOVE_TIMEOUT,
+                    });
+                } else if raw.mask & libc::IN_MOVED_TO != 0 {
+                    // Each new path pairs with an old one in turn, any left over with the last.
+                    match paired.get(index).or(paired.last()) {
+                        Some(pending) => {
+                            if pending.dir {
+                                self.move_watches(&pending.from, &path);
+                            }
+                            events.push(Event::renamed(pending.from.clone(), path));
+                        }
+                        None => {
+                            if dir {
+                                new_dirs.push(path.clone());
+                            }
+                            events.push(Event::new(path, EventKind::Create));
+                        }
+                    }
+                } else if raw.mask & libc::IN_MOVE_SELF != 0 && self.is_watched_parent(&path) {
+                    // Reported by the watch of the directory it was in.
+                } else if let Some(kind) = kind(raw.mask) {
+                    if dir && raw.mask & libc::IN_CREATE != 0 {
+                        new_dirs.push(path.clone());
+                    }
+                    events.push(Event::new(path, kind));
+                }
+            }
+            // The old paths no new one was paired with moved along with the others.
+            for pending in paired.into_iter().skip(count) {
+                if pending.dir {
+                    self.drop_paths(|path| path.starts_with(&pending.from));
+                }
+            }
+            if raw.mask & libc::IN_IGNORED != 0 {
+                if let Some(paths) = self.watches.remove(&raw.wd) {
+                    for path in paths {
+                        self.registered.remove(&path);
+                    }
+                }
+            }
+        }
+        for dir in new_dirs {
+            events.extend(self.register_new(&dir));
+        }
+        events.extend(self.expire_moves(Instant::now()));
+        if overflowed {
+            if events.is_empty() {
+                return Err(WatchError::Overflow);
+            }
+            self.overflowed = true;
+        }
+        Ok(events)
+    }
+}
+
+impl AsRawFd for InotifyWatcher {
+    /// The inotify instance, which becomes readable when events are waiting.
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Waits until `fd` can be read or `deadline` passes, returning whether it can.
+pub(crate) fn wait_readable(fd: BorrowedFd<'_>, deadline: Option<Instant>) -> io::Result<bool> {
+    loop {
+        let timeout = match remaining(deadline) {
+            // Rounded up, so the wait doesn't end just before the deadline.
+            Some(left) => left.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32,
+            None => -1,
+        };
+        let mut poll = libc::pollfd {
+            fd: fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: one valid pollfd is passed.
+        let ready = unsafe { libc::poll(&mut poll, 1, timeout) };
+        if ready >= 0 {
+            return Ok(ready > 0);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// The kind of event an inotify mask reports, if it reports a change.
+fn kind(mask: u32) -> Option<EventKind> {
+    if mask & libc::IN_CREATE != 0 {
+        Some(EventKind::Create)
+    } else if mask & (libc::IN_MODIFY | libc::IN_ATTRIB) != 0 {
+        Some(EventKind::Modify)
+    } else if mask & (libc::IN_DELETE | libc::IN_DELETE_SELF) != 0 {
+        Some(EventKind::Delete)
+    } else if mask & libc::IN_MOVE_SELF != 0 {
+        Some(EventKind::Rename)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// The paths and kinds of `events`. inotify merges repeats of an event which are still
+    /// queued, so how many come through varies and only changes between them are kept.
+    fn kinds(events: &[Event]) -> Vec<(PathBuf, EventKind)> {
+        let mut kinds: Vec<_> = events
+            .iter()
+            .map(|event| (event.path.clone(), event.kind))
+            .collect();
+        kinds.dedup();
+        kinds
+    }
+
+    #[test]
+    fn reports_changes_under_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir_all(root.join("skip")).unwrap();
+        fs::write(root.join("sub/old.txt"), "").unwrap();
+        let config: Config = format!("include -r {0}\nexclude {0}/skip", root.display())
+            .parse()
+            .unwrap();
+        let mut watcher = InotifyWatcher::new(&config).unwrap();
+        let mut watched: Vec<_> = watcher.watched().collect();
+        watched.sort();
+        assert_eq!(watched, [root, &root.join("sub")]);
+
+        fs::write(root.join("skip/a"), "").unwrap();
+        fs::write(root.join("sub/new.txt"), "new").unwrap();
+        fs::rename(root.join("sub/old.txt"), root.join("sub/renamed.txt")).unwrap();
+        fs::remove_file(root.join("sub/renamed.txt")).unwrap();
+
+        let events = watcher.read_events().unwrap();
+        assert_eq!(
+            kinds(&events),
+            [
+                (root.join("sub/new.txt"), EventKind::Create),
+                (root.join("sub/new.txt"), EventKind::Modify),
+                (root.join("sub/renamed.txt"), EventKind::Rename),
+                (root.join("sub/renamed.txt"), EventKind::Delete),
+            ]
+        );
+        let renamed = events.iter().find(|event| event.kind == EventKind::Rename);
+        assert_eq!(renamed.unwrap().from, Some(root.join("sub/old.txt")));
+    }
+
+    #[test]
+    fn registers_directories_created_later() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let config: Config = format!("include -r {}", root.display()).parse().unwrap();
+        let mut watcher = InotifyWatcher::new(&config).unwrap();
+
+        // Created before the watcher can register the new directories.
+        fs::create_dir_all(root.join("new/deeper")).unwrap();
+        fs::write(root.join("new/deeper/early"), "x").unwrap();
+        let mut seen = Vec::new();
+        while !seen.contains(&(root.join("new/deeper/early"), EventKind::Create)) {
+            seen.extend(kinds(&watcher.read_events().unwrap()));
+        }
+        assert!(seen.contains(&(root.join("new"), EventKind::Create)));
+        assert!(seen.contains(&(root.join("new/deeper"), EventKind::Create)));
+        let mut watched: Vec<_> = watcher.watched().collect();
+        watched.sort();
+        assert_eq!(watched, [root, &root.join("new"), &root.join("new/deeper")]);
+
+        fs::write(root.join("new/deeper/late"), "x").unwrap();
+        let events = watcher.read_events().unwrap();
+        assert_eq!(
+            kinds(&events)[0],
+            (root.join("new/deeper/late"), EventKind::Create)
+        );
+    }
+
+    #[test]
+    fn pairs_the_ends_of_renames() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("watched");
+        let outside = dir.path().join("outside");
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(root.join("leaving"), "").unwrap();
+        fs::write(outside.join("arriving"), "").unwrap();
+        let config: Config = format!("include -r {}", root.display()).parse().unwrap();
+        let mut watcher = InotifyWatcher::new(&config).unwrap();
+
+        fs::rename(root.join("dir"), root.join("moved")).unwrap();
+        fs::rename(root.join("leaving"), outside.join("left")).unwrap();
+        fs::rename(outside.join("arriving"), root.join("arrived")).unwrap();
+        let mut events = Vec::new();
+        while events.len() < 3 {
+            events.extend(watcher.read_events().unwrap());
+        }
+        let summary: Vec<_> = events
+            .iter()
+            .map(|event| (event.from.clone(), event.path.clone(), event.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    Some(root.join("dir")),
+                    root.join("moved"),
+                    EventKind::Rename
+                ),
+                (None, root.join("arrived"), EventKind::Create),
+                (None, root.join("leaving"), EventKind::Delete),
+            ]
+        );
+
+        fs::write(root.join("moved/file"), "x").unwrap();
+        let events = watcher.read_events().unwrap();
+        assert_eq!(
+            kinds(&events)[0],
+            (root.join("moved/file"), EventKind::Create)
+        );
+    }
+
+    #[test]
+    fn watches_included_files_themselves() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("passwd");
+        fs::write(&file, "root").unwrap();
+        fs::write(dir.path().join("other"), "").unwrap();
+        let config: Config = format!("include {}", file.display()).parse().unwrap();
+        let mut watcher = InotifyWatcher::new(&config).unwrap();
+
+        fs::write(dir.path().join("other"), "changed").unwrap();
+        fs::write(&file, "root, admin").unwrap();
+        fs::remove_file(&file).unwrap();
+
+        let events = watcher.read_events().unwrap();
+        assert_eq!(
+            kinds(&events),
+            [
+                (file.clone(), EventKind::Modify),
+                (file.clone(), EventKind::Delete),
+            ]
+        );
+        assert_eq!(watcher.watched().count(), 0);
+    }
+
+    #[test]
+    fn reports_includes_which_cant_be_watched() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let config: Config = format!("include {}", missing.display()).parse().unwrap();
+        let err = InotifyWatcher::new(&config).unwrap_err();
+        assert!(
+            matches!(&err, WatchError::Watch { path, error }
+                if *path == missing && error.kind() == io::ErrorKind::NotFound),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn adds_and_removes_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a/deeper")).unwrap();
+        fs::create_dir_all(root.join("b")).unwrap();
+        let config: Config = format!("include {}", root.join("a").display())
+            .parse()
+            .unwrap();
+        let mut watcher = InotifyWatcher::new(&config).unwrap();
+        assert_eq!(
+            watcher.read_events_timeout(Some(Duration::ZERO)).unwrap(),
+            []
+        );
+
+        let added: Config = format!("include -r {}", root.display()).parse().unwrap();
+        watcher.add(added.includes()[0].clone()).unwrap();
+        let mut watched: Vec<_> = watcher.watched().collect();
+        watched.sort();
+        assert_eq!(
+            watched,
+            [
+                root,
+                &root.join("a"),
+                &root.join("a/deeper"),
+                &root.join("b")
+            ]
+        );
+
+        watcher.remove(&PathSpec::Path(root.to_path_buf())).unwrap();
+        assert_eq!(watcher.watched().collect::<Vec<_>>(), [&root.join("a")]);
+        let err = watcher
+            .remove(&PathSpec::Path(root.to_path_buf()))
+            .unwrap_err();
+        assert!(matches!(err, WatchError::NotIncluded(_)), "{err:?}");
+    }
+
+    #[test]
+    fn reports_events_on_every_path_to_a_watch() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        let link = dir.path().join("link");
+        fs::write(&file, "").unwrap();
+        fs::hard_link(&file, &link).unwrap();
+        let config: Config = format!("include {}\ninclude {}", file.display(), link.display())
+            .parse()
+            .unwrap();
+        let mut watcher = InotifyWatcher::new(&config).unwrap();
+        assert_eq!(watcher.watches.len(), 1);
+        assert_eq!(watcher.watched().collect::<Vec<_>>(), [&file, &link]);
+
+        // Truncating and writing may each come through, on both paths.
+        let modified = |watcher: &mut InotifyWatcher| {
+            let events = watcher.read_events().unwrap();
+            assert!(events.iter().all(|event| event.kind == EventKind::Modify));
+            let mut paths: Vec<_> = events.into_iter().map(|event| event.path).collect();
+            paths.sort();
+            paths.dedup();
+            paths
+        };
+        fs::write(&file, "changed").unwrap();
+        assert_eq!(modified(&mut watcher), [file.clone(), link.clone()]);
+
+        // Dropping one of the paths keeps the watch for the other.
+        watcher.remove(&PathSpec::Path(link.clone())).unwrap();
+        assert_eq!(watcher.watched().collect::<Vec<_>>(), [&file]);
+        fs::write(&link, "again").unwrap();
+        assert_eq!(modified(&mut watcher), [file]);
+    }
+
+    #[test]
+    fn pairs_renames_from_every_path_to_a_watch_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let (real, alias, dest) = (
+            dir.path().join("real"),
+            dir.path().join("alias"),
+            dir.path().join("dest"),
+        );
+        fs::create_dir(&real).unwrap();
+        fs::create_dir(&dest).unwrap();
+        std::os::unix::fs::symlink(&real, &alias).unwrap();
+        fs::write(real.join("file"), "").unwrap();
+        let config: Config = format!("include {}\ninclude {}", real.display(), dest.display())
+            .parse()
+            .unwrap();
+        let mut watcher = InotifyWatcher::new(&config).unwrap();
+        // As a symlink to a directory found below a recursive include would be.
+        watcher.add_watch(alias.clone()).unwrap();
+        assert_eq!(watcher.watches.len(), 2);
+
+        fs::rename(real.join("file"), dest.join("file")).unwrap();
+        let events = watcher.read_events().unwrap();
+        let renamed: Vec<_> = events
+            .iter()
+            .map(|event| (event.from.clone(), event.path.clone(), event.kind))
+            .collect();
+        assert_eq!(
+            renamed,
+            [(
+                Some(real.join("file")),
+                dest.join("file"),
+                EventKind::Rename
+            )]
+        );
+        // The move queued for the alias went with it, rather than waiting to become a delete.
+        let later = watcher.read_events_timeout(Some(MOVE_TIMEOUT * 3));
+        assert_eq!(later.unwrap(), []);
+    }
+
+    #[test]
+    fn reports_overflows_after_the_events_read_with_them() {
+        let max_queued: usize = fs::read_to_string("/proc/sys/fs/inotify/max_queued_events")
+            .ok()
+            .and_then(|max| max.trim().parse().ok())
+            .unwrap_or(usize::MAX);
+        if max_queued > 100_000 {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let config: Config = format!("include {}", dir.path().display()).parse().unwrap();
+        let mut watcher = InotifyWatcher::new(&config).unwrap();
+        // Creating and deleting a file queues events which aren't merged with the last one.
+        // Names this long make the events 48 bytes, so the overflow is read along with some.
+        for n in 0..max_queued {
+            let path = dir.path().join(format!("created-and-deleted-{}", n % 2));
+            fs::write(&path, "").unwrap();
+            fs::remove_file(&path).unwrap();
+        }
+        let mut events = 0;
+        loop {
+            match watcher.read_events_timeout(Some(Duration::from_secs(1))) {
+                Ok(read) if read.is_empty() => panic!("the overflow was never reported"),
+                Ok(read) => events += read.len(),
+                Err(WatchError::Overflow) => break,
+                Err(err) => panic!("{err}"),
+            }
+        }
+        assert!(events > 0);
+    }
+}
@@ -0,0 +1,116 @@
+//! Watching the paths a configuration includes and reporting what happens to them.
+//!
+//! A watcher is built from a [`configuration::Config`] and registers every include with the
+//! operating system, including the includes of watch groups. Recursive includes are walked up
+//! front, registering each subdirectory within their depth which the configuration watches, so
+//! excluded, hidden or ignored subtrees are never registered. Symlinked directories are only
+//! descended into where `follow_symlinks` allows it, and a directory reached twice is
+//! registered once.
+//!
+//! Changes are reported as [`Event`]s naming the path, what happened to it and when it was seen.
+//! They are what the kernel reported: events for every file in a watched directory come through,
+//! including those the configuration would filter out by extension, size or owner, until
+//! [`Filtered`] applies those filters, counting what it drops. Where a
+//! backend sees both ends of a rename, inotify, Windows and polling by inode, it reports one
+//! [`EventKind::Rename`] of the new path naming the old one in [`Event::from`]. A move into or
+//! out of the watched tree comes through as a creation or deletion.
+//!
+//! On Linux [`InotifyWatcher`] uses inotify, registering a watch per directory, including
+//! directories which appear below a recursive include later on. For hosts with
+//! trees too large for that, [`FanotifyWatcher`] marks whole filesystems with fanotify instead
+//! and checks every path it's told about against the configuration, which takes
+//! `CAP_SYS_ADMIN`.
+//!
+//! On macOS [`FsEventsWatcher`] watches included directories with FSEvents, which covers whole
+//! trees, and included files with kqueue.
+//!
+//! On Windows [`WindowsWatcher`] reads each included directory with ReadDirectoryChangesW.
+//!
+//! [`PollWatcher`] works anywhere, rescanning the includes every `poll_interval` instead of
+//! relying on the operating system, which suits network and FUSE mounts.
+//!
+//! [`watch_paths`] lists what would be registered for a configuration without watching it, and
+//! [`watch_entries`] the includes those are found from, watch groups' among them.
+//!
+//! Every backend implements [`Watcher`]. [`AutoWatcher`] picks one for each include, polling
+//! network and FUSE mounts and using the native backend everywhere else.
+//!
+//! [`Watcher::events`] iterates over events one at a time. With the `tokio` feature
+//! `Watcher::stream` turns a watcher into a `futures_core::Stream` of events for async code,
+//! reading it on a thread of its own.
+//!
+//! [`Debounced`] wraps a watcher so a burst of events for the same path, such as an editor
+//! writing a temporary file and renaming it over the original, comes out as one event once the
+//! path has been quiet for its `debounce` delay. [`Deduplicated`] is lighter, holding nothing
+//! back: with `dedupe 2s` an event is dropped if one for the same path and of the same kind
+//! came through less than two seconds before. [`Batched`] groups events into batches by
+//! count and age, as a `batch 100 events / 1s` line asks for, so sinks can handle many at once.
+//! [`Verified`] hashes modified files with blake3, as `verify_content on` asks for, and drops
+//! modifications which left a file's contents as they were.
+//!
+//! A [`WatchState`] records the modification time, size, permissions, owner and digest of every
+//! watched path. Kept in a `state_file` between runs, it tells which files changed while
+//! nothing was watching. Recorded once in a `baseline_file`, it tells how they've come to
+//! differ from that baseline.
+//!
+//! Directives like `batch` which only the watcher understands are added to a parser with
+//! [`register_directives`].
+
+mod auto;
+mod backend;
+mod batch;
+mod debounce;
+mod dedupe;
+mod error;
+mod event;
+#[cfg(target_os = "linux")]
+mod fanotify;
+mod filter;
+mod hash;
+#[cfg(target_os = "linux")]
+mod inotify;
+#[cfg(target_os = "macos")]
+mod macos;
+mod poll;
+mod state;
+#[cfg(feature = "tokio")]
+mod stream;
+mod walk;
+#[cfg(windows)]
+mod windows;
+mod worker;
+
+pub use auto::{select, AutoWatcher, Backend};
+pub use backend::{Capabilities, Watcher};
+pub use batch::{BatchDirective, BatchPolicy, Batched, Batcher};
+pub use configuration::EventKind;
+pub use debounce::{Debounced, Debouncer};
+pub use dedupe::{DedupeDirective, Deduplicated, Deduplicator};
+pub use error::WatchError;
+pub use event::{Event, Events};
+#[cfg(target_os = "linux")]
+pub use fanotify::FanotifyWatcher;
+pub use filter::{DropReason, Filter, FilterCounts, Filtered};
+pub use hash::{hash_file, HashCache, Verified, VerifyDirective};
+#[cfg(target_os = "linux")]
+pub use inotify::InotifyWatcher;
+#[cfg(target_os = "macos")]
+pub use macos::FsEventsWatcher;
+pub use poll::PollWatcher;
+pub use state::{BaselineFileDirective, PathState, StateFileDirective, WatchState};
+#[cfg(feature = "tokio")]
+pub use stream::EventStream;
+pub use walk::{watch_entries, watch_paths};
+#[cfg(windows)]
+pub use windows::WindowsWatcher;
+
+/// Registers the directives this crate defines, such as [`BatchDirective`], so configuration
+/// text using them parses.
+pub fn register_directives(registry: &mut configuration::DirectiveRegistry) {
+    registry
+        .register(BatchDirective::NAME, BatchDirective)
+        .register(DedupeDirective::NAME, DedupeDirective)
+        .register(StateFileDirective::NAME, StateFileDirective)
+        .register(BaselineFileDirective::NAME, BaselineFileDirective)
+        .register(VerifyDirective::NAME, VerifyDirective);
+}
@@ -0,0 +1,515 @@
+//! The macOS backend, built on FSEvents with kqueue for individual files.
+
+use std::{
+    collections::VecDeque,
+    ffi::{c_char, c_void, CStr, CString, OsStr},
+    fs, io, mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+    ptr, slice,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use configuration::{Config, EventKind, PathSpec, WatchEntry};
+use fsevent_sys::{
+    core_foundation as cf, FSEventStreamContext, FSEventStreamCreate, FSEventStreamEventFlags,
+    FSEventStreamEventId, FSEventStreamInvalidate, FSEventStreamRef, FSEventStreamRelease,
+    FSEventStreamScheduleWithRunLoop, FSEventStreamStart, FSEventStreamStop,
+};
+
+use crate::{
+    backend::{deadline, remaining},
+    walk, Capabilities, Event, WatchError, Watcher,
+};
+
+/// How long FSEvents gathers events before delivering them, in seconds.
+const LATENCY: f64 = 0.05;
+
+/// How long the backend's threads wait before checking whether the watcher was dropped, in
+/// seconds.
+const WAKE_INTERVAL: f64 = 0.25;
+
+/// The FSEvents flags which mean events were lost and the tree has to be rescanned.
+const DROPPED: FSEventStreamEventFlags = fsevent_sys::kFSEventStreamEventFlagMustScanSubDirs
+    | fsevent_sys::kFSEventStreamEventFlagUserDropped
+    | fsevent_sys::kFSEventStreamEventFlagKernelDropped;
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRunLoopRunInMode(
+        mode: cf::CFStringRef,
+        seconds: cf::CFTimeInterval,
+        return_after_source_handled: cf::Boolean,
+    ) -> i32;
+}
+
+type Batch = Result<Vec<Event>, WatchError>;
+
+/// Watches the includes of a configuration with FSEvents, and files included by themselves
+/// with kqueue.
+///
+/// One FSEvents stream covers every included directory and everything below it, so new
+/// subdirectories are covered as soon as they appear, and only events for paths the
+/// configuration watches are returned. FSEvents reports changes to directory trees, so an
+/// include naming a file gets a kqueue watch of its own instead.
+///
+/// Both run on threads of their own, which stop when the watcher is dropped. Adding or
+/// removing an include starts them again.
+#[derive(Debug)]
+pub struct FsEventsWatcher {
+    config: Config,
+    /// Kept so the channel stays open while no thread is running, as for an empty
+    /// configuration.
+    _sender: mpsc::Sender<Batch>,
+    receiver: mpsc::Receiver<Batch>,
+    /// What was received before the threads were last started again.
+    pending: VecDeque<Batch>,
+    /// The path FSEvents reports for each included directory, with symlinks such as `/var`
+    /// resolved, and the directory as the configuration names it.
+    roots: Vec<(PathBuf, PathBuf)>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl FsEventsWatcher {
+    /// Starts watching every path `config` includes.
+    pub fn new(config: &Config) -> Result<Self, WatchError> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = Self {
+            config: config.clone(),
+            _sender: sender.clone(),
+            receiver,
+            pending: VecDeque::new(),
+            roots: Vec::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+            threads: Vec::new(),
+        };
+        let (mut dirs, mut files) = (Vec::new(), Vec::new());
+        for root in walk::roots(config) {
+            match fs::metadata(&root) {
+                Ok(metadata) if metadata.is_dir() => dirs.push(root),
+                Ok(_) => files.push(root),
+                Err(error) => return Err(WatchError::Watch { path: root, error }),
+            }
+        }
+        if !dirs.is_empty() {
+            watcher.roots = dirs
+                .iter()
+                .map(|dir| {
+                    (
+                        fs::canonicalize(dir).unwrap_or_else(|_| dir.clone()),
+                        dir.clone(),
+                    )
+                })
+                .collect();
+            let thread = start_stream(dirs, sender.clone(), watcher.stop.clone())?;
+            watcher.threads.push(thread);
+        }
+        if !files.is_empty() {
+            let thread = watch_files(files, sender, watcher.stop.clone())?;
+            watcher.threads.push(thread);
+        }
+        Ok(watcher)
+    }
+
+    /// Starts watching `config` in place of the current configuration. The new stream starts
+    /// before the old one stops, so nothing happening in between is missed.
+    fn restart(&mut self, config: Config) -> Result<(), WatchError> {
+        let mut replacement = Self::new(&config)?;
+        replacement.pending = mem::take(&mut self.pending);
+        replacement.pending.extend(self.receiver.try_iter());
+        *self = replacement;
+        Ok(())
+    }
+
+    /// `path` as reported by FSEvents, below the included directory as the configuration names
+    /// it.
+    fn configured(&self, path: PathBuf) -> PathBuf {
+        self.roots
+            .iter()
+            .find_map(|(canonical, root)| Some(root.join(path.strip_prefix(canonical).ok()?)))
+            .unwrap_or(path)
+    }
+}
+
+impl Watcher for FsEventsWatcher {
+    /// Only events for paths the configuration watches are returned.
+    ///
+    /// FSEvents reports everything which happened to a path within a short window as one
+    /// event, so a file created and written arrives as both. Such events are returned in the
+    /// order create, modify, rename, delete.
+    fn read_events_timeout(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, WatchError> {
+        let deadline = deadline(timeout);
+        loop {
+            let received = match self.pending.pop_front() {
+                Some(batch) => Ok(batch),
+                None => match remaining(deadline) {
+                    Some(left) => self.receiver.recv_timeout(left),
+                    None => self
+                        .receiver
+                        .recv()
+                        .map_err(|_| RecvTimeoutError::Disconnected),
+                },
+            };
+            // The watcher holds a sender itself, so the channel can't be disconnected.
+            let Ok(batch) = received else {
+                return Ok(Vec::new());
+            };
+            let events: Vec<_> = batch?
+                .into_iter()
+                .filter_map(|mut event| {
+                    event.path = self.configured(event.path);
+                    self.config.is_watched(&event.path).then_some(event)
+                })
+                .collect();
+            if !events.is_empty() {
+                return Ok(events);
+            }
+        }
+    }
+
+    fn add(&mut self, entry: WatchEntry) -> Result<(), WatchError> {
+        let mut config = self.config.clone();
+        config.add_include(entry);
+        self.restart(config)
+    }
+
+    fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+        let mut config = self.config.clone();
+        config
+            .remove_include(path)
+            .ok_or_else(|| WatchError::NotIncluded(path.clone()))?;
+        self.restart(config)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            recursive: true,
+            realtime: true,
+            remote: false,
+        }
+    }
+}
+
+impl Drop for FsEventsWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Starts a thread running an FSEvents stream for `dirs`, which sends what it reports until
+/// `stop` is set.
+fn start_stream(
+    dirs: Vec<PathBuf>,
+    sender: mpsc::Sender<Batch>,
+    stop: Arc<AtomicBool>,
+) -> Result<JoinHandle<()>, WatchError> {
+    let mut c_dirs = Vec::new();
+    for dir in dirs {
+        match CString::new(dir.as_os_str().as_bytes()) {
+            Ok(c_dir) => c_dirs.push(c_dir),
+            Err(err) => {
+                return Err(WatchError::Watch {
+                    path: dir,
+                    error: err.into(),
+                })
+            }
+        }
+    }
+    let (started, outcome) = mpsc::channel();
+    let thread = thread::spawn(move || {
+        // The stream is created, run and released on this thread, and only it uses the sender.
+        let info = Box::into_raw(Box::new(sender));
+        // SAFETY: every object created here is released here, the context points at the boxed
+        // sender until the stream is released, and the run loop is this thread's own.
+        unsafe {
+            let paths =
+                cf::CFArrayCreateMutable(cf::kCFAllocatorDefault, 0, &cf::kCFTypeArrayCallBacks);
+            for c_dir in &c_dirs {
+                let path = cf::CFStringCreateWithCString(
+                    cf::kCFAllocatorDefault,
+                    c_dir.as_ptr(),
+                    cf::kCFStringEncodingUTF8,
+                );
+                cf::CFArrayAppendValue(paths, path);
+                cf::CFRelease(path);
+            }
+            let context = FSEventStreamContext {
+                version: 0,
+                info: info.cast(),
+                retain: None,
+                release: None,
+                copy_description: None,
+            };
+            let stream = FSEventStreamCreate(
+                cf::kCFAllocatorDefault,
+                callback,
+                &context,
+                paths,
+                fsevent_sys::kFSEventStreamEventIdSinceNow,
+                LATENCY,
+                fsevent_sys::kFSEventStreamCreateFlagFileEvents
+                    | fsevent_sys::kFSEventStreamCreateFlagNoDefer,
+            );
+            cf::CFRelease(paths);
+
+            if stream.is_null() {
+                let _ = started.send(Err(io::Error::other(
+                    "FSEvents stream could not be created",
+                )));
+            } else {
+                FSEventStreamScheduleWithRunLoop(
+                    stream,
+                    cf::CFRunLoopGetCurrent(),
+                    cf::kCFRunLoopDefaultMode,
+                );
+                if FSEventStreamStart(stream) == 0 {
+                    let _ = started.send(Err(io::Error::other(
+                        "FSEvents stream could not be started",
+                    )));
+                } else {
+                    let _ = started.send(Ok(()));
+                    while !stop.load(Ordering::Relaxed) {
+                        CFRunLoopRunInMode(cf::kCFRunLoopDefaultMode, WAKE_INTERVAL, 0);
+                    }
+                    FSEventStreamStop(stream);
+                }
+                FSEventStreamInvalidate(stream);
+                FSEventStreamRelease(stream);
+            }
+            drop(Box::from_raw(info));
+        }
+    });
+    match outcome.recv() {
+        Ok(Ok(())) => Ok(thread),
+        Ok(Err(err)) => Err(WatchError::Io(err)),
+        Err(_) => Err(WatchError::Io(io::Error::other(
+            "the FSEvents thread stopped",
+        ))),
+    }
+}
+
+extern "C" fn callback(
+    _stream: FSEventStreamRef,
+    info: *mut c_void,
+    count: usize,
+    paths: *mut c_void,
+    flags: *const FSEventStreamEventFlags,
+    _ids: *const FSEventStreamEventId,
+) {
+    // SAFETY: `info` is the sender boxed for this stream, and without
+    // kFSEventStreamCreateFlagUseCFTypes FSEvents passes `count` C strings and flags.
+    let (sender, paths, flags) = unsafe {
+        (
+            &*info.cast::<mpsc::Sender<Batch>>(),
+            slice::from_raw_parts(paths.cast::<*const c_char>(), count),
+            slice::from_raw_parts(flags, count),
+        )
+    };
+    let mut events = Vec::new();
+    let mut dropped = false;
+    for (&path, &flags) in paths.iter().zip(flags) {
+        if flags & DROPPED != 0 {
+            dropped = true;
+            continue;
+        }
+        // SAFETY: FSEvents passes nul terminated paths, valid for the duration of the call.
+        let path = Path::new(OsStr::from_bytes(
+            unsafe { CStr::from_ptr(path) }.to_bytes(),
+        ));
+        events.extend(stream_kinds(flags).map(|kind| Event::new(path, kind)));
+    }
+    if dropped {
+        let _ = sender.send(Err(WatchError::Overflow));
+    }
+    if !events.is_empty() {
+        let _ = sender.send(Ok(events));
+    }
+}
+
+/// The kinds of change FSEvents flags report, in the order they most likely happened.
+fn stream_kinds(flags: FSEventStreamEventFlags) -> impl Iterator<Item = EventKind> {
+    [
+        (
+            fsevent_sys::kFSEventStreamEventFlagItemCreated,
+            EventKind::Create,
+        ),
+        (
+            fsevent_sys::kFSEventStreamEventFlagItemModified
+                | fsevent_sys::kFSEventStreamEventFlagItemInodeMetaMod
+                | fsevent_sys::kFSEventStreamEventFlagItemChangeOwner
+                | fsevent_sys::kFSEventStreamEventFlagItemXattrMod
+                | fsevent_sys::kFSEventStreamEventFlagItemFinderInfoMod,
+            EventKind::Modify,
+        ),
+        (
+            fsevent_sys::kFSEventStreamEventFlagItemRenamed,
+            EventKind::Rename,
+        ),
+        (
+            fsevent_sys::kFSEventStreamEventFlagItemRemoved,
+            EventKind::Delete,
+        ),
+    ]
+    .into_iter()
+    .filter(move |(bits, _)| flags & bits != 0)
+    .map(|(_, kind)| kind)
+}
+
+/// Opens each of `files` for kqueue and starts a thread sending their changes until `stop` is
+/// set.
+fn watch_files(
+    files: Vec<PathBuf>,
+    sender: mpsc::Sender<Batch>,
+    stop: Arc<AtomicBool>,
+) -> Result<JoinHandle<()>, WatchError> {
+    // SAFETY: kqueue takes no arguments, and a non-negative result is a new fd.
+    let kq = unsafe { libc::kqueue() };
+    if kq < 0 {
+        return Err(WatchError::Io(io::Error::last_os_error()));
+    }
+    // SAFETY: the fd was just created and nothing else owns it.
+    let kq = unsafe { OwnedFd::from_raw_fd(kq) };
+    let mut opened = Vec::new();
+    for path in files {
+        let fail = |path, error| WatchError::Watch { path, error };
+        let c_path = match CString::new(path.as_os_str().as_bytes()) {
+            Ok(c_path) => c_path,
+            Err(err) => return Err(fail(path, err.into())),
+        };
+        // SAFETY: `c_path` is a valid nul terminated string for the duration of the call.
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_EVTONLY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(fail(path, io::Error::last_os_error()));
+        }
+        // SAFETY: the fd was just opened and nothing else owns it.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let change = libc::kevent {
+            ident: fd.as_raw_fd() as usize,
+            filter: libc::EVFILT_VNODE,
+            flags: libc::EV_ADD | libc::EV_CLEAR,
+            fflags: libc::NOTE_WRITE
+                | libc::NOTE_EXTEND
+                | libc::NOTE_ATTRIB
+                | libc::NOTE_RENAME
+                | libc::NOTE_DELETE
+                | libc::NOTE_REVOKE,
+            data: 0,
+            udata: ptr::null_mut(),
+        };
+        // SAFETY: one change is passed and none are received.
+        let added =
+            unsafe { libc::kevent(kq.as_raw_fd(), &change, 1, ptr::null_mut(), 0, ptr::null()) };
+        if added < 0 {
+            return Err(fail(path, io::Error::last_os_error()));
+        }
+        opened.push((fd, path));
+    }
+
+    Ok(thread::spawn(move || {
+        let timeout = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: (WAKE_INTERVAL * 1e9) as libc::c_long,
+        };
+        // SAFETY: kevent is plain data, for which all zeroes is valid.
+        let mut received: [libc::kevent; 16] = unsafe { mem::zeroed() };
+        while !stop.load(Ordering::Relaxed) {
+            // SAFETY: `received` is valid for writes of its whole length.
+            let count = unsafe {
+                libc::kevent(
+                    kq.as_raw_fd(),
+                    ptr::null(),
+                    0,
+                    received.as_mut_ptr(),
+                    received.len() as libc::c_int,
+                    &timeout,
+                )
+            };
+            if count < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                let _ = sender.send(Err(WatchError::Io(err)));
+                return;
+            }
+            let events: Vec<_> = received[..count as usize]
+                .iter()
+                .filter_map(|change| {
+                    let (_, path) = opened
+                        .iter()
+                        .find(|(fd, _)| fd.as_raw_fd() as usize == change.ident)?;
+                    Some(file_kinds(change.fflags).map(move |kind| Event::new(path, kind)))
+                })
+                .flatten()
+                .collect();
+            if !events.is_empty() && sender.send(Ok(events)).is_err() {
+                return;
+            }
+        }
+    }))
+}
+
+/// The kinds of change kqueue vnode flags report.
+fn file_kinds(flags: u32) -> impl Iterator<Item = EventKind> {
+    [
+        (
+            libc::NOTE_WRITE | libc::NOTE_EXTEND | libc::NOTE_ATTRIB,
+            EventKind::Modify,
+        ),
+        (libc::NOTE_RENAME, EventKind::Rename),
+        (libc::NOTE_DELETE | libc::NOTE_REVOKE, EventKind::Delete),
+    ]
+    .into_iter()
+    .filter(move |(bits, _)| flags & bits != 0)
+    .map(|(_, kind)| kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_changes_under_includes_and_to_included_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("tree/skip")).unwrap();
+        fs::write(root.join("single"), "").unwrap();
+        let config: Config = format!(
+            "include -r {0}/tree\nexclude {0}/tree/skip\ninclude {0}/single",
+            root.display()
+        )
+        .parse()
+        .unwrap();
+        let mut watcher = FsEventsWatcher::new(&config).unwrap();
+
+        fs::write(root.join("tree/skip/file"), "x").unwrap();
+        fs::write(root.join("tree/new.txt"), "x").unwrap();
+        fs::write(root.join("single"), "x").unwrap();
+
+        let mut seen = Vec::new();
+        while !(seen.contains(&(root.join("tree/new.txt"), EventKind::Create))
+            && seen.contains(&(root.join("single"), EventKind::Modify)))
+        {
+            let events = watcher.read_events().unwrap();
+            seen.extend(events.into_iter().map(|event| (event.path, event.kind)));
+        }
+        assert!(
+            seen.iter()
+                .all(|(path, _)| !path.starts_with(root.join("tree/skip"))),
+            "{seen:?}"
+        );
+    }
+}
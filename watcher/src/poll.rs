@@ -0,0 +1,267 @@
+//! The polling backend, for filesystems which don't report their own changes.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, Metadata},
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use configuration::{Config, EventKind, PathSpec, WatchEntry};
+
+use crate::{
+    backend::{deadline, remaining},
+    walk, Capabilities, Event, WatchError, Watcher,
+};
+
+/// How often paths are rescanned when the configuration has no `poll_interval`.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Watches the includes of a configuration by rescanning them every `poll_interval`.
+///
+/// Kernel notifications are unreliable or missing on network and FUSE mounts, such as NFS and
+/// CIFS, so this backend compares what `stat` reports instead. Each scan walks the includes the
+/// way the other backends register them, picking up new subdirectories on its own, and records
+/// the modification time, size and inode of every file and directory it finds. Changes made
+/// and undone between two scans go unnoticed.
+#[derive(Debug)]
+pub struct PollWatcher {
+    config: Config,
+    interval: Duration,
+    snapshot: BTreeMap<PathBuf, Snapshot>,
+    next_scan: Instant,
+}
+
+/// What a scan recorded about a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Snapshot {
+    modified: Option<SystemTime>,
+    size: u64,
+    /// The device and inode, where the platform exposes them.
+    inode: Option<(u64, u64)>,
+    dir: bool,
+}
+
+impl PollWatcher {
+    /// Takes a first snapshot of every path `config` includes.
+    pub fn new(config: &Config) -> Result<Self, WatchError> {
+        check_roots(config)?;
+        let interval = config.poll_interval().unwrap_or(DEFAULT_INTERVAL);
+        Ok(Self {
+            snapshot: scan(config),
+            config: config.clone(),
+            interval,
+            next_scan: Instant::now() + interval,
+        })
+    }
+
+    /// How long the watcher waits between scans.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl Watcher for PollWatcher {
+    /// What is already in the new include is recorded right away, so only later changes to it
+    /// are reported.
+    fn add(&mut self, entry: WatchEntry) -> Result<(), WatchError> {
+        let before = self.config.clone();
+        self.config.add_include(entry);
+        if let Err(err) = check_roots(&self.config) {
+            self.config = before;
+            return Err(err);
+        }
+        let fresh = scan(&self.config)
+            .into_iter()
+            .filter(|(path, _)| !before.is_watched(path));
+        self.snapshot.extend(fresh);
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+        self.config
+            .remove_include(path)
+            .ok_or_else(|| WatchError::NotIncluded(path.clone()))?;
+        let config = &self.config;
+        self.snapshot.retain(|path, _| config.is_watched(path));
+        Ok(())
+    }
+
+    /// Blocks until a scan finds changes. Within a scan, renames come first as the old path
+    /// followed by the new one, matched up by inode, and the other changes follow in path
+    /// order. A path which now holds a different file, as after an editor replaces it, is
+    /// reported as deleted and created again.
+    fn read_events_timeout(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, WatchError> {
+        let deadline = deadline(timeout);
+        loop {
+            let until_scan = self.next_scan.saturating_duration_since(Instant::now());
+            match remaining(deadline) {
+                Some(left) if left < until_scan => {
+                    thread::sleep(left);
+                    return Ok(Vec::new());
+                }
+                _ => thread::sleep(until_scan),
+            }
+            self.next_scan = Instant::now() + self.interval;
+            let snapshot = scan(&self.config);
+            let events = changes(&self.snapshot, &snapshot);
+            self.snapshot = snapshot;
+            if !events.is_empty() {
+                return Ok(events);
+            }
+        }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            recursive: true,
+            realtime: false,
+            remote: true,
+        }
+    }
+}
+
+/// Fails if an include of `config` doesn't exist, as registering it with the other backends
+/// would.
+fn check_roots(config: &Config) -> Result<(), WatchError> {
+    for root in walk::roots(config) {
+        if let Err(error) = fs::metadata(&root) {
+            return Err(WatchError::Watch { path: root, error });
+        }
+    }
+    Ok(())
+}
+
+fn scan(config: &Config) -> BTreeMap<PathBuf, Snapshot> {
+    walk::scan(config, |_, metadata| Snapshot::of(metadata))
+}
+
+impl Snapshot {
+    fn of(metadata: &Metadata) -> Self {
+        Self {
+            modified: metadata.modified().ok(),
+            size: metadata.len(),
+            inode: inode(metadata),
+            dir: metadata.is_dir(),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// The events which turn `old` into `new`.
+fn changes(old: &BTreeMap<PathBuf, Snapshot>, new: &BTreeMap<PathBuf, Snapshot>) -> Vec<Event> {
+    let mut deleted: Vec<_> = old.keys().filter(|path| !new.contains_key(*path)).collect();
+    let mut created: Vec<_> = new.keys().filter(|path| !old.contains_key(*path)).collect();
+
+    let mut events = Vec::new();
+    created.retain(|to| {
+        let inode = new[*to].inode;
+        let Some(index) = deleted
+            .iter()
+            .position(|from| inode.is_some() && old[*from].inode == inode)
+        else {
+            return true;
+        };
+        let from = deleted.remove(index);
+        events.push(Event::renamed(from, *to));
+        false
+    });
+
+    let mut rest: BTreeMap<&PathBuf, Vec<EventKind>> = BTreeMap::new();
+    for path in deleted {
+        rest.insert(path, vec![EventKind::Delete]);
+    }
+    for path in created {
+        rest.insert(path, vec![EventKind::Create]);
+    }
+    for (path, before) in old {
+        let Some(after) = new.get(path) else {
+            continue;
+        };
+        if before.inode != after.inode || before.dir != after.dir {
+            rest.insert(path, vec![EventKind::Delete, EventKind::Create]);
+        } else if !after.dir && (before.modified != after.modified || before.size != after.size) {
+            rest.insert(path, vec![EventKind::Modify]);
+        }
+    }
+    for (path, kinds) in rest {
+        events.extend(kinds.into_iter().map(|kind| Event::new(path, kind)));
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("skip")).unwrap();
+        for file in ["grow", "gone", "moved", "replaced"] {
+            fs::write(root.join(file), "x").unwrap();
+        }
+        let config: Config = format!(
+            "include -r {0}\nexclude {0}/skip\npoll_interval 10ms",
+            root.display()
+        )
+        .parse()
+        .unwrap();
+        let mut watcher = PollWatcher::new(&config).unwrap();
+        assert_eq!(watcher.interval(), Duration::from_millis(10));
+
+        fs::write(root.join("grow"), "xx").unwrap();
+        fs::remove_file(root.join("gone")).unwrap();
+        fs::rename(root.join("moved"), root.join("renamed")).unwrap();
+        fs::write(root.join("replacement"), "y").unwrap();
+        fs::rename(root.join("replacement"), root.join("replaced")).unwrap();
+        fs::create_dir_all(root.join("new/deeper")).unwrap();
+        fs::write(root.join("new/deeper/file"), "x").unwrap();
+        fs::write(root.join("skip/file"), "x").unwrap();
+
+        let events = watcher.read_events().unwrap();
+        let renamed = events.iter().find(|event| event.kind == EventKind::Rename);
+        assert_eq!(
+            renamed.map(|event| event.from.clone()),
+            cfg!(unix).then(|| Some(root.join("moved")))
+        );
+        let events: Vec<_> = events
+            .into_iter()
+            .map(|event| (event.path, event.kind))
+            .collect();
+        let mut expected = vec![
+            (root.join("gone"), EventKind::Delete),
+            (root.join("grow"), EventKind::Modify),
+            (root.join("new"), EventKind::Create),
+            (root.join("new/deeper"), EventKind::Create),
+            (root.join("new/deeper/file"), EventKind::Create),
+        ];
+        if cfg!(unix) {
+            expected.insert(0, (root.join("renamed"), EventKind::Rename));
+            expected.extend([
+                (root.join("replaced"), EventKind::Delete),
+                (root.join("replaced"), EventKind::Create),
+            ]);
+        } else {
+            expected.extend([
+                (root.join("moved"), EventKind::Delete),
+                (root.join("renamed"), EventKind::Create),
+                (root.join("replaced"), EventKind::Modify),
+            ]);
+            expected.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        assert_eq!(events, expected);
+    }
+}
@@ -0,0 +1,449 @@
+//! Remembering what the watched paths were like across restarts, declared with `state_file`, so
+//! changes made while nothing was watching can be caught up on, and as a baseline, declared
+//! with `baseline_file`, for later scans to be compared against.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, Metadata},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use configuration::{Config, Directive, EventKind};
+
+use crate::{hash_file, walk, Event};
+
+/// The first line of a state file, naming its format.
+const HEADER: &str = "overwatch-state 1";
+
+/// The `state_file <path>` directive, parsing to where the [`WatchState`] is kept between
+/// runs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StateFileDirective;
+
+impl StateFileDirective {
+    pub const NAME: &'static str = "state_file";
+
+    /// The file the last `state_file` directive of `config` names, if there is one.
+    pub fn path(config: &Config) -> Option<&Path> {
+        config
+            .custom_values::<PathBuf>(Self::NAME)
+            .last()
+            .map(PathBuf::as_path)
+    }
+}
+
+impl Directive for StateFileDirective {
+    type Value = PathBuf;
+
+    fn parse(&self, args: &str) -> Result<PathBuf, String> {
+        match args.trim() {
+            path if path.starts_with('/') => Ok(PathBuf::from(path)),
+            path => Err(format!("expected an absolute path, found {path:?}")),
+        }
+    }
+}
+
+/// The `baseline_file <path>` directive, parsing to where the [`WatchState`] recorded as a
+/// baseline is kept.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BaselineFileDirective;
+
+impl BaselineFileDirective {
+    pub const NAME: &'static str = "baseline_file";
+
+    /// The file the last `baseline_file` directive of `config` names, if there is one.
+    pub fn path(config: &Config) -> Option<&Path> {
+        config
+            .custom_values::<PathBuf>(Self::NAME)
+            .last()
+            .map(PathBuf::as_path)
+    }
+}
+
+impl Directive for BaselineFileDirective {
+    type Value = PathBuf;
+
+    fn parse(&self, args: &str) -> Result<PathBuf, String> {
+        StateFileDirective.parse(args)
+    }
+}
+
+/// What was last seen of a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathState {
+    pub modified: Option<SystemTime>,
+    pub size: u64,
+    pub dir: bool,
+    /// The permission bits, and the ids of the owning user and group, where the platform has
+    /// them.
+    pub mode: Option<u32>,
+    pub owner: Option<(u32, u32)>,
+    /// The blake3 digest of a file's contents. Directories, symlinks and files over their
+    /// `max_size` have none.
+    pub hash: Option<blake3::Hash>,
+}
+
+impl PathState {
+    /// What `metadata` says of `path`. A file within its `max_size` is hashed, unless
+    /// `previous`, what was seen of it before, has the digest for the same modification time
+    /// and size.
+    pub fn of(
+        config: &Config,
+        path: &Path,
+        metadata: &Metadata,
+        previous: Option<&PathState>,
+    ) -> Self {
+        let mut state = PathState {
+            modified: metadata.modified().ok(),
+            size: metadata.len(),
+            dir: metadata.is_dir(),
+            mode: mode(metadata),
+            owner: owner(metadata),
+            hash: None,
+        };
+        state.hash = match previous {
+            Some(seen) if seen.modified == state.modified && seen.size == state.size => seen.hash,
+            _ if hashable(config, path, metadata) => hash_file(path).ok(),
+            _ => None,
+        };
+        state
+    }
+}
+
+/// What every path a configuration watches was like when it was scanned: the includes, and
+/// the entries directly inside each directory the walk reaches.
+///
+/// Saved to a state file as overwatch stops, and compared against a fresh scan as it starts
+/// again, it tells what changed in between. Paths which aren't valid UTF-8 or hold a newline
+/// aren't kept, so changes to them are missed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WatchState {
+    paths: BTreeMap<PathBuf, PathState>,
+}
+
+impl WatchState {
+    /// Scans the paths `config` watches, hashing the files within their `max_size`. A file
+    /// `previous` saw with the same modification time and size keeps the digest it had, rather
+    /// than being read again.
+    pub fn scan(config: &Config, previous: Option<&WatchState>) -> Self {
+        let paths = walk::scan(config, |path, metadata| {
+            let seen = previous.and_then(|previous| previous.paths.get(path));
+            PathState::of(config, path, metadata, seen)
+        });
+        Self { paths }
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&PathState> {
+        self.paths.get(path)
+    }
+
+    /// The paths recorded, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &PathState)> {
+        self.paths
+            .iter()
+            .map(|(path, state)| (path.as_path(), state))
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// The events which turn this state into `now`, stamped with when each path was last
+    /// modified: paths created, deleted and modified in between. A file is modified when its
+    /// contents, permissions or owner change, and a directory only when its permissions or
+    /// owner do. A file whose modification time or size changed but whose digest didn't was
+    /// left as it was.
+    pub fn changes(&self, now: &WatchState) -> Vec<Event> {
+        let mut events = Vec::new();
+        for path in self.paths.keys() {
+            if !now.paths.contains_key(path) {
+                events.push(Event::new(path, EventKind::Delete));
+            }
+        }
+        for (path, after) in &now.paths {
+            let kind = match self.paths.get(path) {
+                None => EventKind::Create,
+                Some(before) if before.dir != after.dir => EventKind::Create,
+                Some(before) if !modified(before, after) => continue,
+                Some(_) => EventKind::Modify,
+            };
+            let mut event = Event::new(path, kind);
+            if let Some(modified) = after.modified {
+                event.timestamp = modified;
+            }
+            events.push(event);
+        }
+        events.sort_by(|a, b| a.path.cmp(&b.path));
+        events
+    }
+
+    /// Reads the state saved at `path`, or none if nothing was saved there yet.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Option<Self>> {
+        match File::open(path) {
+            Ok(file) => Self::read(BufReader::new(file)).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes the state to `path`, replacing what was saved there once it's all written.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".tmp");
+        let partial = PathBuf::from(partial);
+        let mut out = BufWriter::new(File::create(&partial)?);
+        self.write(&mut out)?;
+        out.into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        fs::rename(partial, path)
+    }
+
+    /// Reads a state in the format [`WatchState::write`] writes.
+    pub fn read(input: impl BufRead) -> io::Result<Self> {
+        let mut lines = input.lines();
+        if lines.next().transpose()?.as_deref() != Some(HEADER) {
+            return Err(invalid(1, "not an overwatch state file"));
+        }
+        let mut paths = BTreeMap::new();
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            let (path, state) = parse_line(&line).ok_or_else(|| invalid(index + 2, &line))?;
+            paths.insert(path, state);
+        }
+        Ok(Self { paths })
+    }
+
+    /// Writes the state as a line naming the format followed by a line for each path: `f` or
+    /// `d`, the modification time, the size, the permission bits in octal, the owner's
+    /// `uid:gid`, the digest and the path, with `-` for what there is none of.
+    pub fn write(&self, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "{HEADER}")?;
+        for (path, state) in &self.paths {
+            let Some(path) = path.to_str().filter(|path| !path.contains('\n')) else {
+                continue;
+            };
+            let none = || "-".to_string();
+            let kind = if state.dir { 'd' } else { 'f' };
+            let modified = match state.modified.and_then(since_epoch) {
+                Some(since) => format!("{}.{:09}", since.as_secs(), since.subsec_nanos()),
+                None => none(),
+            };
+            let mode = state.mode.map_or_else(none, |mode| format!("{mode:o}"));
+            let owner = state
+                .owner
+                .map_or_else(none, |(uid, gid)| format!("{uid}:{gid}"));
+            let hash = state
+                .hash
+                .map_or_else(none, |hash| hash.to_hex().to_string());
+            writeln!(
+                out,
+                "{kind} {modified} {} {mode} {owner} {hash} {path}",
+                state.size
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a scan hashes the file at `path`: a regular file within its `max_size`.
+fn hashable(config: &Config, path: &Path, metadata: &Metadata) -> bool {
+    let max_size = config
+        .include_for(path)
+        .and_then(|entry| config.max_size_for(&entry));
+    metadata.is_file() && max_size.is_none_or(|max| metadata.len() <= max)
+}
+
+fn modified(before: &PathState, after: &PathState) -> bool {
+    if before.mode != after.mode || before.owner != after.owner {
+        return true;
+    }
+    if after.dir {
+        return false;
+    }
+    match (before.hash, after.hash) {
+        (Some(before), Some(after)) => before != after,
+        _ => before.modified != after.modified || before.size != after.size,
+    }
+}
+
+fn since_epoch(time: SystemTime) -> Option<Duration> {
+    time.duration_since(SystemTime::UNIX_EPOCH).ok()
+}
+
+#[cfg(unix)]
+fn mode(metadata: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode(_metadata: &Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn owner(metadata: &Metadata) -> Option<(u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.uid(), metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn owner(_metadata: &Metadata) -> Option<(u32, u32)> {
+    None
+}
+
+/// Parses a line of a state file, as [`WatchState::write`] writes them.
+fn parse_line(line: &str) -> Option<(PathBuf, PathState)> {
+    let mut fields = line.splitn(7, ' ');
+    let dir = match fields.next()? {
+        "d" => true,
+        "f" => false,
+        _ => return None,
+    };
+    let modified = match fields.next()? {
+        "-" => None,
+        time => {
+            let (secs, nanos) = time.split_once('.')?;
+            let since = Duration::new(secs.parse().ok()?, nanos.parse().ok()?);
+            Some(SystemTime::UNIX_EPOCH.checked_add(since)?)
+        }
+    };
+    let size = fields.next()?.parse().ok()?;
+    let mode = match fields.next()? {
+        "-" => None,
+        mode => Some(u32::from_str_radix(mode, 8).ok()?),
+    };
+    let owner = match fields.next()? {
+        "-" => None,
+        owner => {
+            let (uid, gid) = owner.split_once(':')?;
+            Some((uid.parse().ok()?, gid.parse().ok()?))
+        }
+    };
+    let hash = match fields.next()? {
+        "-" => None,
+        hex => Some(blake3::Hash::from_hex(hex).ok()?),
+    };
+    let path = PathBuf::from(fields.next().filter(|path| !path.is_empty())?);
+    let state = PathState {
+        modified,
+        size,
+        dir,
+        mode,
+        owner,
+        hash,
+    };
+    Some((path, state))
+}
+
+fn invalid(line: usize, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {line} of the state file: {reason}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catches_up_on_changes_made_while_down() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("watched");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        for file in ["same", "touched", "changed", "gone", "with space"] {
+            fs::write(root.join(file), file).unwrap();
+        }
+        let config: Config = format!("include -r {}", root.display()).parse().unwrap();
+        let saved = dir.path().join("state");
+        assert_eq!(WatchState::load(&saved).unwrap(), None);
+
+        let before = WatchState::scan(&config, None);
+        assert!(before.get(&root.join("same")).unwrap().hash.is_some());
+        assert_eq!(before.get(&root.join("sub")).unwrap().hash, None);
+        before.save(&saved).unwrap();
+        let loaded = WatchState::load(&saved).unwrap().unwrap();
+        assert_eq!(loaded, before);
+
+        let later = SystemTime::now() + Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(root.join("touched"))
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        fs::write(root.join("changed"), "different").unwrap();
+        fs::remove_file(root.join("gone")).unwrap();
+        fs::write(root.join("sub/new"), "new").unwrap();
+
+        let now = WatchState::scan(&config, Some(&loaded));
+        let events: Vec<_> = loaded
+            .changes(&now)
+            .into_iter()
+            .map(|event| (event.path, event.kind))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (root.join("changed"), EventKind::Modify),
+                (root.join("gone"), EventKind::Delete),
+                (root.join("sub/new"), EventKind::Create),
+            ]
+        );
+
+        fs::write(&saved, "something else\n").unwrap();
+        assert!(WatchState::load(&saved).is_err());
+        fs::write(&saved, format!("{HEADER}\nf - x - - - /srv\n")).unwrap();
+        let err = WatchState::load(&saved).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2 of the state file: f - x - - - /srv"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn catches_permission_and_owner_changes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("watched");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        for file in ["chmodded", "chowned", "same"] {
+            fs::write(root.join(file), file).unwrap();
+        }
+        let config: Config = format!("include -r {}", root.display()).parse().unwrap();
+        let mut before = WatchState::scan(&config, None);
+
+        for path in [root.join("chmodded"), root.join("sub")] {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o700)).unwrap();
+        }
+        // Changing the owner needs root, so the file is taken to be someone else's before.
+        let chowned = before.paths.get_mut(&root.join("chowned")).unwrap();
+        chowned.owner = chowned.owner.map(|(user, group)| (user + 1, group));
+
+        let now = WatchState::scan(&config, Some(&before));
+        let events: Vec<_> = before
+            .changes(&now)
+            .into_iter()
+            .map(|event| (event.path, event.kind))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (root.join("chmodded"), EventKind::Modify),
+                (root.join("chowned"), EventKind::Modify),
+                (root.join("sub"), EventKind::Modify),
+            ]
+        );
+    }
+}
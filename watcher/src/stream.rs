@@ -0,0 +1,268 @@
+//! Reading events from async code.
+
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use configuration::{PathSpec, Reload, WatchEntry};
+use futures_core::Stream;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    worker::{stopped, Batch, Worker},
+    Event, WatchError, Watcher,
+};
+
+/// The events of a watcher as a [`Stream`], returned by [`Watcher::stream`].
+///
+/// The watcher is read on a thread of its own, so polling the stream never blocks the runtime.
+/// Like [`crate::Events`], the stream ends at the first error, which
+/// [`EventStream::take_error`] then returns. Includes can still be added and removed while the
+/// stream is read, for instance as a [`configuration::ConfigReloader`] reports reloads:
+///
+/// ```no_run
+/// # use configuration::{ConfigReloader, ParseOptions, ReloadEvent};
+/// # use watcher::{AutoWatcher, Watcher};
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let reloader = ConfigReloader::new("/etc/overwatch.conf", ParseOptions::default())?;
+/// let mut reloads = reloader.subscribe_async();
+/// let stream = AutoWatcher::new(&reloader.current())?.stream();
+/// while let Some(ReloadEvent::Reloaded(reload)) = reloads.recv().await {
+///     stream.apply(&reload).await?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct EventStream {
+    receiver: mpsc::UnboundedReceiver<Batch>,
+    pending: VecDeque<Event>,
+    error: Option<WatchError>,
+    worker: Worker,
+}
+
+impl EventStream {
+    pub(crate) fn new<W: Watcher + Send + 'static>(watcher: W) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            receiver,
+            pending: VecDeque::new(),
+            error: None,
+            worker: Worker::spawn(watcher, move |batch| sender.send(batch).is_ok()),
+        }
+    }
+
+    /// Starts watching `entry`, as [`Watcher::add`] does.
+    pub async fn add(&self, entry: WatchEntry) -> Result<(), WatchError> {
+        self.call(move |watcher| watcher.add(entry)).await
+    }
+
+    /// Stops watching the include of `path`, as [`Watcher::remove`] does.
+    pub async fn remove(&self, path: &PathSpec) -> Result<(), WatchError> {
+        let path = path.clone();
+        self.call(move |watcher| watcher.remove(&path)).await
+    }
+
+    /// Stops watching the includes a reload removed and starts watching those it added.
+    pub async fn apply(&self, reload: &Reload) -> Result<(), WatchError> {
+        for entry in &reload.removed {
+            self.remove(&entry.path).await?;
+        }
+        for entry in &reload.added {
+            self.add(entry.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// The error which ended the stream, if any. Polling again afterwards waits for more
+    /// events.
+    pub fn take_error(&mut self) -> Option<WatchError> {
+        self.error.take()
+    }
+
+    /// Runs `command` on the watcher's thread, waiting for it to finish.
+    async fn call<F>(&self, command: F) -> Result<(), WatchError>
+    where
+        F: FnOnce(&mut dyn Watcher) -> Result<(), WatchError> + Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        self.worker.submit(Box::new(move |watcher| {
+            let _ = reply.send(command(watcher));
+        }))?;
+        result.await.map_err(|_| stopped())?
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        if self.error.is_some() {
+            return Poll::Ready(None);
+        }
+        while self.pending.is_empty() {
+            match self.receiver.poll_recv(cx) {
+                Poll::Ready(Some(Ok(events))) => self.pending.extend(events),
+                Poll::Ready(Some(Err(err))) => {
+                    self.error = Some(err);
+                    return Poll::Ready(None);
+                }
+                Poll::Ready(None) => {
+                    self.error = Some(stopped());
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(self.pending.pop_front())
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        // Not joined, which would block the runtime until the thread's next tick.
+        self.worker.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, future, path::PathBuf, sync::Arc, thread, time::Duration};
+
+    use configuration::{Config, EventKind};
+
+    use super::*;
+    use crate::{Capabilities, PollWatcher};
+
+    /// Returns the batches it was given, then fails once, then never has events again.
+    struct Scripted(Vec<Vec<Event>>, bool);
+
+    impl Watcher for Scripted {
+        fn add(&mut self, _entry: WatchEntry) -> Result<(), WatchError> {
+            Ok(())
+        }
+
+        fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+            Err(WatchError::NotIncluded(path.clone()))
+        }
+
+        fn read_events_timeout(
+            &mut self,
+            timeout: Option<Duration>,
+        ) -> Result<Vec<Event>, WatchError> {
+            if !self.0.is_empty() {
+                return Ok(self.0.remove(0));
+            }
+            if !self.1 {
+                self.1 = true;
+                return Err(WatchError::Overflow);
+            }
+            thread::sleep(timeout.unwrap_or(Duration::MAX));
+            Ok(Vec::new())
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                recursive: false,
+                realtime: false,
+                remote: false,
+            }
+        }
+    }
+
+    /// The next event of `stream`, or `None` if it doesn't arrive within `timeout`.
+    async fn next(stream: &mut EventStream, timeout: Duration) -> Option<Option<Event>> {
+        let next = future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx));
+        tokio::time::timeout(timeout, next).await.ok()
+    }
+
+    #[tokio::test]
+    async fn streams_events_and_takes_new_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("first")).unwrap();
+        fs::create_dir_all(root.join("second")).unwrap();
+        let config: Config = format!(
+            "include {}\npoll_interval 10ms",
+            root.join("first").display()
+        )
+        .parse()
+        .unwrap();
+        let mut stream = PollWatcher::new(&config).unwrap().stream();
+
+        let second: Config = format!("include {}", root.join("second").display())
+            .parse()
+            .unwrap();
+        stream.add(second.includes()[0].clone()).await.unwrap();
+        let err = stream.remove(&PathSpec::Path(root.join("missing"))).await;
+        assert!(matches!(err, Err(WatchError::NotIncluded(_))), "{err:?}");
+
+        fs::write(root.join("second/file"), "x").unwrap();
+        let event = next(&mut stream, Duration::from_secs(10))
+            .await
+            .flatten()
+            .unwrap();
+        assert_eq!(
+            (event.path, event.kind),
+            (root.join("second/file"), EventKind::Create)
+        );
+    }
+
+    #[tokio::test]
+    async fn applies_reloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("first")).unwrap();
+        fs::create_dir_all(root.join("second")).unwrap();
+        let parse = |name: &str| -> Config {
+            format!("include {}\npoll_interval 10ms", root.join(name).display())
+                .parse()
+                .unwrap()
+        };
+        let (old, new) = (parse("first"), parse("second"));
+        let mut stream = PollWatcher::new(&old).unwrap().stream();
+
+        let reload = Reload {
+            added: new.includes().to_vec(),
+            removed: old.includes().to_vec(),
+            diff: Config::diff(&old, &new),
+            config: Arc::new(new),
+        };
+        stream.apply(&reload).await.unwrap();
+        fs::write(root.join("first/file"), "x").unwrap();
+        fs::write(root.join("second/file"), "x").unwrap();
+        let event = next(&mut stream, Duration::from_secs(10))
+            .await
+            .flatten()
+            .unwrap();
+        assert_eq!(event.path, root.join("second/file"));
+        // The include of first is gone, so removing it again fails.
+        assert!(stream.apply(&reload).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn ends_at_the_first_error() {
+        let event = |path: &str| Event::new(path, EventKind::Create);
+        let watcher = Scripted(vec![vec![event("/a"), event("/b")]], false);
+        let mut stream = watcher.stream();
+        let timeout = Duration::from_secs(10);
+
+        let paths = [
+            next(&mut stream, timeout).await.unwrap(),
+            next(&mut stream, timeout).await.unwrap(),
+        ];
+        let paths = paths.map(|event| event.unwrap().path);
+        assert_eq!(paths, [PathBuf::from("/a"), PathBuf::from("/b")]);
+        assert!(next(&mut stream, timeout).await.unwrap().is_none());
+        assert!(next(&mut stream, timeout).await.unwrap().is_none());
+        assert!(matches!(stream.take_error(), Some(WatchError::Overflow)));
+        assert!(stream.take_error().is_none());
+
+        // Polling again waits for more.
+        assert!(next(&mut stream, Duration::from_millis(100))
+            .await
+            .is_none());
+    }
+}
@@ -0,0 +1,263 @@
+//! Finding the directories and files a configuration asks to watch.
+
+use std::{
+    collections::{hash_map, BTreeMap, HashMap},
+    fs::{self, Metadata},
+    path::{Path, PathBuf},
+};
+
+use configuration::{Config, Recursion, WatchEntry};
+
+/// Every include of `config`, followed by the includes of its watch groups with their group's
+/// settings filled in.
+pub fn watch_entries(config: &Config) -> Vec<WatchEntry> {
+    let groups = config.groups().iter().flat_map(|group| group.entries());
+    config.includes().iter().cloned().chain(groups).collect()
+}
+
+/// What each include of `config` names and is watched, the paths walks start from.
+pub(crate) fn roots(config: &Config) -> Vec<PathBuf> {
+    watch_entries(config)
+        .iter()
+        .flat_map(|entry| entry.path.expand())
+        .filter(|root| config.is_watched(root))
+        .collect()
+}
+
+/// The paths to register for `config`: what each include names, and for recursive includes
+/// every subdirectory within their depth which is watched. Each directory is listed once, however
+/// many includes or symlinks reach it.
+pub fn watch_paths(config: &Config) -> Vec<PathBuf> {
+    let mut walk = Walk::new(config);
+    for entry in watch_entries(config) {
+        for root in entry.path.expand() {
+            if config.is_watched(&root) {
+                walk.visit(&entry, root, 0);
+            }
+        }
+    }
+    walk.paths
+}
+
+/// Records what `of` makes of every path `config` watches: the includes, and the entries
+/// directly inside each directory the walk reaches. Symlinks inside a directory are described
+/// by their own metadata rather than their target's.
+pub(crate) fn scan<T>(
+    config: &Config,
+    mut of: impl FnMut(&Path, &Metadata) -> T,
+) -> BTreeMap<PathBuf, T> {
+    let mut scanned = BTreeMap::new();
+    for path in watch_paths(config) {
+        let Ok(metadata) = fs::metadata(&path) else {
+            continue;
+        };
+        if metadata.is_dir() {
+            for entry in fs::read_dir(&path).into_iter().flatten().flatten() {
+                let child = entry.path();
+                if scanned.contains_key(&child) || !config.is_watched(&child) {
+                    continue;
+                }
+                if let Ok(metadata) = fs::symlink_metadata(&child) {
+                    let value = of(&child, &metadata);
+                    scanned.insert(child, value);
+                }
+            }
+        }
+        let value = of(&path, &metadata);
+        scanned.insert(path, value);
+    }
+    scanned
+}
+
+/// The paths to register for `dir`, a directory which appeared after the walk: itself and its
+/// subdirectories, as far as the recursive includes reaching it go. Empty if none does.
+#[cfg(target_os = "linux")]
+pub(crate) fn watch_paths_below(config: &Config, dir: &Path) -> Vec<PathBuf> {
+    let mut walk = Walk::new(config);
+    if !config.is_watched(dir) {
+        return Vec::new();
+    }
+    for entry in watch_entries(config) {
+        if entry.options.recursion != Recursion::Recursive {
+            continue;
+        }
+        for root in entry.path.expand() {
+            if let Ok(below) = dir.strip_prefix(&root) {
+                let level = below.components().count();
+                if level > 0 && level <= entry.options.max_depth.unwrap_or(usize::MAX) {
+                    walk.visit(&entry, dir.to_path_buf(), level);
+                }
+            }
+        }
+    }
+    walk.paths
+}
+
+struct Walk<'a> {
+    config: &'a Config,
+    /// The canonical paths of everything listed, with how many levels below each were walked,
+    /// so links back up a tree end the walk. A directory is walked again only when an include
+    /// reaches deeper below it.
+    seen: HashMap<PathBuf, usize>,
+    paths: Vec<PathBuf>,
+}
+
+impl<'a> Walk<'a> {
+    fn new(config: &'a Config) -> Self {
+        Self {
+            config,
+            seen: HashMap::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Walks the tree below `root`, which is `level` levels below the path `entry` names.
+    fn visit(&mut self, entry: &WatchEntry, root: PathBuf, level: usize) {
+        let levels = match entry.options.recursion {
+            Recursion::NonRecursive => 0,
+            Recursion::Recursive => entry.options.max_depth.unwrap_or(usize::MAX),
+        };
+        let follow = self.config.follow_symlinks_for(entry);
+        let mut pending = vec![(root, level)];
+        while let Some((path, level)) = pending.pop() {
+            // A path which doesn't exist is still listed, so registering it reports why.
+            let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+            let below = levels - level;
+            let first = match self.seen.entry(canonical) {
+                hash_map::Entry::Occupied(walked) if *walked.get() >= below => continue,
+                hash_map::Entry::Occupied(mut walked) => {
+                    walked.insert(below);
+                    false
+                }
+                hash_map::Entry::Vacant(walked) => {
+                    walked.insert(below);
+                    true
+                }
+            };
+            if below > 0 {
+                let mut children = subdirectories(&path, follow);
+                children.retain(|child| self.config.is_watched(child));
+                // Reversed so they are popped, and listed, in name order.
+                children.sort_unstable_by(|a, b| b.cmp(a));
+                pending.extend(children.into_iter().map(|child| (child, level + 1)));
+            }
+            if first {
+                self.paths.push(path);
+            }
+        }
+    }
+}
+
+/// The directories directly inside `dir`, including symlinks to directories if `follow` is set.
+/// Entries which can't be read, such as ones removed while listing, are skipped.
+fn subdirectories(dir: &Path, follow: bool) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_symlink() => follow && entry.path().is_dir(),
+            Ok(kind) => kind.is_dir(),
+            Err(_) => false,
+        })
+        .map(|entry| entry.path())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walks_what_the_config_watches() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for sub in ["a/b/c", "a/.git", "skip/x", "flat/sub", "other"] {
+            fs::create_dir_all(root.join(sub)).unwrap();
+        }
+        fs::write(root.join("file.txt"), "").unwrap();
+
+        let test_cases = vec![
+            (
+                "include -r {root}/a\ninclude -s {root}/flat",
+                vec!["a", "a/.git", "a/b", "a/b/c", "flat"],
+            ),
+            (
+                "include -r {root} depth=1\nexclude {root}/skip\ninclude_hidden off",
+                vec!["", "a", "flat", "other"],
+            ),
+            (
+                "include {root}/file.txt\ninclude -s {root}/missing\ninclude -s {root}/o*",
+                vec!["file.txt", "missing", "other"],
+            ),
+            (
+                "include -r {root}/a\nwatch group {\ninclude -r {root}/a/b\n}",
+                vec!["a", "a/.git", "a/b", "a/b/c"],
+            ),
+        ];
+        for (config, expected) in test_cases {
+            let config: Config = config
+                .replace("{root}", &root.display().to_string())
+                .parse()
+                .unwrap();
+            let expected: Vec<_> = expected.iter().map(|sub| root.join(sub)).collect();
+            assert_eq!(watch_paths(&config), expected, "{config:?}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_symlinks_only_when_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        std::os::unix::fs::symlink(root.join("target"), root.join("a/link")).unwrap();
+        std::os::unix::fs::symlink(root, root.join("a/loop")).unwrap();
+
+        let test_cases = vec![
+            ("follow_symlinks off", vec!["a"]),
+            // The link back up reaches `target` a second time, so it is only listed once.
+            ("follow_symlinks on", vec!["a", "a/link", "a/loop"]),
+        ];
+        for (setting, expected) in test_cases {
+            let config: Config = format!("include -r {}/a\n{setting}", root.display())
+                .parse()
+                .unwrap();
+            let expected: Vec<_> = expected.iter().map(|sub| root.join(sub)).collect();
+            assert_eq!(watch_paths(&config), expected, "{setting}");
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn walks_directories_which_appear_later() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for sub in ["new/deeper/deepest", "new/skip", "flat/new"] {
+            fs::create_dir_all(root.join(sub)).unwrap();
+        }
+        let config: Config = format!(
+            "include -r {0} depth=2\nexclude {0}/new/skip\ninclude {0}/flat",
+            root.display()
+        )
+        .parse()
+        .unwrap();
+        let test_cases = vec![
+            ("new", vec!["new", "new/deeper"]),
+            ("new/deeper", vec!["new/deeper"]),
+            ("new/deeper/deepest", vec![]),
+            ("new/skip", vec![]),
+            ("flat/new", vec!["flat/new"]),
+        ];
+        for (sub, expected) in test_cases {
+            let expected: Vec<_> = expected.iter().map(|sub| root.join(sub)).collect();
+            assert_eq!(
+                watch_paths_below(&config, &root.join(sub)),
+                expected,
+                "{sub}"
+            );
+        }
+    }
+}
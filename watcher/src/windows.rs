@@ -0,0 +1,425 @@
+//! The Windows backend, built on ReadDirectoryChangesW.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fmt, fs, io, mem,
+    os::windows::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+    ptr, slice,
+    time::Duration,
+};
+
+use configuration::{Config, EventKind, PathSpec, Recursion, WatchEntry};
+use windows_sys::Win32::{
+    Foundation::{CloseHandle, ERROR_NOTIFY_ENUM_DIR, HANDLE, INVALID_HANDLE_VALUE, WAIT_TIMEOUT},
+    Storage::FileSystem::{
+        CreateFileW, ReadDirectoryChangesW, FILE_ACTION_ADDED, FILE_ACTION_MODIFIED,
+        FILE_ACTION_REMOVED, FILE_ACTION_RENAMED_NEW_NAME, FILE_ACTION_RENAMED_OLD_NAME,
+        FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OVERLAPPED, FILE_LIST_DIRECTORY, FILE_NOTIFY_CHANGE,
+        FILE_NOTIFY_CHANGE_ATTRIBUTES, FILE_NOTIFY_CHANGE_CREATION, FILE_NOTIFY_CHANGE_DIR_NAME,
+        FILE_NOTIFY_CHANGE_FILE_NAME, FILE_NOTIFY_CHANGE_LAST_WRITE, FILE_NOTIFY_CHANGE_SECURITY,
+        FILE_NOTIFY_CHANGE_SIZE, FILE_NOTIFY_INFORMATION, FILE_SHARE_DELETE, FILE_SHARE_READ,
+        FILE_SHARE_WRITE, OPEN_EXISTING,
+    },
+    System::{
+        Threading::INFINITE,
+        IO::{
+            CancelIoEx, CreateIoCompletionPort, GetOverlappedResult, GetQueuedCompletionStatus,
+            OVERLAPPED,
+        },
+    },
+};
+
+use crate::{
+    backend::{deadline, remaining},
+    walk, Capabilities, Event, WatchError, Watcher,
+};
+
+/// The changes every directory is watched for.
+const NOTIFY_FILTER: FILE_NOTIFY_CHANGE = FILE_NOTIFY_CHANGE_FILE_NAME
+    | FILE_NOTIFY_CHANGE_DIR_NAME
+    | FILE_NOTIFY_CHANGE_ATTRIBUTES
+    | FILE_NOTIFY_CHANGE_SIZE
+    | FILE_NOTIFY_CHANGE_LAST_WRITE
+    | FILE_NOTIFY_CHANGE_CREATION
+    | FILE_NOTIFY_CHANGE_SECURITY;
+
+/// The size of each directory's buffer in bytes. Changes beyond what fits between two reads
+/// are lost, and 64K is the most a network share accepts.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Watches the includes of a configuration with ReadDirectoryChangesW.
+///
+/// Each included directory is read with overlapped I/O, completing on one I/O completion port,
+/// and a recursive include covers its whole tree with a single read, so new subdirectories are
+/// covered as soon as they appear. An include naming a file watches the directory holding it.
+/// Only events for paths the configuration watches are returned.
+pub struct WindowsWatcher {
+    config: Config,
+    port: HANDLE,
+    /// By completion key. Boxed so the buffers and `OVERLAPPED`s the kernel writes to never
+    /// move, even when the map grows.
+    directories: HashMap<usize, Box<Directory>>,
+    /// The key of the next directory opened. Keys aren't reused, so a read cancelled when its
+    /// directory was closed can't complete as another directory's.
+    next_key: usize,
+}
+
+struct Directory {
+    handle: HANDLE,
+    path: PathBuf,
+    recursive: bool,
+    overlapped: OVERLAPPED,
+    /// Aligned to a `u32`, as ReadDirectoryChangesW requires.
+    buffer: Vec<u32>,
+}
+
+// SAFETY: the handles can be used from any thread, and the buffers are only touched by the
+// thread owning the watcher.
+unsafe impl Send for WindowsWatcher {}
+
+impl WindowsWatcher {
+    /// Opens every directory `config` includes, or which holds a file it includes, and starts
+    /// reading changes from it.
+    pub fn new(config: &Config) -> Result<Self, WatchError> {
+        // SAFETY: creating a port takes no pointers.
+        let port = unsafe { CreateIoCompletionPort(INVALID_HANDLE_VALUE, ptr::null_mut(), 0, 1) };
+        if port.is_null() {
+            return Err(WatchError::Io(io::Error::last_os_error()));
+        }
+        let mut watcher = Self {
+            config: config.clone(),
+            port,
+            directories: HashMap::new(),
+            next_key: 0,
+        };
+        watcher.sync()?;
+        Ok(watcher)
+    }
+
+    /// The directories the configuration needs read, and whether each is read recursively.
+    fn wanted(&self) -> Result<HashSet<(PathBuf, bool)>, WatchError> {
+        let mut wanted = HashSet::new();
+        for entry in walk::watch_entries(&self.config) {
+            for root in entry.path.expand() {
+                if !self.config.is_watched(&root) {
+                    continue;
+                }
+                let metadata = fs::metadata(&root).map_err(|error| WatchError::Watch {
+                    path: root.clone(),
+                    error,
+                })?;
+                wanted.insert(if metadata.is_dir() {
+                    (root, entry.options.recursion == Recursion::Recursive)
+                } else {
+                    let parent = root.parent().unwrap_or(Path::new(".")).to_path_buf();
+                    (parent, false)
+                });
+            }
+        }
+        Ok(wanted)
+    }
+
+    /// Opens the directories the configuration needs and which aren't open yet, and closes
+    /// those it no longer needs.
+    fn sync(&mut self) -> Result<(), WatchError> {
+        let mut wanted = self.wanted()?;
+        self.directories.retain(|_, directory| {
+            let keep = wanted.remove(&(directory.path.clone(), directory.recursive));
+            if !keep {
+                directory.close();
+            }
+            keep
+        });
+        for (dir, recursive) in wanted {
+            self.watch(dir, recursive)?;
+        }
+        Ok(())
+    }
+
+    fn watch(&mut self, path: PathBuf, recursive: bool) -> Result<(), WatchError> {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        // SAFETY: `wide` is a nul terminated path for the duration of the call.
+        let handle = unsafe {
+            CreateFileW(
+                wide.as_ptr(),
+                FILE_LIST_DIRECTORY,
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                ptr::null(),
+                OPEN_EXISTING,
+                FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OVERLAPPED,
+                ptr::null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            let error = io::Error::last_os_error();
+            return Err(WatchError::Watch { path, error });
+        }
+        let key = self.next_key;
+        self.next_key += 1;
+        // Inserted first, so the handle is closed on drop whatever happens next.
+        let directory = self.directories.entry(key).or_insert(Box::new(Directory {
+            handle,
+            path,
+            recursive,
+            // SAFETY: OVERLAPPED is plain data, for which all zeroes is valid.
+            overlapped: unsafe { mem::zeroed() },
+            buffer: vec![0; BUFFER_SIZE / mem::size_of::<u32>()],
+        }));
+        // SAFETY: both handles are open.
+        if unsafe { CreateIoCompletionPort(handle, self.port, key, 0) }.is_null() {
+            let error = io::Error::last_os_error();
+            return Err(directory.fail(error));
+        }
+        directory.read().map_err(|error| directory.fail(error))
+    }
+}
+
+impl Directory {
+    /// Starts reading the next changes, which complete on the watcher's port.
+    fn read(&mut self) -> io::Result<()> {
+        // SAFETY: the buffer and `OVERLAPPED` are boxed and outlive the read, which is
+        // cancelled and waited for before they are freed.
+        let started = unsafe {
+            ReadDirectoryChangesW(
+                self.handle,
+                self.buffer.as_mut_ptr().cast(),
+                BUFFER_SIZE as u32,
+                self.recursive.into(),
+                NOTIFY_FILTER,
+                ptr::null_mut(),
+                &mut self.overlapped,
+                None,
+            )
+        };
+        if started == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Cancels the read in progress and closes the directory.
+    fn close(&self) {
+        let mut len = 0;
+        // SAFETY: the handle is open, and waiting on the cancelled read makes sure the kernel
+        // is done with the buffer before it is freed.
+        unsafe {
+            CancelIoEx(self.handle, &self.overlapped);
+            GetOverlappedResult(self.handle, &self.overlapped, &mut len, 1);
+            CloseHandle(self.handle);
+        }
+    }
+
+    fn fail(&self, error: io::Error) -> WatchError {
+        WatchError::Watch {
+            path: self.path.clone(),
+            error,
+        }
+    }
+
+    /// The changes in the first `len` bytes of the buffer, a chain of
+    /// `FILE_NOTIFY_INFORMATION` records.
+    /// The events in the first `len` bytes of the buffer. A rename is reported as its old
+    /// name immediately followed by its new one, which are paired into one event.
+    fn changes(&self, len: usize) -> Vec<Event> {
+        // SAFETY: the buffer holds `BUFFER_SIZE` bytes, of which the kernel wrote `len`.
+        let bytes = unsafe { slice::from_raw_parts(self.buffer.as_ptr().cast::<u8>(), len) };
+        let name_offset = mem::offset_of!(FILE_NOTIFY_INFORMATION, FileName);
+        let mut events = Vec::new();
+        let mut renamed_from = None;
+        let mut offset = 0;
+        while offset + name_offset <= len {
+            // SAFETY: a whole record header is in bounds.
+            let info = unsafe {
+                bytes
+                    .as_ptr()
+                    .add(offset)
+                    .cast::<FILE_NOTIFY_INFORMATION>()
+                    .read_unaligned()
+            };
+            let start = offset + name_offset;
+            let end = (start + info.FileNameLength as usize).min(len);
+            let name: Vec<u16> = bytes[start..end]
+                .chunks_exact(2)
+                .map(|pair| u16::from_ne_bytes([pair[0], pair[1]]))
+                .collect();
+            let path = self.path.join(OsString::from_wide(&name));
+            match (renamed_from.take(), info.Action) {
+                (Some(from), FILE_ACTION_RENAMED_NEW_NAME) => {
+                    events.push(Event::renamed(from, path));
+                }
+                (from, action) => {
+                    // An old name without its new one was moved out of the directory.
+                    events.extend(from.map(|from| Event::new(from, EventKind::Delete)));
+                    match action {
+                        FILE_ACTION_RENAMED_OLD_NAME => renamed_from = Some(path),
+                        action => events.extend(kind(action).map(|kind| Event::new(path, kind))),
+                    }
+                }
+            }
+            if info.NextEntryOffset == 0 {
+                break;
+            }
+            offset += info.NextEntryOffset as usize;
+        }
+        events.extend(renamed_from.map(|from| Event::new(from, EventKind::Delete)));
+        events
+    }
+}
+
+impl Watcher for WindowsWatcher {
+    /// A directory which can no longer be read, such as one which was removed, is reported
+    /// as [`WatchError::Watch`] and no longer watched.
+    fn read_events_timeout(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, WatchError> {
+        let deadline = deadline(timeout);
+        loop {
+            let wait = remaining(deadline).map_or(INFINITE, |left| {
+                // Rounded up, so a wait doesn't end just short of the deadline.
+                left.as_nanos()
+                    .div_ceil(1_000_000)
+                    .min(u128::from(INFINITE - 1)) as u32
+            });
+            let (mut len, mut key, mut overlapped) = (0, 0, ptr::null_mut());
+            // SAFETY: the port is open and the out pointers are valid.
+            let completed = unsafe {
+                GetQueuedCompletionStatus(self.port, &mut len, &mut key, &mut overlapped, wait)
+            };
+            if overlapped.is_null() {
+                let error = io::Error::last_os_error();
+                if error.raw_os_error() == Some(WAIT_TIMEOUT as i32) {
+                    return Ok(Vec::new());
+                }
+                return Err(WatchError::Io(error));
+            }
+            let Some(directory) = self.directories.get_mut(&key) else {
+                continue;
+            };
+            if completed == 0 {
+                let error = io::Error::last_os_error();
+                if error.raw_os_error() != Some(ERROR_NOTIFY_ENUM_DIR as i32) {
+                    return Err(directory.fail(error));
+                }
+                len = 0;
+            }
+            let events = directory.changes(len as usize);
+            directory.read().map_err(|error| directory.fail(error))?;
+            // Nothing read means the changes didn't fit in the buffer.
+            if len == 0 {
+                return Err(WatchError::Overflow);
+            }
+
+            let events: Vec<_> = events
+                .into_iter()
+                .filter(|event| self.config.is_watched(&event.path))
+                .collect();
+            if !events.is_empty() {
+                return Ok(events);
+            }
+        }
+    }
+
+    fn add(&mut self, entry: WatchEntry) -> Result<(), WatchError> {
+        self.config.add_include(entry);
+        self.sync()
+    }
+
+    fn remove(&mut self, path: &PathSpec) -> Result<(), WatchError> {
+        self.config
+            .remove_include(path)
+            .ok_or_else(|| WatchError::NotIncluded(path.clone()))?;
+        self.sync()
+    }
+
+    /// Network shares report changes too, as long as the server supports it.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            recursive: true,
+            realtime: true,
+            remote: true,
+        }
+    }
+}
+
+impl Drop for WindowsWatcher {
+    fn drop(&mut self) {
+        for directory in self.directories.values() {
+            directory.close();
+        }
+        // SAFETY: the port is open, and closed once.
+        unsafe { CloseHandle(self.port) };
+    }
+}
+
+impl fmt::Debug for WindowsWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let directories: Vec<_> = self.directories.values().map(|dir| &dir.path).collect();
+        f.debug_struct("WindowsWatcher")
+            .field("config", &self.config)
+            .field("directories", &directories)
+            .finish()
+    }
+}
+
+/// The kind of event a `FILE_ACTION` reports.
+fn kind(action: u32) -> Option<EventKind> {
+    match action {
+        FILE_ACTION_ADDED => Some(EventKind::Create),
+        FILE_ACTION_MODIFIED => Some(EventKind::Modify),
+        FILE_ACTION_REMOVED => Some(EventKind::Delete),
+        // The new name of a rename whose old name was outside the directory.
+        FILE_ACTION_RENAMED_NEW_NAME => Some(EventKind::Create),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use configuration::WatchOptions;
+
+    use super::*;
+
+    #[test]
+    fn reports_changes_under_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("tree/skip")).unwrap();
+        fs::write(root.join("single"), "").unwrap();
+        fs::write(root.join("sibling"), "").unwrap();
+        // Built in code, since the backslashes of Windows paths would need escaping in quotes.
+        let path = |sub: &str| root.join(sub).display().to_string();
+        let config = Config::builder()
+            .include_with(
+                path("tree"),
+                WatchOptions {
+                    recursion: Recursion::Recursive,
+                    ..Default::default()
+                },
+            )
+            .exclude(path("tree/skip"))
+            .include(path("single"))
+            .build()
+            .unwrap();
+        let mut watcher = WindowsWatcher::new(&config).unwrap();
+
+        fs::write(root.join("tree/skip/file"), "x").unwrap();
+        fs::write(root.join("sibling"), "x").unwrap();
+        fs::create_dir_all(root.join("tree/new/deeper")).unwrap();
+        fs::write(root.join("tree/new/deeper/file"), "x").unwrap();
+        fs::write(root.join("single"), "x").unwrap();
+
+        let mut seen = Vec::new();
+        while !(seen.contains(&(root.join("tree/new/deeper/file"), EventKind::Create))
+            && seen.contains(&(root.join("single"), EventKind::Modify)))
+        {
+            let events = watcher.read_events().unwrap();
+            seen.extend(events.into_iter().map(|event| (event.path, event.kind)));
+        }
+        assert!(
+            seen.iter()
+                .all(|(path, _)| !path.starts_with(root.join("tree/skip"))
+                    && *path != root.join("sibling")),
+            "{seen:?}"
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! Running a watcher on a thread of its own, for callers which read several at once or don't
+//! want to block.
+
+use std::{
+    io,
+    sync::mpsc::{self, TryRecvError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{Event, WatchError, Watcher};
+
+/// How long the thread waits for events before checking for commands.
+const TICK: Duration = Duration::from_millis(250);
+
+/// What one read of a watcher returned.
+pub(crate) type Batch = Result<Vec<Event>, WatchError>;
+
+/// Something for the thread to do with its watcher between reads.
+pub(crate) type Command = Box<dyn FnOnce(&mut dyn Watcher) + Send>;
+
+/// A watcher being read on a thread of its own, which stops once the worker is stopped or
+/// whoever receives its batches is gone.
+#[derive(Debug)]
+pub(crate) struct Worker {
+    commands: Option<mpsc::Sender<Command>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    /// Starts reading `watcher`, passing every batch of events or error to `send` until it
+    /// returns false.
+    pub(crate) fn spawn<W, F>(mut watcher: W, mut send: F) -> Self
+    where
+        W: Watcher + Send + 'static,
+        F: FnMut(Batch) -> bool + Send + 'static,
+    {
+        let (commands, received) = mpsc::channel::<Command>();
+        let thread = thread::spawn(move || loop {
+            loop {
+                match received.try_recv() {
+                    Ok(command) => command(&mut watcher),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+            match watcher.read_events_timeout(Some(TICK)) {
+                Ok(batch) if batch.is_empty() => {}
+                batch => {
+                    if !send(batch) {
+                        return;
+                    }
+                }
+            }
+        });
+        Self {
+            commands: Some(commands),
+            thread: Some(thread),
+        }
+    }
+
+    /// Queues `command` to run before the next read.
+    pub(crate) fn submit(&self, command: Command) -> Result<(), WatchError> {
+        let commands = self.commands.as_ref().ok_or_else(stopped)?;
+        commands.send(command).map_err(|_| stopped())
+    }
+
+    /// Runs `command` on the thread, waiting for it to finish.
+    pub(crate) fn call<F>(&self, command: F) -> Result<(), WatchError>
+    where
+        F: FnOnce(&mut dyn Watcher) -> Result<(), WatchError> + Send + 'static,
+    {
+        let (reply, result) = mpsc::channel();
+        self.submit(Box::new(move |watcher| {
+            let _ = reply.send(command(watcher));
+        }))?;
+        result.recv().map_err(|_| stopped())?
+    }
+
+    /// Tells the thread to stop, which it does within a tick.
+    pub(crate) fn stop(&mut self) {
+        self.commands = None;
+    }
+
+    /// Stops the thread and waits for it to finish.
+    pub(crate) fn join(&mut self) {
+        self.stop();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The error for a thread which is no longer running.
+pub(crate) fn stopped() -> WatchError {
+    WatchError::Io(io::Error::other("the watcher's thread stopped"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use configuration::{Config, PathSpec};
+
+    use super::*;
+    use crate::PollWatcher;
+
+    #[test]
+    fn reads_on_its_own_thread_between_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("first")).unwrap();
+        fs::create_dir_all(root.join("second")).unwrap();
+        let parse = |name: &str| -> Config {
+            format!("include {}\npoll_interval 10ms", root.join(name).display())
+                .parse()
+                .unwrap()
+        };
+        let watcher = PollWatcher::new(&parse("first")).unwrap();
+        let (sender, batches) = mpsc::channel();
+        let mut worker = Worker::spawn(watcher, move |batch| sender.send(batch).is_ok());
+
+        let entry = parse("second").includes()[0].clone();
+        worker.call(move |watcher| watcher.add(entry)).unwrap();
+        let missing = PathSpec::Path(root.join("missing"));
+        let err = worker.call(move |watcher| watcher.remove(&missing));
+        assert!(matches!(err, Err(WatchError::NotIncluded(_))), "{err:?}");
+
+        fs::write(root.join("second/file"), "x").unwrap();
+        let events = batches.recv_timeout(Duration::from_secs(10)).unwrap();
+        let paths: Vec<_> = events
+            .unwrap()
+            .into_iter()
+            .map(|event| event.path)
+            .collect();
+        assert_eq!(paths, [root.join("second/file")]);
+
+        worker.join();
+        assert!(worker.submit(Box::new(|_| {})).is_err());
+        assert!(worker.call(|_| Ok(())).is_err());
+    }
+}